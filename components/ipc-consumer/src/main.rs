@@ -16,8 +16,13 @@ use kaal_sdk::{
     syscall,
     message::{Channel, ChannelConfig},
     channel_setup::{establish_channel, ChannelRole},
+    ipc::Semaphore,
 };
 
+/// Offset of the semaphore counting written messages, past the magic
+/// value and the messages
+const SEMAPHORE_OFFSET: usize = 64;
+
 // Declare this as a service component
 kaal_sdk::component! {
     name: "ipc_consumer",
@@ -80,15 +85,19 @@ impl Component for IpcConsumer {
             syscall::print("  - Notification capability ready\n");
             syscall::print("\n");
 
-            // Wait a bit for producer to initialize
-            for _ in 0..5 {
-                syscall::yield_now();
-            }
+            if channel_config.buffer_addr != 0 {
+                // Counts the messages the producer has written
+                let written = &*((channel_config.buffer_addr + SEMAPHORE_OFFSET) as *const Semaphore);
+                let notification = channel_config.notification_cap.slot() as u64;
+
+                // Block until the producer has written its first message
+                if written.acquire(notification).is_err() {
+                    syscall::print("[consumer] Wait failed\n");
+                }
 
-            // Check the magic value written by producer
-            syscall::print("[consumer] Checking shared memory...\n");
+                // Check the magic value written by producer
+                syscall::print("[consumer] Checking shared memory...\n");
 
-            if channel_config.buffer_addr != 0 {
                 let shared_ptr = channel_config.buffer_addr as *mut u32;
                 let magic = *shared_ptr;
 
@@ -99,25 +108,15 @@ impl Component for IpcConsumer {
                 }
                 syscall::print("\n");
 
-                // Read test messages with optional notification waiting
+                // Read test messages, waiting for each to be written
                 syscall::print("[consumer] Reading test messages from shared memory...\n");
 
-                // Wait for producer to write data
-                for _ in 0..10 {
-                    // Check for notifications if we have the capability
-                    if channel_config.notification_cap != 0 {
-                        let signals = syscall::poll(channel_config.notification_cap).unwrap_or(0);
-                        if signals != 0 {
-                            syscall::print("  [Received signal: ");
-                            syscall::print("X");
-                            syscall::print("]\n");
-                        }
+                for i in 0..5 {
+                    // The first permit was taken before the magic check
+                    if i > 0 && notification != 0 && written.acquire(notification).is_err() {
+                        syscall::print("[consumer] Wait failed\n");
                     }
-                    syscall::yield_now();
-                }
 
-                // Read the test messages
-                for i in 0..5 {
                     // Read message from shared memory
                     let msg_ptr = (channel_config.buffer_addr + 4 + (i * 4)) as *mut u32;
                     let message = *msg_ptr;
@@ -146,23 +145,16 @@ impl Component for IpcConsumer {
 
                 // Wait for producer to signal us
                 // This puts thread in BlockedOnReceive state → removed from ready queue
-                if channel_config.notification_cap != 0 {
-                    loop {
-                        match syscall::wait(channel_config.notification_cap) {
-                            Ok(signals) => {
-                                syscall::print("[consumer] Received signal, but no more messages to read\n");
-                                // Continue blocking
-                            }
-                            Err(_) => {
-                                syscall::print("[consumer] Wait failed, yielding\n");
-                                syscall::yield_now();
-                            }
+                loop {
+                    match syscall::wait(&channel_config.notification_cap) {
+                        Ok(signals) => {
+                            syscall::print("[consumer] Received signal, but no more messages to read\n");
+                            // Continue blocking
+                        }
+                        Err(_) => {
+                            syscall::print("[consumer] Wait failed, yielding\n");
+                            syscall::yield_now();
                         }
-                    }
-                } else {
-                    // No notification cap - just yield forever (shouldn't happen)
-                    loop {
-                        syscall::yield_now();
                     }
                 }
             } else {
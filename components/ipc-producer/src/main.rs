@@ -16,8 +16,13 @@ use kaal_sdk::{
     syscall,
     message::{Channel, ChannelConfig},
     channel_setup::{establish_channel, ChannelRole},
+    ipc::Semaphore,
 };
 
+/// Offset of the semaphore counting written messages, past the magic
+/// value and the messages
+const SEMAPHORE_OFFSET: usize = 64;
+
 // Declare this as a service component
 kaal_sdk::component! {
     name: "ipc_producer",
@@ -117,6 +122,9 @@ impl Component for IpcProducer {
                 *shared_ptr = 0xDEADBEEF;
                 syscall::print("[producer] Wrote magic value 0xDEADBEEF to shared memory\n");
 
+                // The buffer starts zeroed: no messages written yet
+                let written = &*((channel_config.buffer_addr + SEMAPHORE_OFFSET) as *const Semaphore);
+
                 // Write test data to shared memory
                 syscall::print("[producer] Writing test data to shared memory...\n");
                 for i in 0..5 {
@@ -127,15 +135,8 @@ impl Component for IpcProducer {
                     syscall::print("  → Wrote test message ");
                     syscall::print("X\n");
 
-                    // Signal consumer if we have notification capability
-                    if channel_config.notification_cap != 0 {
-                        let _ = syscall::signal(channel_config.notification_cap, 1 << i);
-                    }
-
-                    // Yield to let consumer see the data
-                    for _ in 0..5 {
-                        syscall::yield_now();
-                    }
+                    // Wake the consumer
+                    written.release(channel_config.notification_cap.slot() as u64);
                 }
 
                 syscall::print("[producer] All test data written!\n");
@@ -148,23 +149,16 @@ impl Component for IpcProducer {
 
                 // Wait for consumer to signal us
                 // This puts thread in BlockedOnReceive state → removed from ready queue
-                if channel_config.notification_cap != 0 {
-                    loop {
-                        match syscall::wait(channel_config.notification_cap) {
-                            Ok(signals) => {
-                                syscall::print("[producer] Received signal, but no more work to do\n");
-                                // Continue blocking
-                            }
-                            Err(_) => {
-                                syscall::print("[producer] Wait failed, yielding\n");
-                                syscall::yield_now();
-                            }
+                loop {
+                    match syscall::wait(&channel_config.notification_cap) {
+                        Ok(signals) => {
+                            syscall::print("[producer] Received signal, but no more work to do\n");
+                            // Continue blocking
+                        }
+                        Err(_) => {
+                            syscall::print("[producer] Wait failed, yielding\n");
+                            syscall::yield_now();
                         }
-                    }
-                } else {
-                    // No notification cap - just yield forever (shouldn't happen)
-                    loop {
-                        syscall::yield_now();
                     }
                 }
             } else {
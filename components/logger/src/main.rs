@@ -0,0 +1,251 @@
+//! Log Server
+//!
+//! Prints the output of the components the root task spawns. Their
+//! `printf!` and `log!` messages arrive on the log endpoint, which the root
+//! task hands this component to receive on (`log:serve`). Each component's
+//! text is put together into whole lines, and each line printed with the
+//! component's name and the message level:
+//!
+//! ```text
+//! INFO  [uart_driver] Initialized: 115200 8N1, FIFOs enabled
+//! WARN  [uart_driver] RX buffer overflow!
+//! ```
+//!
+//! Only the log server writes those components' output to the console, so
+//! lines printed at the same time come out one after the other instead of
+//! mixed up.
+//!
+//! The root task watches it for hangs (`heartbeat_ms` in system.toml): a
+//! hung log server holds up every component's output, but not the root
+//! task's report of the hang, which goes to the console directly. A timer on
+//! a notification bound to it ends its receive, so it beats while no one
+//! logs.
+
+#![no_std]
+#![no_main]
+
+use core::mem::ManuallyDrop;
+
+use kaal_sdk::{
+    capability::{Cap, Endpoint, Notification, Timer},
+    component::{self, Component},
+    env::{self, InitialCap},
+    log::{Level, Record, MAX_NAME},
+    printf,
+    syscall,
+};
+
+kaal_sdk::component! {
+    name: "logger",
+    type: Service,
+    version: "0.1.0",
+    capabilities: ["log:serve"],
+    impl: Logger
+}
+
+/// Components with a line in progress at once; a component past that has
+/// the oldest one's line printed unfinished to make room
+const MAX_SENDERS: usize = 16;
+
+/// Longest line; longer ones are broken up
+const MAX_LINE: usize = 200;
+
+/// How often we signal our heartbeat, well within `heartbeat_ms`
+const HEARTBEAT_PERIOD_US: u64 = 250_000;
+
+/// Signal bit of the heartbeat timer
+const HEARTBEAT_BADGE: u64 = 1 << 0;
+
+/// A line a component has not finished yet
+struct Line {
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    /// Level of its first piece
+    level: Level,
+    text: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl Line {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    fn text(&self) -> &str {
+        core::str::from_utf8(&self.text[..self.len]).unwrap_or("")
+    }
+}
+
+pub struct Logger {
+    /// The log endpoint, which the root task keeps in our CSpace for good
+    endpoint: ManuallyDrop<Cap<Endpoint>>,
+    /// Lines in progress, by component; the oldest first
+    lines: [Option<Line>; MAX_SENDERS],
+    /// Timer waking us up to signal our heartbeat, and its notification;
+    /// None if the root task does not watch us
+    heartbeat: Option<(Cap<Notification>, Timer)>,
+}
+
+impl Component for Logger {
+    fn init() -> kaal_sdk::Result<Self> {
+        let Some(endpoint) = env::cap_slot(InitialCap::LogServer) else {
+            printf!("[logger] No log endpoint, is log:serve in system.toml?\n");
+            return Err(kaal_sdk::Error::CapabilityNotFound);
+        };
+        printf!("[logger] Serving the log endpoint (slot {})\n", endpoint);
+        let endpoint = unsafe { Cap::borrow_raw(endpoint, Endpoint) };
+
+        let heartbeat = match env::cap_slot(InitialCap::Heartbeat).map(|_| heartbeat_timer()) {
+            Some(Ok(heartbeat)) => Some(heartbeat),
+            Some(Err(e)) => {
+                printf!("[logger] No heartbeat timer, we will be reported hung: {:?}\n", e);
+                None
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            endpoint,
+            lines: [const { None }; MAX_SENDERS],
+            heartbeat,
+        })
+    }
+
+    fn run(&mut self) -> ! {
+        let mut message = [0u8; syscall::MAX_MESSAGE];
+        loop {
+            match syscall::recv(&self.endpoint, &mut message) {
+                // Our bound notification, not a message
+                Ok((0, _signals)) if self.heartbeat.is_some() => component::heartbeat(),
+                Ok((len, _badge)) => {
+                    if let Some(record) = Record::decode(&message[..len.min(message.len())]) {
+                        self.take(&record);
+                    }
+                }
+                Err(_) => {
+                    printf!("[logger] ERROR: Receive on the log endpoint failed\n");
+                    syscall::yield_now();
+                }
+            }
+        }
+    }
+}
+
+impl Logger {
+    /// Add a message to its component's line, printing the lines it ends
+    fn take(&mut self, record: &Record) {
+        let mut rest = record.text;
+        while !rest.is_empty() {
+            let index = self.line_of(record.name, record.level);
+            match rest.split_once('\n') {
+                Some((piece, after)) => {
+                    self.append(index, piece);
+                    self.flush(index);
+                    rest = after;
+                }
+                None => {
+                    self.append(index, rest);
+                    rest = "";
+                }
+            }
+        }
+    }
+
+    /// Index of `name`'s line in progress, started at `level` if there is
+    /// none
+    fn line_of(&mut self, name: &str, level: Level) -> usize {
+        if let Some(index) = self.lines.iter().position(|line| line.as_ref().is_some_and(|line| line.name() == name)) {
+            return index;
+        }
+        let index = match self.lines.iter().position(|line| line.is_none()) {
+            Some(index) => index,
+            None => {
+                self.flush(0);
+                self.lines.rotate_left(1);
+                MAX_SENDERS - 1
+            }
+        };
+
+        let mut line = Line {
+            name: [0; MAX_NAME],
+            name_len: name.len().min(MAX_NAME),
+            level,
+            text: [0; MAX_LINE],
+            len: 0,
+        };
+        line.name[..line.name_len].copy_from_slice(&name.as_bytes()[..line.name_len]);
+        self.lines[index] = Some(line);
+        index
+    }
+
+    /// Add `text` to a line, printing it whenever it fills up
+    fn append(&mut self, index: usize, mut text: &str) {
+        let Some(line) = self.lines[index].as_mut() else {
+            return;
+        };
+        while !text.is_empty() {
+            let mut len = text.len().min(MAX_LINE - line.len);
+            while !text.is_char_boundary(len) {
+                len -= 1;
+            }
+            if len == 0 {
+                // Full: print it and carry on with an empty one
+                print_line(line);
+                line.len = 0;
+                continue;
+            }
+            line.text[line.len..line.len + len].copy_from_slice(&text.as_bytes()[..len]);
+            line.len += len;
+            text = &text[len..];
+        }
+    }
+
+    /// Print a line and drop it
+    fn flush(&mut self, index: usize) {
+        let Some(line) = self.lines[index].take() else {
+            return;
+        };
+        print_line(&line);
+    }
+}
+
+/// A periodic timer for our heartbeat, on a notification bound to us so
+/// that it ends our receive on the log endpoint
+fn heartbeat_timer() -> kaal_sdk::Result<(Cap<Notification>, Timer)> {
+    let notification = Cap::<Notification>::create()?;
+    notification.bind()?;
+    let timer = Timer::create(&notification, HEARTBEAT_BADGE)?;
+    timer.set_periodic(HEARTBEAT_PERIOD_US)?;
+    Ok((notification, timer))
+}
+
+/// Print a line tagged with its level and component
+///
+/// A line starting with the component's name in brackets, as most
+/// components' output does, is not tagged with it twice.
+fn print_line(line: &Line) {
+    let name = line.name();
+    let mut text = line.text();
+    if let Some(rest) = text.strip_prefix('[').and_then(|rest| rest.strip_prefix(name)).and_then(|rest| rest.strip_prefix(']')) {
+        text = rest.strip_prefix(' ').unwrap_or(rest);
+    }
+
+    let mut out = [0u8; 16 + MAX_NAME + MAX_LINE];
+    let mut len = 0;
+    for part in [level_tag(line.level), "[", name, "] ", text, "\n"] {
+        out[len..len + part.len()].copy_from_slice(part.as_bytes());
+        len += part.len();
+    }
+    // One print per line, so the console never gets half of one
+    syscall::print(core::str::from_utf8(&out[..len]).unwrap_or(""));
+}
+
+/// Tag of a level, padded to the same width for all
+fn level_tag(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR ",
+        Level::Warn => "WARN  ",
+        Level::Info => "INFO  ",
+        Level::Debug => "DEBUG ",
+    }
+}
@@ -17,8 +17,7 @@ use kaal_sdk::{
     component::Component,
     printf,
     syscall,
-    message::{Channel, ChannelConfig as MsgChannelConfig},
-    channel_setup::{establish_channel, ChannelRole},
+    interfaces::{UartInput, UartInputServer},
 };
 use kaal_tui::{screen, cursor};
 
@@ -31,9 +30,6 @@ kaal_sdk::component! {
     impl: Notepad
 }
 
-/// IPC buffer size for UART channel (4KB)
-const IPC_BUFFER_SIZE: usize = 4096;
-
 /// Text editor state
 pub struct Notepad {
     lines: [Line; 32],          // Maximum 32 lines
@@ -41,7 +37,7 @@ pub struct Notepad {
     current_line: Line,
     current_pos: usize,
     char_count: usize,
-    input_channel: Channel<u8>,
+    input_channel: UartInputServer,
 }
 
 /// A single line of text
@@ -108,23 +104,13 @@ impl Component for Notepad {
         printf!("\n");
         printf!("Ready. Start typing!\n");
 
-        // Establish IPC channel with UART driver for input
-        // Retry until uart_driver is ready (it may not have started yet)
-        let input_channel = loop {
-            match establish_channel("kaal.uart.output", IPC_BUFFER_SIZE, ChannelRole::Consumer) {
-                Ok(config) => {
-                    let msg_config = MsgChannelConfig {
-                        shared_memory: config.buffer_addr,
-                        receiver_notify: config.notification_cap as u64,
-                        sender_notify: config.notification_cap as u64,
-                    };
-
-                    break unsafe { Channel::receiver(msg_config) };
-                }
-                Err(_) => {
-                    // UART driver not ready yet, yield and retry
-                    syscall::yield_now();
-                }
+        // Join the UART driver's input channel; uart_driver is in our
+        // depends_on, so it has set the channel up before we are spawned
+        let input_channel = match UartInputServer::connect() {
+            Ok(channel) => channel,
+            Err(e) => {
+                printf!("[notepad] Failed to join input channel: {}\n", e);
+                return Err(kaal_sdk::Error::NotFound);
             }
         };
 
@@ -145,10 +131,7 @@ impl Component for Notepad {
         loop {
             // Receive character from UART driver via IPC (blocking on notification)
             match self.input_channel.receive() {
-                Ok(ch) => {
-                    // Process the character
-                    self.process_char(ch);
-                }
+                Ok(message) => message.dispatch(self),
                 Err(_) => {
                     // Error receiving, yield and try again
                     syscall::yield_now();
@@ -158,6 +141,12 @@ impl Component for Notepad {
     }
 }
 
+impl UartInput for Notepad {
+    fn key(&mut self, byte: u8) {
+        self.process_char(byte);
+    }
+}
+
 impl Notepad {
     /// Process a single character of input
     fn process_char(&mut self, ch: u8) {
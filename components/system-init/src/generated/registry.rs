@@ -1,6 +1,6 @@
 // Component registry for system_init
 //
-// This file is auto-generated by build.nu from components.toml
+// This file is auto-generated by build.nu from system.toml
 // DO NOT EDIT MANUALLY
 
 /// Component descriptor with embedded binary
@@ -74,7 +74,7 @@ pub const COMPONENT_REGISTRY: &[ComponentDescriptor] = &[
         name: "system_monitor",
         priority: 90,
         autostart: true,
-        capabilities_bitmask: 9,
+        capabilities_bitmask: 11,
         binary_data: include_bytes!("../../../../components/system-monitor/target/aarch64-unknown-none/release/system-monitor"),
     },
 ];
\ No newline at end of file
@@ -1,19 +1,28 @@
 //! System Init Component
 //!
-//! The first component spawned by the root-task. Responsible for:
-//! - Initializing core system services
-//! - Spawning other components based on priority
+//! The init component (`boot:init`): the one component the root task
+//! spawns at boot. Responsible for:
+//! - Deciding which of the root task's components run, in what order and
+//!   with how much untyped memory, and having the root task start them
+//!   (`kaal_sdk::boot`)
+//! - Spawning its own components (`spawned_by = "system_init"`)
 //! - Managing system-wide initialization
 
 #![no_std]
 #![no_main]
 
 use kaal_sdk::{
+    boot,
     component::Component,
     syscall,
     printf,
+    Error,
 };
 
+/// Untyped memory the components the root task starts get, in KiB, by
+/// name; the others get none
+const UNTYPED_KB: &[(&str, u32)] = &[];
+
 // Component registry - auto-generated by build system
 mod generated {
     include!("generated/registry.rs");
@@ -64,6 +73,8 @@ impl Component for SystemInit {
             }
         };
 
+        start_system_components();
+
         // ═══════════════════════════════════════════════════════════
         // Spawn delegated components (spawned_by="system_init")
         // ═══════════════════════════════════════════════════════════
@@ -106,7 +117,7 @@ impl Component for SystemInit {
         loop {
             // Block waiting for notification events
             // This removes us from the scheduler's ready queue
-            match syscall::wait(notification_cap) {
+            match syscall::wait(&notification_cap) {
                 Ok(signals) => {
                     if signals != 0 {
                         syscall::print("[system_init] Received notification signal\n");
@@ -120,3 +131,50 @@ impl Component for SystemInit {
         }
     }
 }
+
+/// Phase 2 of boot: have the root task start its autostart components
+///
+/// The root task refuses a component while one it depends on is not
+/// running, so this goes over the list in rounds, until a round starts
+/// nothing more.
+fn start_system_components() {
+    if !boot::is_init() {
+        syscall::print("[system_init] Not the init component, the root task starts the system\n");
+        return;
+    }
+
+    syscall::print("\n");
+    syscall::print("═══════════════════════════════════════════════════════════\n");
+    syscall::print("  Starting System Components\n");
+    syscall::print("═══════════════════════════════════════════════════════════\n");
+    syscall::print("\n");
+
+    loop {
+        let mut started = 0;
+        let mut waiting = 0;
+        for component in boot::components().filter(|c| c.autostart && !c.running) {
+            let name = component.name();
+            let untyped_kb = UNTYPED_KB.iter().find(|(n, _)| *n == name).map_or(0, |&(_, kb)| kb);
+            match boot::start(name, untyped_kb) {
+                Ok(pid) => {
+                    printf!("  ✓ Started {} (PID: {})\n", name, pid);
+                    started += 1;
+                }
+                Err(Error::WouldBlock) => waiting += 1,
+                Err(Error::Busy) => {}
+                Err(_) => printf!("  ✗ Failed to start {}\n", name),
+            }
+        }
+        if waiting == 0 || started == 0 {
+            if waiting > 0 {
+                printf!("[system_init] ✗ {} components wait on dependencies that are not running\n", waiting);
+            }
+            break;
+        }
+    }
+
+    // The root task emits its capability audit
+    if boot::done().is_err() {
+        syscall::print("[system_init] ✗ Failed to tell the root task boot is done\n");
+    }
+}
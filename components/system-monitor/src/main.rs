@@ -2,12 +2,13 @@
 #![no_main]
 
 use kaal_sdk::{
+    boot,
     component::Component,
     printf,
     syscall,
-    message::Channel,
-    channel_setup::{establish_channel, ChannelRole, ChannelConfig},
-    message::ChannelConfig as MsgChannelConfig,
+    process::{self, ThreadStats},
+    time,
+    interfaces::{UartInput, UartInputServer},
 };
 use kaal_tui::{screen, cursor, style, draw, ui, Color};
 
@@ -22,34 +23,52 @@ kaal_sdk::component! {
 
 const SCREEN_WIDTH: usize = 80;
 
+/// Kernel log lines shown by the log view
+const KLOG_LINES: usize = 30;
+
+/// Rows of the process table
+const PROCESS_ROWS: usize = 3;
+
+/// Threads read from the kernel for the process table
+const MAX_THREADS: usize = 64;
+
+/// An application the monitor launches
+struct App {
+    title: &'static str,
+    /// Its component in system.toml, which the root task starts for us
+    name: &'static str,
+}
+
+/// Applications launched by the keys '1', '2', ...
+const APPS: &[App] = &[
+    App { title: "Notepad", name: "notepad" },
+    App { title: "Todo App", name: "todo_app" },
+];
+
 pub struct SystemMonitor {
-    input_channel: Channel<u8>,
-    refresh_counter: usize,
+    input_channel: UartInputServer,
+    /// Whether we launched each application, by `APPS` index
+    launched: [bool; APPS.len()],
+    /// The application launched last, which 'k' stops first
+    last_launched: Option<usize>,
 }
 
 impl Component for SystemMonitor {
     fn init() -> kaal_sdk::Result<Self> {
-        // Establish IPC channel with UART driver for input
-        let input_channel = loop {
-            match establish_channel("kaal.uart.output", 4096, ChannelRole::Consumer) {
-                Ok(config) => {
-                    let msg_config = MsgChannelConfig {
-                        shared_memory: config.buffer_addr,
-                        receiver_notify: config.notification_cap as u64,
-                        sender_notify: config.notification_cap as u64,
-                    };
-
-                    break unsafe { Channel::receiver(msg_config) };
-                }
-                Err(_) => {
-                    syscall::yield_now();
-                }
+        // Join the UART driver's input channel; uart_driver is in our
+        // depends_on, so it has set the channel up before we are spawned
+        let input_channel = match UartInputServer::connect() {
+            Ok(channel) => channel,
+            Err(e) => {
+                printf!("[system_monitor] Failed to join input channel: {}\n", e);
+                return Err(kaal_sdk::Error::NotFound);
             }
         };
 
         Ok(Self {
             input_channel,
-            refresh_counter: 0,
+            launched: [false; APPS.len()],
+            last_launched: None,
         })
     }
 
@@ -60,9 +79,7 @@ impl Component for SystemMonitor {
         loop {
             // Wait for input
             match self.input_channel.receive() {
-                Ok(byte) => {
-                    self.handle_input(byte);
-                }
+                Ok(message) => message.dispatch(self),
                 Err(_) => {
                     syscall::yield_now();
                 }
@@ -71,6 +88,12 @@ impl Component for SystemMonitor {
     }
 }
 
+impl UartInput for SystemMonitor {
+    fn key(&mut self, byte: u8) {
+        self.handle_input(byte);
+    }
+}
+
 impl SystemMonitor {
     fn draw_full_ui(&self) {
         screen::clear();
@@ -165,20 +188,34 @@ impl SystemMonitor {
         printf!("Frames:  31684 free / 32768 total");
 
         cursor::goto(19, 2);
-        printf!("Uptime:  0d 0h {}m {}s", self.refresh_counter / 60, self.refresh_counter % 60);
+        let secs = time::uptime().as_secs();
+        printf!("Uptime:  {}d {}h {}m {}s", secs / 86400, (secs / 3600) % 24, (secs / 60) % 60, secs % 60);
+        if let Ok(stats) = process::idle_stats() {
+            printf!("    CPU idle: {}%", stats.idle_percent());
+        }
     }
 
     fn draw_process_section(&self) {
+        // Busiest threads first
+        let mut threads = [ThreadStats::default(); MAX_THREADS];
+        let mut count = 0;
+        for stats in process::threads().take(MAX_THREADS) {
+            threads[count] = stats;
+            count += 1;
+        }
+        let threads = &mut threads[..count];
+        threads.sort_unstable_by(|a, b| b.cpu_time_us.cmp(&a.cpu_time_us));
+
         cursor::goto(20, 1);
         draw::hline(SCREEN_WIDTH, "─");
 
         cursor::goto(21, 2);
         style::fg(Color::BrightYellow);
         style::bold();
-        printf!("PROCESSES (2)");
+        printf!("PROCESSES ({:<2})", count);
         style::reset();
         style::fg(Color::BrightBlack);
-        printf!("                                 [Press 'k' + number to kill]");
+        printf!("                          [Top {} by CPU time, 'r' to refresh]", PROCESS_ROWS);
         style::reset();
 
         cursor::goto(22, 1);
@@ -187,32 +224,27 @@ impl SystemMonitor {
         // Table header
         cursor::goto(23, 2);
         style::fg(Color::BrightCyan);
-        printf!("PID        Name              Priority    State        Memory");
+        printf!("PID          Priority  State     CPU time     Instructions   Switches");
         style::reset();
 
         cursor::goto(24, 2);
         style::fg(Color::BrightBlack);
-        printf!("────────── ───────────────── ─────────── ──────────── ──────────");
+        printf!("──────────── ───────── ───────── ──────────── ────────────── ──────────");
         style::reset();
 
-        // Process list (hardcoded for now, will add syscalls later)
-        let processes = [
-            ("1083781120", "uart_driver", "50", "Running", "32 KB"),
-            ("1083846656", "system_monitor", "100", "Running", "16 KB"),
-        ];
-
-        for (i, (pid, name, priority, state, memory)) in processes.iter().enumerate() {
+        for (i, thread) in threads.iter().take(PROCESS_ROWS).enumerate() {
             cursor::goto(25 + i, 2);
             style::fg(Color::White);
-            printf!("{:<10} ", pid);
-            style::fg(Color::BrightWhite);
-            printf!("{:<17} ", name);
+            printf!("{:<#12x} ", thread.tid);
             style::fg(Color::Yellow);
-            printf!("{:<11} ", priority);
+            printf!("{:<9} ", thread.priority);
             style::fg(Color::BrightGreen);
-            printf!("{:<12} ", state);
+            printf!("{:<9} ", thread.state().name());
             style::fg(Color::Cyan);
-            printf!("{}", memory);
+            printf!("{:>7}.{:03}s ", thread.cpu_time_us / 1_000_000, (thread.cpu_time_us / 1000) % 1000);
+            style::fg(Color::White);
+            printf!("{:>14} ", thread.instructions);
+            printf!("{:>10}", thread.switches);
             style::reset();
         }
     }
@@ -265,6 +297,11 @@ impl SystemMonitor {
         style::fg(Color::White);
         printf!(" Kill Process  ");
 
+        style::fg(Color::BrightYellow);
+        printf!("[l]");
+        style::fg(Color::White);
+        printf!(" Kernel Log  ");
+
         style::fg(Color::BrightMagenta);
         printf!("[q]");
         style::fg(Color::White);
@@ -273,7 +310,69 @@ impl SystemMonitor {
         style::reset();
     }
 
-    fn draw_status_message(&self, message: &str, is_error: bool) {
+    fn draw_kernel_log(&self) {
+        screen::clear();
+        cursor::home();
+
+        cursor::goto(1, 2);
+        style::fg(Color::BrightYellow);
+        style::bold();
+        printf!("KERNEL LOG");
+        style::reset();
+        style::fg(Color::BrightBlack);
+        printf!("                                      [l] Reload  [r] Back");
+        style::reset();
+
+        cursor::goto(2, 1);
+        draw::hline(SCREEN_WIDTH, "─");
+
+        // Read the tail of the log: find where it ends, then read back
+        let mut buf = [0u8; 2048];
+        let text = match syscall::klog_read(&mut [], usize::MAX)
+            .and_then(|(_, end)| syscall::klog_read(&mut buf, end.saturating_sub(2048)))
+        {
+            Ok((len, end)) => {
+                let text = &buf[..len];
+                // Unless the whole log fit, the first line is cut off
+                match text.iter().position(|&b| b == b'\n') {
+                    Some(newline) if end > len => &text[newline + 1..],
+                    _ => text,
+                }
+            }
+            Err(_) => {
+                self.draw_status_message("Failed to read kernel log", true);
+                return;
+            }
+        };
+
+        // Keep the last KLOG_LINES lines
+        let mut lines: [&[u8]; KLOG_LINES] = [&[]; KLOG_LINES];
+        let mut count = 0;
+        for line in text.split(|&b| b == b'\n') {
+            lines[count % KLOG_LINES] = line;
+            count += 1;
+        }
+        if text.ends_with(b"\n") {
+            count -= 1; // split() yields an empty last line
+        }
+
+        let shown = count.min(KLOG_LINES);
+        style::fg(Color::White);
+        for i in 0..shown {
+            let line = lines[(count - shown + i) % KLOG_LINES];
+            let line = &line[..line.len().min(SCREEN_WIDTH - 2)];
+            // Cutting the line may split a multi-byte character
+            let line = match core::str::from_utf8(line) {
+                Ok(s) => s,
+                Err(e) => core::str::from_utf8(&line[..e.valid_up_to()]).unwrap_or(""),
+            };
+            cursor::goto(3 + i, 2);
+            printf!("{}", line);
+        }
+        style::reset();
+    }
+
+    fn draw_status_message(&self, message: impl core::fmt::Display, is_error: bool) {
         cursor::goto(36, 2);
         screen::clear_line();
 
@@ -305,25 +404,82 @@ impl SystemMonitor {
             }
             b'r' | b'R' => {
                 // Refresh
-                self.refresh_counter += 1;
                 self.draw_full_ui();
                 self.draw_status_message("Display refreshed", false);
             }
             b'1' => {
-                self.draw_status_message("Launching Notepad... (spawning not yet implemented)", false);
+                self.launch(0);
             }
             b'2' => {
-                self.draw_status_message("Launching Todo App... (spawning not yet implemented)", false);
+                self.launch(1);
             }
             b'3' => {
                 self.draw_status_message("Hex Editor coming soon!", false);
             }
+            b'l' | b'L' => {
+                self.draw_kernel_log();
+            }
             b'k' | b'K' => {
-                self.draw_status_message("Process killing not yet implemented", false);
+                self.kill_launched();
             }
             _ => {
                 // Ignore other keys
             }
         }
     }
+
+    /// Have the root task start the application `APPS[index]`, unless it
+    /// is running already
+    fn launch(&mut self, index: usize) {
+        let app = &APPS[index];
+        match boot::start(app.name, 0) {
+            Ok(pid) => {
+                self.draw_status_message(format_args!("Launched {} (PID {:#x})", app.title, pid), false);
+                self.launched[index] = true;
+                self.last_launched = Some(index);
+            }
+            Err(kaal_sdk::Error::Busy) => {
+                self.draw_status_message(format_args!("{} is already running", app.title), false);
+            }
+            Err(kaal_sdk::Error::CapabilityNotFound) => {
+                self.draw_status_message("No boot endpoint: launching needs boot:control", true);
+            }
+            Err(e) => {
+                self.draw_status_message(format_args!("Failed to launch {}: {:?}", app.title, e), true);
+            }
+        }
+    }
+
+    /// Have the root task stop the application launched last, or else
+    /// another one we launched
+    ///
+    /// It is asked to shut down, and destroyed if it has not exited
+    /// within the root task's timeout.
+    fn kill_launched(&mut self) {
+        let index = self
+            .last_launched
+            .filter(|&index| self.launched[index])
+            .or_else(|| self.launched.iter().rposition(|&launched| launched));
+        let Some(index) = index else {
+            self.draw_status_message("No launched application to kill", true);
+            return;
+        };
+        self.last_launched = None;
+        self.launched[index] = false;
+
+        let app = &APPS[index];
+        match boot::stop(app.name) {
+            Ok(exited) => {
+                self.draw_process_section();
+                let how = if exited { "Stopped" } else { "Killed" };
+                self.draw_status_message(format_args!("{} {}", how, app.title), false);
+            }
+            Err(kaal_sdk::Error::NotFound) => {
+                self.draw_status_message(format_args!("{} had already exited", app.title), false);
+            }
+            Err(e) => {
+                self.draw_status_message(format_args!("Failed to kill {}: {:?}", app.title, e), true);
+            }
+        }
+    }
 }
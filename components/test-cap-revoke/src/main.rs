@@ -4,6 +4,8 @@
 //! 1. Testing syscall interface (SYS_CAP_DERIVE, SYS_CAP_MINT, SYS_CAP_REVOKE)
 //! 2. Verifying error handling (invalid slots, permissions)
 //! 3. Testing derivation lifecycle (derive → revoke parent → children gone)
+//! 4. Sweeping whole subtrees (derived, minted and copied caps) on revoke
+//! 5. Deleting a middle capability without losing its descendants
 //!
 //! Component requires CAP_CAPS capability to test capability operations.
 
@@ -131,17 +133,38 @@ fn syscall_cap_derive(cnode_cap: u64, src_slot: u64, dest_slot: u64, rights: u64
     result
 }
 
-fn syscall_endpoint_create() -> u64 {
+fn syscall_cap_mint(cnode_cap: u64, src_slot: u64, dest_slot: u64, badge: u64) -> u64 {
     let result: u64;
     unsafe {
         core::arch::asm!(
             "mov x8, {syscall}",
+            "mov x0, {cnode_cap}",
+            "mov x1, {src_slot}",
+            "mov x2, {dest_slot}",
+            "mov x3, {badge}",
             "svc #0",
             "mov {result}, x0",
-            syscall = in(reg) SYS_ENDPOINT_CREATE,
+            syscall = in(reg) SYS_CAP_MINT,
+            cnode_cap = in(reg) cnode_cap,
+            src_slot = in(reg) src_slot,
+            dest_slot = in(reg) dest_slot,
+            badge = in(reg) badge,
             result = out(reg) result,
             out("x8") _,
-            out("x0") _,
+        );
+    }
+    result
+}
+
+fn syscall_endpoint_create() -> u64 {
+    let result: u64;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall}",
+            "svc #0",
+            syscall = in(reg) SYS_ENDPOINT_CREATE,
+            inlateout("x0") 0u64 => result, // a new slot
+            out("x8") _,
         );
     }
     result
@@ -232,6 +255,12 @@ pub extern "C" fn _start() -> ! {
     // Test 4: Copy, Move, and Delete operations
     test_cap_operations();
 
+    // Test 5: Revoke sweeps derived, minted and copied caps
+    test_revoke_sweep();
+
+    // Test 6: Delete keeps descendants reachable by revoke
+    test_delete_keeps_descendants();
+
     print("═══════════════════════════════════════════════\n");
     print("  All tests completed!\n");
     print("═══════════════════════════════════════════════\n");
@@ -448,6 +477,136 @@ fn test_cap_operations() {
     print("\n");
 }
 
+/// Check that a slot is empty: copying out of it must fail
+fn slot_is_empty(slot: u64, scratch: u64) -> bool {
+    if syscall_cap_copy(0, slot, 0, scratch) == u64::MAX {
+        return true;
+    }
+    // Not empty - undo the probe copy
+    syscall_cap_delete(0, scratch);
+    false
+}
+
+/// Allocate `N` capability slots, or None if the CSpace is full
+fn allocate_slots<const N: usize>() -> Option<[u64; N]> {
+    let mut slots = [0u64; N];
+    for slot in slots.iter_mut() {
+        *slot = syscall_cap_allocate();
+        if *slot == u64::MAX {
+            return None;
+        }
+    }
+    Some(slots)
+}
+
+/// Test 5: Revoke sweeps the whole derivation subtree
+///
+/// endpoint ─┬─ copy
+///           └─ derived ─── badged ─── badged copy
+///
+/// Revoking the endpoint must empty every slot in the tree.
+fn test_revoke_sweep() {
+    print("[TEST 5] Revoke Sweep of Derived, Minted and Copied Caps\n");
+
+    let endpoint = syscall_endpoint_create();
+    let [copy, derived, badged, badged_copy, scratch] = match allocate_slots::<5>() {
+        Some(slots) if endpoint != u64::MAX => slots,
+        _ => {
+            print("  ✗ FAIL: Could not create endpoint or allocate slots\n\n");
+            return;
+        }
+    };
+
+    print("  [5a] Building derivation tree...\n");
+    if syscall_cap_copy(0, endpoint, 0, copy) != 0
+        || syscall_cap_derive(0, endpoint, derived, 0x7) != 0
+        || syscall_cap_mint(0, derived, badged, 0x5A) != 0
+        || syscall_cap_copy(0, badged, 0, badged_copy) != 0 {
+        print("    ✗ FAIL: Could not build tree\n\n");
+        return;
+    }
+    print("    ✓ Built tree of 5 capabilities\n");
+
+    print("  [5b] Re-badging a badged cap must fail...\n");
+    if syscall_cap_mint(0, badged, scratch, 0x77) == u64::MAX {
+        print("    ✓ Correctly rejected\n");
+    } else {
+        print("    ✗ FAIL: Badged cap was re-badged\n");
+        syscall_cap_delete(0, scratch);
+    }
+
+    print("  [5c] Revoking the endpoint...\n");
+    if syscall_cap_revoke(0, endpoint) != 0 {
+        print("    ✗ FAIL: Revoke failed\n\n");
+        return;
+    }
+
+    let mut remaining = 0;
+    for slot in [endpoint, copy, derived, badged, badged_copy] {
+        if !slot_is_empty(slot, scratch) {
+            remaining += 1;
+        }
+    }
+    if remaining == 0 {
+        print("    ✓ All 5 slots swept\n");
+    } else {
+        print("    ✗ FAIL: ");
+        print_u64(remaining);
+        print(" capabilities survived the revoke\n");
+    }
+
+    print("\n");
+}
+
+/// Test 6: Deleting a middle capability keeps its descendants
+///
+/// endpoint ─── derived ─── grandchild
+///
+/// Deleting `derived` leaves `grandchild` usable; revoking the endpoint
+/// afterwards still removes it.
+fn test_delete_keeps_descendants() {
+    print("[TEST 6] Delete Keeps Descendants\n");
+
+    let endpoint = syscall_endpoint_create();
+    let [derived, grandchild, scratch] = match allocate_slots::<3>() {
+        Some(slots) if endpoint != u64::MAX => slots,
+        _ => {
+            print("  ✗ FAIL: Could not create endpoint or allocate slots\n\n");
+            return;
+        }
+    };
+
+    if syscall_cap_derive(0, endpoint, derived, 0x7) != 0
+        || syscall_cap_derive(0, derived, grandchild, 0x3) != 0 {
+        print("  ✗ FAIL: Could not build tree\n\n");
+        return;
+    }
+
+    print("  [6a] Deleting the middle capability...\n");
+    if syscall_cap_delete(0, derived) != 0 {
+        print("    ✗ FAIL: Delete failed\n\n");
+        return;
+    }
+    if slot_is_empty(grandchild, scratch) {
+        print("    ✗ FAIL: Grandchild was deleted with its parent\n\n");
+        return;
+    }
+    print("    ✓ Grandchild survived\n");
+
+    print("  [6b] Revoking the endpoint...\n");
+    if syscall_cap_revoke(0, endpoint) != 0 {
+        print("    ✗ FAIL: Revoke failed\n\n");
+        return;
+    }
+    if slot_is_empty(grandchild, scratch) {
+        print("    ✓ Grandchild revoked through the endpoint\n");
+    } else {
+        print("    ✗ FAIL: Grandchild survived the revoke\n");
+    }
+
+    print("\n");
+}
+
 #[panic_handler]
 fn panic(_: &core::panic::PanicInfo) -> ! {
     print("[test] PANIC!\n");
@@ -169,7 +169,7 @@ fn test_irq_capabilities() {
             return;
         }
     };
-    printf!("  ✓ PASS: Notification created at slot {}\n", notification_cap);
+    printf!("  ✓ PASS: Notification created at slot {}\n", notification_cap.slot());
 
     // Test 2b: Allocate slot for IRQHandler
     printf!("Test 2b: Allocate capability slot for IRQHandler\n");
@@ -193,7 +193,7 @@ fn test_irq_capabilities() {
     let result = syscall::irq_handler_get(
         fake_irq_control_slot,
         test_irq,
-        notification_cap,
+        &notification_cap,
         irq_handler_slot
     );
 
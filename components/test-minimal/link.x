@@ -18,12 +18,13 @@ SECTIONS
     *(.text .text.*)
   } > RAM
 
-  .rodata : ALIGN(8)
+  /* Text, rodata and data get their own pages, mapped RX, RO and RW */
+  .rodata : ALIGN(4096)
   {
     *(.rodata .rodata.*)
   } > RAM
 
-  .data : ALIGN(8)
+  .data : ALIGN(4096)
   {
     *(.data .data.*)
   } > RAM
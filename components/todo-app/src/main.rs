@@ -5,8 +5,7 @@ use kaal_sdk::{
     component::Component,
     printf,
     syscall,
-    message::{Channel, ChannelConfig as MsgChannelConfig},
-    channel_setup::{establish_channel, ChannelRole},
+    interfaces::{UartInput, UartInputServer},
 };
 use kaal_tui::{screen, cursor, style, draw, ui, Color};
 
@@ -61,7 +60,7 @@ pub struct TodoApp {
     input_buffer: [u8; MAX_TODO_LEN],
     input_len: usize,
     mode: Mode,
-    input_channel: Channel<u8>,
+    input_channel: UartInputServer,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -70,6 +69,13 @@ enum Mode {
     Insert,   // Adding new todo
 }
 
+impl UartInput for TodoApp {
+    fn key(&mut self, byte: u8) {
+        self.handle_input(byte);
+        self.draw();
+    }
+}
+
 impl Component for TodoApp {
     fn init() -> kaal_sdk::Result<Self> {
         // Clear screen and show title
@@ -79,23 +85,13 @@ impl Component for TodoApp {
         printf!("[todo] Todo App starting...\n");
         printf!("[todo] Setting up input channel...\n");
 
-        // Establish IPC channel with UART driver for input
-        // Retry until uart_driver is ready (it may not have started yet)
-        let input_channel = loop {
-            match establish_channel("kaal.uart.output", 4096, ChannelRole::Consumer) {
-                Ok(config) => {
-                    let msg_config = MsgChannelConfig {
-                        shared_memory: config.buffer_addr,
-                        receiver_notify: config.notification_cap as u64,
-                        sender_notify: config.notification_cap as u64,
-                    };
-
-                    break unsafe { Channel::receiver(msg_config) };
-                }
-                Err(_) => {
-                    // UART driver not ready yet, yield and retry
-                    syscall::yield_now();
-                }
+        // Join the UART driver's input channel; uart_driver is in our
+        // depends_on, so it has set the channel up before we are spawned
+        let input_channel = match UartInputServer::connect() {
+            Ok(channel) => channel,
+            Err(e) => {
+                printf!("[todo] Failed to join input channel: {}\n", e);
+                return Err(kaal_sdk::Error::NotFound);
             }
         };
 
@@ -126,10 +122,7 @@ impl Component for TodoApp {
         loop {
             // Wait for input
             match self.input_channel.receive() {
-                Ok(byte) => {
-                    self.handle_input(byte);
-                    self.draw();
-                }
+                Ok(message) => message.dispatch(self),
                 Err(_) => {
                     syscall::yield_now();
                 }
@@ -17,11 +17,11 @@ mod pl011;
 mod ring_buffer;
 
 use kaal_sdk::{
+    capability::{Cap, Notification},
     component::Component,
-    printf,
+    log_debug, log_error, log_info, log_warn,
     syscall,
-    message::{Channel, ChannelConfig as MsgChannelConfig},
-    channel_setup::{establish_channel, ChannelRole},
+    interfaces::UartInputClient,
 };
 use pl011::Pl011;
 use ring_buffer::RingBuffer;
@@ -39,11 +39,11 @@ kaal_sdk::component! {
 pub struct UartDriver {
     uart: Pl011,
     rx_buffer: RingBuffer<4096>,
-    notification_cap: usize,
+    notification_cap: Cap<Notification>,
     irq_handler_slot: usize,
     irq_count: u32,
     char_count: u32,
-    output_channel: Option<Channel<u8>>,
+    output_channel: Option<UartInputClient>,
 }
 
 // Platform constants (from build-config.toml)
@@ -52,25 +52,21 @@ const UART0_SIZE: usize = 0x1000;      // 4KB MMIO region
 const IRQ_CONTROL_SLOT: usize = 1;     // IRQControl capability from root-task (slot 0 is reserved)
 const UART0_IRQ: usize = 33;           // UART0 IRQ number
 
-/// IPC buffer size for output channel (4KB)
-const IPC_BUFFER_SIZE: usize = 4096;
-
 impl Component for UartDriver {
     fn init() -> kaal_sdk::Result<Self> {
 
         // Map UART MMIO region
-        printf!("[uart_driver] Mapping UART0 MMIO: {:#x} ({} bytes)\n", UART0_BASE, UART0_SIZE);
+        log_debug!("Mapping UART0 MMIO: {:#x} ({} bytes)", UART0_BASE, UART0_SIZE);
 
         let uart_virt = match unsafe {
-            syscall::memory_map(UART0_BASE, UART0_SIZE, 0x3) // RW permissions
+            syscall::memory_map(UART0_BASE, UART0_SIZE, 0x13) // RW permissions, device memory
         } {
             Ok(virt) => {
-                printf!("  ✓ Mapped to virtual address: {:#x}\n", virt);
+                log_debug!("Mapped to virtual address: {:#x}", virt);
                 virt
             }
             Err(_) => {
-                printf!("  ✗ FAIL: Failed to map UART MMIO\n");
-                printf!("  Driver requires memory:map capability\n");
+                log_error!("Failed to map UART MMIO (requires memory:map)");
                 return Err(kaal_sdk::Error::SyscallFailed);
             }
         };
@@ -78,51 +74,43 @@ impl Component for UartDriver {
         // Initialize UART hardware
         let mut uart = unsafe { Pl011::new(uart_virt) };
         unsafe { uart.init(); }
-        printf!("[uart_driver] Initialized: 115200 8N1, FIFOs enabled\n");
+        log_info!("Initialized: 115200 8N1, FIFOs enabled");
 
         // Create notification for UART IRQ (separate from IPC notification)
         let irq_notification_cap = syscall::notification_create()?;
         let irq_handler_slot = syscall::cap_allocate()?;
 
         // Bind UART IRQ to IRQ notification
-        printf!("[uart_driver] Binding IRQ {} to notification {}\n", UART0_IRQ, irq_notification_cap);
+        log_debug!("Binding IRQ {} to notification {}", UART0_IRQ, irq_notification_cap.slot());
         match unsafe {
             syscall::irq_handler_get(
                 IRQ_CONTROL_SLOT,
                 UART0_IRQ,
-                irq_notification_cap,
+                &irq_notification_cap,
                 irq_handler_slot,
             )
         } {
             Ok(()) => {
-                printf!("[uart_driver] IRQ {} bound successfully\n", UART0_IRQ);
+                log_debug!("IRQ {} bound successfully", UART0_IRQ);
             }
             Err(_) => {
-                printf!("[uart_driver] WARN: IRQ binding failed (requires IRQControl)\n");
+                log_warn!("IRQ binding failed (requires IRQControl)");
             }
         }
 
-        printf!("[uart_driver] Ready (MMIO: {:#x}, IRQ: {})\n", uart_virt, UART0_IRQ);
+        log_info!("Ready (MMIO: {:#x}, IRQ: {})", uart_virt, UART0_IRQ);
         uart.write_str("\r\nUART driver online\r\n");
 
         // Establish IPC channel with notepad for output
-        printf!("[uart_driver] Establishing output channel to notepad...\n");
-        let output_channel = match establish_channel("kaal.uart.output", IPC_BUFFER_SIZE, ChannelRole::Producer) {
-            Ok(config) => {
-                printf!("[uart_driver] Output channel established (buffer: {:#x})\n", config.buffer_addr);
-                printf!("[uart_driver] IPC notification cap: {}\n", config.notification_cap);
-                // Note: establish_channel() has already initialized SharedRing with notification
-
-                let msg_config = MsgChannelConfig {
-                    shared_memory: config.buffer_addr,
-                    receiver_notify: config.notification_cap as u64,
-                    sender_notify: config.notification_cap as u64,
-                };
-                Some(unsafe { Channel::sender(msg_config) })
+        log_debug!("Establishing output channel to notepad...");
+        let output_channel = match UartInputClient::connect() {
+            Ok(channel) => {
+                log_info!("Output channel established ({})", UartInputClient::CHANNEL);
+                Some(channel)
             }
             Err(e) => {
-                printf!("[uart_driver] WARN: Failed to establish output channel: {}\n", e);
-                printf!("[uart_driver] Will buffer input but not forward to applications\n");
+                log_warn!("Failed to establish output channel: {}", e);
+                log_warn!("Will buffer input but not forward to applications");
                 None
             }
         };
@@ -141,7 +129,7 @@ impl Component for UartDriver {
     fn run(&mut self) -> ! {
         loop {
             // Wait for IRQ notification - this blocks until hardware interrupt fires
-            match syscall::wait(self.notification_cap) {
+            match syscall::wait(&self.notification_cap) {
                 Ok(_badge) => {
                     self.irq_count += 1;
 
@@ -155,7 +143,7 @@ impl Component for UartDriver {
                         match unsafe { syscall::irq_handler_ack(self.irq_handler_slot) } {
                             Ok(_) => {}
                             Err(_) => {
-                                printf!("[uart_driver] ERROR: Failed to ACK IRQ!\n");
+                                log_error!("Failed to ACK IRQ");
                             }
                         }
                     } else {
@@ -165,7 +153,7 @@ impl Component for UartDriver {
                     }
                 }
                 Err(_) => {
-                    printf!("[uart_driver] ERROR: wait() failed!\n");
+                    log_error!("wait() failed");
                     syscall::yield_now();
                 }
             }
@@ -186,16 +174,16 @@ impl UartDriver {
             // Push to stream (SharedRing buffer) - notepad will consume from stream
             if let Some(ref mut channel) = self.output_channel {
                 // Use try_send (non-blocking) - driver should never block
-                if let Err(e) = channel.try_send(byte) {
+                if let Err(e) = channel.try_key(byte) {
                     use kaal_sdk::ipc::IpcError;
                     if !matches!(e, IpcError::BufferFull { .. }) {
-                        printf!("[uart_driver] WARN: Failed to send: {:?}\n", e);
+                        log_warn!("Failed to send: {:?}", e);
                     }
                 }
             } else {
                 // No channel - store in buffer
                 if self.rx_buffer.push(byte).is_err() {
-                    printf!("[uart_driver] WARN: RX buffer overflow!\n");
+                    log_warn!("RX buffer overflow");
                 }
             }
         }
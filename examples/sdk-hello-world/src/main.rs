@@ -25,7 +25,7 @@ pub extern "C" fn _start() -> ! {
 
         // Test 2: Notifications
         syscall::print("[sdk] Test 2: Notification Management\n");
-        match capability::Notification::create() {
+        match capability::Cap::<capability::Notification>::create() {
             Ok(notification) => {
                 syscall::print("  ✓ Created notification using SDK\n");
 
@@ -57,6 +57,9 @@ use crate::objects::TCB;
 /// if it's a new thread).
 #[inline(never)]
 pub unsafe fn switch_context(current: *mut TCB, next: *mut TCB) {
+    // Switch address space first; TLB entries are ASID-tagged, so no flush
+    crate::memory::asid::activate((*next).context().saved_ttbr0);
+
     // Call the assembly implementation
     // Both TCBs have TrapFrame as first field, so we can pass the TCB pointer directly
     switch_context_asm(current as *mut u8, next as *mut u8);
@@ -123,12 +126,8 @@ switch_context_asm:
     // Restore x30 (link register / return address)
     ldr x30, [x1, #(30 * 8)]
 
-    // Switch to next thread's page table (TTBR0_EL1)
-    ldr x10, [x1, #(36 * 8)]  // saved_ttbr0 field (offset 36)
-    msr ttbr0_el1, x10
-    tlbi vmalle1is           // Invalidate all TLB entries
-    dsb ish
-    isb
+    // The next thread's page table (TTBR0_EL1) was already loaded, tagged
+    // with its ASID, by switch_context
 
     // Restore stack pointer for next thread
     ldr x10, [x1, #(31 * 8)]  // sp_el0 from next thread
@@ -190,8 +190,9 @@ global_asm!(
 global_asm!(
     ".global handle_lower_el_aarch64_sync",
     "handle_lower_el_aarch64_sync:",
-    // Save all context to stack (296 bytes = 288 + 8 for TTBR0)
-    "    sub sp, sp, #296",
+    // Save all context to stack: TrapFrame (296 bytes = 288 + 8 for TTBR0),
+    // padded to 304, then the FP/SIMD area (528 bytes) - see fpu.rs
+    "    sub sp, sp, #832",
     "    stp x0, x1, [sp, #0]",
     "    stp x2, x3, [sp, #16]",
     "    stp x4, x5, [sp, #32]",
@@ -220,36 +221,74 @@ global_asm!(
     // we don't need to switch TTBR0. The user page table already contains kernel mappings
     // with EL1-only access permissions, so kernel code can run while user code can't access it.
     "    mrs x5, ttbr0_el1",           // Save user's page table (for debugging)
+    "    and x5, x5, #0xffffffffffff", // Page table root only, without the ASID
     "    str x5, [sp, #288]",          // Store at offset 288
+    // Spill live user FP/SIMD state (only while EL0 has access, see fpu.rs)
+    "    mrs x6, cpacr_el1",
+    "    tbz x6, #21, 3f",
+    "    stp q0, q1, [sp, #304]",
+    "    stp q2, q3, [sp, #336]",
+    "    stp q4, q5, [sp, #368]",
+    "    stp q6, q7, [sp, #400]",
+    "    stp q8, q9, [sp, #432]",
+    "    stp q10, q11, [sp, #464]",
+    "    stp q12, q13, [sp, #496]",
+    "    stp q14, q15, [sp, #528]",
+    "    stp q16, q17, [sp, #560]",
+    "    stp q18, q19, [sp, #592]",
+    "    stp q20, q21, [sp, #624]",
+    "    stp q22, q23, [sp, #656]",
+    "    stp q24, q25, [sp, #688]",
+    "    stp q26, q27, [sp, #720]",
+    "    stp q28, q29, [sp, #752]",
+    "    stp q30, q31, [sp, #784]",
+    "    mrs x7, fpcr",
+    "    mrs x8, fpsr",
+    "    add x9, sp, #816",
+    "    stp x7, x8, [x9]",
+    "3:",
     // No page table switch needed - we stay on the user PT with kernel mappings
     "    mov x0, sp",                  // Pass TrapFrame* to handler
     // Call Rust handler
     "    bl exception_lower_el_aarch64_sync_handler",
-    // No need to restore TTBR0 since we never changed it
+    // Switch to the (possibly new) thread's address space. Its TLB entries
+    // are tagged with its ASID, so no flush is needed (see memory::asid)
+    "    ldr x0, [sp, #288]",          // ttbr0_el1 (saved)
+    "    bl exception_lower_el_activate_vspace",
     // Restore system registers first (using temporary registers)
     "    ldr x10, [sp, #248]",         // sp_el0
     "    ldr x11, [sp, #256]",         // elr_el1
     "    ldr x12, [sp, #264]",         // spsr_el1
-    "    ldr x13, [sp, #288]",         // ttbr0_el1 (saved)
     "    msr sp_el0, x10",
     "    msr elr_el1, x11",
     // Clear IRQ mask bit (I=bit 7) in SPSR before returning to userspace
     "    bic x12, x12, #0x80",         // Clear bit 7 (I bit) - enable IRQs
     "    msr spsr_el1, x12",
-    // Conditional TLB invalidation: Only flush if TTBR0 changed
-    // This is critical - unconditional flush breaks kernel memory access!
-    "    mrs x14, ttbr0_el1",          // Read current TTBR0
-    "    cmp x13, x14",                // Compare saved vs current
-    "    b.eq 1f",                     // Skip TLB flush if unchanged
-    // TTBR0 changed - must flush TLB per ARM architecture requirements
-    "    msr ttbr0_el1, x13",          // Restore new TTBR0
-    "    tlbi vmalle1is",              // Invalidate all TLB entries
-    "    dsb ish",                     // Ensure TLB invalidation completes
-    "    b 2f",
-    "1:",                              // TTBR0 unchanged - no TLB flush needed
-    "    msr ttbr0_el1, x13",          // Restore TTBR0 anyway (cheap)
-    "2:",
     "    isb",                         // Synchronize context
+    // Reload user FP/SIMD state if it is live (possibly just loaded by a trap)
+    "    mrs x15, cpacr_el1",
+    "    tbz x15, #21, 4f",
+    "    ldp q0, q1, [sp, #304]",
+    "    ldp q2, q3, [sp, #336]",
+    "    ldp q4, q5, [sp, #368]",
+    "    ldp q6, q7, [sp, #400]",
+    "    ldp q8, q9, [sp, #432]",
+    "    ldp q10, q11, [sp, #464]",
+    "    ldp q12, q13, [sp, #496]",
+    "    ldp q14, q15, [sp, #528]",
+    "    ldp q16, q17, [sp, #560]",
+    "    ldp q18, q19, [sp, #592]",
+    "    ldp q20, q21, [sp, #624]",
+    "    ldp q22, q23, [sp, #656]",
+    "    ldp q24, q25, [sp, #688]",
+    "    ldp q26, q27, [sp, #720]",
+    "    ldp q28, q29, [sp, #752]",
+    "    ldp q30, q31, [sp, #784]",
+    "    add x17, sp, #816",
+    "    ldp x15, x16, [x17]",
+    "    msr fpcr, x15",
+    "    msr fpsr, x16",
+    "4:",
     // Now restore GPRs (including x0-x3 with potentially modified syscall returns)
     "    ldp x0, x1, [sp, #0]",
     "    ldp x2, x3, [sp, #16]",
@@ -267,7 +306,7 @@ global_asm!(
     "    ldp x26, x27, [sp, #208]",
     "    ldp x28, x29, [sp, #224]",
     "    ldr x30, [sp, #240]",
-    "    add sp, sp, #296",            // Adjusted for new stack frame size
+    "    add sp, sp, #832",            // TrapFrame + FP/SIMD area
     "    eret",
 );
 
@@ -277,8 +316,9 @@ global_asm!(
 global_asm!(
     ".global handle_lower_el_aarch64_irq",
     "handle_lower_el_aarch64_irq:",
-    // Save all context to stack (296 bytes = 288 + 8 for TTBR0)
-    "    sub sp, sp, #296",
+    // Save all context to stack: TrapFrame (296 bytes = 288 + 8 for TTBR0),
+    // padded to 304, then the FP/SIMD area (528 bytes) - see fpu.rs
+    "    sub sp, sp, #832",
     "    stp x0, x1, [sp, #0]",
     "    stp x2, x3, [sp, #16]",
     "    stp x4, x5, [sp, #32]",
@@ -305,32 +345,73 @@ global_asm!(
     "    str x4, [sp, #280]",
     // Save TTBR0 (unified page table design - need to preserve it)
     "    mrs x5, ttbr0_el1",
+    "    and x5, x5, #0xffffffffffff", // Page table root only, without the ASID
     "    str x5, [sp, #288]",
+    // Spill live user FP/SIMD state (only while EL0 has access, see fpu.rs)
+    "    mrs x6, cpacr_el1",
+    "    tbz x6, #21, 3f",
+    "    stp q0, q1, [sp, #304]",
+    "    stp q2, q3, [sp, #336]",
+    "    stp q4, q5, [sp, #368]",
+    "    stp q6, q7, [sp, #400]",
+    "    stp q8, q9, [sp, #432]",
+    "    stp q10, q11, [sp, #464]",
+    "    stp q12, q13, [sp, #496]",
+    "    stp q14, q15, [sp, #528]",
+    "    stp q16, q17, [sp, #560]",
+    "    stp q18, q19, [sp, #592]",
+    "    stp q20, q21, [sp, #624]",
+    "    stp q22, q23, [sp, #656]",
+    "    stp q24, q25, [sp, #688]",
+    "    stp q26, q27, [sp, #720]",
+    "    stp q28, q29, [sp, #752]",
+    "    stp q30, q31, [sp, #784]",
+    "    mrs x7, fpcr",
+    "    mrs x8, fpsr",
+    "    add x9, sp, #816",
+    "    stp x7, x8, [x9]",
+    "3:",
     // No page table switch - unified design
-    // Call Rust IRQ handler
+    // Call Rust IRQ handler (may replace the frame on preemption)
+    "    mov x0, sp",                  // Pass TrapFrame* to handler
     "    bl exception_lower_el_aarch64_irq",
+    // Switch address space if the IRQ preempted the thread (ASID-tagged)
+    "    ldr x0, [sp, #288]",          // ttbr0_el1 (saved)
+    "    bl exception_lower_el_activate_vspace",
     // Restore system registers
     "    ldr x10, [sp, #248]",         // sp_el0
     "    ldr x11, [sp, #256]",         // elr_el1
     "    ldr x12, [sp, #264]",         // spsr_el1
-    "    ldr x13, [sp, #288]",         // ttbr0_el1 (saved)
     "    msr sp_el0, x10",
     "    msr elr_el1, x11",
     // Clear IRQ mask bit (I=bit 7) in SPSR before returning to userspace
     "    bic x12, x12, #0x80",         // Clear bit 7 (I bit) - enable IRQs
     "    msr spsr_el1, x12",
-    // Restore TTBR0 (check if changed, but unlikely for IRQ)
-    "    mrs x14, ttbr0_el1",
-    "    cmp x13, x14",
-    "    b.eq 1f",
-    "    msr ttbr0_el1, x13",
-    "    tlbi vmalle1is",
-    "    dsb ish",
-    "    b 2f",
-    "1:",
-    "    msr ttbr0_el1, x13",
-    "2:",
     "    isb",
+    // Reload user FP/SIMD state if it is live (possibly just loaded by a trap)
+    "    mrs x15, cpacr_el1",
+    "    tbz x15, #21, 4f",
+    "    ldp q0, q1, [sp, #304]",
+    "    ldp q2, q3, [sp, #336]",
+    "    ldp q4, q5, [sp, #368]",
+    "    ldp q6, q7, [sp, #400]",
+    "    ldp q8, q9, [sp, #432]",
+    "    ldp q10, q11, [sp, #464]",
+    "    ldp q12, q13, [sp, #496]",
+    "    ldp q14, q15, [sp, #528]",
+    "    ldp q16, q17, [sp, #560]",
+    "    ldp q18, q19, [sp, #592]",
+    "    ldp q20, q21, [sp, #624]",
+    "    ldp q22, q23, [sp, #656]",
+    "    ldp q24, q25, [sp, #688]",
+    "    ldp q26, q27, [sp, #720]",
+    "    ldp q28, q29, [sp, #752]",
+    "    ldp q30, q31, [sp, #784]",
+    "    add x17, sp, #816",
+    "    ldp x15, x16, [x17]",
+    "    msr fpcr, x15",
+    "    msr fpsr, x16",
+    "4:",
     // Restore GPRs
     "    ldp x0, x1, [sp, #0]",
     "    ldp x2, x3, [sp, #16]",
@@ -348,7 +429,7 @@ global_asm!(
     "    ldp x26, x27, [sp, #208]",
     "    ldp x28, x29, [sp, #224]",
     "    ldr x30, [sp, #240]",
-    "    add sp, sp, #296",
+    "    add sp, sp, #832",
     "    eret",
 );
 
@@ -424,7 +505,9 @@ extern "C" fn exception_curr_el_spx_irq() {
         if let Some(irq_id) = crate::arch::aarch64::gic::acknowledge_irq() {
             // Check if this is the timer IRQ (special case - handled by kernel)
             if irq_id == crate::generated::memory_config::IRQ_TIMER {
-                crate::scheduler::timer::timer_tick();
+                crate::scheduler::timer::kernel_tick();
+            } else if irq_id == super::hyp::PREEMPT_IRQ {
+                // A guest's preemption timer, already stopped on the guest exit
             } else {
                 // Check if a userspace driver has registered for this IRQ
                 crate::objects::irq_handler::handle_irq(irq_id);
@@ -482,10 +565,23 @@ extern "C" fn debug_eret_state(elr: u64, spsr: u64) {
 /// Called from assembly stub with TrapFrame* in x0
 #[no_mangle]
 extern "C" fn exception_lower_el_aarch64_sync_handler(frame: &mut TrapFrame) {
+    unsafe { super::fpu::enter(frame) };
+
     // Extract exception class from ESR_EL1 (bits 26-31)
     let esr = frame.esr_el1;
     let ec = (esr >> 26) & 0x3F;
 
+    // EC 0x07 = FP/SIMD access trapped: load the thread's FP state and retry
+    if ec == 0x07 {
+        unsafe { super::fpu::handle_access_trap() };
+        return;
+    }
+
+    // Write to a copy-on-write page: the page is now a private copy, retry
+    if unsafe { crate::memory::cow::handle_fault(frame) } {
+        return;
+    }
+
     // Debug: Log ALL non-syscall EL0 exceptions
     if ec != 0x15 {
         crate::kprintln!("[exception] EL0 sync: EC={:#x}, PC={:#x}, FAR={:#x}, SP={:#x}",
@@ -502,7 +598,23 @@ extern "C" fn exception_lower_el_aarch64_sync_handler(frame: &mut TrapFrame) {
             crate::kprintln!("  x8={:#x}, x0={:#x}, x1={:#x}",
                             frame.x8, frame.x0, frame.x1);
         }
-        crate::syscall::handle_syscall(frame);
+        #[cfg(feature = "syscall-trace")]
+        let trace = unsafe { crate::syscall::trace::enter(frame) };
+
+        // Call/ReplyRecv handoffs skip the dispatcher and scheduler
+        let fastpath = crate::syscall::fastpath::try_fastpath(frame);
+        if !fastpath {
+            crate::syscall::handle_syscall(frame);
+        }
+
+        #[cfg(feature = "syscall-trace")]
+        unsafe { crate::syscall::trace::exit(trace, frame, fastpath) };
+        return;
+    }
+
+    // Translation/permission faults go to the thread's pager, if it has
+    // one; anything else is a crash its supervisor hears of the same way
+    if unsafe { crate::ipc::fault::handle_user_fault(frame) } {
         return;
     }
 
@@ -524,26 +636,66 @@ extern "C" fn exception_lower_el_aarch64_sync_handler(frame: &mut TrapFrame) {
 }
 
 #[no_mangle]
-extern "C" fn exception_lower_el_aarch64_irq() {
+extern "C" fn exception_lower_el_aarch64_irq(frame: &mut TrapFrame) {
     // IRQ while userspace is running
     unsafe {
-        // Acknowledge interrupt and get IRQ number from GIC
-        if let Some(irq_id) = crate::arch::aarch64::gic::acknowledge_irq() {
-            // Check if this is the timer IRQ (special case - handled by kernel)
-            if irq_id == crate::generated::memory_config::IRQ_TIMER {
-                crate::scheduler::timer::timer_tick();
-                // Timer is kernel-handled, so EOI immediately
-                crate::arch::aarch64::gic::end_of_interrupt(irq_id);
-            } else {
-                // Userspace IRQ - signal driver and DEFER EOI until IRQHandler_Ack
-                // The IRQ is now masked at GIC (IAR read masks it)
-                // Driver will service device, then call IRQHandler_Ack to EOI and unmask
-                crate::objects::irq_handler::handle_irq(irq_id);
-                // DO NOT call end_of_interrupt here - deferred until userspace acks
+        super::fpu::enter(frame);
+        dispatch_irq(Some(frame));
+    }
+}
+
+/// Handle the interrupt the idle loop woke up for
+///
+/// The kernel runs with IRQs masked, so the idle loop takes pending
+/// interrupts itself (see `scheduler::idle`). There is no thread to
+/// preempt: the timer tick is charged like one taken in the kernel.
+///
+/// # Safety
+///
+/// Must be called with IRQs masked, from the idle loop
+pub unsafe fn idle_irq() {
+    dispatch_irq(None);
+}
+
+/// Acknowledge and handle the pending interrupt
+///
+/// `frame` is the interrupted thread's, which a timer tick may preempt;
+/// None when the CPU was idle.
+unsafe fn dispatch_irq(frame: Option<&mut TrapFrame>) {
+    // Acknowledge interrupt and get IRQ number from GIC
+    if let Some(irq_id) = crate::arch::aarch64::gic::acknowledge_irq() {
+        // Check if this is the timer IRQ (special case - handled by kernel)
+        if irq_id == crate::generated::memory_config::IRQ_TIMER {
+            // Timer is kernel-handled, so EOI before a possible switch
+            crate::arch::aarch64::gic::end_of_interrupt(irq_id);
+            match frame {
+                Some(frame) => crate::scheduler::timer::timer_tick(frame),
+                None => crate::scheduler::timer::kernel_tick(),
             }
+        } else if irq_id == super::hyp::PREEMPT_IRQ {
+            // A guest's preemption timer, already stopped on the guest
+            // exit; the kernel tick it stood in for is pending itself
+            crate::arch::aarch64::gic::end_of_interrupt(irq_id);
+        } else if crate::arch::aarch64::gic::msi::is_msi(irq_id) {
+            // MSIs are edge-triggered with no active state: complete now,
+            // the notification remembers the signal
+            crate::arch::aarch64::gic::end_of_interrupt(irq_id);
+            crate::objects::irq_handler::handle_irq(irq_id);
+        } else {
+            // Userspace IRQ - signal driver and DEFER EOI until IRQHandler_Ack
+            // The IRQ is now masked at GIC (IAR read masks it)
+            // Driver will service device, then call IRQHandler_Ack to EOI and unmask
+            crate::objects::irq_handler::handle_irq(irq_id);
+            // DO NOT call end_of_interrupt here - deferred until userspace acks
         }
-        // Spurious IRQ if None - just return
     }
+    // Spurious IRQ if None - just return
+}
+
+/// Load the address space of the thread about to return to EL0
+#[no_mangle]
+extern "C" fn exception_lower_el_activate_vspace(ttbr0: u64) {
+    unsafe { crate::memory::asid::activate(ttbr0) };
 }
 
 #[no_mangle]
@@ -0,0 +1,173 @@
+//! Lazy FP/SIMD Context Switching
+//!
+//! Userspace FP/SIMD state (q0-q31, FPCR, FPSR) is switched lazily. EL0
+//! access to the FP/SIMD registers is disabled (`CPACR_EL1.FPEN = 0b01`)
+//! whenever the running thread's FP state is not loaded, so its first FP
+//! or SIMD instruction traps (EC 0x07). The trap loads the thread's saved
+//! state and enables EL0 access; the state stays live until the next
+//! thread switch, which saves it back into the outgoing TCB.
+//!
+//! The kernel itself is compiled with NEON and uses the vector registers
+//! freely (memcpy and friends), so live user state cannot be left in the
+//! registers across kernel code. The lower-EL exception stubs therefore
+//! spill the registers into an FP area just above the `TrapFrame` on entry
+//! and reload them on exit, but only while EL0 access is enabled - threads
+//! that never touch FP pay nothing.
+//!
+//! ```text
+//! sp + 0            TrapFrame (296 bytes, padded to FP_FRAME_OFFSET)
+//! sp + 304          q0-q31
+//! sp + 816          FPCR, FPSR
+//! sp + EXCEPTION_FRAME_SIZE
+//! ```
+//!
+//! Threads start with all-zero FP state, so nothing leaks between them.
+
+use core::arch::asm;
+use super::context::TrapFrame;
+use crate::objects::TCB;
+
+/// Offset of the FP area from the start of the exception frame
+///
+/// Must match the lower-EL stubs in `exception.rs`.
+pub const FP_FRAME_OFFSET: usize = 304;
+
+/// Size of the whole lower-EL exception frame
+///
+/// Must match the lower-EL stubs in `exception.rs`.
+pub const EXCEPTION_FRAME_SIZE: usize = FP_FRAME_OFFSET + core::mem::size_of::<FpState>();
+
+/// CPACR_EL1.FPEN bit that enables EL0 access (bit 20 enables EL1)
+const CPACR_FPEN_EL0: u64 = 1 << 21;
+
+/// CPACR_EL1.FPEN with EL0 and EL1 access
+const CPACR_FPEN_MASK: u64 = 0b11 << 20;
+
+/// Saved FP/SIMD state of a thread
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FpState {
+    /// Vector registers q0-q31
+    pub q: [u128; 32],
+    /// Floating-point control register
+    pub fpcr: u64,
+    /// Floating-point status register
+    pub fpsr: u64,
+}
+
+impl FpState {
+    /// Initial state: all registers zero, default rounding, no exceptions
+    pub const fn new() -> Self {
+        Self { q: [0; 32], fpcr: 0, fpsr: 0 }
+    }
+}
+
+impl Default for FpState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// FP area of the exception frame being handled
+///
+/// Set on every entry from EL0; holds the current thread's registers while
+/// its FP state is live.
+static mut LIVE_FRAME: *mut FpState = core::ptr::null_mut();
+
+/// Trap EL0 FP/SIMD access until a thread uses it
+///
+/// # Safety
+///
+/// Must be called once during boot, at EL1, before entering userspace.
+pub unsafe fn init() {
+    let mut cpacr: u64;
+    asm!("mrs {}, cpacr_el1", out(reg) cpacr, options(nomem, nostack));
+    cpacr = (cpacr & !CPACR_FPEN_MASK) | (CPACR_FPEN_MASK & !CPACR_FPEN_EL0);
+    asm!("msr cpacr_el1, {}", "isb", in(reg) cpacr, options(nomem, nostack));
+    crate::kprintln!("[fpu] Lazy FP/SIMD switching enabled");
+}
+
+/// Whether EL0 currently has FP/SIMD access (the current thread's state is live)
+#[inline]
+fn el0_enabled() -> bool {
+    let cpacr: u64;
+    unsafe {
+        asm!("mrs {}, cpacr_el1", out(reg) cpacr, options(nomem, nostack));
+    }
+    cpacr & CPACR_FPEN_EL0 != 0
+}
+
+/// Enable or disable EL0 FP/SIMD access
+#[inline]
+unsafe fn set_el0_enabled(enabled: bool) {
+    let mut cpacr: u64;
+    asm!("mrs {}, cpacr_el1", out(reg) cpacr, options(nomem, nostack));
+    if enabled {
+        cpacr |= CPACR_FPEN_EL0;
+    } else {
+        cpacr &= !CPACR_FPEN_EL0;
+    }
+    asm!("msr cpacr_el1, {}", "isb", in(reg) cpacr, options(nomem, nostack));
+}
+
+/// Record the exception frame of an exception taken from EL0
+///
+/// # Safety
+///
+/// `frame` must be the trap frame built by a lower-EL stub in `exception.rs`.
+pub unsafe fn enter(frame: &mut TrapFrame) {
+    LIVE_FRAME = (frame as *mut TrapFrame as *mut u8).add(FP_FRAME_OFFSET) as *mut FpState;
+}
+
+/// Handle an FP/SIMD access trap from EL0
+///
+/// Loads the current thread's FP state into the exception frame and
+/// enables EL0 access; the stub restores the registers and the trapped
+/// instruction is re-executed.
+///
+/// # Safety
+///
+/// Must be called from the lower-EL synchronous exception handler after
+/// `enter`.
+pub unsafe fn handle_access_trap() {
+    let current = crate::scheduler::current_thread();
+    if current.is_null() || LIVE_FRAME.is_null() {
+        panic!("[fpu] FP/SIMD trap without a current thread");
+    }
+
+    *LIVE_FRAME = *(*current).fp_state();
+    set_el0_enabled(true);
+}
+
+/// Save the outgoing thread's FP state on a thread switch
+///
+/// Called for every change of the current thread. If `prev` had its state
+/// live, it is copied out of the exception frame and EL0 access is disabled
+/// again, so `next` traps on its first FP instruction.
+///
+/// # Safety
+///
+/// `prev` must be a valid TCB pointer or null.
+pub unsafe fn switch_out(prev: *mut TCB) {
+    if !el0_enabled() {
+        return;
+    }
+
+    if !prev.is_null() && !LIVE_FRAME.is_null() {
+        *(*prev).fp_state_mut() = *LIVE_FRAME;
+    }
+    set_el0_enabled(false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_layout() {
+        assert_eq!(core::mem::size_of::<FpState>(), 528);
+        assert!(FP_FRAME_OFFSET >= core::mem::size_of::<TrapFrame>());
+        assert_eq!(FP_FRAME_OFFSET % 16, 0);
+        assert_eq!(EXCEPTION_FRAME_SIZE, 832);
+    }
+}
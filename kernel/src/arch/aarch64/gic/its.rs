@@ -0,0 +1,308 @@
+//! GICv3 Interrupt Translation Service (ITS)
+//!
+//! The ITS turns a device's message write (DeviceID, EventID) into an LPI.
+//! It is driven through a command queue in memory and keeps its own
+//! translation tables, which the kernel allocates at init:
+//!
+//! - **Device table**: per-DeviceID pointer to an Interrupt Translation
+//!   Table (ITT), installed with MAPD
+//! - **Collection table**: maps the kernel's single collection to the boot
+//!   CPU's redistributor (MAPC)
+//! - **ITTs**: per-device EventID → LPI mappings (MAPTI), allocated by
+//!   `msi` when a device gets its first vector
+//!
+//! Each command is 32 bytes (four little-endian u64). The kernel issues a
+//! command, bumps GITS_CWRITER and waits for GITS_CREADR to catch up, so
+//! every call here completes synchronously.
+
+use core::arch::asm;
+use core::ptr::{read_volatile, write_volatile};
+use super::v3::TABLE_ATTRS;
+
+// =============================================================================
+// ITS Registers (GITS_*)
+// =============================================================================
+
+/// GITS_CTLR - bit 0: Enabled, bit 31: Quiescent
+const GITS_CTLR: usize = 0x0000;
+
+/// GITS_TYPER - bits 4-7: ITT entry size - 1, bits 8-12: EventID bits - 1,
+/// bits 13-17: DeviceID bits - 1, bit 19: PTA
+const GITS_TYPER: usize = 0x0008;
+
+/// GITS_CBASER - command queue base
+const GITS_CBASER: usize = 0x0080;
+
+/// GITS_CWRITER / GITS_CREADR - command queue write and read offsets
+const GITS_CWRITER: usize = 0x0088;
+const GITS_CREADR: usize = 0x0090;
+
+/// GITS_TRANSLATER - doorbell devices write their EventID to (second 64KB frame)
+const GITS_TRANSLATER: usize = 0x10040;
+
+/// GITS_BASERn - translation table bases (8 registers)
+const GITS_BASER: usize = 0x0100;
+const GITS_BASER_COUNT: usize = 8;
+
+const GITS_CTLR_ENABLED: u32 = 1 << 0;
+const GITS_CTLR_QUIESCENT: u32 = 1 << 31;
+const GITS_TYPER_PTA: u64 = 1 << 19;
+const GITS_CREADR_STALLED: u64 = 1 << 0;
+
+/// GITS_CBASER/GITS_BASERn.Valid
+const GITS_VALID: u64 = 1 << 63;
+
+/// GITS_BASERn.Type values
+const BASER_TYPE_DEVICE: u64 = 1;
+const BASER_TYPE_COLLECTION: u64 = 4;
+
+/// GITS_BASERn.Size/Page_Size: at most 256 pages of 4KB
+const BASER_MAX_PAGES: usize = 256;
+
+// =============================================================================
+// ITS Commands
+// =============================================================================
+
+const CMD_SYNC: u64 = 0x05;
+const CMD_MAPD: u64 = 0x08;
+const CMD_MAPC: u64 = 0x09;
+const CMD_MAPTI: u64 = 0x0A;
+const CMD_INVALL: u64 = 0x0D;
+const CMD_DISCARD: u64 = 0x0F;
+
+/// Alignment of an Interrupt Translation Table
+pub const ITT_ALIGN: usize = 256;
+
+/// Size of one command
+const CMD_SIZE: usize = 32;
+
+/// Command queue size (one page)
+const QUEUE_SIZE: usize = crate::memory::PAGE_SIZE;
+
+/// DeviceID bits the device table covers at most (PCIe requester IDs)
+const MAX_DEVICE_ID_BITS: u32 = 16;
+
+/// The kernel's only interrupt collection, targeting the boot CPU
+pub const COLLECTION: u64 = 0;
+
+/// ITS base address (0 = no ITS)
+static mut ITS_BASE: usize = 0;
+
+/// Command queue (physical = virtual)
+static mut QUEUE: usize = 0;
+
+/// Next write offset in the command queue
+static mut WRITE: usize = 0;
+
+/// Redistributor target in MAPC/SYNC format
+static mut TARGET: u64 = 0;
+
+/// Number of DeviceIDs covered by the device table
+static mut DEVICE_IDS: u64 = 0;
+
+/// ITT entry size in bytes
+static mut ITT_ENTRY_SIZE: usize = 0;
+
+/// Number of EventID bits supported
+static mut EVENT_ID_BITS: u32 = 0;
+
+unsafe fn reg(offset: usize) -> usize {
+    ITS_BASE + offset
+}
+
+/// Initialize the ITS
+///
+/// Allocates the command queue and translation tables, enables the ITS
+/// and maps `COLLECTION` to the redistributor at `rd_base`. Returns false
+/// if the ITS could not be set up.
+///
+/// # Safety
+/// Must be called once from `v3::init`, after LPIs are enabled.
+pub(super) unsafe fn init(its_base: usize, rd_base: usize) -> bool {
+    ITS_BASE = its_base;
+
+    // Disable and wait for quiescence before reprogramming
+    let ctlr = read_volatile(reg(GITS_CTLR) as *const u32);
+    if ctlr & GITS_CTLR_ENABLED != 0 {
+        write_volatile(reg(GITS_CTLR) as *mut u32, ctlr & !GITS_CTLR_ENABLED);
+    }
+    for _ in 0..1_000_000 {
+        if read_volatile(reg(GITS_CTLR) as *const u32) & GITS_CTLR_QUIESCENT != 0 {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+
+    let typer = read_volatile(reg(GITS_TYPER) as *const u64);
+    let event_id_bits = (((typer >> 8) & 0x1F) + 1) as u32;
+    ITT_ENTRY_SIZE = (((typer >> 4) & 0xF) + 1) as usize;
+    EVENT_ID_BITS = event_id_bits;
+    let device_id_bits = core::cmp::min((((typer >> 13) & 0x1F) + 1) as u32, MAX_DEVICE_ID_BITS);
+
+    // Command queue
+    let Some(queue) = crate::memory::alloc_frame() else {
+        crate::kprintln!("[ITS] ERROR: Failed to allocate command queue");
+        return false;
+    };
+    QUEUE = queue.phys_addr().as_usize();
+    core::ptr::write_bytes(QUEUE as *mut u8, 0, QUEUE_SIZE);
+    write_volatile(reg(GITS_CBASER) as *mut u64,
+                   GITS_VALID | QUEUE as u64 | TABLE_ATTRS | (QUEUE_SIZE / crate::memory::PAGE_SIZE - 1) as u64);
+    WRITE = 0;
+    write_volatile(reg(GITS_CWRITER) as *mut u64, 0);
+
+    // Translation tables
+    let mut device_ids = 0;
+    for n in 0..GITS_BASER_COUNT {
+        let baser = reg(GITS_BASER + n * 8);
+        let value = read_volatile(baser as *const u64);
+        let kind = (value >> 56) & 0x7;
+        let entry_size = (((value >> 48) & 0x1F) + 1) as usize;
+
+        let entries = match kind {
+            BASER_TYPE_DEVICE => 1usize << device_id_bits,
+            BASER_TYPE_COLLECTION => 1,
+            _ => continue,
+        };
+        let pages = core::cmp::min((entries * entry_size).div_ceil(crate::memory::PAGE_SIZE), BASER_MAX_PAGES);
+        let Some(table) = crate::memory::alloc_contiguous(pages, crate::memory::PAGE_SIZE) else {
+            crate::kprintln!("[ITS] ERROR: Failed to allocate {} page table", pages);
+            return false;
+        };
+        let table = table.phys_addr().as_usize();
+        core::ptr::write_bytes(table as *mut u8, 0, pages * crate::memory::PAGE_SIZE);
+
+        // 4KB pages (Page_Size = 0), flat table
+        write_volatile(baser as *mut u64,
+                       GITS_VALID | (kind << 56) | (((entry_size - 1) as u64) << 48)
+                       | table as u64 | TABLE_ATTRS | (pages - 1) as u64);
+
+        if kind == BASER_TYPE_DEVICE {
+            device_ids = ((pages * crate::memory::PAGE_SIZE) / entry_size) as u64;
+        }
+    }
+    asm!("dsb sy", options(nostack));
+
+    write_volatile(reg(GITS_CTLR) as *mut u32, GITS_CTLR_ENABLED);
+
+    // MAPC/SYNC name the redistributor by physical address or processor number
+    TARGET = if typer & GITS_TYPER_PTA != 0 {
+        rd_base as u64 & 0x000F_FFFF_FFFF_0000
+    } else {
+        let gicr_typer = read_volatile((rd_base + 0x0008) as *const u64);
+        ((gicr_typer >> 8) & 0xFFFF) << 16
+    };
+
+    if device_ids == 0 {
+        crate::kprintln!("[ITS] ERROR: No device table");
+        return false;
+    }
+    if !send([CMD_MAPC, 0, GITS_VALID | TARGET | COLLECTION, 0]) || !sync() {
+        return false;
+    }
+    DEVICE_IDS = device_ids;
+
+    crate::kprintln!("[ITS] Initialized at {:#x}: {} DeviceIDs, {} EventID bits",
+                     its_base, device_ids, event_id_bits);
+    true
+}
+
+/// Whether the ITS is running
+pub fn available() -> bool {
+    unsafe { DEVICE_IDS != 0 }
+}
+
+/// Doorbell address a device writes its EventID to (GITS_TRANSLATER)
+pub fn doorbell() -> u64 {
+    unsafe { (ITS_BASE + GITS_TRANSLATER) as u64 }
+}
+
+/// Whether `device_id` is covered by the device table
+pub fn valid_device(device_id: u32) -> bool {
+    unsafe { (device_id as u64) < DEVICE_IDS }
+}
+
+/// Size in bytes of an ITT holding 2^`event_bits` events
+pub fn itt_size(event_bits: u32) -> usize {
+    unsafe { (ITT_ENTRY_SIZE << event_bits).max(ITT_ALIGN) }
+}
+
+/// Largest number of EventID bits the ITS supports
+pub fn max_event_bits() -> u32 {
+    unsafe { EVENT_ID_BITS }
+}
+
+/// Install (or with `itt` = None, remove) a device's ITT
+///
+/// # Safety
+/// `itt` must be zeroed, `ITT_ALIGN`-aligned memory of `itt_size(event_bits)`
+/// bytes that stays allocated while mapped.
+pub unsafe fn map_device(device_id: u32, itt: Option<usize>, event_bits: u32) -> bool {
+    let dw2 = match itt {
+        Some(itt) => GITS_VALID | (itt as u64 & 0x000F_FFFF_FFFF_FF00),
+        None => 0,
+    };
+    send([CMD_MAPD | ((device_id as u64) << 32), (event_bits - 1) as u64, dw2, 0]) && sync()
+}
+
+/// Translate (device_id, event) into `lpi`, delivered to `COLLECTION`
+///
+/// # Safety
+/// The device must be mapped with `map_device`.
+pub unsafe fn map_event(device_id: u32, event: u32, lpi: u32) -> bool {
+    send([CMD_MAPTI | ((device_id as u64) << 32), event as u64 | ((lpi as u64) << 32), COLLECTION, 0])
+        && sync()
+}
+
+/// Remove the translation for (device_id, event) and drop any pending LPI
+///
+/// # Safety
+/// The device must be mapped with `map_device`.
+pub unsafe fn unmap_event(device_id: u32, event: u32) -> bool {
+    send([CMD_DISCARD | ((device_id as u64) << 32), event as u64, 0, 0]) && sync()
+}
+
+/// Queue a command and wait for the ITS to consume it
+///
+/// Returns false if the ITS stalled or did not respond.
+unsafe fn send(cmd: [u64; 4]) -> bool {
+    let slot = (QUEUE + WRITE) as *mut u64;
+    for (i, word) in cmd.iter().enumerate() {
+        write_volatile(slot.add(i), *word);
+    }
+    asm!("dsb sy", options(nostack));
+
+    WRITE = (WRITE + CMD_SIZE) % QUEUE_SIZE;
+    write_volatile(reg(GITS_CWRITER) as *mut u64, WRITE as u64);
+
+    for _ in 0..1_000_000 {
+        let readr = read_volatile(reg(GITS_CREADR) as *const u64);
+        if readr & GITS_CREADR_STALLED != 0 {
+            crate::kprintln!("[ITS] ERROR: Command queue stalled (command {:#x})", cmd[0] & 0xFF);
+            return false;
+        }
+        if readr as usize & (QUEUE_SIZE - 1) == WRITE {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+
+    crate::kprintln!("[ITS] ERROR: Command {:#x} timed out", cmd[0] & 0xFF);
+    false
+}
+
+/// Wait until earlier commands' effects are visible at the redistributor
+unsafe fn sync() -> bool {
+    send([CMD_SYNC, 0, TARGET, 0])
+}
+
+/// Make the redistributor reload the LPI configuration table
+///
+/// # Safety
+/// Must not race with other ITS commands (kernel context).
+pub(super) unsafe fn invalidate_all() {
+    if available() {
+        send([CMD_INVALL, 0, COLLECTION, 0]);
+        sync();
+    }
+}
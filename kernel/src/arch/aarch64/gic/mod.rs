@@ -0,0 +1,163 @@
+//! ARM Generic Interrupt Controller (GIC)
+//!
+//! Two interrupt controller generations are supported:
+//! - `v2`: GICv2 (memory-mapped CPU interface), e.g. QEMU virt's default
+//! - `v3`: GICv3 (redistributors, system register CPU interface) with LPIs
+//!   delivered through the Interrupt Translation Service (`its`), e.g.
+//!   QEMU `-machine virt,gic-version=3` and most newer boards
+//!
+//! On GICv3, `msi` allocates message-signaled interrupts for PCIe devices
+//! on top of the ITS.
+//!
+//! The version and register addresses are taken from the device tree
+//! (`boot::dtb::find_gic`) early in boot. Without a GIC node the build's
+//! configured GICv2 addresses are used. The rest of the kernel calls the
+//! functions here, which dispatch to the detected version.
+
+pub mod v2;
+pub mod v3;
+pub mod its;
+pub mod msi;
+
+pub use crate::boot::dtb::{GicInfo, GicVersion};
+use crate::generated::memory_config::{GIC_DIST_BASE, GIC_DIST_SIZE, GIC_CPU_BASE, GIC_CPU_SIZE};
+
+/// Maximum number of interrupts supported (32 SGI/PPI + 988 SPI)
+pub const MAX_IRQS: usize = 1020;
+
+/// Active GIC configuration (set by `configure` before the MMU is enabled)
+static mut CONFIG: GicInfo = GicInfo {
+    version: GicVersion::V2,
+    dist_base: GIC_DIST_BASE,
+    dist_size: GIC_DIST_SIZE,
+    cpu_base: GIC_CPU_BASE,
+    cpu_size: GIC_CPU_SIZE,
+    redist_base: 0,
+    redist_size: 0,
+    its_base: 0,
+    its_size: 0,
+};
+
+/// Select the interrupt controller found in the device tree
+///
+/// `None` keeps the build's configured GICv2.
+///
+/// # Safety
+/// Must be called once during boot, before the GIC regions are mapped.
+pub unsafe fn configure(info: Option<GicInfo>) {
+    match info {
+        Some(info) => {
+            let name = match info.version {
+                GicVersion::V2 => "GICv2",
+                GicVersion::V3 => "GICv3",
+            };
+            crate::kprintln!("[GIC] Device tree: {}, distributor {:#x}", name, info.dist_base);
+            CONFIG = info;
+        }
+        None => crate::kprintln!("[GIC] No GIC in device tree, using configured GICv2 at {:#x}", GIC_DIST_BASE),
+    }
+}
+
+/// Active GIC configuration
+pub fn config() -> &'static GicInfo {
+    unsafe { &*core::ptr::addr_of!(CONFIG) }
+}
+
+/// Detected GIC version
+pub fn version() -> GicVersion {
+    config().version
+}
+
+/// Register regions the kernel accesses, as (base, size)
+///
+/// Each must be identity-mapped in every address space the kernel runs
+/// on. Only the boot CPU's redistributor is included.
+pub fn mmio_regions() -> impl Iterator<Item = (usize, usize)> {
+    let config = config();
+    [
+        (config.dist_base, config.dist_size),
+        (config.cpu_base, config.cpu_size),
+        (config.redist_base, core::cmp::min(config.redist_size, v3::REDIST_WINDOW)),
+        (config.its_base, config.its_size),
+    ]
+    .into_iter()
+    .filter(|&(_, size)| size != 0)
+}
+
+/// Initialize the GIC
+///
+/// # Safety
+/// Must be called once during boot, after the MMU is enabled and the
+/// frame allocator is initialized.
+pub unsafe fn init() {
+    match version() {
+        GicVersion::V2 => v2::init(),
+        GicVersion::V3 => v3::init(),
+    }
+}
+
+/// Enable a specific interrupt
+///
+/// # Safety
+/// Must be called with a valid IRQ number
+pub unsafe fn enable_irq(irq: u32) {
+    match version() {
+        GicVersion::V2 => v2::enable_irq(irq),
+        GicVersion::V3 => v3::enable_irq(irq),
+    }
+}
+
+/// Disable a specific interrupt
+///
+/// # Safety
+/// Must be called with a valid IRQ number
+pub unsafe fn disable_irq(irq: u32) {
+    match version() {
+        GicVersion::V2 => v2::disable_irq(irq),
+        GicVersion::V3 => v3::disable_irq(irq),
+    }
+}
+
+/// Set interrupt priority (0 = highest, 255 = lowest)
+///
+/// # Safety
+/// Must be called with a valid IRQ number
+pub unsafe fn set_priority(irq: u32, priority: u8) {
+    match version() {
+        GicVersion::V2 => v2::set_priority(irq, priority),
+        GicVersion::V3 => v3::set_priority(irq, priority),
+    }
+}
+
+/// Acknowledge an interrupt and return its ID (None if spurious)
+///
+/// # Safety
+/// Must be called from IRQ context
+pub unsafe fn acknowledge_irq() -> Option<u32> {
+    match version() {
+        GicVersion::V2 => v2::acknowledge_irq(),
+        GicVersion::V3 => v3::acknowledge_irq(),
+    }
+}
+
+/// Signal end of interrupt processing
+///
+/// # Safety
+/// Must be called from IRQ context with the correct IRQ ID
+pub unsafe fn end_of_interrupt(irq: u32) {
+    match version() {
+        GicVersion::V2 => v2::end_of_interrupt(irq),
+        GicVersion::V3 => v3::end_of_interrupt(irq),
+    }
+}
+
+/// Get the highest priority pending interrupt (without acknowledging)
+///
+/// # Safety
+/// Safe to call from any context
+pub unsafe fn get_highest_pending() -> Option<u32> {
+    match version() {
+        GicVersion::V2 => v2::get_highest_pending(),
+        GicVersion::V3 => v3::get_highest_pending(),
+    }
+}
@@ -0,0 +1,177 @@
+//! Message-Signaled Interrupts (MSI/MSI-X)
+//!
+//! A PCIe device raises an MSI by writing a message (data) to an address.
+//! On GICv3 the address is the ITS doorbell and the data is an EventID;
+//! the ITS, which sees the writer's DeviceID (the PCIe requester ID),
+//! translates the pair into an LPI.
+//!
+//! Vectors are allocated per device: the first vector of a DeviceID
+//! installs an ITT with room for `EVENTS_PER_DEVICE` events, and each
+//! vector takes an LPI from the kernel's MSI range (`MSI_BASE` ..
+//! `MSI_BASE + MAX_MSIS`). The message is handed back to the driver, which
+//! programs it into the device's MSI capability or MSI-X table.
+//!
+//! GICv2 has no ITS, so MSI allocation fails there.
+
+use super::{its, v3, GicVersion};
+
+/// First MSI INTID (the start of the LPI range)
+pub const MSI_BASE: u32 = v3::LPI_BASE;
+
+/// Number of MSI vectors the kernel hands out
+pub const MAX_MSIS: usize = 256;
+
+/// Number of devices that can have MSIs at once
+pub const MAX_MSI_DEVICES: usize = 32;
+
+/// EventID bits per device ITT
+const EVENT_BITS: u32 = 5;
+
+/// Vectors per device
+pub const EVENTS_PER_DEVICE: usize = 1 << EVENT_BITS;
+
+/// Message a device writes to raise an MSI
+#[derive(Debug, Clone, Copy)]
+pub struct MsiMessage {
+    /// Doorbell address
+    pub address: u64,
+    /// Message data (the EventID)
+    pub data: u32,
+}
+
+/// A device with at least one vector
+#[derive(Clone, Copy)]
+struct MsiDevice {
+    device_id: u32,
+    /// ITT frame (0 = slot unused)
+    itt: usize,
+    /// Allocated events (bit n = EventID n)
+    events: u32,
+}
+
+static mut DEVICES: [MsiDevice; MAX_MSI_DEVICES] =
+    [MsiDevice { device_id: 0, itt: 0, events: 0 }; MAX_MSI_DEVICES];
+
+/// Owner of each MSI vector: (device slot, EventID)
+static mut VECTORS: [Option<(usize, u32)>; MAX_MSIS] = [None; MAX_MSIS];
+
+/// Whether `irq` is in the MSI range
+pub fn is_msi(irq: u32) -> bool {
+    irq >= MSI_BASE && ((irq - MSI_BASE) as usize) < MAX_MSIS
+}
+
+/// Whether MSIs can be allocated on this system
+pub fn available() -> bool {
+    super::version() == GicVersion::V3 && v3::lpis_enabled() && its::available()
+}
+
+/// Lowest clear bit in `mask` below `limit`
+fn first_free(mask: u32, limit: usize) -> Option<u32> {
+    (0..limit as u32).find(|bit| mask & (1 << bit) == 0)
+}
+
+/// Find the device slot for `device_id`, installing an ITT if it has none
+unsafe fn device_slot(device_id: u32, event_bits: u32) -> Option<usize> {
+    let devices = &mut *core::ptr::addr_of_mut!(DEVICES);
+    if let Some(slot) = devices.iter().position(|d| d.itt != 0 && d.device_id == device_id) {
+        return Some(slot);
+    }
+
+    let slot = devices.iter().position(|d| d.itt == 0)?;
+    // At most 32 events of 16 bytes: one (page-aligned) frame
+    let frame = crate::memory::alloc_frame()?;
+    let itt = frame.phys_addr().as_usize();
+    core::ptr::write_bytes(itt as *mut u8, 0, its::itt_size(event_bits));
+
+    if !its::map_device(device_id, Some(itt), event_bits) {
+        crate::memory::dealloc_frame(frame);
+        return None;
+    }
+
+    devices[slot] = MsiDevice { device_id, itt, events: 0 };
+    Some(slot)
+}
+
+/// Allocate an MSI vector for a device
+///
+/// # Arguments
+/// * `device_id` - The device's ITS DeviceID (the PCIe requester ID,
+///   bus << 8 | device << 3 | function, on the usual `msi-map` layout)
+///
+/// # Returns
+/// The vector's INTID and the message to program into the device, or None
+/// if MSIs are unavailable or the device or kernel is out of vectors. The
+/// vector is enabled.
+///
+/// # Safety
+/// Must be called from syscall context.
+pub unsafe fn alloc(device_id: u32) -> Option<(u32, MsiMessage)> {
+    if !available() || !its::valid_device(device_id) {
+        return None;
+    }
+
+    let vectors = &mut *core::ptr::addr_of_mut!(VECTORS);
+    let index = vectors.iter().position(|v| v.is_none())?;
+    let event_bits = core::cmp::min(EVENT_BITS, its::max_event_bits());
+    let slot = device_slot(device_id, event_bits)?;
+
+    let devices = &mut *core::ptr::addr_of_mut!(DEVICES);
+    let event = first_free(devices[slot].events, 1 << event_bits)?;
+    let irq = MSI_BASE + index as u32;
+    if !its::map_event(device_id, event, irq) {
+        return None;
+    }
+
+    devices[slot].events |= 1 << event;
+    vectors[index] = Some((slot, event));
+    super::enable_irq(irq);
+
+    Some((irq, MsiMessage { address: its::doorbell(), data: event }))
+}
+
+/// Release an MSI vector
+///
+/// Disables the LPI and removes its translation; the device's ITT is
+/// released with its last vector.
+///
+/// # Safety
+/// Must be called from syscall context; `irq` must no longer be in use.
+pub unsafe fn free(irq: u32) {
+    if !is_msi(irq) {
+        return;
+    }
+    let vectors = &mut *core::ptr::addr_of_mut!(VECTORS);
+    let Some((slot, event)) = vectors[(irq - MSI_BASE) as usize].take() else {
+        return;
+    };
+
+    super::disable_irq(irq);
+    let devices = &mut *core::ptr::addr_of_mut!(DEVICES);
+    let device = &mut devices[slot];
+    its::unmap_event(device.device_id, event);
+    device.events &= !(1 << event);
+
+    if device.events == 0 {
+        its::map_device(device.device_id, None, 1);
+        let frame = crate::memory::PhysAddr::new(device.itt);
+        crate::memory::dealloc_frame(crate::memory::PageFrameNumber::from_phys_addr(frame));
+        device.itt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msi_range_and_event_allocation() {
+        assert!(!is_msi(MSI_BASE - 1));
+        assert!(is_msi(MSI_BASE));
+        assert!(!is_msi(MSI_BASE + MAX_MSIS as u32));
+
+        assert_eq!(first_free(0, EVENTS_PER_DEVICE), Some(0));
+        assert_eq!(first_free(0b1011, EVENTS_PER_DEVICE), Some(2));
+        assert_eq!(first_free(u32::MAX, EVENTS_PER_DEVICE), None);
+        assert_eq!(first_free(0b11, 2), None);
+    }
+}
@@ -19,19 +19,21 @@
 //!
 //! ## Platform-Specific IRQ Mapping
 //! IRQ numbers are defined in build-config.toml and generated at build time.
-//! See `kernel/src/generated/memory_config.rs` for actual values.
+//! See `kernel/src/generated/memory_config.rs` for actual values. Register
+//! base addresses come from the device tree (see `gic::configure`).
 
 use core::ptr::{read_volatile, write_volatile};
-use crate::generated::memory_config::{GIC_DIST_BASE, GIC_CPU_BASE};
+use super::MAX_IRQS;
 
-/// GIC Distributor base address (from platform configuration)
-const GICD_BASE: usize = GIC_DIST_BASE;
-
-/// GIC CPU Interface base address (from platform configuration)
-const GICC_BASE: usize = GIC_CPU_BASE;
+/// Address of a GIC Distributor register
+fn gicd(offset: usize) -> usize {
+    super::config().dist_base + offset
+}
 
-/// Maximum number of interrupts supported (32 SGI/PPI + 988 SPI)
-pub const MAX_IRQS: usize = 1020;
+/// Address of a GIC CPU Interface register
+fn gicc(offset: usize) -> usize {
+    super::config().cpu_base + offset
+}
 
 // =============================================================================
 // GIC Distributor Registers (GICD_*)
@@ -40,44 +42,44 @@ pub const MAX_IRQS: usize = 1020;
 /// GICD_CTLR - Distributor Control Register
 /// Bit 0: Enable Group 0 interrupts
 /// Bit 1: Enable Group 1 interrupts
-const GICD_CTLR: usize = GICD_BASE + 0x000;
+const GICD_CTLR: usize = 0x000;
 
 /// GICD_TYPER - Interrupt Controller Type Register
 /// Bits 0-4: Number of implemented ITLinesNumber (N)
 ///           Total SPIs = 32 * (N + 1)
-const GICD_TYPER: usize = GICD_BASE + 0x004;
+const GICD_TYPER: usize = 0x004;
 
 /// GICD_ISENABLERn - Interrupt Set-Enable Registers
 /// Each bit enables one interrupt (write 1 to enable)
 /// Register n controls interrupts [32n : 32n+31]
-const GICD_ISENABLER: usize = GICD_BASE + 0x100;
+const GICD_ISENABLER: usize = 0x100;
 
 /// GICD_ICENABLERn - Interrupt Clear-Enable Registers
 /// Each bit disables one interrupt (write 1 to disable)
-const GICD_ICENABLER: usize = GICD_BASE + 0x180;
+const GICD_ICENABLER: usize = 0x180;
 
 /// GICD_ISPENDRn - Interrupt Set-Pending Registers
 /// Each bit sets interrupt to pending state (for testing)
-const GICD_ISPENDR: usize = GICD_BASE + 0x200;
+const GICD_ISPENDR: usize = 0x200;
 
 /// GICD_ICPENDRn - Interrupt Clear-Pending Registers
 /// Each bit clears pending state
-const GICD_ICPENDR: usize = GICD_BASE + 0x280;
+const GICD_ICPENDR: usize = 0x280;
 
 /// GICD_IPRIORITYRn - Interrupt Priority Registers
 /// 8 bits per interrupt (0 = highest priority, 255 = lowest)
-const GICD_IPRIORITYR: usize = GICD_BASE + 0x400;
+const GICD_IPRIORITYR: usize = 0x400;
 
 /// GICD_ITARGETSRn - Interrupt Processor Targets Registers
 /// 8 bits per interrupt, each bit represents a CPU core
 /// Bit 0 = CPU0, Bit 1 = CPU1, etc.
-const GICD_ITARGETSR: usize = GICD_BASE + 0x800;
+const GICD_ITARGETSR: usize = 0x800;
 
 /// GICD_ICFGRn - Interrupt Configuration Registers
 /// 2 bits per interrupt:
 /// - Bit 0: Reserved (should be 0)
 /// - Bit 1: 0 = level-sensitive, 1 = edge-triggered
-const GICD_ICFGR: usize = GICD_BASE + 0xC00;
+const GICD_ICFGR: usize = 0xC00;
 
 // =============================================================================
 // GIC CPU Interface Registers (GICC_*)
@@ -87,34 +89,34 @@ const GICD_ICFGR: usize = GICD_BASE + 0xC00;
 /// Bit 0: Enable Group 0 interrupts
 /// Bit 1: Enable Group 1 interrupts
 /// Bit 9: EOImode (0 = priority drop and deactivate, 1 = priority drop only)
-const GICC_CTLR: usize = GICC_BASE + 0x000;
+const GICC_CTLR: usize = 0x000;
 
 /// GICC_PMR - Interrupt Priority Mask Register
 /// Interrupts with priority lower than this value are masked
 /// 0 = all masked, 255 = all enabled
-const GICC_PMR: usize = GICC_BASE + 0x004;
+const GICC_PMR: usize = 0x004;
 
 /// GICC_BPR - Binary Point Register
 /// Controls priority grouping for preemption
-const GICC_BPR: usize = GICC_BASE + 0x008;
+const GICC_BPR: usize = 0x008;
 
 /// GICC_IAR - Interrupt Acknowledge Register
 /// Read to acknowledge an interrupt and get its ID
 /// Bits 0-9: Interrupt ID
 /// Bits 10-12: CPU ID (for SGIs)
-const GICC_IAR: usize = GICC_BASE + 0x00C;
+const GICC_IAR: usize = 0x00C;
 
 /// GICC_EOIR - End of Interrupt Register
 /// Write interrupt ID here to signal completion
-const GICC_EOIR: usize = GICC_BASE + 0x010;
+const GICC_EOIR: usize = 0x010;
 
 /// GICC_RPR - Running Priority Register (read-only)
 /// Shows current running priority
-const GICC_RPR: usize = GICC_BASE + 0x014;
+const GICC_RPR: usize = 0x014;
 
 /// GICC_HPPIR - Highest Priority Pending Interrupt Register (read-only)
 /// Shows ID of highest priority pending interrupt
-const GICC_HPPIR: usize = GICC_BASE + 0x018;
+const GICC_HPPIR: usize = 0x018;
 
 /// Special interrupt ID returned when no interrupt is pending
 const SPURIOUS_IRQ: u32 = 1023;
@@ -130,48 +132,52 @@ const SPURIOUS_IRQ: u32 = 1023;
 /// 2. Disable all interrupts
 /// 3. Configure default priorities and targets
 /// 4. Enable the GIC distributor and CPU interface
+///
+/// # Safety
+/// Must be called once during boot, after `gic::configure` has set the
+/// GICv2 base addresses.
 pub unsafe fn init() {
     crate::kprintln!("[GIC] Initializing GICv2...");
 
     // Read GIC type to discover number of interrupt lines
-    let typer = read_volatile(GICD_TYPER as *const u32);
+    let typer = read_volatile(gicd(GICD_TYPER) as *const u32);
     let itlines = (typer & 0x1F) as usize; // Bits 0-4
     let max_irqs = 32 * (itlines + 1);
     crate::kprintln!("[GIC] ITLinesNumber: {}, Max IRQs: {}", itlines, max_irqs);
 
     // Disable distributor while configuring
-    write_volatile(GICD_CTLR as *mut u32, 0);
+    write_volatile(gicd(GICD_CTLR) as *mut u32, 0);
 
     // Disable all interrupts
     for i in 0..((max_irqs + 31) / 32) {
-        write_volatile((GICD_ICENABLER + i * 4) as *mut u32, 0xFFFFFFFF);
+        write_volatile(gicd(GICD_ICENABLER + i * 4) as *mut u32, 0xFFFFFFFF);
     }
 
     // Clear all pending interrupts
     for i in 0..((max_irqs + 31) / 32) {
-        write_volatile((GICD_ICPENDR + i * 4) as *mut u32, 0xFFFFFFFF);
+        write_volatile(gicd(GICD_ICPENDR + i * 4) as *mut u32, 0xFFFFFFFF);
     }
 
     // Set default priority (0xA0 = 160) for all interrupts
     // Priority range: 0 (highest) - 255 (lowest)
     for i in 0..(max_irqs / 4) {
-        write_volatile((GICD_IPRIORITYR + i * 4) as *mut u32, 0xA0A0A0A0);
+        write_volatile(gicd(GICD_IPRIORITYR + i * 4) as *mut u32, 0xA0A0A0A0);
     }
 
     // Route all SPIs to CPU 0
     // First 32 interrupts (SGI/PPI) are banked per-CPU, so start from 32
     for i in (32 / 4)..(max_irqs / 4) {
-        write_volatile((GICD_ITARGETSR + i * 4) as *mut u32, 0x01010101); // Target CPU 0
+        write_volatile(gicd(GICD_ITARGETSR + i * 4) as *mut u32, 0x01010101); // Target CPU 0
     }
 
     // Configure all SPIs as level-sensitive (default)
     // Bits [1:0] are read-only (SGI), start from register 1
     for i in 1..((max_irqs + 15) / 16) {
-        write_volatile((GICD_ICFGR + i * 4) as *mut u32, 0x00000000);
+        write_volatile(gicd(GICD_ICFGR + i * 4) as *mut u32, 0x00000000);
     }
 
     // Enable distributor (Group 0 interrupts)
-    write_volatile(GICD_CTLR as *mut u32, 0x1);
+    write_volatile(gicd(GICD_CTLR) as *mut u32, 0x1);
 
     // Initialize CPU interface
     init_cpu_interface();
@@ -185,16 +191,16 @@ pub unsafe fn init() {
 /// For now, we only support single-core (CPU 0).
 unsafe fn init_cpu_interface() {
     // Disable CPU interface while configuring
-    write_volatile(GICC_CTLR as *mut u32, 0);
+    write_volatile(gicc(GICC_CTLR) as *mut u32, 0);
 
     // Set priority mask to allow all interrupts (255 = lowest priority)
-    write_volatile(GICC_PMR as *mut u32, 0xFF);
+    write_volatile(gicc(GICC_PMR) as *mut u32, 0xFF);
 
     // Set binary point to 0 (no grouping, all 8 bits for priority)
-    write_volatile(GICC_BPR as *mut u32, 0);
+    write_volatile(gicc(GICC_BPR) as *mut u32, 0);
 
     // Enable CPU interface (Group 0 interrupts)
-    write_volatile(GICC_CTLR as *mut u32, 0x1);
+    write_volatile(gicc(GICC_CTLR) as *mut u32, 0x1);
 
     crate::kprintln!("[GIC] CPU interface initialized");
 }
@@ -217,7 +223,7 @@ pub unsafe fn enable_irq(irq: u32) {
 
     // Write 1 to the corresponding bit to enable
     write_volatile(
-        (GICD_ISENABLER + reg * 4) as *mut u32,
+        gicd(GICD_ISENABLER + reg * 4) as *mut u32,
         1 << bit,
     );
 
@@ -241,7 +247,7 @@ pub unsafe fn disable_irq(irq: u32) {
 
     // Write 1 to the corresponding bit to disable
     write_volatile(
-        (GICD_ICENABLER + reg * 4) as *mut u32,
+        gicd(GICD_ICENABLER + reg * 4) as *mut u32,
         1 << bit,
     );
 
@@ -265,7 +271,7 @@ pub unsafe fn set_priority(irq: u32, priority: u8) {
     let offset = (irq % 4) * 8;
 
     // Read-modify-write to set priority for this IRQ
-    let addr = (GICD_IPRIORITYR + reg * 4) as *mut u32;
+    let addr = gicd(GICD_IPRIORITYR + reg * 4) as *mut u32;
     let mut val = read_volatile(addr);
     val &= !(0xFF << offset);
     val |= (priority as u32) << offset;
@@ -280,7 +286,7 @@ pub unsafe fn set_priority(irq: u32, priority: u8) {
 /// # Safety
 /// Must be called from IRQ context
 pub unsafe fn acknowledge_irq() -> Option<u32> {
-    let iar = read_volatile(GICC_IAR as *const u32);
+    let iar = read_volatile(gicc(GICC_IAR) as *const u32);
     let irq_id = iar & 0x3FF; // Bits 0-9
 
     if irq_id == SPURIOUS_IRQ {
@@ -302,7 +308,7 @@ pub unsafe fn acknowledge_irq() -> Option<u32> {
 /// # Safety
 /// Must be called from IRQ context with the correct IRQ ID
 pub unsafe fn end_of_interrupt(irq: u32) {
-    write_volatile(GICC_EOIR as *mut u32, irq);
+    write_volatile(gicc(GICC_EOIR) as *mut u32, irq);
 }
 
 /// Get the highest priority pending interrupt (without acknowledging)
@@ -310,7 +316,7 @@ pub unsafe fn end_of_interrupt(irq: u32) {
 /// # Safety
 /// Safe to call from any context
 pub unsafe fn get_highest_pending() -> Option<u32> {
-    let hppir = read_volatile(GICC_HPPIR as *const u32);
+    let hppir = read_volatile(gicc(GICC_HPPIR) as *const u32);
     let irq_id = hppir & 0x3FF;
 
     if irq_id == SPURIOUS_IRQ {
@@ -0,0 +1,483 @@
+//! ARM Generic Interrupt Controller (GICv3) Driver
+//!
+//! GICv3 splits the GICv2 design three ways:
+//!
+//! ## GIC Distributor (GICD)
+//! - Configures SPIs (32-1019) and routes them by CPU affinity (`GICD_IROUTER`)
+//!   instead of target bitmasks
+//!
+//! ## GIC Redistributors (GICR)
+//! - One per CPU, two 64KB frames each (RD_base + SGI_base)
+//! - SGIs/PPIs (0-31) are configured here rather than in the distributor
+//! - Owns the LPI configuration and pending tables
+//!
+//! ## CPU Interface (ICC_* system registers)
+//! - Acknowledge/EOI through `ICC_IAR1_EL1` / `ICC_EOIR1_EL1` instead of MMIO
+//!
+//! ## LPIs (8192+)
+//! Message-signaled interrupts are Locality-specific Peripheral Interrupts,
+//! configured through in-memory tables and mapped from device writes by the
+//! ITS (see `its`). LPIs are only enabled when the device tree describes an
+//! ITS.
+//!
+//! All interrupts are Group 1 (the group non-secure EL1 handles); the
+//! kernel is single-core, so SPIs are routed to the boot CPU.
+
+use core::arch::asm;
+use core::ptr::{read_volatile, write_volatile};
+use super::MAX_IRQS;
+
+/// Bytes of the redistributor region mapped and searched for the boot CPU
+///
+/// One GICv4 redistributor (four 64KB frames), or two GICv3 ones.
+pub const REDIST_WINDOW: usize = 0x40000;
+
+/// First LPI INTID
+pub const LPI_BASE: u32 = 8192;
+
+/// INTID bits supported for LPIs (INTIDs below 2^14: 8192 LPIs)
+const LPI_ID_BITS: u32 = 14;
+
+/// Number of LPIs
+pub const LPI_COUNT: usize = (1 << LPI_ID_BITS) - LPI_BASE as usize;
+
+/// Default interrupt priority (matches GICv2)
+const DEFAULT_PRIORITY: u8 = 0xA0;
+
+// =============================================================================
+// GIC Distributor Registers (GICD_*)
+// =============================================================================
+
+/// GICD_CTLR - Distributor Control Register
+const GICD_CTLR: usize = 0x0000;
+
+/// GICD_TYPER - Interrupt Controller Type Register
+/// Bits 0-4: ITLinesNumber, bit 17: LPIS, bits 19-23: IDbits
+const GICD_TYPER: usize = 0x0004;
+
+/// GICD_IGROUPRn - Interrupt Group Registers (1 = Group 1)
+const GICD_IGROUPR: usize = 0x0080;
+
+/// GICD_ISENABLERn / GICD_ICENABLERn - Interrupt Set/Clear-Enable Registers
+const GICD_ISENABLER: usize = 0x0100;
+const GICD_ICENABLER: usize = 0x0180;
+
+/// GICD_ICPENDRn - Interrupt Clear-Pending Registers
+const GICD_ICPENDR: usize = 0x0280;
+
+/// GICD_IPRIORITYRn - Interrupt Priority Registers (byte-accessible)
+const GICD_IPRIORITYR: usize = 0x0400;
+
+/// GICD_ICFGRn - Interrupt Configuration Registers
+const GICD_ICFGR: usize = 0x0C00;
+
+/// GICD_IROUTERn - Interrupt Routing Registers (64-bit, affinity)
+const GICD_IROUTER: usize = 0x6000;
+
+/// GICD_CTLR.EnableGrp1 (EnableGrp1A when security is enabled)
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
+
+/// GICD_CTLR.ARE (ARE_NS when security is enabled) - affinity routing
+const GICD_CTLR_ARE: u32 = 1 << 4;
+
+/// GICD_CTLR.RWP - register write pending
+const GICD_CTLR_RWP: u32 = 1 << 31;
+
+/// GICD_TYPER.LPIS - LPIs supported
+const GICD_TYPER_LPIS: u32 = 1 << 17;
+
+// =============================================================================
+// GIC Redistributor Registers (GICR_*)
+// =============================================================================
+
+/// Offset of the SGI_base frame from RD_base
+const GICR_SGI_OFFSET: usize = 0x10000;
+
+/// GICR_CTLR - Redistributor Control Register
+/// Bit 0: EnableLPIs, bit 3: RWP
+const GICR_CTLR: usize = 0x0000;
+
+/// GICR_TYPER - Redistributor Type Register (64-bit)
+/// Bit 0: PLPIS, bit 1: VLPIS, bit 4: Last, bits 8-23: processor number,
+/// bits 32-63: affinity
+const GICR_TYPER: usize = 0x0008;
+
+/// GICR_WAKER - Redistributor Wake Register
+const GICR_WAKER: usize = 0x0014;
+
+/// GICR_PROPBASER - LPI Configuration table base
+const GICR_PROPBASER: usize = 0x0070;
+
+/// GICR_PENDBASER - LPI Pending table base
+const GICR_PENDBASER: usize = 0x0078;
+
+/// SGI_base frame registers (same layout as the distributor's first words)
+const GICR_IGROUPR0: usize = GICR_SGI_OFFSET + 0x0080;
+const GICR_ISENABLER0: usize = GICR_SGI_OFFSET + 0x0100;
+const GICR_ICENABLER0: usize = GICR_SGI_OFFSET + 0x0180;
+const GICR_ICPENDR0: usize = GICR_SGI_OFFSET + 0x0280;
+const GICR_IPRIORITYR: usize = GICR_SGI_OFFSET + 0x0400;
+const GICR_ICFGR1: usize = GICR_SGI_OFFSET + 0x0C04;
+
+const GICR_CTLR_ENABLE_LPIS: u32 = 1 << 0;
+const GICR_CTLR_RWP: u32 = 1 << 3;
+const GICR_TYPER_PLPIS: u64 = 1 << 0;
+const GICR_TYPER_VLPIS: u64 = 1 << 1;
+const GICR_TYPER_LAST: u64 = 1 << 4;
+const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+
+/// Table attributes for GICR_PROPBASER/PENDBASER and the ITS tables:
+/// inner write-back cacheable (bits 7-9 = 0b111), inner shareable (bits 10-11 = 0b01)
+pub(super) const TABLE_ATTRS: u64 = (0b111 << 7) | (0b01 << 10);
+
+/// GICR_PENDBASER.PTZ - pending table is zero
+const GICR_PENDBASER_PTZ: u64 = 1 << 62;
+
+/// LPI configuration byte: enable bit
+const LPI_ENABLE: u8 = 1 << 0;
+
+/// Special INTIDs (1020-1023) mean no interrupt
+const SPECIAL_INTID_BASE: u32 = 1020;
+
+/// Boot CPU's redistributor (RD_base)
+static mut RD_BASE: usize = 0;
+
+/// LPI configuration table (one byte per LPI), 0 if LPIs are unavailable
+static mut LPI_CONFIG: usize = 0;
+
+/// Affinity of the boot CPU in GICR_TYPER/GICD_IROUTER layout
+/// (Aff3 at bits 24-31 (TYPER) / 32-39 (IROUTER), Aff2..Aff0 below)
+fn cpu_affinity() -> (u64, u64) {
+    let mpidr: u64;
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack)) };
+    let aff210 = mpidr & 0x00FF_FFFF;
+    let aff3 = (mpidr >> 32) & 0xFF;
+    ((aff3 << 24) | aff210, (aff3 << 32) | aff210)
+}
+
+fn gicd(offset: usize) -> usize {
+    super::config().dist_base + offset
+}
+
+unsafe fn gicr(offset: usize) -> usize {
+    RD_BASE + offset
+}
+
+/// Wait for a register write to take effect
+unsafe fn wait_rwp(ctlr: usize, rwp: u32) {
+    for _ in 0..1_000_000 {
+        if read_volatile(ctlr as *const u32) & rwp == 0 {
+            return;
+        }
+        core::hint::spin_loop();
+    }
+    crate::kprintln!("[GIC] WARNING: register write still pending at {:#x}", ctlr);
+}
+
+/// Find the boot CPU's redistributor in the mapped window
+unsafe fn find_redistributor() -> usize {
+    let config = super::config();
+    let window = core::cmp::min(config.redist_size, REDIST_WINDOW);
+    let (affinity, _) = cpu_affinity();
+
+    let mut rd = config.redist_base;
+    while rd + 2 * GICR_SGI_OFFSET <= config.redist_base + window {
+        let typer = read_volatile((rd + GICR_TYPER) as *const u64);
+        if typer >> 32 == affinity {
+            return rd;
+        }
+        if typer & GICR_TYPER_LAST != 0 {
+            break;
+        }
+        // GICv4 redistributors have two extra frames for virtual LPIs
+        rd += if typer & GICR_TYPER_VLPIS != 0 { 4 * GICR_SGI_OFFSET } else { 2 * GICR_SGI_OFFSET };
+    }
+
+    crate::kprintln!("[GIC] WARNING: no redistributor for affinity {:#x}, using the first", affinity);
+    config.redist_base
+}
+
+// =============================================================================
+// GICv3 Driver Implementation
+// =============================================================================
+
+/// Initialize the GICv3
+///
+/// Configures the distributor (SPIs), the boot CPU's redistributor
+/// (SGIs/PPIs, LPI tables) and its system register CPU interface, then
+/// the ITS if the device tree describes one.
+///
+/// # Safety
+/// Must be called once during boot, after `gic::configure` has set the
+/// GICv3 base addresses and the frame allocator is initialized.
+pub unsafe fn init() {
+    crate::kprintln!("[GIC] Initializing GICv3...");
+
+    let typer = read_volatile(gicd(GICD_TYPER) as *const u32);
+    let itlines = (typer & 0x1F) as usize;
+    let max_irqs = core::cmp::min(32 * (itlines + 1), MAX_IRQS);
+    crate::kprintln!("[GIC] ITLinesNumber: {}, Max IRQs: {}", itlines, max_irqs);
+
+    // Disable the distributor, then turn on affinity routing
+    write_volatile(gicd(GICD_CTLR) as *mut u32, 0);
+    wait_rwp(gicd(GICD_CTLR), GICD_CTLR_RWP);
+    write_volatile(gicd(GICD_CTLR) as *mut u32, GICD_CTLR_ARE);
+    wait_rwp(gicd(GICD_CTLR), GICD_CTLR_RWP);
+
+    // SPIs: Group 1, disabled, not pending, default priority, level-sensitive,
+    // routed to this CPU
+    let (_, route) = cpu_affinity();
+    for i in 1..max_irqs.div_ceil(32) {
+        write_volatile(gicd(GICD_IGROUPR + i * 4) as *mut u32, 0xFFFFFFFF);
+        write_volatile(gicd(GICD_ICENABLER + i * 4) as *mut u32, 0xFFFFFFFF);
+        write_volatile(gicd(GICD_ICPENDR + i * 4) as *mut u32, 0xFFFFFFFF);
+    }
+    for irq in 32..max_irqs {
+        write_volatile(gicd(GICD_IPRIORITYR + irq) as *mut u8, DEFAULT_PRIORITY);
+        write_volatile(gicd(GICD_IROUTER + irq * 8) as *mut u64, route);
+    }
+    for i in 2..max_irqs.div_ceil(16) {
+        write_volatile(gicd(GICD_ICFGR + i * 4) as *mut u32, 0);
+    }
+    wait_rwp(gicd(GICD_CTLR), GICD_CTLR_RWP);
+
+    write_volatile(gicd(GICD_CTLR) as *mut u32, GICD_CTLR_ARE | GICD_CTLR_ENABLE_GRP1);
+    wait_rwp(gicd(GICD_CTLR), GICD_CTLR_RWP);
+
+    init_redistributor();
+    init_cpu_interface();
+
+    let config = super::config();
+    if config.its_base != 0 && typer & GICD_TYPER_LPIS != 0 {
+        init_lpis();
+    } else {
+        crate::kprintln!("[GIC] No ITS, LPIs disabled");
+    }
+
+    crate::kprintln!("[GIC] GICv3 initialized successfully");
+}
+
+/// Wake the boot CPU's redistributor and configure SGIs/PPIs
+unsafe fn init_redistributor() {
+    let rd_base = find_redistributor();
+    RD_BASE = rd_base;
+    crate::kprintln!("[GIC] Redistributor at {:#x}", rd_base);
+
+    // Mark the CPU awake so the redistributor forwards interrupts
+    let waker = read_volatile(gicr(GICR_WAKER) as *const u32);
+    write_volatile(gicr(GICR_WAKER) as *mut u32, waker & !GICR_WAKER_PROCESSOR_SLEEP);
+    for _ in 0..1_000_000 {
+        if read_volatile(gicr(GICR_WAKER) as *const u32) & GICR_WAKER_CHILDREN_ASLEEP == 0 {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+
+    write_volatile(gicr(GICR_IGROUPR0) as *mut u32, 0xFFFFFFFF);
+    write_volatile(gicr(GICR_ICENABLER0) as *mut u32, 0xFFFFFFFF);
+    write_volatile(gicr(GICR_ICPENDR0) as *mut u32, 0xFFFFFFFF);
+    for irq in 0..32 {
+        write_volatile(gicr(GICR_IPRIORITYR + irq) as *mut u8, DEFAULT_PRIORITY);
+    }
+    // PPIs level-sensitive (SGIs are always edge)
+    write_volatile(gicr(GICR_ICFGR1) as *mut u32, 0);
+    wait_rwp(gicr(GICR_CTLR), GICR_CTLR_RWP);
+}
+
+/// Enable the system register CPU interface for Group 1 interrupts
+unsafe fn init_cpu_interface() {
+    // ICC_SRE_EL1.SRE - use system registers instead of the GICC MMIO interface
+    let mut sre: u64;
+    asm!("mrs {}, icc_sre_el1", out(reg) sre, options(nomem, nostack));
+    sre |= 1;
+    asm!("msr icc_sre_el1, {}", "isb", in(reg) sre, options(nostack));
+
+    // Allow all priorities, no grouping, EOI both drops priority and deactivates
+    asm!("msr icc_pmr_el1, {}", in(reg) 0xFFu64, options(nomem, nostack));
+    asm!("msr icc_bpr1_el1, {}", in(reg) 0u64, options(nomem, nostack));
+    asm!("msr icc_ctlr_el1, {}", in(reg) 0u64, options(nomem, nostack));
+
+    // Enable Group 1 interrupts
+    asm!("msr icc_igrpen1_el1, {}", "isb", in(reg) 1u64, options(nostack));
+
+    crate::kprintln!("[GIC] CPU interface initialized (system registers)");
+}
+
+/// Allocate the LPI tables, enable LPIs at the redistributor and start the ITS
+unsafe fn init_lpis() {
+    if read_volatile(gicr(GICR_TYPER) as *const u64) & GICR_TYPER_PLPIS == 0 {
+        crate::kprintln!("[GIC] Redistributor has no physical LPIs, LPIs disabled");
+        return;
+    }
+
+    // Configuration table: one byte per LPI
+    let config_pages = LPI_COUNT.div_ceil(crate::memory::PAGE_SIZE);
+    // Pending table: one bit per INTID (including the non-LPI range), 64KB aligned
+    let pending_bytes = (1usize << LPI_ID_BITS) / 8;
+    let pending_pages = pending_bytes.div_ceil(crate::memory::PAGE_SIZE);
+
+    let (Some(config), Some(pending)) = (
+        crate::memory::alloc_contiguous(config_pages, crate::memory::PAGE_SIZE),
+        crate::memory::alloc_contiguous(pending_pages, 0x10000),
+    ) else {
+        crate::kprintln!("[GIC] ERROR: Failed to allocate LPI tables, LPIs disabled");
+        return;
+    };
+    let config = config.phys_addr().as_usize();
+    let pending = pending.phys_addr().as_usize();
+
+    // All LPIs disabled at the default priority; nothing pending
+    core::ptr::write_bytes(config as *mut u8, DEFAULT_PRIORITY, LPI_COUNT);
+    core::ptr::write_bytes(pending as *mut u8, 0, pending_pages * crate::memory::PAGE_SIZE);
+    asm!("dsb sy", options(nostack));
+
+    write_volatile(gicr(GICR_PROPBASER) as *mut u64,
+                   config as u64 | TABLE_ATTRS | (LPI_ID_BITS - 1) as u64);
+    write_volatile(gicr(GICR_PENDBASER) as *mut u64,
+                   pending as u64 | TABLE_ATTRS | GICR_PENDBASER_PTZ);
+
+    let ctlr = read_volatile(gicr(GICR_CTLR) as *const u32);
+    write_volatile(gicr(GICR_CTLR) as *mut u32, ctlr | GICR_CTLR_ENABLE_LPIS);
+    asm!("dsb sy", options(nostack));
+
+    if !super::its::init(super::config().its_base, RD_BASE) {
+        crate::kprintln!("[GIC] ERROR: ITS initialization failed, LPIs disabled");
+        return;
+    }
+
+    LPI_CONFIG = config;
+    crate::kprintln!("[GIC] LPIs enabled: INTIDs {}-{}", LPI_BASE, LPI_BASE as usize + LPI_COUNT - 1);
+}
+
+/// Whether LPIs (and the ITS) are available
+pub fn lpis_enabled() -> bool {
+    unsafe { LPI_CONFIG != 0 }
+}
+
+/// Whether `irq` is an LPI INTID this driver supports
+pub fn is_lpi(irq: u32) -> bool {
+    irq >= LPI_BASE && ((irq - LPI_BASE) as usize) < LPI_COUNT
+}
+
+/// Update an LPI's configuration byte and make the redistributor reload it
+unsafe fn update_lpi(irq: u32, f: impl FnOnce(u8) -> u8) {
+    if !lpis_enabled() || !is_lpi(irq) {
+        return;
+    }
+    let entry = (LPI_CONFIG + (irq - LPI_BASE) as usize) as *mut u8;
+    write_volatile(entry, f(read_volatile(entry)));
+    asm!("dsb sy", options(nostack));
+    super::its::invalidate_all();
+}
+
+/// Enable a specific interrupt
+///
+/// # Arguments
+/// * `irq` - Interrupt number (0-1019, or an LPI)
+///
+/// # Safety
+/// Must be called with a valid IRQ number
+pub unsafe fn enable_irq(irq: u32) {
+    if is_lpi(irq) {
+        update_lpi(irq, |config| config | LPI_ENABLE);
+        return;
+    }
+    if irq >= MAX_IRQS as u32 {
+        crate::kprintln!("[GIC] ERROR: Invalid IRQ {}", irq);
+        return;
+    }
+
+    let bit = 1 << (irq % 32);
+    if irq < 32 {
+        write_volatile(gicr(GICR_ISENABLER0) as *mut u32, bit);
+    } else {
+        write_volatile(gicd(GICD_ISENABLER + (irq / 32) as usize * 4) as *mut u32, bit);
+    }
+}
+
+/// Disable a specific interrupt
+///
+/// # Safety
+/// Must be called with a valid IRQ number
+pub unsafe fn disable_irq(irq: u32) {
+    if is_lpi(irq) {
+        update_lpi(irq, |config| config & !LPI_ENABLE);
+        return;
+    }
+    if irq >= MAX_IRQS as u32 {
+        return;
+    }
+
+    let bit = 1 << (irq % 32);
+    if irq < 32 {
+        write_volatile(gicr(GICR_ICENABLER0) as *mut u32, bit);
+        wait_rwp(gicr(GICR_CTLR), GICR_CTLR_RWP);
+    } else {
+        write_volatile(gicd(GICD_ICENABLER + (irq / 32) as usize * 4) as *mut u32, bit);
+        wait_rwp(gicd(GICD_CTLR), GICD_CTLR_RWP);
+    }
+
+    crate::kprintln!("[GIC] Disabled IRQ {}", irq);
+}
+
+/// Set interrupt priority
+///
+/// # Arguments
+/// * `irq` - Interrupt number
+/// * `priority` - Priority value (0 = highest, 255 = lowest)
+///
+/// # Safety
+/// Must be called with a valid IRQ number
+pub unsafe fn set_priority(irq: u32, priority: u8) {
+    if is_lpi(irq) {
+        // Bits 0-1 of the configuration byte hold the enable bit
+        update_lpi(irq, |config| (priority & 0xFC) | (config & 0x3));
+    } else if irq < 32 {
+        write_volatile(gicr(GICR_IPRIORITYR + irq as usize) as *mut u8, priority);
+    } else if irq < MAX_IRQS as u32 {
+        write_volatile(gicd(GICD_IPRIORITYR + irq as usize) as *mut u8, priority);
+    }
+}
+
+/// Acknowledge an interrupt and return its ID
+///
+/// Returns the interrupt ID, or None if spurious.
+///
+/// # Safety
+/// Must be called from IRQ context
+pub unsafe fn acknowledge_irq() -> Option<u32> {
+    let iar: u64;
+    asm!("mrs {}, icc_iar1_el1", out(reg) iar, options(nomem, nostack));
+    let irq_id = (iar & 0xFF_FFFF) as u32;
+
+    if (SPECIAL_INTID_BASE..LPI_BASE).contains(&irq_id) {
+        None
+    } else {
+        Some(irq_id)
+    }
+}
+
+/// Signal end of interrupt processing
+///
+/// # Safety
+/// Must be called from IRQ context with the correct IRQ ID
+pub unsafe fn end_of_interrupt(irq: u32) {
+    asm!("msr icc_eoir1_el1, {}", "isb", in(reg) irq as u64, options(nostack));
+}
+
+/// Get the highest priority pending interrupt (without acknowledging)
+///
+/// # Safety
+/// Safe to call from any context
+pub unsafe fn get_highest_pending() -> Option<u32> {
+    let hppir: u64;
+    asm!("mrs {}, icc_hppir1_el1", out(reg) hppir, options(nomem, nostack));
+    let irq_id = (hppir & 0xFF_FFFF) as u32;
+
+    if (SPECIAL_INTID_BASE..LPI_BASE).contains(&irq_id) {
+        None
+    } else {
+        Some(irq_id)
+    }
+}
@@ -0,0 +1,743 @@
+//! EL2 Hypervisor Stub
+//!
+//! KaaL always runs at EL1. When the bootloader enters it at EL2 (QEMU
+//! with `-machine virt,virtualization=on`, most real boards), `_start`
+//! calls `hyp_init`, which configures EL2 for an EL1 kernel, installs a
+//! small stub vector table at EL2 and drops to EL1. The stub stays
+//! resident and serves `hvc` calls from the kernel:
+//!
+//! - `hvc #0`: forwarded to the firmware as `smc #0`, so PSCI calls work
+//!   whichever EL the kernel was entered at
+//! - `hvc #1`: run a guest until it exits (`hypervisor` feature)
+//! - `hvc #2`: flush the TLB entries of a guest's VMID (`hypervisor` feature)
+//!
+//! ## Guests
+//!
+//! With the `hypervisor` feature, `hvc #1` switches the CPU to a guest
+//! described by a `VcpuContext` (see `objects::vcpu`): it saves the
+//! kernel's EL1 system registers, loads the guest's, enables stage-2
+//! translation and erets to the guest's EL1. Every exception the guest
+//! takes to EL2 (a trap, or a physical interrupt - those stay routed to
+//! EL2 while a guest runs) switches back and returns from the `hvc` with
+//! the exit reason. The kernel is never preempted inside a guest: an IRQ
+//! exit returns to the VMM's `SYS_VCPU_RUN` with IRQs masked, and the
+//! interrupt is taken as usual once the VMM thread returns to EL0.
+//!
+//! The guest and the kernel both use the EL1 virtual timer. While a guest
+//! runs, the kernel's tick is carried by the EL2 physical timer
+//! (`PREEMPT_IRQ`) so a guest cannot hold the CPU past the kernel's next
+//! tick.
+//!
+//! The stub runs with the EL2 MMU off. It only touches its own stack and
+//! the `VcpuContext` (identity-mapped, so kernel pointers are physical
+//! addresses), which the kernel cleans from its caches around each call.
+
+use core::arch::global_asm;
+
+/// Interrupt of the EL2 physical timer, which carries the kernel's tick
+/// while a guest runs (PPI 10)
+pub const PREEMPT_IRQ: u32 = 26;
+
+/// HCR_EL2 while the kernel runs: EL1 is AArch64, nothing is trapped
+const HCR_HOST: u64 = 1 << 31;
+
+/// Size of the stub's stack (EL2 only saves two registers on it)
+const HYP_STACK_SIZE: usize = 256;
+
+/// Stack of the EL2 stub
+#[repr(C, align(16))]
+struct HypStack([u8; HYP_STACK_SIZE]);
+
+static mut HYP_STACK: HypStack = HypStack([0; HYP_STACK_SIZE]);
+
+/// Set by `hyp_init` when the kernel was entered at EL2 and the stub is
+/// installed
+static mut HYP_INSTALLED: u64 = 0;
+
+// Configure EL2 and drop to EL1
+//
+// Called by `_start` first thing, before the boot parameters in x0-x7
+// are saved: uses x9-x10 only. At EL1 it just returns; at EL2 it
+// "returns" to the caller at EL1 on the same stack, with interrupts
+// masked.
+global_asm!(
+    ".pushsection .text.hyp_init, \"ax\"",
+    ".global hyp_init",
+    "hyp_init:",
+    "    mrs x9, CurrentEL",
+    "    cmp x9, #(2 << 2)",
+    "    b.ne 3f",
+    // EL1 is AArch64; nothing trapped, no stage 2 until a guest runs
+    "    movz x9, #(({hcr} >> 16) & 0xffff), lsl #16",
+    "    msr hcr_el2, x9",
+    "    msr vttbr_el2, xzr",
+    "    msr hstr_el2, xzr",
+    // FP/SIMD is not trapped (CPTR_EL2 RES1 bits, TFP = 0)
+    "    mov x9, #0x33ff",
+    "    msr cptr_el2, x9",
+    // EL1 may use the physical counter and timer; no virtual offset
+    "    mov x9, #3",
+    "    msr cnthctl_el2, x9",
+    "    msr cntvoff_el2, xzr",
+    "    msr cnthp_ctl_el2, xzr",
+    // All PMU counters belong to EL1 (MDCR_EL2.HPMN = PMCR_EL0.N)
+    "    mrs x9, pmcr_el0",
+    "    ubfx x9, x9, #11, #5",
+    "    msr mdcr_el2, x9",
+    // EL1 reads of MIDR/MPIDR return these
+    "    mrs x9, midr_el1",
+    "    msr vpidr_el2, x9",
+    "    mrs x9, mpidr_el1",
+    "    msr vmpidr_el2, x9",
+    // GICv3 system register interface usable from EL1
+    "    mrs x9, id_aa64pfr0_el1",
+    "    ubfx x9, x9, #24, #4",
+    "    cbz x9, 1f",
+    "    mrs x9, S3_4_C12_C9_5",     // ICC_SRE_EL2
+    "    orr x9, x9, #0x1",          // SRE
+    "    orr x9, x9, #0x8",          // Enable (EL1 accesses ICC_SRE_EL1)
+    "    msr S3_4_C12_C9_5, x9",
+    "1:",
+    // Stage 2 for guests: 40-bit IPA, 4KB granule starting at level 0,
+    // write-back inner shareable walks, PA size as implemented (at most 48)
+    "    mrs x9, id_aa64mmfr0_el1",
+    "    and x9, x9, #0xf",
+    "    mov x10, #5",
+    "    cmp x9, x10",
+    "    csel x9, x9, x10, ls",
+    "    lsl x9, x9, #16",
+    "    movz x10, #0x3598",
+    "    movk x10, #0x8000, lsl #16",
+    "    orr x9, x9, x10",
+    "    msr vtcr_el2, x9",
+    // EL2 MMU and caches off (SCTLR_EL2 RES1 bits only)
+    "    movz x9, #0x0830",
+    "    movk x9, #0x30c5, lsl #16",
+    "    msr sctlr_el2, x9",
+    // Stub vectors and stack; EL1 continues on the current stack
+    "    adrp x9, hyp_vector_table",
+    "    add x9, x9, :lo12:hyp_vector_table",
+    "    msr vbar_el2, x9",
+    "    mov x9, sp",
+    "    msr sp_el1, x9",
+    "    adrp x9, {stack}",
+    "    add x9, x9, :lo12:{stack}",
+    "    add x9, x9, #{stack_size}",
+    "    mov sp, x9",
+    "    msr tpidr_el2, xzr",
+    // Tell the kernel the stub is there (MMU off: write through to memory)
+    "    adrp x9, {installed}",
+    "    add x9, x9, :lo12:{installed}",
+    "    mov x10, #1",
+    "    str x10, [x9]",
+    "    dc civac, x9",
+    "    dsb sy",
+    // Drop to EL1h with DAIF masked, returning to the caller
+    "    mov x9, #0x3c5",
+    "    msr spsr_el2, x9",
+    "    msr elr_el2, x30",
+    "    isb",
+    "    eret",
+    "3:",
+    "    ret",
+    ".popsection",
+    hcr = const HCR_HOST,
+    stack = sym HYP_STACK,
+    stack_size = const HYP_STACK_SIZE,
+    installed = sym HYP_INSTALLED,
+);
+
+// EL2 vector table
+//
+// Only exceptions from EL1/EL0 are expected: `hvc` calls from the kernel,
+// and while a guest runs, its traps and the physical interrupts routed to
+// EL2. Exceptions taken at EL2 itself are stub bugs: the CPU parks.
+global_asm!(
+    ".pushsection .text.hyp_vectors, \"ax\"",
+    ".balign 2048",
+    ".global hyp_vector_table",
+    "hyp_vector_table:",
+    // Current EL with SP_EL0, current EL with SP_ELx
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    // Lower EL (AArch64)
+    ".balign 0x80", "    b hyp_lower_sync",
+    ".balign 0x80", "    b hyp_lower_irq",
+    ".balign 0x80", "    b hyp_lower_irq",     // FIQ
+    ".balign 0x80", "    b hyp_lower_serror",
+    // Lower EL (AArch32)
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+    ".balign 0x80", "    b hyp_hang",
+
+    ".global hyp_hang",
+    "hyp_hang:",
+    "    wfe",
+    "    b hyp_hang",
+
+    // TPIDR_EL2 holds the running guest's context, 0 while the kernel runs
+    "hyp_lower_sync:",
+    "    stp x0, x1, [sp, #-16]!",
+    "    mrs x0, tpidr_el2",
+    "    mov x1, #{exit_trap}",
+    "    cbnz x0, hyp_guest_exit",
+    "    ldp x0, x1, [sp], #16",
+    // A call from the kernel: only HVC, immediate selects the service.
+    // x16-x17 are free (the kernel's hvc wrappers clobber x0-x17)
+    "    mrs x16, esr_el2",
+    "    ubfx x17, x16, #26, #6",
+    "    cmp x17, #0x16",            // EC: HVC from AArch64
+    "    b.ne hyp_hang",
+    "    and x16, x16, #0xffff",
+    "    cbnz x16, hyp_vcpu_call",
+    "    smc #0",                    // hvc #0: PSCI, forwarded to firmware
+    "    eret",
+
+    "hyp_lower_irq:",
+    "    stp x0, x1, [sp, #-16]!",
+    "    mrs x0, tpidr_el2",
+    "    cbz x0, hyp_hang",          // IRQs only reach EL2 while a guest runs
+    "    mov x1, #{exit_irq}",
+    "    b hyp_guest_exit",
+
+    "hyp_lower_serror:",
+    "    stp x0, x1, [sp, #-16]!",
+    "    mrs x0, tpidr_el2",
+    "    cbz x0, hyp_hang",
+    "    mov x1, #{exit_serror}",
+    "    b hyp_guest_exit",
+    ".popsection",
+    exit_trap = const VCPU_EXIT_TRAP,
+    exit_irq = const VCPU_EXIT_IRQ,
+    exit_serror = const VCPU_EXIT_SERROR,
+);
+
+/// Exit reason: a physical interrupt arrived while the guest ran
+pub const VCPU_EXIT_IRQ: u64 = 0;
+
+/// Exit reason: the guest trapped (ESR_EL2 says why)
+pub const VCPU_EXIT_TRAP: u64 = 1;
+
+/// Exit reason: an SError was taken while the guest ran
+pub const VCPU_EXIT_SERROR: u64 = 2;
+
+/// Whether the kernel was entered at EL2 and the stub is installed
+pub fn installed() -> bool {
+    unsafe { core::ptr::read_volatile(core::ptr::addr_of!(HYP_INSTALLED)) != 0 }
+}
+
+// Without guest support, `hvc #1`/`hvc #2` fail and no guest ever exits
+#[cfg(not(feature = "hypervisor"))]
+global_asm!(
+    ".pushsection .text.hyp_vectors, \"ax\"",
+    ".global hyp_vcpu_call",
+    "hyp_vcpu_call:",
+    "    mov x0, #-1",
+    "    eret",
+    ".global hyp_guest_exit",
+    "hyp_guest_exit:",
+    "    b hyp_hang",
+    ".popsection",
+);
+
+#[cfg(feature = "hypervisor")]
+pub use guest::*;
+
+#[cfg(feature = "hypervisor")]
+mod guest {
+    use core::arch::{asm, global_asm};
+    use core::mem::offset_of;
+    use crate::arch::aarch64::fpu::FpState;
+
+    /// EL1 system registers, switched between the kernel and a guest
+    ///
+    /// Field order is the order of the `hyp_save_el1`/`hyp_restore_el1`
+    /// macros below.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct El1Regs {
+        pub sctlr_el1: u64,
+        pub ttbr0_el1: u64,
+        pub ttbr1_el1: u64,
+        pub tcr_el1: u64,
+        pub mair_el1: u64,
+        pub amair_el1: u64,
+        pub vbar_el1: u64,
+        pub contextidr_el1: u64,
+        pub tpidr_el1: u64,
+        pub tpidr_el0: u64,
+        pub tpidrro_el0: u64,
+        pub sp_el0: u64,
+        pub sp_el1: u64,
+        pub elr_el1: u64,
+        pub spsr_el1: u64,
+        pub esr_el1: u64,
+        pub far_el1: u64,
+        pub afsr0_el1: u64,
+        pub afsr1_el1: u64,
+        pub par_el1: u64,
+        pub cpacr_el1: u64,
+        pub cntkctl_el1: u64,
+        pub csselr_el1: u64,
+        pub cntv_ctl_el0: u64,
+        pub cntv_cval_el0: u64,
+        _reserved: u64,
+    }
+
+    /// Number of registers in `El1Regs`
+    pub const EL1_REG_COUNT: usize = 25;
+
+    impl El1Regs {
+        /// Register `index` in field order
+        pub fn get_mut(&mut self, index: usize) -> Option<&mut u64> {
+            if index >= EL1_REG_COUNT {
+                return None;
+            }
+            // repr(C) struct of u64s
+            let regs = self as *mut Self as *mut u64;
+            Some(unsafe { &mut *regs.add(index) })
+        }
+    }
+
+    /// Guest state switched by `hvc #1`
+    ///
+    /// Shared with the EL2 stub, which accesses it with the MMU off:
+    /// aligned for its paired loads and stores, and cleaned from the
+    /// caches around each run by `enter_guest`.
+    #[repr(C, align(64))]
+    pub struct VcpuContext {
+        /// Guest x0-x30
+        pub gpr: [u64; 31],
+        /// Guest PC (ELR_EL2)
+        pub pc: u64,
+        /// Guest PSTATE (SPSR_EL2)
+        pub pstate: u64,
+        /// HCR_EL2 while the guest runs
+        pub hcr: u64,
+        /// Stage-2 table root and VMID
+        pub vttbr: u64,
+        /// MPIDR_EL1 as seen by the guest
+        pub vmpidr: u64,
+        /// Syndrome of the last exit (ESR_EL2, FAR_EL2, HPFAR_EL2)
+        pub esr: u64,
+        pub far: u64,
+        pub hpfar: u64,
+        _pad: u64,
+        /// Guest EL1 system registers
+        pub el1: El1Regs,
+        /// Guest FP/SIMD registers
+        pub fp: FpState,
+        // Kernel state saved by the stub during a run
+        host_gpr: [u64; 13],
+        host_elr: u64,
+        host_spsr: u64,
+        host_vmpidr: u64,
+        host_fp: [u64; 8],
+        host_el1: El1Regs,
+    }
+
+    impl VcpuContext {
+        /// Zeroed context
+        pub const fn new() -> Self {
+            const ZERO_EL1: El1Regs = El1Regs {
+                sctlr_el1: 0, ttbr0_el1: 0, ttbr1_el1: 0, tcr_el1: 0, mair_el1: 0,
+                amair_el1: 0, vbar_el1: 0, contextidr_el1: 0, tpidr_el1: 0, tpidr_el0: 0,
+                tpidrro_el0: 0, sp_el0: 0, sp_el1: 0, elr_el1: 0, spsr_el1: 0,
+                esr_el1: 0, far_el1: 0, afsr0_el1: 0, afsr1_el1: 0, par_el1: 0,
+                cpacr_el1: 0, cntkctl_el1: 0, csselr_el1: 0, cntv_ctl_el0: 0,
+                cntv_cval_el0: 0, _reserved: 0,
+            };
+            Self {
+                gpr: [0; 31],
+                pc: 0,
+                pstate: 0,
+                hcr: 0,
+                vttbr: 0,
+                vmpidr: 0,
+                esr: 0,
+                far: 0,
+                hpfar: 0,
+                _pad: 0,
+                el1: ZERO_EL1,
+                fp: FpState::new(),
+                host_gpr: [0; 13],
+                host_elr: 0,
+                host_spsr: 0,
+                host_vmpidr: 0,
+                host_fp: [0; 8],
+                host_el1: ZERO_EL1,
+            }
+        }
+    }
+
+    // Guest entry (`hvc #1`, x1 = context) and exit
+    global_asm!(
+        ".pushsection .text.hyp_vectors, \"ax\"",
+        // Save/restore EL1 registers at \regs (an `El1Regs`), using x2-x3
+        ".macro hyp_save_el1 regs",
+        "    mrs x2, sctlr_el1",
+        "    mrs x3, ttbr0_el1",
+        "    stp x2, x3, [\\regs, #0]",
+        "    mrs x2, ttbr1_el1",
+        "    mrs x3, tcr_el1",
+        "    stp x2, x3, [\\regs, #16]",
+        "    mrs x2, mair_el1",
+        "    mrs x3, amair_el1",
+        "    stp x2, x3, [\\regs, #32]",
+        "    mrs x2, vbar_el1",
+        "    mrs x3, contextidr_el1",
+        "    stp x2, x3, [\\regs, #48]",
+        "    mrs x2, tpidr_el1",
+        "    mrs x3, tpidr_el0",
+        "    stp x2, x3, [\\regs, #64]",
+        "    mrs x2, tpidrro_el0",
+        "    mrs x3, sp_el0",
+        "    stp x2, x3, [\\regs, #80]",
+        "    mrs x2, sp_el1",
+        "    mrs x3, elr_el1",
+        "    stp x2, x3, [\\regs, #96]",
+        "    mrs x2, spsr_el1",
+        "    mrs x3, esr_el1",
+        "    stp x2, x3, [\\regs, #112]",
+        "    mrs x2, far_el1",
+        "    mrs x3, afsr0_el1",
+        "    stp x2, x3, [\\regs, #128]",
+        "    mrs x2, afsr1_el1",
+        "    mrs x3, par_el1",
+        "    stp x2, x3, [\\regs, #144]",
+        "    mrs x2, cpacr_el1",
+        "    mrs x3, cntkctl_el1",
+        "    stp x2, x3, [\\regs, #160]",
+        "    mrs x2, csselr_el1",
+        "    mrs x3, cntv_ctl_el0",
+        "    stp x2, x3, [\\regs, #176]",
+        "    mrs x2, cntv_cval_el0",
+        "    str x2, [\\regs, #192]",
+        ".endm",
+
+        ".macro hyp_restore_el1 regs",
+        "    ldp x2, x3, [\\regs, #0]",
+        "    msr sctlr_el1, x2",
+        "    msr ttbr0_el1, x3",
+        "    ldp x2, x3, [\\regs, #16]",
+        "    msr ttbr1_el1, x2",
+        "    msr tcr_el1, x3",
+        "    ldp x2, x3, [\\regs, #32]",
+        "    msr mair_el1, x2",
+        "    msr amair_el1, x3",
+        "    ldp x2, x3, [\\regs, #48]",
+        "    msr vbar_el1, x2",
+        "    msr contextidr_el1, x3",
+        "    ldp x2, x3, [\\regs, #64]",
+        "    msr tpidr_el1, x2",
+        "    msr tpidr_el0, x3",
+        "    ldp x2, x3, [\\regs, #80]",
+        "    msr tpidrro_el0, x2",
+        "    msr sp_el0, x3",
+        "    ldp x2, x3, [\\regs, #96]",
+        "    msr sp_el1, x2",
+        "    msr elr_el1, x3",
+        "    ldp x2, x3, [\\regs, #112]",
+        "    msr spsr_el1, x2",
+        "    msr esr_el1, x3",
+        "    ldp x2, x3, [\\regs, #128]",
+        "    msr far_el1, x2",
+        "    msr afsr0_el1, x3",
+        "    ldp x2, x3, [\\regs, #144]",
+        "    msr afsr1_el1, x2",
+        "    msr par_el1, x3",
+        "    ldp x2, x3, [\\regs, #160]",
+        "    msr cpacr_el1, x2",
+        "    msr cntkctl_el1, x3",
+        // Compare value before control, so no stale expiry fires
+        "    ldr x2, [\\regs, #192]",
+        "    msr cntv_cval_el0, x2",
+        "    ldp x2, x3, [\\regs, #176]",
+        "    msr csselr_el1, x2",
+        "    msr cntv_ctl_el0, x3",
+        ".endm",
+
+        // Dispatch of `hvc #N` (N != 0) from the kernel, x16 = N
+        ".global hyp_vcpu_call",
+        "hyp_vcpu_call:",
+        "    cmp x16, #1",
+        "    b.eq hyp_enter_guest",
+        "    cmp x16, #2",
+        "    b.eq hyp_flush_vmid",
+        "    mov x0, #-1",
+        "    eret",
+
+        // hvc #2, x1 = VTTBR of the guest: drop its stage-1 and stage-2 TLB entries
+        "hyp_flush_vmid:",
+        "    mrs x2, vttbr_el2",
+        "    msr vttbr_el2, x1",
+        "    isb",
+        "    tlbi vmalls12e1is",
+        "    dsb ish",
+        "    msr vttbr_el2, x2",
+        "    isb",
+        "    mov x0, #0",
+        "    eret",
+
+        // hvc #1, x1 = VcpuContext
+        "hyp_enter_guest:",
+        "    mov x0, x1",
+        // Kernel callee-saved registers and return state
+        "    add x2, x0, #{host_gpr}",
+        "    stp x18, x19, [x2, #0]",
+        "    stp x20, x21, [x2, #16]",
+        "    stp x22, x23, [x2, #32]",
+        "    stp x24, x25, [x2, #48]",
+        "    stp x26, x27, [x2, #64]",
+        "    stp x28, x29, [x2, #80]",
+        "    str x30, [x2, #96]",
+        "    mrs x3, elr_el2",
+        "    mrs x4, spsr_el2",
+        "    add x2, x0, #{host_elr}",
+        "    stp x3, x4, [x2]",
+        "    mrs x3, vmpidr_el2",
+        "    str x3, [x0, #{host_vmpidr}]",
+        "    add x2, x0, #{host_fp}",
+        "    stp d8, d9, [x2, #0]",
+        "    stp d10, d11, [x2, #16]",
+        "    stp d12, d13, [x2, #32]",
+        "    stp d14, d15, [x2, #48]",
+        "    add x17, x0, #{host_el1}",
+        "    hyp_save_el1 x17",
+        // The kernel's next tick preempts the guest via the EL2 timer
+        "    ldr x3, [x17, #192]",       // kernel CNTV_CVAL
+        "    ldr x4, [x17, #184]",       // kernel CNTV_CTL
+        "    msr cnthp_cval_el2, x3",
+        "    and x4, x4, #1",            // ENABLE, interrupt unmasked
+        "    msr cnthp_ctl_el2, x4",
+        // Guest state
+        "    add x17, x0, #{el1}",
+        "    hyp_restore_el1 x17",
+        "    add x2, x0, #{fp}",
+        "    ldp q0, q1, [x2, #0]",
+        "    ldp q2, q3, [x2, #32]",
+        "    ldp q4, q5, [x2, #64]",
+        "    ldp q6, q7, [x2, #96]",
+        "    ldp q8, q9, [x2, #128]",
+        "    ldp q10, q11, [x2, #160]",
+        "    ldp q12, q13, [x2, #192]",
+        "    ldp q14, q15, [x2, #224]",
+        "    ldp q16, q17, [x2, #256]",
+        "    ldp q18, q19, [x2, #288]",
+        "    ldp q20, q21, [x2, #320]",
+        "    ldp q22, q23, [x2, #352]",
+        "    ldp q24, q25, [x2, #384]",
+        "    ldp q26, q27, [x2, #416]",
+        "    ldp q28, q29, [x2, #448]",
+        "    ldp q30, q31, [x2, #480]",
+        "    add x2, x2, #512",            // FPCR, FPSR
+        "    ldp x3, x4, [x2]",
+        "    msr fpcr, x3",
+        "    msr fpsr, x4",
+        "    ldp x3, x4, [x0, #{pc}]",   // pc, pstate
+        "    msr elr_el2, x3",
+        "    msr spsr_el2, x4",
+        "    ldp x3, x4, [x0, #{hcr}]",  // hcr, vttbr
+        "    msr vttbr_el2, x4",
+        "    msr hcr_el2, x3",
+        "    ldr x3, [x0, #{vmpidr}]",
+        "    msr vmpidr_el2, x3",
+        "    msr tpidr_el2, x0",
+        "    isb",
+        "    ldp x2, x3, [x0, #16]",
+        "    ldp x4, x5, [x0, #32]",
+        "    ldp x6, x7, [x0, #48]",
+        "    ldp x8, x9, [x0, #64]",
+        "    ldp x10, x11, [x0, #80]",
+        "    ldp x12, x13, [x0, #96]",
+        "    ldp x14, x15, [x0, #112]",
+        "    ldp x16, x17, [x0, #128]",
+        "    ldp x18, x19, [x0, #144]",
+        "    ldp x20, x21, [x0, #160]",
+        "    ldp x22, x23, [x0, #176]",
+        "    ldp x24, x25, [x0, #192]",
+        "    ldp x26, x27, [x0, #208]",
+        "    ldp x28, x29, [x0, #224]",
+        "    ldr x30, [x0, #240]",
+        "    ldp x0, x1, [x0, #0]",
+        "    eret",
+
+        // Exit from the guest: x0 = context, x1 = exit reason, the guest's
+        // x0-x1 on the stack
+        ".global hyp_guest_exit",
+        "hyp_guest_exit:",
+        "    stp x2, x3, [x0, #16]",
+        "    stp x4, x5, [x0, #32]",
+        "    stp x6, x7, [x0, #48]",
+        "    stp x8, x9, [x0, #64]",
+        "    stp x10, x11, [x0, #80]",
+        "    stp x12, x13, [x0, #96]",
+        "    stp x14, x15, [x0, #112]",
+        "    stp x16, x17, [x0, #128]",
+        "    stp x18, x19, [x0, #144]",
+        "    stp x20, x21, [x0, #160]",
+        "    stp x22, x23, [x0, #176]",
+        "    stp x24, x25, [x0, #192]",
+        "    stp x26, x27, [x0, #208]",
+        "    stp x28, x29, [x0, #224]",
+        "    str x30, [x0, #240]",
+        "    ldp x2, x3, [sp], #16",
+        "    stp x2, x3, [x0, #0]",
+        "    mrs x2, elr_el2",
+        "    mrs x3, spsr_el2",
+        "    stp x2, x3, [x0, #{pc}]",
+        "    mrs x2, esr_el2",
+        "    str x2, [x0, #{esr}]",
+        "    mrs x2, far_el2",
+        "    mrs x3, hpfar_el2",
+        "    stp x2, x3, [x0, #{far}]",
+        "    add x17, x0, #{el1}",
+        "    hyp_save_el1 x17",
+        "    add x2, x0, #{fp}",
+        "    stp q0, q1, [x2, #0]",
+        "    stp q2, q3, [x2, #32]",
+        "    stp q4, q5, [x2, #64]",
+        "    stp q6, q7, [x2, #96]",
+        "    stp q8, q9, [x2, #128]",
+        "    stp q10, q11, [x2, #160]",
+        "    stp q12, q13, [x2, #192]",
+        "    stp q14, q15, [x2, #224]",
+        "    stp q16, q17, [x2, #256]",
+        "    stp q18, q19, [x2, #288]",
+        "    stp q20, q21, [x2, #320]",
+        "    stp q22, q23, [x2, #352]",
+        "    stp q24, q25, [x2, #384]",
+        "    stp q26, q27, [x2, #416]",
+        "    stp q28, q29, [x2, #448]",
+        "    stp q30, q31, [x2, #480]",
+        "    mrs x3, fpcr",
+        "    mrs x4, fpsr",
+        "    add x2, x2, #512",
+        "    stp x3, x4, [x2]",
+        // Back to the kernel: no stage 2, no traps
+        "    msr tpidr_el2, xzr",
+        "    movz x2, #(({hcr_host} >> 16) & 0xffff), lsl #16",
+        "    msr hcr_el2, x2",
+        "    msr vttbr_el2, xzr",
+        "    msr cnthp_ctl_el2, xzr",
+        "    ldr x2, [x0, #{host_vmpidr}]",
+        "    msr vmpidr_el2, x2",
+        "    add x17, x0, #{host_el1}",
+        "    hyp_restore_el1 x17",
+        "    add x2, x0, #{host_fp}",
+        "    ldp d8, d9, [x2, #0]",
+        "    ldp d10, d11, [x2, #16]",
+        "    ldp d12, d13, [x2, #32]",
+        "    ldp d14, d15, [x2, #48]",
+        "    add x2, x0, #{host_elr}",
+        "    ldp x3, x4, [x2]",
+        "    msr elr_el2, x3",
+        "    msr spsr_el2, x4",
+        "    add x2, x0, #{host_gpr}",
+        "    ldp x18, x19, [x2, #0]",
+        "    ldp x20, x21, [x2, #16]",
+        "    ldp x22, x23, [x2, #32]",
+        "    ldp x24, x25, [x2, #48]",
+        "    ldp x26, x27, [x2, #64]",
+        "    ldp x28, x29, [x2, #80]",
+        "    ldr x30, [x2, #96]",
+        "    mov x0, x1",
+        "    isb",
+        "    eret",
+        ".popsection",
+
+        host_gpr = const offset_of!(VcpuContext, host_gpr),
+        host_elr = const offset_of!(VcpuContext, host_elr),
+        host_vmpidr = const offset_of!(VcpuContext, host_vmpidr),
+        host_fp = const offset_of!(VcpuContext, host_fp),
+        host_el1 = const offset_of!(VcpuContext, host_el1),
+        el1 = const offset_of!(VcpuContext, el1),
+        fp = const offset_of!(VcpuContext, fp),
+        pc = const offset_of!(VcpuContext, pc),
+        hcr = const offset_of!(VcpuContext, hcr),
+        vmpidr = const offset_of!(VcpuContext, vmpidr),
+        esr = const offset_of!(VcpuContext, esr),
+        far = const offset_of!(VcpuContext, far),
+        hcr_host = const super::HCR_HOST,
+    );
+
+    /// Clean and invalidate `len` bytes at `addr` from the data caches
+    unsafe fn flush_dcache(addr: usize, len: usize) {
+        let ctr: u64;
+        asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+        let line = 4usize << ((ctr >> 16) & 0xf);
+
+        let mut line_addr = addr & !(line - 1);
+        while line_addr < addr + len {
+            asm!("dc civac, {}", in(reg) line_addr, options(nostack));
+            line_addr += line;
+        }
+        asm!("dsb sy", options(nostack));
+    }
+
+    /// Run a guest until it exits; returns the exit reason (`VCPU_EXIT_*`)
+    ///
+    /// # Safety
+    ///
+    /// The stub must be installed, and `context` must describe a guest at
+    /// EL0/EL1 with a valid stage-2 table in `vttbr`.
+    pub unsafe fn enter_guest(context: *mut VcpuContext) -> u64 {
+        let len = core::mem::size_of::<VcpuContext>();
+
+        // The stub reads and writes memory directly
+        flush_dcache(context as usize, len);
+        let reason: u64;
+        asm!(
+            "hvc #1",
+            inout("x0") 0u64 => reason,
+            inout("x1") context => _,
+            clobber_abi("C"),
+        );
+        flush_dcache(context as usize, len);
+        reason
+    }
+
+    /// Drop the TLB entries of the guest with stage-2 root and VMID `vttbr`
+    ///
+    /// # Safety
+    ///
+    /// The stub must be installed.
+    pub unsafe fn flush_guest_tlb(vttbr: u64) {
+        asm!(
+            "hvc #2",
+            inout("x0") 0u64 => _,
+            inout("x1") vttbr => _,
+            clobber_abi("C"),
+        );
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_context_layout() {
+            // Offsets used by the stub macros and the paired loads/stores
+            assert_eq!(offset_of!(El1Regs, sp_el0), 88);
+            assert_eq!(offset_of!(El1Regs, cntv_ctl_el0), 184);
+            assert_eq!(offset_of!(El1Regs, cntv_cval_el0), 192);
+            assert_eq!(offset_of!(VcpuContext, pstate), offset_of!(VcpuContext, pc) + 8);
+            assert_eq!(offset_of!(VcpuContext, vttbr), offset_of!(VcpuContext, hcr) + 8);
+            assert_eq!(offset_of!(VcpuContext, hpfar), offset_of!(VcpuContext, far) + 8);
+            assert_eq!(offset_of!(VcpuContext, fp) % 16, 0);
+            assert_eq!(offset_of!(VcpuContext, el1) % 16, 0);
+
+            let mut regs = El1Regs::default();
+            *regs.get_mut(11).unwrap() = 7;
+            assert_eq!(regs.sp_el0, 7);
+            assert!(regs.get_mut(EL1_REG_COUNT).is_none());
+        }
+    }
+}
@@ -24,6 +24,11 @@ pub enum MemoryAttribute {
     Normal = 0xFF,
     /// Device memory, non-gathering, non-reordering, non-early write acknowledgement
     Device = 0x00,
+    /// Normal memory, inner/outer non-cacheable
+    NormalNonCacheable = 0x44,
+    /// Device memory, gathering, reordering, early write acknowledgement
+    /// (write-combining)
+    DeviceGre = 0x0C,
 }
 
 /// Translation Control Register (TCR_EL1) flags
@@ -86,7 +91,9 @@ pub unsafe fn init_mmu(config: MmuConfig) {
     // Setup MAIR_EL1 (Memory Attribute Indirection Register)
     let mair_value =
         ((MemoryAttribute::Normal as u64)) |   // Attr0: Normal memory
-        (MemoryAttribute::Device as u64) << 8;    // Attr1: Device memory
+        (MemoryAttribute::Device as u64) << 8 |   // Attr1: Device memory
+        (MemoryAttribute::NormalNonCacheable as u64) << 16 |  // Attr2: Normal non-cacheable
+        (MemoryAttribute::DeviceGre as u64) << 24;  // Attr3: Write-combining
 
     asm!(
         "msr mair_el1, {mair}",
@@ -8,3 +8,7 @@ pub mod exception;
 pub mod context;
 pub mod context_switch;
 pub mod gic;
+pub mod timer;
+pub mod pmu;
+pub mod fpu;
+pub mod hyp;
@@ -61,6 +61,12 @@ bitflags! {
         const UXN           = 1 << 54; // Unprivileged execute never
         const PXN           = 1 << 53; // Privileged execute never
 
+        // Software bits (ignored by the MMU)
+        /// Copy-on-write page: mapped read-only, copied on the first write
+        const SW_COW        = 1 << 55;
+        /// Private copy made by a copy-on-write fault, freed with the address space
+        const SW_COW_COPY   = 1 << 56;
+
         // Common combinations
 
         /// Normal memory, cacheable
@@ -69,6 +75,12 @@ bitflags! {
         /// Device memory (MMIO)
         const DEVICE        = Self::ATTR_INDEX_1.bits();
 
+        /// Normal memory, non-cacheable
+        const NORMAL_NC     = Self::ATTR_INDEX_2.bits();
+
+        /// Device memory, write-combining (gathering, reordering, early ack)
+        const DEVICE_WC     = Self::ATTR_INDEX_3.bits();
+
         /// Kernel read/write data
         const KERNEL_DATA   = Self::VALID.bits()
                             | Self::TABLE_OR_PAGE.bits()
@@ -127,6 +139,16 @@ bitflags! {
                             | Self::NORMAL.bits()
                             | Self::NOT_GLOBAL.bits();  // UXN=0, PXN=0 allows execution
 
+        /// User copy-on-write image page (read-only and executable until written)
+        const USER_COW      = Self::VALID.bits()
+                            | Self::TABLE_OR_PAGE.bits()
+                            | Self::AP_RO_ALL.bits()
+                            | Self::ACCESSED.bits()
+                            | Self::INNER_SHARE.bits()
+                            | Self::NORMAL.bits()
+                            | Self::NOT_GLOBAL.bits()
+                            | Self::SW_COW.bits();
+
         /// User data (read-write, no execute)
         const USER_DATA     = Self::VALID.bits()
                             | Self::TABLE_OR_PAGE.bits()
@@ -147,6 +169,29 @@ bitflags! {
                             | Self::UXN.bits()
                             | Self::PXN.bits()
                             | Self::NOT_GLOBAL.bits();
+
+        /// User non-cacheable memory (read-write, no execute), e.g. DMA
+        /// descriptor rings shared with a non-coherent device
+        const USER_NON_CACHEABLE = Self::VALID.bits()
+                            | Self::TABLE_OR_PAGE.bits()
+                            | Self::AP_RW_ALL.bits()
+                            | Self::ACCESSED.bits()
+                            | Self::INNER_SHARE.bits()
+                            | Self::NORMAL_NC.bits()
+                            | Self::UXN.bits()
+                            | Self::PXN.bits()
+                            | Self::NOT_GLOBAL.bits();
+
+        /// User write-combining device memory (read-write, no execute),
+        /// e.g. framebuffers
+        const USER_WRITE_COMBINE = Self::VALID.bits()
+                            | Self::TABLE_OR_PAGE.bits()
+                            | Self::AP_RW_ALL.bits()
+                            | Self::ACCESSED.bits()
+                            | Self::DEVICE_WC.bits()
+                            | Self::UXN.bits()
+                            | Self::PXN.bits()
+                            | Self::NOT_GLOBAL.bits();
     }
 }
 
@@ -0,0 +1,102 @@
+//! Performance Monitors Unit
+//!
+//! Low-level access to the PMUv3 counters used for per-thread CPU
+//! accounting: the 64-bit cycle counter (`PMCCNTR_EL0`) and event counter 0
+//! programmed to count retired instructions. Both count at EL0 and EL1.
+//!
+//! Cores without a PMUv3 (or with an IMPLEMENTATION DEFINED one) read as
+//! zero; accounting then only has the generic timer to go by.
+
+use core::arch::asm;
+
+/// PMCR_EL0.E - enable all counters
+const PMCR_E: u64 = 1 << 0;
+
+/// PMCR_EL0.P - reset event counters
+const PMCR_P: u64 = 1 << 1;
+
+/// PMCR_EL0.C - reset the cycle counter
+const PMCR_C: u64 = 1 << 2;
+
+/// PMCR_EL0.LC - 64-bit cycle counter overflow
+const PMCR_LC: u64 = 1 << 6;
+
+/// PMCNTENSET_EL0 bit for the cycle counter
+const CNTEN_CYCLES: u64 = 1 << 31;
+
+/// PMCNTENSET_EL0 bit for event counter 0
+const CNTEN_EVENT0: u64 = 1 << 0;
+
+/// Common event: instruction architecturally executed
+const EVENT_INST_RETIRED: u64 = 0x08;
+
+/// Set once the counters are running
+static mut PMU_PRESENT: bool = false;
+
+/// Start the cycle and instruction counters
+///
+/// Returns false if the core has no usable PMU.
+///
+/// # Safety
+///
+/// Must be called once during boot, at EL1.
+pub unsafe fn init() -> bool {
+    let dfr0: u64;
+    asm!("mrs {}, id_aa64dfr0_el1", out(reg) dfr0, options(nomem, nostack));
+
+    // ID_AA64DFR0_EL1.PMUVer: 0 = none, 0xF = IMPLEMENTATION DEFINED
+    let pmu_ver = (dfr0 >> 8) & 0xF;
+    if pmu_ver == 0 || pmu_ver == 0xF {
+        return false;
+    }
+
+    asm!(
+        "msr pmevtyper0_el0, {event}",
+        "msr pmcr_el0, {pmcr}",
+        "msr pmcntenset_el0, {enable}",
+        "isb",
+        event = in(reg) EVENT_INST_RETIRED,
+        pmcr = in(reg) PMCR_E | PMCR_P | PMCR_C | PMCR_LC,
+        enable = in(reg) CNTEN_CYCLES | CNTEN_EVENT0,
+        options(nomem, nostack)
+    );
+
+    PMU_PRESENT = true;
+    true
+}
+
+/// Whether the counters are running
+#[inline]
+pub fn present() -> bool {
+    unsafe { PMU_PRESENT }
+}
+
+/// Read the cycle counter (0 without a PMU)
+#[inline]
+pub fn read_cycles() -> u64 {
+    if !present() {
+        return 0;
+    }
+
+    let cycles: u64;
+    unsafe {
+        asm!("mrs {}, pmccntr_el0", out(reg) cycles, options(nomem, nostack));
+    }
+    cycles
+}
+
+/// Read the retired-instruction counter (0 without a PMU)
+///
+/// The event counter is 32 bits wide; callers take wrapping differences.
+#[inline]
+pub fn read_instructions() -> u32 {
+    if !present() {
+        return 0;
+    }
+
+    let instructions: u64;
+    unsafe {
+        asm!("mrs {}, pmevcntr0_el0", out(reg) instructions, options(nomem, nostack));
+    }
+    instructions as u32
+}
@@ -0,0 +1,62 @@
+//! ARM Generic Timer
+//!
+//! Low-level access to the EL1 virtual timer, which drives the scheduler
+//! tick. The virtual timer raises PPI `IRQ_TIMER` when its down-counter
+//! (`CNTV_TVAL_EL0`) reaches zero; writing a new value both re-arms it and
+//! clears the pending interrupt.
+//!
+//! Tick policy (rate, timeslice accounting) lives in `scheduler::timer`.
+
+use core::arch::asm;
+
+/// CNTV_CTL_EL0.ENABLE
+const CTL_ENABLE: u64 = 1 << 0;
+
+/// Counter frequency in Hz (CNTFRQ_EL0, programmed by firmware)
+#[inline]
+pub fn frequency() -> u64 {
+    let freq: u64;
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) freq, options(nomem, nostack));
+    }
+    freq
+}
+
+/// Read the physical counter (CNTPCT_EL0)
+#[inline]
+pub fn read_counter() -> u64 {
+    let counter: u64;
+    unsafe {
+        asm!("isb", "mrs {}, cntpct_el0", out(reg) counter, options(nomem, nostack));
+    }
+    counter
+}
+
+/// Fire the timer interrupt `ticks` counter cycles from now
+///
+/// # Safety
+///
+/// The timer IRQ must be routed to a handler that re-arms or stops the
+/// timer, otherwise it fires continuously.
+#[inline]
+pub unsafe fn arm(ticks: u64) {
+    asm!(
+        "msr cntv_tval_el0, {ticks}",
+        "msr cntv_ctl_el0, {ctl}",  // Enable=1, IMask=0
+        "isb",
+        ticks = in(reg) ticks,
+        ctl = in(reg) CTL_ENABLE,
+        options(nomem, nostack)
+    );
+}
+
+/// Stop the timer (no further interrupts)
+///
+/// # Safety
+///
+/// Stops the scheduler tick: nothing is preempted until the timer is
+/// armed again, so the caller must re-arm it or not rely on preemption.
+#[inline]
+pub unsafe fn disable() {
+    asm!("msr cntv_ctl_el0, xzr", "isb", options(nomem, nostack));
+}
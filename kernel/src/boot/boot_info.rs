@@ -19,7 +19,7 @@ use core::mem::size_of;
 pub const BOOT_INFO_MAGIC: u32 = 0x4B41414C;
 
 /// Boot info structure version
-pub const BOOT_INFO_VERSION: u32 = 1;
+pub const BOOT_INFO_VERSION: u32 = 3;
 
 /// Maximum number of untyped memory regions
 pub const MAX_UNTYPED_REGIONS: usize = 128;
@@ -30,6 +30,9 @@ pub const MAX_DEVICE_REGIONS: usize = 32;
 /// Maximum number of initial capability slots
 pub const MAX_INITIAL_CAPS: usize = 256;
 
+/// Maximum kernel command line length (longer command lines are truncated)
+pub const MAX_CMDLINE_LEN: usize = 256;
+
 /// Untyped memory region descriptor
 ///
 /// Describes a region of physical memory that can be retyped into kernel objects.
@@ -179,6 +182,22 @@ pub struct BootInfo {
     /// IRQControl capability physical address (for delegation to drivers)
     pub irq_control_paddr: u64,
 
+    /// Physical address of the device tree blob (0 if none)
+    pub dtb_paddr: u64,
+
+    /// Size of the device tree blob in bytes
+    pub dtb_size: u64,
+
+    /// Length of the kernel command line in bytes
+    pub cmdline_len: u32,
+
+    /// Reserved for alignment
+    _reserved_cmdline: u32,
+
+    /// Kernel command line (`/chosen/bootargs`), not NUL-terminated.
+    /// Kept ahead of the region arrays so it lands in the first mapped page.
+    pub cmdline: [u8; MAX_CMDLINE_LEN],
+
     /// Untyped memory regions
     pub untyped_regions: [UntypedRegion; MAX_UNTYPED_REGIONS],
 
@@ -206,6 +225,11 @@ impl BootInfo {
             kernel_virt_base: 0,
             user_virt_start: 0,
             irq_control_paddr: 0,
+            dtb_paddr: 0,
+            dtb_size: 0,
+            cmdline_len: 0,
+            _reserved_cmdline: 0,
+            cmdline: [0; MAX_CMDLINE_LEN],
             untyped_regions: [UntypedRegion {
                 paddr: 0,
                 size_bits: 0,
@@ -265,6 +289,16 @@ impl BootInfo {
         Ok(())
     }
 
+    /// Set the kernel command line, truncating at a char boundary if needed
+    pub fn set_cmdline(&mut self, cmdline: &str) {
+        let mut len = cmdline.len().min(MAX_CMDLINE_LEN);
+        while !cmdline.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.cmdline[..len].copy_from_slice(&cmdline.as_bytes()[..len]);
+        self.cmdline_len = len as u32;
+    }
+
     /// Get the size of the boot info structure in bytes
     pub const fn size() -> usize {
         size_of::<Self>()
@@ -311,6 +345,18 @@ mod tests {
         assert_eq!(region.device_type, 1);
     }
 
+    #[test]
+    fn test_set_cmdline() {
+        let mut boot_info = BootInfo::new();
+        boot_info.set_cmdline("loglevel=debug");
+        assert_eq!(boot_info.cmdline_len, 14);
+        assert_eq!(&boot_info.cmdline[..14], b"loglevel=debug");
+
+        let long = [b'x'; MAX_CMDLINE_LEN + 10];
+        boot_info.set_cmdline(core::str::from_utf8(&long).unwrap());
+        assert_eq!(boot_info.cmdline_len as usize, MAX_CMDLINE_LEN);
+    }
+
     #[test]
     fn test_boot_info_size() {
         // Boot info should be reasonably sized (under 64KB)
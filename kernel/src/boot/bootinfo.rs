@@ -15,6 +15,8 @@ use crate::memory::PhysAddr;
 /// - x3 = root_task_entry: Virtual entry point of root task
 /// - x4 = dtb_addr: Physical address of device tree blob
 /// - x5 = dtb_size: Size of device tree blob in bytes
+/// - x6 = cmdline_addr: Command line from `/chosen/bootargs` (0 if none)
+/// - x7 = cmdline_len: Command line length in bytes
 #[derive(Debug, Clone, Copy)]
 pub struct BootInfo {
     /// Physical start address of root task ELF image
@@ -34,6 +36,9 @@ pub struct BootInfo {
 
     /// Size of device tree blob in bytes
     pub dtb_size: usize,
+
+    /// Kernel command line (empty if the bootloader passed none)
+    pub cmdline: &'static str,
 }
 
 impl BootInfo {
@@ -53,9 +58,16 @@ impl BootInfo {
             root_task_entry,
             dtb_addr,
             dtb_size,
+            cmdline: "",
         }
     }
 
+    /// Attach the kernel command line
+    pub const fn with_cmdline(mut self, cmdline: &'static str) -> Self {
+        self.cmdline = cmdline;
+        self
+    }
+
     /// Get the size of the root task image in bytes
     pub fn root_task_size(&self) -> usize {
         self.root_task_end - self.root_task_start
@@ -71,6 +83,21 @@ impl BootInfo {
     }
 }
 
+/// Build the command line string from the raw pointer/length boot parameters
+///
+/// Returns an empty string if no command line was passed or it is not UTF-8.
+///
+/// # Safety
+/// `addr..addr + len` must be readable memory that stays valid for the
+/// kernel's lifetime (the elfloader places it in reserved loader memory).
+pub unsafe fn cmdline_from_raw(addr: usize, len: usize) -> &'static str {
+    if addr == 0 || len == 0 {
+        return "";
+    }
+    let bytes = core::slice::from_raw_parts(addr as *const u8, len);
+    core::str::from_utf8(bytes).unwrap_or("")
+}
+
 /// Global boot information storage
 static mut BOOT_INFO: Option<BootInfo> = None;
 
@@ -120,6 +147,18 @@ mod tests {
         assert!(boot_info.is_valid());
     }
 
+    #[test]
+    fn test_bootinfo_cmdline() {
+        let boot_info = BootInfo::new(0x41000000, 0x41010000, 0, 0x41000000, 0x40000000, 8192);
+        assert_eq!(boot_info.cmdline, "");
+
+        let args = "loglevel=debug";
+        let raw = unsafe { cmdline_from_raw(args.as_ptr() as usize, args.len()) };
+        let boot_info = boot_info.with_cmdline(raw);
+        assert_eq!(boot_info.cmdline, "loglevel=debug");
+        assert_eq!(unsafe { cmdline_from_raw(0, 0) }, "");
+    }
+
     #[test]
     fn test_bootinfo_invalid() {
         let boot_info = BootInfo::new(0, 0, 0, 0, 0, 0);
@@ -4,6 +4,7 @@
 //! This is a minimal implementation for Chapter 1 - just enough to get:
 //! - Model name
 //! - Memory regions
+//! - Interrupt controller (GIC) version and registers
 
 use core::str;
 
@@ -42,6 +43,7 @@ const FDT_MAGIC: u32 = 0xd00dfeed;
 const FDT_BEGIN_NODE: u32 = 0x00000001;
 const FDT_END_NODE: u32 = 0x00000002;
 const FDT_PROP: u32 = 0x00000003;
+const FDT_NOP: u32 = 0x00000004;
 const FDT_END: u32 = 0x00000009;
 
 /// Parse device tree at given physical address
@@ -185,6 +187,174 @@ pub fn parse(dtb_addr: usize) -> Result<DtbInfo, DtbError> {
     })
 }
 
+/// Interrupt controller architecture version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GicVersion {
+    V2,
+    V3,
+}
+
+/// Interrupt controller information extracted from the DTB
+///
+/// Unused regions have a size of 0.
+#[derive(Debug, Clone, Copy)]
+pub struct GicInfo {
+    pub version: GicVersion,
+    /// Distributor (GICD)
+    pub dist_base: usize,
+    pub dist_size: usize,
+    /// GICv2 CPU interface (GICC)
+    pub cpu_base: usize,
+    pub cpu_size: usize,
+    /// GICv3 redistributor region (GICR)
+    pub redist_base: usize,
+    pub redist_size: usize,
+    /// GICv3 Interrupt Translation Service (ITS)
+    pub its_base: usize,
+    pub its_size: usize,
+}
+
+/// Maximum node depth tracked while searching for the GIC
+const MAX_DEPTH: usize = 16;
+
+/// What a node's `compatible` says it is
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GicNode {
+    None,
+    V2,
+    V3,
+    Its,
+}
+
+/// Classify a `compatible` string list
+fn gic_node_kind(compatible: &[u8]) -> GicNode {
+    for name in compatible.split(|&b| b == 0) {
+        match name {
+            b"arm,gic-v3" => return GicNode::V3,
+            b"arm,gic-v3-its" => return GicNode::Its,
+            b"arm,cortex-a15-gic" | b"arm,cortex-a9-gic" | b"arm,cortex-a7-gic"
+            | b"arm,gic-400" => return GicNode::V2,
+            _ => {}
+        }
+    }
+    GicNode::None
+}
+
+/// Read the `index`th (address, size) pair of a `reg` property
+///
+/// Like the memory node parsing above, this assumes 2 address cells and
+/// 2 size cells, as used by the 64-bit platforms KaaL supports.
+fn reg_entry(reg: usize, len: usize, index: usize) -> Option<(usize, usize)> {
+    if len < (index + 1) * 16 {
+        return None;
+    }
+    let entry = reg + index * 16;
+    Some((read_u64(entry) as usize, read_u64(entry + 8) as usize))
+}
+
+/// Find the interrupt controller in the device tree
+///
+/// Looks for a GICv3 (`arm,gic-v3`, with an optional `arm,gic-v3-its`
+/// child) or GICv2 node and returns its register regions. Returns None if
+/// the DTB is invalid or has no supported GIC, in which case the build's
+/// configured GICv2 addresses are used.
+pub fn find_gic(dtb_addr: usize) -> Option<GicInfo> {
+    let header = unsafe { &*(dtb_addr as *const FdtHeader) };
+    if u32::from_be(header.magic) != FDT_MAGIC {
+        return None;
+    }
+
+    let struct_base = dtb_addr + u32::from_be(header.off_dt_struct) as usize;
+    let strings_base = dtb_addr + u32::from_be(header.off_dt_strings) as usize;
+    let struct_size = u32::from_be(header.size_dt_struct) as usize;
+
+    // Per-depth node kind and `reg` property (address, length)
+    let mut kinds = [GicNode::None; MAX_DEPTH];
+    let mut regs = [(0usize, 0usize); MAX_DEPTH];
+    let mut depth = 0;
+    let mut gic: Option<GicInfo> = None;
+    let mut its: Option<(usize, usize)> = None;
+
+    let mut offset = 0;
+    while offset < struct_size {
+        let token = read_u32(struct_base + offset);
+        offset += 4;
+
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = read_string(struct_base + offset);
+                offset = align_up(offset + name.len() + 1, 4);
+                depth += 1;
+                if depth >= MAX_DEPTH {
+                    return None;
+                }
+                kinds[depth] = GicNode::None;
+                regs[depth] = (0, 0);
+            }
+            FDT_END_NODE => {
+                if depth == 0 {
+                    return None;
+                }
+                let (reg, len) = regs[depth];
+                match kinds[depth] {
+                    GicNode::V3 if gic.is_none() => {
+                        let (dist_base, dist_size) = reg_entry(reg, len, 0)?;
+                        let (redist_base, redist_size) = reg_entry(reg, len, 1)?;
+                        gic = Some(GicInfo {
+                            version: GicVersion::V3,
+                            dist_base, dist_size,
+                            cpu_base: 0, cpu_size: 0,
+                            redist_base, redist_size,
+                            its_base: 0, its_size: 0,
+                        });
+                    }
+                    GicNode::V2 if gic.is_none() => {
+                        let (dist_base, dist_size) = reg_entry(reg, len, 0)?;
+                        let (cpu_base, cpu_size) = reg_entry(reg, len, 1)?;
+                        gic = Some(GicInfo {
+                            version: GicVersion::V2,
+                            dist_base, dist_size,
+                            cpu_base, cpu_size,
+                            redist_base: 0, redist_size: 0,
+                            its_base: 0, its_size: 0,
+                        });
+                    }
+                    GicNode::Its if its.is_none() => its = reg_entry(reg, len, 0),
+                    _ => {}
+                }
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = read_u32(struct_base + offset) as usize;
+                let nameoff = read_u32(struct_base + offset + 4) as usize;
+                let data = struct_base + offset + 8;
+                offset = align_up(offset + 8 + len, 4);
+
+                match read_string_from_table(strings_base, nameoff) {
+                    "compatible" => {
+                        let compatible = unsafe { core::slice::from_raw_parts(data as *const u8, len) };
+                        kinds[depth] = gic_node_kind(compatible);
+                    }
+                    "reg" => regs[depth] = (data, len),
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => return None,
+        }
+    }
+
+    let mut gic = gic?;
+    if gic.version == GicVersion::V3 {
+        if let Some((its_base, its_size)) = its {
+            gic.its_base = its_base;
+            gic.its_size = its_size;
+        }
+    }
+    Some(gic)
+}
+
 /// Read big-endian u32
 #[inline]
 fn read_u32(addr: usize) -> u32 {
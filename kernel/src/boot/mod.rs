@@ -22,12 +22,14 @@ pub struct BootParams {
     pub root_v_entry: usize,
     pub pv_offset: usize,
     pub dtb_size: usize,
+    pub cmdline_addr: usize,
+    pub cmdline_len: usize,
 }
 
 /// Kernel entry point (called from _start)
 ///
 /// This is the first Rust function that executes.
-/// Boot parameters are in callee-saved registers x19-x23 (set by _start).
+/// Boot parameters are in callee-saved registers x19-x26 (set by _start).
 pub fn kernel_entry() -> ! {
     // CRITICAL: Get boot parameters from registers FIRST before any function calls
     // The registers x19-x23 are callee-saved, but we must read them before
@@ -44,6 +46,13 @@ pub fn kernel_entry() -> ! {
     crate::kprintln!("[boot] Root task: {:#x} - {:#x}", params.root_p_start, params.root_p_end);
     crate::kprintln!("[boot] Entry: {:#x}", params.root_v_entry);
     crate::kprintln!("[boot] PV offset: {:#x}", params.pv_offset);
+    if crate::arch::aarch64::hyp::installed() {
+        crate::kprintln!("[boot] Entered at EL2: hyp stub installed");
+    }
+    let cmdline = unsafe { bootinfo::cmdline_from_raw(params.cmdline_addr, params.cmdline_len) };
+    if !cmdline.is_empty() {
+        crate::kprintln!("[boot] Command line: {}", cmdline);
+    }
     crate::kprintln!("");
 
     // Initialize global boot info
@@ -54,7 +63,8 @@ pub fn kernel_entry() -> ! {
         params.root_v_entry,
         crate::memory::PhysAddr::new(params.dtb_addr),
         params.dtb_size,
-    );
+    )
+    .with_cmdline(cmdline);
     unsafe {
         bootinfo::init_boot_info(boot_info);
     }
@@ -78,6 +88,11 @@ pub fn kernel_entry() -> ! {
         }
     };
 
+    // Pick the interrupt controller before its registers are mapped
+    unsafe {
+        crate::arch::aarch64::gic::configure(dtb::find_gic(params.dtb_addr));
+    }
+
     crate::kprintln!("");
 
     // Memory Management - See docs/chapters/CHAPTER_02_STATUS.md
@@ -212,22 +227,15 @@ pub fn kernel_entry() -> ! {
         ).expect("Failed to map UART");
 
         // 5. Map GIC (Generic Interrupt Controller) for interrupt handling
-        crate::kprintln!("  Mapping GIC: {:#x} - {:#x}",
-            crate::generated::memory_config::GIC_DIST_BASE,
-            crate::generated::memory_config::GIC_DIST_BASE + crate::generated::memory_config::GIC_DIST_SIZE);
-        crate::memory::paging::identity_map_region(
-            &mut mapper,
-            crate::generated::memory_config::GIC_DIST_BASE,
-            crate::generated::memory_config::GIC_DIST_SIZE,
-            PageTableFlags::KERNEL_DEVICE,
-        ).expect("Failed to map GIC Distributor");
-
-        crate::memory::paging::identity_map_region(
-            &mut mapper,
-            crate::generated::memory_config::GIC_CPU_BASE,
-            crate::generated::memory_config::GIC_CPU_SIZE,
-            PageTableFlags::KERNEL_DEVICE,
-        ).expect("Failed to map GIC CPU Interface");
+        for (base, size) in crate::arch::aarch64::gic::mmio_regions() {
+            crate::kprintln!("  Mapping GIC: {:#x} - {:#x}", base, base + size);
+            crate::memory::paging::identity_map_region(
+                &mut mapper,
+                base,
+                size,
+                PageTableFlags::KERNEL_DEVICE,
+            ).expect("Failed to map GIC");
+        }
 
         // CRITICAL: Install exception handlers BEFORE MMU enable!
         // MMU enable might trigger exceptions, so handlers must be ready
@@ -293,10 +301,17 @@ pub fn kernel_entry() -> ! {
 
         // Initialize timer for preemption
         crate::scheduler::timer::init();
+        crate::scheduler::watchdog::init();
 
         // Enable timer interrupt in GIC
         crate::arch::aarch64::gic::enable_irq(crate::generated::memory_config::IRQ_TIMER);
 
+        // The EL2 timer stands in for the tick while a guest runs
+        #[cfg(feature = "hypervisor")]
+        if crate::arch::aarch64::hyp::installed() {
+            crate::arch::aarch64::gic::enable_irq(crate::arch::aarch64::hyp::PREEMPT_IRQ);
+        }
+
         // Enable IRQs at CPU level
         core::arch::asm!("msr daifclr, #2"); // Clear IRQ mask (bit 1 = IRQ)
     }
@@ -322,7 +337,7 @@ pub fn kernel_entry() -> ! {
 
 /// Get boot parameters from saved registers
 ///
-/// The _start function saves x0-x5 into x19-x24
+/// The _start function saves x0-x7 into x19-x26
 /// We retrieve them here
 #[inline(always)]
 unsafe fn get_boot_params() -> BootParams {
@@ -332,8 +347,10 @@ unsafe fn get_boot_params() -> BootParams {
     let root_v_entry: usize;
     let pv_offset: usize;
     let dtb_size: usize;
+    let cmdline_addr: usize;
+    let cmdline_len: usize;
 
-    // Use specific registers to avoid clobbering x19-x24
+    // Use specific registers to avoid clobbering x19-x26
     asm!(
         "mov {dtb}, x19",
         "mov {root_start}, x20",
@@ -341,12 +358,16 @@ unsafe fn get_boot_params() -> BootParams {
         "mov {entry}, x22",
         "mov {offset}, x23",
         "mov {dtb_size}, x24",
+        "mov {cmdline_addr}, x25",
+        "mov {cmdline_len}, x26",
         dtb = out(reg) dtb_addr,
         root_start = out(reg) root_p_start,
         root_end = out(reg) root_p_end,
         entry = out(reg) root_v_entry,
         offset = out(reg) pv_offset,
         dtb_size = out(reg) dtb_size,
+        cmdline_addr = out(reg) cmdline_addr,
+        cmdline_len = out(reg) cmdline_len,
         options(nomem, nostack),
     );
 
@@ -357,5 +378,7 @@ unsafe fn get_boot_params() -> BootParams {
         root_v_entry,
         pv_offset,
         dtb_size,
+        cmdline_addr,
+        cmdline_len,
     }
 }
@@ -40,6 +40,14 @@ unsafe fn populate_boot_info() -> Result<boot_info::BootInfo, RootTaskError> {
     info.user_virt_start = memory_config::USER_VIRT_START;
     info.ipc_buffer_vaddr = 0x8000_0000; // Fixed IPC buffer location
 
+    // Forward the command line so runtime services can read boot options,
+    // and the device tree so they can find the platform's devices
+    if let Some(kernel_info) = bootinfo::get_boot_info() {
+        info.set_cmdline(kernel_info.cmdline);
+        info.dtb_paddr = kernel_info.dtb_addr.as_u64();
+        info.dtb_size = kernel_info.dtb_size as u64;
+    }
+
     // TODO: Set capability slots when CSpace is implemented
     info.cspace_root_slot = 0; // Placeholder
     info.vspace_root_slot = 0; // Placeholder
@@ -90,6 +98,8 @@ unsafe fn populate_boot_info() -> Result<boot_info::BootInfo, RootTaskError> {
     crate::kprintln!("  Devices:  {} regions", info.num_device_regions);
     crate::kprintln!("  Untyped:  {} regions", info.num_untyped_regions);
     crate::kprintln!("  RAM size: {} MB", info.ram_size / (1024 * 1024));
+    crate::kprintln!("  Cmdline:  {} bytes", info.cmdline_len);
+    crate::kprintln!("  DTB:      {:#x} ({} bytes)", info.dtb_paddr, info.dtb_size);
 
     Ok(info)
 }
@@ -180,21 +190,26 @@ pub unsafe fn create_and_start_root_task() -> ! {
     ).expect("Failed to map UART into user PT");
 
     // Map GIC (interrupt controller) for IRQ handling in syscalls
-    crate::kprintln!("    GIC Distributor: {:#x}", memory_config::GIC_DIST_BASE);
-    crate::memory::paging::identity_map_region(
-        &mut mapper,
-        memory_config::GIC_DIST_BASE,
-        memory_config::GIC_DIST_SIZE,
-        PageTableFlags::KERNEL_DEVICE,
-    ).expect("Failed to map GIC distributor into user PT");
+    for (base, size) in crate::arch::aarch64::gic::mmio_regions() {
+        crate::kprintln!("    GIC: {:#x}", base);
+        crate::memory::paging::identity_map_region(
+            &mut mapper,
+            base,
+            size,
+            PageTableFlags::KERNEL_DEVICE,
+        ).expect("Failed to map GIC into user PT");
+    }
 
-    crate::kprintln!("    GIC CPU Interface: {:#x}", memory_config::GIC_CPU_BASE);
-    crate::memory::paging::identity_map_region(
-        &mut mapper,
-        memory_config::GIC_CPU_BASE,
-        memory_config::GIC_CPU_SIZE,
-        PageTableFlags::KERNEL_DEVICE,
-    ).expect("Failed to map GIC CPU interface into user PT");
+    // Map the hardware watchdog (if configured) so the timer tick can kick it
+    for &(base, size) in crate::config::WATCHDOG_MMIO {
+        crate::kprintln!("    Watchdog: {:#x}", base);
+        crate::memory::paging::identity_map_region(
+            &mut mapper,
+            base,
+            size,
+            PageTableFlags::KERNEL_DEVICE,
+        ).expect("Failed to map watchdog into user PT");
+    }
 
     crate::kprintln!("  ✓ Kernel regions mapped");
 
@@ -518,6 +533,7 @@ pub unsafe fn create_and_start_root_task() -> ! {
     crate::kprintln!("  Registering with scheduler...");
     // Register with scheduler as current thread
     crate::scheduler::test_set_current_thread(root_tcb_ptr);
+    crate::scheduler::stats::register(root_tcb_ptr);
 
     crate::kprintln!("  Root TCB:        {:#x} ✓", root_tcb_ptr as usize);
 
@@ -593,7 +609,7 @@ pub unsafe fn create_and_start_root_task() -> ! {
         "eret",
         entry = in(reg) entry_addr,
         sp = in(reg) stack_top,
-        ttbr0 = in(reg) user_page_table_phys.as_usize(),
+        ttbr0 = in(reg) crate::memory::asid::ttbr0_for(user_page_table_phys.as_usize() as u64),
         options(noreturn)
     );
 }
@@ -10,6 +10,7 @@
 //! - **console**: Just `putc()` for debug output (no interrupts, no buffering)
 //! - **timer**: Basic ticks for scheduling (Chapter 3)
 //! - **irq**: IRQ routing to user-space (Chapter 3)
+//! - **watchdog**: Optional hardware reset timer for hang detection
 //!
 //! Full-featured drivers live in user-space as framework components:
 //! - **uart_driver**: Full PL011 with interrupts, DMA, buffering
@@ -38,6 +39,7 @@
 //! ```
 
 pub mod console;
+pub mod watchdog;
 
 // Future chapters:
 // pub mod timer;   // Chapter 3: Timer for scheduling
@@ -0,0 +1,35 @@
+//! Watchdog component trait
+//!
+//! Provides a minimal hardware watchdog interface for the kernel's hang
+//! detection (`scheduler::watchdog`). The kernel only starts the watchdog
+//! and refreshes it from the timer tick while the system is healthy; if
+//! the kernel itself wedges with interrupts masked, the hardware resets
+//! the board.
+//!
+//! The watchdog is optional: without a `watchdog-*` feature the hang
+//! detection still runs in software, it just cannot catch a kernel that
+//! stopped taking timer interrupts.
+
+/// Watchdog trait for hardware reset timers
+///
+/// Kernel components implement this to let the kernel arm, refresh and
+/// disarm a reset timer. Implementations convert the timeout from
+/// milliseconds into their own clock.
+pub trait Watchdog: Send + Sync {
+    /// Short name for boot messages
+    fn name(&self) -> &'static str;
+
+    /// Start the watchdog, resetting the system after `timeout_ms`
+    /// milliseconds without a `kick()`
+    fn start(&self, timeout_ms: u64);
+
+    /// Restart the countdown
+    fn kick(&self);
+
+    /// Stop the watchdog
+    fn stop(&self);
+}
+
+// Component implementations
+pub mod sp805;
+pub mod sbsa;
@@ -0,0 +1,75 @@
+//! ARM SBSA Generic Watchdog component (minimal)
+//!
+//! The generic watchdog has two frames: the control frame (enable and the
+//! offset register) and the refresh frame (any write restarts the
+//! countdown). It counts at the system counter frequency. After `WOR`
+//! ticks it raises WS0 (an interrupt the kernel ignores), and after
+//! another `WOR` ticks WS1, which resets the system - so a timeout of T
+//! means an offset of T/2.
+
+use super::Watchdog;
+use core::ptr;
+
+/// WCS - control and status (control frame)
+const WCS: usize = 0x000;
+
+/// WOR - offset register, low 32 bits (control frame)
+const WOR: usize = 0x008;
+
+/// WRR - refresh register (refresh frame)
+const WRR: usize = 0x000;
+
+/// WCS.EN - enable the watchdog
+const WCS_EN: u32 = 1 << 0;
+
+/// SBSA generic watchdog component configuration
+#[derive(Clone, Copy)]
+pub struct SbsaConfig {
+    /// Physical address of the control frame
+    pub control_base: usize,
+    /// Physical address of the refresh frame
+    pub refresh_base: usize,
+}
+
+/// SBSA generic watchdog (kernel component)
+pub struct SbsaWatchdog {
+    control_base: usize,
+    refresh_base: usize,
+}
+
+impl SbsaWatchdog {
+    /// Create a new SBSA generic watchdog from configuration
+    ///
+    /// Both frames must be mapped in every address space the kernel runs
+    /// on (see `config::WATCHDOG_MMIO`).
+    pub const fn new(config: SbsaConfig) -> Self {
+        Self {
+            control_base: config.control_base,
+            refresh_base: config.refresh_base,
+        }
+    }
+}
+
+impl Watchdog for SbsaWatchdog {
+    fn name(&self) -> &'static str {
+        "SBSA generic watchdog"
+    }
+
+    fn start(&self, timeout_ms: u64) {
+        let ticks = crate::arch::aarch64::timer::frequency() * timeout_ms / 1000 / 2;
+        let offset = ticks.clamp(1, u32::MAX as u64) as u32;
+        unsafe {
+            ptr::write_volatile((self.control_base + WOR) as *mut u32, offset);
+            ptr::write_volatile((self.refresh_base + WRR) as *mut u32, 0);
+            ptr::write_volatile((self.control_base + WCS) as *mut u32, WCS_EN);
+        }
+    }
+
+    fn kick(&self) {
+        unsafe { ptr::write_volatile((self.refresh_base + WRR) as *mut u32, 0) };
+    }
+
+    fn stop(&self) {
+        unsafe { ptr::write_volatile((self.control_base + WCS) as *mut u32, 0) };
+    }
+}
@@ -0,0 +1,93 @@
+//! ARM SP805 watchdog component (minimal)
+//!
+//! The SP805 counts down from `WdogLoad` at WDOGCLK. The first time the
+//! counter reaches zero it raises its interrupt and reloads; if the
+//! interrupt is still pending the second time, it asserts reset. The
+//! kernel never services the interrupt, so a timeout of T means a load
+//! value of T/2.
+
+use super::Watchdog;
+use core::ptr;
+
+/// WdogLoad - counter reload value
+const WDOG_LOAD: usize = 0x000;
+
+/// WdogControl - INTEN (bit 0) and RESEN (bit 1)
+const WDOG_CONTROL: usize = 0x008;
+
+/// WdogIntClr - any write clears the interrupt and reloads the counter
+const WDOG_INTCLR: usize = 0x00C;
+
+/// WdogLock - write the unlock key to allow register writes
+const WDOG_LOCK: usize = 0xC00;
+
+/// WdogControl.INTEN - enable the counter and its interrupt
+const CONTROL_INTEN: u32 = 1 << 0;
+
+/// WdogControl.RESEN - enable the reset output
+const CONTROL_RESEN: u32 = 1 << 1;
+
+/// Key that unlocks the registers (any other value locks them)
+const LOCK_KEY: u32 = 0x1ACC_E551;
+
+/// SP805 watchdog component configuration
+#[derive(Clone, Copy)]
+pub struct Sp805Config {
+    /// Physical MMIO base address
+    pub mmio_base: usize,
+    /// WDOGCLK frequency in Hz
+    pub clock_hz: u64,
+}
+
+/// SP805 watchdog (kernel component)
+pub struct Sp805Watchdog {
+    mmio_base: usize,
+    clock_hz: u64,
+}
+
+impl Sp805Watchdog {
+    /// Create a new SP805 watchdog from configuration
+    ///
+    /// The MMIO base must be mapped in every address space the kernel runs
+    /// on (see `config::WATCHDOG_MMIO`).
+    pub const fn new(config: Sp805Config) -> Self {
+        Self {
+            mmio_base: config.mmio_base,
+            clock_hz: config.clock_hz,
+        }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.mmio_base + offset) as *mut u32, value) };
+    }
+
+    /// Write registers with the lock temporarily released
+    fn unlocked(&self, f: impl FnOnce(&Self)) {
+        self.write(WDOG_LOCK, LOCK_KEY);
+        f(self);
+        self.write(WDOG_LOCK, 0);
+    }
+}
+
+impl Watchdog for Sp805Watchdog {
+    fn name(&self) -> &'static str {
+        "SP805"
+    }
+
+    fn start(&self, timeout_ms: u64) {
+        let load = (self.clock_hz * timeout_ms / 1000 / 2).clamp(1, u32::MAX as u64) as u32;
+        self.unlocked(|wdog| {
+            wdog.write(WDOG_LOAD, load);
+            wdog.write(WDOG_INTCLR, 1);
+            wdog.write(WDOG_CONTROL, CONTROL_INTEN | CONTROL_RESEN);
+        });
+    }
+
+    fn kick(&self) {
+        self.unlocked(|wdog| wdog.write(WDOG_INTCLR, 1));
+    }
+
+    fn stop(&self) {
+        self.unlocked(|wdog| wdog.write(WDOG_CONTROL, 0));
+    }
+}
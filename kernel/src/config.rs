@@ -4,6 +4,7 @@
 //! composition based on cargo features.
 
 use crate::components::console::{Console, pl011::{Pl011Console, Pl011Config}};
+use crate::components::watchdog::Watchdog;
 
 /// Console component selection (compile-time)
 ///
@@ -58,3 +59,48 @@ pub fn console() -> &'static impl Console {
 pub fn console() -> &'static impl Console {
     &CONSOLE
 }
+
+/// Hardware watchdog selection (compile-time)
+///
+/// - `watchdog-sp805`: ARM SP805 (Arm FVP / Juno non-secure watchdog)
+/// - `watchdog-sbsa`: SBSA generic watchdog (QEMU `sbsa-ref`)
+///
+/// Without either, hang detection runs in software only (see
+/// `scheduler::watchdog`).
+#[cfg(feature = "watchdog-sp805")]
+pub static WATCHDOG: crate::components::watchdog::sp805::Sp805Watchdog =
+    crate::components::watchdog::sp805::Sp805Watchdog::new(crate::components::watchdog::sp805::Sp805Config {
+        mmio_base: 0x1C0F_0000,
+        clock_hz: 32_768,
+    });
+
+#[cfg(all(feature = "watchdog-sbsa", not(feature = "watchdog-sp805")))]
+pub static WATCHDOG: crate::components::watchdog::sbsa::SbsaWatchdog =
+    crate::components::watchdog::sbsa::SbsaWatchdog::new(crate::components::watchdog::sbsa::SbsaConfig {
+        control_base: 0x5001_1000,
+        refresh_base: 0x5001_0000,
+    });
+
+/// MMIO regions (base, size) of the hardware watchdog
+///
+/// The kernel runs on the current process's page table, so these are
+/// mapped into every address space alongside the UART and GIC.
+#[cfg(feature = "watchdog-sp805")]
+pub const WATCHDOG_MMIO: &[(usize, usize)] = &[(0x1C0F_0000, 0x1000)];
+
+#[cfg(all(feature = "watchdog-sbsa", not(feature = "watchdog-sp805")))]
+pub const WATCHDOG_MMIO: &[(usize, usize)] = &[(0x5001_0000, 0x1000), (0x5001_1000, 0x1000)];
+
+#[cfg(not(any(feature = "watchdog-sp805", feature = "watchdog-sbsa")))]
+pub const WATCHDOG_MMIO: &[(usize, usize)] = &[];
+
+/// Get the hardware watchdog, if one is configured
+#[cfg(any(feature = "watchdog-sp805", feature = "watchdog-sbsa"))]
+pub fn watchdog() -> Option<&'static dyn Watchdog> {
+    Some(&WATCHDOG)
+}
+
+#[cfg(not(any(feature = "watchdog-sp805", feature = "watchdog-sbsa")))]
+pub fn watchdog() -> Option<&'static dyn Watchdog> {
+    None
+}
@@ -0,0 +1,91 @@
+//! Kernel log ring buffer
+//!
+//! Everything written through `kprint!`/`kprintln!` is also kept in a
+//! fixed-size ring buffer, so kernel messages survive after the UART has
+//! scrolled past them. Userspace reads the buffer with `SYS_KLOG_READ`.
+//!
+//! Bytes are addressed by their offset in the log stream: the total number
+//! of bytes logged since boot. Once more than `KLOG_SIZE` bytes have been
+//! written the oldest are overwritten, and a reader asking for an
+//! overwritten offset continues from the oldest byte still held.
+//!
+//! Writers reserve their range with a single atomic add, so a message
+//! logged from an interrupt taken in the middle of another one cannot
+//! deadlock; at worst the two messages interleave.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of the ring buffer in bytes (power of two)
+pub const KLOG_SIZE: usize = 16 * 1024;
+
+/// The ring buffer
+static mut KLOG_BUFFER: [u8; KLOG_SIZE] = [0; KLOG_SIZE];
+
+/// Total number of bytes logged since boot
+static KLOG_HEAD: AtomicU64 = AtomicU64::new(0);
+
+/// Append a string to the log
+pub fn write(s: &str) {
+    let bytes = s.as_bytes();
+    let start = KLOG_HEAD.fetch_add(bytes.len() as u64, Ordering::AcqRel);
+
+    let buffer = core::ptr::addr_of_mut!(KLOG_BUFFER) as *mut u8;
+    for (i, &byte) in bytes.iter().enumerate() {
+        let index = (start as usize).wrapping_add(i) % KLOG_SIZE;
+        unsafe { buffer.add(index).write_volatile(byte) };
+    }
+}
+
+/// Offset one past the newest byte logged
+pub fn head() -> u64 {
+    KLOG_HEAD.load(Ordering::Acquire)
+}
+
+/// Range of log offsets that a read starting at `offset` covers
+///
+/// `offset` is clamped into the retained part of the log; at most `len`
+/// bytes are returned. Returns `(start, end)`.
+fn read_range(offset: u64, len: usize, head: u64) -> (u64, u64) {
+    let oldest = head.saturating_sub(KLOG_SIZE as u64);
+    let start = offset.clamp(oldest, head);
+    let end = core::cmp::min(head, start + len as u64);
+    (start, end)
+}
+
+/// Copy log bytes starting at `offset` into `buf`
+///
+/// Returns the number of bytes copied and the offset to continue reading
+/// from. If `offset` has already been overwritten, reading starts at the
+/// oldest retained byte instead; an offset past the newest byte returns
+/// nothing and the current head.
+pub fn read(offset: u64, buf: &mut [u8]) -> (usize, u64) {
+    let (start, end) = read_range(offset, buf.len(), head());
+
+    let buffer = core::ptr::addr_of!(KLOG_BUFFER) as *const u8;
+    let len = (end - start) as usize;
+    for (i, byte) in buf[..len].iter_mut().enumerate() {
+        let index = (start as usize).wrapping_add(i) % KLOG_SIZE;
+        *byte = unsafe { buffer.add(index).read_volatile() };
+    }
+
+    (len, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_range_clamps_to_retained_log() {
+        // Nothing overwritten yet
+        assert_eq!(read_range(0, 64, 100), (0, 64));
+        assert_eq!(read_range(90, 64, 100), (90, 100));
+
+        // Offsets past the head return nothing
+        assert_eq!(read_range(u64::MAX, 64, 100), (100, 100));
+
+        // Overwritten offsets skip to the oldest byte still held
+        let head = KLOG_SIZE as u64 + 500;
+        assert_eq!(read_range(0, 64, head), (500, 564));
+    }
+}
@@ -14,19 +14,29 @@
 //! - `log-debug`: DEBUG level and above
 //! - `log-trace`: TRACE level (everything)
 
+pub mod klog;
+
 use crate::components::console::Console;
 use core::fmt;
 
-/// Debug writer (uses UART)
+/// Debug writer (uses UART, and keeps a copy in the kernel log)
 pub struct DebugWriter;
 
 impl fmt::Write for DebugWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         crate::config::console().puts(s);
+        klog::write(s);
         Ok(())
     }
 }
 
+/// Write console output on behalf of userspace
+///
+/// Goes to the UART only: the kernel log is kept for kernel messages.
+pub fn user_write(s: &str) {
+    crate::config::console().puts(s);
+}
+
 /// Log levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(u8)]
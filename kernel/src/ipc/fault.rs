@@ -0,0 +1,362 @@
+//! Fault IPC - Page Fault Delegation to Userspace Pagers
+//!
+//! When a thread with a registered fault endpoint takes a translation,
+//! access flag or permission fault, the kernel does not panic. Instead it
+//! blocks the thread and sends a fault message to the endpoint, exactly as
+//! if the thread had called `send()` on it. The pager receives the message
+//! with an ordinary `SYS_RECV`, maps a frame (e.g. with `SYS_MEMORY_MAP_INTO`)
+//! and resumes the thread with `SYS_FAULT_RESUME`, which re-executes the
+//! faulting instruction. Fault messages carry badge 0.
+//!
+//! ## Fault Protocol
+//!
+//! ```text
+//! Faulting thread           Kernel                      Pager
+//!   |                         |                           |
+//!   | ldr x0, [unmapped]      |                           |
+//!   |------------------------>| BlockedOnFault            | recv(fault_ep)
+//!   |                         |-------------------------->| FaultMessage
+//!   |                         |                           |
+//!   |                         |      memory_map_into(...) |
+//!   |                         |<--------------------------|
+//!   |                         |      fault_resume(tcb)    |
+//!   |                         |<--------------------------|
+//!   | (retries instruction)   |                           |
+//!   v                         v                           v
+//! ```
+//!
+//! ## Crashes and Exits
+//!
+//! The endpoint also hears of the thread's end, so a supervisor can restart
+//! it: any other exception it takes (`FAULT_CRASH`), and `SYS_PROCESS_EXIT`
+//! (`FAULT_EXIT`, with the exit code as the address). The thread stays
+//! blocked, its resources held, until the receiver destroys it with
+//! `SYS_PROCESS_DESTROY`; `SYS_FAULT_RESUME` refuses to resume it.
+//!
+//! A worker thread's crash or exit ends its whole process, so its message
+//! carries the TID of the process's main thread, the one its supervisor
+//! knows and destroys. Page faults carry the worker's own TID.
+//!
+//! Threads without a fault endpoint keep the old behaviour: the fault is
+//! reported on the console and the kernel panics, and an exit tears the
+//! process down at once.
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::objects::{ThreadState, TCB};
+
+/// Fault kind: data read
+pub const FAULT_READ: u64 = 0;
+
+/// Fault kind: data write
+pub const FAULT_WRITE: u64 = 1;
+
+/// Fault kind: instruction fetch
+pub const FAULT_EXECUTE: u64 = 2;
+
+/// Fault kind: an exception no mapping resolves (alignment, undefined
+/// instruction, ...); the thread can only be destroyed
+pub const FAULT_CRASH: u64 = 3;
+
+/// Fault kind: the thread called `SYS_PROCESS_EXIT`
+pub const FAULT_EXIT: u64 = 4;
+
+/// ESR_EL1 exception class: SVC from AArch64
+const EC_SVC64: u64 = 0x15;
+
+/// ESR_EL1 exception class: instruction abort from a lower EL
+const EC_INSTRUCTION_ABORT_LOWER: u64 = 0x20;
+
+/// ESR_EL1 exception class: data abort from a lower EL
+const EC_DATA_ABORT_LOWER: u64 = 0x24;
+
+/// ESR_EL1.ISS.WnR - data abort caused by a write
+const ISS_WNR: u64 = 1 << 6;
+
+/// General purpose registers a fault message carries: x0 up to x22
+pub const FAULT_REGS: usize = 23;
+
+/// Fault message delivered to the pager
+///
+/// Sent as the IPC payload (little-endian u64 words): the five words a
+/// pager needs, then the thread's registers for a crash report. A receive
+/// buffer shorter than [`SIZE`](Self::SIZE) gets the first five and the
+/// registers that fit. The pager gets the faulting thread's TID so it can
+/// tell its clients apart.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultMessage {
+    /// TID of the faulting thread (of its process, for a worker's crash
+    /// or exit)
+    pub tid: u64,
+    /// `FAULT_READ`, `FAULT_WRITE`, `FAULT_EXECUTE`, `FAULT_CRASH` or
+    /// `FAULT_EXIT`
+    pub kind: u64,
+    /// Faulting virtual address (FAR_EL1), or the exit code for `FAULT_EXIT`
+    pub addr: u64,
+    /// Faulting instruction (ELR_EL1)
+    pub pc: u64,
+    /// Raw syndrome (ESR_EL1)
+    pub esr: u64,
+    /// x0 to x22
+    pub regs: [u64; FAULT_REGS],
+    /// Frame pointer (x29)
+    pub fp: u64,
+    /// Link register (x30)
+    pub lr: u64,
+    /// Stack pointer (SP_EL0)
+    pub sp: u64,
+    /// Processor state (SPSR_EL1)
+    pub spsr: u64,
+}
+
+impl FaultMessage {
+    /// Size of the encoded message in bytes: as large as IPC messages go
+    pub const SIZE: usize = (5 + FAULT_REGS + 4) * 8;
+
+    /// Size of the words before the registers, the least a receive buffer
+    /// must hold
+    pub const HEADER_SIZE: usize = 5 * 8;
+
+    /// Build the fault message for a thread from its saved context
+    pub fn from_thread(tcb: &TCB) -> Self {
+        let c = tcb.context();
+        let kind = fault_kind(c.esr_el1);
+        // A worker's crash or exit is its process's
+        let tid = if kind == FAULT_EXIT || kind == FAULT_CRASH {
+            unsafe { (*tcb.process()).tid() }
+        } else {
+            tcb.tid()
+        };
+        Self {
+            tid: tid as u64,
+            kind,
+            // An exit's code is its syscall argument
+            addr: if kind == FAULT_EXIT { c.x0 } else { c.far_el1 },
+            pc: c.elr_el1,
+            esr: c.esr_el1,
+            regs: [
+                c.x0, c.x1, c.x2, c.x3, c.x4, c.x5, c.x6, c.x7, c.x8, c.x9, c.x10, c.x11,
+                c.x12, c.x13, c.x14, c.x15, c.x16, c.x17, c.x18, c.x19, c.x20, c.x21, c.x22,
+            ],
+            fp: c.x29,
+            lr: c.x30,
+            sp: c.sp_el0,
+            spsr: c.spsr_el1,
+        }
+    }
+
+    /// Encode the message as it is delivered to userspace
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        let header = [self.tid, self.kind, self.addr, self.pc, self.esr];
+        let trailer = [self.fp, self.lr, self.sp, self.spsr];
+        let words = header.iter().chain(&self.regs).chain(&trailer);
+        for (chunk, word) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(words) {
+            *chunk = word.to_le_bytes();
+        }
+        bytes
+    }
+}
+
+/// The part of an encoded fault message a receive buffer of `len` bytes
+/// takes: whole words, at least the header; None if that does not fit
+pub fn fitting(message: &[u8; FaultMessage::SIZE], len: usize) -> Option<&[u8]> {
+    (len >= FaultMessage::HEADER_SIZE).then(|| &message[..len.min(FaultMessage::SIZE) / 8 * 8])
+}
+
+/// Classify a syndrome as read, write or execute fault, crash or exit
+fn fault_kind(esr: u64) -> u64 {
+    let ec = (esr >> 26) & 0x3F;
+    if ec == EC_SVC64 {
+        FAULT_EXIT
+    } else if !is_pager_fault(esr) {
+        FAULT_CRASH
+    } else if ec == EC_INSTRUCTION_ABORT_LOWER {
+        FAULT_EXECUTE
+    } else if esr & ISS_WNR != 0 {
+        FAULT_WRITE
+    } else {
+        FAULT_READ
+    }
+}
+
+/// Check whether an EL0 exception is a fault a pager can resolve
+///
+/// Only instruction/data aborts with a translation, access flag or
+/// permission fault status qualify; alignment faults, external aborts and
+/// the like are bugs in the thread, not missing mappings.
+pub fn is_pager_fault(esr: u64) -> bool {
+    let ec = (esr >> 26) & 0x3F;
+    if ec != EC_INSTRUCTION_ABORT_LOWER && ec != EC_DATA_ABORT_LOWER {
+        return false;
+    }
+
+    // IFSC/DFSC: 0b0001xx translation, 0b0010xx access flag, 0b0011xx permission
+    let fsc = esr & 0x3F;
+    (0x04..=0x0F).contains(&fsc)
+}
+
+/// Deliver a fault taken by the current thread to its fault endpoint
+///
+/// Used for page faults, crashes, and exits (from the `SYS_PROCESS_EXIT`
+/// trap frame). Saves the faulting context, blocks the thread in
+/// `BlockedOnFault` and hands the fault message to the thread's fault endpoint: directly to a
+/// waiting receiver, or by queueing the thread as a sender. `tf` is then
+/// replaced with the next thread's context.
+///
+/// Returns false (leaving everything untouched) if the thread has no fault
+/// endpoint, in which case the caller reports the fault as before.
+///
+/// # Safety
+///
+/// - Must be called from the lower-EL synchronous exception handler or a
+///   syscall it dispatched
+/// - `tf` must be the trap frame the exception return restores
+/// - Scheduler must be initialized
+pub unsafe fn handle_user_fault(tf: &mut TrapFrame) -> bool {
+    let current = crate::scheduler::current_thread();
+    if current.is_null() {
+        return false;
+    }
+
+    let endpoint_ptr = (*current).fault_endpoint();
+    if endpoint_ptr.is_null() {
+        return false;
+    }
+
+    let thread = &mut *current;
+    let endpoint = &mut *endpoint_ptr;
+    let endpoint_addr = endpoint_ptr as usize;
+
+    // Save the faulting context; resuming the thread re-executes the
+    // instruction at ELR
+    *thread.context_mut() = *tf;
+
+    if let Some(receiver_tcb) = endpoint.dequeue_receiver() {
+        // Pager already waiting: deliver straight into its receive buffer
+        let receiver = &mut *receiver_tcb;
+        let message = FaultMessage::from_thread(thread).to_bytes();
+        let delivered = fitting(&message, receiver.context().x2 as usize)
+            .is_some_and(|message| crate::syscall::deliver_to_receiver(receiver, message, 0));
+
+        if !delivered {
+            // Put the pager back and let the caller report the fault
+            endpoint.queue_receive(receiver_tcb);
+            return false;
+        }
+
+        receiver.set_reply_to(core::ptr::null_mut());
+        receiver.set_state(ThreadState::Runnable);
+        crate::scheduler::enqueue(receiver_tcb);
+    } else {
+        // No pager waiting: queue as a sender, the message is built from the
+        // saved context when the pager receives
+        endpoint.queue_send(current);
+    }
+
+    thread.block_on_fault(endpoint_addr);
+
+    crate::kprintln!("[fault] TID {} faulted at {:#x} (PC {:#x}, ESR {:#x}), sent to fault endpoint {:#x}",
+                     thread.tid(), tf.far_el1, tf.elr_el1, tf.esr_el1, endpoint_addr);
+
+    let next = crate::scheduler::schedule();
+    if next.is_null() {
+        // Nothing else to run (shouldn't happen with the idle thread)
+        endpoint.dequeue_specific_sender(current);
+        thread.set_state(ThreadState::Running);
+        return false;
+    }
+
+    crate::syscall::switch_to(tf, next);
+    true
+}
+
+/// Resume a thread blocked on a fault
+///
+/// The thread is made runnable with its saved context unchanged, so it
+/// retries the faulting instruction. Returns false if the thread is not
+/// waiting on a fault, or its fault is a crash or exit, which retrying
+/// cannot get past.
+///
+/// # Safety
+///
+/// - `tcb` must be a valid TCB pointer
+/// - Scheduler must be initialized
+pub unsafe fn resume_faulted(tcb: *mut TCB) -> bool {
+    let thread = &mut *tcb;
+    let endpoint = match thread.state() {
+        ThreadState::BlockedOnFault { endpoint } => endpoint as *mut crate::objects::Endpoint,
+        _ => return false,
+    };
+    if !is_pager_fault(thread.context().esr_el1) {
+        return false;
+    }
+
+    // Resumed before the pager received the message: drop it from the queue
+    (*endpoint).dequeue_specific_sender(tcb);
+
+    crate::scheduler::unblock(tcb);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fault_classification() {
+        // Data abort, translation fault level 3, read
+        assert!(is_pager_fault(0x9200_0007));
+        assert_eq!(fault_kind(0x9200_0007), FAULT_READ);
+
+        // Data abort, permission fault level 3, write
+        assert!(is_pager_fault(0x9200_004F));
+        assert_eq!(fault_kind(0x9200_004F), FAULT_WRITE);
+
+        // Instruction abort, translation fault level 2
+        assert!(is_pager_fault(0x8200_0006));
+        assert_eq!(fault_kind(0x8200_0006), FAULT_EXECUTE);
+
+        // Data abort, alignment fault: not for the pager
+        assert!(!is_pager_fault(0x9200_0021));
+        assert_eq!(fault_kind(0x9200_0021), FAULT_CRASH);
+
+        // Undefined instruction: a crash
+        assert_eq!(fault_kind(0x0200_0000), FAULT_CRASH);
+
+        // SVC: not an abort, but how an exit is reported
+        assert!(!is_pager_fault(0x5600_0000));
+        assert_eq!(fault_kind(0x5600_0000), FAULT_EXIT);
+    }
+
+    #[test]
+    fn fault_message_encoding() {
+        let mut regs = [0; FAULT_REGS];
+        regs[0] = 0xAAAA;
+        regs[22] = 0xBBBB;
+        let msg = FaultMessage {
+            tid: 1,
+            kind: FAULT_WRITE,
+            addr: 0x4000_1000,
+            pc: 0x20_0000,
+            esr: 0x9200_004F,
+            regs,
+            fp: 0x7FFF_F000,
+            lr: 0x20_0100,
+            sp: 0x7FFF_EF00,
+            spsr: 0,
+        };
+        let bytes = msg.to_bytes();
+        assert_eq!(bytes.len(), FaultMessage::SIZE);
+        assert_eq!(FaultMessage::SIZE, 256);
+        assert_eq!(&bytes[16..24], &0x4000_1000u64.to_le_bytes());
+        assert_eq!(&bytes[40..48], &0xAAAAu64.to_le_bytes());
+        assert_eq!(&bytes[216..224], &0xBBBBu64.to_le_bytes());
+        assert_eq!(&bytes[232..240], &0x20_0100u64.to_le_bytes());
+
+        // Short buffers get the header and whole registers, too short ones nothing
+        assert_eq!(fitting(&bytes, 1024).map(<[u8]>::len), Some(FaultMessage::SIZE));
+        assert_eq!(fitting(&bytes, 44).map(<[u8]>::len), Some(FaultMessage::HEADER_SIZE));
+        assert_eq!(fitting(&bytes, 39), None);
+    }
+}
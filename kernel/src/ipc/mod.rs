@@ -37,6 +37,7 @@ pub mod message;
 pub mod operations;
 pub mod cap_transfer;
 pub mod call;
+pub mod fault;
 pub mod test_runner;
 
 // Re-export main types
@@ -44,3 +45,4 @@ pub use message::{Message, IpcBuffer, IpcError, MAX_MSG_REGS, FAST_PATH_REGS, MA
 pub use operations::{send, recv};
 pub use cap_transfer::{TransferMode, grant_capability, mint_capability, derive_capability};
 pub use call::{call, reply};
+pub use fault::FaultMessage;
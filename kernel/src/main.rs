@@ -5,18 +5,31 @@ use core::arch::global_asm;
 
 // Kernel entry point - save boot parameters and call kernel_entry
 //
-// Elfloader passes parameters in x0-x5:
+// The boot CPU enters at _start; secondary cores enter at _start + 4
+// (SECONDARY_ENTRY_OFFSET in the elfloader), and are parked in a WFE loop.
+// Telling them apart by entry point rather than by MPIDR leaves no doubt
+// whichever affinity the boot CPU has.
+//
+// Elfloader passes parameters in x0-x7:
 //   x0 = user_img_start, x1 = user_img_end, x2 = pv_offset
 //   x3 = user_entry, x4 = dtb_addr, x5 = dtb_size
+//   x6 = cmdline_addr, x7 = cmdline_len
 //
-// Kernel saves parameters in x19-x24:
+// Kernel saves parameters in x19-x26:
 //   x19 = dtb_addr, x20 = root_p_start, x21 = root_p_end
 //   x22 = root_v_entry, x23 = pv_offset, x24 = dtb_size
+//   x25 = cmdline_addr, x26 = cmdline_len
 global_asm!(
     ".section .text._start",
     ".global _start",
     ".type _start, @function",
     "_start:",
+    "    b 1f",
+    "    // _start + 4: secondary cores (released by an SMP elfloader)",
+    "    b 3f",
+    "1:",
+    "    // Entered at EL2: install the hyp stub and drop to EL1",
+    "    bl hyp_init",
     "    // Enable FP/SIMD before anything else (CPACR_EL1.FPEN = 0b11)",
     "    mrs x10, cpacr_el1",
     "    orr x10, x10, #(0x3 << 20)",
@@ -29,7 +42,15 @@ global_asm!(
     "    mov x22, x3",      // x22 = user_entry (from x3)
     "    mov x23, x2",      // x23 = pv_offset (from x2)
     "    mov x24, x5",      // x24 = dtb_size (from x5)
+    "    mov x25, x6",      // x25 = cmdline_addr (from x6)
+    "    mov x26, x7",      // x26 = cmdline_len (from x7)
     "    b {kernel_entry}", // Jump to kernel_entry
+    "3:",
+    "    // Secondary cores park here until the kernel brings them up",
+    "    bl hyp_init",
+    "2:",
+    "    wfe",
+    "    b 2b",
     kernel_entry = sym kaal_kernel::boot::kernel_entry,
 );
 
@@ -0,0 +1,194 @@
+//! Address Space Identifiers (ASIDs)
+//!
+//! Every user address space (page table root) is given an 8-bit ASID,
+//! carried in TTBR0_EL1[55:48]. User mappings are non-global, so their TLB
+//! entries are tagged with the ASID and entries of different address spaces
+//! can live side by side: switching processes is a TTBR0 write, with no TLB
+//! flush. Kernel mappings are global and shared by all ASIDs.
+//!
+//! ASIDs are handed out on first use, when an address space is first
+//! activated. When all 255 are taken, every ASID except the active one is
+//! recycled with a single full TLB flush (a rollover); address spaces that
+//! lose their ASID get a new one the next time they run. ASID 0 is never
+//! allocated: it tags whatever the boot code cached.
+//!
+//! Page table roots are stored in trap frames and TCBs as plain physical
+//! addresses; `ttbr0_for` produces the tagged TTBR0 value.
+
+use core::arch::asm;
+
+/// Number of ASIDs (8-bit ASIDs, TCR_EL1.AS = 0)
+pub const NUM_ASIDS: usize = 256;
+
+/// TTBR0_EL1.ASID shift
+const TTBR_ASID_SHIFT: u64 = 48;
+
+/// TTBR0_EL1 bits holding the table base address
+pub const TTBR_BADDR_MASK: u64 = (1 << TTBR_ASID_SHIFT) - 1;
+
+/// Page table root owning each ASID (0 = free)
+static mut ASID_OWNERS: [u64; NUM_ASIDS] = [0; NUM_ASIDS];
+
+/// Where the search for a free ASID starts
+static mut NEXT_ASID: usize = 1;
+
+/// Find the ASID of a page table root, if it has one
+fn lookup(owners: &[u64; NUM_ASIDS], root: u64) -> Option<usize> {
+    if root == 0 {
+        return None;
+    }
+    (1..NUM_ASIDS).find(|&asid| owners[asid] == root)
+}
+
+/// Pick a free ASID, never `active`, searching from `next`
+fn find_free(owners: &[u64; NUM_ASIDS], next: usize, active: usize) -> Option<usize> {
+    (0..NUM_ASIDS - 1)
+        .map(|i| 1 + (next - 1 + i) % (NUM_ASIDS - 1))
+        .find(|&asid| owners[asid] == 0 && asid != active)
+}
+
+/// ASID currently loaded in TTBR0_EL1
+fn active_asid() -> usize {
+    let ttbr0: u64;
+    unsafe {
+        asm!("mrs {}, ttbr0_el1", out(reg) ttbr0, options(nomem, nostack));
+    }
+    (ttbr0 >> TTBR_ASID_SHIFT) as usize & (NUM_ASIDS - 1)
+}
+
+/// TTBR0_EL1 value for a page table root, allocating its ASID if needed
+///
+/// # Safety
+///
+/// `root` must be the physical address of a user page table root. Must not
+/// race with other ASID operations (interrupts masked in the kernel).
+pub unsafe fn ttbr0_for(root: u64) -> u64 {
+    let root = root & TTBR_BADDR_MASK;
+    if root == 0 {
+        return 0;
+    }
+    let owners = &mut *core::ptr::addr_of_mut!(ASID_OWNERS);
+
+    let asid = match lookup(owners, root) {
+        Some(asid) => asid,
+        None => {
+            let active = active_asid();
+            let asid = match find_free(owners, NEXT_ASID, active) {
+                Some(asid) => asid,
+                None => {
+                    rollover(owners, active);
+                    find_free(owners, 1, active).expect("[asid] no ASID free after rollover")
+                }
+            };
+            owners[asid] = root;
+            NEXT_ASID = asid % (NUM_ASIDS - 1) + 1;
+            asid
+        }
+    };
+
+    root | ((asid as u64) << TTBR_ASID_SHIFT)
+}
+
+/// Free every ASID but the active one and flush their TLB entries
+unsafe fn rollover(owners: &mut [u64; NUM_ASIDS], active: usize) {
+    for (asid, owner) in owners.iter_mut().enumerate() {
+        if asid != active {
+            *owner = 0;
+        }
+    }
+
+    asm!(
+        "dsb ishst",
+        "tlbi vmalle1is",
+        "dsb ish",
+        "isb",
+        options(nostack),
+    );
+}
+
+/// Switch TTBR0_EL1 to a page table root
+///
+/// Does nothing if the root is already active. No TLB maintenance is
+/// needed: the old address space's entries stay tagged with its ASID.
+///
+/// # Safety
+///
+/// `root` must be the physical address of a user page table root that
+/// maps the kernel, or 0 (ignored).
+pub unsafe fn activate(root: u64) {
+    if root == 0 {
+        return;
+    }
+
+    let ttbr0 = ttbr0_for(root);
+    let current: u64;
+    asm!("mrs {}, ttbr0_el1", out(reg) current, options(nomem, nostack));
+    if current != ttbr0 {
+        asm!("msr ttbr0_el1, {}", "isb", in(reg) ttbr0, options(nostack));
+    }
+}
+
+/// Invalidate the TLB entries of one address space
+///
+/// Call after changing or removing mappings in the tables under `root`.
+/// An address space without an ASID has nothing cached, so only the
+/// barrier publishing the table writes is needed.
+///
+/// # Safety
+///
+/// Must not race with other ASID operations.
+pub unsafe fn flush_vspace(root: u64) {
+    let owners = &*core::ptr::addr_of!(ASID_OWNERS);
+    match lookup(owners, root & TTBR_BADDR_MASK) {
+        Some(asid) => asm!(
+            "dsb ishst",
+            "tlbi aside1is, {}",
+            "dsb ish",
+            "isb",
+            in(reg) (asid as u64) << TTBR_ASID_SHIFT,
+            options(nostack),
+        ),
+        None => asm!("dsb ishst", "isb", options(nostack)),
+    }
+}
+
+/// Give up the ASID of an address space that is being destroyed
+///
+/// Its TLB entries are invalidated so the ASID can be reused.
+///
+/// # Safety
+///
+/// Must not race with other ASID operations.
+pub unsafe fn release(root: u64) {
+    flush_vspace(root);
+
+    let owners = &mut *core::ptr::addr_of_mut!(ASID_OWNERS);
+    if let Some(asid) = lookup(owners, root & TTBR_BADDR_MASK) {
+        owners[asid] = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_free_skips_reserved_and_active() {
+        let mut owners = [0u64; NUM_ASIDS];
+        assert_eq!(find_free(&owners, 1, 0), Some(1));
+        assert_eq!(find_free(&owners, 1, 1), Some(2));
+
+        // Wraps around past 255, never handing out ASID 0
+        owners[255] = 0x1000;
+        assert_eq!(find_free(&owners, 255, 0), Some(1));
+
+        for owner in owners.iter_mut().skip(1) {
+            *owner = 0x2000;
+        }
+        owners[7] = 0;
+        assert_eq!(find_free(&owners, 1, 7), None);
+        assert_eq!(find_free(&owners, 1, 0), Some(7));
+        assert_eq!(lookup(&owners, 0x2000), Some(1));
+        assert_eq!(lookup(&owners, 0), None);
+    }
+}
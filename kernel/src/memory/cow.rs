@@ -0,0 +1,175 @@
+//! Copy-on-Write Frame Sharing
+//!
+//! `SYS_PROCESS_CREATE` with `PROCESS_CREATE_COW` does not give the new
+//! process the image frames the parent loaded; it maps them read-only and
+//! marked `SW_COW`. Any number of processes created from one loaded image
+//! share its frames. The first write to such a page takes a permission
+//! fault: the kernel copies the frame into a fresh one, maps the copy
+//! writable (marked `SW_COW_COPY`) and retries the instruction. Pages that
+//! are only read or executed, i.e. the code, are never copied.
+//!
+//! The shared frames stay owned by the parent, which must not modify the
+//! image while processes created from it are alive. Private copies belong
+//! to the address space and are freed with its page tables.
+//!
+//! The kernel writes to user memory at EL1 through the user mapping, which
+//! is read-only too, so `copy_to_user` breaks COW on the destination pages
+//! first (`prepare_write`).
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::arch::aarch64::page_table::{PageTable, PageTableFlags};
+use super::{alloc_frame, dealloc_frame, MappingError, PageFrameNumber, PageMapper, PhysAddr, VirtAddr, PAGE_SIZE};
+
+/// Data cache line size used for maintenance (ARM64 typical)
+const CACHE_LINE_SIZE: usize = 64;
+
+/// ESR_EL1 exception class: data abort from a lower EL
+const EC_DATA_ABORT_LOWER: u64 = 0x24;
+
+/// ESR_EL1.ISS.WnR - data abort caused by a write
+const ISS_WNR: u64 = 1 << 6;
+
+/// Whether an EL0 exception is a write to a page mapped read-only
+pub fn is_write_permission_fault(esr: u64) -> bool {
+    let ec = (esr >> 26) & 0x3F;
+    // DFSC 0b0011xx: permission fault at any level
+    ec == EC_DATA_ABORT_LOWER && esr & ISS_WNR != 0 && esr & 0x3C == 0x0C
+}
+
+/// Flags of the private copy of a copy-on-write page
+fn copy_flags(flags: PageTableFlags) -> PageTableFlags {
+    (flags - PageTableFlags::SW_COW - PageTableFlags::AP_RO_ALL)
+        | PageTableFlags::AP_RW_ALL
+        | PageTableFlags::SW_COW_COPY
+}
+
+/// Replace the copy-on-write page at `vaddr` with a private writable copy
+///
+/// # Returns
+/// - `Ok(true)`: the page was copied; the caller must flush the TLB
+/// - `Ok(false)`: `vaddr` is not in a copy-on-write page
+/// - `Err(MappingError::FrameAllocFailed)`: out of memory
+///
+/// # Safety
+/// The address space must not be modified concurrently.
+unsafe fn break_page(mapper: &mut PageMapper, vaddr: usize) -> Result<bool, MappingError> {
+    let page = VirtAddr::new(vaddr & !(PAGE_SIZE - 1));
+    let Some((table, index)) = mapper.page_entry(page) else {
+        return Ok(false);
+    };
+
+    let flags = table.get_flags(index);
+    if !flags.contains(PageTableFlags::SW_COW) {
+        return Ok(false);
+    }
+
+    let shared = table.get_addr(index).ok_or(MappingError::NotMapped)?;
+    let copy = alloc_frame().ok_or(MappingError::FrameAllocFailed)?.phys_addr();
+    core::ptr::copy_nonoverlapping(shared.as_usize() as *const u8, copy.as_usize() as *mut u8, PAGE_SIZE);
+    table.set_entry(index, copy, copy_flags(flags));
+
+    // The copy may hold code: clean it to the point of unification and
+    // drop stale instructions
+    for line in (copy.as_usize()..copy.as_usize() + PAGE_SIZE).step_by(CACHE_LINE_SIZE) {
+        core::arch::asm!("dc cvau, {}", in(reg) line);
+    }
+    core::arch::asm!("dsb ish", "ic iallu", "dsb ish", "isb");
+    Ok(true)
+}
+
+/// Mapper for the address space with page table root `root`
+unsafe fn mapper_for(root: u64) -> PageMapper {
+    let root = root & crate::memory::asid::TTBR_BADDR_MASK;
+    PageMapper::new(&mut *(root as *mut PageTable))
+}
+
+/// Resolve a write fault on a copy-on-write page
+///
+/// Returns true if the fault was a write to a copy-on-write page, which is
+/// now a private writable copy: returning from the exception retries the
+/// write. False leaves the fault to the caller.
+///
+/// # Safety
+/// - Must be called from the lower-EL synchronous exception handler
+/// - `tf` must be the faulting thread's trap frame
+pub unsafe fn handle_fault(tf: &TrapFrame) -> bool {
+    if !is_write_permission_fault(tf.esr_el1) {
+        return false;
+    }
+
+    let mut mapper = mapper_for(tf.saved_ttbr0);
+    match break_page(&mut mapper, tf.far_el1 as usize) {
+        Ok(true) => {
+            crate::memory::asid::flush_vspace(tf.saved_ttbr0);
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            crate::kprintln!("[cow] Cannot copy page at {:#x}: {:?}", tf.far_el1, e);
+            false
+        }
+    }
+}
+
+/// Break copy-on-write for every page of a user buffer the kernel writes
+///
+/// Returns false if a page could not be copied.
+///
+/// # Safety
+/// `root` must be the page table root of a user address space.
+pub unsafe fn prepare_write(root: u64, user_ptr: u64, len: usize) -> bool {
+    if len == 0 || root & crate::memory::asid::TTBR_BADDR_MASK == 0 {
+        return true;
+    }
+
+    let mut mapper = mapper_for(root);
+    let first = user_ptr as usize & !(PAGE_SIZE - 1);
+    let end = user_ptr as usize + len;
+    let mut copied = false;
+
+    for page in (first..end).step_by(PAGE_SIZE) {
+        match break_page(&mut mapper, page) {
+            Ok(true) => copied = true,
+            Ok(false) => {}
+            Err(_) => return false,
+        }
+    }
+
+    if copied {
+        crate::memory::asid::flush_vspace(root);
+    }
+    true
+}
+
+/// Free the frame of a page entry if it is a private copy
+///
+/// Called for every level-3 entry when an address space is destroyed.
+///
+/// # Safety
+/// The address space must be going away; the entry must not be used again.
+pub unsafe fn release_page(entry: u64) {
+    let flags = PageTableFlags::from_bits_truncate(entry);
+    if flags.contains(PageTableFlags::VALID | PageTableFlags::SW_COW_COPY) {
+        let frame = PhysAddr::new((entry & 0x0000_FFFF_FFFF_F000) as usize);
+        dealloc_frame(PageFrameNumber::from_phys_addr(frame));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cow_fault_and_flags() {
+        // Write permission fault (DFSC 0x0F, level 3) from EL0
+        assert!(is_write_permission_fault((0x24 << 26) | ISS_WNR | 0x0F));
+        // Read permission fault, write translation fault
+        assert!(!is_write_permission_fault((0x24 << 26) | 0x0F));
+        assert!(!is_write_permission_fault((0x24 << 26) | ISS_WNR | 0x07));
+
+        let copy = copy_flags(PageTableFlags::USER_COW);
+        assert!(!copy.contains(PageTableFlags::SW_COW));
+        assert!(copy.contains(PageTableFlags::SW_COW_COPY));
+        assert_eq!(copy - PageTableFlags::SW_COW_COPY, PageTableFlags::USER_RWX);
+    }
+}
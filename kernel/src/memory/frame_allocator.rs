@@ -124,6 +124,37 @@ impl FrameAllocator {
         None
     }
 
+    /// Allocate `count` physically contiguous frames
+    ///
+    /// The first frame's physical address is aligned to `align` bytes (a
+    /// power of two, at least `PAGE_SIZE`). Used for hardware tables that
+    /// must be contiguous in physical memory.
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PageFrameNumber> {
+        if count == 0 || count > self.free_frames {
+            return None;
+        }
+
+        let align_frames = (align / PAGE_SIZE).max(1);
+        let misalign = (self.ram_base / PAGE_SIZE) % align_frames;
+        let mut start = (align_frames - misalign) % align_frames;
+        while start + count <= self.total_frames {
+            match (start..start + count).find(|&frame| self.bitmap.is_set(frame)) {
+                // Skip to the next aligned start past the allocated frame
+                Some(used) => start += (used - start) / align_frames * align_frames + align_frames,
+                None => {
+                    for frame in start..start + count {
+                        self.bitmap.set(frame);
+                    }
+                    self.free_frames -= count;
+                    let phys_addr = self.ram_base + start * PAGE_SIZE;
+                    return Some(PageFrameNumber::from_phys_addr(PhysAddr::new(phys_addr)));
+                }
+            }
+        }
+
+        None
+    }
+
     /// Deallocate a physical frame
     ///
     /// # Safety
@@ -196,4 +227,18 @@ mod tests {
         allocator.reserve_region(PhysAddr::new(0x100000), 64 * 1024);
         assert_eq!(allocator.free_frames(), initial_free - 16);
     }
+
+    #[test]
+    fn test_frame_allocator_contiguous() {
+        let mut allocator = FrameAllocator::new();
+        allocator.add_region(PhysAddr::new(0x100000), 1024 * 1024);
+
+        // A frame in the first 64KB pushes the run to the next 64KB boundary
+        allocator.alloc().unwrap();
+        let run = allocator.alloc_contiguous(4, 64 * 1024).unwrap();
+        assert_eq!(run.phys_addr().as_usize(), 0x110000);
+        assert_eq!(allocator.free_frames(), 256 - 5);
+
+        assert!(allocator.alloc_contiguous(512, PAGE_SIZE).is_none());
+    }
 }
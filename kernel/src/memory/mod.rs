@@ -17,12 +17,16 @@
 //! - `frame_allocator`: Physical page frame allocator
 //! - `paging`: Page table abstraction (TODO)
 //! - `heap`: Kernel heap allocator (TODO)
+//! - `asid`: Address space identifiers for tagged TLB entries
+//! - `cow`: Copy-on-write sharing of process image frames
 
 pub mod address;
 pub mod frame_allocator;
 pub mod paging;
 pub mod heap;
 pub mod bitmap;
+pub mod asid;
+pub mod cow;
 
 pub use address::{PhysAddr, VirtAddr, PageFrameNumber};
 pub use address::{PAGE_SIZE, LARGE_PAGE_SIZE, HUGE_PAGE_SIZE};
@@ -90,6 +94,15 @@ pub fn alloc_frame() -> Option<PageFrameNumber> {
         .and_then(|allocator| allocator.lock().alloc())
 }
 
+/// Allocate `count` physically contiguous frames aligned to `align` bytes
+///
+/// Returns the first frame, or None if no suitable run is free.
+pub fn alloc_contiguous(count: usize, align: usize) -> Option<PageFrameNumber> {
+    FRAME_ALLOCATOR
+        .get()
+        .and_then(|allocator| allocator.lock().alloc_contiguous(count, align))
+}
+
 /// Deallocate a physical frame
 ///
 /// # Safety
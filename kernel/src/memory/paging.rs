@@ -10,7 +10,7 @@
 use crate::arch::aarch64::page_table::{
     PageTable, PageTableFlags, PageTableLevel,
 };
-use crate::memory::{PhysAddr, VirtAddr, alloc_frame};
+use crate::memory::{PhysAddr, VirtAddr, PageFrameNumber, alloc_frame, dealloc_frame};
 use crate::memory::{PAGE_SIZE, LARGE_PAGE_SIZE, HUGE_PAGE_SIZE};
 
 /// Page mapping error
@@ -24,6 +24,8 @@ pub enum MappingError {
     AlreadyMapped,
     /// Invalid page table level for operation
     InvalidLevel,
+    /// No mapping exists at this address
+    NotMapped,
 }
 
 /// Page size for mappings
@@ -56,6 +58,45 @@ impl PageSize {
     pub const fn is_aligned(&self, addr: usize) -> bool {
         addr & (self.bytes() - 1) == 0
     }
+
+    /// Get the page size mapped by a leaf entry at the given level
+    pub const fn from_level(level: PageTableLevel) -> Option<PageSize> {
+        match level {
+            PageTableLevel::L1 => Some(PageSize::Size1GB),
+            PageTableLevel::L2 => Some(PageSize::Size2MB),
+            PageTableLevel::L3 => Some(PageSize::Size4KB),
+            PageTableLevel::L0 => None,
+        }
+    }
+}
+
+/// Free the tables referenced by `table` (at `level`), depth first
+unsafe fn free_table_level(table: *mut PageTable, level: PageTableLevel) {
+    let next_level = match level.next() {
+        Some(next) => next,
+        None => {
+            // L3 entries are pages, not tables; only private copies made by
+            // copy-on-write faults belong to the address space
+            for &entry in (*table).entries.iter() {
+                super::cow::release_page(entry);
+            }
+            return;
+        }
+    };
+
+    for index in 0..(*table).entries.len() {
+        let entry = (*table).entries[index];
+        let is_table = entry & PageTableFlags::VALID.bits() != 0
+            && entry & PageTableFlags::TABLE_OR_PAGE.bits() != 0;
+        if !is_table {
+            continue;
+        }
+
+        let next_table = PhysAddr::new((entry & 0x0000_FFFF_FFFF_F000) as usize);
+        free_table_level(next_table.as_usize() as *mut PageTable, next_level);
+        (*table).clear_entry(index);
+        dealloc_frame(PageFrameNumber::from_phys_addr(next_table));
+    }
 }
 
 /// Page mapper for managing page tables
@@ -154,10 +195,103 @@ impl PageMapper {
         Ok(())
     }
 
-    /// Translate a virtual address to a physical address
+    /// Map a physically contiguous region with pages of a single size
     ///
-    /// Walks the page tables to find the physical address mapping.
-    pub fn translate(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+    /// Large pages (2MB/1GB) are installed as L2/L1 block descriptors, so a
+    /// big region such as a DMA pool or framebuffer costs a handful of
+    /// entries (and TLB slots) instead of one per 4KB page.
+    ///
+    /// # Arguments
+    /// - `vaddr`, `paddr`: Start of the region (must be aligned to `page_size`)
+    /// - `size`: Size in bytes (must be a multiple of `page_size`)
+    /// - `flags`: Page table entry flags
+    /// - `page_size`: Size of each mapping
+    pub fn map_region(
+        &mut self,
+        vaddr: VirtAddr,
+        paddr: PhysAddr,
+        size: usize,
+        flags: PageTableFlags,
+        page_size: PageSize,
+    ) -> Result<(), MappingError> {
+        if !page_size.is_aligned(size) {
+            return Err(MappingError::AddressMisaligned);
+        }
+
+        for offset in (0..size).step_by(page_size.bytes()) {
+            self.map(
+                VirtAddr::new(vaddr.as_usize() + offset),
+                PhysAddr::new(paddr.as_usize() + offset),
+                flags,
+                page_size,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Unmap whatever leaf entry (page or block) maps a virtual address
+    ///
+    /// Unlike `unmap`, the caller does not need to know the page size the
+    /// address was mapped with. `vaddr` must be the start of the page or
+    /// block.
+    ///
+    /// # Returns
+    /// - `Ok(page_size)`: Size of the mapping that was removed
+    /// - `Err(MappingError::NotMapped)`: Nothing is mapped at `vaddr`
+    /// - `Err(MappingError::AddressMisaligned)`: `vaddr` is inside a block
+    pub fn unmap_leaf(&mut self, vaddr: VirtAddr) -> Result<PageSize, MappingError> {
+        let mut table = self.root as *mut PageTable;
+        let mut level = PageTableLevel::L0;
+
+        loop {
+            let index = level.index(vaddr);
+            let entry = unsafe { (*table).entries[index] };
+
+            if entry & PageTableFlags::VALID.bits() == 0 {
+                return Err(MappingError::NotMapped);
+            }
+
+            let is_table = entry & PageTableFlags::TABLE_OR_PAGE.bits() != 0;
+            let is_leaf = match level.next() {
+                Some(_) => !is_table && level.supports_blocks(),
+                None => true,
+            };
+
+            if is_leaf {
+                let page_size = PageSize::from_level(level).ok_or(MappingError::InvalidLevel)?;
+                if !page_size.is_aligned(vaddr.as_usize()) {
+                    return Err(MappingError::AddressMisaligned);
+                }
+                unsafe { (*table).clear_entry(index) };
+                return Ok(page_size);
+            }
+
+            let next_table_addr = (entry & 0x0000_FFFF_FFFF_F000) as usize;
+            table = next_table_addr as *mut PageTable;
+            level = level.next().unwrap();
+        }
+    }
+
+    /// Find the level-3 entry of a 4KB page
+    ///
+    /// Returns the table holding the entry and its index, or None if
+    /// `vaddr` is not mapped by a 4KB page.
+    pub fn page_entry(&mut self, vaddr: VirtAddr) -> Option<(&mut PageTable, usize)> {
+        let table = self.walk_to_level(vaddr, PageTableLevel::L3, false).ok()?;
+        let index = PageTableLevel::L3.index(vaddr);
+        if !table.is_valid(index) {
+            return None;
+        }
+        Some((table, index))
+    }
+
+    /// Find the leaf entry mapping a virtual address
+    ///
+    /// Returns the page or block entry and the level it was found at
+    /// (L1/L2 for 1GB/2MB blocks, L3 for 4KB pages), or None if `vaddr`
+    /// is not mapped.
+    pub fn leaf_entry(&self, vaddr: VirtAddr) -> Option<(u64, PageTableLevel)> {
         let mut table = self.root as *const PageTable;
         let mut level = PageTableLevel::L0;
 
@@ -173,10 +307,7 @@ impl PageMapper {
             // Check if this is a block entry (1GB or 2MB)
             let is_table = entry & PageTableFlags::TABLE_OR_PAGE.bits() != 0;
             if !is_table && level.supports_blocks() {
-                // Block entry - extract address and add offset
-                let block_addr = (entry & 0x0000_FFFF_FFFF_F000) as usize;
-                let offset = vaddr.as_usize() & (level.block_size() - 1);
-                return Some(PhysAddr::new(block_addr + offset));
+                return Some((entry, level));
             }
 
             // Move to next level
@@ -186,16 +317,22 @@ impl PageMapper {
                     let next_table_addr = (entry & 0x0000_FFFF_FFFF_F000) as usize;
                     table = next_table_addr as *const PageTable;
                 }
-                None => {
-                    // L3 entry - page mapping
-                    let page_addr = (entry & 0x0000_FFFF_FFFF_F000) as usize;
-                    let offset = vaddr.as_usize() & (PAGE_SIZE - 1);
-                    return Some(PhysAddr::new(page_addr + offset));
-                }
+                // L3 entry - page mapping
+                None => return Some((entry, level)),
             }
         }
     }
 
+    /// Translate a virtual address to a physical address
+    ///
+    /// Walks the page tables to find the physical address mapping.
+    pub fn translate(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        let (entry, level) = self.leaf_entry(vaddr)?;
+        let frame_addr = (entry & 0x0000_FFFF_FFFF_F000) as usize;
+        let offset = vaddr.as_usize() & (level.block_size() - 1);
+        Some(PhysAddr::new(frame_addr + offset))
+    }
+
     /// Walk page tables to a specific level, optionally allocating tables
     ///
     /// # Arguments
@@ -244,6 +381,11 @@ impl PageMapper {
                 *entry = (phys_addr.as_usize() as u64) | flags.bits();
             }
 
+            // A block entry already maps this whole range; it is not a table
+            if *entry & PageTableFlags::TABLE_OR_PAGE.bits() == 0 {
+                return Err(MappingError::AlreadyMapped);
+            }
+
             // Move to next level
             let next_table_addr = (*entry & 0x0000_FFFF_FFFF_F000) as usize;
             table = next_table_addr as *mut PageTable;
@@ -253,6 +395,20 @@ impl PageMapper {
         Ok(unsafe { &mut *table })
     }
 
+    /// Free every intermediate page table below the root
+    ///
+    /// Used when an address space is destroyed. Only the tables the mapper
+    /// allocated are returned to the frame allocator, together with the
+    /// private copies made by copy-on-write faults; the other frames mapped
+    /// by leaf entries and the root table itself belong to the caller.
+    ///
+    /// # Safety
+    /// - The address space must not be in use by any thread
+    /// - Every table below the root must have come from `alloc_frame`
+    pub unsafe fn free_tables(self) {
+        free_table_level(self.root as *mut PageTable, PageTableLevel::L0)
+    }
+
     /// Get the physical address of the root page table
     pub fn root_phys_addr(&self) -> PhysAddr {
         PhysAddr::new(self.root as *const _ as usize)
@@ -165,10 +165,20 @@ impl Capability {
         })
     }
 
-    /// Mint a new capability with a badge (for endpoints)
+    /// Mint a new capability with a badge (for endpoints and notifications)
+    ///
+    /// The badge identifies the holder to the server: it is delivered with
+    /// every message sent through an endpoint capability, and is the bit
+    /// pattern OR'd in by a signal through a notification capability.
+    /// Badges are non-zero, and a badged capability cannot be re-badged, so
+    /// a client can never pass itself off as another.
     pub fn mint(&self, badge: u64) -> Result<Self, CapError> {
-        // Only endpoints can be badged
-        if !matches!(self.cap_type, CapType::Endpoint) {
+        // Only endpoints and notifications can be badged
+        if !matches!(self.cap_type, CapType::Endpoint | CapType::Notification) {
+            return Err(CapError::InvalidOperation);
+        }
+
+        if badge == 0 || self.badge() != 0 {
             return Err(CapError::InvalidOperation);
         }
 
@@ -198,6 +208,14 @@ impl Capability {
         self.guard = badge;
     }
 
+    /// Set the guard word (for CNode capabilities)
+    ///
+    /// See `cnode_cdt::encode_guard` for the encoding.
+    #[inline]
+    pub fn set_guard(&mut self, guard: u64) {
+        self.guard = guard;
+    }
+
     /// Set the rights for this capability
     #[inline]
     pub fn set_rights(&mut self, rights: CapRights) {
@@ -247,6 +265,12 @@ pub enum CapType {
 
     /// Reply - one-time reply capability for IPC call/reply
     Reply = 11,
+
+    /// Timer - signals a notification after a timeout
+    Timer = 12,
+
+    /// VCPU - virtual CPU running a guest under stage-2 translation
+    Vcpu = 13,
 }
 
 /// Capability rights (bitflags)
@@ -402,4 +426,21 @@ mod tests {
         // Cannot derive with more rights
         assert!(derived.derive(CapRights::WRITE).is_err());
     }
+
+    #[test]
+    fn mint_badge() {
+        let ep = Capability::new(CapType::Endpoint, 0x1000);
+        let badged = ep.mint(0x42).unwrap();
+        assert_eq!(badged.badge(), 0x42);
+
+        // Badged caps cannot be re-badged, and zero is not a badge
+        assert!(badged.mint(0x43).is_err());
+        assert!(ep.mint(0).is_err());
+
+        let ntfn = Capability::new(CapType::Notification, 0x2000);
+        assert_eq!(ntfn.mint(0x1).unwrap().badge(), 0x1);
+
+        let tcb = Capability::new(CapType::Tcb, 0x3000);
+        assert!(tcb.mint(0x1).is_err());
+    }
 }
@@ -20,16 +20,23 @@
 //! ## Revocation
 //!
 //! When revoking a capability, we recursively revoke all descendants to ensure
-//! no dangling capabilities remain after the parent is revoked.
+//! no dangling capabilities remain after the parent is revoked. Descendants
+//! may live in other CSpaces, so every node records the CNode slot holding
+//! it; the revoke sweep empties those slots as it frees the nodes.
+//!
+//! Deleting a single capability does not touch its descendants: they are
+//! handed to its parent, so a later revoke of the parent still reaches them.
 //!
 //! ## Memory
 //!
-//! Each CDT node adds 24 bytes of overhead (3 pointers):
+//! Each CDT node adds 40 bytes of overhead (3 tree pointers + slot location):
 //! - parent: *mut CapNode
 //! - first_child: *mut CapNode
 //! - next_sibling: *mut CapNode
+//! - cnode: *mut CNodeCdt, slot: usize
 
 use super::capability::{Capability, CapRights, CapError};
+use super::cnode_cdt::CNodeCdt;
 
 /// CDT node wrapping a capability with derivation tree links
 #[repr(C)]
@@ -45,6 +52,12 @@ pub struct CapNode {
 
     /// Next sibling (children form a linked list under parent)
     pub next_sibling: Option<*mut CapNode>,
+
+    /// CNode holding this node (null if not in a CNode)
+    pub cnode: *mut CNodeCdt,
+
+    /// Slot index in `cnode`
+    pub slot: usize,
 }
 
 impl CapNode {
@@ -61,6 +74,8 @@ impl CapNode {
             parent: None,
             first_child: None,
             next_sibling: None,
+            cnode: core::ptr::null_mut(),
+            slot: 0,
         }
     }
 
@@ -78,6 +93,8 @@ impl CapNode {
             parent: Some(parent),
             first_child: None,
             next_sibling: None,
+            cnode: core::ptr::null_mut(),
+            slot: 0,
         }
     }
 
@@ -136,16 +153,15 @@ impl CapNode {
         let child_ptr = allocator(child_node);
 
         // Link child into our children list
-        (*child_ptr).next_sibling = self.first_child;
-        self.first_child = Some(child_ptr);
+        self.adopt(child_ptr);
 
         Ok(child_ptr)
     }
 
-    /// Mint a child capability with a badge (for endpoints)
+    /// Mint a child capability with a badge (for endpoints and notifications)
     ///
     /// # Arguments
-    /// * `badge` - Badge to add to the endpoint capability
+    /// * `badge` - Badge to add to the capability
     /// * `allocator` - Function to allocate a new CDT node
     ///
     /// # Returns
@@ -161,7 +177,7 @@ impl CapNode {
     where
         F: FnOnce(CapNode) -> *mut CapNode,
     {
-        // Mint the capability (validates it's an endpoint)
+        // Mint the capability (validates its type and badge)
         let child_cap = self.capability.mint(badge)?;
 
         // Create child node
@@ -171,8 +187,7 @@ impl CapNode {
         let child_ptr = allocator(child_node);
 
         // Link child into our children list
-        (*child_ptr).next_sibling = self.first_child;
-        self.first_child = Some(child_ptr);
+        self.adopt(child_ptr);
 
         Ok(child_ptr)
     }
@@ -205,41 +220,95 @@ impl CapNode {
         }
     }
 
-    /// Revoke this capability and all descendants (recursive)
+    /// Link an existing node as a child of this node
     ///
-    /// This performs a depth-first traversal of the derivation tree,
-    /// revoking all children before revoking this node.
+    /// # Safety
+    /// `child_ptr` must be a valid node that is not linked under another parent
+    pub unsafe fn adopt(&mut self, child_ptr: *mut CapNode) {
+        (*child_ptr).parent = Some(self as *mut CapNode);
+        (*child_ptr).next_sibling = self.first_child;
+        self.first_child = Some(child_ptr);
+    }
+
+    /// Take this node out of the derivation tree
     ///
-    /// # Arguments
-    /// * `deallocator` - Function to free a CDT node
+    /// The node is removed from its parent's child list and its children
+    /// are handed to the parent (or become roots if there is none), so
+    /// deleting one capability never orphans or revokes the ones derived
+    /// from it.
     ///
     /// # Safety
-    /// - Caller must ensure no other references to this node or its descendants exist
-    /// - After this call, the node and all descendants are freed
-    /// - Caller is responsible for removing this node from parent's child list if needed
-    pub unsafe fn revoke_recursive<F>(
+    /// Parent and child pointers must be valid
+    pub unsafe fn unlink(&mut self) {
+        let self_ptr = self as *mut CapNode;
+        if let Some(parent_ptr) = self.parent {
+            (*parent_ptr).remove_child(self_ptr);
+        }
+
+        let mut child = self.first_child.take();
+        while let Some(child_ptr) = child {
+            child = (*child_ptr).next_sibling;
+            match self.parent {
+                Some(parent_ptr) => (*parent_ptr).adopt(child_ptr),
+                None => {
+                    (*child_ptr).parent = None;
+                    (*child_ptr).next_sibling = None;
+                }
+            }
+        }
+
+        self.parent = None;
+    }
+
+    /// Free all descendants of this capability (depth-first)
+    ///
+    /// `deallocator` is called once for every descendant, children before
+    /// their parent, with the node's capability still intact so the caller
+    /// can clear its slot and release the object it refers to. This node
+    /// itself is left alone, with no children.
+    ///
+    /// # Safety
+    /// - Caller must ensure no other references to the descendants exist
+    /// - After this call, all descendants are freed
+    pub unsafe fn revoke_descendants<F>(
         &mut self,
         deallocator: &mut F,
     ) where
         F: FnMut(*mut CapNode),
     {
-        // Revoke all children first (depth-first traversal)
-        let mut child = self.first_child;
+        let mut child = self.first_child.take();
         while let Some(child_ptr) = child {
             let child_node = &mut *child_ptr;
             let next = child_node.next_sibling;
 
-            // Recursively revoke child and its descendants
-            child_node.revoke_recursive(deallocator);
-
-            // Free the child node
+            // Free the child's subtree, then the child itself
+            child_node.revoke_descendants(deallocator);
             deallocator(child_ptr);
 
             child = next;
         }
+    }
 
-        // Clear children list
-        self.first_child = None;
+    /// Revoke this capability and all descendants (recursive)
+    ///
+    /// This performs a depth-first traversal of the derivation tree,
+    /// revoking all children before revoking this node.
+    ///
+    /// # Arguments
+    /// * `deallocator` - Function to free a CDT node
+    ///
+    /// # Safety
+    /// - Caller must ensure no other references to this node or its descendants exist
+    /// - After this call, all descendants are freed and this node is nullified
+    /// - This node is removed from its parent's child list
+    pub unsafe fn revoke_recursive<F>(
+        &mut self,
+        deallocator: &mut F,
+    ) where
+        F: FnMut(*mut CapNode),
+    {
+        // Revoke all children first (depth-first traversal)
+        self.revoke_descendants(deallocator);
 
         // Nullify this capability
         self.capability = Capability::null();
@@ -400,4 +469,28 @@ mod tests {
             assert_eq!(root.first_child, Some(child1));
         }
     }
+
+    #[test]
+    fn test_unlink_hands_children_to_parent() {
+        let cap = Capability::new(CapType::Endpoint, 0x1000);
+        let mut root = CapNode::new_root(cap);
+        let mut alloc = TestAllocator::new();
+
+        unsafe {
+            // root -> child -> grandchild
+            let child = root.derive_child(CapRights::ALL, |node| alloc.alloc(node)).unwrap();
+            let grandchild = (*child).derive_child(CapRights::READ, |node| alloc.alloc(node)).unwrap();
+
+            // Deleting the middle node keeps the grandchild under the root
+            (*child).unlink();
+            assert_eq!(root.first_child, Some(grandchild));
+            assert_eq!((*grandchild).parent, Some(&mut root as *mut CapNode));
+
+            // Revoking the root still reaches it
+            let mut freed = 0;
+            root.revoke_descendants(&mut |_| freed += 1);
+            assert_eq!(freed, 1);
+            assert!(!root.has_children());
+        }
+    }
 }
@@ -4,15 +4,16 @@
 //!
 //! ## Design
 //!
-//! We use a simple bump allocator for CDT nodes during Phase 2.
-//! This can be upgraded to a more sophisticated allocator (slab, buddy) later.
+//! We use a simple bump allocator for CDT nodes, with a free list in front
+//! of it: nodes released by delete/revoke are reused before the bump pointer
+//! advances, so capability churn does not exhaust the region.
 //!
 //! ## Memory Layout
 //!
 //! CDT nodes are allocated from a dedicated memory region:
-//! - Size: CapNode = 56 bytes (32 byte cap + 24 bytes tree pointers)
+//! - Size: CapNode = 72 bytes (32 byte cap + 24 bytes tree pointers + 16 bytes slot location)
 //! - Alignment: 8 bytes (pointer alignment)
-//! - Capacity: Configurable at init (default: 4096 nodes = 224 KB)
+//! - Capacity: Configurable at init (default: 4096 nodes = 288 KB)
 
 use super::cdt::CapNode;
 use crate::memory::PhysAddr;
@@ -27,7 +28,7 @@ pub struct CdtAllocatorConfig {
 }
 
 impl CdtAllocatorConfig {
-    /// Create config with default size (4096 nodes = ~224 KB)
+    /// Create config with default size (4096 nodes = ~288 KB)
     pub const fn with_capacity(base: PhysAddr, node_count: usize) -> Self {
         Self {
             base,
@@ -45,10 +46,9 @@ impl CdtAllocatorConfig {
 ///
 /// This allocator:
 /// - Allocates CDT nodes sequentially from a fixed region
-/// - Does NOT support deallocation (simplified for now)
-/// - Thread-safe via atomic operations
-///
-/// **Future**: Upgrade to slab allocator with free list
+/// - Keeps freed nodes on an intrusive free list and reuses them first
+/// - Relies on the kernel being non-preemptible (single core) for the
+///   free list; the bump pointer itself is atomic
 pub struct CdtAllocator {
     /// Base address of CDT region
     base: usize,
@@ -56,6 +56,11 @@ pub struct CdtAllocator {
     offset: AtomicUsize,
     /// Total size of region
     size: usize,
+    /// Head of the free list (0 = empty); each free node stores the next
+    /// free node's address in its first word
+    free_list: AtomicUsize,
+    /// Number of nodes on the free list
+    free_count: AtomicUsize,
 }
 
 impl CdtAllocator {
@@ -65,6 +70,8 @@ impl CdtAllocator {
             base: 0,
             offset: AtomicUsize::new(0),
             size: 0,
+            free_list: AtomicUsize::new(0),
+            free_count: AtomicUsize::new(0),
         }
     }
 
@@ -78,6 +85,8 @@ impl CdtAllocator {
         self.base = config.base.as_usize();
         self.offset.store(0, Ordering::Relaxed);
         self.size = config.size;
+        self.free_list.store(0, Ordering::Relaxed);
+        self.free_count.store(0, Ordering::Relaxed);
 
         crate::kprintln!("[cdt] Initialized CDT allocator:");
         crate::kprintln!("      Base: {:#x}", self.base);
@@ -96,6 +105,15 @@ impl CdtAllocator {
     /// # Safety
     /// Caller must initialize the returned memory before use
     pub fn alloc(&self) -> Option<*mut CapNode> {
+        // Reuse a freed node if there is one
+        let head = self.free_list.load(Ordering::Relaxed);
+        if head != 0 {
+            let next = unsafe { *(head as *const usize) };
+            self.free_list.store(next, Ordering::Relaxed);
+            self.free_count.fetch_sub(1, Ordering::Relaxed);
+            return Some(head as *mut CapNode);
+        }
+
         let node_size = core::mem::size_of::<CapNode>();
         let node_align = core::mem::align_of::<CapNode>();
 
@@ -118,22 +136,27 @@ impl CdtAllocator {
 
     /// Deallocate a CDT node
     ///
-    /// # Note
-    /// Current implementation (bump allocator) does NOT actually free memory.
-    /// This is a placeholder for future slab allocator implementation.
+    /// The node is pushed on the free list and handed out again by the
+    /// next `alloc`.
     ///
     /// # Safety
     /// - `ptr` must have been allocated by this allocator
     /// - `ptr` must not be used after deallocation
-    pub unsafe fn dealloc(&self, _ptr: *mut CapNode) {
-        // Bump allocator: no actual deallocation
-        // TODO: Implement free list when upgrading to slab allocator
+    pub unsafe fn dealloc(&self, ptr: *mut CapNode) {
+        if ptr.is_null() {
+            return;
+        }
+
+        *(ptr as *mut usize) = self.free_list.load(Ordering::Relaxed);
+        self.free_list.store(ptr as usize, Ordering::Relaxed);
+        self.free_count.fetch_add(1, Ordering::Relaxed);
     }
 
     /// Get current allocation statistics
     pub fn stats(&self) -> CdtAllocatorStats {
-        let used_bytes = self.offset.load(Ordering::Relaxed);
         let node_size = core::mem::size_of::<CapNode>();
+        let freed_bytes = self.free_count.load(Ordering::Relaxed) * node_size;
+        let used_bytes = self.offset.load(Ordering::Relaxed) - freed_bytes;
 
         CdtAllocatorStats {
             nodes_allocated: used_bytes / node_size,
@@ -301,4 +324,27 @@ mod tests {
         let stats = allocator.stats();
         assert_eq!(stats.utilization(), 25); // 25% utilization
     }
+
+    #[test]
+    fn test_dealloc_reuses_node() {
+        let mut region = [0u64; 64];
+        let mut allocator = CdtAllocator::new();
+        unsafe {
+            let config = CdtAllocatorConfig::with_capacity(
+                PhysAddr::new(region.as_mut_ptr() as usize),
+                2
+            );
+            allocator.init(config);
+        }
+
+        let node1 = allocator.alloc().unwrap();
+        let _node2 = allocator.alloc().unwrap();
+        assert!(allocator.alloc().is_none());
+
+        // A freed node is handed out again
+        unsafe { allocator.dealloc(node1) };
+        assert_eq!(allocator.stats().nodes_allocated, 1);
+        assert_eq!(allocator.alloc(), Some(node1));
+        assert!(allocator.alloc().is_none());
+    }
 }
@@ -8,7 +8,32 @@
 //! - Slots contain `Option<*mut CapNode>` instead of `Option<Capability>`
 //! - Insert operations allocate CDT nodes
 //! - Derive operations create parent-child relationships in the CDT
-//! - Revoke operations recursively delete all descendants
+//! - Revoke operations recursively delete all descendants, emptying their
+//!   slots in whichever CNode (CSpace) holds them
+//! - Delete removes a single capability; its descendants move up to its parent
+//!
+//! ## Object Lifetime
+//!
+//! Endpoints, notifications, timers and VCPUs count the capabilities
+//! referring to them. Every capability entering a slot retains its object
+//! and every one leaving releases it; when the last capability is gone the
+//! object's queued threads are cancelled, as no one could ever wake them, a
+//! timer is disarmed and a VCPU's guest address space is freed.
+//!
+//! ## Guarded Lookup
+//!
+//! A CSpace can be a tree: a slot may hold a CNode capability, and
+//! `resolve` walks the tree seL4-style. A capability address (CPtr) is
+//! consumed from its most significant bit, `depth` bits in all. The root
+//! CNode takes `size_bits` bits as the slot index; at every CNode
+//! capability on the way, its guard bits must match the next bits of the
+//! address before the next CNode's index bits are taken. The lookup ends
+//! when exactly `depth` bits are used up. Guards let a small CNode cover a
+//! large address range without intermediate levels.
+//!
+//! Syscalls take the depth from the top byte of their CPtr arguments
+//! (`CPTR_DEPTH_SHIFT`); a depth of 0 is a plain slot index in the root,
+//! as in a flat CSpace.
 //!
 //! ## Migration Path
 //!
@@ -16,11 +41,47 @@
 //! Once fully tested, we can deprecate the old CNode and rename CNodeCdt → CNode.
 
 use crate::memory::PhysAddr;
-use super::{Capability, CapError, CapRights};
+use super::{Capability, CapError, CapRights, CapType, Endpoint, Notification, Timer};
 use super::cdt::CapNode;
 use super::cdt_allocator::{alloc_cdt_node, dealloc_cdt_node};
 use core::ptr;
 
+/// Bit position of the lookup depth in a syscall CPtr argument
+pub const CPTR_DEPTH_SHIFT: u32 = 56;
+
+/// Maximum number of address bits a lookup can resolve
+pub const MAX_CPTR_DEPTH: u32 = CPTR_DEPTH_SHIFT;
+
+/// Bits of a CNode capability's guard word holding the guard size
+const GUARD_SIZE_BITS: u32 = 6;
+
+/// Encode a CNode capability guard word from a guard value and size
+///
+/// Returns None if the guard is wider than `MAX_CPTR_DEPTH` bits or the
+/// value does not fit in `bits`.
+pub fn encode_guard(value: u64, bits: u32) -> Option<u64> {
+    if bits > MAX_CPTR_DEPTH || value >> bits != 0 {
+        return None;
+    }
+    Some((value << GUARD_SIZE_BITS) | bits as u64)
+}
+
+/// Guard value and size of a CNode capability
+pub fn cnode_guard(cap: &Capability) -> (u64, u32) {
+    let word = cap.guard();
+    (word >> GUARD_SIZE_BITS, (word & ((1 << GUARD_SIZE_BITS) - 1)) as u32)
+}
+
+/// The `bits` address bits of `cptr` just above bit `shift`
+#[inline]
+fn address_bits(cptr: u64, shift: u32, bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        (cptr >> shift) & (u64::MAX >> (64 - bits))
+    }
+}
+
 /// CNode with CDT support - capability container with revocation
 ///
 /// This is an enhanced CNode that tracks capability derivation for safe revocation.
@@ -152,6 +213,73 @@ impl CNodeCdt {
         self.lookup_node(index).is_none()
     }
 
+    /// Resolve a capability address to a CNode and slot index
+    ///
+    /// Walks the CNode tree from this (root) CNode, consuming `depth` bits
+    /// of `cptr` as described in the module documentation. The slot found
+    /// may be empty, so this also addresses destinations.
+    ///
+    /// # Errors
+    /// - Returns `CapError::InvalidArgument` if `depth` is 0, exceeds
+    ///   `MAX_CPTR_DEPTH`, or does not end exactly at a slot
+    /// - Returns `CapError::InvalidCapability` if a guard does not match
+    /// - Returns `CapError::NotFound` if the path runs through an empty slot
+    /// - Returns `CapError::InvalidOperation` if it runs through a capability
+    ///   that is not a CNode
+    pub fn resolve(&self, cptr: u64, depth: u32) -> Result<(*mut CNodeCdt, usize), CapError> {
+        if depth == 0 || depth > MAX_CPTR_DEPTH {
+            return Err(CapError::InvalidArgument);
+        }
+
+        // The root is not reached through a capability, so it has no guard
+        let mut cnode = self as *const CNodeCdt as *mut CNodeCdt;
+        let (mut guard, mut guard_bits) = (0, 0);
+        let mut remaining = depth;
+
+        loop {
+            // Every level consumes at least MIN_SIZE_BITS, so this terminates
+            // even if a CNode holds a capability to itself
+            let radix = unsafe { (*cnode).size_bits as u32 };
+            if guard_bits + radix > remaining {
+                return Err(CapError::InvalidArgument);
+            }
+
+            remaining -= guard_bits;
+            if address_bits(cptr, remaining, guard_bits) != guard {
+                return Err(CapError::InvalidCapability);
+            }
+
+            remaining -= radix;
+            let index = address_bits(cptr, remaining, radix) as usize;
+            if remaining == 0 {
+                return Ok((cnode, index));
+            }
+
+            let cap = unsafe { (*cnode).lookup(index) }.ok_or(CapError::NotFound)?;
+            if cap.cap_type() != CapType::CNode {
+                return Err(CapError::InvalidOperation);
+            }
+            (guard, guard_bits) = cnode_guard(cap);
+            cnode = cap.object_ptr() as *mut CNodeCdt;
+        }
+    }
+
+    /// Look up a capability by syscall CPtr argument
+    ///
+    /// The top byte of `cptr` is the lookup depth; 0 means the rest is a
+    /// slot index in this CNode. Returns None if the address does not
+    /// resolve or the slot is empty.
+    pub fn lookup_cptr(&self, cptr: u64) -> Option<&Capability> {
+        let depth = (cptr >> CPTR_DEPTH_SHIFT) as u32;
+        if depth == 0 {
+            return self.lookup(cptr as usize);
+        }
+
+        let address = cptr & ((1 << CPTR_DEPTH_SHIFT) - 1);
+        let (cnode, index) = self.resolve(address, depth).ok()?;
+        unsafe { (*cnode).lookup(index) }
+    }
+
     /// Insert a root capability at the specified index
     ///
     /// This creates a new CDT node with no parent (original capability).
@@ -179,13 +307,48 @@ impl CNodeCdt {
             ptr::write(node_ptr, CapNode::new_root(cap));
 
             // Insert into slot
-            ptr::write(self.slots_mut().add(index), Some(node_ptr));
+            self.place(index, node_ptr);
         }
 
-        self.count += 1;
         Ok(())
     }
 
+    /// Put a CDT node into an empty slot
+    ///
+    /// Records the slot in the node (so revocation can find it) and
+    /// retains the capability's object.
+    ///
+    /// # Safety
+    /// `index` must be valid and empty, `node_ptr` a valid node
+    unsafe fn place(&mut self, index: usize, node_ptr: *mut CapNode) {
+        (*node_ptr).cnode = self as *mut CNodeCdt;
+        (*node_ptr).slot = index;
+        ptr::write(self.slots_mut().add(index), Some(node_ptr));
+        self.count += 1;
+
+        retain_object(&(*node_ptr).capability);
+    }
+
+    /// Empty the slot holding a CDT node, release its object and free it
+    ///
+    /// The node must already be out of the derivation tree (or be going
+    /// away with its whole subtree). Works for nodes in any CNode.
+    ///
+    /// # Safety
+    /// `node_ptr` must be a valid node, and its CNode (if any) still valid
+    unsafe fn release_node(node_ptr: *mut CapNode) {
+        let node = &mut *node_ptr;
+        if !node.cnode.is_null() {
+            let cnode = &mut *node.cnode;
+            ptr::write(cnode.slots_mut().add(node.slot), None);
+            cnode.count -= 1;
+        }
+
+        release_object(&node.capability);
+        node.capability = Capability::null();
+        dealloc_cdt_node(node_ptr);
+    }
+
     /// Derive a capability from one slot to another
     ///
     /// Creates a child capability with reduced rights.
@@ -232,26 +395,25 @@ impl CNodeCdt {
 
         // Insert child into destination slot
         unsafe {
-            ptr::write(self.slots_mut().add(dest_index), Some(child_ptr));
+            self.place(dest_index, child_ptr);
         }
 
-        self.count += 1;
         Ok(())
     }
 
     /// Mint a badged capability from one slot to another
     ///
-    /// Creates a child endpoint capability with a badge.
+    /// Creates a child endpoint or notification capability with a badge.
     ///
     /// # Arguments
-    /// * `src_index` - Source slot (must contain an endpoint capability)
+    /// * `src_index` - Source slot (must contain an unbadged endpoint or notification capability)
     /// * `dest_index` - Destination slot (must be empty)
     /// * `badge` - Badge value to attach to the capability
     ///
     /// # Errors
     /// - Returns `CapError::NotFound` if source slot is empty
     /// - Returns `CapError::SlotOccupied` if destination slot is occupied
-    /// - Returns `CapError::InvalidOperation` if source is not an endpoint or indices invalid
+    /// - Returns `CapError::InvalidOperation` if source cannot be badged, badge is zero or indices invalid
     /// - Returns `CapError::OutOfMemory` if CDT allocator is out of memory
     pub fn mint(
         &mut self,
@@ -284,16 +446,17 @@ impl CNodeCdt {
 
         // Insert child into destination slot
         unsafe {
-            ptr::write(self.slots_mut().add(dest_index), Some(child_ptr));
+            self.place(dest_index, child_ptr);
         }
 
-        self.count += 1;
         Ok(())
     }
 
     /// Delete a capability at the specified index (non-recursive)
     ///
-    /// Replaces the slot with None. This does NOT revoke - use `revoke()` for that.
+    /// Empties the slot. This does NOT revoke - use `revoke()` for that:
+    /// capabilities derived from the deleted one stay valid and become
+    /// children of its parent.
     ///
     /// # Errors
     /// - Returns `CapError::InvalidOperation` if index is out of bounds
@@ -307,22 +470,22 @@ impl CNodeCdt {
         let node_ptr = self.lookup_node(index)
             .ok_or(CapError::NotFound)?;
 
-        // Free the CDT node
         unsafe {
-            dealloc_cdt_node(node_ptr);
+            // Take the node out of the tree, keeping its descendants
+            (*node_ptr).unlink();
 
-            // Clear the slot
-            ptr::write(self.slots_mut().add(index), None);
+            // Clear the slot and free the CDT node
+            Self::release_node(node_ptr);
         }
 
-        self.count -= 1;
         Ok(())
     }
 
     /// Revoke a capability and all its descendants (recursive)
     ///
     /// This is the key feature of CDT: recursively delete all derived capabilities
-    /// to ensure no dangling references remain.
+    /// to ensure no dangling references remain. Descendants are removed from
+    /// whichever CNode holds them, including other threads' CSpaces.
     ///
     /// # Arguments
     /// * `index` - Slot containing the capability to revoke
@@ -340,81 +503,77 @@ impl CNodeCdt {
             .ok_or(CapError::NotFound)?;
 
         unsafe {
-            // Recursively revoke all descendants
-            (*node_ptr).revoke_recursive(&mut |ptr| dealloc_cdt_node(ptr));
+            // Sweep all descendants out of their slots
+            (*node_ptr).revoke_descendants(&mut |ptr| Self::release_node(ptr));
 
-            // Free the root node
-            dealloc_cdt_node(node_ptr);
-
-            // Clear the slot
-            ptr::write(self.slots_mut().add(index), None);
+            // Then the revoked capability itself
+            (*node_ptr).unlink();
+            Self::release_node(node_ptr);
         }
 
-        self.count -= 1;
         Ok(())
     }
 
-    /// Move a capability from one slot to another (preserves CDT relationships)
+    /// Copy a capability to another slot
+    ///
+    /// Creates an exact copy of the capability, preserving all rights and badges.
+    /// See `copy_between` for where the copy goes in the CDT.
+    pub fn copy(&mut self, src_index: usize, dest_index: usize) -> Result<(), CapError> {
+        let this = self as *mut CNodeCdt;
+        unsafe { Self::copy_between(this, src_index, this, dest_index) }
+    }
+
+    /// Copy a capability into a slot of another (or the same) CNode
+    ///
+    /// Copying an original (root) capability records the copy as derived
+    /// from it, so revoking the original reaches copies in every CSpace
+    /// (as seL4 does for unbadged capabilities). A copy of a derived
+    /// capability is its sibling, under the same parent.
     ///
     /// # Errors
     /// - Returns `CapError::NotFound` if source slot is empty
     /// - Returns `CapError::SlotOccupied` if destination slot is occupied
     /// - Returns `CapError::InvalidOperation` if indices are out of bounds
-    /// Copy a capability to another slot
     ///
-    /// Creates an exact copy of the capability, preserving all rights and badges.
-    /// The copy shares the same parent in the CDT as the source.
-    pub fn copy(&mut self, src_index: usize, dest_index: usize) -> Result<(), CapError> {
-        if !self.is_valid_index(src_index) || !self.is_valid_index(dest_index) {
+    /// # Safety
+    /// `src` and `dest` must be valid CNodes (they may be the same)
+    pub unsafe fn copy_between(
+        src: *mut CNodeCdt,
+        src_index: usize,
+        dest: *mut CNodeCdt,
+        dest_index: usize,
+    ) -> Result<(), CapError> {
+        if !(*src).is_valid_index(src_index) || !(*dest).is_valid_index(dest_index) {
             return Err(CapError::InvalidOperation);
         }
 
         // Get source node
-        let src_node_ptr = self.lookup_node(src_index)
+        let src_node_ptr = (*src).lookup_node(src_index)
             .ok_or(CapError::NotFound)?;
 
         // Check destination is empty
-        if !self.is_empty(dest_index) {
+        if !(*dest).is_empty(dest_index) {
             return Err(CapError::SlotOccupied);
         }
 
-        unsafe {
-            // Clone the capability from source node
-            let src_node = &*src_node_ptr;
-            let cap_copy = src_node.capability.clone();
-            let parent_ptr = src_node.parent;
-
-            // Allocate a new node for the copy
-            let new_node_ptr = alloc_cdt_node()
-                .expect("CDT allocator out of memory");
-
-            // Create new node with copied capability and same parent
-            let new_node = CapNode {
-                capability: cap_copy,
-                parent: parent_ptr,
-                first_child: None,
-                next_sibling: None,
-            };
-
-            ptr::write(new_node_ptr, new_node);
-
-            // If there's a parent, add this copy as a child (sibling to source)
-            if let Some(parent) = parent_ptr {
-                let parent_ref = &mut *parent;
-                // Insert as first child, pushing existing children down
-                let old_first = parent_ref.first_child;
-                parent_ref.first_child = Some(new_node_ptr);
-                (*new_node_ptr).next_sibling = old_first;
-            }
+        let new_node_ptr = alloc_cdt_node()
+            .ok_or(CapError::InsufficientMemory)?;
+        ptr::write(new_node_ptr, CapNode::new_root((*src_node_ptr).capability));
 
-            // Insert into destination slot
-            ptr::write(self.slots_mut().add(dest_index), Some(new_node_ptr));
-            self.count += 1;
-        }
+        // Link into the tree: under the original, or next to the source
+        let parent_ptr = (*src_node_ptr).parent.unwrap_or(src_node_ptr);
+        (*parent_ptr).adopt(new_node_ptr);
 
+        (*dest).place(dest_index, new_node_ptr);
         Ok(())
     }
 
+    /// Move a capability from one slot to another (preserves CDT relationships)
+    ///
+    /// # Errors
+    /// - Returns `CapError::NotFound` if source slot is empty
+    /// - Returns `CapError::SlotOccupied` if destination slot is occupied
+    /// - Returns `CapError::InvalidOperation` if indices are out of bounds
     pub fn move_cap(&mut self, src_index: usize, dest_index: usize) -> Result<(), CapError> {
         if !self.is_valid_index(src_index) || !self.is_valid_index(dest_index) {
             return Err(CapError::InvalidOperation);
@@ -433,12 +592,66 @@ impl CNodeCdt {
         unsafe {
             ptr::write(self.slots_mut().add(dest_index), Some(node_ptr));
             ptr::write(self.slots_mut().add(src_index), None);
+            (*node_ptr).slot = dest_index;
         }
 
         Ok(())
     }
 }
 
+/// Account for a capability entering a CNode slot
+unsafe fn retain_object(cap: &Capability) {
+    match cap.cap_type() {
+        CapType::Endpoint => (*(cap.object_ptr() as *mut Endpoint)).retain_cap(),
+        CapType::Notification => (*(cap.object_ptr() as *mut Notification)).retain_cap(),
+        CapType::Timer => (*(cap.object_ptr() as *mut Timer)).retain_cap(),
+        #[cfg(feature = "hypervisor")]
+        CapType::Vcpu => (*(cap.object_ptr() as *mut super::Vcpu)).retain_cap(),
+        _ => {}
+    }
+}
+
+/// Account for a capability leaving a CNode slot
+///
+/// When the last capability to an endpoint or notification goes, threads
+/// still queued on it are cancelled: no one holds a capability to wake them.
+/// A notification's bound thread is unbound as well.
+unsafe fn release_object(cap: &Capability) {
+    match cap.cap_type() {
+        CapType::Endpoint => {
+            let endpoint = &mut *(cap.object_ptr() as *mut Endpoint);
+            if endpoint.release_cap() && !endpoint.is_idle() {
+                crate::kprintln!("[cdt] last capability to endpoint {:#x} deleted, cancelling IPC",
+                                 cap.object_ptr());
+                endpoint.cancel_all();
+            }
+        }
+        CapType::Notification => {
+            let notification = &mut *(cap.object_ptr() as *mut Notification);
+            if notification.release_cap() {
+                if notification.has_waiters() {
+                    notification.cancel_all();
+                }
+                notification.unbind();
+            }
+        }
+        CapType::Timer => {
+            let timer = cap.object_ptr() as *mut Timer;
+            if (*timer).release_cap() && (*timer).is_armed() {
+                super::timer::disarm(timer);
+            }
+        }
+        #[cfg(feature = "hypervisor")]
+        CapType::Vcpu => {
+            let vcpu = cap.object_ptr() as *mut super::Vcpu;
+            if (*vcpu).release_cap() {
+                super::vcpu::destroy(vcpu);
+            }
+        }
+        _ => {}
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -539,7 +752,75 @@ mod tests {
 
         assert_eq!(cnode.count(), 0);
         assert!(cnode.is_empty(0));
-        // Note: Slots 1 and 2 are NOT automatically cleared (they're descendants)
-        // In a real implementation, we'd need to track reverse mappings
+        // Descendants' slots are swept too
+        assert!(cnode.is_empty(1));
+        assert!(cnode.is_empty(2));
+    }
+
+    #[test]
+    fn test_guarded_lookup() {
+        unsafe {
+            init_cdt_allocator(CdtAllocatorConfig::with_capacity(
+                PhysAddr::new(0x2000000),
+                1000
+            ));
+        }
+
+        let mut root = unsafe { CNodeCdt::new(4, PhysAddr::new(0x1000000)).unwrap() };
+        let mut leaf = unsafe { CNodeCdt::new(4, PhysAddr::new(0x1001000)).unwrap() };
+        leaf.insert_root(9, Capability::new(CapType::Endpoint, 0x5000)).unwrap();
+
+        // Root slot 2 -> leaf, guarded by the 4 bits 0b1010
+        let leaf_cap = Capability::with_guard(CapType::CNode, &mut leaf as *mut CNodeCdt as usize,
+                                              encode_guard(0b1010, 4).unwrap());
+        root.insert_root(2, leaf_cap).unwrap();
+        assert_eq!(cnode_guard(root.lookup(2).unwrap()), (0b1010, 4));
+
+        // 4 root bits, 4 guard bits, 4 leaf bits
+        let cptr = (0x2 << 8) | (0b1010 << 4) | 9;
+        let (cnode, index) = root.resolve(cptr, 12).unwrap();
+        assert_eq!((cnode as usize, index), (&leaf as *const CNodeCdt as usize, 9));
+        assert_eq!(root.lookup_cptr((12 << CPTR_DEPTH_SHIFT) | cptr).unwrap().object_ptr(), 0x5000);
+
+        // Depth 4 stops at the CNode capability itself; flat lookups still work
+        assert_eq!(root.lookup_cptr((4 << CPTR_DEPTH_SHIFT) | 2).unwrap().cap_type(), CapType::CNode);
+        assert_eq!(root.lookup_cptr(2).unwrap().cap_type(), CapType::CNode);
+
+        // Wrong guard, depth ending mid-level, path through an empty slot
+        assert_eq!(root.resolve((0x2 << 8) | (0b1011 << 4) | 9, 12), Err(CapError::InvalidCapability));
+        assert_eq!(root.resolve(cptr >> 2, 10), Err(CapError::InvalidArgument));
+        assert_eq!(root.resolve((0x3 << 8) | (0b1010 << 4) | 9, 12), Err(CapError::NotFound));
+        assert!(encode_guard(0b100, 2).is_none());
+    }
+
+    #[test]
+    fn test_revoke_across_cnodes() {
+        unsafe {
+            init_cdt_allocator(CdtAllocatorConfig::with_capacity(
+                PhysAddr::new(0x2000000),
+                1000
+            ));
+        }
+
+        let mut parent = unsafe { CNodeCdt::new(4, PhysAddr::new(0x1000000)).unwrap() };
+        let mut child = unsafe { CNodeCdt::new(4, PhysAddr::new(0x1001000)).unwrap() };
+
+        // Original in `parent`, a copy and a derived cap of the copy in `child`
+        parent.insert_root(0, Capability::new(CapType::Tcb, 0x5000)).unwrap();
+        unsafe {
+            CNodeCdt::copy_between(&mut parent, 0, &mut child, 3).unwrap();
+        }
+        child.derive(3, 4, CapRights::READ).unwrap();
+        assert_eq!(child.count(), 2);
+
+        // Deleting the copy keeps the derived cap, now under the original
+        child.delete(3).unwrap();
+        assert!(!child.is_empty(4));
+
+        // Revoking the original sweeps the other CNode
+        parent.revoke(0).unwrap();
+        assert!(child.is_empty(4));
+        assert_eq!(child.count(), 0);
+        assert_eq!(parent.count(), 0);
     }
 }
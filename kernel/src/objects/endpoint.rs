@@ -49,6 +49,13 @@ pub struct Endpoint {
     /// badges, the receiver can distinguish which capability was used
     /// to send the message.
     badge: u64,
+
+    /// Number of capabilities referring to this endpoint
+    ///
+    /// Maintained by `CNodeCdt` as capabilities enter and leave CNode
+    /// slots. When the last one goes, queued threads are cancelled since
+    /// nobody can reach them any more.
+    cap_refs: usize,
 }
 
 impl Endpoint {
@@ -58,6 +65,7 @@ impl Endpoint {
             send_queue: ThreadQueue::new(),
             recv_queue: ThreadQueue::new(),
             badge: 0,
+            cap_refs: 0,
         }
     }
 
@@ -67,6 +75,7 @@ impl Endpoint {
             send_queue: ThreadQueue::new(),
             recv_queue: ThreadQueue::new(),
             badge,
+            cap_refs: 0,
         }
     }
 
@@ -171,6 +180,14 @@ impl Endpoint {
         self.recv_queue.dequeue()
     }
 
+    /// Get the first thread in the receive queue without dequeuing it
+    ///
+    /// Used by the IPC fastpath to check the receiver before committing.
+    #[inline]
+    pub fn first_receiver(&self) -> Option<*mut TCB> {
+        self.recv_queue.peek()
+    }
+
     /// Dequeue a specific thread from the send queue
     ///
     /// Used for cancellation or timeout scenarios.
@@ -193,21 +210,40 @@ impl Endpoint {
 
     /// Cancel all waiting threads
     ///
-    /// Removes all threads from both queues and marks them as runnable.
-    /// Used when an endpoint is destroyed or reset.
+    /// Removes all threads from both queues and makes them runnable, with
+    /// their pending syscall failing (x0 = u64::MAX). Used when an endpoint
+    /// is destroyed or reset.
     ///
     /// # Safety
     /// - All TCB pointers in the queues must be valid
+    /// - Scheduler must be initialized
     pub unsafe fn cancel_all(&mut self) {
-        // Cancel all senders
-        while let Some(tcb) = self.send_queue.dequeue() {
+        while let Some(tcb) = self.send_queue.dequeue().or_else(|| self.recv_queue.dequeue()) {
+            (*tcb).context_mut().x0 = u64::MAX;
             (*tcb).unblock();
+            crate::scheduler::enqueue(tcb);
         }
+    }
 
-        // Cancel all receivers
-        while let Some(tcb) = self.recv_queue.dequeue() {
-            (*tcb).unblock();
-        }
+    /// Record a new capability referring to this endpoint
+    #[inline]
+    pub fn retain_cap(&mut self) {
+        self.cap_refs += 1;
+    }
+
+    /// Drop a capability referring to this endpoint
+    ///
+    /// Returns true if it was the last one.
+    #[inline]
+    pub fn release_cap(&mut self) -> bool {
+        self.cap_refs = self.cap_refs.saturating_sub(1);
+        self.cap_refs == 0
+    }
+
+    /// Number of capabilities referring to this endpoint
+    #[inline]
+    pub fn cap_refs(&self) -> usize {
+        self.cap_refs
     }
 
     /// Check if the endpoint is idle (no queued threads)
@@ -227,6 +263,7 @@ impl core::fmt::Debug for Endpoint {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Endpoint")
             .field("badge", &self.badge)
+            .field("cap_refs", &self.cap_refs)
             .field("send_queue_len", &self.send_queue.len())
             .field("recv_queue_len", &self.recv_queue.len())
             .finish()
@@ -314,7 +351,6 @@ impl ThreadQueue {
     }
 
     /// Peek at the front thread without removing it
-    #[allow(dead_code)]
     fn peek(&self) -> Option<*mut TCB> {
         if self.count > 0 {
             Some(self.threads[0])
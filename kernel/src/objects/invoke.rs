@@ -144,6 +144,8 @@ pub unsafe fn invoke_capability(
         CapType::IrqHandler => invoke_irq_handler(cap, args),
         CapType::IrqControl => invoke_irq_control(cap, args),
         CapType::Reply => Err(InvocationError::InvalidCapability), // Reply caps are used directly by IPC, not invoked
+        CapType::Timer => Err(InvocationError::InvalidCapability), // Timers are set with SYS_TIMER_SET
+        CapType::Vcpu => Err(InvocationError::InvalidCapability), // VCPUs have their own syscalls
     }
 }
 
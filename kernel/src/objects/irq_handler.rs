@@ -31,6 +31,28 @@
 //!    - Driver services the device
 //!    - Driver calls `IRQHandler_Ack(irq_handler)` to re-enable IRQ
 //!
+//! ## Message-Signaled Interrupts
+//!
+//! PCIe devices use MSI/MSI-X instead of interrupt lines. A driver asks
+//! for a vector with `IRQControl_GetMSI(irq_control, device_id, notif, slot)`;
+//! the kernel allocates an LPI through the GIC ITS (`gic::msi`), creates an
+//! IRQHandler for it and returns the address/data message the driver
+//! programs into the device. MSIs are edge-triggered, so the kernel
+//! completes them as soon as the notification is signaled and
+//! `IRQHandler_Ack` is a no-op for them. The notification badge is
+//! `1 << (event % 64)`, so several vectors of one device can share a
+//! notification.
+//!
+//! ## Shared IRQs
+//!
+//! Several drivers can subscribe to one wired line (legacy PCI INTx, or a
+//! monitor watching a driver's interrupt) with `IRQControl_GetShared`, each
+//! with its own notification and badge. Every subscriber is signaled when
+//! the line fires, and the line stays masked until all of them have
+//! acknowledged. Sharing is opt-in on both sides: an exclusive handler
+//! refuses subscribers, and a line with subscribers refuses an exclusive
+//! handler. `IRQHandler_Clear` unsubscribes one handler.
+//!
 //! ## Differences from seL4
 //!
 //! seL4 requires explicit IRQ acknowledgment from userspace before the IRQ
@@ -40,6 +62,7 @@
 //! - Provides backpressure if driver is slow
 
 use crate::arch::aarch64::gic;
+use crate::arch::aarch64::gic::msi;
 use crate::objects::Notification;
 
 /// IRQ Handler - capability for receiving hardware interrupts
@@ -59,7 +82,8 @@ use crate::objects::Notification;
 ///
 /// ## Safety
 ///
-/// - Only one IRQHandler per IRQ number (enforced by IRQ_HANDLERS table)
+/// - Only one IRQHandler per IRQ number (enforced by IRQ_HANDLERS table),
+///   unless every handler on the line is shared
 /// - IRQ is masked until driver acknowledges
 /// - Prevents IRQ storms and race conditions
 #[repr(C)]
@@ -82,6 +106,18 @@ pub struct IRQHandler {
     /// IRQs start disabled and must be explicitly enabled by the first Ack.
     /// After each interrupt, the IRQ is masked until the next Ack.
     enabled: bool,
+
+    /// Bits signaled on the notification when the IRQ fires
+    badge: u64,
+
+    /// Whether this handler shares its line with others
+    shared: bool,
+
+    /// Signaled and not yet acknowledged
+    pending: bool,
+
+    /// Next handler on a shared line
+    next: *mut IRQHandler,
 }
 
 impl IRQHandler {
@@ -99,9 +135,56 @@ impl IRQHandler {
             irq_num,
             notification,
             enabled: false,
+            badge: 1 << (irq_num % 64),
+            shared: false,
+            pending: false,
+            next: core::ptr::null_mut(),
+        }
+    }
+
+    /// Create a handler for a wired IRQ shared with other handlers
+    ///
+    /// # Arguments
+    /// * `irq_num` - Hardware IRQ number (from GIC)
+    /// * `notification` - Notification to signal on IRQ
+    /// * `badge` - Bits to signal on it (non-zero)
+    ///
+    /// # Safety
+    /// - `notification` must be a valid pointer to a Notification object
+    pub unsafe fn new_shared(irq_num: u32, notification: *mut Notification, badge: u64) -> Self {
+        Self {
+            shared: true,
+            badge,
+            ..Self::new(irq_num, notification)
+        }
+    }
+
+    /// Create a handler for an allocated MSI vector
+    ///
+    /// # Arguments
+    /// * `irq_num` - INTID returned by `gic::msi::alloc`
+    /// * `event` - The vector's EventID (selects the badge bit)
+    /// * `notification` - Notification to signal on IRQ
+    ///
+    /// # Safety
+    /// Same as `new`
+    pub unsafe fn new_msi(irq_num: u32, event: u32, notification: *mut Notification) -> Self {
+        Self {
+            irq_num,
+            notification,
+            enabled: true,
+            badge: 1 << (event % 64),
+            shared: false,
+            pending: false,
+            next: core::ptr::null_mut(),
         }
     }
 
+    /// Whether this handler is for an MSI vector
+    pub fn is_msi(&self) -> bool {
+        msi::is_msi(self.irq_num)
+    }
+
     /// Get the IRQ number
     pub fn irq_num(&self) -> u32 {
         self.irq_num
@@ -117,6 +200,11 @@ impl IRQHandler {
         self.enabled
     }
 
+    /// Whether the handler is still bound to its IRQ (not cleared)
+    pub fn is_bound(&self) -> bool {
+        !self.notification.is_null()
+    }
+
     /// Acknowledge IRQ and re-enable it
     ///
     /// This is called by the userspace driver after it has serviced the interrupt.
@@ -125,6 +213,17 @@ impl IRQHandler {
     /// # Safety
     /// Must be called from the owning driver thread
     pub unsafe fn ack(&mut self) {
+        // MSIs were completed when they fired and are never masked
+        if self.is_msi() {
+            return;
+        }
+
+        // A shared line stays masked until every handler it woke is done
+        self.pending = false;
+        if line_pending(self.irq_num) {
+            return;
+        }
+
         // Signal End Of Interrupt to the GIC
         // This is deferred from the IRQ handler to ensure the device has cleared
         // its interrupt line before we tell the GIC the interrupt is complete.
@@ -140,9 +239,10 @@ impl IRQHandler {
     ///
     /// # Safety
     /// Must be called from IRQ context with valid notification pointer
-    pub unsafe fn signal_irq(&self) {
+    pub unsafe fn signal_irq(&mut self) {
         if !self.notification.is_null() {
-            (*self.notification).signal(1 << self.irq_num);
+            self.pending = true;
+            (*self.notification).signal(self.badge);
         }
     }
 }
@@ -178,13 +278,27 @@ impl IRQControl {
 
 /// Global IRQ handler table
 ///
-/// Maps IRQ numbers to IRQHandler objects. Only one handler per IRQ is allowed.
+/// Maps IRQ numbers to IRQHandler objects: one handler per IRQ, or the
+/// first of a list (through `IRQHandler::next`) on a shared line.
+/// Entries 0..MAX_IRQS are wired IRQs; the MSI range follows them.
 ///
 /// # Safety
 /// - Protected by kernel context (single-threaded kernel)
 /// - Only accessed from syscall context
 /// - Prevents multiple drivers from claiming the same IRQ
-static mut IRQ_HANDLERS: [Option<*mut IRQHandler>; gic::MAX_IRQS] = [None; gic::MAX_IRQS];
+static mut IRQ_HANDLERS: [Option<*mut IRQHandler>; gic::MAX_IRQS + msi::MAX_MSIS] =
+    [None; gic::MAX_IRQS + msi::MAX_MSIS];
+
+/// Index of an IRQ in `IRQ_HANDLERS`
+fn handler_index(irq_num: u32) -> Option<usize> {
+    if irq_num < gic::MAX_IRQS as u32 {
+        Some(irq_num as usize)
+    } else if msi::is_msi(irq_num) {
+        Some(gic::MAX_IRQS + (irq_num - msi::MSI_BASE) as usize)
+    } else {
+        None
+    }
+}
 
 /// Register an IRQ handler
 ///
@@ -194,27 +308,98 @@ static mut IRQ_HANDLERS: [Option<*mut IRQHandler>; gic::MAX_IRQS] = [None; gic::
 ///
 /// # Returns
 /// - `Ok(())` if registration succeeded
-/// - `Err(())` if IRQ is already registered
+/// - `Err(())` if IRQ is already registered (and not shared by both the
+///   existing handlers and this one)
 ///
 /// # Safety
 /// - Must be called from syscall context
 /// - `handler` must be a valid IRQHandler pointer
-/// - IRQ number must be valid (<MAX_IRQS)
+/// - IRQ number must be valid (<MAX_IRQS, or an allocated MSI)
 pub unsafe fn register_irq_handler(irq_num: u32, handler: *mut IRQHandler) -> Result<(), ()> {
-    if irq_num >= gic::MAX_IRQS as u32 {
+    let Some(index) = handler_index(irq_num) else {
         return Err(());
-    }
+    };
 
-    let slot = &mut IRQ_HANDLERS[irq_num as usize];
-    if slot.is_some() {
+    let slot = &mut IRQ_HANDLERS[index];
+    match *slot {
+        None => *slot = Some(handler),
+        Some(head) if (*head).shared && (*handler).shared => {
+            (*handler).next = head;
+            *slot = Some(handler);
+        }
         // IRQ already claimed
-        return Err(());
+        Some(_) => return Err(()),
     }
-
-    *slot = Some(handler);
     Ok(())
 }
 
+/// Remove one handler from its IRQ
+///
+/// The handler stays allocated (its capability still names it) but is
+/// no longer signaled, and acknowledging through it fails. If it was the
+/// last handler the IRQ is disabled; if it was the last one the line was
+/// waiting for, the line is completed and unmasked.
+///
+/// # Safety
+/// - Must be called from syscall context
+/// - `handler` must be a valid IRQHandler pointer
+pub unsafe fn unlink_irq_handler(handler: *mut IRQHandler) {
+    let irq_num = (*handler).irq_num;
+    let Some(index) = handler_index(irq_num) else {
+        return;
+    };
+    let Some(head) = IRQ_HANDLERS[index] else {
+        return;
+    };
+
+    if head == handler {
+        let next = (*handler).next;
+        IRQ_HANDLERS[index] = (!next.is_null()).then_some(next);
+    } else {
+        let mut prev = head;
+        while !(*prev).next.is_null() && (*prev).next != handler {
+            prev = (*prev).next;
+        }
+        if (*prev).next != handler {
+            return;
+        }
+        (*prev).next = (*handler).next;
+    }
+
+    let was_pending = (*handler).pending;
+    (*handler).next = core::ptr::null_mut();
+    (*handler).notification = core::ptr::null_mut();
+    (*handler).pending = false;
+
+    if IRQ_HANDLERS[index].is_none() {
+        unregister_irq_handler(irq_num);
+        // MSIs were completed when they fired
+        if was_pending && !msi::is_msi(irq_num) {
+            gic::end_of_interrupt(irq_num);
+        }
+    } else if was_pending && !line_pending(irq_num) {
+        gic::end_of_interrupt(irq_num);
+        gic::enable_irq(irq_num);
+    }
+}
+
+/// Whether any handler on an IRQ's line is still to acknowledge it
+///
+/// # Safety
+/// Must be called from kernel context
+unsafe fn line_pending(irq_num: u32) -> bool {
+    let mut handler = handler_index(irq_num)
+        .and_then(|index| IRQ_HANDLERS[index])
+        .unwrap_or(core::ptr::null_mut());
+    while !handler.is_null() {
+        if (*handler).pending {
+            return true;
+        }
+        handler = (*handler).next;
+    }
+    false
+}
+
 /// Unregister an IRQ handler
 ///
 /// # Arguments
@@ -224,16 +409,20 @@ pub unsafe fn register_irq_handler(irq_num: u32, handler: *mut IRQHandler) -> Re
 /// - Must be called from syscall context
 /// - Caller must own the IRQHandler for this IRQ
 pub unsafe fn unregister_irq_handler(irq_num: u32) {
-    if irq_num < gic::MAX_IRQS as u32 {
-        IRQ_HANDLERS[irq_num as usize] = None;
-        gic::disable_irq(irq_num);
+    if let Some(index) = handler_index(irq_num) {
+        IRQ_HANDLERS[index] = None;
+        if msi::is_msi(irq_num) {
+            msi::free(irq_num);
+        } else {
+            gic::disable_irq(irq_num);
+        }
     }
 }
 
 /// Handle an IRQ from the GIC
 ///
 /// This is called by the kernel IRQ exception handler when an interrupt fires.
-/// It looks up the registered handler and signals its notification.
+/// It looks up the registered handlers and signals their notifications.
 ///
 /// # Arguments
 /// * `irq_num` - IRQ number from GIC IAR
@@ -242,16 +431,16 @@ pub unsafe fn unregister_irq_handler(irq_num: u32) {
 /// - Must be called from IRQ exception context
 /// - GIC interrupt must already be acknowledged (IAR read)
 pub unsafe fn handle_irq(irq_num: u32) {
-    if irq_num >= gic::MAX_IRQS as u32 {
+    let Some(index) = handler_index(irq_num) else {
         crate::kprintln!("[IRQ] Invalid IRQ number: {}", irq_num);
         return;
-    }
+    };
 
-    if let Some(handler_ptr) = IRQ_HANDLERS[irq_num as usize] {
-        if !handler_ptr.is_null() {
-            let handler = &*handler_ptr;
-            handler.signal_irq();
-        }
+    let mut handler_ptr = IRQ_HANDLERS[index].unwrap_or(core::ptr::null_mut());
+    while !handler_ptr.is_null() {
+        let handler = &mut *handler_ptr;
+        handler.signal_irq();
+        handler_ptr = handler.next;
     }
     // If no handler registered, just ignore (already ACKed at GIC)
 }
@@ -263,9 +452,5 @@ pub unsafe fn handle_irq(irq_num: u32) {
 /// # Safety
 /// Must be called from syscall context
 pub unsafe fn get_irq_handler(irq_num: u32) -> Option<*mut IRQHandler> {
-    if irq_num < gic::MAX_IRQS as u32 {
-        IRQ_HANDLERS[irq_num as usize]
-    } else {
-        None
-    }
+    handler_index(irq_num).and_then(|index| IRQ_HANDLERS[index])
 }
@@ -14,6 +14,8 @@
 //! - **VSpace**: Virtual address space root
 //! - **Page**: Physical memory page
 //! - **IRQ Handler/Control**: Interrupt handling
+//! - **Timer**: Timeout notifications
+//! - **VCPU**: Virtual CPUs for guests (`hypervisor` feature)
 //!
 //! ## Capability-Based Security
 //!
@@ -34,6 +36,9 @@ pub mod tcb;
 pub mod untyped;
 pub mod invoke;
 pub mod irq_handler;  // IRQ handling capabilities
+pub mod timer;  // Timeout notifications
+#[cfg(feature = "hypervisor")]
+pub mod vcpu;  // Virtual CPUs (EL2 guests)
 pub mod test_runner;
 
 #[cfg(test)]
@@ -45,7 +50,10 @@ pub use cdt::CapNode;
 pub use cnode::CNode;
 pub use endpoint::Endpoint;
 pub use notification::Notification;
-pub use tcb::{TCB, ThreadState};
+pub use tcb::{Donation, TCB, ThreadState};
 pub use untyped::{UntypedMemory, ObjectType};
 pub use invoke::{invoke_capability, InvocationArgs, InvocationError, InvocationResult};
 pub use irq_handler::{IRQHandler, IRQControl};
+pub use timer::Timer;
+#[cfg(feature = "hypervisor")]
+pub use vcpu::Vcpu;
@@ -14,6 +14,7 @@
 //! - **Signal**: Set notification bits (non-blocking)
 //! - **Wait**: Block until notification bits are set, then clear and return them
 //! - **Poll**: Check notification bits without blocking
+//! - **Bind**: Tie it to a thread, whose endpoint receives signals then end
 //!
 //! ## Use Cases
 //!
@@ -71,6 +72,20 @@ impl ThreadQueue {
             Some(tcb)
         }
     }
+
+    fn remove(&mut self, tcb: *mut TCB) -> bool {
+        for i in 0..self.count {
+            if self.threads[i] == tcb {
+                for j in i..self.count - 1 {
+                    self.threads[j] = self.threads[j + 1];
+                }
+                self.threads[self.count - 1] = core::ptr::null_mut();
+                self.count -= 1;
+                return true;
+            }
+        }
+        false
+    }
 }
 
 /// Notification object for lightweight signaling
@@ -86,6 +101,15 @@ pub struct Notification {
     /// Queue of threads waiting on this notification
     /// When signaled, all waiting threads are woken
     wait_queue: ThreadQueue,
+
+    /// Number of capabilities referring to this notification
+    /// Maintained by `CNodeCdt`; waiters are cancelled when it drops to 0
+    cap_refs: usize,
+
+    /// Thread bound to this notification (null if none)
+    ///
+    /// A signal with no waiter ends the receive it is blocked in.
+    bound_tcb: *mut TCB,
 }
 
 impl Notification {
@@ -94,13 +118,16 @@ impl Notification {
         Self {
             signal_word: AtomicU64::new(0),
             wait_queue: ThreadQueue::new(),
+            cap_refs: 0,
+            bound_tcb: core::ptr::null_mut(),
         }
     }
 
     /// Signal the notification with a bitmask
     ///
     /// Sets the specified bits in the notification word. If any threads are
-    /// waiting, they are woken up with the signal bits.
+    /// waiting, they are woken up with the signal bits; if none is, a bound
+    /// thread blocked receiving on an endpoint is.
     ///
     /// # Arguments
     ///
@@ -129,7 +156,69 @@ impl Notification {
                 thread.set_state(crate::objects::ThreadState::Runnable);
                 crate::scheduler::enqueue(tcb);
             }
+        } else if !self.bound_tcb.is_null() {
+            self.wake_bound();
+        }
+    }
+
+    /// End the receive the bound thread is blocked in, if it is, handing it
+    /// the signal bits
+    ///
+    /// The receive returns no message (0 bytes) with the bits as its badge.
+    ///
+    /// # Safety
+    ///
+    /// Must be called with interrupts disabled, with a bound thread
+    unsafe fn wake_bound(&mut self) {
+        let tcb = self.bound_tcb;
+        let thread = &mut *tcb;
+        let crate::objects::ThreadState::BlockedOnReceive { endpoint } = thread.state() else {
+            return;
+        };
+        (*(endpoint as *mut crate::objects::Endpoint)).dequeue_specific_receiver(tcb);
+
+        let signals = self.signal_word.swap(0, Ordering::Acquire);
+        thread.context_mut().x0 = 0;
+        thread.context_mut().x1 = signals;
+        // No capability came along (SYS_RECV_CAP)
+        if thread.take_cap_receive_slot().is_some() {
+            thread.context_mut().x2 = 0;
         }
+
+        thread.set_state(crate::objects::ThreadState::Runnable);
+        crate::scheduler::enqueue(tcb);
+    }
+
+    /// Bind the notification to `tcb`, replacing the bindings either had
+    ///
+    /// # Safety
+    ///
+    /// `tcb` must be a valid TCB, which unbinds before it is freed
+    pub unsafe fn bind(&mut self, tcb: *mut TCB) {
+        self.unbind();
+        let previous = (*tcb).bound_notification();
+        if !previous.is_null() {
+            (*previous).unbind();
+        }
+        self.bound_tcb = tcb;
+        (*tcb).set_bound_notification(self);
+    }
+
+    /// Undo the binding to a thread, if there is one
+    ///
+    /// # Safety
+    ///
+    /// The bound thread, if any, must still be valid
+    pub unsafe fn unbind(&mut self) {
+        if !self.bound_tcb.is_null() {
+            (*self.bound_tcb).set_bound_notification(core::ptr::null_mut());
+            self.bound_tcb = core::ptr::null_mut();
+        }
+    }
+
+    /// Thread bound to this notification (null if none)
+    pub fn bound_tcb(&self) -> *mut TCB {
+        self.bound_tcb
     }
 
     /// Wait for notification signals (blocking)
@@ -196,6 +285,14 @@ impl Notification {
         self.wait_queue.len()
     }
 
+    /// Remove a specific thread from the wait queue
+    ///
+    /// Used when a waiting thread is destroyed. Returns true if the thread
+    /// was waiting.
+    pub fn remove_waiter(&mut self, tcb: *mut TCB) -> bool {
+        self.wait_queue.remove(tcb)
+    }
+
     /// Cancel all waiting threads
     ///
     /// Wakes all threads with signal bits set to 0, indicating cancellation.
@@ -218,6 +315,21 @@ impl Notification {
         // Clear any pending signals
         self.signal_word.store(0, Ordering::Release);
     }
+
+    /// Record a new capability referring to this notification
+    #[inline]
+    pub fn retain_cap(&mut self) {
+        self.cap_refs += 1;
+    }
+
+    /// Drop a capability referring to this notification
+    ///
+    /// Returns true if it was the last one.
+    #[inline]
+    pub fn release_cap(&mut self) -> bool {
+        self.cap_refs = self.cap_refs.saturating_sub(1);
+        self.cap_refs == 0
+    }
 }
 
 impl core::fmt::Debug for Notification {
@@ -28,8 +28,10 @@
 //! ```
 
 use crate::arch::aarch64::context::TrapFrame;
+use crate::arch::aarch64::fpu::FpState;
+use crate::scheduler::stats::CpuUsage;
 use crate::memory::VirtAddr;
-use super::CNode;
+use super::{CNode, Endpoint, Notification};
 
 /// Thread Control Block - represents a thread of execution
 ///
@@ -75,6 +77,9 @@ pub struct TCB {
     /// Time slice remaining (in ticks)
     time_slice: u32,
 
+    /// Scheduling domain (see `scheduler::domain`)
+    domain: u8,
+
     /// Thread ID (for debugging)
     tid: usize,
 
@@ -85,7 +90,9 @@ pub struct TCB {
     /// - Bit 1: CAP_PROCESS (process_create, process_delete)
     /// - Bit 2: CAP_IPC (notification, endpoint operations)
     /// - Bit 3: CAP_CAPS (capability operations)
-    /// - Bit 4-63: Reserved for future capabilities
+    /// - Bit 4: CAP_DOMAIN (scheduling domain assignment)
+    /// - Bit 5: CAP_KLOG (reading the kernel log)
+    /// - Bit 6-63: Reserved for future capabilities
     ///
     /// Root-task gets all capabilities (0xFFFFFFFFFFFFFFFF)
     capabilities: u64,
@@ -101,6 +108,83 @@ pub struct TCB {
     /// Used by cap_allocate syscall to allocate capability slots.
     /// Slots 0-99 are reserved for well-known capabilities, starts at 100.
     next_cap_slot: u64,
+
+    /// Fault handler endpoint (null if none registered)
+    ///
+    /// Page faults taken by this thread are sent as a fault message to this
+    /// endpoint instead of killing the system, so a userspace pager can map
+    /// the missing page and resume the thread.
+    fault_endpoint: *mut Endpoint,
+
+    /// Main thread of the process this thread is a worker of (null for a
+    /// process's main thread)
+    ///
+    /// Workers are created with `SYS_THREAD_CREATE` and share their main
+    /// thread's CSpace and VSpace, and with them its allocators.
+    process: *mut TCB,
+
+    /// Next thread in the process's list of workers (null at the end)
+    ///
+    /// The list starts at the main thread's `next_thread`.
+    next_thread: *mut TCB,
+
+    /// Notification bound to this thread (null if none)
+    ///
+    /// A signal on it ends a receive the thread is blocked in, so that it
+    /// can wait for messages and signals at once (see
+    /// `SYS_TCB_BIND_NOTIFICATION`).
+    bound_notification: *mut Notification,
+
+    /// Caller waiting for this thread's reply (null if none)
+    ///
+    /// Set when this thread receives a message sent with `SYS_CALL`; the
+    /// next `SYS_REPLY` or `SYS_REPLY_RECV` answers it. This is the implicit
+    /// one-shot reply capability.
+    reply_to: *mut TCB,
+
+    /// Server whose `reply_to` is this thread (null if none)
+    ///
+    /// The way back from a caller to the server that owes it a reply, so
+    /// that destroying the caller clears the server's `reply_to` before the
+    /// caller's frame is freed. Kept in step by `set_reply_to`.
+    replying_server: *mut TCB,
+
+    /// Badge of the endpoint capability used by a pending send or call
+    ///
+    /// Recorded while the thread waits in an endpoint's send queue and
+    /// handed to the receiver together with the message.
+    ipc_badge: u64,
+
+    /// Capability slot sent along with a pending send (`SYS_SEND_CAP`)
+    ipc_cap: Option<usize>,
+
+    /// Slot a capability may be received into with the next message
+    /// (`SYS_RECV_CAP`)
+    ///
+    /// Taken when a message is delivered, so it only holds for that one.
+    cap_receive_slot: Option<usize>,
+
+    /// Priority lent to this thread by a waiter (see
+    /// `scheduler::donate_priority`)
+    donation: Option<Donation>,
+
+    /// CPU time consumed so far (see `scheduler::stats`)
+    cpu_usage: CpuUsage,
+
+    /// Saved FP/SIMD registers
+    ///
+    /// Only up to date while the thread's FP state is not live in the CPU
+    /// (see `arch::aarch64::fpu`).
+    fp_state: FpState,
+}
+
+/// Priority a thread holds on loan from a thread waiting for its signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Donation {
+    /// The thread's own priority, restored when the loan ends
+    pub base_priority: u8,
+    /// Address of the notification whose signal, by this thread, ends it
+    pub notification: usize,
 }
 
 /// Thread state - lifecycle states of a thread
@@ -135,6 +219,12 @@ pub enum ThreadState {
         /// Notification object address
         notification: usize,
     },
+
+    /// Thread took a page fault and is waiting for its pager to resume it
+    BlockedOnFault {
+        /// Fault handler endpoint address
+        endpoint: usize,
+    },
 }
 
 impl TCB {
@@ -144,6 +234,10 @@ impl TCB {
     /// Default time slice (in ticks)
     pub const DEFAULT_TIME_SLICE: u32 = 10;
 
+    /// First slot [`alloc_cap_slot`](Self::alloc_cap_slot) hands out; the
+    /// ones below are reserved for well-known capabilities
+    pub const FIRST_ALLOCATED_CAP_SLOT: u64 = 100;
+
     // Capability bit definitions
     /// Memory management capabilities (allocate, map, unmap)
     pub const CAP_MEMORY: u64 = 1 << 0;
@@ -157,6 +251,12 @@ impl TCB {
     /// Capability management (allocate, insert, delete caps)
     pub const CAP_CAPS: u64 = 1 << 3;
 
+    /// Scheduling domain control (move threads between domains)
+    pub const CAP_DOMAIN: u64 = 1 << 4;
+
+    /// Kernel log access (klog_read)
+    pub const CAP_KLOG: u64 = 1 << 5;
+
     /// All capabilities (for privileged processes like root-task)
     pub const CAP_ALL: u64 = 0xFFFFFFFFFFFFFFFF;
 
@@ -220,10 +320,23 @@ impl TCB {
             state: ThreadState::Inactive,
             priority: Self::DEFAULT_PRIORITY,
             time_slice: Self::DEFAULT_TIME_SLICE,
+            domain: 0,
             tid,
             capabilities,
             next_virt_addr: crate::generated::memory_config::USER_VIRT_START,
-            next_cap_slot: 100, // Slots 0-99 reserved for well-known capabilities
+            next_cap_slot: Self::FIRST_ALLOCATED_CAP_SLOT,
+            fault_endpoint: core::ptr::null_mut(),
+            process: core::ptr::null_mut(),
+            next_thread: core::ptr::null_mut(),
+            bound_notification: core::ptr::null_mut(),
+            reply_to: core::ptr::null_mut(),
+            replying_server: core::ptr::null_mut(),
+            ipc_badge: 0,
+            ipc_cap: None,
+            cap_receive_slot: None,
+            donation: None,
+            cpu_usage: CpuUsage::default(),
+            fp_state: FpState::new(),
         }
     }
 
@@ -272,6 +385,35 @@ impl TCB {
         self.priority = priority;
     }
 
+    /// Get the priority donation in effect, if any
+    #[inline]
+    pub fn donation(&self) -> Option<Donation> {
+        self.donation
+    }
+
+    /// Set or clear the priority donation
+    ///
+    /// Use `scheduler::donate_priority` and `scheduler::end_donation`,
+    /// which also change the priority.
+    #[inline]
+    pub fn set_donation(&mut self, donation: Option<Donation>) {
+        self.donation = donation;
+    }
+
+    /// Get the scheduling domain
+    #[inline]
+    pub fn domain(&self) -> u8 {
+        self.domain
+    }
+
+    /// Set the scheduling domain
+    ///
+    /// Use `scheduler::set_domain` for threads that may be queued.
+    #[inline]
+    pub fn set_domain(&mut self, domain: u8) {
+        self.domain = domain;
+    }
+
     /// Get the time slice remaining
     #[inline]
     pub fn time_slice(&self) -> u32 {
@@ -331,6 +473,187 @@ impl TCB {
         self.ipc_buffer
     }
 
+    /// Get the fault handler endpoint (null if none registered)
+    #[inline]
+    pub fn fault_endpoint(&self) -> *mut Endpoint {
+        self.fault_endpoint
+    }
+
+    /// Set the fault handler endpoint (null to unregister)
+    #[inline]
+    pub fn set_fault_endpoint(&mut self, endpoint: *mut Endpoint) {
+        self.fault_endpoint = endpoint;
+    }
+
+    /// Get the notification bound to this thread (null if none)
+    #[inline]
+    pub fn bound_notification(&self) -> *mut Notification {
+        self.bound_notification
+    }
+
+    /// Set the notification bound to this thread (null to unbind)
+    ///
+    /// Only `Notification::bind` and `Notification::unbind` call this, which
+    /// keep both sides of the binding in step.
+    #[inline]
+    pub fn set_bound_notification(&mut self, notification: *mut Notification) {
+        self.bound_notification = notification;
+    }
+
+    /// Get the main thread of this thread's process (itself for a main
+    /// thread)
+    #[inline]
+    pub fn process(&self) -> *mut TCB {
+        if self.process.is_null() {
+            self as *const TCB as *mut TCB
+        } else {
+            self.process
+        }
+    }
+
+    /// Whether this is a worker thread of another thread's process
+    #[inline]
+    pub fn is_worker(&self) -> bool {
+        !self.process.is_null()
+    }
+
+    /// Make this thread a worker of `process`'s main thread
+    #[inline]
+    pub fn set_process(&mut self, process: *mut TCB) {
+        self.process = process;
+    }
+
+    /// Get the next thread in the process's list of workers
+    #[inline]
+    pub fn next_thread(&self) -> *mut TCB {
+        self.next_thread
+    }
+
+    /// Set the next thread in the process's list of workers
+    #[inline]
+    pub fn set_next_thread(&mut self, next: *mut TCB) {
+        self.next_thread = next;
+    }
+
+    /// Get the caller waiting for this thread's reply (null if none)
+    #[inline]
+    pub fn reply_to(&self) -> *mut TCB {
+        self.reply_to
+    }
+
+    /// Set the caller waiting for this thread's reply (null to clear)
+    ///
+    /// Also points the caller's `replying_server` at this thread, and
+    /// clears the previous caller's. Callers are live TCBs: destroying one
+    /// clears the `reply_to` naming it (see `clear_replying_server`).
+    #[inline]
+    pub fn set_reply_to(&mut self, caller: *mut TCB) {
+        let this = self as *mut TCB;
+        unsafe {
+            if !self.reply_to.is_null() && (*self.reply_to).replying_server == this {
+                (*self.reply_to).replying_server = core::ptr::null_mut();
+            }
+            if !caller.is_null() {
+                (*caller).replying_server = this;
+            }
+        }
+        self.reply_to = caller;
+    }
+
+    /// Forget the reply this thread waits for: clear the `reply_to` of the
+    /// server that owes it one, if any
+    ///
+    /// For a thread about to be destroyed, so that no server replies into
+    /// its freed frame.
+    #[inline]
+    pub fn clear_replying_server(&mut self) {
+        let this = self as *mut TCB;
+        let server = self.replying_server;
+        if !server.is_null() && unsafe { (*server).reply_to } == this {
+            unsafe { (*server).set_reply_to(core::ptr::null_mut()) };
+        }
+        self.replying_server = core::ptr::null_mut();
+    }
+
+    /// Get the badge of this thread's pending send or call
+    #[inline]
+    pub fn ipc_badge(&self) -> u64 {
+        self.ipc_badge
+    }
+
+    /// Record the badge of this thread's pending send or call
+    #[inline]
+    pub fn set_ipc_badge(&mut self, badge: u64) {
+        self.ipc_badge = badge;
+    }
+
+    /// Get the capability slot sent with this thread's pending send
+    #[inline]
+    pub fn ipc_cap(&self) -> Option<usize> {
+        self.ipc_cap
+    }
+
+    /// Record the capability slot sent with this thread's pending send
+    #[inline]
+    pub fn set_ipc_cap(&mut self, cap_slot: Option<usize>) {
+        self.ipc_cap = cap_slot;
+    }
+
+    /// Get the slot this thread receives a capability into, if any
+    #[inline]
+    pub fn cap_receive_slot(&self) -> Option<usize> {
+        self.cap_receive_slot
+    }
+
+    /// Set the slot this thread receives a capability into
+    #[inline]
+    pub fn set_cap_receive_slot(&mut self, slot: Option<usize>) {
+        self.cap_receive_slot = slot;
+    }
+
+    /// Take the receive slot for the message being delivered
+    #[inline]
+    pub fn take_cap_receive_slot(&mut self) -> Option<usize> {
+        self.cap_receive_slot.take()
+    }
+
+    /// Get the CPU time consumed by this thread
+    #[inline]
+    pub fn cpu_usage(&self) -> &CpuUsage {
+        &self.cpu_usage
+    }
+
+    /// Get mutable access to the CPU usage counters
+    #[inline]
+    pub fn cpu_usage_mut(&mut self) -> &mut CpuUsage {
+        &mut self.cpu_usage
+    }
+
+    /// Add a period of running time to this thread's CPU usage
+    pub fn charge_cpu(&mut self, used: &CpuUsage) {
+        self.cpu_usage.time += used.time;
+        self.cpu_usage.cycles += used.cycles;
+        self.cpu_usage.instructions += used.instructions;
+    }
+
+    /// Get the saved FP/SIMD state
+    #[inline]
+    pub fn fp_state(&self) -> &FpState {
+        &self.fp_state
+    }
+
+    /// Get mutable access to the saved FP/SIMD state
+    #[inline]
+    pub fn fp_state_mut(&mut self) -> &mut FpState {
+        &mut self.fp_state
+    }
+
+    /// Get the capability bitmask
+    #[inline]
+    pub fn capabilities(&self) -> u64 {
+        self.capabilities
+    }
+
     /// Check if the thread is runnable
     #[inline]
     pub fn is_runnable(&self) -> bool {
@@ -346,6 +669,7 @@ impl TCB {
                 | ThreadState::BlockedOnSend { .. }
                 | ThreadState::BlockedOnReply
                 | ThreadState::BlockedOnNotification { .. }
+                | ThreadState::BlockedOnFault { .. }
         )
     }
 
@@ -377,6 +701,11 @@ impl TCB {
         self.state = ThreadState::BlockedOnReply;
     }
 
+    /// Block the thread until its pager resolves a fault
+    pub fn block_on_fault(&mut self, endpoint: usize) {
+        self.state = ThreadState::BlockedOnFault { endpoint };
+    }
+
     /// Unblock the thread (make it runnable)
     pub fn unblock(&mut self) {
         if self.is_blocked() {
@@ -441,6 +770,15 @@ impl TCB {
         addr
     }
 
+    /// Allocate a virtual address range aligned to `align` bytes
+    ///
+    /// Used for large-page mappings, whose virtual address must be aligned
+    /// to the block size. `align` must be a power of two.
+    pub fn alloc_virt_range_aligned(&mut self, size: u64, align: u64) -> u64 {
+        self.next_virt_addr = self.next_virt_addr.next_multiple_of(align);
+        self.alloc_virt_range(size)
+    }
+
     /// Get the current next_virt_addr (for debugging)
     #[inline]
     pub fn next_virt_addr(&self) -> u64 {
@@ -456,6 +794,12 @@ impl TCB {
         self.next_cap_slot += 1;
         slot
     }
+
+    /// Whether `slot` is one [`alloc_cap_slot`](Self::alloc_cap_slot) has
+    /// handed out
+    pub fn cap_slot_allocated(&self, slot: u64) -> bool {
+        (Self::FIRST_ALLOCATED_CAP_SLOT..self.next_cap_slot).contains(&slot)
+    }
 }
 
 impl core::fmt::Debug for TCB {
@@ -464,6 +808,7 @@ impl core::fmt::Debug for TCB {
             .field("tid", &self.tid)
             .field("state", &self.state)
             .field("priority", &self.priority)
+            .field("domain", &self.domain)
             .field("time_slice", &self.time_slice)
             .field("cspace_root", &format_args!("{:p}", self.cspace_root))
             .field("vspace_root", &format_args!("{:#x}", self.vspace_root))
@@ -502,6 +847,7 @@ mod tests {
             assert_eq!(tcb.tid(), 1);
             assert_eq!(tcb.state(), ThreadState::Inactive);
             assert_eq!(tcb.priority(), TCB::DEFAULT_PRIORITY);
+            assert_eq!(tcb.domain(), 0);
             assert_eq!(tcb.context().elr_el1, 0x200000);
             assert_eq!(tcb.context().sp_el0, 0x300000);
         }
@@ -544,6 +890,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn tcb_donation() {
+        let mut cnode_memory = [crate::objects::Capability::null(); 16];
+        let cnode_ptr = &mut cnode_memory[0] as *mut _ as *mut CNode;
+
+        unsafe {
+            let mut tcb = TCB::new(
+                1,
+                cnode_ptr,
+                0x40000000,
+                VirtAddr::new(0x10000000),
+                0x200000,
+                0x300000,
+            );
+
+            assert_eq!(tcb.donation(), None);
+
+            let donation = Donation { base_priority: TCB::DEFAULT_PRIORITY, notification: 0x5000 };
+            tcb.set_donation(Some(donation));
+            assert_eq!(tcb.donation(), Some(donation));
+
+            tcb.set_donation(None);
+            assert_eq!(tcb.donation(), None);
+        }
+    }
+
     #[test]
     fn tcb_time_slice() {
         let mut cnode_memory = [crate::objects::Capability::null(); 16];
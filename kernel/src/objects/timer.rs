@@ -0,0 +1,196 @@
+//! Timer Objects
+//!
+//! A timer signals a notification after a timeout, once or periodically,
+//! so userspace can sleep, bound its waits and keep time without
+//! busy-yielding.
+//!
+//! ## Usage
+//!
+//! 1. `SYS_TIMER_CREATE(notification, badge)` creates a timer bound to a
+//!    notification and returns its capability
+//! 2. `SYS_TIMER_SET(timer, timeout_us, period_us)` arms it: after
+//!    `timeout_us` the notification is signaled with the badge, and then
+//!    every `period_us` if that is non-zero
+//! 3. The thread waits on the notification as usual (`SYS_WAIT`), possibly
+//!    together with other signals bound to the same notification
+//!
+//! Setting a timer again replaces its pending timeout; a timeout and
+//! period of 0 cancel it.
+//!
+//! ## Resolution
+//!
+//! Armed timers are checked on every scheduler tick, so timeouts are
+//! rounded up to the tick (`scheduler::timer::TICK_MS`). A periodic timer
+//! that falls behind signals once and continues from the current time
+//! rather than signaling a burst.
+//!
+//! ## Lifetime
+//!
+//! Like endpoints and notifications, a timer counts its capabilities; when
+//! the last one is deleted the timer is cancelled.
+
+use crate::objects::Notification;
+
+/// Maximum number of timers armed at once, system-wide
+pub const MAX_ARMED_TIMERS: usize = 64;
+
+/// Timer - signals a notification when its timeout expires
+pub struct Timer {
+    /// Notification signaled on expiry
+    notification: *mut Notification,
+
+    /// Bits signaled on the notification
+    badge: u64,
+
+    /// Counter value at which the timer expires
+    deadline: u64,
+
+    /// Counter ticks between expiries (0 = one-shot)
+    period: u64,
+
+    /// Whether the timer is in the armed list
+    armed: bool,
+
+    /// Number of capabilities referring to this timer
+    cap_count: usize,
+}
+
+impl Timer {
+    /// Create a disarmed timer bound to a notification
+    pub const fn new(notification: *mut Notification, badge: u64) -> Self {
+        Self {
+            notification,
+            badge,
+            deadline: 0,
+            period: 0,
+            armed: false,
+            cap_count: 0,
+        }
+    }
+
+    /// Whether the timer is armed
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Count a new capability to this timer
+    pub fn retain_cap(&mut self) {
+        self.cap_count += 1;
+    }
+
+    /// Count a deleted capability; returns true if it was the last one
+    pub fn release_cap(&mut self) -> bool {
+        self.cap_count = self.cap_count.saturating_sub(1);
+        self.cap_count == 0
+    }
+}
+
+/// Armed timers (null = free entry)
+static mut ARMED: [*mut Timer; MAX_ARMED_TIMERS] = [core::ptr::null_mut(); MAX_ARMED_TIMERS];
+
+/// Convert microseconds to counter ticks
+fn us_to_ticks(us: u64) -> u64 {
+    (crate::scheduler::timer::timer_frequency() as u128 * us as u128 / 1_000_000) as u64
+}
+
+/// Whether the counter value `now` has reached `deadline`
+fn reached(now: u64, deadline: u64) -> bool {
+    now.wrapping_sub(deadline) as i64 >= 0
+}
+
+/// Arm a timer, replacing any pending timeout
+///
+/// The timer expires `timeout_us` from now, or `period_us` from now if
+/// `timeout_us` is 0, and then every `period_us` if that is non-zero. Both
+/// 0 cancels the timer. Returns false if too many timers are armed.
+///
+/// # Safety
+///
+/// `timer` must be a valid timer object; must not race with `tick`
+/// (called from a syscall, IRQs masked).
+pub unsafe fn arm(timer: *mut Timer, timeout_us: u64, period_us: u64) -> bool {
+    let first_us = if timeout_us != 0 { timeout_us } else { period_us };
+    if first_us == 0 {
+        disarm(timer);
+        return true;
+    }
+
+    let armed = &mut *core::ptr::addr_of_mut!(ARMED);
+    if !(*timer).armed {
+        let Some(entry) = armed.iter_mut().find(|t| t.is_null()) else {
+            return false;
+        };
+        *entry = timer;
+    }
+
+    let t = &mut *timer;
+    t.deadline = crate::scheduler::timer::read_counter().wrapping_add(us_to_ticks(first_us));
+    t.period = us_to_ticks(period_us);
+    t.armed = true;
+    true
+}
+
+/// Cancel a timer; a signal it already delivered stays pending
+///
+/// # Safety
+///
+/// `timer` must be a valid timer object; must not race with `tick`.
+pub unsafe fn disarm(timer: *mut Timer) {
+    let armed = &mut *core::ptr::addr_of_mut!(ARMED);
+    if let Some(entry) = armed.iter_mut().find(|t| **t == timer) {
+        *entry = core::ptr::null_mut();
+    }
+    (*timer).armed = false;
+}
+
+/// Signal every armed timer whose deadline has passed
+///
+/// # Safety
+///
+/// Must be called from the timer interrupt.
+pub unsafe fn tick() {
+    let now = crate::scheduler::timer::read_counter();
+    let armed = &mut *core::ptr::addr_of_mut!(ARMED);
+
+    for entry in armed.iter_mut().filter(|t| !t.is_null()) {
+        let timer = &mut **entry;
+        if !reached(now, timer.deadline) {
+            continue;
+        }
+
+        (*timer.notification).signal(timer.badge);
+
+        if timer.period == 0 {
+            timer.armed = false;
+            *entry = core::ptr::null_mut();
+        } else {
+            timer.deadline = timer.deadline.wrapping_add(timer.period);
+            if reached(now, timer.deadline) {
+                // Fell behind: skip the missed expiries
+                timer.deadline = now.wrapping_add(timer.period);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_reached() {
+        assert!(reached(100, 100));
+        assert!(reached(101, 100));
+        assert!(!reached(99, 100));
+        // Across counter wrap-around
+        assert!(reached(5, u64::MAX - 5));
+        assert!(!reached(u64::MAX - 5, 5));
+
+        let mut timer = Timer::new(core::ptr::null_mut(), 1);
+        timer.retain_cap();
+        timer.retain_cap();
+        assert!(!timer.release_cap());
+        assert!(timer.release_cap());
+        assert!(!timer.is_armed());
+    }
+}
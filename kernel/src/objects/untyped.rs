@@ -252,6 +252,8 @@ impl UntypedMemory {
             CapType::IrqHandler => 0,              // Zero-size (just metadata)
             CapType::IrqControl => 0,              // Zero-size
             CapType::Reply => 0,                   // Zero-size (just metadata)
+            CapType::Timer => 6,                   // 64B minimum
+            CapType::Vcpu => 12,                   // 4KB
         };
 
         if size_bits < min_size_bits {
@@ -0,0 +1,297 @@
+//! Virtual CPUs
+//!
+//! A VCPU runs a guest operating system at EL1 under stage-2 translation,
+//! so a component (the VMM) can host a whole OS confined to the physical
+//! memory it hands it - the ARM_HYP configuration of seL4. Needs the
+//! `hypervisor` feature and a kernel entered at EL2 (see
+//! `arch::aarch64::hyp`).
+//!
+//! ## Usage
+//!
+//! 1. `SYS_VCPU_CREATE()` creates a VCPU with an empty guest physical
+//!    address space and returns its capability
+//! 2. `SYS_VCPU_MAP(vcpu, phys, size, ipa, permissions)` maps memory at a
+//!    guest physical address (IPA)
+//! 3. `SYS_VCPU_WRITE_REG` sets the guest's entry point and boot registers
+//!    (a new VCPU starts at EL1h with interrupts masked and its MMU off)
+//! 4. `SYS_VCPU_RUN(vcpu)` runs the guest until it exits and returns why;
+//!    the VMM handles the exit and runs it again
+//!
+//! ## Exits
+//!
+//! - `VCPU_EXIT_IRQ`: a physical interrupt arrived, or the kernel's tick
+//!   came due. The guest was preempted and can be run again as is
+//! - `VCPU_EXIT_TRAP`: the guest trapped to EL2; ESR_EL2 says why: an
+//!   HVC, SMC or WFI, or a stage-2 abort on an IPA that is not mapped (or
+//!   not with that access), e.g. an emulated device. The guest PC points at
+//!   the trapping instruction (after it, for HVC): the VMM advances it past
+//!   the instructions it emulates
+//! - `VCPU_EXIT_SERROR`: an asynchronous abort was taken
+//!
+//! ## Interrupts
+//!
+//! Guests see no interrupt controller: physical interrupts stay with the
+//! kernel. The VMM emulates one (its registers trap as stage-2 aborts) and
+//! raises the guest's IRQ line with `SYS_VCPU_INJECT_IRQ`. The guest's
+//! virtual timer runs on the real hardware; when it fires, the guest exits
+//! with `VCPU_EXIT_IRQ` and the VMM finds it pending in CNTV_CTL_EL0.
+//!
+//! ## Lifetime
+//!
+//! Like timers, a VCPU counts its capabilities; when the last one is
+//! deleted its stage-2 tables and VMID are released.
+
+use crate::arch::aarch64::hyp::{self, El1Regs, VcpuContext, EL1_REG_COUNT};
+use crate::arch::aarch64::page_table::{PageTable, PageTableFlags};
+use crate::memory::{dealloc_frame, MappingError, PageFrameNumber, PageMapper, PageSize, PhysAddr, VirtAddr};
+
+/// Size of a guest physical address space (VTCR_EL2.T0SZ, see `hyp_init`)
+pub const IPA_BITS: u32 = 40;
+
+// Register numbers of SYS_VCPU_READ_REG / SYS_VCPU_WRITE_REG: x0-x30 are
+// 0-30, then the guest PC and PSTATE, its MPIDR, and the EL1 system
+// registers in `El1Regs` order (SCTLR_EL1 first)
+pub const VCPU_REG_PC: u64 = 31;
+pub const VCPU_REG_PSTATE: u64 = 32;
+pub const VCPU_REG_MPIDR: u64 = 33;
+pub const VCPU_REG_EL1_BASE: u64 = 34;
+
+/// Number of registers
+pub const VCPU_REG_COUNT: u64 = VCPU_REG_EL1_BASE + EL1_REG_COUNT as u64;
+
+/// HCR_EL2 while a guest runs: stage 2 (VM), set/way invalidation as
+/// clean+invalidate (SWIO), physical interrupts to EL2 (FMO, IMO, AMO),
+/// WFI and SMC trapped (TWI, TSC), EL1 is AArch64 (RW)
+const HCR_GUEST: u64 = (1 << 0) | (1 << 1) | (1 << 3) | (1 << 4) | (1 << 5)
+    | (1 << 13) | (1 << 19) | (1 << 31);
+
+/// HCR_EL2.VI: the guest's IRQ line
+const HCR_VI: u64 = 1 << 7;
+
+/// Initial PSTATE: EL1h, DAIF masked
+const PSTATE_EL1H_MASKED: u64 = 0x3c5;
+
+/// Initial SCTLR_EL1: RES1 bits only (MMU and caches off)
+const SCTLR_EL1_RESET: u64 = 0x30d0_0800;
+
+// Stage-2 descriptor bits (attributes differ from stage 1)
+const S2_PAGE: u64 = 0b11;              // Valid, page (the mapper clears bit 1 for blocks)
+const S2_MEMATTR_NORMAL: u64 = 0xf << 2; // Normal write-back; stage 1 may restrict it
+const S2AP_READ: u64 = 1 << 6;
+const S2AP_WRITE: u64 = 1 << 7;
+const S2_INNER_SHARE: u64 = 3 << 8;
+const S2_AF: u64 = 1 << 10;
+const S2_XN: u64 = 1 << 54;
+
+/// VMIDs in use (8-bit VMIDs; VMID 0 is the kernel's)
+static mut VMIDS: [u64; 4] = [1, 0, 0, 0];
+
+/// Allocate a VMID
+unsafe fn alloc_vmid() -> Option<u16> {
+    let vmids = &mut *core::ptr::addr_of_mut!(VMIDS);
+    for (word_index, word) in vmids.iter_mut().enumerate() {
+        if *word != u64::MAX {
+            let bit = (!*word).trailing_zeros();
+            *word |= 1 << bit;
+            return Some((word_index * 64) as u16 + bit as u16);
+        }
+    }
+    None
+}
+
+/// Free a VMID; its TLB entries must be gone
+unsafe fn free_vmid(vmid: u16) {
+    let vmids = &mut *core::ptr::addr_of_mut!(VMIDS);
+    vmids[vmid as usize / 64] &= !(1 << (vmid % 64));
+}
+
+/// Stage-2 descriptor for `permissions` (1=read, 2=write, 4=exec)
+fn stage2_flags(permissions: u64) -> PageTableFlags {
+    let mut bits = S2_PAGE | S2_MEMATTR_NORMAL | S2_INNER_SHARE | S2_AF;
+    if permissions & 1 != 0 {
+        bits |= S2AP_READ;
+    }
+    if permissions & 2 != 0 {
+        bits |= S2AP_WRITE;
+    }
+    if permissions & 4 == 0 {
+        bits |= S2_XN;
+    }
+    PageTableFlags::from_bits_retain(bits)
+}
+
+/// Virtual CPU and the guest physical address space it runs in
+pub struct Vcpu {
+    /// Guest registers, switched by the EL2 stub
+    context: VcpuContext,
+
+    /// Stage-2 table root (level 0)
+    stage2_root: *mut PageTable,
+
+    /// VMID tagging the guest's TLB entries
+    vmid: u16,
+
+    /// Guest IRQ line
+    irq_pending: bool,
+
+    /// Number of capabilities referring to this VCPU
+    cap_count: usize,
+}
+
+impl Vcpu {
+    /// Create a VCPU over an empty (zeroed) stage-2 root table
+    ///
+    /// Returns None if all VMIDs are in use.
+    ///
+    /// # Safety
+    ///
+    /// `stage2_root` must be a zeroed page table from `alloc_frame`, owned
+    /// by the VCPU from now on.
+    pub unsafe fn new(stage2_root: *mut PageTable) -> Option<Self> {
+        let vmid = alloc_vmid()?;
+
+        let mut context = VcpuContext::new();
+        context.pstate = PSTATE_EL1H_MASKED;
+        context.vmpidr = 1 << 31; // RES1; CPU 0 of its cluster
+        context.el1.sctlr_el1 = SCTLR_EL1_RESET;
+
+        Some(Self {
+            context,
+            stage2_root,
+            vmid,
+            irq_pending: false,
+            cap_count: 0,
+        })
+    }
+
+    /// VTTBR_EL2 of the guest: stage-2 root and VMID
+    fn vttbr(&self) -> u64 {
+        self.stage2_root as u64 | (self.vmid as u64) << 48
+    }
+
+    /// Map `size` bytes of physical memory at guest physical address `ipa`
+    ///
+    /// `permissions` as for `SYS_MEMORY_MAP` (1=read, 2=write, 4=exec).
+    /// Addresses and size must be aligned to `page_size`; an IPA that is
+    /// already mapped is an error.
+    pub fn map(&mut self, ipa: u64, phys: u64, size: u64, permissions: u64, page_size: PageSize)
+        -> Result<(), MappingError>
+    {
+        match ipa.checked_add(size) {
+            Some(end) if end <= 1 << IPA_BITS => {}
+            _ => return Err(MappingError::AddressMisaligned),
+        }
+
+        let mut mapper = unsafe { PageMapper::new(&mut *self.stage2_root) };
+        mapper.map_region(VirtAddr::new(ipa as usize), PhysAddr::new(phys as usize),
+                          size as usize, stage2_flags(permissions), page_size)?;
+
+        // New entries only: no stale TLB entries to drop
+        unsafe { core::arch::asm!("dsb ishst", options(nostack)) };
+        Ok(())
+    }
+
+    /// Register `reg` (`VCPU_REG_*`)
+    pub fn reg_mut(&mut self, reg: u64) -> Option<&mut u64> {
+        let context = &mut self.context;
+        match reg {
+            0..=30 => Some(&mut context.gpr[reg as usize]),
+            VCPU_REG_PC => Some(&mut context.pc),
+            VCPU_REG_PSTATE => Some(&mut context.pstate),
+            VCPU_REG_MPIDR => Some(&mut context.vmpidr),
+            _ if reg < VCPU_REG_COUNT => context.el1.get_mut((reg - VCPU_REG_EL1_BASE) as usize),
+            _ => None,
+        }
+    }
+
+    /// Guest EL1 system registers
+    pub fn el1(&self) -> &El1Regs {
+        &self.context.el1
+    }
+
+    /// Raise or lower the guest's IRQ line
+    pub fn set_irq(&mut self, level: bool) {
+        self.irq_pending = level;
+    }
+
+    /// Syndrome of the last trap: ESR_EL2, FAR_EL2 and the faulting IPA
+    /// (meaningful for stage-2 aborts only)
+    pub fn exit_syndrome(&self) -> (u64, u64, u64) {
+        let ipa = ((self.context.hpfar >> 4) << 12) | (self.context.far & 0xfff);
+        (self.context.esr, self.context.far, ipa)
+    }
+
+    /// Run the guest until it exits; returns the exit reason
+    ///
+    /// Returns None without running it if its PSTATE is not EL0 or EL1
+    /// (AArch64).
+    ///
+    /// # Safety
+    ///
+    /// The EL2 stub must be installed; called from a syscall (IRQs masked).
+    pub unsafe fn run(&mut self) -> Option<u64> {
+        // M[4:0]: EL0t, EL1t or EL1h - never EL2 or AArch32
+        if !matches!(self.context.pstate & 0x1f, 0b00000 | 0b00100 | 0b00101) {
+            return None;
+        }
+
+        self.context.hcr = HCR_GUEST | if self.irq_pending { HCR_VI } else { 0 };
+        self.context.vttbr = self.vttbr();
+        Some(hyp::enter_guest(&mut self.context))
+    }
+
+    /// Count a new capability to this VCPU
+    pub fn retain_cap(&mut self) {
+        self.cap_count += 1;
+    }
+
+    /// Count a deleted capability; returns true if it was the last one
+    pub fn release_cap(&mut self) -> bool {
+        self.cap_count = self.cap_count.saturating_sub(1);
+        self.cap_count == 0
+    }
+}
+
+/// Release the guest physical address space and VMID of a VCPU
+///
+/// # Safety
+///
+/// `vcpu` must be a valid VCPU that no capability refers to any more.
+pub unsafe fn destroy(vcpu: *mut Vcpu) {
+    let vcpu = &mut *vcpu;
+    if vcpu.stage2_root.is_null() {
+        return;
+    }
+
+    hyp::flush_guest_tlb(vcpu.vttbr());
+    free_vmid(vcpu.vmid);
+
+    // Only the tables: the mapped frames belong to the VMM
+    PageMapper::new(&mut *vcpu.stage2_root).free_tables();
+    dealloc_frame(PageFrameNumber::from_phys_addr(PhysAddr::new(vcpu.stage2_root as usize)));
+    vcpu.stage2_root = core::ptr::null_mut();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage2_flags_and_registers() {
+        let rw = stage2_flags(0b011).bits();
+        assert_eq!(rw & (S2AP_READ | S2AP_WRITE), S2AP_READ | S2AP_WRITE);
+        assert_ne!(rw & S2_XN, 0);
+        let rx = stage2_flags(0b101).bits();
+        assert_eq!(rx & (S2AP_READ | S2AP_WRITE | S2_XN), S2AP_READ);
+
+        assert!(core::mem::size_of::<Vcpu>() <= 4096);
+
+        let mut vcpu = unsafe { Vcpu::new(core::ptr::null_mut()) }.unwrap();
+        *vcpu.reg_mut(VCPU_REG_EL1_BASE).unwrap() = 0x1234;
+        assert_eq!(vcpu.el1().sctlr_el1, 0x1234);
+        assert!(vcpu.reg_mut(VCPU_REG_COUNT).is_none());
+
+        vcpu.retain_cap();
+        assert!(vcpu.release_cap());
+    }
+}
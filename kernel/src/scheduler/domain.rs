@@ -0,0 +1,145 @@
+//! Scheduling Domains
+//!
+//! Domains partition CPU time between groups of threads, after seL4's
+//! domain scheduler. Every thread belongs to one domain (0 by default),
+//! and the kernel cycles through a static schedule of `(domain, ticks)`
+//! windows. During a window only threads of that domain are picked, so a
+//! security-critical component placed in its own domain cannot be starved
+//! by untrusted ones, and gets its CPU time at fixed points.
+//!
+//! The idle thread has no user context, so when the active domain has
+//! nothing runnable, a thread of another domain that is already running
+//! keeps the CPU until a thread of the active domain becomes runnable.
+//!
+//! Within a domain, the usual fixed-priority round-robin applies.
+//!
+//! The domain-switch timer is driven by the preemption tick: each tick
+//! counts against the current window, and when it runs out the next
+//! window's domain becomes active and the running thread is preempted.
+//!
+//! Threads are moved between domains with `SYS_DOMAIN_SET`, which needs
+//! the `TCB::CAP_DOMAIN` capability. The default schedule is a single
+//! window for domain 0, which behaves exactly like no domain scheduling.
+
+/// Number of scheduling domains
+pub const NUM_DOMAINS: usize = 16;
+
+/// One window of the domain schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DomainWindow {
+    /// Domain that runs during this window
+    pub domain: u8,
+    /// Window length in timer ticks
+    pub ticks: u32,
+}
+
+/// The domain schedule, repeated forever
+///
+/// Edit this table to isolate components in time, e.g.
+/// `[{ domain: 0, ticks: 10 }, { domain: 1, ticks: 5 }]` gives domain 1
+/// a third of the CPU in 5-tick windows.
+pub static SCHEDULE: &[DomainWindow] = &[DomainWindow { domain: 0, ticks: 100 }];
+
+/// Position in a domain schedule
+struct ScheduleState {
+    /// Index of the current window
+    index: usize,
+    /// Ticks left in the current window
+    remaining: u32,
+}
+
+impl ScheduleState {
+    const fn new() -> Self {
+        Self { index: 0, remaining: 0 }
+    }
+
+    /// Start at the first window of `schedule`
+    fn reset(&mut self, schedule: &[DomainWindow]) -> u8 {
+        self.index = 0;
+        self.remaining = schedule[0].ticks;
+        schedule[0].domain
+    }
+
+    /// Charge one tick; returns the next domain when the window ends
+    fn tick(&mut self, schedule: &[DomainWindow]) -> Option<u8> {
+        self.remaining = self.remaining.saturating_sub(1);
+        if self.remaining > 0 || schedule.len() <= 1 {
+            if self.remaining == 0 {
+                self.remaining = schedule[0].ticks;
+            }
+            return None;
+        }
+
+        self.index = (self.index + 1) % schedule.len();
+        self.remaining = schedule[self.index].ticks;
+        Some(schedule[self.index].domain)
+    }
+}
+
+static mut STATE: ScheduleState = ScheduleState::new();
+
+/// Whether `schedule` is usable: non-empty, valid domains, non-zero windows
+fn valid_schedule(schedule: &[DomainWindow]) -> bool {
+    !schedule.is_empty()
+        && schedule.iter().all(|w| (w.domain as usize) < NUM_DOMAINS && w.ticks > 0)
+}
+
+/// Start the domain schedule and return the first domain
+///
+/// # Safety
+///
+/// Must be called once during boot, before the scheduler runs threads.
+pub unsafe fn init() -> u8 {
+    assert!(valid_schedule(SCHEDULE), "invalid domain schedule");
+    let state = &mut *core::ptr::addr_of_mut!(STATE);
+    let first = state.reset(SCHEDULE);
+    if SCHEDULE.len() > 1 {
+        crate::kprintln!("[sched] Domain schedule: {} windows, starting in domain {}", SCHEDULE.len(), first);
+    }
+    first
+}
+
+/// Charge one timer tick to the current domain window
+///
+/// Returns the next domain when the window has run out.
+///
+/// # Safety
+///
+/// Must be called from the timer interrupt with `init` done.
+pub unsafe fn tick() -> Option<u8> {
+    let state = &mut *core::ptr::addr_of_mut!(STATE);
+    state.tick(SCHEDULE)
+}
+
+/// Whether `domain` is a valid domain number
+#[inline]
+pub fn valid_domain(domain: u64) -> bool {
+    domain < NUM_DOMAINS as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_rotation() {
+        assert!(valid_schedule(SCHEDULE));
+        assert!(!valid_schedule(&[]));
+        assert!(!valid_schedule(&[DomainWindow { domain: NUM_DOMAINS as u8, ticks: 1 }]));
+        assert!(!valid_schedule(&[DomainWindow { domain: 0, ticks: 0 }]));
+
+        let schedule = [DomainWindow { domain: 0, ticks: 2 }, DomainWindow { domain: 3, ticks: 1 }];
+        let mut state = ScheduleState::new();
+        assert_eq!(state.reset(&schedule), 0);
+        assert_eq!(state.tick(&schedule), None);
+        assert_eq!(state.tick(&schedule), Some(3));
+        assert_eq!(state.tick(&schedule), Some(0));
+        assert_eq!(state.tick(&schedule), None);
+
+        // A single window never switches
+        let single = [DomainWindow { domain: 0, ticks: 1 }];
+        state.reset(&single);
+        assert_eq!(state.tick(&single), None);
+        assert_eq!(state.tick(&single), None);
+    }
+}
@@ -0,0 +1,129 @@
+//! Idle Loop
+//!
+//! When every thread is blocked, the scheduler picks the idle thread. It
+//! has no user context to return to, so the kernel idles in its place: the
+//! switch that found nothing to run waits in WFI, on the kernel stack of
+//! the exception it was handling, until an interrupt makes a thread
+//! runnable, and then returns into that thread instead.
+//!
+//! The kernel runs with IRQs masked. WFI wakes on a pending interrupt all
+//! the same, and the loop handles it itself
+//! (`arch::aarch64::exception::idle_irq`): a device IRQ signals its
+//! driver's notification, a timer tick fires expired timer objects and
+//! advances the domain schedule.
+//!
+//! Time spent idle is charged to the idle thread (TID 0) like any thread's,
+//! and summed up for `SYS_IDLE_STATS`.
+
+use crate::objects::TCB;
+
+/// CPU idle statistics as returned to userspace (little-endian, 4 × u64)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdleStats {
+    /// Time since the scheduler started, in microseconds
+    pub uptime_us: u64,
+    /// Time spent idle, in microseconds
+    pub idle_us: u64,
+    /// Times the CPU went idle
+    pub idle_entries: u64,
+    /// Interrupts that woke the CPU while idle
+    pub wakeups: u64,
+}
+
+impl IdleStats {
+    /// Size of the encoded stats in bytes
+    pub const SIZE: usize = 4 * 8;
+
+    /// Encode the stats as they are delivered to userspace
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        let words = [self.uptime_us, self.idle_us, self.idle_entries, self.wakeups];
+        for (chunk, word) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(words) {
+            *chunk = word.to_le_bytes();
+        }
+        bytes
+    }
+}
+
+/// Counter reading when the scheduler started
+static mut START: u64 = 0;
+
+/// Counter ticks spent idle
+static mut IDLE_TICKS: u64 = 0;
+
+static mut IDLE_ENTRIES: u64 = 0;
+static mut WAKEUPS: u64 = 0;
+
+/// Start the uptime clock
+///
+/// # Safety
+///
+/// Must be called once during scheduler initialization.
+pub unsafe fn init() {
+    START = super::timer::read_counter();
+}
+
+/// Idle until a thread is runnable, and return it
+///
+/// Called with the scheduler's pick when that is the idle thread. The idle
+/// thread becomes the current thread while waiting; the caller switches
+/// to the returned thread.
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - Must be called with IRQs masked, from an exception taken from EL0
+pub unsafe fn wait_for_thread() -> *mut TCB {
+    let idle = super::scheduler().idle();
+    super::set_current_thread(idle);
+    IDLE_ENTRIES += 1;
+
+    let start = super::timer::read_counter();
+    let next = loop {
+        core::arch::asm!("dsb sy", "wfi", options(nostack));
+        crate::arch::aarch64::exception::idle_irq();
+        WAKEUPS += 1;
+
+        let next = super::schedule();
+        if next != idle {
+            break next;
+        }
+    };
+    IDLE_TICKS += super::timer::read_counter().wrapping_sub(start);
+    next
+}
+
+/// Idle statistics so far
+///
+/// # Safety
+///
+/// Scheduler must be initialized
+pub unsafe fn stats() -> IdleStats {
+    let now = super::timer::read_counter();
+    IdleStats {
+        uptime_us: ticks_to_us(now.wrapping_sub(START)),
+        idle_us: ticks_to_us(IDLE_TICKS),
+        idle_entries: IDLE_ENTRIES,
+        wakeups: WAKEUPS,
+    }
+}
+
+fn ticks_to_us(ticks: u64) -> u64 {
+    let freq = super::timer::timer_frequency().max(1);
+    (ticks as u128 * 1_000_000 / freq as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_stats_encoding() {
+        let stats = IdleStats { uptime_us: 2_000_000, idle_us: 1_500_000, idle_entries: 40, wakeups: 90 };
+        let bytes = stats.to_bytes();
+        assert_eq!(&bytes[0..8], &2_000_000u64.to_le_bytes());
+        assert_eq!(&bytes[8..16], &1_500_000u64.to_le_bytes());
+        assert_eq!(&bytes[24..32], &90u64.to_le_bytes());
+    }
+}
@@ -14,7 +14,13 @@
 //! - 256 priority levels (0 = highest, 255 = lowest)
 //! - O(1) scheduling via priority bitmap
 //! - Deterministic behavior
-//! - Explicit yield points (no automatic preemption yet)
+//! - Timer-driven preemption of userspace (see [`timer`]): a thread that
+//!   exhausts its timeslice goes to the tail of its priority queue, and a
+//!   runnable higher-priority thread takes over at the next tick
+//! - Domain scheduling for temporal isolation (see [`domain`]): threads
+//!   only run during their domain's windows of a static schedule
+//! - An idle loop that waits for interrupts in WFI when nothing is
+//!   runnable (see [`idle`])
 //!
 //! ## Thread States
 //!
@@ -45,6 +51,10 @@ use crate::objects::TCB;
 
 mod types;
 pub mod timer;
+pub mod stats;
+pub mod watchdog;
+pub mod domain;
+pub mod idle;
 
 pub use types::{Scheduler, ThreadQueue, SchedulerError};
 
@@ -69,6 +79,10 @@ static mut SCHEDULER: Option<Scheduler> = None;
 /// - idle_tcb must be valid for the lifetime of the kernel
 pub unsafe fn init(idle_tcb: *mut TCB) {
     SCHEDULER = Some(Scheduler::new(idle_tcb));
+    scheduler().set_domain(domain::init());
+    stats::init(idle_tcb);
+    idle::init();
+    crate::arch::aarch64::fpu::init();
 }
 
 /// Get a reference to the global scheduler
@@ -81,6 +95,15 @@ unsafe fn scheduler() -> &'static mut Scheduler {
     SCHEDULER.as_mut().expect("Scheduler not initialized")
 }
 
+/// Get the idle thread
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+pub unsafe fn idle_thread() -> *mut TCB {
+    scheduler().idle()
+}
+
 /// Get the currently running thread
 ///
 /// Returns a pointer to the TCB of the thread currently executing on the CPU.
@@ -95,15 +118,35 @@ pub unsafe fn current_thread() -> *mut TCB {
 /// Set the current running thread
 ///
 /// This is called by context switcher to update the current thread pointer.
+/// The time since the last switch is charged to the outgoing thread.
 ///
 /// # Safety
 ///
 /// - Scheduler must be initialized
 /// - tcb must be valid
 unsafe fn set_current_thread(tcb: *mut TCB) {
+    let prev = scheduler().current();
+    if prev != tcb {
+        crate::arch::aarch64::fpu::switch_out(prev);
+    }
+    stats::account_switch(prev, tcb);
     scheduler().set_current(tcb);
 }
 
+/// Stop running the current thread, making the idle thread current
+///
+/// For a thread about to be destroyed: its time is charged and its FP
+/// state saved while its TCB frame is still there. The caller then picks
+/// the next thread with [`schedule`].
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - The current thread must be valid
+pub unsafe fn leave_current() {
+    set_current_thread(scheduler().idle());
+}
+
 /// Set the current thread (public for testing)
 ///
 /// **FOR TESTING ONLY** - Sets the scheduler's current thread pointer.
@@ -245,6 +288,128 @@ pub unsafe fn yield_current() {
     // Note: Execution continues here AFTER another thread yields back to us
 }
 
+/// Check whether a thread with higher priority than `priority` is runnable
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+pub unsafe fn higher_priority_ready(priority: u8) -> bool {
+    // Lower priority number = higher priority
+    matches!(scheduler().highest_ready_priority(), Some(ready) if ready < priority)
+}
+
+/// Get the active scheduling domain
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+pub unsafe fn current_domain() -> u8 {
+    scheduler().domain()
+}
+
+/// Whether `tcb` may run in the active domain
+///
+/// The idle thread belongs to every domain.
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - tcb must be valid
+pub unsafe fn in_current_domain(tcb: *mut TCB) -> bool {
+    scheduler().in_active_domain(tcb)
+}
+
+/// Make `domain` the active scheduling domain
+///
+/// Called by the domain-switch timer. The running thread is not switched
+/// out here; the caller preempts it once it is outside the active domain.
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+pub unsafe fn switch_domain(domain: u8) {
+    crate::ksched_debug!("[sched] Domain switch: {} -> {}", scheduler().domain(), domain);
+    scheduler().set_domain(domain);
+}
+
+/// Move a thread to another scheduling domain
+///
+/// A runnable thread is re-queued under its new domain. A running thread
+/// keeps the CPU until the next timer tick, which preempts it if its new
+/// domain is not active.
+///
+/// # Arguments
+///
+/// * `tcb` - Thread to modify
+/// * `domain` - New domain (below `domain::NUM_DOMAINS`)
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - tcb must be valid
+pub unsafe fn set_domain(tcb: *mut TCB, domain: u8) {
+    if tcb.is_null() || (*tcb).domain() == domain {
+        return;
+    }
+
+    if (*tcb).state() == crate::objects::ThreadState::Runnable {
+        dequeue(tcb);
+        (*tcb).set_domain(domain);
+        enqueue(tcb);
+    } else {
+        (*tcb).set_domain(domain);
+    }
+}
+
+/// Preempt the current thread from an exception taken in userspace
+///
+/// Saves the interrupted context from `tf` into the current TCB, rotates the
+/// thread to the tail of its priority queue (refilling an exhausted
+/// timeslice), and replaces `tf` with the context of the next thread. The exception return
+/// path then resumes the next thread (including its TTBR0).
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - `tf` must be the trap frame the exception return restores
+pub unsafe fn preempt_current(tf: &mut crate::arch::aarch64::context::TrapFrame) {
+    let current = current_thread();
+    if current.is_null() {
+        return;
+    }
+
+    let current_tcb = &mut *current;
+    if current_tcb.state() != crate::objects::ThreadState::Running {
+        return;
+    }
+
+    *current_tcb.context_mut() = *tf;
+    if current_tcb.time_slice() == 0 {
+        current_tcb.refill_time_slice();
+    }
+    current_tcb.set_state(crate::objects::ThreadState::Runnable);
+    enqueue(current);
+
+    let next = schedule();
+    if next == scheduler().idle() {
+        // Nothing runnable in the active domain. The idle thread has no
+        // user context to return to, so the current thread keeps the CPU.
+        dequeue(current);
+        current_tcb.set_state(crate::objects::ThreadState::Running);
+        return;
+    }
+    if next.is_null() || next == current {
+        // Only runnable thread at its priority: keep running
+        current_tcb.set_state(crate::objects::ThreadState::Running);
+        return;
+    }
+
+    let next_tcb = &mut *next;
+    next_tcb.set_state(crate::objects::ThreadState::Running);
+    set_current_thread(next);
+    *tf = *next_tcb.context();
+}
+
 /// Block the current thread
 ///
 /// Removes the current thread from the ready queue and yields to another thread.
@@ -322,8 +487,9 @@ pub unsafe fn unblock(tcb: *mut TCB) {
 
     // Check if we should preempt current thread
     // If unblocked thread has higher priority than current, should reschedule
+    // (threads of an inactive domain wait for their window)
     let current = current_thread();
-    if !current.is_null() {
+    if !current.is_null() && in_current_domain(tcb) {
         let current_priority = (*current).priority();
         let unblocked_priority = tcb_ref.priority();
 
@@ -383,8 +549,65 @@ pub unsafe fn set_priority(tcb: *mut TCB, priority: u8) {
 
         // Lower priority number = higher priority
         // If modified thread now has higher priority than current, preempt
-        if priority < current_priority && tcb_ref.state() == crate::objects::ThreadState::Runnable {
+        if priority < current_priority
+            && tcb_ref.state() == crate::objects::ThreadState::Runnable
+            && in_current_domain(tcb)
+        {
             yield_current();
         }
     }
 }
+
+/// Lend `priority` to `tcb` until it signals `notification`
+///
+/// Priority inheritance for notification-based channels: a thread about
+/// to wait for `tcb`'s signal lends it its priority, so threads ranked
+/// between the two cannot keep `tcb` (and with it the waiter) from
+/// running. Only ever raises the priority. A thread lent priority through
+/// several notifications keeps the highest, and drops back to its own at
+/// the signal of the latest.
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - tcb must be valid
+pub unsafe fn donate_priority(tcb: *mut TCB, priority: u8, notification: usize) {
+    if tcb.is_null() {
+        return;
+    }
+
+    let tcb_ref = &mut *tcb;
+
+    // Lower priority number = higher priority
+    if priority >= tcb_ref.priority() {
+        return;
+    }
+
+    let base_priority = match tcb_ref.donation() {
+        Some(donation) => donation.base_priority,
+        None => tcb_ref.priority(),
+    };
+    tcb_ref.set_donation(Some(crate::objects::Donation { base_priority, notification }));
+    set_priority(tcb, priority);
+}
+
+/// End the priority `tcb` was lent until it signals `notification`, if
+/// that is the donation it holds
+///
+/// # Safety
+///
+/// - Scheduler must be initialized
+/// - tcb must be valid
+pub unsafe fn end_donation(tcb: *mut TCB, notification: usize) {
+    if tcb.is_null() {
+        return;
+    }
+
+    let tcb_ref = &mut *tcb;
+    if let Some(donation) = tcb_ref.donation() {
+        if donation.notification == notification {
+            tcb_ref.set_donation(None);
+            set_priority(tcb, donation.base_priority);
+        }
+    }
+}
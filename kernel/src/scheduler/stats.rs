@@ -0,0 +1,275 @@
+//! Per-Thread CPU Accounting
+//!
+//! Every change of the current thread goes through `scheduler::set_current`,
+//! which charges the time since the previous switch to the outgoing thread:
+//! generic timer ticks (`CNTPCT_EL0`), and cycles and retired instructions
+//! from the PMU where there is one (`arch::aarch64::pmu`). Time spent in the
+//! kernel on a thread's behalf (syscalls, its interrupts) is charged to it.
+//!
+//! Live threads are also listed in a small registry so that
+//! `SYS_THREAD_STATS` can enumerate them without a TCB capability.
+
+use crate::arch::aarch64::pmu;
+use crate::objects::{ThreadState, TCB};
+
+/// Maximum number of threads listed by `SYS_THREAD_STATS`
+pub const MAX_THREADS: usize = 64;
+
+/// CPU time consumed by a thread
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuUsage {
+    /// Generic timer ticks spent running
+    pub time: u64,
+    /// CPU cycles (0 without a PMU)
+    pub cycles: u64,
+    /// Retired instructions (0 without a PMU)
+    pub instructions: u64,
+    /// Number of times the thread was switched to
+    pub switches: u64,
+}
+
+/// Thread statistics as returned to userspace (little-endian, 9 × u64)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadStats {
+    /// Thread ID (the TCB address for processes)
+    pub tid: u64,
+    /// Scheduling priority (0 = highest)
+    pub priority: u64,
+    /// `STATE_*` code
+    pub state: u64,
+    /// CPU time in microseconds
+    pub cpu_time_us: u64,
+    /// CPU cycles (0 without a PMU)
+    pub cycles: u64,
+    /// Retired instructions (0 without a PMU)
+    pub instructions: u64,
+    /// Number of times the thread was switched to
+    pub switches: u64,
+    /// Capability bitmask
+    pub capabilities: u64,
+    /// TID of the thread's process: its own for a main thread
+    pub process: u64,
+}
+
+impl ThreadStats {
+    /// Size of the encoded stats in bytes
+    pub const SIZE: usize = 9 * 8;
+
+    /// Smallest buffer `SYS_THREAD_STATS` fills: the record before
+    /// `process` was added, which it is cut down to
+    pub const MIN_SIZE: usize = 8 * 8;
+
+    /// Encode the stats as they are delivered to userspace
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        let words = [
+            self.tid, self.priority, self.state, self.cpu_time_us,
+            self.cycles, self.instructions, self.switches, self.capabilities,
+            self.process,
+        ];
+        for (chunk, word) in bytes.as_chunks_mut::<8>().0.iter_mut().zip(words) {
+            *chunk = word.to_le_bytes();
+        }
+        bytes
+    }
+}
+
+// `ThreadStats::state` codes
+pub const STATE_INACTIVE: u64 = 0;
+pub const STATE_RUNNING: u64 = 1;
+pub const STATE_RUNNABLE: u64 = 2;
+pub const STATE_BLOCKED_RECV: u64 = 3;
+pub const STATE_BLOCKED_SEND: u64 = 4;
+pub const STATE_BLOCKED_REPLY: u64 = 5;
+pub const STATE_BLOCKED_NOTIFICATION: u64 = 6;
+pub const STATE_BLOCKED_FAULT: u64 = 7;
+
+/// Map a thread state to its `STATE_*` code
+pub fn state_code(state: ThreadState) -> u64 {
+    match state {
+        ThreadState::Inactive => STATE_INACTIVE,
+        ThreadState::Running => STATE_RUNNING,
+        ThreadState::Runnable => STATE_RUNNABLE,
+        ThreadState::BlockedOnReceive { .. } => STATE_BLOCKED_RECV,
+        ThreadState::BlockedOnSend { .. } => STATE_BLOCKED_SEND,
+        ThreadState::BlockedOnReply => STATE_BLOCKED_REPLY,
+        ThreadState::BlockedOnNotification { .. } => STATE_BLOCKED_NOTIFICATION,
+        ThreadState::BlockedOnFault { .. } => STATE_BLOCKED_FAULT,
+    }
+}
+
+/// Short name of a `STATE_*` code
+pub fn state_name(code: u64) -> &'static str {
+    match code {
+        STATE_INACTIVE => "Inactive",
+        STATE_RUNNING => "Running",
+        STATE_RUNNABLE => "Runnable",
+        STATE_BLOCKED_RECV => "BlockedRecv",
+        STATE_BLOCKED_SEND => "BlockedSend",
+        STATE_BLOCKED_REPLY => "BlockedReply",
+        STATE_BLOCKED_NOTIFICATION => "BlockedNotif",
+        STATE_BLOCKED_FAULT => "BlockedFault",
+        _ => "Unknown",
+    }
+}
+
+/// Counter readings at the last switch
+#[derive(Clone, Copy)]
+struct Stamp {
+    time: u64,
+    cycles: u64,
+    instructions: u32,
+}
+
+impl Stamp {
+    fn now() -> Self {
+        Self {
+            time: crate::arch::aarch64::timer::read_counter(),
+            cycles: pmu::read_cycles(),
+            instructions: pmu::read_instructions(),
+        }
+    }
+
+    /// Usage between `self` and `now`
+    fn elapsed(self, now: &Stamp) -> CpuUsage {
+        CpuUsage {
+            time: now.time.wrapping_sub(self.time),
+            cycles: now.cycles.wrapping_sub(self.cycles),
+            instructions: now.instructions.wrapping_sub(self.instructions) as u64,
+            switches: 0,
+        }
+    }
+}
+
+/// Counters at the last switch
+static mut LAST_SWITCH: Stamp = Stamp { time: 0, cycles: 0, instructions: 0 };
+
+/// Live threads
+static mut THREADS: [*mut TCB; MAX_THREADS] = [core::ptr::null_mut(); MAX_THREADS];
+
+/// Start accounting
+///
+/// # Safety
+///
+/// Must be called once during scheduler initialization.
+pub unsafe fn init(idle: *mut TCB) {
+    if pmu::init() {
+        crate::kprintln!("[sched] CPU accounting: timer, PMU cycles and instructions");
+    } else {
+        crate::kprintln!("[sched] CPU accounting: timer only (no PMU)");
+    }
+    LAST_SWITCH = Stamp::now();
+    register(idle);
+}
+
+/// Charge the time since the last switch to `prev` and start timing `next`
+///
+/// # Safety
+///
+/// `prev` and `next` must be valid TCB pointers or null.
+pub unsafe fn account_switch(prev: *mut TCB, next: *mut TCB) {
+    let now = Stamp::now();
+    let used = LAST_SWITCH.elapsed(&now);
+    LAST_SWITCH = now;
+
+    if !prev.is_null() {
+        (*prev).charge_cpu(&used);
+    }
+    if !next.is_null() && next != prev {
+        (*next).cpu_usage_mut().switches += 1;
+    }
+}
+
+/// Add a thread to the registry
+///
+/// Returns false if the registry is full; the thread still runs but is
+/// not listed.
+///
+/// # Safety
+///
+/// `tcb` must stay valid until it is unregistered.
+pub unsafe fn register(tcb: *mut TCB) -> bool {
+    let threads = &mut *core::ptr::addr_of_mut!(THREADS);
+    match threads.iter_mut().find(|slot| slot.is_null()) {
+        Some(slot) => {
+            *slot = tcb;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove a thread from the registry
+///
+/// # Safety
+///
+/// Must not race with `register` or `thread_stats`.
+pub unsafe fn unregister(tcb: *mut TCB) {
+    let threads = &mut *core::ptr::addr_of_mut!(THREADS);
+    if let Some(slot) = threads.iter_mut().find(|slot| **slot == tcb) {
+        *slot = core::ptr::null_mut();
+    }
+}
+
+/// Statistics of the `index`-th live thread
+///
+/// The running thread is also charged the time since the last switch.
+/// Returns None once `index` is past the last thread.
+///
+/// # Safety
+///
+/// All registered TCB pointers must be valid.
+pub unsafe fn thread_stats(index: usize) -> Option<ThreadStats> {
+    let threads = &*core::ptr::addr_of!(THREADS);
+    let tcb = threads.iter().copied().filter(|tcb| !tcb.is_null()).nth(index)?;
+    let thread = &*tcb;
+
+    let mut usage = *thread.cpu_usage();
+    if tcb == super::current_thread() {
+        let running = LAST_SWITCH.elapsed(&Stamp::now());
+        usage.time += running.time;
+        usage.cycles += running.cycles;
+        usage.instructions += running.instructions;
+    }
+
+    let freq = super::timer::timer_frequency();
+    let cpu_time_us = if freq == 0 { 0 } else { (usage.time as u128 * 1_000_000 / freq as u128) as u64 };
+
+    Some(ThreadStats {
+        tid: thread.tid() as u64,
+        priority: thread.priority() as u64,
+        state: state_code(thread.state()),
+        cpu_time_us,
+        cycles: usage.cycles,
+        instructions: usage.instructions,
+        switches: usage.switches,
+        capabilities: thread.capabilities(),
+        process: (*thread.process()).tid() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_wraps_instruction_counter() {
+        let before = Stamp { time: 100, cycles: 1_000, instructions: u32::MAX - 9 };
+        let after = Stamp { time: 150, cycles: 4_000, instructions: 10 };
+        let used = before.elapsed(&after);
+        assert_eq!(used.time, 50);
+        assert_eq!(used.cycles, 3_000);
+        assert_eq!(used.instructions, 20);
+    }
+
+    #[test]
+    fn stats_encoding() {
+        let stats = ThreadStats { tid: 7, state: STATE_RUNNABLE, cpu_time_us: 1234, process: 5, ..Default::default() };
+        let bytes = stats.to_bytes();
+        assert_eq!(bytes.len(), ThreadStats::SIZE);
+        assert_eq!(&bytes[16..24], &STATE_RUNNABLE.to_le_bytes());
+        assert_eq!(&bytes[24..32], &1234u64.to_le_bytes());
+        assert_eq!(&bytes[64..72], &5u64.to_le_bytes());
+    }
+}
@@ -1,48 +1,58 @@
 //! Timer-Based Preemption
 //!
-//! This module implements time-slicing preemption using the ARM Generic Timer.
-//! It configures the timer to fire periodic interrupts, allowing the scheduler
-//! to preempt long-running threads and enforce fairness.
+//! This module implements time-slicing preemption on top of the ARM Generic
+//! Timer (`arch::aarch64::timer`). The timer fires `TIMER_TICK_HZ` times per
+//! second (set per platform in `build-config.toml`), and every tick is
+//! charged to the running thread, so a component that never yields cannot
+//! starve the rest of the system.
 //!
 //! ## ARM Generic Timer
 //!
 //! ARM provides a Generic Timer that can generate periodic interrupts:
-//! - EL1 Physical Timer (used for kernel scheduling)
-//! - EL1 Virtual Timer (for virtualization)
+//! - EL1 Physical Timer
+//! - EL1 Virtual Timer (used for kernel scheduling)
 //! - EL0 Physical/Virtual Timers (for userspace)
 //!
-//! We use the **EL1 Physical Timer** for scheduling interrupts.
+//! We use the **EL1 Virtual Timer** for scheduling interrupts.
 //!
 //! ## Timer Registers
 //!
 //! - `CNTFRQ_EL0`: Timer frequency (Hz)
-//! - `CNTP_TVAL_EL0`: Timer value (counts down to 0)
-//! - `CNTP_CTL_EL0`: Timer control (enable/disable, interrupt status)
+//! - `CNTV_TVAL_EL0`: Timer value (counts down to 0)
+//! - `CNTV_CTL_EL0`: Timer control (enable/disable, interrupt status)
 //! - `CNTPCT_EL0`: Current counter value
 //!
 //! ## Preemption Strategy
 //!
-//! 1. Configure timer to fire every TIMESLICE_MS milliseconds
-//! 2. On timer interrupt:
-//!    - Decrement current thread's timeslice
-//!    - If timeslice == 0:
-//!      - Reset timeslice
-//!      - Call yield_current() to switch threads
-//! 3. Higher-priority threads always preempt lower-priority ones
-
-use core::arch::asm;
-
-/// Timeslice duration in milliseconds
+//! 1. Configure the timer to fire every `TICK_MS` milliseconds
+//! 2. On a timer interrupt from userspace:
+//!    - Decrement the current thread's timeslice
+//!    - If the timeslice is used up, refill it and rotate the thread to the
+//!      tail of its priority queue (round-robin within a priority)
+//!    - If a higher-priority thread is runnable, switch to it right away
+//! 3. Every tick also counts against the current domain window (see
+//!    `scheduler::domain`); when it ends, the next domain becomes active
+//!    and a thread outside it is preempted
+//! 4. Expired timer objects (`objects::timer`) signal their notifications
+//!    before the tick is charged, so a thread they wake can preempt
+//! 5. Ticks taken while the kernel runs are only charged; the kernel is not
+//!    preemptible, so the switch happens on the next tick from userspace
+//!    or at the next scheduling point
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::arch::aarch64::timer as generic_timer;
+use crate::generated::memory_config::TIMER_TICK_HZ;
+
+/// Tick period in milliseconds
 ///
-/// Each thread gets this much CPU time before being preempted.
-/// Typical values: 1-10ms
-pub const TIMESLICE_MS: u32 = 5;
+/// Each thread runs for `TCB::DEFAULT_TIME_SLICE` ticks before it is
+/// preempted. Typical values: 1-10ms
+pub const TICK_MS: u32 = (1000 / TIMER_TICK_HZ) as u32;
 
-/// Timeslice in timer ticks
+/// Timer reload value (counter cycles per tick)
 ///
-/// This is calculated based on timer frequency and TIMESLICE_MS.
-/// Will be initialized at boot.
-static mut TIMESLICE_TICKS: u64 = 0;
+/// Calculated from the timer frequency and `TIMER_TICK_HZ` at boot.
+static mut RELOAD_TICKS: u64 = 0;
 
 /// Timer frequency in Hz
 ///
@@ -59,18 +69,14 @@ static mut TIMER_FREQ_HZ: u64 = 0;
 /// - Must be called with interrupts disabled
 /// - IRQ handler must be set up before enabling timer
 pub unsafe fn init() {
-    // Read timer frequency from CNTFRQ_EL0
-    let freq: u64;
-    asm!("mrs {}, cntfrq_el0", out(reg) freq);
+    let freq = generic_timer::frequency();
+    let reload = freq / TIMER_TICK_HZ;
     TIMER_FREQ_HZ = freq;
-
-    // Calculate timeslice in ticks
-    // timeslice_ticks = (freq_hz * timeslice_ms) / 1000
-    TIMESLICE_TICKS = (freq * (TIMESLICE_MS as u64)) / 1000;
+    RELOAD_TICKS = reload;
 
     crate::kprintln!("[timer] Timer frequency: {} Hz", freq);
-    crate::kprintln!("[timer] Timeslice: {} ms ({} ticks)",
-                     TIMESLICE_MS, TIMESLICE_TICKS);
+    crate::kprintln!("[timer] Tick: {} Hz ({} ms, {} cycles), timeslice {} ticks",
+                     TIMER_TICK_HZ, TICK_MS, reload, crate::objects::TCB::DEFAULT_TIME_SLICE);
 
     // Enable timer
     start_timer();
@@ -78,102 +84,104 @@ pub unsafe fn init() {
 
 /// Start the preemption timer
 ///
-/// Configures the timer to fire after TIMESLICE_TICKS.
+/// Configures the timer to fire after one tick period.
 ///
 /// # Safety
 ///
 /// - Timer must be initialized (init() called)
 pub unsafe fn start_timer() {
-    // Set timer value (counts down from this value)
-    // Use VIRTUAL timer (cntv) instead of physical (cntp) so it fires at EL0
-    asm!(
-        "msr cntv_tval_el0, {}",
-        in(reg) TIMESLICE_TICKS
-    );
-
-    // Enable timer and unmask interrupt
-    // CNTV_CTL_EL0: bit 0 = enable, bit 1 = imask (0 = not masked)
-    asm!(
-        "msr cntv_ctl_el0, {val}",
-        val = in(reg) 0b01u64  // Enable=1, IMask=0
-    );
+    generic_timer::arm(RELOAD_TICKS);
 }
 
 /// Stop the preemption timer
 ///
 /// Disables the timer interrupt.
 pub unsafe fn stop_timer() {
-    // Disable timer (clear enable bit)
-    asm!(
-        "msr cntv_ctl_el0, {val}",
-        val = in(reg) 0b00u64  // Enable=0
-    );
+    generic_timer::disable();
 }
 
-/// Timer interrupt handler
+/// Charge one tick to the current thread
 ///
-/// Called when the timer fires. This is invoked from the IRQ exception handler.
-///
-/// # Safety
-///
-/// - Must be called from IRQ exception context
-/// - Scheduler must be initialized
-pub unsafe fn timer_tick() {
-    // Acknowledge timer interrupt by reloading the timer value
-    start_timer();
+/// Advances the domain schedule, then returns true if the thread should give
+/// up the CPU: its domain is no longer active, its timeslice is used up, or
+/// a higher-priority thread is waiting.
+unsafe fn charge_tick() -> bool {
+    if let Some(domain) = super::domain::tick() {
+        crate::scheduler::switch_domain(domain);
+    }
 
-    // Get current thread
     let current = crate::scheduler::current_thread();
     if current.is_null() {
-        return; // No current thread (shouldn't happen)
+        return false; // No current thread (shouldn't happen)
     }
 
-    let current_tcb = &mut *current;
-
-    // Decrement timeslice
-    let timeslice = current_tcb.time_slice();
-
-    if timeslice > 0 {
-        current_tcb.set_time_slice(timeslice - 1);
+    if !crate::scheduler::in_current_domain(current) {
+        return true;
     }
 
-    // Check the NEW value after decrementing
-    let new_timeslice = current_tcb.time_slice();
+    let current_tcb = &mut *current;
+    if current_tcb.tick() {
+        return true;
+    }
 
-    // If timeslice expired, preempt
-    if new_timeslice == 0 {
-        // Reset timeslice for next run
-        current_tcb.refill_time_slice();
+    crate::scheduler::higher_priority_ready(current_tcb.priority())
+}
 
-        // crate::kprintln!("[timer] Timeslice expired for TCB {}, preempting",
-        //                  current_tcb.tid());
+/// Timer interrupt taken from userspace
+///
+/// Called from the lower-EL IRQ handler with the interrupted thread's trap
+/// frame. On preemption the frame is replaced with the next thread's
+/// context, and the exception return resumes that thread instead.
+///
+/// # Safety
+///
+/// - Must be called from IRQ exception context
+/// - Scheduler must be initialized
+pub unsafe fn timer_tick(tf: &mut TrapFrame) {
+    // Acknowledge timer interrupt by reloading the timer value
+    start_timer();
+    super::watchdog::tick(Some(tf));
+    crate::objects::timer::tick();
 
-        // Preempt current thread
-        crate::scheduler::yield_current();
+    if charge_tick() {
+        crate::ksched_debug!("[timer] Preempting TCB {}", (*crate::scheduler::current_thread()).tid());
+        crate::scheduler::preempt_current(tf);
     }
 }
 
+/// Timer interrupt taken while the kernel was running
+///
+/// The tick is charged but the kernel is never preempted; an exhausted
+/// timeslice stays at zero and takes effect on the next userspace tick.
+///
+/// # Safety
+///
+/// - Must be called from IRQ exception context
+/// - Scheduler must be initialized
+pub unsafe fn kernel_tick() {
+    start_timer();
+    super::watchdog::tick(None);
+    crate::objects::timer::tick();
+    charge_tick();
+}
+
 /// Get timer frequency in Hz
 #[inline]
 pub fn timer_frequency() -> u64 {
     unsafe { TIMER_FREQ_HZ }
 }
 
-/// Get timeslice in ticks
+/// Get the timer reload value (counter cycles per tick)
 #[inline]
-pub fn timeslice_ticks() -> u64 {
-    unsafe { TIMESLICE_TICKS }
+pub fn reload_ticks() -> u64 {
+    unsafe { RELOAD_TICKS }
 }
 
 /// Read current timer counter value
 ///
 /// Returns the current value of the physical counter.
 pub fn read_counter() -> u64 {
-    let counter: u64;
-    unsafe {
-        asm!("mrs {}, cntpct_el0", out(reg) counter);
-    }
-    counter
+    generic_timer::read_counter()
 }
 
 /// Get elapsed time since last call (in microseconds)
@@ -194,7 +202,7 @@ mod tests {
 
     #[test]
     fn test_timer_constants() {
-        assert!(TIMESLICE_MS > 0);
-        assert!(TIMESLICE_MS <= 100); // Reasonable range
+        assert!(TICK_MS > 0);
+        assert!(TICK_MS <= 100); // Reasonable range
     }
 }
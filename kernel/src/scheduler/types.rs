@@ -4,6 +4,7 @@
 
 use crate::objects::TCB;
 use core::ptr;
+use super::domain::NUM_DOMAINS;
 
 /// Number of priority levels (0 = highest, 255 = lowest)
 pub const NUM_PRIORITIES: usize = 256;
@@ -11,7 +12,7 @@ pub const NUM_PRIORITIES: usize = 256;
 /// Scheduler - manages runnable threads
 ///
 /// The scheduler maintains per-priority ready queues and selects
-/// the highest-priority thread to run next.
+/// the highest-priority thread of the active domain to run next.
 pub struct Scheduler {
     /// Ready queues per priority level
     /// Index 0 = highest priority, 255 = lowest
@@ -23,15 +24,24 @@ pub struct Scheduler {
     /// Idle thread (runs when nothing else is ready)
     idle: *mut TCB,
 
-    /// Priority bitmap for O(1) lookup
+    /// Priority bitmap for O(1) lookup, one per domain
     ///
-    /// Each bit represents whether that priority level has runnable threads.
-    /// Divided into 4 x u64 = 256 bits total.
-    /// priority_bitmap[0] covers priorities 0-63
-    /// priority_bitmap[1] covers priorities 64-127
-    /// priority_bitmap[2] covers priorities 128-191
-    /// priority_bitmap[3] covers priorities 192-255
-    priority_bitmap: [u64; 4],
+    /// Each bit represents whether that priority level has runnable threads
+    /// in the domain. Divided into 4 x u64 = 256 bits total.
+    /// priority_bitmap[d][0] covers priorities 0-63
+    /// priority_bitmap[d][1] covers priorities 64-127
+    /// priority_bitmap[d][2] covers priorities 128-191
+    /// priority_bitmap[d][3] covers priorities 192-255
+    priority_bitmap: [[u64; 4]; NUM_DOMAINS],
+
+    /// Runnable threads per domain and priority level
+    ///
+    /// The ready queues are shared by all domains; these counts keep the
+    /// per-domain bitmaps exact.
+    ready_count: [[u8; NUM_PRIORITIES]; NUM_DOMAINS],
+
+    /// Domain whose threads are currently scheduled
+    domain: u8,
 }
 
 impl Scheduler {
@@ -45,7 +55,9 @@ impl Scheduler {
             ready_queues: [ThreadQueue::new(); NUM_PRIORITIES],
             current: idle_tcb,
             idle: idle_tcb,
-            priority_bitmap: [0; 4],
+            priority_bitmap: [[0; 4]; NUM_DOMAINS],
+            ready_count: [[0; NUM_PRIORITIES]; NUM_DOMAINS],
+            domain: 0,
         }
     }
 
@@ -61,6 +73,35 @@ impl Scheduler {
         self.current = tcb;
     }
 
+    /// Get the idle thread
+    #[inline]
+    pub fn idle(&self) -> *mut TCB {
+        self.idle
+    }
+
+    /// Get the active domain
+    #[inline]
+    pub fn domain(&self) -> u8 {
+        self.domain
+    }
+
+    /// Switch the active domain
+    #[inline]
+    pub fn set_domain(&mut self, domain: u8) {
+        if (domain as usize) < NUM_DOMAINS {
+            self.domain = domain;
+        }
+    }
+
+    /// Whether `tcb` may run in the active domain (the idle thread always can)
+    ///
+    /// # Safety
+    ///
+    /// - tcb must be valid
+    pub unsafe fn in_active_domain(&self, tcb: *mut TCB) -> bool {
+        tcb == self.idle || (*tcb).domain() == self.domain
+    }
+
     /// Add thread to ready queue
     ///
     /// # Safety
@@ -79,11 +120,19 @@ impl Scheduler {
             return; // Invalid priority
         }
 
+        let domain = (*tcb).domain() as usize;
+        if domain >= NUM_DOMAINS {
+            return;
+        }
+
         // Add to priority queue
-        self.ready_queues[priority].enqueue(tcb);
+        if !self.ready_queues[priority].enqueue(tcb) {
+            return;
+        }
 
-        // Set bit in bitmap
-        self.set_priority_bit(priority as u8);
+        // Set bit in the domain's bitmap
+        self.ready_count[domain][priority] += 1;
+        self.set_priority_bit(domain, priority as u8);
     }
 
     /// Remove thread from ready queue
@@ -102,27 +151,36 @@ impl Scheduler {
         }
 
         // Remove from priority queue
-        self.ready_queues[priority].dequeue(tcb);
+        if self.ready_queues[priority].dequeue(tcb) {
+            self.ready_removed((*tcb).domain() as usize, priority);
+        }
+    }
 
-        // Clear bit in bitmap if queue now empty
-        if self.ready_queues[priority].is_empty() {
-            self.clear_priority_bit(priority as u8);
+    /// Account for a thread of `domain` leaving the `priority` queue
+    fn ready_removed(&mut self, domain: usize, priority: usize) {
+        if domain >= NUM_DOMAINS {
+            return;
+        }
+        self.ready_count[domain][priority] = self.ready_count[domain][priority].saturating_sub(1);
+
+        // Clear bit in bitmap if the domain has nothing left at this priority
+        if self.ready_count[domain][priority] == 0 {
+            self.clear_priority_bit(domain, priority as u8);
         }
     }
 
     /// Pick the next thread to run
     ///
-    /// Returns the highest-priority runnable thread, or the idle thread
-    /// if no threads are ready.
+    /// Returns the highest-priority runnable thread of the active domain,
+    /// or the idle thread if none is ready. Threads of other domains stay
+    /// queued until their domain's window.
     pub unsafe fn schedule(&mut self) -> *mut TCB {
         // Find highest priority with runnable threads
         if let Some(priority) = self.find_highest_priority() {
-            // Dequeue from that priority level
-            if let Some(tcb) = self.ready_queues[priority as usize].dequeue_head() {
-                // Update bitmap if queue now empty
-                if self.ready_queues[priority as usize].is_empty() {
-                    self.clear_priority_bit(priority);
-                }
+            // Dequeue the first thread of the domain at that priority level
+            let domain = self.domain;
+            if let Some(tcb) = self.ready_queues[priority as usize].dequeue_first_in(domain) {
+                self.ready_removed(domain as usize, priority as usize);
                 return tcb;
             }
         }
@@ -131,12 +189,18 @@ impl Scheduler {
         self.idle
     }
 
-    /// Find the highest priority level with runnable threads
+    /// Highest priority level with runnable threads in the active domain, if any
+    #[inline]
+    pub fn highest_ready_priority(&self) -> Option<u8> {
+        self.find_highest_priority()
+    }
+
+    /// Find the highest priority level with runnable threads in the active domain
     ///
     /// Returns None if no threads are ready.
     fn find_highest_priority(&self) -> Option<u8> {
         // Check each u64 in the bitmap (highest priority first)
-        for (chunk_idx, &chunk) in self.priority_bitmap.iter().enumerate() {
+        for (chunk_idx, &chunk) in self.priority_bitmap[self.domain as usize].iter().enumerate() {
             if chunk != 0 {
                 // Found non-empty chunk, find highest bit (lowest priority number)
                 let leading_zeros = chunk.leading_zeros() as usize;
@@ -155,20 +219,20 @@ impl Scheduler {
         None
     }
 
-    /// Set a bit in the priority bitmap
-    fn set_priority_bit(&mut self, priority: u8) {
+    /// Set a bit in a domain's priority bitmap
+    fn set_priority_bit(&mut self, domain: usize, priority: u8) {
         let priority = priority as usize;
         let chunk_idx = priority / 64;
         let bit_idx = 63 - (priority % 64); // Reverse bit order for leading_zeros
-        self.priority_bitmap[chunk_idx] |= 1u64 << bit_idx;
+        self.priority_bitmap[domain][chunk_idx] |= 1u64 << bit_idx;
     }
 
-    /// Clear a bit in the priority bitmap
-    fn clear_priority_bit(&mut self, priority: u8) {
+    /// Clear a bit in a domain's priority bitmap
+    fn clear_priority_bit(&mut self, domain: usize, priority: u8) {
         let priority = priority as usize;
         let chunk_idx = priority / 64;
         let bit_idx = 63 - (priority % 64); // Reverse bit order for leading_zeros
-        self.priority_bitmap[chunk_idx] &= !(1u64 << bit_idx);
+        self.priority_bitmap[domain][chunk_idx] &= !(1u64 << bit_idx);
     }
 }
 
@@ -221,15 +285,18 @@ impl ThreadQueue {
     ///
     /// - tcb must be valid
     /// - Thread must not already be in the queue
-    pub unsafe fn enqueue(&mut self, tcb: *mut TCB) {
+    ///
+    /// Returns false if the queue is full.
+    pub unsafe fn enqueue(&mut self, tcb: *mut TCB) -> bool {
         if self.count >= MAX_QUEUE_SIZE {
             // Queue full (shouldn't happen with reasonable thread counts)
             crate::ksched_debug!("[sched] WARNING: Thread queue full, dropping enqueue");
-            return;
+            return false;
         }
 
         self.threads[self.count] = tcb;
         self.count += 1;
+        true
     }
 
     /// Remove thread from queue
@@ -275,6 +342,20 @@ impl ThreadQueue {
 
         Some(head)
     }
+
+    /// Dequeue the first thread belonging to `domain`
+    ///
+    /// Threads of other domains keep their place in the queue.
+    ///
+    /// # Safety
+    ///
+    /// - All queued TCBs must be valid
+    pub unsafe fn dequeue_first_in(&mut self, domain: u8) -> Option<*mut TCB> {
+        let index = self.threads[..self.count].iter().position(|&t| (*t).domain() == domain)?;
+        let tcb = self.threads[index];
+        self.dequeue(tcb);
+        Some(tcb)
+    }
 }
 
 /// Scheduler errors
@@ -0,0 +1,186 @@
+//! Hang Detection Watchdog
+//!
+//! Userspace (normally the root task) arms the watchdog with
+//! `SYS_WATCHDOG_KICK` and must then keep kicking it within the timeout.
+//! Every timer tick checks how long ago the last kick was; once the
+//! timeout has passed, the kernel prints a diagnostic dump (the
+//! interrupted context and every thread's state and CPU time) and resets
+//! the system through PSCI, instead of sitting silently frozen.
+//!
+//! The timer tick cannot catch a kernel that stopped taking interrupts.
+//! For that, a hardware watchdog (`components::watchdog`, selected with a
+//! `watchdog-*` feature) is started with twice the timeout and refreshed
+//! from the tick only while the software watchdog is satisfied, so it
+//! bites when the tick itself stops.
+//!
+//! The watchdog stays disarmed until the first kick; systems that never
+//! kick it are unaffected.
+
+use crate::arch::aarch64::context::TrapFrame;
+use super::stats;
+
+/// Timeout used when the first kick does not give one
+pub const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Shortest accepted timeout (a few scheduler ticks)
+pub const MIN_TIMEOUT_MS: u64 = 100;
+
+/// PSCI SYSTEM_RESET function ID
+const PSCI_SYSTEM_RESET: u64 = 0x8400_0009;
+
+/// Timeout in milliseconds (0 = disarmed)
+static mut TIMEOUT_MS: u64 = 0;
+
+/// Counter value at the last kick
+static mut LAST_KICK: u64 = 0;
+
+/// Set while the dump is being printed, so a tick taken meanwhile does
+/// not start another one
+static mut BITTEN: bool = false;
+
+/// Report the watchdog configuration
+pub fn init() {
+    match crate::config::watchdog() {
+        Some(hw) => crate::kprintln!("[watchdog] Hang detection: timer tick + {}", hw.name()),
+        None => crate::kprintln!("[watchdog] Hang detection: timer tick only (no hardware watchdog)"),
+    }
+}
+
+/// Whether `timeout_ms` is an acceptable timeout (0 keeps the current one)
+pub fn valid_timeout(timeout_ms: u64) -> bool {
+    timeout_ms == 0 || timeout_ms >= MIN_TIMEOUT_MS
+}
+
+/// Whether more than `timeout_ticks` counter ticks passed between `last_kick` and `now`
+fn expired(last_kick: u64, now: u64, timeout_ticks: u64) -> bool {
+    now.wrapping_sub(last_kick) > timeout_ticks
+}
+
+/// Convert milliseconds to counter ticks
+fn ms_to_ticks(ms: u64) -> u64 {
+    (super::timer::timer_frequency() as u128 * ms as u128 / 1000) as u64
+}
+
+/// Arm or kick the watchdog
+///
+/// `timeout_ms` of 0 keeps the current timeout (`DEFAULT_TIMEOUT_MS` on
+/// the first kick); anything else must be at least `MIN_TIMEOUT_MS` and
+/// replaces it. Returns false for a timeout that is too short.
+///
+/// # Safety
+///
+/// Must not race with `tick` (called with IRQs masked, from a syscall).
+pub unsafe fn kick(timeout_ms: u64) -> bool {
+    if !valid_timeout(timeout_ms) {
+        return false;
+    }
+
+    let timeout_ms = match (timeout_ms, TIMEOUT_MS) {
+        (0, 0) => DEFAULT_TIMEOUT_MS,
+        (0, current) => current,
+        (new, _) => new,
+    };
+
+    LAST_KICK = super::timer::read_counter();
+    if timeout_ms != TIMEOUT_MS {
+        if TIMEOUT_MS == 0 {
+            crate::kprintln!("[watchdog] Armed, timeout {} ms", timeout_ms);
+        }
+        TIMEOUT_MS = timeout_ms;
+        if let Some(hw) = crate::config::watchdog() {
+            hw.start(timeout_ms * 2);
+        }
+    }
+    true
+}
+
+/// Check the watchdog on a timer tick
+///
+/// `tf` is the interrupted userspace context, or None if the tick
+/// interrupted the kernel.
+///
+/// # Safety
+///
+/// Must be called from the timer interrupt.
+pub unsafe fn tick(tf: Option<&TrapFrame>) {
+    if TIMEOUT_MS == 0 || BITTEN {
+        return;
+    }
+
+    let now = super::timer::read_counter();
+    if expired(LAST_KICK, now, ms_to_ticks(TIMEOUT_MS)) {
+        bite(tf, now);
+    }
+
+    if let Some(hw) = crate::config::watchdog() {
+        hw.kick();
+    }
+}
+
+/// Print the diagnostic dump and reset the system
+unsafe fn bite(tf: Option<&TrapFrame>, now: u64) -> ! {
+    BITTEN = true;
+
+    let freq = super::timer::timer_frequency().max(1);
+    let silent_ms = now.wrapping_sub(LAST_KICK) as u128 * 1000 / freq as u128;
+    let timeout_ms = TIMEOUT_MS;
+
+    crate::kprintln!("");
+    crate::kprintln!("[watchdog] ========== WATCHDOG TIMEOUT ==========");
+    crate::kprintln!("[watchdog] No kick for {} ms (timeout {} ms)", silent_ms, timeout_ms);
+
+    let current = super::current_thread();
+    let tid = if current.is_null() { 0 } else { (*current).tid() };
+    match tf {
+        Some(tf) => crate::kprintln!("[watchdog] Interrupted TID {:#x} at PC {:#x}, SP {:#x}, LR {:#x}",
+                                     tid, tf.elr_el1, tf.sp_el0, tf.x30),
+        None => crate::kprintln!("[watchdog] Interrupted the kernel (current TID {:#x})", tid),
+    }
+
+    crate::kprintln!("[watchdog] Threads:");
+    crate::kprintln!("[watchdog]   {:>18}  {:>4}  {:<12}  {:>12}  {:>8}", "TID", "PRIO", "STATE", "CPU (us)", "SWITCHES");
+    let mut index = 0;
+    while let Some(thread) = stats::thread_stats(index) {
+        crate::kprintln!("[watchdog]   {:>#18x}  {:>4}  {:<12}  {:>12}  {:>8}",
+                         thread.tid, thread.priority, stats::state_name(thread.state),
+                         thread.cpu_time_us, thread.switches);
+        index += 1;
+    }
+
+    crate::kprintln!("[watchdog] Resetting system");
+    reset()
+}
+
+/// Reset through PSCI; spin if the firmware does not
+fn reset() -> ! {
+    unsafe {
+        core::arch::asm!(
+            "hvc #0",
+            inout("x0") PSCI_SYSTEM_RESET => _,
+            options(nomem, nostack),
+            clobber_abi("C"),
+        );
+    }
+
+    // No PSCI: leave the rest to the hardware watchdog, if any
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry_and_timeouts() {
+        assert!(!expired(100, 150, 50));
+        assert!(expired(100, 151, 50));
+        // Counter wrap
+        assert!(!expired(u64::MAX - 10, 20, 50));
+
+        assert!(valid_timeout(0));
+        assert!(!valid_timeout(MIN_TIMEOUT_MS - 1));
+        assert!(valid_timeout(MIN_TIMEOUT_MS));
+    }
+}
@@ -0,0 +1,148 @@
+//! IPC fastpath for Call and ReplyRecv
+//!
+//! Client/server RPC is dominated by two transitions: a client calling a
+//! server that is already waiting in `SYS_REPLY_RECV`, and the server
+//! replying to a client while going back to wait. When nothing else is
+//! competing for the CPU, both are a direct thread-to-thread handoff, so
+//! (following seL4) they are handled here, ahead of the generic syscall
+//! dispatcher and without touching the scheduler's ready queues:
+//!
+//! - the message is copied straight between the two user buffers
+//! - the other thread is switched to directly by replacing the trap frame
+//!
+//! Every condition is checked before any state changes; if one fails the
+//! fastpath returns false and the syscall takes the slow path in
+//! `syscall::sys_ipc_call` / `sys_ipc_reply_recv`, which has the same
+//! semantics. The budget is the documented <500-cycle IPC latency.
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::objects::{ThreadState, TCB};
+use super::{numbers, IPC_MAX_MESSAGE};
+
+/// Try to handle a syscall on the fastpath
+///
+/// Called from the EL0 synchronous exception handler before
+/// `handle_syscall`. Returns true if the syscall was completed (and `tf`
+/// may now hold another thread's context).
+pub fn try_fastpath(tf: &mut TrapFrame) -> bool {
+    match tf.syscall_number() {
+        numbers::SYS_CALL => unsafe { fastpath_call(tf) },
+        numbers::SYS_REPLY_RECV => unsafe { fastpath_reply_recv(tf) },
+        _ => false,
+    }
+}
+
+/// Call with a receiver already waiting
+///
+/// Taken if the receiver runs at the caller's priority or higher, no
+/// higher-priority thread is ready, and the request fits its buffer.
+unsafe fn fastpath_call(tf: &mut TrapFrame) -> bool {
+    let [endpoint_cap_slot, request_ptr, request_len, ..] = tf.syscall_args();
+    let len = request_len as usize;
+    if len > IPC_MAX_MESSAGE || endpoint_cap_slot >= 4096 {
+        return false;
+    }
+
+    let current = crate::scheduler::current_thread();
+    if current.is_null() {
+        return false;
+    }
+
+    let (endpoint_ptr, badge) = super::lookup_badged_endpoint(endpoint_cap_slot as usize);
+    if endpoint_ptr.is_null() {
+        return false;
+    }
+    let endpoint = &mut *endpoint_ptr;
+
+    let receiver_tcb = match endpoint.first_receiver() {
+        Some(tcb) => tcb,
+        None => return false,
+    };
+    let receiver = &mut *receiver_tcb;
+
+    // Lower number = higher priority
+    if receiver.priority() > (*current).priority()
+        || crate::scheduler::higher_priority_ready(receiver.priority())
+        || receiver.context().x2 < request_len {
+        return false;
+    }
+
+    let mut message = [0u8; IPC_MAX_MESSAGE];
+    if len > 0 && !super::copy_from_user(request_ptr, &mut message, len, tf.saved_ttbr0) {
+        return false;
+    }
+
+    endpoint.dequeue_receiver();
+    if !super::deliver_to_receiver(receiver, &message[..len], badge) {
+        endpoint.queue_receive(receiver_tcb);
+        return false;
+    }
+
+    // Caller waits for the reply; its saved x3/x4 are the reply buffer
+    *(*current).context_mut() = *tf;
+    (*current).block_on_reply();
+    receiver.set_reply_to(current);
+
+    super::switch_to(tf, receiver_tcb);
+    true
+}
+
+/// ReplyRecv with a caller to answer and no message already pending
+///
+/// Taken if no thread with higher priority than the caller is ready and
+/// the reply fits the caller's buffer. The server blocks on the endpoint
+/// and the caller is switched to directly.
+unsafe fn fastpath_reply_recv(tf: &mut TrapFrame) -> bool {
+    let [endpoint_cap_slot, _, _, reply_ptr, reply_len, ..] = tf.syscall_args();
+    let len = reply_len as usize;
+    if len > IPC_MAX_MESSAGE || endpoint_cap_slot >= 4096 {
+        return false;
+    }
+
+    let current = crate::scheduler::current_thread();
+    if current.is_null() {
+        return false;
+    }
+
+    let caller_tcb: *mut TCB = (*current).reply_to();
+    if caller_tcb.is_null() {
+        return false;
+    }
+    let caller = &mut *caller_tcb;
+
+    if caller.state() != ThreadState::BlockedOnReply
+        || crate::scheduler::higher_priority_ready(caller.priority())
+        || caller.context().x4 < reply_len {
+        return false;
+    }
+
+    let endpoint_ptr = super::lookup_endpoint_capability(endpoint_cap_slot as usize);
+    if endpoint_ptr.is_null() {
+        return false;
+    }
+    let endpoint = &mut *endpoint_ptr;
+
+    // A queued sender means the server would not block: slow path
+    if endpoint.has_senders() {
+        return false;
+    }
+
+    let mut message = [0u8; IPC_MAX_MESSAGE];
+    if len > 0 && !super::copy_from_user(reply_ptr, &mut message, len, tf.saved_ttbr0) {
+        return false;
+    }
+
+    if !super::deliver_reply(caller, &message[..len]) {
+        return false;
+    }
+    (*current).set_reply_to(core::ptr::null_mut());
+
+    // Server waits for the next message; its saved x1/x2 are the buffer
+    // (ReplyRecv receives no capability)
+    *(*current).context_mut() = *tf;
+    (*current).set_cap_receive_slot(None);
+    endpoint.queue_receive(current);
+
+    super::switch_to(tf, caller_tcb);
+    true
+}
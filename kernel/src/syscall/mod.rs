@@ -6,11 +6,20 @@
 
 pub mod numbers;
 pub mod channel;
+pub mod fastpath;
+pub mod process;
+pub mod thread;
+#[cfg(feature = "syscall-trace")]
+pub mod trace;
+mod user;
+#[cfg(feature = "hypervisor")]
+mod vcpu;
 
 use crate::arch::aarch64::context::TrapFrame;
 use crate::{kprintln, ksyscall_debug};
 use crate::objects::{TCB, Endpoint, Notification};
 use core::ptr;
+pub(crate) use user::{copy_from_user, copy_to_user};
 
 /// Shared memory registry entry
 #[derive(Copy, Clone)]
@@ -20,6 +29,7 @@ struct ShmemEntry {
     phys_addr: usize,    // Physical address of shared memory
     size: usize,         // Size in bytes
     notification_obj: usize, // Kernel notification object pointer (for cross-CSpace signaling)
+    owner: usize,        // TID of the registering process (its main thread)
     valid: bool,         // Whether this entry is in use
 }
 
@@ -31,6 +41,7 @@ impl ShmemEntry {
             phys_addr: 0,
             size: 0,
             notification_obj: 0,
+            owner: 0,
             valid: false,
         }
     }
@@ -64,6 +75,14 @@ static mut SHMEM_REGISTRY: [ShmemEntry; 16] = [ShmemEntry::new(); 16];
 /// - capability not found in CSpace
 /// - capability is not an Endpoint type
 unsafe fn lookup_endpoint_capability(cap_slot: usize) -> *mut Endpoint {
+    lookup_badged_endpoint(cap_slot).0
+}
+
+/// Look up an endpoint capability and its badge
+///
+/// Like `lookup_endpoint_capability`, also returning the capability's
+/// badge (0 if unbadged), which identifies the sender to the receiver.
+unsafe fn lookup_badged_endpoint(cap_slot: usize) -> (*mut Endpoint, u64) {
     use crate::objects::CapType;
     use crate::objects::cnode_cdt::CNodeCdt;
 
@@ -71,22 +90,22 @@ unsafe fn lookup_endpoint_capability(cap_slot: usize) -> *mut Endpoint {
     let current_tcb = crate::scheduler::current_thread();
     if current_tcb.is_null() {
         ksyscall_debug!("[syscall] lookup_endpoint: no current thread");
-        return ptr::null_mut();
+        return (ptr::null_mut(), 0);
     }
 
     let cspace_root = (*current_tcb).cspace_root();
     if cspace_root.is_null() {
         ksyscall_debug!("[syscall] lookup_endpoint: thread has no CSpace root");
-        return ptr::null_mut();
+        return (ptr::null_mut(), 0);
     }
 
     // Look up capability in CSpace
     let cnode = &*(cspace_root as *const CNodeCdt);
-    let cap = match cnode.lookup(cap_slot) {
+    let cap = match cnode.lookup_cptr(cap_slot as u64) {
         Some(c) => c,
         None => {
             ksyscall_debug!("[syscall] lookup_endpoint: cap_slot {} not found in CSpace", cap_slot);
-            return ptr::null_mut();
+            return (ptr::null_mut(), 0);
         }
     };
 
@@ -94,11 +113,48 @@ unsafe fn lookup_endpoint_capability(cap_slot: usize) -> *mut Endpoint {
     if cap.cap_type() != CapType::Endpoint {
         ksyscall_debug!("[syscall] lookup_endpoint: cap_slot {} is not an Endpoint (type={:?})",
                  cap_slot, cap.cap_type());
-        return ptr::null_mut();
+        return (ptr::null_mut(), 0);
     }
 
     // Return pointer to Endpoint object
-    cap.object_ptr() as *mut Endpoint
+    (cap.object_ptr() as *mut Endpoint, cap.badge())
+}
+
+/// Look up a TCB capability in the current thread's CSpace
+///
+/// Returns null if the slot is empty or not a TCB capability
+unsafe fn lookup_tcb_capability(cap_slot: usize) -> *mut TCB {
+    use crate::objects::CapType;
+    use crate::objects::cnode_cdt::CNodeCdt;
+
+    let current_tcb = crate::scheduler::current_thread();
+    if current_tcb.is_null() {
+        ksyscall_debug!("[syscall] lookup_tcb: no current thread");
+        return ptr::null_mut();
+    }
+
+    let cspace_root = (*current_tcb).cspace_root();
+    if cspace_root.is_null() {
+        ksyscall_debug!("[syscall] lookup_tcb: thread has no CSpace root");
+        return ptr::null_mut();
+    }
+
+    let cnode = &*(cspace_root as *const CNodeCdt);
+    let cap = match cnode.lookup_cptr(cap_slot as u64) {
+        Some(c) => c,
+        None => {
+            ksyscall_debug!("[syscall] lookup_tcb: cap_slot {} not found in CSpace", cap_slot);
+            return ptr::null_mut();
+        }
+    };
+
+    if cap.cap_type() != CapType::Tcb {
+        ksyscall_debug!("[syscall] lookup_tcb: cap_slot {} is not a TCB (type={:?})",
+                 cap_slot, cap.cap_type());
+        return ptr::null_mut();
+    }
+
+    cap.object_ptr() as *mut TCB
 }
 
 /// Insert an endpoint capability into the current thread's CSpace
@@ -138,88 +194,8 @@ unsafe fn insert_endpoint_capability(cap_slot: usize, endpoint: *mut Endpoint) -
     }
 }
 
-/// Copy data from userspace to kernel space
-///
-/// Temporarily switches to the caller's TTBR0 to access userspace memory.
-/// This is safe because we're running in EL1 with kernel permissions.
-///
-/// # Safety
-/// - user_ptr must be a valid userspace pointer
-/// - len must not exceed buffer sizes
-/// - caller_ttbr0 must be the physical address of a valid page table
-unsafe fn copy_from_user(user_ptr: u64, kernel_buf: &mut [u8], len: usize, caller_ttbr0: u64) -> bool {
-    if len == 0 || len > kernel_buf.len() {
-        return false;
-    }
-
-    // Save current TTBR0
-    let saved_ttbr0: u64;
-    core::arch::asm!(
-        "mrs {}, ttbr0_el1",
-        out(reg) saved_ttbr0,
-    );
-
-    // Switch to caller's TTBR0 to access userspace memory
-    core::arch::asm!(
-        "msr ttbr0_el1, {}",
-        "isb",
-        in(reg) caller_ttbr0,
-    );
-
-    // Copy data from userspace
-    let user_slice = core::slice::from_raw_parts(user_ptr as *const u8, len);
-    kernel_buf[..len].copy_from_slice(user_slice);
-
-    // Restore kernel's TTBR0
-    core::arch::asm!(
-        "msr ttbr0_el1, {}",
-        "isb",
-        in(reg) saved_ttbr0,
-    );
-
-    true
-}
-
-/// Copy data from kernel space to userspace
-///
-/// Temporarily switches to the caller's TTBR0 to access userspace memory.
-///
-/// # Safety
-/// - user_ptr must be a valid userspace pointer
-/// - len must not exceed buffer sizes
-/// - caller_ttbr0 must be the physical address of a valid page table
-unsafe fn copy_to_user(kernel_buf: &[u8], user_ptr: u64, len: usize, caller_ttbr0: u64) -> bool {
-    if len == 0 || len > kernel_buf.len() {
-        return false;
-    }
-
-    // Save current TTBR0
-    let saved_ttbr0: u64;
-    core::arch::asm!(
-        "mrs {}, ttbr0_el1",
-        out(reg) saved_ttbr0,
-    );
-
-    // Switch to caller's TTBR0 to access userspace memory
-    core::arch::asm!(
-        "msr ttbr0_el1, {}",
-        "isb",
-        in(reg) caller_ttbr0,
-    );
-
-    // Copy data to userspace
-    let user_slice = core::slice::from_raw_parts_mut(user_ptr as *mut u8, len);
-    user_slice.copy_from_slice(&kernel_buf[..len]);
-
-    // Restore kernel's TTBR0
-    core::arch::asm!(
-        "msr ttbr0_el1, {}",
-        "isb",
-        in(reg) saved_ttbr0,
-    );
-
-    true
-}
+/// Set by `switch_to` when the CPU idled before resuming a thread
+static mut IDLED: bool = false;
 
 /// Syscall dispatcher - called from exception handler
 ///
@@ -228,36 +204,61 @@ unsafe fn copy_to_user(kernel_buf: &[u8], user_ptr: u64, len: usize, caller_ttbr
 pub fn handle_syscall(tf: &mut TrapFrame) {
     let syscall_num = tf.syscall_number();
     let args = tf.syscall_args();
+    let caller = unsafe { crate::scheduler::current_thread() };
+    unsafe { IDLED = false };
 
     // Dispatch based on syscall number
     let result = match syscall_num {
         numbers::SYS_DEBUG_PUTCHAR => sys_debug_putchar(args[0]),
         numbers::SYS_DEBUG_PRINT => sys_debug_print(tf, args[0], args[1]),
+        #[cfg(feature = "debug-syscall")]
+        numbers::SYS_DEBUG_CAP_IDENTIFY => sys_debug_cap_identify(tf, args[0]),
+        numbers::SYS_KLOG_READ => sys_klog_read(tf, args[0], args[1], args[2]),
+        numbers::SYS_THREAD_STATS => sys_thread_stats(tf, args[0], args[1], args[2]),
+        numbers::SYS_IDLE_STATS => sys_idle_stats(tf, args[0], args[1]),
+        numbers::SYS_WATCHDOG_KICK => sys_watchdog_kick(args[0]),
+        numbers::SYS_DOMAIN_SET => sys_domain_set(args[0], args[1]),
+        #[cfg(feature = "syscall-trace")]
+        numbers::SYS_TRACE_CTL => trace::sys_trace_ctl(tf, args[0], args[1], args[2], args[3]),
         numbers::SYS_YIELD => sys_yield(tf),
 
         // Chapter 5: IPC syscalls
-        numbers::SYS_SEND => sys_ipc_send(tf, args[0], args[1], args[2]),
-        numbers::SYS_RECV => sys_ipc_recv(tf, args[0], args[1], args[2]),
+        numbers::SYS_SEND => sys_ipc_send(tf, args[0], args[1], args[2], None),
+        numbers::SYS_RECV => sys_ipc_recv(tf, args[0], args[1], args[2], None),
         numbers::SYS_CALL => sys_ipc_call(tf, args[0], args[1], args[2], args[3], args[4]),
         numbers::SYS_REPLY => sys_ipc_reply(tf, args[0], args[1]),
+        numbers::SYS_REPLY_RECV => sys_ipc_reply_recv(tf, args[0], args[1], args[2], args[3], args[4]),
+        numbers::SYS_SEND_CAP => sys_ipc_send(tf, args[0], args[1], args[2], Some(args[3])),
+        numbers::SYS_RECV_CAP => sys_ipc_recv(tf, args[0], args[1], args[2], Some(args[3])),
+        numbers::SYS_TCB_DONATE_PRIORITY => sys_tcb_donate_priority(args[0], args[1]),
 
         // Chapter 9: Capability management syscalls
         numbers::SYS_CAP_ALLOCATE => sys_cap_allocate(),
         numbers::SYS_MEMORY_ALLOCATE => sys_memory_allocate(args[0]),
         numbers::SYS_DEVICE_REQUEST => sys_device_request(args[0]),
-        numbers::SYS_ENDPOINT_CREATE => sys_endpoint_create(),
+        numbers::SYS_ENDPOINT_CREATE => sys_endpoint_create(args[0]),
         numbers::SYS_PROCESS_CREATE => sys_process_create(
             tf,  // Pass TrapFrame to set extra return values
             args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7],
             tf.x9,  // Priority passed in x9
-            tf.x10  // Capabilities passed in x10
+            tf.x10,  // Capabilities passed in x10
+            tf.x11,  // Flags passed in x11
+            tf.x12   // Boot block description, with PROCESS_CREATE_BOOT_BLOCK
         ),
         numbers::SYS_MEMORY_MAP => sys_memory_map(tf, args[0], args[1], args[2]),
         numbers::SYS_MEMORY_UNMAP => sys_memory_unmap(args[0], args[1]),
         numbers::SYS_MEMORY_REMAP => sys_memory_remap(args[0], args[1], args[2]),
         numbers::SYS_MEMORY_SHARE => sys_memory_share(args[0], args[1], args[2], args[3], args[4]),
         numbers::SYS_RETYPE => sys_retype(args[0], args[1], args[2], args[3], args[4]),
+        numbers::SYS_TCB_SET_FAULT_HANDLER => sys_tcb_set_fault_handler(args[0], args[1]),
+        numbers::SYS_FAULT_RESUME => sys_fault_resume(args[0]),
+        numbers::SYS_PROCESS_EXIT => process::sys_process_exit(tf, args[0]),
+        numbers::SYS_PROCESS_DESTROY => process::sys_process_destroy(tf, args[0]),
+        numbers::SYS_THREAD_CREATE => thread::sys_thread_create(tf, args[0], args[1], args[2], args[3], args[4], args[5]),
+        numbers::SYS_THREAD_EXIT => thread::sys_thread_exit(tf),
         numbers::SYS_MEMORY_MAP_INTO => sys_memory_map_into(args[0], args[1], args[2], args[3], args[4]),
+        numbers::SYS_MEMORY_UNMAP_FROM => sys_memory_unmap_from(args[0], args[1], args[2]),
+        numbers::SYS_MEMORY_PROTECT_IN => sys_memory_protect_in(args[0], args[1], args[2], args[3]),
         numbers::SYS_CAP_INSERT_INTO => sys_cap_insert_into(args[0], args[1], args[2], args[3]),
         numbers::SYS_CAP_INSERT_SELF => sys_cap_insert_self(args[0], args[1], args[2]),
         numbers::SYS_CAP_REVOKE => sys_cap_revoke(args[0], args[1]),
@@ -266,12 +267,30 @@ pub fn handle_syscall(tf: &mut TrapFrame) {
         numbers::SYS_CAP_COPY => sys_cap_copy(args[0], args[1], args[2], args[3]),
         numbers::SYS_CAP_DELETE => sys_cap_delete(args[0], args[1]),
         numbers::SYS_CAP_MOVE => sys_cap_move(args[0], args[1], args[2], args[3]),
+        numbers::SYS_CNODE_SET_GUARD => sys_cnode_set_guard(args[0], args[1], args[2], args[3]),
 
         // Chapter 9 Phase 2: Notification syscalls for shared memory IPC
-        numbers::SYS_NOTIFICATION_CREATE => sys_notification_create(),
-        numbers::SYS_SIGNAL => sys_signal(args[0], args[1]),
+        numbers::SYS_NOTIFICATION_CREATE => sys_notification_create(args[0]),
+        numbers::SYS_SIGNAL => sys_signal(tf, args[0], args[1]),
         numbers::SYS_WAIT => sys_wait(tf, args[0]),
         numbers::SYS_POLL => sys_poll(args[0]),
+        numbers::SYS_TCB_BIND_NOTIFICATION => sys_tcb_bind_notification(args[0]),
+        numbers::SYS_TIMER_CREATE => sys_timer_create(args[0], args[1]),
+        numbers::SYS_TIMER_SET => sys_timer_set(args[0], args[1], args[2]),
+
+        // Guests (hypervisor builds only)
+        #[cfg(feature = "hypervisor")]
+        numbers::SYS_VCPU_CREATE => vcpu::sys_vcpu_create(),
+        #[cfg(feature = "hypervisor")]
+        numbers::SYS_VCPU_MAP => vcpu::sys_vcpu_map(args[0], args[1], args[2], args[3], args[4]),
+        #[cfg(feature = "hypervisor")]
+        numbers::SYS_VCPU_RUN => vcpu::sys_vcpu_run(tf, args[0]),
+        #[cfg(feature = "hypervisor")]
+        numbers::SYS_VCPU_READ_REG => vcpu::sys_vcpu_read_reg(tf, args[0], args[1]),
+        #[cfg(feature = "hypervisor")]
+        numbers::SYS_VCPU_WRITE_REG => vcpu::sys_vcpu_write_reg(args[0], args[1], args[2]),
+        #[cfg(feature = "hypervisor")]
+        numbers::SYS_VCPU_INJECT_IRQ => vcpu::sys_vcpu_inject_irq(args[0], args[1]),
 
         // Chapter 9 Phase 6: Channel management syscalls
         numbers::SYS_CHANNEL_ESTABLISH => channel::sys_channel_establish(tf, args[0], args[1], args[2]),
@@ -284,8 +303,13 @@ pub fn handle_syscall(tf: &mut TrapFrame) {
         numbers::SYS_SHMEM_GET_NOTIFICATION => sys_shmem_get_notification(tf, args[0], args[1], args[2]),
 
         // IRQ handling syscalls
-        numbers::SYS_IRQ_HANDLER_GET => sys_irq_handler_get(tf, args[0], args[1], args[2], args[3]),
+        numbers::SYS_IRQ_HANDLER_GET => sys_irq_handler_get(tf, args[0], args[1], args[2], args[3], None),
         numbers::SYS_IRQ_HANDLER_ACK => sys_irq_handler_ack(tf, args[0]),
+        numbers::SYS_IRQ_MSI_GET => sys_irq_msi_get(tf, args[0], args[1], args[2], args[3]),
+        numbers::SYS_IRQ_HANDLER_GET_SHARED => {
+            sys_irq_handler_get(tf, args[0], args[1], args[2], args[3], Some(args[4]))
+        }
+        numbers::SYS_IRQ_HANDLER_CLEAR => sys_irq_handler_clear(args[0]),
 
         // System control syscalls
         numbers::SYS_SHUTDOWN => sys_shutdown(),
@@ -305,8 +329,12 @@ pub fn handle_syscall(tf: &mut TrapFrame) {
         }
     };
 
-    // Set return value
-    tf.set_return_value(result);
+    // Set return value, unless the syscall blocked and switched threads:
+    // `tf` then holds the next thread's context, whose x0 is already right.
+    // That is the caller's own if it blocked and woke up while idling.
+    if unsafe { crate::scheduler::current_thread() == caller && !IDLED } {
+        tf.set_return_value(result);
+    }
 }
 
 /// Yield CPU to next process using scheduler
@@ -326,6 +354,7 @@ fn sys_yield(tf: &mut TrapFrame) -> u64 {
         // Save current thread's full context to its TCB
         // The TrapFrame passed to us contains the saved userspace registers
         *(*current).context_mut() = *tf;
+        (*current).context_mut().x0 = 0; // sys_yield's return value when resumed
 
         // Mark current thread as runnable and re-enqueue
         (*current).set_state(crate::objects::ThreadState::Runnable);
@@ -387,16 +416,10 @@ fn sys_yield(tf: &mut TrapFrame) -> u64 {
         // CRITICAL: Switch TTBR0 NOW to the next thread's page table
         // The exception handler will restore this same value when we eret,
         // but we need to switch now so any kernel operations use the correct
-        // page table (e.g., when kernel reads from user memory).
+        // page table (e.g., when kernel reads from user memory). Entries are
+        // ASID-tagged, so no TLB flush is needed.
         unsafe {
-            core::arch::asm!(
-                "msr ttbr0_el1, {ttbr0}",    // Switch to next thread's page table
-                "dsb ish",                     // Ensure page table switch completes
-                "tlbi vmalle1is",              // Invalidate all TLB entries
-                "dsb ish",                     // Ensure TLB invalidation completes
-                "isb",                         // Synchronize instruction fetch
-                ttbr0 = in(reg) next_context.saved_ttbr0,
-            );
+            crate::memory::asid::activate(next_context.saved_ttbr0);
         }
     }
     0 // Success
@@ -405,7 +428,8 @@ fn sys_yield(tf: &mut TrapFrame) -> u64 {
 /// Debug syscall: print a single character
 fn sys_debug_putchar(ch: u64) -> u64 {
     if ch <= 0x7F {
-        crate::kprint!("{}", ch as u8 as char);
+        let mut utf8 = [0u8; 4];
+        crate::debug::user_write((ch as u8 as char).encode_utf8(&mut utf8));
         0 // Success
     } else {
         u64::MAX // Error: invalid character
@@ -448,7 +472,7 @@ fn sys_debug_print(tf: &TrapFrame, ptr: u64, len: u64) -> u64 {
 
     // Print from kernel buffer
     if let Ok(s) = core::str::from_utf8(&buffer[..copy_len]) {
-        crate::kprint!("{}", s);
+        crate::debug::user_write(s);
         0 // Success
     } else {
         ksyscall_debug!("[syscall] sys_debug_print: invalid UTF-8");
@@ -456,6 +480,196 @@ fn sys_debug_print(tf: &TrapFrame, ptr: u64, len: u64) -> u64 {
     }
 }
 
+/// Debug syscall: identify a capability
+///
+/// Returns the type in x0 and the rights, badge and object address in
+/// x1-x3. An empty or unresolvable slot reads as a null capability.
+#[cfg(feature = "debug-syscall")]
+fn sys_debug_cap_identify(tf: &mut TrapFrame, cptr: u64) -> u64 {
+    use crate::objects::{cnode_cdt::CNodeCdt, Capability};
+
+    let cap = unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() || (*current_tcb).cspace_root().is_null() {
+            return u64::MAX;
+        }
+
+        let cspace = &*((*current_tcb).cspace_root() as *const CNodeCdt);
+        cspace.lookup_cptr(cptr).copied().unwrap_or(Capability::null())
+    };
+
+    ksyscall_debug!("[syscall] debug_cap_identify: cptr={:#x} -> {:?}", cptr, cap);
+
+    tf.x1 = cap.rights().bits() as u64;
+    tf.x2 = cap.guard();
+    tf.x3 = cap.object_ptr() as u64;
+    cap.cap_type() as u64
+}
+
+/// Read the kernel log
+///
+/// Args:
+/// - buffer_ptr: Userspace buffer to copy log bytes into
+/// - buffer_len: Size of the buffer
+/// - offset: Log offset to start reading from (see `debug::klog`)
+///
+/// Returns the number of bytes copied in x0 and the offset to continue
+/// from in x1, or u64::MAX on error. Pass u64::MAX as the offset to get
+/// the current end of the log without reading anything. Requires CAP_KLOG.
+fn sys_klog_read(tf: &mut TrapFrame, buffer_ptr: u64, buffer_len: u64, offset: u64) -> u64 {
+    const CHUNK: usize = 256;
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || !(*current).has_capability(TCB::CAP_KLOG) {
+            ksyscall_debug!("[syscall] klog_read: caller lacks CAP_KLOG capability");
+            return u64::MAX;
+        }
+    }
+
+    let mut offset = offset;
+    let mut copied = 0usize;
+    let mut chunk = [0u8; CHUNK];
+
+    while copied < buffer_len as usize {
+        let want = core::cmp::min(CHUNK, buffer_len as usize - copied);
+        let (len, next) = crate::debug::klog::read(offset, &mut chunk[..want]);
+        // The first read may skip over bytes that were overwritten
+        offset = next;
+        if len == 0 {
+            break;
+        }
+
+        let dst = match buffer_ptr.checked_add(copied as u64) {
+            Some(dst) => dst,
+            None => return u64::MAX,
+        };
+        if !unsafe { copy_to_user(&chunk[..len], dst, len, tf.saved_ttbr0) } {
+            ksyscall_debug!("[syscall] klog_read: failed to copy to user");
+            return u64::MAX;
+        }
+        copied += len;
+    }
+
+    tf.x1 = offset;
+    copied as u64
+}
+
+/// Get CPU usage statistics for a thread
+///
+/// Args:
+/// - index: Position in the kernel's list of live threads (0, 1, ...)
+/// - buffer_ptr: Userspace buffer for a `ThreadStats` record (9 × u64)
+/// - buffer_len: Size of the buffer
+///
+/// Returns 0 on success, u64::MAX when `index` is past the last thread or
+/// the buffer is too small. Callers enumerate threads by counting up from 0.
+/// A buffer of the older 8-word record gets the record without `process`.
+fn sys_thread_stats(tf: &TrapFrame, index: u64, buffer_ptr: u64, buffer_len: u64) -> u64 {
+    use crate::scheduler::stats::ThreadStats;
+
+    if (buffer_len as usize) < ThreadStats::MIN_SIZE {
+        ksyscall_debug!("[syscall] thread_stats: buffer too small ({} bytes)", buffer_len);
+        return u64::MAX;
+    }
+
+    let stats = match unsafe { crate::scheduler::stats::thread_stats(index as usize) } {
+        Some(stats) => stats,
+        None => return u64::MAX,
+    };
+
+    let len = (buffer_len as usize).min(ThreadStats::SIZE);
+    if !unsafe { copy_to_user(&stats.to_bytes()[..len], buffer_ptr, len, tf.saved_ttbr0) } {
+        ksyscall_debug!("[syscall] thread_stats: failed to copy to user");
+        return u64::MAX;
+    }
+
+    0
+}
+
+/// Copy the CPU idle statistics to userspace
+///
+/// Args:
+/// - buffer_ptr: User buffer for an `IdleStats` record
+/// - buffer_len: Buffer size in bytes
+///
+/// Returns 0 on success, u64::MAX if the buffer is too small or unwritable.
+fn sys_idle_stats(tf: &TrapFrame, buffer_ptr: u64, buffer_len: u64) -> u64 {
+    use crate::scheduler::idle::IdleStats;
+
+    if (buffer_len as usize) < IdleStats::SIZE {
+        ksyscall_debug!("[syscall] idle_stats: buffer too small ({} bytes)", buffer_len);
+        return u64::MAX;
+    }
+
+    let stats = unsafe { crate::scheduler::idle::stats() };
+    if !unsafe { copy_to_user(&stats.to_bytes(), buffer_ptr, IdleStats::SIZE, tf.saved_ttbr0) } {
+        ksyscall_debug!("[syscall] idle_stats: failed to copy to user");
+        return u64::MAX;
+    }
+
+    0
+}
+
+/// Arm or kick the hang detection watchdog
+///
+/// Args:
+/// - timeout_ms: New timeout in milliseconds, or 0 to keep the current one
+///
+/// Returns 0 on success, u64::MAX if the caller lacks CAP_PROCESS or the
+/// timeout is below `watchdog::MIN_TIMEOUT_MS`.
+fn sys_watchdog_kick(timeout_ms: u64) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || !(*current).has_capability(TCB::CAP_PROCESS) {
+            ksyscall_debug!("[syscall] watchdog_kick: caller lacks CAP_PROCESS capability");
+            return u64::MAX;
+        }
+
+        if !crate::scheduler::watchdog::kick(timeout_ms) {
+            ksyscall_debug!("[syscall] watchdog_kick: timeout {} ms too short", timeout_ms);
+            return u64::MAX;
+        }
+    }
+    0
+}
+
+/// Move a thread to another scheduling domain
+///
+/// Args:
+/// - target_tcb_cap: TCB capability slot of the thread
+/// - domain: Domain number (below `scheduler::domain::NUM_DOMAINS`)
+///
+/// Returns: 0 on success, u64::MAX on error
+///
+/// Requires CAP_DOMAIN. Threads only run during their domain's windows of
+/// the kernel's domain schedule.
+fn sys_domain_set(target_tcb_cap: u64, domain: u64) -> u64 {
+    ksyscall_debug!("[syscall] domain_set: target_tcb_cap={}, domain={}", target_tcb_cap, domain);
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || !(*current).has_capability(TCB::CAP_DOMAIN) {
+            ksyscall_debug!("[syscall] domain_set: caller lacks CAP_DOMAIN capability");
+            return u64::MAX;
+        }
+
+        if !crate::scheduler::domain::valid_domain(domain) {
+            ksyscall_debug!("[syscall] domain_set: invalid domain {}", domain);
+            return u64::MAX;
+        }
+
+        let target = lookup_tcb_capability(target_tcb_cap as usize);
+        if target.is_null() {
+            return u64::MAX;
+        }
+
+        crate::scheduler::set_domain(target, domain as u8);
+        ksyscall_debug!("[syscall] domain_set: TID {} -> domain {}", (*target).tid(), domain);
+    }
+    0
+}
+
 //
 // Chapter 9: Capability Management Syscalls
 //
@@ -479,8 +693,9 @@ fn sys_cap_allocate() -> u64 {
             return u64::MAX; // Permission denied
         }
 
-        // Allocate from the thread's per-process capability slot allocator
-        let tcb_mut = &mut *current_tcb;
+        // Allocate from the process's capability slot allocator, which its
+        // worker threads share
+        let tcb_mut = &mut *(*current_tcb).process();
         tcb_mut.alloc_cap_slot()
     }
 }
@@ -566,17 +781,45 @@ fn sys_device_request(device_id: u64) -> u64 {
     }
 }
 
+/// Slot a syscall creating a capability puts it in: `dest_slot` if the
+/// caller allocated it earlier, a new one for 0, or u64::MAX if it may not
+///
+/// Naming a slot lets the caller reuse one it emptied; the insert fails if
+/// it is not empty. Either way takes the capability-management capability.
+fn creation_slot(dest_slot: u64) -> u64 {
+    if dest_slot == 0 {
+        return sys_cap_allocate();
+    }
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() || !(*current_tcb).has_capability(TCB::CAP_CAPS) {
+            return u64::MAX;
+        }
+        if !(*(*current_tcb).process()).cap_slot_allocated(dest_slot) {
+            ksyscall_debug!("[syscall] creation_slot: slot {} was never allocated", dest_slot);
+            return u64::MAX;
+        }
+    }
+    dest_slot
+}
+
 /// Create IPC endpoint
 ///
+/// Args: dest_slot - empty slot to put the capability in, 0 for a new one
+///
 /// Returns: endpoint capability slot
 ///
-/// Allocates a capability slot for a new IPC endpoint.
 /// The endpoint object itself is managed through the capability system.
-fn sys_endpoint_create() -> u64 {
+fn sys_endpoint_create(dest_slot: u64) -> u64 {
     use crate::objects::Endpoint;
     use crate::memory::alloc_frame;
     use core::ptr;
 
+    let slot = creation_slot(dest_slot);
+    if slot == u64::MAX {
+        return u64::MAX;
+    }
+
     // Allocate a physical frame for the Endpoint object
     let endpoint_frame = match unsafe { alloc_frame() } {
         Some(pfn) => pfn,
@@ -596,9 +839,6 @@ fn sys_endpoint_create() -> u64 {
         ksyscall_debug!("[syscall] endpoint_create: created Endpoint at 0x{:x}", endpoint_ptr as u64);
     }
 
-    // Allocate capability slot for the endpoint
-    let slot = sys_cap_allocate();
-
     // Insert endpoint capability into current thread's CSpace
     unsafe {
         if !insert_endpoint_capability(slot as usize, endpoint_ptr) {
@@ -611,6 +851,31 @@ fn sys_endpoint_create() -> u64 {
     slot
 }
 
+/// Boot block of a process being created, as `SYS_PROCESS_CREATE` reads
+/// it from the creator's memory at x12
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct BootBlockArgs {
+    /// Physical address of the page the creator filled in
+    phys: u64,
+    /// Page-aligned virtual address to map it at
+    vaddr: u64,
+}
+
+impl BootBlockArgs {
+    const SIZE: usize = core::mem::size_of::<Self>();
+
+    /// Read the boot block description at `ptr` in the caller's memory
+    fn read(tf: &TrapFrame, ptr: u64) -> Option<Self> {
+        let mut bytes = [0u8; Self::SIZE];
+        if !unsafe { copy_from_user(ptr, &mut bytes, Self::SIZE, tf.saved_ttbr0) } {
+            return None;
+        }
+        let word = |i: usize| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+        Some(Self { phys: word(0), vaddr: word(1) })
+    }
+}
+
 /// Create a new process with full isolation
 ///
 /// Args:
@@ -622,6 +887,10 @@ fn sys_endpoint_create() -> u64 {
 /// - code_vaddr: Virtual address where code should be mapped (from ELF min_vaddr)
 /// - code_size: Size of code region in bytes
 /// - stack_phys: Physical address where stack is located
+/// - flags: `PROCESS_CREATE_COW` to share the code region copy-on-write,
+///   `PROCESS_CREATE_BOOT_BLOCK` to map a boot block
+/// - boot_block_ptr: user pointer to a [`BootBlockArgs`], with
+///   `PROCESS_CREATE_BOOT_BLOCK`
 ///
 /// Returns: Process ID (TID), or u64::MAX on error
 ///
@@ -642,6 +911,8 @@ fn sys_process_create(
     stack_phys: u64,
     priority: u64,  // Priority parameter from x9
     capabilities: u64,  // Capabilities parameter from x10
+    flags: u64,  // Flags parameter from x11
+    boot_block_ptr: u64,  // Boot block parameter from x12
 ) -> u64 {
     use crate::memory::{alloc_frame, VirtAddr};
     use crate::objects::{TCB, CNode};
@@ -737,24 +1008,19 @@ fn sys_process_create(
     }
 
     // Map GIC (interrupt controller) for IRQ handling in syscalls
-    if let Err(e) = crate::memory::paging::identity_map_region(
-        &mut mapper,
-        memory_config::GIC_DIST_BASE,
-        memory_config::GIC_DIST_SIZE,
-        PageTableFlags::KERNEL_DEVICE,
-    ) {
-        ksyscall_debug!("[syscall] process_create: failed to map GIC distributor: {:?}", e);
-        return u64::MAX;
+    for (base, size) in crate::arch::aarch64::gic::mmio_regions() {
+        if crate::memory::paging::identity_map_region(&mut mapper, base, size, PageTableFlags::KERNEL_DEVICE).is_err() {
+            ksyscall_debug!("[syscall] process_create: failed to map GIC at {:#x}", base);
+            return u64::MAX;
+        }
     }
 
-    if let Err(e) = crate::memory::paging::identity_map_region(
-        &mut mapper,
-        memory_config::GIC_CPU_BASE,
-        memory_config::GIC_CPU_SIZE,
-        PageTableFlags::KERNEL_DEVICE,
-    ) {
-        ksyscall_debug!("[syscall] process_create: failed to map GIC CPU interface: {:?}", e);
-        return u64::MAX;
+    // Map the hardware watchdog (if configured) so the timer tick can kick it
+    for &(base, size) in crate::config::WATCHDOG_MMIO {
+        if crate::memory::paging::identity_map_region(&mut mapper, base, size, PageTableFlags::KERNEL_DEVICE).is_err() {
+            ksyscall_debug!("[syscall] process_create: failed to map watchdog at {:#x}", base);
+            return u64::MAX;
+        }
     }
 
     // Map the code region at the virtual address expected by the ELF
@@ -768,6 +1034,14 @@ fn sys_process_create(
     let code_virt_base = code_vaddr as usize;
     let code_pages = (code_size as usize).div_ceil(PAGE_SIZE);
 
+    // A copy-on-write image stays shared with the parent (and any other
+    // process created from it) until a page is written
+    let code_flags = if flags & numbers::PROCESS_CREATE_COW != 0 {
+        PageTableFlags::USER_COW
+    } else {
+        PageTableFlags::USER_RWX
+    };
+
     ksyscall_debug!("[syscall] process_create: mapping {} code pages at virt={:#x} -> phys={:#x}",
         code_pages, code_virt_base, code_phys);
 
@@ -775,7 +1049,7 @@ fn sys_process_create(
         let virt = VA::new(code_virt_base + (i * PAGE_SIZE));
         let phys = PA::new(code_phys as usize + (i * PAGE_SIZE));
         crate::kprintln!("[syscall] Mapping page {}: virt={:#x} -> phys={:#x}", i, virt.as_usize(), phys.as_usize());
-        if let Err(e) = mapper.map(virt, phys, PageTableFlags::USER_RWX, PageSize::Size4KB) {
+        if let Err(e) = mapper.map(virt, phys, code_flags, PageSize::Size4KB) {
             kprintln!("  ERROR: Failed to map code page {}: {:?}", i, e);
             return u64::MAX;
         }
@@ -811,14 +1085,33 @@ fn sys_process_create(
         }
     }
 
-    // Flush TLB and ensure page table updates are visible
+    // Map the boot block read-only; its address is the process's first
+    // argument
+    let boot_block = if flags & numbers::PROCESS_CREATE_BOOT_BLOCK != 0 {
+        let Some(block) = BootBlockArgs::read(tf, boot_block_ptr) else {
+            ksyscall_debug!("[syscall] process_create: bad boot block pointer {:#x}", boot_block_ptr);
+            return u64::MAX;
+        };
+        if !(block.phys as usize).is_multiple_of(PAGE_SIZE) || !(block.vaddr as usize).is_multiple_of(PAGE_SIZE) {
+            ksyscall_debug!("[syscall] process_create: boot block not page-aligned");
+            return u64::MAX;
+        }
+        let virt = VA::new(block.vaddr as usize);
+        let phys = PA::new(block.phys as usize);
+        let flags = protect_flags(PageTableFlags::USER_DATA, 0x1);
+        if let Err(e) = mapper.map(virt, phys, flags, PageSize::Size4KB) {
+            kprintln!("  ERROR: Failed to map boot block: {:?}", e);
+            return u64::MAX;
+        }
+        Some(block)
+    } else {
+        None
+    };
+
+    // Ensure page table updates are visible (a new address space has no
+    // ASID yet, so nothing of it is cached in the TLB)
     unsafe {
-        core::arch::asm!(
-            "dsb ishst",           // Ensure all page table writes complete
-            "tlbi vmalle1is",      // Invalidate all TLB entries for EL1
-            "dsb ish",             // Ensure TLB invalidation completes
-            "isb",                 // Synchronize context
-        );
+        crate::memory::asid::flush_vspace(page_table_root);
     }
 
     ksyscall_debug!("[syscall] process_create: page tables set up and TLB flushed");
@@ -866,6 +1159,9 @@ fn sys_process_create(
             capabilities,  // Capabilities passed from caller
         );
         core::ptr::write(tcb_ptr, tcb);
+        if let Some(block) = boot_block {
+            (*tcb_ptr).set_arguments(block.vaddr, 0, 0);
+        }
 
         // Initialize saved_ttbr0 in the context for context switching
         (*tcb_ptr).context_mut().saved_ttbr0 = page_table_root;
@@ -882,6 +1178,12 @@ fn sys_process_create(
         (*tcb_ptr).set_priority(priority as u8);
         crate::kprintln!("[syscall] process_create: set priority {} for component", priority);
 
+        // Children start in their creator's scheduling domain
+        let creator = crate::scheduler::current_thread();
+        if !creator.is_null() {
+            (*tcb_ptr).set_domain((*creator).domain());
+        }
+
         // Set state to Runnable
         (*tcb_ptr).set_state(crate::objects::ThreadState::Runnable);
 
@@ -889,6 +1191,7 @@ fn sys_process_create(
         // Note: scheduler::enqueue handles uninitialized scheduler gracefully
         crate::kprintln!("[syscall] process_create: enqueuing TCB at {:#x}", tcb_ptr as usize);
         scheduler::enqueue(tcb_ptr);
+        scheduler::stats::register(tcb_ptr);
 
         // TCB is now managed by scheduler
     }
@@ -907,14 +1210,9 @@ fn sys_process_create(
     crate::kprintln!("[syscall] process_create: set TrapFrame - x1={:#x}, x2={:#x}, x3={:#x}",
                      tf.x1, tf.x2, tf.x3);
 
-    // Full TLB and cache flush to ensure all processes see correct memory
+    // Publish the new address space's tables (flushes only its own ASID)
     unsafe {
-        core::arch::asm!(
-            "dsb ishst",          // Data sync barrier
-            "tlbi vmalle1is",     // Invalidate all TLB entries for all PEs
-            "dsb ish",            // Ensure TLB invalidation completes
-            "isb",                // Instruction sync barrier
-        );
+        crate::memory::asid::flush_vspace(page_table_root);
     }
 
     pid as u64
@@ -933,12 +1231,40 @@ fn sys_process_create(
 /// Production improvement: Use per-process VSpace allocator with free list
 static mut NEXT_VIRT_ADDR: u64 = crate::generated::memory_config::USER_VIRT_START;
 
+/// Decode the page size requested in a memory mapping syscall's permissions
+fn map_page_size(permissions: u64) -> Option<crate::memory::PageSize> {
+    use crate::memory::PageSize;
+
+    match permissions & numbers::MAP_PAGE_SIZE_MASK {
+        numbers::MAP_PAGE_4KB => Some(PageSize::Size4KB),
+        numbers::MAP_PAGE_2MB => Some(PageSize::Size2MB),
+        numbers::MAP_PAGE_1GB => Some(PageSize::Size1GB),
+        _ => None,
+    }
+}
+
+/// Page table flags for the memory type requested in a memory mapping
+/// syscall's permissions
+fn map_flags(permissions: u64) -> crate::arch::aarch64::page_table::PageTableFlags {
+    use crate::arch::aarch64::page_table::PageTableFlags;
+
+    match permissions & numbers::MAP_MEMORY_TYPE_MASK {
+        numbers::MAP_DEVICE => PageTableFlags::USER_DEVICE,
+        numbers::MAP_NON_CACHEABLE => PageTableFlags::USER_NON_CACHEABLE,
+        numbers::MAP_WRITE_COMBINE => PageTableFlags::USER_WRITE_COMBINE,
+        _ => PageTableFlags::USER_DATA,
+    }
+}
+
 /// Map physical memory into caller's virtual address space
 ///
 /// Args:
 /// - phys_addr: Physical address to map
 /// - size: Size in bytes (will be rounded up to page size)
-/// - permissions: Access permissions (1=read, 2=write, 4=exec)
+/// - permissions: Access permissions (1=read, 2=write, 4=exec), plus the
+///   memory type in bits 4-5 (`numbers::MAP_NORMAL` etc., default normal
+///   cacheable) and the page size in bits 8-9 (`numbers::MAP_PAGE_*`,
+///   default 4KB)
 ///
 /// Returns: Virtual address where memory is mapped, or u64::MAX on error
 ///
@@ -949,8 +1275,8 @@ static mut NEXT_VIRT_ADDR: u64 = crate::generated::memory_config::USER_VIRT_STAR
 /// the caller's TTBR0 in saved_ttbr0. During the exception, TTBR0 is temporarily
 /// switched to the kernel page table, so we must use the saved value.
 fn sys_memory_map(tf: &mut TrapFrame, phys_addr: u64, size: u64, permissions: u64) -> u64 {
-    use crate::memory::{PAGE_SIZE, VirtAddr, PhysAddr, PageSize};
-    use crate::arch::aarch64::page_table::{PageTable, PageTableFlags};
+    use crate::memory::{VirtAddr, PhysAddr};
+    use crate::arch::aarch64::page_table::PageTable;
 
     // Check if caller has memory mapping capability
     let current_tcb = unsafe { crate::scheduler::current_thread() };
@@ -966,10 +1292,21 @@ fn sys_memory_map(tf: &mut TrapFrame, phys_addr: u64, size: u64, permissions: u6
         }
     }
 
+    let page_size = match map_page_size(permissions) {
+        Some(page_size) => page_size,
+        None => {
+            ksyscall_debug!("[syscall] memory_map: invalid page size in permissions {:#x}", permissions);
+            return u64::MAX;
+        }
+    };
+
+    if !page_size.is_aligned(phys_addr as usize) {
+        ksyscall_debug!("[syscall] memory_map: phys_addr {:#x} not aligned to {:?}", phys_addr, page_size);
+        return u64::MAX;
+    }
+
     // Round size up to page boundary
-    let page_size = PAGE_SIZE as u64;
-    let num_pages = size.div_ceil(page_size) as usize;
-    let aligned_size = num_pages as u64 * page_size;
+    let aligned_size = size.next_multiple_of(page_size.bytes() as u64);
 
     // Get caller's page table from TrapFrame (saved during exception entry)
     let page_table_phys = tf.saved_ttbr0 as usize;
@@ -978,36 +1315,30 @@ fn sys_memory_map(tf: &mut TrapFrame, phys_addr: u64, size: u64, permissions: u6
     // Get mutable reference to caller's page table
     let page_table = unsafe { &mut *(page_table_phys as *mut PageTable) };
 
-    // Allocate virtual address from the caller's per-thread allocator
-    let virt_addr = unsafe { (*current_tcb).alloc_virt_range(aligned_size) };
+    // Allocate virtual address from the caller's per-process allocator
+    // (block mappings need a virtual address aligned to the block size)
+    let virt_addr = unsafe { (*(*current_tcb).process()).alloc_virt_range_aligned(aligned_size, page_size.bytes() as u64) };
 
-    // Use USER_DATA preset for userspace read-write data
-    // This includes: VALID, TABLE_OR_PAGE, AP_RW_ALL, ACCESSED, INNER_SHARE,
-    //               NORMAL, UXN, PXN, NOT_GLOBAL
-    let flags = PageTableFlags::USER_DATA;
+    // User read-write, no execute, with the requested memory type
+    // (USER_DATA unless the caller asked for device or non-cacheable memory)
+    let flags = map_flags(permissions);
 
-    ksyscall_debug!("[syscall] memory_map: using USER_DATA flags = {:#x}", flags.bits());
+    ksyscall_debug!("[syscall] memory_map: using flags = {:#x}", flags.bits());
 
     // Create PageMapper once for all mappings
     let mut mapper = unsafe { crate::memory::PageMapper::new(page_table) };
 
-    // Map each page
-    for i in 0..num_pages {
-        let page_virt = VirtAddr::new((virt_addr as usize) + (i * PAGE_SIZE));
-        let page_phys = PhysAddr::new((phys_addr as usize) + (i * PAGE_SIZE));
-
-        match mapper.map(page_virt, page_phys, flags, PageSize::Size4KB) {
-            Ok(()) => {
-                ksyscall_debug!("[syscall] memory_map: mapped page {} virt={:#x} -> phys={:#x}",
-                         i, page_virt.as_usize(), page_phys.as_usize());
-            },
-            Err(e) => {
-                crate::kprintln!("[syscall] memory_map: failed to map page {} at virt={:#x}, error={:?}",
-                         i, page_virt.as_usize(), e);
-                crate::kprintln!("[syscall] memory_map: phys_addr={:#x}, num_pages={}, failed at page {}",
-                         phys_addr, num_pages, i);
-                return u64::MAX;
-            }
+    // Map the region with the requested page size
+    match mapper.map_region(VirtAddr::new(virt_addr as usize), PhysAddr::new(phys_addr as usize),
+                            aligned_size as usize, flags, page_size) {
+        Ok(()) => {
+            ksyscall_debug!("[syscall] memory_map: mapped virt={:#x} -> phys={:#x}, size={:#x} ({:?})",
+                     virt_addr, phys_addr, aligned_size, page_size);
+        },
+        Err(e) => {
+            crate::kprintln!("[syscall] memory_map: failed to map virt={:#x} -> phys={:#x}, size={:#x} ({:?}), error={:?}",
+                     virt_addr, phys_addr, aligned_size, page_size, e);
+            return u64::MAX;
         }
     }
 
@@ -1033,7 +1364,7 @@ fn sys_memory_map(tf: &mut TrapFrame, phys_addr: u64, size: u64, permissions: u6
 ///
 /// Returns: 0 on success, u64::MAX on error
 fn sys_memory_unmap(virt_addr: u64, size: u64) -> u64 {
-    use crate::memory::{PAGE_SIZE, VirtAddr as VA, PageSize, PageMapper};
+    use crate::memory::{PAGE_SIZE, VirtAddr as VA, PageMapper};
     use crate::arch::aarch64::page_table::PageTable;
 
     ksyscall_debug!("[syscall] memory_unmap: virt={:#x}, size={}", virt_addr, size);
@@ -1087,23 +1418,23 @@ fn sys_memory_unmap(virt_addr: u64, size: u64) -> u64 {
         );
     }
 
-    // Unmap each page in the range
-    for i in 0..num_pages {
-        let virt = VA::new(virt_addr as usize + (i * PAGE_SIZE));
-        if let Err(e) = mapper.unmap(virt, PageSize::Size4KB) {
-            ksyscall_debug!("[syscall] memory_unmap: failed to unmap page {}: {:?}", i, e);
-            // Continue unmapping other pages even if one fails
+    // Unmap each page or block in the range (large pages come out whole)
+    let end = virt_addr as usize + num_pages * PAGE_SIZE;
+    let mut addr = virt_addr as usize;
+    while addr < end {
+        match mapper.unmap_leaf(VA::new(addr)) {
+            Ok(page_size) => addr += page_size.bytes(),
+            Err(e) => {
+                ksyscall_debug!("[syscall] memory_unmap: failed to unmap {:#x}: {:?}", addr, e);
+                // Continue unmapping other pages even if one fails
+                addr += PAGE_SIZE;
+            }
         }
     }
 
-    // Flush TLB to ensure unmapped pages are not cached
+    // Flush the caller's TLB entries so unmapped pages are not cached
     unsafe {
-        core::arch::asm!(
-            "dsb ishst",           // Ensure page table writes complete
-            "tlbi vmalle1is",      // Invalidate all TLB entries for EL1
-            "dsb ish",             // Ensure TLB invalidation completes
-            "isb",                 // Synchronize context
-        );
+        crate::memory::asid::flush_vspace(page_table_phys as u64);
     }
 
     ksyscall_debug!("[syscall] memory_unmap -> success ({} pages)", num_pages);
@@ -1197,14 +1528,9 @@ fn sys_memory_remap(virt_addr: u64, size: u64, new_permissions: u64) -> u64 {
         }
     }
 
-    // Flush TLB to ensure new permissions take effect
+    // Flush the caller's TLB entries so the new permissions take effect
     unsafe {
-        core::arch::asm!(
-            "dsb ishst",           // Ensure page table writes complete
-            "tlbi vmalle1is",      // Invalidate all TLB entries for EL1
-            "dsb ish",             // Ensure TLB invalidation completes
-            "isb",                 // Synchronize context
-        );
+        crate::memory::asid::flush_vspace(page_table_phys as u64);
     }
 
     ksyscall_debug!("[syscall] memory_remap -> success ({} pages)", num_pages);
@@ -1252,7 +1578,7 @@ fn sys_memory_share(target_tcb_cap: u64, source_virt_addr: u64, size: u64,
         }
 
         let caller_cspace = &*(cspace_root as *const CNodeCdt);
-        let tcb_capability = match caller_cspace.lookup(target_tcb_cap as usize) {
+        let tcb_capability = match caller_cspace.lookup_cptr(target_tcb_cap) {
             Some(cap) => cap,
             None => {
                 ksyscall_debug!("[syscall] memory_share: TCB capability not found");
@@ -1319,13 +1645,8 @@ fn sys_memory_share(target_tcb_cap: u64, source_virt_addr: u64, size: u64,
                            i, src_virt.as_usize(), phys_addr.as_usize(), dest_virt.as_usize());
         }
 
-        // Flush TLB for target process
-        core::arch::asm!(
-            "dsb ishst",
-            "tlbi vmalle1is",
-            "dsb ish",
-            "isb",
-        );
+        // Flush TLB for target process (its ASID only)
+        crate::memory::asid::flush_vspace(target_page_table as u64);
 
         ksyscall_debug!("[syscall] memory_share -> success ({} pages)", num_pages);
         0
@@ -1339,7 +1660,9 @@ fn sys_memory_share(target_tcb_cap: u64, source_virt_addr: u64, size: u64,
 /// - phys_addr: Physical address to map
 /// - size: Size in bytes
 /// - virt_addr: Target virtual address in target process (caller specifies)
-/// - permissions: Permission bits (read=1, write=2, exec=4)
+/// - permissions: Permission bits (read=1, write=2, exec=4), plus the
+///   memory type in bits 4-5 (`numbers::MAP_NORMAL` etc.) and the page size
+///   in bits 8-9 (`numbers::MAP_PAGE_*`, default 4KB)
 ///
 /// Returns: 0 on success, u64::MAX on error
 ///
@@ -1347,18 +1670,32 @@ fn sys_memory_share(target_tcb_cap: u64, source_virt_addr: u64, size: u64,
 /// process's address space at a specific virtual address, enabling inter-process
 /// IPC via shared memory. The caller must have a TCB capability for the target.
 fn sys_memory_map_into(target_tcb_cap: u64, phys_addr: u64, size: u64, virt_addr: u64, permissions: u64) -> u64 {
-    use crate::memory::{PAGE_SIZE, VirtAddr, PhysAddr, PageSize};
-    use crate::arch::aarch64::page_table::{PageTable, PageTableFlags};
+    use crate::memory::{VirtAddr, PhysAddr};
+    use crate::arch::aarch64::page_table::PageTable;
     use crate::objects::CapType;
     use crate::objects::cnode_cdt::CNodeCdt;
 
     crate::kprintln!("[syscall] memory_map_into: target_tcb_cap={}, phys={:#x}, size={}, virt={:#x}, perms={:#x}",
               target_tcb_cap, phys_addr, size, virt_addr, permissions);
 
-    // Round size up to page boundary
-    let page_size = PAGE_SIZE as u64;
-    let num_pages = size.div_ceil(page_size) as usize;
-    let aligned_size = num_pages as u64 * page_size;
+    let page_size = match map_page_size(permissions) {
+        Some(page_size) => page_size,
+        None => {
+            crate::kprintln!("[syscall] memory_map_into: ✗ invalid page size in permissions {:#x}", permissions);
+            return u64::MAX;
+        }
+    };
+
+    // Block mappings need both addresses aligned to the block size
+    if !page_size.is_aligned(phys_addr as usize) || !page_size.is_aligned(virt_addr as usize) {
+        crate::kprintln!("[syscall] memory_map_into: ✗ phys={:#x}/virt={:#x} not aligned to {:?}",
+                  phys_addr, virt_addr, page_size);
+        return u64::MAX;
+    }
+
+    // Round size up to page boundary
+    let aligned_size = size.next_multiple_of(page_size.bytes() as u64);
+    let num_pages = aligned_size / page_size.bytes() as u64;
 
     // Look up target TCB capability from caller's CSpace
     unsafe {
@@ -1376,7 +1713,7 @@ fn sys_memory_map_into(target_tcb_cap: u64, phys_addr: u64, size: u64, virt_addr
 
         // Look up target TCB capability
         let cnode = &*(cspace_root as *const CNodeCdt);
-        let cap = match cnode.lookup(target_tcb_cap as usize) {
+        let cap = match cnode.lookup_cptr(target_tcb_cap) {
             Some(c) => c,
             None => {
                 crate::kprintln!("[syscall] memory_map_into: ✗ cap_slot {} not found in CSpace", target_tcb_cap);
@@ -1412,29 +1749,24 @@ fn sys_memory_map_into(target_tcb_cap: u64, phys_addr: u64, size: u64, virt_addr
         crate::kprintln!("[syscall] memory_map_into: mapping to virt range {:#x} - {:#x} in target process",
                   virt_addr, virt_addr + aligned_size);
 
-        // Use USER_DATA preset for userspace read-write data
-        let flags = PageTableFlags::USER_DATA;
+        // User read-write, no execute, with the requested memory type
+        let flags = map_flags(permissions);
 
-        ksyscall_debug!("[syscall] memory_map_into: using USER_DATA flags = {:#x}", flags.bits());
+        ksyscall_debug!("[syscall] memory_map_into: using flags = {:#x}", flags.bits());
 
         // Create PageMapper for target's page table
         let mut mapper = crate::memory::PageMapper::new(target_page_table);
 
-        // Map each page into target's address space
-        for i in 0..num_pages {
-            let page_virt = VirtAddr::new((virt_addr as usize) + (i * PAGE_SIZE));
-            let page_phys = PhysAddr::new((phys_addr as usize) + (i * PAGE_SIZE));
-
-            match mapper.map(page_virt, page_phys, flags, PageSize::Size4KB) {
-                Ok(()) => {
-                    crate::kprintln!("[syscall] memory_map_into: ✓ mapped page {} virt={:#x} -> phys={:#x}",
-                             i, page_virt.as_usize(), page_phys.as_usize());
-                },
-                Err(e) => {
-                    crate::kprintln!("[syscall] memory_map_into: ✗ failed to map page {}: {:?}",
-                             i, e);
-                    return u64::MAX;
-                }
+        // Map the region into target's address space
+        match mapper.map_region(VirtAddr::new(virt_addr as usize), PhysAddr::new(phys_addr as usize),
+                                aligned_size as usize, flags, page_size) {
+            Ok(()) => {
+                crate::kprintln!("[syscall] memory_map_into: ✓ mapped virt={:#x} -> phys={:#x} ({} x {:?})",
+                         virt_addr, phys_addr, num_pages, page_size);
+            },
+            Err(e) => {
+                crate::kprintln!("[syscall] memory_map_into: ✗ failed to map region: {:?}", e);
+                return u64::MAX;
             }
         }
 
@@ -1448,6 +1780,199 @@ fn sys_memory_map_into(target_tcb_cap: u64, phys_addr: u64, size: u64, virt_addr
     }
 }
 
+/// Unmap memory from a target process's address space
+///
+/// Args:
+/// - target_tcb_cap: TCB capability slot of the target process
+/// - virt_addr: Virtual address of the region in the target
+/// - size: Size in bytes
+///
+/// Returns: 0 on success, u64::MAX on error
+fn sys_memory_unmap_from(target_tcb_cap: u64, virt_addr: u64, size: u64) -> u64 {
+    use crate::memory::{PAGE_SIZE, VirtAddr as VA, PageMapper};
+    use crate::arch::aarch64::page_table::PageTable;
+    use crate::objects::CapType;
+    use crate::objects::cnode_cdt::CNodeCdt;
+
+    ksyscall_debug!("[syscall] memory_unmap_from: target_tcb_cap={}, virt={:#x}, size={}",
+                    target_tcb_cap, virt_addr, size);
+
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() {
+            ksyscall_debug!("[syscall] memory_unmap_from: no current thread");
+            return u64::MAX;
+        }
+
+        if !(*current_tcb).has_capability(TCB::CAP_MEMORY) {
+            ksyscall_debug!("[syscall] memory_unmap_from: caller lacks CAP_MEMORY capability");
+            return u64::MAX;
+        }
+
+        let cspace_root = (*current_tcb).cspace_root();
+        if cspace_root.is_null() {
+            ksyscall_debug!("[syscall] memory_unmap_from: thread has no CSpace root");
+            return u64::MAX;
+        }
+
+        // Look up target TCB capability
+        let cnode = &*(cspace_root as *const CNodeCdt);
+        let cap = match cnode.lookup_cptr(target_tcb_cap) {
+            Some(c) if c.cap_type() == CapType::Tcb => c,
+            _ => {
+                ksyscall_debug!("[syscall] memory_unmap_from: cap_slot {} is not a TCB", target_tcb_cap);
+                return u64::MAX;
+            }
+        };
+        let target_tcb_ptr = cap.object_ptr() as *mut TCB;
+        if target_tcb_ptr.is_null() {
+            ksyscall_debug!("[syscall] memory_unmap_from: null target TCB pointer");
+            return u64::MAX;
+        }
+
+        let page_table_phys = (*target_tcb_ptr).vspace_root();
+        let mut mapper = PageMapper::new(&mut *(page_table_phys as *mut PageTable));
+
+        // Unmap each page or block in the range (large pages come out whole)
+        let num_pages = size.div_ceil(PAGE_SIZE as u64) as usize;
+        let end = virt_addr as usize + num_pages * PAGE_SIZE;
+        let mut addr = virt_addr as usize;
+        while addr < end {
+            match mapper.unmap_leaf(VA::new(addr)) {
+                Ok(page_size) => addr += page_size.bytes(),
+                Err(e) => {
+                    ksyscall_debug!("[syscall] memory_unmap_from: failed to unmap {:#x}: {:?}", addr, e);
+                    addr += PAGE_SIZE;
+                }
+            }
+        }
+
+        // Flush the target's TLB entries so unmapped pages are not cached
+        crate::memory::asid::flush_vspace(page_table_phys as u64);
+
+        ksyscall_debug!("[syscall] memory_unmap_from -> success ({} pages)", num_pages);
+        0
+    }
+}
+
+/// Change the permissions of memory mapped in a target process
+///
+/// Args:
+/// - target_tcb_cap: TCB capability slot of the target process
+/// - virt_addr: Page-aligned virtual address of the region in the target
+/// - size: Size in bytes (rounded up to pages)
+/// - permissions: 1=read, 2=write, 4=exec
+///
+/// Returns: 0 on success, u64::MAX on error
+///
+/// Only 4KB pages the process can already access are changed, and only if
+/// every page of the range is one, so the kernel's own mappings in the
+/// address space stay out of reach.
+fn sys_memory_protect_in(target_tcb_cap: u64, virt_addr: u64, size: u64, permissions: u64) -> u64 {
+    use crate::memory::{PAGE_SIZE, VirtAddr as VA, PageMapper};
+    use crate::arch::aarch64::page_table::{PageTable, PageTableFlags};
+    use crate::objects::CapType;
+    use crate::objects::cnode_cdt::CNodeCdt;
+
+    ksyscall_debug!("[syscall] memory_protect_in: target_tcb_cap={}, virt={:#x}, size={}, perms={:#x}",
+                    target_tcb_cap, virt_addr, size, permissions);
+
+    if virt_addr as usize % PAGE_SIZE != 0 {
+        ksyscall_debug!("[syscall] memory_protect_in: virt={:#x} is not page-aligned", virt_addr);
+        return u64::MAX;
+    }
+
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() {
+            ksyscall_debug!("[syscall] memory_protect_in: no current thread");
+            return u64::MAX;
+        }
+
+        if !(*current_tcb).has_capability(TCB::CAP_MEMORY) {
+            ksyscall_debug!("[syscall] memory_protect_in: caller lacks CAP_MEMORY capability");
+            return u64::MAX;
+        }
+
+        let cspace_root = (*current_tcb).cspace_root();
+        if cspace_root.is_null() {
+            ksyscall_debug!("[syscall] memory_protect_in: thread has no CSpace root");
+            return u64::MAX;
+        }
+
+        // Look up target TCB capability
+        let cnode = &*(cspace_root as *const CNodeCdt);
+        let cap = match cnode.lookup_cptr(target_tcb_cap) {
+            Some(c) if c.cap_type() == CapType::Tcb => c,
+            _ => {
+                ksyscall_debug!("[syscall] memory_protect_in: cap_slot {} is not a TCB", target_tcb_cap);
+                return u64::MAX;
+            }
+        };
+        let target_tcb_ptr = cap.object_ptr() as *mut TCB;
+        if target_tcb_ptr.is_null() {
+            ksyscall_debug!("[syscall] memory_protect_in: null target TCB pointer");
+            return u64::MAX;
+        }
+
+        let page_table_phys = (*target_tcb_ptr).vspace_root();
+        let mut mapper = PageMapper::new(&mut *(page_table_phys as *mut PageTable));
+        let num_pages = size.div_ceil(PAGE_SIZE as u64) as usize;
+        let page = |i: usize| VA::new(virt_addr as usize + i * PAGE_SIZE);
+
+        // Check the whole range first, so a failure changes nothing
+        for i in 0..num_pages {
+            let user_page = mapper
+                .page_entry(page(i))
+                .is_some_and(|(table, index)| table.get_flags(index).contains(PageTableFlags::AP_RW_ALL));
+            if !user_page {
+                ksyscall_debug!("[syscall] memory_protect_in: {:#x} is not a user page", page(i).as_usize());
+                return u64::MAX;
+            }
+        }
+
+        for i in 0..num_pages {
+            if let Some((table, index)) = mapper.page_entry(page(i)) {
+                if let Some(phys) = table.get_addr(index) {
+                    table.set_entry(index, phys, protect_flags(table.get_flags(index), permissions));
+                }
+            }
+        }
+
+        crate::memory::asid::flush_vspace(page_table_phys as u64);
+
+        ksyscall_debug!("[syscall] memory_protect_in -> success ({} pages)", num_pages);
+        0
+    }
+}
+
+/// Flags of a user page given new permissions (1=read, 2=write, 4=exec)
+///
+/// A shared copy-on-write page stays mapped read-only: left writable, it is
+/// still copied on the first write; made read-only, it is no longer copied,
+/// so a write faults.
+fn protect_flags(
+    flags: crate::arch::aarch64::page_table::PageTableFlags,
+    permissions: u64,
+) -> crate::arch::aarch64::page_table::PageTableFlags {
+    use crate::arch::aarch64::page_table::PageTableFlags;
+
+    let mut flags = flags - PageTableFlags::AP_RO_ALL - PageTableFlags::UXN - PageTableFlags::PXN;
+    let writable = permissions & 0x2 != 0;
+    if !writable {
+        flags -= PageTableFlags::SW_COW;
+    }
+    if writable && !flags.contains(PageTableFlags::SW_COW) {
+        flags |= PageTableFlags::AP_RW_ALL;
+    } else {
+        flags |= PageTableFlags::AP_RO_ALL;
+    }
+    if permissions & 0x4 == 0 {
+        flags |= PageTableFlags::UXN | PageTableFlags::PXN;
+    }
+    flags
+}
+
 /// Retype untyped memory into a kernel object (seL4-style capability-based spawning)
 ///
 /// Args:
@@ -1497,7 +2022,7 @@ fn sys_retype(untyped_cap_slot: u64, object_type: u64, size_bits: u64,
         crate::kprintln!("[syscall] retype: cspace reference created, calling lookup...");
 
         // 1. Lookup UntypedMemory capability
-        let untyped_cap = match caller_cspace.lookup(untyped_cap_slot as usize) {
+        let untyped_cap = match caller_cspace.lookup_cptr(untyped_cap_slot) {
             Some(cap) => cap,
             None => {
                 crate::kprintln!("[syscall] retype: untyped cap not found at slot {}", untyped_cap_slot);
@@ -1541,6 +2066,14 @@ fn sys_retype(untyped_cap_slot: u64, object_type: u64, size_bits: u64,
             }
         };
 
+        // A CNode's slot pointers fill the whole object: 2^(size_bits - 3) slots
+        if target_type == CapType::CNode
+            && !(CNodeCdt::MIN_SIZE_BITS as u64 + 3..=CNodeCdt::MAX_SIZE_BITS as u64 + 3).contains(&size_bits)
+        {
+            crate::kprintln!("[syscall] retype: invalid CNode size 2^{}", size_bits);
+            return u64::MAX;
+        }
+
         // 3. Retype: allocate from untyped memory
         let obj_paddr = match untyped.retype(target_type, size_bits as u8) {
             Ok(paddr) => paddr,
@@ -1558,7 +2091,7 @@ fn sys_retype(untyped_cap_slot: u64, object_type: u64, size_bits: u64,
             // 0 means "use caller's own CSpace"
             cspace_root as *const CNodeCdt
         } else {
-            match caller_cspace.lookup(dest_cnode_cap as usize) {
+            match caller_cspace.lookup_cptr(dest_cnode_cap) {
                 Some(cap) if cap.cap_type() == CapType::CNode => {
                     cap.object_ptr() as *const CNodeCdt
                 }
@@ -1598,8 +2131,32 @@ fn sys_retype(untyped_cap_slot: u64, object_type: u64, size_bits: u64,
                             obj_paddr.as_u64() + (1u64 << size_bits));
 
             // Capability should point to the STRUCT, not the covered region
+            struct_paddr
+        } else if target_type == CapType::CNode {
+            // Like UntypedMemory: the CNodeCdt struct gets its own frame,
+            // the retyped region holds the slots
+            let struct_paddr = match crate::memory::alloc_frame() {
+                Some(frame) => frame.phys_addr(),
+                None => {
+                    crate::kprintln!("[syscall] retype: failed to allocate frame for CNode struct");
+                    return u64::MAX;
+                }
+            };
+            let cnode = CNodeCdt::new(size_bits as u8 - 3, obj_paddr)
+                .expect("[FATAL] Failed to create CNode object");
+            core::ptr::write(struct_paddr.as_usize() as *mut CNodeCdt, cnode);
+
             struct_paddr
         } else {
+            // IPC objects track their capabilities, so they must be valid
+            // before the first one is inserted
+            match target_type {
+                CapType::Endpoint => core::ptr::write(obj_paddr.as_usize() as *mut Endpoint, Endpoint::new()),
+                CapType::Notification => core::ptr::write(obj_paddr.as_usize() as *mut Notification,
+                                                          Notification::new()),
+                _ => {}
+            }
+
             // For other object types, capability points to the allocated memory
             obj_paddr
         };
@@ -1622,6 +2179,81 @@ fn sys_retype(untyped_cap_slot: u64, object_type: u64, size_bits: u64,
     }
 }
 
+/// Register a pager endpoint for a thread's page faults
+///
+/// Args:
+/// - target_tcb_cap: TCB capability slot of the thread to page
+/// - endpoint_cap_slot: Endpoint capability slot in the caller's CSpace,
+///   or u64::MAX to unregister
+///
+/// Returns: 0 on success, u64::MAX on error
+fn sys_tcb_set_fault_handler(target_tcb_cap: u64, endpoint_cap_slot: u64) -> u64 {
+    ksyscall_debug!("[syscall] tcb_set_fault_handler: target_tcb_cap={}, endpoint_cap_slot={}",
+        target_tcb_cap, endpoint_cap_slot);
+
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() {
+            ksyscall_debug!("[syscall] tcb_set_fault_handler: no current thread");
+            return u64::MAX;
+        }
+
+        if !(*current_tcb).has_capability(TCB::CAP_PROCESS) {
+            ksyscall_debug!("[syscall] tcb_set_fault_handler: caller lacks CAP_PROCESS");
+            return u64::MAX;
+        }
+
+        let target = lookup_tcb_capability(target_tcb_cap as usize);
+        if target.is_null() {
+            return u64::MAX;
+        }
+
+        let endpoint = if endpoint_cap_slot == u64::MAX {
+            ptr::null_mut()
+        } else {
+            let endpoint = lookup_endpoint_capability(endpoint_cap_slot as usize);
+            if endpoint.is_null() {
+                ksyscall_debug!("[syscall] tcb_set_fault_handler: endpoint not found for cap_slot {}",
+                    endpoint_cap_slot);
+                return u64::MAX;
+            }
+            endpoint
+        };
+
+        (*target).set_fault_endpoint(endpoint);
+        ksyscall_debug!("[syscall] tcb_set_fault_handler: TID {} -> endpoint {:p}", (*target).tid(), endpoint);
+        0
+    }
+}
+
+/// Resume a thread blocked on a page fault
+///
+/// Args:
+/// - target_tcb_cap: TCB capability slot of the faulted thread
+///
+/// Returns: 0 on success, u64::MAX on error
+///
+/// The thread retries the faulting instruction, so the pager must have
+/// mapped the page first.
+fn sys_fault_resume(target_tcb_cap: u64) -> u64 {
+    ksyscall_debug!("[syscall] fault_resume: target_tcb_cap={}", target_tcb_cap);
+
+    unsafe {
+        let target = lookup_tcb_capability(target_tcb_cap as usize);
+        if target.is_null() {
+            return u64::MAX;
+        }
+
+        if !crate::ipc::fault::resume_faulted(target) {
+            ksyscall_debug!("[syscall] fault_resume: TID {} is not blocked on a fault", (*target).tid());
+            return u64::MAX;
+        }
+
+        ksyscall_debug!("[syscall] fault_resume: TID {} resumed", (*target).tid());
+        0
+    }
+}
+
 /// Insert capability into target process's CSpace (Phase 5)
 ///
 /// Args:
@@ -1672,7 +2304,7 @@ fn sys_cap_insert_into(target_tcb_cap: u64, target_slot: u64, cap_type: u64, obj
         crate::kprintln!("[syscall] cap_insert_into: casting cspace_root to CNodeCdt...");
         let cnode = &*(cspace_root as *const CNodeCdt);
         crate::kprintln!("[syscall] cap_insert_into: looking up TCB cap at slot {}...", target_tcb_cap);
-        let tcb_cap = match cnode.lookup(target_tcb_cap as usize) {
+        let tcb_cap = match cnode.lookup_cptr(target_tcb_cap) {
             Some(c) => c,
             None => {
                 crate::kprintln!("[syscall] cap_insert_into: TCB cap_slot {} not found", target_tcb_cap);
@@ -1755,7 +2387,9 @@ fn sys_cap_insert_into(target_tcb_cap: u64, target_slot: u64, cap_type: u64, obj
 ///
 /// Recursively deletes the capability at the specified slot in the caller's CSpace
 /// along with all capabilities derived from it. This implements seL4's capability
-/// revocation using the CDT (Capability Derivation Tree).
+/// revocation using the CDT (Capability Derivation Tree). Derived capabilities
+/// are removed from whichever CSpace holds them, and threads blocked on an
+/// endpoint or notification whose last capability goes are cancelled.
 ///
 /// # Arguments
 /// - cnode_cap: CNode capability slot (currently unused, uses caller's CSpace root)
@@ -1804,7 +2438,7 @@ fn sys_cap_revoke(cnode_cap: u64, slot: u64) -> u64 {
         } else {
             // ✅ seL4-style implementation: Lookup CNode capability with proper validation
             // 1. Lookup the CNode capability from caller's CSpace
-            let cnode_capability = match caller_cspace.lookup(cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_revoke: CNode capability not found at slot {}", cnode_cap);
@@ -1895,7 +2529,7 @@ fn sys_cap_derive(cnode_cap: u64, src_slot: u64, dest_slot: u64, new_rights: u64
             &mut *(cspace_root as *mut CNodeCdt)
         } else {
             // Lookup CNode capability with validation
-            let cnode_capability = match caller_cspace.lookup(cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_derive: CNode not found at slot {}", cnode_cap);
@@ -1936,14 +2570,15 @@ fn sys_cap_derive(cnode_cap: u64, src_slot: u64, dest_slot: u64, new_rights: u64
     }
 }
 
-/// Mint a badged endpoint capability
+/// Mint a badged endpoint or notification capability
 ///
-/// Creates a badged child capability for IPC endpoint identification.
-/// The badge allows the receiver to identify which endpoint was used.
+/// Creates a badged child capability so a server can tell its clients
+/// apart: the badge is returned with every received message, or signalled
+/// into the notification word.
 ///
 /// # Arguments
 /// - cnode_cap: CNode capability slot
-/// - src_slot: Source endpoint capability slot
+/// - src_slot: Source endpoint or notification capability slot (unbadged)
 /// - dest_slot: Destination slot (must be empty)
 /// - badge: Badge value (non-zero)
 ///
@@ -1953,7 +2588,7 @@ fn sys_cap_derive(cnode_cap: u64, src_slot: u64, dest_slot: u64, new_rights: u64
 /// # Security
 /// - Requires CAP_CAPS permission
 /// - Requires WRITE rights on CNode capability
-/// - Source must be an endpoint capability
+/// - Source must be an unbadged endpoint or notification capability
 fn sys_cap_mint(cnode_cap: u64, src_slot: u64, dest_slot: u64, badge: u64) -> u64 {
     use crate::objects::cnode_cdt::CNodeCdt;
     use crate::objects::{CapType, CapRights};
@@ -1987,7 +2622,7 @@ fn sys_cap_mint(cnode_cap: u64, src_slot: u64, dest_slot: u64, badge: u64) -> u6
             &mut *(cspace_root as *mut CNodeCdt)
         } else {
             // Lookup CNode capability with validation
-            let cnode_capability = match caller_cspace.lookup(cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_mint: CNode not found at slot {}", cnode_cap);
@@ -2053,11 +2688,11 @@ fn sys_cap_copy(src_cnode_cap: u64, src_slot: u64, dest_cnode_cap: u64, dest_slo
         let caller_cspace = &*(cspace_root as *const CNodeCdt);
 
         // Get source CNode
-        let src_cnode = if src_cnode_cap == 0 {
+        let src_cnode: *mut CNodeCdt = if src_cnode_cap == 0 {
             ksyscall_debug!("[syscall] cap_copy: using caller's own CSpace as source");
-            &mut *(cspace_root as *mut CNodeCdt)
+            cspace_root as *mut CNodeCdt
         } else {
-            let cnode_capability = match caller_cspace.lookup(src_cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(src_cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_copy: source CNode not found");
@@ -2075,18 +2710,18 @@ fn sys_cap_copy(src_cnode_cap: u64, src_slot: u64, dest_cnode_cap: u64, dest_slo
                 return u64::MAX;
             }
 
-            &mut *(cnode_capability.object_ptr() as *mut CNodeCdt)
+            cnode_capability.object_ptr() as *mut CNodeCdt
         };
 
         // Get destination CNode
-        let dest_cnode = if dest_cnode_cap == 0 {
+        let dest_cnode: *mut CNodeCdt = if dest_cnode_cap == 0 {
             ksyscall_debug!("[syscall] cap_copy: using caller's own CSpace as dest");
-            &mut *(cspace_root as *mut CNodeCdt)
+            cspace_root as *mut CNodeCdt
         } else if dest_cnode_cap == src_cnode_cap {
             // Same CNode for source and dest
             src_cnode
         } else {
-            let cnode_capability = match caller_cspace.lookup(dest_cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(dest_cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_copy: dest CNode not found");
@@ -2104,10 +2739,10 @@ fn sys_cap_copy(src_cnode_cap: u64, src_slot: u64, dest_cnode_cap: u64, dest_slo
                 return u64::MAX;
             }
 
-            &mut *(cnode_capability.object_ptr() as *mut CNodeCdt)
+            cnode_capability.object_ptr() as *mut CNodeCdt
         };
 
-        match dest_cnode.copy(src_slot as usize, dest_slot as usize) {
+        match CNodeCdt::copy_between(src_cnode, src_slot as usize, dest_cnode, dest_slot as usize) {
             Ok(()) => {
                 ksyscall_debug!("[syscall] cap_copy: ✓ copied cap from slot {} to {}",
                               src_slot, dest_slot);
@@ -2152,7 +2787,7 @@ fn sys_cap_delete(cnode_cap: u64, slot: u64) -> u64 {
             ksyscall_debug!("[syscall] cap_delete: using caller's own CSpace");
             &mut *(cspace_root as *mut CNodeCdt)
         } else {
-            let cnode_capability = match caller_cspace.lookup(cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_delete: CNode not found");
@@ -2219,7 +2854,7 @@ fn sys_cap_move(src_cnode_cap: u64, src_slot: u64, dest_cnode_cap: u64, dest_slo
             ksyscall_debug!("[syscall] cap_move: using caller's own CSpace");
             &mut *(cspace_root as *mut CNodeCdt)
         } else if src_cnode_cap == dest_cnode_cap && src_cnode_cap != 0 {
-            let cnode_capability = match caller_cspace.lookup(src_cnode_cap as usize) {
+            let cnode_capability = match caller_cspace.lookup_cptr(src_cnode_cap) {
                 Some(cap) => cap,
                 None => {
                     ksyscall_debug!("[syscall] cap_move: CNode not found");
@@ -2257,6 +2892,88 @@ fn sys_cap_move(src_cnode_cap: u64, src_slot: u64, dest_cnode_cap: u64, dest_slo
     }
 }
 
+/// Set the guard of a CNode capability
+///
+/// # Arguments
+/// - cnode_cap: CNode holding the capability (0 = caller's CSpace root)
+/// - slot: Slot of the CNode capability to change
+/// - guard: Guard value, matched against the address bits during lookup
+/// - guard_bits: Guard size in bits
+///
+/// # Returns
+/// 0 on success, u64::MAX on error
+///
+/// # Security
+/// - Requires CAP_CAPS permission
+/// - Requires WRITE rights on the holding CNode capability
+/// - The guard only changes how the CNode is addressed, not any rights
+fn sys_cnode_set_guard(cnode_cap: u64, slot: u64, guard: u64, guard_bits: u64) -> u64 {
+    use crate::objects::cnode_cdt::{encode_guard, CNodeCdt};
+    use crate::objects::{CapType, CapRights};
+
+    ksyscall_debug!("[syscall] cnode_set_guard: cnode={}, slot={}, guard={:#x}/{}",
+                  cnode_cap, slot, guard, guard_bits);
+
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() {
+            return u64::MAX;
+        }
+
+        if !(*current_tcb).has_capability(TCB::CAP_CAPS) {
+            ksyscall_debug!("[syscall] cnode_set_guard: caller lacks CAP_CAPS");
+            return u64::MAX;
+        }
+
+        let cspace_root = (*current_tcb).cspace_root();
+        if cspace_root.is_null() {
+            return u64::MAX;
+        }
+
+        let caller_cspace = &*(cspace_root as *const CNodeCdt);
+
+        let target_cnode = if cnode_cap == 0 {
+            &mut *(cspace_root as *mut CNodeCdt)
+        } else {
+            let cnode_capability = match caller_cspace.lookup_cptr(cnode_cap) {
+                Some(cap) => cap,
+                None => {
+                    ksyscall_debug!("[syscall] cnode_set_guard: CNode not found");
+                    return u64::MAX;
+                }
+            };
+
+            if cnode_capability.cap_type() != CapType::CNode {
+                ksyscall_debug!("[syscall] cnode_set_guard: not a CNode");
+                return u64::MAX;
+            }
+
+            if !cnode_capability.rights().contains(CapRights::WRITE) {
+                ksyscall_debug!("[syscall] cnode_set_guard: insufficient rights");
+                return u64::MAX;
+            }
+
+            &mut *(cnode_capability.object_ptr() as *mut CNodeCdt)
+        };
+
+        let Some(word) = u32::try_from(guard_bits).ok().and_then(|bits| encode_guard(guard, bits)) else {
+            ksyscall_debug!("[syscall] cnode_set_guard: invalid guard {:#x}/{}", guard, guard_bits);
+            return u64::MAX;
+        };
+
+        match target_cnode.lookup_mut(slot as usize) {
+            Some(cap) if cap.cap_type() == CapType::CNode => {
+                cap.set_guard(word);
+                0
+            }
+            _ => {
+                ksyscall_debug!("[syscall] cnode_set_guard: slot {} is not a CNode capability", slot);
+                u64::MAX
+            }
+        }
+    }
+}
+
 /// Insert capability into caller's own CSpace
 ///
 /// Simpler variant of sys_cap_insert_into that operates on the caller's CSpace.
@@ -2341,22 +3058,150 @@ fn sys_cap_insert_self(cap_slot: u64, cap_type: u64, object_ptr: u64) -> u64 {
     }
 }
 
-/// IPC Send: Send message to endpoint
-///
-/// Args:
-/// - endpoint_cap_slot: Capability slot for endpoint
-/// - message_ptr: Pointer to message data (in user space)
-/// - message_len: Length of message data
+/// Largest message carried by SYS_SEND / SYS_CALL / SYS_REPLY (bytes)
+pub(crate) const IPC_MAX_MESSAGE: usize = 256;
+
+/// Copy a message into a blocked thread's user buffer
 ///
-/// Returns:
-/// - 0 on success
-/// - u64::MAX on error
-fn sys_ipc_send(tf: &mut TrapFrame, endpoint_cap_slot: u64, message_ptr: u64, message_len: u64) -> u64 {
+/// On success the thread's saved x0 (its syscall return value) is set to
+/// the message length.
+unsafe fn deliver_into(thread: &mut TCB, message: &[u8], buffer_ptr: u64, buffer_len: u64) -> bool {
+    if message.len() as u64 > buffer_len {
+        return false;
+    }
+
+    let ttbr0 = thread.context().saved_ttbr0;
+    if !message.is_empty() && !copy_to_user(message, buffer_ptr, message.len(), ttbr0) {
+        return false;
+    }
+
+    thread.context_mut().x0 = message.len() as u64;
+    true
+}
+
+/// Deliver a message to a thread blocked in SYS_RECV or SYS_REPLY_RECV
+///
+/// The receive buffer is in the receiver's saved x1/x2 (its syscall args).
+/// On success the sender's badge is returned to the receiver in x1, and a
+/// receiver in SYS_RECV_CAP gets 0 in x2 (no capability; see
+/// `transfer_cap` for senders that have one).
+pub(crate) unsafe fn deliver_to_receiver(receiver: &mut TCB, message: &[u8], badge: u64) -> bool {
+    let (buffer_ptr, buffer_len) = (receiver.context().x1, receiver.context().x2);
+    if !deliver_into(receiver, message, buffer_ptr, buffer_len) {
+        return false;
+    }
+    receiver.context_mut().x1 = badge;
+    if receiver.take_cap_receive_slot().is_some() {
+        receiver.context_mut().x2 = 0;
+    }
+    true
+}
+
+/// Copy the capability in `sender`'s `cap_slot` to `receiver`'s `dest_slot`
+///
+/// The copy is a CDT child of the sender's capability. Fails if that has
+/// no GRANT right, or `dest_slot` is taken.
+unsafe fn transfer_cap(sender: &TCB, cap_slot: usize, receiver: &TCB, dest_slot: usize) -> bool {
+    use crate::objects::cnode_cdt::CNodeCdt;
+    use crate::objects::CapRights;
+
+    let src = sender.cspace_root() as *mut CNodeCdt;
+    let dest = receiver.cspace_root() as *mut CNodeCdt;
+    if src.is_null() || dest.is_null() {
+        return false;
+    }
+    match (*src).lookup(cap_slot) {
+        Some(cap) if cap.rights().contains(CapRights::GRANT) => {}
+        _ => {
+            ksyscall_debug!("[syscall] IPC cap transfer: slot {} empty or lacks GRANT", cap_slot);
+            return false;
+        }
+    }
+    match CNodeCdt::copy_between(src, cap_slot, dest, dest_slot) {
+        Ok(()) => true,
+        Err(e) => {
+            ksyscall_debug!("[syscall] IPC cap transfer: copy to slot {} failed: {:?}", dest_slot, e);
+            false
+        }
+    }
+}
+
+/// Deliver a reply to a thread blocked in SYS_CALL
+///
+/// The reply buffer is in the caller's saved x3/x4 (its syscall args).
+pub(crate) unsafe fn deliver_reply(caller: &mut TCB, message: &[u8]) -> bool {
+    let (buffer_ptr, buffer_len) = (caller.context().x3, caller.context().x4);
+    deliver_into(caller, message, buffer_ptr, buffer_len)
+}
+
+/// Read the message of a thread blocked in SYS_SEND or SYS_CALL
+///
+/// A blocked sender's message is still in its own address space, at its
+/// saved x1 with length x2 (its syscall args). Returns the length.
+unsafe fn read_blocked_message(sender: &TCB, buffer: &mut [u8; IPC_MAX_MESSAGE]) -> Option<usize> {
+    let context = sender.context();
+    let len = context.x2 as usize;
+    if len > IPC_MAX_MESSAGE {
+        return None;
+    }
+    if len > 0 && !copy_from_user(context.x1, buffer, len, context.saved_ttbr0) {
+        return None;
+    }
+    Some(len)
+}
+
+/// Make `next` the running thread and return into it from this exception
+///
+/// The exception return restores `tf`, so replacing it with `next`'s saved
+/// context resumes `next` (including its TTBR0). If `next` is the idle
+/// thread, the CPU idles until some thread is runnable, and that one is
+/// resumed instead.
+pub(crate) unsafe fn switch_to(tf: &mut TrapFrame, next: *mut TCB) {
+    let next = if next == crate::scheduler::idle_thread() {
+        IDLED = true;
+        crate::scheduler::idle::wait_for_thread()
+    } else {
+        next
+    };
+    let next_tcb = &mut *next;
+    next_tcb.set_state(crate::objects::ThreadState::Running);
+    crate::scheduler::test_set_current_thread(next);
+    *tf = *next_tcb.context();
+}
+
+/// Switch away from the current thread after it blocked
+///
+/// The current thread's context must already be saved and its state set
+/// to a blocked state. Returns false if there is nothing else to run.
+unsafe fn block_and_switch(tf: &mut TrapFrame) -> bool {
+    let next = crate::scheduler::schedule();
+    if next.is_null() {
+        crate::kprintln!("[syscall] IPC: blocked but no other thread available!");
+        return false;
+    }
+    switch_to(tf, next);
+    true
+}
+
+/// IPC Send: Send message to endpoint
+///
+/// Args:
+/// - endpoint_cap_slot: Capability slot for endpoint
+/// - message_ptr: Pointer to message data (in user space)
+/// - message_len: Length of message data
+/// - cap_slot: Capability to send along (SYS_SEND_CAP), if any
+///
+/// Returns:
+/// - 0 on success
+/// - u64::MAX on error
+fn sys_ipc_send(tf: &mut TrapFrame, endpoint_cap_slot: u64, message_ptr: u64, message_len: u64,
+                cap_slot: Option<u64>) -> u64 {
+    let cap_slot = cap_slot.map(|slot| slot as usize);
     ksyscall_debug!("[syscall] IPC Send: endpoint={}, msg_ptr=0x{:x}, len={}",
         endpoint_cap_slot, message_ptr, message_len);
 
     // Validate message length (max 256 bytes)
-    if message_len > 256 {
+    if message_len as usize > IPC_MAX_MESSAGE {
         ksyscall_debug!("[syscall] IPC Send -> error: message too large ({} bytes)", message_len);
         return u64::MAX;
     }
@@ -2376,7 +3221,7 @@ fn sys_ipc_send(tf: &mut TrapFrame, endpoint_cap_slot: u64, message_ptr: u64, me
         }
 
         // Look up endpoint from capability slot
-        let endpoint_ptr = lookup_endpoint_capability(endpoint_cap_slot as usize);
+        let (endpoint_ptr, badge) = lookup_badged_endpoint(endpoint_cap_slot as usize);
         if endpoint_ptr.is_null() {
             ksyscall_debug!("[syscall] IPC Send -> error: endpoint not found for cap_slot {}", endpoint_cap_slot);
             return u64::MAX;
@@ -2384,33 +3229,35 @@ fn sys_ipc_send(tf: &mut TrapFrame, endpoint_cap_slot: u64, message_ptr: u64, me
 
         let endpoint = &mut *endpoint_ptr;
 
-        // Copy message from userspace to kernel buffer
-        let mut kernel_msg_buffer = [0u8; 256];
-        if !copy_from_user(message_ptr, &mut kernel_msg_buffer, message_len as usize, tf.saved_ttbr0) {
-            ksyscall_debug!("[syscall] IPC Send -> error: failed to copy message from userspace");
-            return u64::MAX;
-        }
-
-        ksyscall_debug!("[syscall] IPC Send: copied {} bytes from userspace", message_len);
-
         // Check if there's a receiver waiting
         if let Some(receiver_tcb) = endpoint.dequeue_receiver() {
             ksyscall_debug!("[syscall] IPC Send: found waiting receiver, transferring message");
 
-            // Copy message to receiver's IPC buffer
-            let receiver = &mut *receiver_tcb;
-            let receiver_context = receiver.context();
-            let receiver_ttbr0 = receiver_context.saved_ttbr0;
-            let receiver_ipc_buffer = receiver.ipc_buffer().as_u64();
+            // Copy message from userspace to kernel buffer
+            let mut kernel_msg_buffer = [0u8; IPC_MAX_MESSAGE];
+            let len = message_len as usize;
+            if len > 0 && !copy_from_user(message_ptr, &mut kernel_msg_buffer, len, tf.saved_ttbr0) {
+                ksyscall_debug!("[syscall] IPC Send -> error: failed to copy message from userspace");
+                endpoint.queue_receive(receiver_tcb);
+                return u64::MAX;
+            }
 
-            if !copy_to_user(&kernel_msg_buffer[..message_len as usize], receiver_ipc_buffer, message_len as usize, receiver_ttbr0) {
+            // Copy message to the receiver's buffer
+            let receiver = &mut *receiver_tcb;
+            let receive_slot = receiver.cap_receive_slot();
+            if !deliver_to_receiver(receiver, &kernel_msg_buffer[..len], badge) {
                 ksyscall_debug!("[syscall] IPC Send -> error: failed to copy message to receiver");
+                endpoint.queue_receive(receiver_tcb);
                 return u64::MAX;
             }
 
-            // Store message length in receiver's x0 (return value)
-            let receiver_ctx_mut = receiver.context_mut();
-            receiver_ctx_mut.x0 = message_len;
+            // And the capability, if both sides have one
+            if let (Some(cap_slot), Some(dest_slot)) = (cap_slot, receive_slot) {
+                receiver.context_mut().x2 = transfer_cap(&*current, cap_slot, receiver, dest_slot) as u64;
+            }
+
+            // Plain send: nothing to reply to
+            receiver.set_reply_to(ptr::null_mut());
 
             // Wake up receiver
             receiver.set_state(crate::objects::ThreadState::Runnable);
@@ -2423,26 +3270,19 @@ fn sys_ipc_send(tf: &mut TrapFrame, endpoint_cap_slot: u64, message_ptr: u64, me
         // No receiver waiting - block sender on endpoint's send queue
         ksyscall_debug!("[syscall] IPC Send: no receiver waiting, blocking sender");
 
-        // Store message in sender's IPC buffer for later transfer
-        let sender = &mut *current;
-        let sender_ipc_buffer = sender.ipc_buffer().as_u64();
-        if !copy_to_user(&kernel_msg_buffer[..message_len as usize], sender_ipc_buffer, message_len as usize, tf.saved_ttbr0) {
-            ksyscall_debug!("[syscall] IPC Send -> error: failed to store message in sender's IPC buffer");
-            return u64::MAX;
-        }
-
-        // Store message length in sender's context for later retrieval
-        let sender_ctx_mut = sender.context_mut();
-        sender_ctx_mut.x2 = message_len;
-
-        // Block sender on endpoint
+        // The saved context keeps message_ptr/len (x1/x2) for the receiver
+        *(*current).context_mut() = *tf;
+        (*current).set_ipc_badge(badge);
+        (*current).set_ipc_cap(cap_slot);
         endpoint.queue_send(current);
 
-        // Context switch to next runnable thread
-        crate::scheduler::yield_current();
+        if !block_and_switch(tf) {
+            endpoint.dequeue_specific_sender(current);
+            (*current).set_state(crate::objects::ThreadState::Running);
+            return u64::MAX;
+        }
 
-        // When we return here, message has been delivered
-        ksyscall_debug!("[syscall] IPC Send -> success after blocking");
+        // Not seen by the sender: the receiver sets its x0 on delivery
         0
     }
 }
@@ -2453,16 +3293,24 @@ fn sys_ipc_send(tf: &mut TrapFrame, endpoint_cap_slot: u64, message_ptr: u64, me
 /// - endpoint_cap_slot: Capability slot for endpoint
 /// - buffer_ptr: Pointer to receive buffer (in user space)
 /// - buffer_len: Length of receive buffer
+/// - receive_slot: Slot for a capability sent along (SYS_RECV_CAP), if any
 ///
 /// Returns:
-/// - Number of bytes received on success
+/// - Number of bytes received on success, with the badge of the sender's
+///   endpoint capability in x1 (0 if unbadged), and with a receive slot
+///   1 in x2 if a capability was put there (0 if not)
 /// - u64::MAX on error
-fn sys_ipc_recv(tf: &mut TrapFrame, endpoint_cap_slot: u64, buffer_ptr: u64, buffer_len: u64) -> u64 {
+///
+/// If the message came from SYS_CALL, the caller stays blocked until this
+/// thread answers with SYS_REPLY or SYS_REPLY_RECV.
+fn sys_ipc_recv(tf: &mut TrapFrame, endpoint_cap_slot: u64, buffer_ptr: u64, buffer_len: u64,
+                receive_slot: Option<u64>) -> u64 {
+    let receive_slot = receive_slot.map(|slot| slot as usize);
     ksyscall_debug!("[syscall] IPC Recv: endpoint={}, buf_ptr=0x{:x}, len={}",
         endpoint_cap_slot, buffer_ptr, buffer_len);
 
     // Validate buffer length
-    if buffer_len > 256 {
+    if buffer_len as usize > IPC_MAX_MESSAGE {
         ksyscall_debug!("[syscall] IPC Recv -> error: buffer too large ({} bytes)", buffer_len);
         return u64::MAX;
     }
@@ -2495,63 +3343,104 @@ fn sys_ipc_recv(tf: &mut TrapFrame, endpoint_cap_slot: u64, buffer_ptr: u64, buf
             ksyscall_debug!("[syscall] IPC Recv: found waiting sender, transferring message");
 
             let sender = &mut *sender_tcb;
+            let sender_state = sender.state();
+
+            // A faulted thread's message is built by the kernel, and the
+            // thread stays blocked until the pager resumes it
+            if let crate::objects::ThreadState::BlockedOnFault { .. } = sender_state {
+                let message = crate::ipc::FaultMessage::from_thread(sender).to_bytes();
+                let Some(message) = crate::ipc::fault::fitting(&message, buffer_len as usize)
+                    .filter(|message| copy_to_user(message, buffer_ptr, message.len(), tf.saved_ttbr0))
+                else {
+                    ksyscall_debug!("[syscall] IPC Recv -> error: failed to deliver fault message");
+                    endpoint.queue_send(sender_tcb);
+                    sender.block_on_fault(endpoint_ptr as usize);
+                    return u64::MAX;
+                };
 
-            // Retrieve message length from sender's context (stored during send)
-            let sender_context = sender.context();
-            let message_len = sender_context.x2 as usize;
-
-            if message_len > buffer_len as usize {
-                ksyscall_debug!("[syscall] IPC Recv -> error: sender message ({} bytes) larger than buffer ({} bytes)",
-                         message_len, buffer_len);
-                return u64::MAX;
+                (*current).set_reply_to(ptr::null_mut());
+                tf.x1 = 0;
+                ksyscall_debug!("[syscall] IPC Recv -> success, fault message from TID {}", sender.tid());
+                return message.len() as u64;
             }
 
-            // Copy message from sender's IPC buffer to kernel buffer
-            let mut kernel_msg_buffer = [0u8; 256];
-            let sender_ttbr0 = sender_context.saved_ttbr0;
-            let sender_ipc_buffer = sender.ipc_buffer().as_u64();
+            // Copy message from the sender's buffer to the receiver's buffer
+            let mut kernel_msg_buffer = [0u8; IPC_MAX_MESSAGE];
+            let message_len = match read_blocked_message(sender, &mut kernel_msg_buffer) {
+                Some(len) if len <= buffer_len as usize => len,
+                _ => {
+                    ksyscall_debug!("[syscall] IPC Recv -> error: sender message ({} bytes) does not fit buffer ({} bytes)",
+                             sender.context().x2, buffer_len);
+                    endpoint.queue_send(sender_tcb);
+                    sender.set_state(sender_state);
+                    return u64::MAX;
+                }
+            };
 
-            if !copy_from_user(sender_ipc_buffer, &mut kernel_msg_buffer, message_len, sender_ttbr0) {
-                ksyscall_debug!("[syscall] IPC Recv -> error: failed to copy message from sender's IPC buffer");
+            if message_len > 0
+                && !copy_to_user(&kernel_msg_buffer[..message_len], buffer_ptr, message_len, tf.saved_ttbr0) {
+                ksyscall_debug!("[syscall] IPC Recv -> error: failed to copy message to receiver's buffer");
+                endpoint.queue_send(sender_tcb);
+                sender.set_state(sender_state);
                 return u64::MAX;
             }
 
-            // Copy message to receiver's buffer
-            if !copy_to_user(&kernel_msg_buffer[..message_len], buffer_ptr, message_len, tf.saved_ttbr0) {
-                ksyscall_debug!("[syscall] IPC Recv -> error: failed to copy message to receiver's buffer");
-                return u64::MAX;
+            if let Some(dest_slot) = receive_slot {
+                let received = sender.ipc_cap()
+                    .is_some_and(|cap_slot| transfer_cap(sender, cap_slot, &*current, dest_slot));
+                tf.x2 = received as u64;
             }
 
-            // Wake up sender
-            sender.set_state(crate::objects::ThreadState::Runnable);
-            crate::scheduler::enqueue(sender_tcb);
+            if sender_state == crate::objects::ThreadState::BlockedOnReply {
+                // SYS_CALL: the caller waits for our reply
+                (*current).set_reply_to(sender_tcb);
+            } else {
+                // SYS_SEND: wake up sender (its send returns 0)
+                (*current).set_reply_to(ptr::null_mut());
+                sender.context_mut().x0 = 0;
+                sender.set_state(crate::objects::ThreadState::Runnable);
+                crate::scheduler::enqueue(sender_tcb);
+            }
 
-            ksyscall_debug!("[syscall] IPC Recv -> success, received {} bytes from sender", message_len);
+            // The badge is returned alongside the length
+            tf.x1 = sender.ipc_badge();
+
+            ksyscall_debug!("[syscall] IPC Recv -> success, received {} bytes from sender (badge {:#x})",
+                message_len, sender.ipc_badge());
             return message_len as u64;
         }
 
+        // Signals pending on our bound notification end the receive at once
+        let bound = (*current).bound_notification();
+        if !bound.is_null() {
+            let signals = (*bound).poll();
+            if signals != 0 {
+                (*current).set_reply_to(ptr::null_mut());
+                tf.x1 = signals;
+                if receive_slot.is_some() {
+                    tf.x2 = 0;
+                }
+                ksyscall_debug!("[syscall] IPC Recv -> bound notification signals {:#x}", signals);
+                return 0;
+            }
+        }
+
         // No sender waiting - block receiver on endpoint's recv queue
         ksyscall_debug!("[syscall] IPC Recv: no sender waiting, blocking receiver");
 
-        let receiver = &mut *current;
-
-        // Store buffer info in receiver's context for later use
-        let receiver_ctx_mut = receiver.context_mut();
-        receiver_ctx_mut.x1 = buffer_ptr;
-        receiver_ctx_mut.x2 = buffer_len;
-
-        // Block receiver on endpoint
+        // The saved context keeps buffer_ptr/len (x1/x2) for the sender
+        *(*current).context_mut() = *tf;
+        (*current).set_cap_receive_slot(receive_slot);
         endpoint.queue_receive(current);
 
-        // Context switch to next runnable thread
-        crate::scheduler::yield_current();
+        if !block_and_switch(tf) {
+            endpoint.dequeue_specific_receiver(current);
+            (*current).set_state(crate::objects::ThreadState::Running);
+            return u64::MAX;
+        }
 
-        // When we return here, message has been received
-        // The message length is stored in x0 by the sender
-        let final_context = (*current).context();
-        let bytes_received = final_context.x0;
-        ksyscall_debug!("[syscall] IPC Recv -> success after blocking, received {} bytes", bytes_received);
-        bytes_received
+        // Not seen by the receiver: the sender sets its x0 on delivery
+        0
     }
 }
 
@@ -2567,47 +3456,177 @@ fn sys_ipc_recv(tf: &mut TrapFrame, endpoint_cap_slot: u64, buffer_ptr: u64, buf
 /// Returns:
 /// - Number of bytes in reply on success
 /// - u64::MAX on error
+///
+/// This is the slow path; the common case (receiver already waiting, no
+/// higher-priority thread ready) is handled by `fastpath::try_fastpath`
+/// before the dispatcher runs.
 fn sys_ipc_call(tf: &mut TrapFrame, endpoint_cap_slot: u64, request_ptr: u64, request_len: u64,
                 reply_ptr: u64, reply_len: u64) -> u64 {
     ksyscall_debug!("[syscall] IPC Call: endpoint={}, req_ptr=0x{:x}, req_len={}, rep_ptr=0x{:x}, rep_len={}",
         endpoint_cap_slot, request_ptr, request_len, reply_ptr, reply_len);
 
-    // TODO: Full implementation
-    // 1. Validate endpoint_cap_slot
-    // 2. Get current TCB
-    // 3. Copy request from userspace
-    // 4. Call ipc::call(endpoint, tcb, request_message)
-    // 5. Handle blocking/context switch
-    // 6. Copy reply to userspace
+    if request_len as usize > IPC_MAX_MESSAGE {
+        ksyscall_debug!("[syscall] IPC Call -> error: request too large ({} bytes)", request_len);
+        return u64::MAX;
+    }
 
-    // For Phase 2, return 0 bytes to test the syscall path
-    ksyscall_debug!("[syscall] IPC Call -> success (stub, 0 bytes)");
-    0
+    if endpoint_cap_slot >= 4096 {
+        ksyscall_debug!("[syscall] IPC Call -> error: invalid endpoint cap slot {}", endpoint_cap_slot);
+        return u64::MAX;
+    }
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            ksyscall_debug!("[syscall] IPC Call -> error: no current thread");
+            return u64::MAX;
+        }
+
+        let (endpoint_ptr, badge) = lookup_badged_endpoint(endpoint_cap_slot as usize);
+        if endpoint_ptr.is_null() {
+            ksyscall_debug!("[syscall] IPC Call -> error: endpoint not found for cap_slot {}", endpoint_cap_slot);
+            return u64::MAX;
+        }
+
+        let endpoint = &mut *endpoint_ptr;
+
+        if let Some(receiver_tcb) = endpoint.dequeue_receiver() {
+            let mut kernel_msg_buffer = [0u8; IPC_MAX_MESSAGE];
+            let len = request_len as usize;
+            let receiver = &mut *receiver_tcb;
+            if (len > 0 && !copy_from_user(request_ptr, &mut kernel_msg_buffer, len, tf.saved_ttbr0))
+                || !deliver_to_receiver(receiver, &kernel_msg_buffer[..len], badge) {
+                ksyscall_debug!("[syscall] IPC Call -> error: failed to transfer request");
+                endpoint.queue_receive(receiver_tcb);
+                return u64::MAX;
+            }
+
+            // The receiver owes us a reply
+            receiver.set_reply_to(current);
+            receiver.set_state(crate::objects::ThreadState::Runnable);
+            crate::scheduler::enqueue(receiver_tcb);
+
+            *(*current).context_mut() = *tf;
+            (*current).block_on_reply();
+        } else {
+            // The saved context keeps request_ptr/len (x1/x2) for the
+            // receiver and reply_ptr/len (x3/x4) for the reply
+            *(*current).context_mut() = *tf;
+            (*current).set_ipc_badge(badge);
+            (*current).set_ipc_cap(None);
+            endpoint.queue_send(current);
+            (*current).block_on_reply();
+        }
+
+        ksyscall_debug!("[syscall] IPC Call: blocking caller for reply");
+
+        if !block_and_switch(tf) {
+            return u64::MAX;
+        }
+
+        // Not seen by the caller: the replier sets its x0
+        0
+    }
+}
+
+/// Answer the caller the current thread owes a reply to
+///
+/// The caller is woken in any case; if the reply cannot be delivered its
+/// SYS_CALL returns u64::MAX. Returns false if there is no caller or the
+/// reply message could not be read.
+unsafe fn reply_to_caller(tf: &TrapFrame, current: *mut TCB, message_ptr: u64, message_len: u64) -> bool {
+    let caller_tcb = (*current).reply_to();
+    if caller_tcb.is_null() {
+        ksyscall_debug!("[syscall] IPC Reply -> error: no caller to reply to");
+        return false;
+    }
+    (*current).set_reply_to(ptr::null_mut());
+
+    let caller = &mut *caller_tcb;
+    if caller.state() != crate::objects::ThreadState::BlockedOnReply {
+        ksyscall_debug!("[syscall] IPC Reply -> error: caller TID {} is not waiting for a reply", caller.tid());
+        return false;
+    }
+
+    let mut kernel_msg_buffer = [0u8; IPC_MAX_MESSAGE];
+    let len = message_len as usize;
+    let read_ok = len <= IPC_MAX_MESSAGE
+        && (len == 0 || copy_from_user(message_ptr, &mut kernel_msg_buffer, len, tf.saved_ttbr0));
+
+    if !read_ok || !deliver_reply(caller, &kernel_msg_buffer[..len]) {
+        ksyscall_debug!("[syscall] IPC Reply: reply ({} bytes) not delivered to TID {}", len, caller.tid());
+        caller.context_mut().x0 = u64::MAX;
+    }
+
+    caller.set_state(crate::objects::ThreadState::Runnable);
+    crate::scheduler::enqueue(caller_tcb);
+    read_ok
 }
 
 /// IPC Reply: Reply to a call
 ///
 /// Args:
-/// - reply_cap_slot: Reply capability slot
 /// - message_ptr: Pointer to reply message
+/// - message_len: Length of reply message
 ///
 /// Returns:
 /// - 0 on success
 /// - u64::MAX on error
-fn sys_ipc_reply(tf: &mut TrapFrame, reply_cap_slot: u64, message_ptr: u64) -> u64 {
-    ksyscall_debug!("[syscall] IPC Reply: reply_cap={}, msg_ptr=0x{:x}",
-        reply_cap_slot, message_ptr);
-
-    // TODO: Full implementation
-    // 1. Validate reply_cap_slot
-    // 2. Get current TCB
-    // 3. Copy reply message from userspace
-    // 4. Call ipc::reply(reply_cap, message)
-    // 5. Wake up caller
-
-    // For Phase 2, return success to test the syscall path
-    ksyscall_debug!("[syscall] IPC Reply -> success (stub)");
-    0
+///
+/// Replies to the caller whose SYS_CALL message this thread received last
+/// (the implicit reply capability).
+fn sys_ipc_reply(tf: &mut TrapFrame, message_ptr: u64, message_len: u64) -> u64 {
+    ksyscall_debug!("[syscall] IPC Reply: msg_ptr=0x{:x}, len={}", message_ptr, message_len);
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            ksyscall_debug!("[syscall] IPC Reply -> error: no current thread");
+            return u64::MAX;
+        }
+
+        if !reply_to_caller(tf, current, message_ptr, message_len) {
+            return u64::MAX;
+        }
+
+        ksyscall_debug!("[syscall] IPC Reply -> success");
+        0
+    }
+}
+
+/// IPC ReplyRecv: Reply to the last caller, then wait for the next message
+///
+/// Args:
+/// - endpoint_cap_slot: Capability slot for endpoint to receive on
+/// - buffer_ptr: Pointer to receive buffer
+/// - buffer_len: Length of receive buffer
+/// - reply_ptr: Pointer to reply message
+/// - reply_len: Length of reply message
+///
+/// Returns:
+/// - Number of bytes received on success, sender badge in x1
+/// - u64::MAX on error
+///
+/// The server loop primitive. With no caller to answer (first iteration)
+/// this is a plain receive. The common case is handled by the fastpath.
+fn sys_ipc_reply_recv(tf: &mut TrapFrame, endpoint_cap_slot: u64, buffer_ptr: u64, buffer_len: u64,
+                      reply_ptr: u64, reply_len: u64) -> u64 {
+    ksyscall_debug!("[syscall] IPC ReplyRecv: endpoint={}, buf_ptr=0x{:x}, buf_len={}, rep_ptr=0x{:x}, rep_len={}",
+        endpoint_cap_slot, buffer_ptr, buffer_len, reply_ptr, reply_len);
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            ksyscall_debug!("[syscall] IPC ReplyRecv -> error: no current thread");
+            return u64::MAX;
+        }
+
+        if !(*current).reply_to().is_null() && !reply_to_caller(tf, current, reply_ptr, reply_len) {
+            return u64::MAX;
+        }
+    }
+
+    sys_ipc_recv(tf, endpoint_cap_slot, buffer_ptr, buffer_len, None)
 }
 
 // ============================================================================
@@ -2616,12 +3635,19 @@ fn sys_ipc_reply(tf: &mut TrapFrame, reply_cap_slot: u64, message_ptr: u64) -> u
 
 /// Create a notification object
 ///
+/// Args: dest_slot - empty slot to put the capability in, 0 for a new one
+///
 /// Returns: notification capability slot, or u64::MAX on error
-fn sys_notification_create() -> u64 {
+fn sys_notification_create(dest_slot: u64) -> u64 {
     use crate::objects::Notification;
     use crate::memory::alloc_frame;
     use core::ptr;
 
+    let slot = creation_slot(dest_slot);
+    if slot == u64::MAX {
+        return u64::MAX;
+    }
+
     // Allocate a physical frame for the Notification object
     let notification_frame = match alloc_frame() {
         Some(pfn) => pfn,
@@ -2639,9 +3665,6 @@ fn sys_notification_create() -> u64 {
         ptr::write(notification_ptr, Notification::new());
     }
 
-    // Allocate capability slot for the notification
-    let slot = sys_cap_allocate();
-
     // Insert notification capability into current thread's CSpace
     unsafe {
         if !insert_notification_capability(slot as usize, notification_ptr) {
@@ -2691,6 +3714,11 @@ unsafe fn insert_notification_capability(cap_slot: usize, notification: *mut Not
 
 /// Look up a notification capability from the current thread's CSpace
 unsafe fn lookup_notification_capability(cap_slot: usize) -> *mut Notification {
+    lookup_badged_notification(cap_slot).0
+}
+
+/// Look up a notification capability and its badge (0 if unbadged)
+unsafe fn lookup_badged_notification(cap_slot: usize) -> (*mut Notification, u64) {
     use crate::objects::{CapType, Capability, Notification};
     use crate::objects::cnode_cdt::CNodeCdt;
 
@@ -2698,22 +3726,22 @@ unsafe fn lookup_notification_capability(cap_slot: usize) -> *mut Notification {
     let current_tcb = crate::scheduler::current_thread();
     if current_tcb.is_null() {
         ksyscall_debug!("[syscall] lookup_notification: no current thread");
-        return ptr::null_mut();
+        return (ptr::null_mut(), 0);
     }
 
     let cspace_root = (*current_tcb).cspace_root();
     if cspace_root.is_null() {
         ksyscall_debug!("[syscall] lookup_notification: thread has no CSpace root");
-        return ptr::null_mut();
+        return (ptr::null_mut(), 0);
     }
 
     // Look up capability in CSpace
     let cnode = &*(cspace_root as *const CNodeCdt);
-    let cap = match cnode.lookup(cap_slot) {
+    let cap = match cnode.lookup_cptr(cap_slot as u64) {
         Some(c) => c,
         None => {
             ksyscall_debug!("[syscall] lookup_notification: cap_slot {} not found in CSpace", cap_slot);
-            return ptr::null_mut();
+            return (ptr::null_mut(), 0);
         }
     };
 
@@ -2721,24 +3749,162 @@ unsafe fn lookup_notification_capability(cap_slot: usize) -> *mut Notification {
     if cap.cap_type() != CapType::Notification {
         ksyscall_debug!("[syscall] lookup_notification: cap_slot {} is not a Notification (type={:?})",
                  cap_slot, cap.cap_type());
-        return ptr::null_mut();
+        return (ptr::null_mut(), 0);
     }
 
     // Return pointer to Notification object
-    cap.object_ptr() as *mut Notification
+    (cap.object_ptr() as *mut Notification, cap.badge())
+}
+
+/// Create a timer object bound to a notification
+///
+/// Args:
+/// - notification_cap_slot: Notification to signal on expiry
+/// - badge: Bits to signal (non-zero); ignored if the notification
+///   capability is badged, whose badge is used instead
+///
+/// Returns: timer capability slot, or u64::MAX on error
+fn sys_timer_create(notification_cap_slot: u64, badge: u64) -> u64 {
+    use crate::objects::{Capability, CapType, Timer};
+    use crate::objects::cnode_cdt::CNodeCdt;
+    use crate::memory::alloc_frame;
+
+    unsafe {
+        let (notification, cap_badge) = lookup_badged_notification(notification_cap_slot as usize);
+        if notification.is_null() {
+            return u64::MAX;
+        }
+
+        let badge = if cap_badge != 0 { cap_badge } else { badge };
+        if badge == 0 {
+            ksyscall_debug!("[syscall] timer_create: badge must be non-zero");
+            return u64::MAX;
+        }
+
+        let slot = sys_cap_allocate();
+        if slot == u64::MAX {
+            return u64::MAX;
+        }
+
+        let timer_ptr = match alloc_frame() {
+            Some(frame) => frame.phys_addr().as_usize() as *mut Timer,
+            None => {
+                ksyscall_debug!("[syscall] timer_create: out of memory");
+                return u64::MAX;
+            }
+        };
+        ptr::write(timer_ptr, Timer::new(notification, badge));
+
+        // sys_cap_allocate succeeded, so there is a current thread with a CSpace
+        let cnode = &mut *((*crate::scheduler::current_thread()).cspace_root() as *mut CNodeCdt);
+        if cnode.insert_root(slot as usize, Capability::new(CapType::Timer, timer_ptr as usize)).is_err() {
+            ksyscall_debug!("[syscall] timer_create: failed to insert at cap_slot {}", slot);
+            return u64::MAX;
+        }
+
+        ksyscall_debug!("[syscall] timer_create: SUCCESS -> cap_slot={}", slot);
+        slot
+    }
+}
+
+/// Arm or cancel a timer
+///
+/// Args:
+/// - timer_cap_slot: Timer capability slot
+/// - timeout_us: Microseconds until the first expiry (0 = one period)
+/// - period_us: Microseconds between later expiries (0 = one-shot)
+///
+/// Both 0 cancel the timer. Returns: 0 on success, u64::MAX on error
+fn sys_timer_set(timer_cap_slot: u64, timeout_us: u64, period_us: u64) -> u64 {
+    use crate::objects::{cnode_cdt::CNodeCdt, timer, CapType, Timer};
+
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() || (*current_tcb).cspace_root().is_null() {
+            return u64::MAX;
+        }
+
+        let cspace = &*((*current_tcb).cspace_root() as *const CNodeCdt);
+        let timer_ptr = match cspace.lookup_cptr(timer_cap_slot) {
+            Some(cap) if cap.cap_type() == CapType::Timer => cap.object_ptr() as *mut Timer,
+            _ => {
+                ksyscall_debug!("[syscall] timer_set: cap_slot {} is not a Timer", timer_cap_slot);
+                return u64::MAX;
+            }
+        };
+
+        if !timer::arm(timer_ptr, timeout_us, period_us) {
+            ksyscall_debug!("[syscall] timer_set: too many armed timers");
+            return u64::MAX;
+        }
+        0
+    }
+}
+
+/// Lend the caller's priority to a thread until it signals a notification
+///
+/// Args:
+/// - tcb_cap: TCB capability slot of the thread that will signal
+/// - notification_cap_slot: Notification the caller is about to wait on
+///
+/// Returns: 0 on success, u64::MAX on error
+fn sys_tcb_donate_priority(tcb_cap: u64, notification_cap_slot: u64) -> u64 {
+    use crate::objects::CapType;
+    use crate::objects::cnode_cdt::CNodeCdt;
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            return u64::MAX;
+        }
+
+        let cspace_root = (*current).cspace_root();
+        if cspace_root.is_null() {
+            ksyscall_debug!("[syscall] tcb_donate_priority: thread has no CSpace root");
+            return u64::MAX;
+        }
+
+        // Look up the thread to lend to
+        let cnode = &*(cspace_root as *const CNodeCdt);
+        let target = match cnode.lookup_cptr(tcb_cap) {
+            Some(cap) if cap.cap_type() == CapType::Tcb => cap.object_ptr() as *mut TCB,
+            _ => {
+                ksyscall_debug!("[syscall] tcb_donate_priority: cap_slot {} is not a TCB", tcb_cap);
+                return u64::MAX;
+            }
+        };
+        if target.is_null() {
+            return u64::MAX;
+        }
+
+        let notification_ptr = lookup_notification_capability(notification_cap_slot as usize);
+        if notification_ptr.is_null() {
+            ksyscall_debug!("[syscall] tcb_donate_priority: notification not found for slot {}", notification_cap_slot);
+            return u64::MAX;
+        }
+
+        ksyscall_debug!("[syscall] tcb_donate_priority: priority {} -> TID {}",
+                        (*current).priority(), (*target).tid());
+        crate::scheduler::donate_priority(target, (*current).priority(), notification_ptr as usize);
+        0
+    }
 }
 
 /// Signal a notification (non-blocking)
 ///
+/// Ends a priority donation the caller holds until it signals this
+/// notification, and yields to a woken waiter that outranks the caller.
+///
 /// Args:
 /// - notification_cap_slot: Capability slot for notification
-/// - badge: Signal bits to set (OR'd with existing signals)
+/// - badge: Signal bits to set (OR'd with existing signals); ignored if the
+///   capability is badged, whose badge is signalled instead
 ///
 /// Returns: 0 on success, u64::MAX on error
-fn sys_signal(notification_cap_slot: u64, badge: u64) -> u64 {
+fn sys_signal(tf: &mut TrapFrame, notification_cap_slot: u64, badge: u64) -> u64 {
     unsafe {
         // Look up notification from capability slot
-        let notification_ptr = lookup_notification_capability(notification_cap_slot as usize);
+        let (notification_ptr, cap_badge) = lookup_badged_notification(notification_cap_slot as usize);
         if notification_ptr.is_null() {
             crate::kprintln!("[syscall] sys_signal: ERROR - notification not found for slot {}", notification_cap_slot);
             return u64::MAX;
@@ -2746,8 +3912,20 @@ fn sys_signal(notification_cap_slot: u64, badge: u64) -> u64 {
 
         let notification = &mut *notification_ptr;
 
-        // Signal the notification
-        notification.signal(badge);
+        // A badged capability always signals its own badge, so the waiter
+        // can tell which holder signalled
+        let bits = if cap_badge != 0 { cap_badge } else { badge };
+        notification.signal(bits);
+
+        // The signal is what a waiter lent the signaller its priority for
+        let current = crate::scheduler::current_thread();
+        crate::scheduler::end_donation(current, notification_ptr as usize);
+
+        // A woken waiter that outranks the signaller runs now rather than
+        // at the next tick
+        if !current.is_null() && crate::scheduler::higher_priority_ready((*current).priority()) {
+            return sys_yield(tf);
+        }
 
         // crate::kprintln!("[syscall] sys_signal: SUCCESS - returning to userspace");
         0
@@ -2811,18 +3989,10 @@ fn sys_wait(tf: &mut TrapFrame, notification_cap_slot: u64) -> u64 {
                     return u64::MAX;
                 }
 
-                // Switch to next thread
-                let next_tcb = &mut *next;
-                next_tcb.set_state(crate::objects::ThreadState::Running);
-                crate::scheduler::test_set_current_thread(next);
-
-                // crate::kprintln!("[syscall] sys_wait: switching to TCB={:#x}, ELR={:#x}, TTBR0={:#x}",
-                //                 next as usize, next_tcb.context().elr_el1, next_tcb.context().saved_ttbr0);
-
-                // Replace our TrapFrame with the next thread's context
+                // Switch to next thread (or idle until there is one)
                 // When we return from this syscall, the exception handler will restore
                 // the next thread's context and eret to it
-                *tf = *next_tcb.context();
+                switch_to(tf, next);
 
                 // Return 0 - but this won't be seen by current thread
                 // When this thread is signaled and resumed, it will return with
@@ -2860,6 +4030,50 @@ fn sys_poll(notification_cap_slot: u64) -> u64 {
     }
 }
 
+/// Bind a notification to the calling thread
+///
+/// Args:
+/// - notification_cap_slot: Capability slot for notification, or u64::MAX
+///   to unbind the thread's notification
+///
+/// Returns: 0 on success, u64::MAX on error (no such notification, or one
+/// bound to another thread)
+fn sys_tcb_bind_notification(notification_cap_slot: u64) -> u64 {
+    ksyscall_debug!("[syscall] tcb_bind_notification: notification={}", notification_cap_slot);
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            return u64::MAX;
+        }
+
+        if notification_cap_slot == u64::MAX {
+            let bound = (*current).bound_notification();
+            if !bound.is_null() {
+                (*bound).unbind();
+            }
+            return 0;
+        }
+
+        let notification_ptr = lookup_notification_capability(notification_cap_slot as usize);
+        if notification_ptr.is_null() {
+            ksyscall_debug!("[syscall] tcb_bind_notification -> error: notification not found for cap_slot {}",
+                notification_cap_slot);
+            return u64::MAX;
+        }
+
+        let notification = &mut *notification_ptr;
+        let bound_tcb = notification.bound_tcb();
+        if !bound_tcb.is_null() && bound_tcb != current {
+            ksyscall_debug!("[syscall] tcb_bind_notification -> error: bound to TID {}", (*bound_tcb).tid());
+            return u64::MAX;
+        }
+
+        notification.bind(current);
+        0
+    }
+}
+
 /// Register shared memory with the kernel registry
 /// Args: name_ptr, name_len, phys_addr, size, notification_cap_slot
 /// Returns: 0 on success, u64::MAX on error
@@ -2893,6 +4107,8 @@ fn sys_shmem_register(tf: &TrapFrame, name_ptr: u64, name_len: u64, phys_addr: u
 
     // Find free slot in registry
     unsafe {
+        let current = crate::scheduler::current_thread();
+        let owner = if current.is_null() { 0 } else { (*(*current).process()).tid() };
         for entry in SHMEM_REGISTRY.iter_mut() {
             if !entry.valid {
                 // Use this slot
@@ -2902,6 +4118,7 @@ fn sys_shmem_register(tf: &TrapFrame, name_ptr: u64, name_len: u64, phys_addr: u
                 entry.phys_addr = phys_addr as usize;
                 entry.size = size as usize;
                 entry.notification_obj = notification_obj;
+                entry.owner = owner;
                 entry.valid = true;
                 return 0;
             }
@@ -2912,6 +4129,23 @@ fn sys_shmem_register(tf: &TrapFrame, name_ptr: u64, name_len: u64, phys_addr: u
     u64::MAX
 }
 
+/// Withdraw every shared memory name a destroyed process registered
+///
+/// A restarted producer registers its channels again; consumers must not
+/// find the dead one's memory first.
+///
+/// # Safety
+/// Must not race with the shmem syscalls (the kernel is single-core)
+pub(crate) unsafe fn shmem_withdraw(owner: usize) {
+    for entry in SHMEM_REGISTRY.iter_mut() {
+        if entry.valid && entry.owner == owner {
+            kprintln!("[syscall] shmem: withdrew '{}' of exited TID {:#x}",
+                      core::str::from_utf8(&entry.name[..entry.name_len]).unwrap_or("<invalid>"), owner);
+            *entry = ShmemEntry::new();
+        }
+    }
+}
+
 /// Query shared memory from the kernel registry
 /// Args: name_ptr, name_len
 /// Returns: (phys_addr << 32) | size on success, 0 if not found
@@ -3012,6 +4246,8 @@ fn sys_shmem_get_notification(tf: &TrapFrame, name_ptr: u64, name_len: u64, dest
 /// * `irq_num` - Hardware IRQ number to allocate (e.g., 27 for timer, 33 for UART0)
 /// * `notification_cap` - Capability slot containing notification to signal on IRQ
 /// * `irq_handler_slot` - Empty capability slot to store the new IRQHandler
+/// * `shared_badge` - `Some(badge)` for IRQControl_GetShared: subscribe to
+///   a shared line, signaling `badge` (non-zero)
 ///
 /// # Returns
 /// - 0 on success
@@ -3019,7 +4255,8 @@ fn sys_shmem_get_notification(tf: &TrapFrame, name_ptr: u64, name_len: u64, dest
 ///
 /// # Security
 /// - Requires IRQControl capability (only root-task has this by default)
-/// - Only one IRQHandler can exist per IRQ number
+/// - Only one IRQHandler can exist per IRQ number, unless all of them
+///   are shared
 /// - IRQHandler is bound to the specific notification
 ///
 /// # Usage
@@ -3028,7 +4265,14 @@ fn sys_shmem_get_notification(tf: &TrapFrame, name_ptr: u64, name_len: u64, dest
 /// let irq_handler_slot = sys_cap_allocate();
 /// sys_irq_handler_get(irq_control_cap, IRQ_UART0, notification_cap, irq_handler_slot);
 /// ```
-fn sys_irq_handler_get(tf: &TrapFrame, irq_control_cap: u64, irq_num: u64, notification_cap: u64, irq_handler_slot: u64) -> u64 {
+fn sys_irq_handler_get(
+    tf: &TrapFrame,
+    irq_control_cap: u64,
+    irq_num: u64,
+    notification_cap: u64,
+    irq_handler_slot: u64,
+    shared_badge: Option<u64>,
+) -> u64 {
     ksyscall_debug!("[syscall] sys_irq_handler_get: irq_control={}, irq={}, notif={}, slot={}",
                    irq_control_cap, irq_num, notification_cap, irq_handler_slot);
 
@@ -3050,7 +4294,7 @@ fn sys_irq_handler_get(tf: &TrapFrame, irq_control_cap: u64, irq_num: u64, notif
         let cnode = &mut *(cspace_root as *mut crate::objects::cnode_cdt::CNodeCdt);
 
         // Look up IRQControl capability
-        let cap = match cnode.lookup(irq_control_cap as usize) {
+        let cap = match cnode.lookup_cptr(irq_control_cap) {
             Some(c) => c,
             None => {
                 kprintln!("[syscall] sys_irq_handler_get: IRQControl cap not found");
@@ -3064,7 +4308,7 @@ fn sys_irq_handler_get(tf: &TrapFrame, irq_control_cap: u64, irq_num: u64, notif
         }
 
         // Look up notification capability
-        let notif_cap = match cnode.lookup(notification_cap as usize) {
+        let notif_cap = match cnode.lookup_cptr(notification_cap) {
             Some(c) => c,
             None => {
                 kprintln!("[syscall] sys_irq_handler_get: notification cap not found");
@@ -3089,35 +4333,46 @@ fn sys_irq_handler_get(tf: &TrapFrame, irq_control_cap: u64, irq_num: u64, notif
             return u64::MAX;
         }
 
-        // Check if IRQ is already allocated
-        if crate::objects::irq_handler::get_irq_handler(irq_num as u32).is_some() {
+        if shared_badge == Some(0) {
+            kprintln!("[syscall] sys_irq_handler_get: shared IRQ {} needs a non-zero badge", irq_num);
+            return u64::MAX;
+        }
+
+        // Check if IRQ is already allocated (a shared line takes more shared handlers)
+        let line = crate::objects::irq_handler::get_irq_handler(irq_num as u32);
+        if line.is_some() && shared_badge.is_none() {
             kprintln!("[syscall] sys_irq_handler_get: IRQ {} already allocated", irq_num);
             return u64::MAX;
         }
 
         // Allocate physical frame for IRQHandler object
-        let handler_frame = crate::memory::alloc_frame();
-        if handler_frame.is_none() {
+        let Some(handler_frame) = crate::memory::alloc_frame() else {
             kprintln!("[syscall] sys_irq_handler_get: failed to allocate IRQHandler frame");
             return u64::MAX;
-        }
+        };
 
-        let handler_phys = handler_frame.unwrap().phys_addr();
+        let handler_phys = handler_frame.phys_addr();
         let handler_ptr = handler_phys.as_usize() as *mut crate::objects::IRQHandler;
 
         // Initialize IRQHandler
-        let handler = crate::objects::IRQHandler::new(irq_num as u32, notification_ptr);
+        let handler = match shared_badge {
+            Some(badge) => crate::objects::IRQHandler::new_shared(irq_num as u32, notification_ptr, badge),
+            None => crate::objects::IRQHandler::new(irq_num as u32, notification_ptr),
+        };
         core::ptr::write(handler_ptr, handler);
 
         // Register handler in global table
         if crate::objects::irq_handler::register_irq_handler(irq_num as u32, handler_ptr).is_err() {
-            kprintln!("[syscall] sys_irq_handler_get: failed to register IRQ handler");
-            // TODO: Deallocate frame
+            kprintln!("[syscall] sys_irq_handler_get: failed to register IRQ handler (IRQ {} not shared)", irq_num);
+            crate::memory::dealloc_frame(handler_frame);
             return u64::MAX;
         }
 
-        // Enable the IRQ in the GIC
-        crate::arch::aarch64::gic::enable_irq(irq_num as u32);
+        // Enable the IRQ in the GIC (a shared line already is, or is
+        // masked until its other handlers acknowledge)
+        if line.is_none() {
+            crate::arch::aarch64::gic::enable_irq(irq_num as u32);
+        }
 
         // Create capability for IRQHandler
         let irq_handler_cap = crate::objects::Capability::new(
@@ -3128,8 +4383,8 @@ fn sys_irq_handler_get(tf: &TrapFrame, irq_control_cap: u64, irq_num: u64, notif
         // Insert capability into caller's CSpace using insert_root (CNodeCdt method)
         if cnode.insert_root(irq_handler_slot as usize, irq_handler_cap).is_err() {
             kprintln!("[syscall] sys_irq_handler_get: failed to insert capability");
-            crate::objects::irq_handler::unregister_irq_handler(irq_num as u32);
-            // TODO: Deallocate frame
+            crate::objects::irq_handler::unlink_irq_handler(handler_ptr);
+            crate::memory::dealloc_frame(handler_frame);
             return u64::MAX;
         }
 
@@ -3191,7 +4446,7 @@ fn sys_irq_handler_ack(tf: &TrapFrame, irq_handler_cap: u64) -> u64 {
         let cnode = &*(cspace_root as *const crate::objects::cnode_cdt::CNodeCdt);
 
         // Look up IRQHandler capability
-        let cap = match cnode.lookup(irq_handler_cap as usize) {
+        let cap = match cnode.lookup_cptr(irq_handler_cap) {
             Some(c) => c,
             None => {
                 kprintln!("[syscall] sys_irq_handler_ack: capability not found");
@@ -3212,6 +4467,10 @@ fn sys_irq_handler_ack(tf: &TrapFrame, irq_handler_cap: u64) -> u64 {
 
         // Acknowledge IRQ (re-enables it at GIC)
         let handler = &mut *handler_ptr;
+        if !handler.is_bound() {
+            kprintln!("[syscall] sys_irq_handler_ack: handler was cleared");
+            return u64::MAX;
+        }
         handler.ack();
 
         ksyscall_debug!("[syscall] sys_irq_handler_ack: ✓ IRQ {} re-enabled", handler.irq_num());
@@ -3220,6 +4479,135 @@ fn sys_irq_handler_ack(tf: &TrapFrame, irq_handler_cap: u64) -> u64 {
     }
 }
 
+/// IRQHandler_Clear - Unbind an IRQHandler from its IRQ
+///
+/// Removes the handler from its IRQ line: its notification is no longer
+/// signaled and, on a shared line, the others no longer wait for its
+/// acknowledgement. The last handler off a line disables the IRQ.
+///
+/// # Arguments
+/// * `irq_handler_cap` - Capability slot containing IRQHandler capability
+///
+/// # Returns
+/// - 0 on success
+/// - u64::MAX on error (invalid capability)
+fn sys_irq_handler_clear(irq_handler_cap: u64) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || (*current).cspace_root().is_null() {
+            return u64::MAX;
+        }
+        let cnode = &*((*current).cspace_root() as *const crate::objects::cnode_cdt::CNodeCdt);
+
+        let handler_ptr = match cnode.lookup_cptr(irq_handler_cap) {
+            Some(cap) if cap.cap_type() == crate::objects::CapType::IrqHandler => {
+                cap.object_ptr() as *mut crate::objects::IRQHandler
+            }
+            _ => {
+                ksyscall_debug!("[syscall] sys_irq_handler_clear: slot {} is not an IRQHandler capability", irq_handler_cap);
+                return u64::MAX;
+            }
+        };
+        if handler_ptr.is_null() {
+            return u64::MAX;
+        }
+
+        crate::objects::irq_handler::unlink_irq_handler(handler_ptr);
+        ksyscall_debug!("[syscall] sys_irq_handler_clear: ✓ IRQ {} handler cleared", (*handler_ptr).irq_num());
+        0
+    }
+}
+
+/// IRQControl_GetMSI - Allocate an MSI vector and its IRQHandler capability
+///
+/// Allocates an LPI for the device through the GIC ITS, binds it to a
+/// notification like `sys_irq_handler_get`, and returns the message the
+/// device must write to raise it.
+///
+/// # Arguments
+/// * `tf` - Trap frame (x1 receives the MSI address, x2 the MSI data)
+/// * `irq_control_cap` - Capability slot containing IRQControl capability
+/// * `device_id` - ITS DeviceID of the device (PCIe requester ID)
+/// * `notification_cap` - Capability slot containing notification to signal on IRQ
+/// * `irq_handler_slot` - Empty capability slot to store the new IRQHandler
+///
+/// # Returns
+/// - 0 on success
+/// - u64::MAX on error (invalid capability, no ITS, out of vectors, etc.)
+fn sys_irq_msi_get(tf: &mut TrapFrame, irq_control_cap: u64, device_id: u64, notification_cap: u64, irq_handler_slot: u64) -> u64 {
+    ksyscall_debug!("[syscall] sys_irq_msi_get: irq_control={}, device={:#x}, notif={}, slot={}",
+                   irq_control_cap, device_id, notification_cap, irq_handler_slot);
+
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || (*current).cspace_root().is_null() {
+            ksyscall_debug!("[syscall] sys_irq_msi_get: no current thread or CSpace");
+            return u64::MAX;
+        }
+        let cnode = &mut *((*current).cspace_root() as *mut crate::objects::cnode_cdt::CNodeCdt);
+
+        match cnode.lookup_cptr(irq_control_cap) {
+            Some(cap) if cap.cap_type() == crate::objects::CapType::IrqControl => {}
+            _ => {
+                ksyscall_debug!("[syscall] sys_irq_msi_get: slot {} is not an IRQControl capability", irq_control_cap);
+                return u64::MAX;
+            }
+        }
+
+        let notification_ptr = match cnode.lookup_cptr(notification_cap) {
+            Some(cap) if cap.cap_type() == crate::objects::CapType::Notification => {
+                cap.object_ptr() as *mut crate::objects::Notification
+            }
+            _ => {
+                ksyscall_debug!("[syscall] sys_irq_msi_get: slot {} is not a Notification capability", notification_cap);
+                return u64::MAX;
+            }
+        };
+        if notification_ptr.is_null() || device_id > u32::MAX as u64 {
+            return u64::MAX;
+        }
+
+        let Some(handler_frame) = crate::memory::alloc_frame() else {
+            ksyscall_debug!("[syscall] sys_irq_msi_get: failed to allocate IRQHandler frame");
+            return u64::MAX;
+        };
+
+        let Some((irq_num, message)) = crate::arch::aarch64::gic::msi::alloc(device_id as u32) else {
+            ksyscall_debug!("[syscall] sys_irq_msi_get: no MSI vector for device {:#x}", device_id);
+            crate::memory::dealloc_frame(handler_frame);
+            return u64::MAX;
+        };
+
+        let handler_ptr = handler_frame.phys_addr().as_usize() as *mut crate::objects::IRQHandler;
+        core::ptr::write(handler_ptr, crate::objects::IRQHandler::new_msi(irq_num, message.data, notification_ptr));
+
+        if crate::objects::irq_handler::register_irq_handler(irq_num, handler_ptr).is_err() {
+            ksyscall_debug!("[syscall] sys_irq_msi_get: MSI {} already registered", irq_num);
+            crate::arch::aarch64::gic::msi::free(irq_num);
+            crate::memory::dealloc_frame(handler_frame);
+            return u64::MAX;
+        }
+
+        let irq_handler_cap = crate::objects::Capability::new(
+            crate::objects::CapType::IrqHandler,
+            handler_ptr as usize,
+        );
+        if cnode.insert_root(irq_handler_slot as usize, irq_handler_cap).is_err() {
+            ksyscall_debug!("[syscall] sys_irq_msi_get: failed to insert capability");
+            crate::objects::irq_handler::unregister_irq_handler(irq_num);
+            crate::memory::dealloc_frame(handler_frame);
+            return u64::MAX;
+        }
+
+        ksyscall_debug!("[syscall] sys_irq_msi_get: ✓ MSI {} for device {:#x}: address={:#x}, data={}",
+                       irq_num, device_id, message.address, message.data);
+
+        tf.x1 = message.address;
+        tf.x2 = message.data as u64;
+        0
+    }
+}
+
 // ============================================================================
 // System Control Syscalls
 // ============================================================================
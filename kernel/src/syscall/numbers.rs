@@ -9,21 +9,74 @@ pub const SYS_DEBUG_PUTCHAR: u64 = 0x1000;
 /// Debug: Print a string to console (ptr, len)
 pub const SYS_DEBUG_PRINT: u64 = 0x1001;
 
+/// Debug: Identify the capability behind a CPtr (`debug-syscall` builds only)
+/// Args: cptr
+/// Returns: x0 = capability type (0 if the slot is empty or the address does
+/// not resolve), x1 = rights, x2 = badge (guard word for CNodes), x3 = object
+/// physical address
+///
+/// Like seL4_DebugCapIdentify, for diagnosing CSpace and CDT bugs.
+pub const SYS_DEBUG_CAP_IDENTIFY: u64 = 0x1002;
+
 /// Yield the CPU to the scheduler
 pub const SYS_YIELD: u64 = 0x01;
 
-/// Send a message on an IPC endpoint (not yet implemented)
+/// Send a message on an IPC endpoint
+/// Args: endpoint_cap_slot, message_ptr, message_len (max 256)
+/// Returns: 0 on success, -1 on error (blocks until a receiver takes it)
 pub const SYS_SEND: u64 = 0x02;
 
-/// Receive a message on an IPC endpoint (not yet implemented)
+/// Receive a message on an IPC endpoint
+/// Args: endpoint_cap_slot, buffer_ptr, buffer_len
+/// Returns: bytes received, or -1 on error (blocks until a sender arrives);
+/// x1 holds the badge of the sender's endpoint capability (0 if unbadged)
 pub const SYS_RECV: u64 = 0x03;
 
-/// Call: Combined send + receive (not yet implemented)
+/// Call: Send a request and block for the reply (RPC)
+/// Args: endpoint_cap_slot, request_ptr, request_len, reply_ptr, reply_len
+/// Returns: reply length, or -1 on error
+///
+/// Handled by the IPC fastpath when a receiver is already waiting.
 pub const SYS_CALL: u64 = 0x04;
 
-/// Reply: Reply to a call (not yet implemented)
+/// Reply: Reply to the last caller received from
+/// Args: message_ptr, message_len
+/// Returns: 0 on success, -1 on error
 pub const SYS_REPLY: u64 = 0x05;
 
+/// ReplyRecv: Reply to the last caller, then receive the next message
+/// Args: endpoint_cap_slot, buffer_ptr, buffer_len, reply_ptr, reply_len
+/// Returns: bytes received, or -1 on error; x1 holds the sender's badge
+///
+/// The server loop primitive; handled by the IPC fastpath when the caller
+/// can be switched to directly.
+pub const SYS_REPLY_RECV: u64 = 0x06;
+
+/// Send a message and a capability on an IPC endpoint
+/// Args: endpoint_cap_slot, message_ptr, message_len, cap_slot
+/// Returns: 0 on success, -1 on error (blocks until a receiver takes it)
+///
+/// The capability in cap_slot (which must have the GRANT right) is copied
+/// into the receive slot of a receiver in SYS_RECV_CAP, as a CDT child of
+/// the sender's so revoking that revokes it. Other receivers get the
+/// message alone.
+pub const SYS_SEND_CAP: u64 = 0x07;
+
+/// Receive a message on an IPC endpoint, and a capability into receive_slot
+/// Args: endpoint_cap_slot, buffer_ptr, buffer_len, receive_slot
+/// Returns: bytes received, or -1 on error; x1 holds the sender's badge,
+/// x2 is 1 if a capability was put in receive_slot and 0 if not
+pub const SYS_RECV_CAP: u64 = 0x08;
+
+/// Lend the caller's priority to a thread until it signals a notification
+/// Args: tcb_cap_slot, notification_cap_slot
+/// Returns: 0 on success, -1 on error
+///
+/// Priority inheritance for notification waits: call it before waiting
+/// on the notification the thread will signal. Never lowers the thread's
+/// priority; the loan ends at its next SYS_SIGNAL of that notification.
+pub const SYS_TCB_DONATE_PRIORITY: u64 = 0x09;
+
 // Capability Management Syscalls (Chapter 9)
 // These syscalls provide the foundation for the capability broker
 
@@ -42,6 +95,7 @@ pub const SYS_MEMORY_ALLOCATE: u64 = 0x11;
 pub const SYS_DEVICE_REQUEST: u64 = 0x12;
 
 /// Create IPC endpoint
+/// Args: dest_slot, an empty slot from SYS_CAP_ALLOCATE, or 0 for a new one
 /// Returns: endpoint capability slot, or -1 on error
 pub const SYS_ENDPOINT_CREATE: u64 = 0x13;
 
@@ -50,14 +104,69 @@ pub const SYS_ENDPOINT_CREATE: u64 = 0x13;
 /// Returns: process ID, or -1 on error
 pub const SYS_PROCESS_CREATE: u64 = 0x14;
 
+/// SYS_PROCESS_CREATE flag (x11): map the image copy-on-write
+///
+/// The image frames are shared read-only instead of being handed to the
+/// process; pages it writes are copied on first write (see
+/// `memory::cow`). Several processes can be created from one image.
+pub const PROCESS_CREATE_COW: u64 = 1 << 0;
+
+/// SYS_PROCESS_CREATE flag (x11): map a boot block into the process
+///
+/// x12 points to two u64s in the creator's memory: the physical address
+/// of a page the creator filled in, then the page-aligned virtual address
+/// to map it at, read-only. The process starts with that address in x0
+/// (see `kaal_sdk::env`).
+pub const PROCESS_CREATE_BOOT_BLOCK: u64 = 1 << 1;
+
 /// Map physical memory into caller's virtual address space
-/// Args: physical_addr, size, permissions (read=1, write=2, exec=4)
+/// Args: physical_addr, size, permissions (read=1, write=2, exec=4, memory type in MAP_MEMORY_TYPE_MASK,
+/// page size in MAP_PAGE_SIZE_MASK)
 /// Returns: virtual address, or -1 on error
 ///
 /// This allows userspace to access allocated physical memory by mapping
 /// it into a free region of its virtual address space.
 pub const SYS_MEMORY_MAP: u64 = 0x15;
 
+// Page size selection for SYS_MEMORY_MAP / SYS_MEMORY_MAP_INTO
+//
+// Bits 8-9 of the permissions argument pick the page size. Large pages are
+// mapped as L2/L1 block descriptors; phys_addr (and virt_addr for MAP_INTO)
+// must be aligned to the page size, and size is rounded up to it.
+
+/// Page size field in the permissions argument
+pub const MAP_PAGE_SIZE_MASK: u64 = 0x3 << 8;
+
+/// 4KB pages (default)
+pub const MAP_PAGE_4KB: u64 = 0 << 8;
+
+/// 2MB large pages (L2 blocks)
+pub const MAP_PAGE_2MB: u64 = 1 << 8;
+
+/// 1GB huge pages (L1 blocks)
+pub const MAP_PAGE_1GB: u64 = 2 << 8;
+
+// Memory type for SYS_MEMORY_MAP / SYS_MEMORY_MAP_INTO
+//
+// Bits 4-5 of the permissions argument pick the memory attributes of the
+// mapping. RAM wants the default; MMIO registers want MAP_DEVICE.
+
+/// Memory type field in the permissions argument
+pub const MAP_MEMORY_TYPE_MASK: u64 = 0x3 << 4;
+
+/// Normal memory, write-back cacheable (default)
+pub const MAP_NORMAL: u64 = 0 << 4;
+
+/// Device-nGnRnE: MMIO registers, every access reaches the device in order
+pub const MAP_DEVICE: u64 = 1 << 4;
+
+/// Normal memory, non-cacheable: buffers shared with non-coherent devices
+pub const MAP_NON_CACHEABLE: u64 = 2 << 4;
+
+/// Device-GRE: write-combining device memory (framebuffers); accesses
+/// must be aligned
+pub const MAP_WRITE_COMBINE: u64 = 3 << 4;
+
 /// Unmap virtual memory from caller's address space
 /// Args: virtual_addr, size
 /// Returns: 0 on success, -1 on error
@@ -67,12 +176,16 @@ pub const SYS_MEMORY_UNMAP: u64 = 0x16;
 // Lightweight signaling for shared memory IPC
 
 /// Create a notification object
+/// Args: dest_slot, an empty slot from SYS_CAP_ALLOCATE, or 0 for a new one
 /// Returns: notification capability slot, or -1 on error
 pub const SYS_NOTIFICATION_CREATE: u64 = 0x17;
 
 /// Signal a notification (non-blocking)
 /// Args: notification_cap_slot, badge (signal bits)
 /// Returns: 0 on success, -1 on error
+///
+/// Through a badged notification capability the capability's badge is
+/// signalled and the badge argument is ignored.
 pub const SYS_SIGNAL: u64 = 0x18;
 
 /// Wait for notification (blocking)
@@ -86,7 +199,8 @@ pub const SYS_WAIT: u64 = 0x19;
 pub const SYS_POLL: u64 = 0x1A;
 
 /// Map physical memory into target process's virtual address space (Phase 5)
-/// Args: target_tcb_cap, phys_addr, size, virt_addr, permissions (read=1, write=2, exec=4)
+/// Args: target_tcb_cap, phys_addr, size, virt_addr, permissions (read=1, write=2, exec=4,
+/// memory type in MAP_MEMORY_TYPE_MASK, page size in MAP_PAGE_SIZE_MASK)
 /// Returns: 0 on success, -1 on error
 ///
 /// Maps physical memory at a specific virtual address in target process.
@@ -94,6 +208,25 @@ pub const SYS_POLL: u64 = 0x1A;
 /// Requires TCB capability for the target process.
 pub const SYS_MEMORY_MAP_INTO: u64 = 0x1B;
 
+/// Unmap virtual memory from target process's address space
+/// Args: target_tcb_cap, virt_addr, size
+/// Returns: 0 on success, -1 on error
+///
+/// Undoes SYS_MEMORY_MAP_INTO, e.g. when shared memory is torn down.
+/// Requires CAP_MEMORY and a TCB capability for the target process.
+pub const SYS_MEMORY_UNMAP_FROM: u64 = 0x3F;
+
+/// Change the permissions of memory mapped in a target process
+/// Args: target_tcb_cap, virt_addr, size, permissions (read=1, write=2, exec=4)
+/// Returns: 0 on success, -1 on error (nothing changes unless every page of
+/// the range is mapped user memory)
+///
+/// Lets the root task map a loaded image's text read-execute, rodata
+/// read-only and data read-write. A copy-on-write page left writable is
+/// still copied on its first write; made read-only, a write faults instead.
+/// Requires CAP_MEMORY and a TCB capability for the target process.
+pub const SYS_MEMORY_PROTECT_IN: u64 = 0x45;
+
 /// Insert capability into target process's CSpace (Phase 5)
 /// Args: target_tcb_cap, cap_slot, cap_type, object_ptr
 /// Returns: 0 on success, -1 on error
@@ -116,7 +249,8 @@ pub const SYS_CAP_INSERT_SELF: u64 = 0x1D;
 /// Returns: 0 on success, -1 on error
 ///
 /// Recursively deletes the capability at the specified slot and all capabilities
-/// derived from it. Requires WRITE rights on the CNode capability.
+/// derived from it, in whichever CSpace they live. Requires WRITE rights on the
+/// CNode capability.
 pub const SYS_CAP_REVOKE: u64 = 0x1E;
 
 /// Derive a capability with reduced rights
@@ -128,12 +262,14 @@ pub const SYS_CAP_REVOKE: u64 = 0x1E;
 /// Requires WRITE rights on the CNode capability.
 pub const SYS_CAP_DERIVE: u64 = 0x1F;
 
-/// Mint a badged capability (for endpoints)
-/// Args: cnode_cap, src_slot, dest_slot, badge
+/// Mint a badged capability (for endpoints and notifications)
+/// Args: cnode_cap, src_slot, dest_slot, badge (non-zero)
 /// Returns: 0 on success, -1 on error
 ///
-/// Creates a badged endpoint capability in the CDT. The badge is used
-/// to identify the sender in IPC. Requires WRITE rights on the CNode capability.
+/// Creates a badged endpoint or notification capability in the CDT. The
+/// badge is used to identify the sender in IPC: it is returned by the
+/// receive, or OR'd into the notification word on signal. The source must
+/// be unbadged. Requires WRITE rights on the CNode capability.
 pub const SYS_CAP_MINT: u64 = 0x20;
 
 /// Copy a capability to another slot
@@ -141,16 +277,19 @@ pub const SYS_CAP_MINT: u64 = 0x20;
 /// Returns: 0 on success, -1 on error
 ///
 /// Creates an exact copy of a capability in a new slot. The copy shares the
-/// same rights and badge as the source. Both capabilities track the same parent
-/// in the CDT. Requires READ rights on source CNode and WRITE rights on dest CNode.
+/// same rights and badge as the source. A copy of an original capability is
+/// derived from it (revoking the original revokes the copy); a copy of a derived
+/// capability shares its parent. Requires READ rights on source CNode and WRITE
+/// rights on dest CNode.
 pub const SYS_CAP_COPY: u64 = 0x21;
 
 /// Delete a capability from a slot
 /// Args: cnode_cap, slot
 /// Returns: 0 on success, -1 on error
 ///
-/// Removes a capability from the specified slot without affecting descendants.
-/// Unlike revoke, this only deletes the specific capability.
+/// Removes a capability from the specified slot without affecting descendants,
+/// which are re-attached to its parent. Unlike revoke, this only deletes the
+/// specific capability.
 /// Requires WRITE rights on the CNode capability.
 pub const SYS_CAP_DELETE: u64 = 0x22;
 
@@ -163,6 +302,77 @@ pub const SYS_CAP_DELETE: u64 = 0x22;
 /// Requires WRITE rights on both source and dest CNode capabilities.
 pub const SYS_CAP_MOVE: u64 = 0x23;
 
+/// Set the guard of a CNode capability
+/// Args: cnode_cap, slot, guard, guard_bits
+/// Returns: 0 on success, -1 on error
+///
+/// CSpaces can be trees of CNodes. Capability arguments of all syscalls
+/// are CPtrs: the top byte is the lookup depth in bits, and the lookup
+/// walks CNode capabilities from the CSpace root, matching each one's
+/// guard before taking the next CNode's index bits. Depth 0 keeps the
+/// low bits as a plain slot index in the root. Requires WRITE rights on
+/// the CNode holding the capability.
+pub const SYS_CNODE_SET_GUARD: u64 = 0x36;
+
+/// Create a timer object bound to a notification
+/// Args: notification_cap_slot, badge
+/// Returns: timer capability slot, or -1 on error
+///
+/// The timer signals the notification with `badge` (or the capability's
+/// own badge, if it is badged) when it expires. Requires CAP_CAPS.
+pub const SYS_TIMER_CREATE: u64 = 0x37;
+
+/// Arm or cancel a timer
+/// Args: timer_cap_slot, timeout_us, period_us
+/// Returns: 0 on success, -1 on error
+///
+/// The timer first expires after `timeout_us` (after `period_us` if that is
+/// 0), then every `period_us` unless it is 0. Both 0 cancel the timer.
+/// Expiry is checked on the scheduler tick, so timeouts are rounded up to
+/// it.
+pub const SYS_TIMER_SET: u64 = 0x38;
+
+/// Create a VCPU with an empty guest physical address space
+/// Args: none
+/// Returns: VCPU capability slot, or u64::MAX on error
+///
+/// Needs CAP_CAPS, and the kernel built with the `hypervisor` feature and
+/// entered at EL2. The guest starts in EL1h with interrupts masked and its
+/// MMU off; set its registers with SYS_VCPU_WRITE_REG before running it.
+pub const SYS_VCPU_CREATE: u64 = 0x39;
+
+/// Map physical memory into a guest's physical address space
+/// Args: vcpu_cap, phys_addr, size, ipa, permissions (read=1, write=2,
+///       exec=4, page size as for SYS_MEMORY_MAP)
+/// Returns: 0 on success, u64::MAX on error
+///
+/// Needs CAP_MEMORY. Guest physical addresses are 40 bits.
+pub const SYS_VCPU_MAP: u64 = 0x3A;
+
+/// Run a guest until it exits
+/// Args: vcpu_cap
+/// Returns: exit reason (VCPU_EXIT_*), or u64::MAX on error
+///
+/// For VCPU_EXIT_TRAP, x1-x3 hold ESR_EL2, FAR_EL2 and the faulting IPA.
+/// The guest exits on any host interrupt (VCPU_EXIT_IRQ), including the
+/// scheduler tick, so the VMM is preempted like any other thread.
+pub const SYS_VCPU_RUN: u64 = 0x3B;
+
+/// Read a guest register
+/// Args: vcpu_cap, reg (x0-x30 = 0-30, then PC, PSTATE, MPIDR, EL1 registers)
+/// Returns: 0 with the value in x1, u64::MAX on error
+pub const SYS_VCPU_READ_REG: u64 = 0x3C;
+
+/// Write a guest register
+/// Args: vcpu_cap, reg, value
+/// Returns: 0 on success, u64::MAX on error
+pub const SYS_VCPU_WRITE_REG: u64 = 0x3D;
+
+/// Raise or lower a guest's virtual IRQ line
+/// Args: vcpu_cap, level (non-zero = asserted)
+/// Returns: 0 on success, u64::MAX on error
+pub const SYS_VCPU_INJECT_IRQ: u64 = 0x3E;
+
 /// Change memory protection flags for existing mapping
 /// Args: virtual_addr, size, new_permissions (read=1, write=2, exec=4)
 /// Returns: 0 on success, -1 on error
@@ -228,6 +438,33 @@ pub const SYS_IRQ_HANDLER_GET: u64 = 0x40;
 /// Must be called by driver after servicing interrupt to re-enable IRQ
 pub const SYS_IRQ_HANDLER_ACK: u64 = 0x41;
 
+/// IRQControl_GetMSI - Allocate an MSI vector for a PCIe device (requires IRQControl capability)
+/// Args: irq_control_cap, device_id, notification_cap, irq_handler_slot
+/// Returns: 0 on success with the MSI address in x1 and data in x2, -1 on error
+///
+/// device_id is the ITS DeviceID (PCIe requester ID). The driver programs
+/// the address/data pair into the device's MSI capability or MSI-X table.
+/// Needs a GICv3 with an ITS.
+pub const SYS_IRQ_MSI_GET: u64 = 0x42;
+
+/// IRQControl_GetShared - Subscribe to a shared wired IRQ (requires IRQControl capability)
+/// Args: irq_control_cap, irq_num, notification_cap, irq_handler_slot, badge
+/// Returns: 0 on success, -1 on error
+///
+/// Like IRQControl_Get, but other shared handlers may subscribe to the same
+/// IRQ. Each is signaled with its own badge (non-zero) when the IRQ fires;
+/// the IRQ is unmasked once all of them have acknowledged it. Fails if the
+/// IRQ has an exclusive handler.
+pub const SYS_IRQ_HANDLER_GET_SHARED: u64 = 0x43;
+
+/// IRQHandler_Clear - Unbind an IRQHandler from its IRQ (requires IRQHandler capability)
+/// Args: irq_handler_cap
+/// Returns: 0 on success, -1 on error
+///
+/// The handler is no longer signaled and no longer holds the IRQ back; the
+/// capability itself stays until deleted.
+pub const SYS_IRQ_HANDLER_CLEAR: u64 = 0x44;
+
 // System control syscalls
 
 /// Shutdown the system
@@ -253,6 +490,130 @@ pub const SYS_SHUTDOWN: u64 = 0x50;
 /// Cannot forge capabilities or access root-task's memory.
 pub const SYS_RETYPE: u64 = 0x26;
 
+/// Register a pager endpoint for a thread's page faults
+/// Args: target_tcb_cap, endpoint_cap_slot (u64::MAX to unregister)
+/// Returns: 0 on success, -1 on error
+///
+/// Translation and permission faults taken by the target thread are sent as a
+/// `FaultMessage` to the endpoint instead of panicking the kernel. The pager
+/// receives them with SYS_RECV. Other exceptions (`FAULT_CRASH`) and
+/// SYS_PROCESS_EXIT (`FAULT_EXIT`) are reported the same way, leaving the
+/// thread for its supervisor to destroy. Requires CAP_PROCESS.
+pub const SYS_TCB_SET_FAULT_HANDLER: u64 = 0x27;
+
+/// Resume a thread blocked on a page fault
+/// Args: target_tcb_cap
+/// Returns: 0 on success, -1 on error (e.g. thread is not faulted)
+///
+/// Called by the pager after mapping the faulting page. The thread retries
+/// the faulting instruction. Crashed and exited threads cannot be resumed.
+pub const SYS_FAULT_RESUME: u64 = 0x28;
+
+/// Terminate the calling process
+/// Args: exit_code
+/// Returns: Does not return (-1 for the idle thread and root task)
+///
+/// The kernel tears down the process's TCB, CSpace and page tables and
+/// returns their frames to the frame allocator. A process with a fault
+/// endpoint instead blocks, and the endpoint receives a `FAULT_EXIT` message
+/// with the exit code; the supervisor destroys it with SYS_PROCESS_DESTROY.
+pub const SYS_PROCESS_EXIT: u64 = 0x29;
+
+/// Terminate another process
+/// Args: target_tcb_cap
+/// Returns: 0 on success, -1 on error
+///
+/// Same teardown as SYS_PROCESS_EXIT. Requires CAP_PROCESS. The caller
+/// should delete the TCB capability afterwards.
+pub const SYS_PROCESS_DESTROY: u64 = 0x2A;
+
+/// Read the kernel log ring buffer
+/// Args: buffer_ptr, buffer_len, offset
+/// Returns: bytes copied (x0) and the offset to continue from (x1), -1 on error
+///
+/// Offsets count bytes logged since boot. Overwritten offsets continue from
+/// the oldest byte still held; u64::MAX returns the current end of the log.
+/// Requires CAP_KLOG.
+pub const SYS_KLOG_READ: u64 = 0x2B;
+
+/// Get CPU usage statistics for a thread
+/// Args: index, buffer_ptr, buffer_len
+/// Returns: 0 on success, -1 when index is past the last thread
+///
+/// Fills the buffer with a `ThreadStats` record (9 × u64: tid, priority,
+/// state, CPU time in µs, cycles, instructions, switches, capabilities,
+/// process). A buffer of 8 × u64 gets all but `process`, the TID of the
+/// thread's main thread. Threads are enumerated by counting index up from 0.
+pub const SYS_THREAD_STATS: u64 = 0x2C;
+
+/// Arm or kick the hang detection watchdog
+/// Args: timeout_ms (0 = keep the current timeout)
+/// Returns: 0 on success, -1 on error
+///
+/// The first kick arms the watchdog (5 s unless a timeout is given);
+/// after that it must be kicked within the timeout or the kernel dumps
+/// its thread state and resets the system. Requires CAP_PROCESS.
+pub const SYS_WATCHDOG_KICK: u64 = 0x2D;
+
+/// Control the syscall trace (kernels built with `syscall-trace` only)
+/// Args: op, arg0, arg1, arg2
+/// Returns: 0 on success (READ: records copied in x0, next sequence number
+/// in x1), -1 on error
+///
+/// Ops: 0 = enable, 1 = disable, 2 = clear, 3 = add TID to the filter
+/// (arg0), 4 = clear the filter (trace everyone), 5 = read records into
+/// buffer arg0 of length arg1 starting at sequence number arg2. Each record
+/// is 13 × u64: seq, timestamp (ns), duration (ns), tid, syscall number,
+/// x0-x5, return value, flags. Requires CAP_PROCESS.
+pub const SYS_TRACE_CTL: u64 = 0x2E;
+
+/// Move a thread to another scheduling domain
+/// Args: target_tcb_cap, domain
+/// Returns: 0 on success, -1 on error
+///
+/// Threads only run during their domain's windows of the kernel's static
+/// domain schedule (16 domains; all threads start in domain 0, and new
+/// processes inherit their creator's). Requires CAP_DOMAIN.
+pub const SYS_DOMAIN_SET: u64 = 0x2F;
+
+/// Get CPU idle statistics
+/// Args: buffer_ptr, buffer_len
+/// Returns: 0 on success, -1 on error
+///
+/// Fills the buffer with an `IdleStats` record (4 × u64: uptime in µs,
+/// time idle in µs, times the CPU went idle, interrupts that woke it).
+pub const SYS_IDLE_STATS: u64 = 0x46;
+
+/// Bind a notification to the calling thread
+/// Args: notification_cap_slot (u64::MAX to unbind)
+/// Returns: 0 on success, -1 on error
+///
+/// A signal on a bound notification that no one waits on ends the receive
+/// (SYS_RECV, SYS_RECV_CAP, SYS_REPLY_RECV) the thread is blocked in, and a
+/// receive finds signals already pending: it returns 0 bytes, with the
+/// signal bits in x1 where a message's badge goes. A thread binds one
+/// notification, a notification one thread; binding replaces the old
+/// binding, and fails for a notification bound to another thread.
+pub const SYS_TCB_BIND_NOTIFICATION: u64 = 0x47;
+
+/// Create a worker thread in the calling process
+/// Args: entry_point, stack_pointer, ipc_buffer, priority, arg0, arg1
+/// Returns: TID of the new thread, or -1 on error
+///
+/// The thread shares the caller's CSpace, VSpace, capabilities, fault
+/// endpoint and scheduling domain, and starts at entry_point with arg0 and
+/// arg1 in x0 and x1. The caller maps its stack (stack_pointer is the top)
+/// and its page-aligned IPC buffer. Its priority is clamped to the
+/// caller's. A process has at most `thread::MAX_WORKERS` workers; they go
+/// when it does, and a crash or exit in any of them is the process's.
+pub const SYS_THREAD_CREATE: u64 = 0x48;
+
+/// End the calling worker thread
+/// Args: none
+/// Returns: Does not return (-1 for a process's main thread, which exits
+/// with SYS_PROCESS_EXIT)
+pub const SYS_THREAD_EXIT: u64 = 0x49;
+
 /// Register current process as root-task for yield (temporary)
 /// Args: vspace_root (TTBR0 physical address)
 /// Returns: 0 on success
@@ -0,0 +1,277 @@
+//! Process Exit and Destruction
+//!
+//! A process created with `SYS_PROCESS_CREATE` owns a TCB frame, a CSpace
+//! (the `CNodeCdt` page plus its slot array) and a page table tree. Until
+//! now none of it was ever given back: a component that finished or was
+//! killed leaked everything.
+//!
+//! `SYS_PROCESS_EXIT` tears down the calling process and `SYS_PROCESS_DESTROY`
+//! tears down another one through a TCB capability. Teardown:
+//!
+//! 1. takes the thread off the ready queue and any IPC queue it waits in,
+//!    and fails the call of a client it owed a reply to
+//! 2. deletes every capability in its CSpace (so endpoints and
+//!    notifications lose their last references as usual)
+//! 3. frees the page tables, the CSpace frames and the TCB frame
+//!
+//! A process's worker threads (see `thread`) share its CSpace and VSpace,
+//! so they go with it: teardown takes every one of its threads off the
+//! queues first, and frees their TCB frames last. Exiting or destroying a
+//! worker ends its whole process; `SYS_THREAD_EXIT` ends just the worker.
+//!
+//! It also unbinds the IRQs the process handled and withdraws the shared
+//! memory names it registered, so a restarted instance can claim them again.
+//!
+//! Frames mapped into the process (code, stack, shared memory) are not
+//! freed: they were supplied by the parent, which still owns them. Only
+//! the private copies made by copy-on-write faults (`memory::cow`) go with
+//! the page tables. The parent should delete its TCB capability to the
+//! destroyed process.
+//!
+//! The idle thread and the root task are never torn down.
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::arch::aarch64::page_table::PageTable;
+use crate::memory::{dealloc_frame, PageFrameNumber, PageMapper, PhysAddr, PAGE_SIZE};
+use crate::objects::cnode_cdt::CNodeCdt;
+use crate::objects::{CapType, Endpoint, IRQHandler, Notification, ThreadState, TCB};
+use crate::ksyscall_debug;
+
+/// TID of the root task; TID 0 is the idle thread
+const ROOT_TASK_TID: usize = 1;
+
+/// Terminate the calling process
+///
+/// # Arguments
+/// * `tf` - Trap frame of the calling thread, replaced with the next thread's
+/// * `exit_code` - Reported on the console, and to the fault endpoint
+///
+/// A process with a fault endpoint is not torn down: it blocks, and its
+/// endpoint receives a `FAULT_EXIT` message carrying `exit_code`, so its
+/// supervisor can decide whether to restart it before destroying it.
+///
+/// # Returns
+/// Does not return to the caller; u64::MAX if the caller may not exit
+pub fn sys_process_exit(tf: &mut TrapFrame, exit_code: u64) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || !can_destroy(current) {
+            ksyscall_debug!("[syscall] process_exit: caller cannot exit");
+            return u64::MAX;
+        }
+
+        crate::kprintln!("[syscall] process_exit: TID {:#x} exited with code {}", (*current).tid(), exit_code);
+
+        // A supervised process waits for its supervisor to destroy it
+        if crate::ipc::fault::handle_user_fault(tf) {
+            return 0;
+        }
+        exit_current(tf, current)
+    }
+}
+
+/// Terminate another process through its TCB capability
+///
+/// # Arguments
+/// * `tf` - Trap frame of the calling thread
+/// * `tcb_cap_slot` - TCB capability of the process to destroy
+///
+/// # Returns
+/// 0 on success, u64::MAX on error. Destroying the caller's own process
+/// behaves like `sys_process_exit`. Requires CAP_PROCESS.
+pub fn sys_process_destroy(tf: &mut TrapFrame, tcb_cap_slot: u64) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            return u64::MAX;
+        }
+
+        if !(*current).has_capability(TCB::CAP_PROCESS) {
+            ksyscall_debug!("[syscall] process_destroy: caller lacks CAP_PROCESS capability");
+            return u64::MAX;
+        }
+
+        let target = super::lookup_tcb_capability(tcb_cap_slot as usize);
+        if target.is_null() || !can_destroy(target) {
+            ksyscall_debug!("[syscall] process_destroy: slot {} is not a destroyable TCB", tcb_cap_slot);
+            return u64::MAX;
+        }
+
+        crate::kprintln!("[syscall] process_destroy: TID {:#x} destroyed by TID {:#x}",
+                         (*target).tid(), (*current).tid());
+
+        if (*target).process() == (*current).process() {
+            return exit_current(tf, current);
+        }
+
+        destroy(target);
+        0
+    }
+}
+
+/// Whether a thread belongs to a process that may be torn down
+unsafe fn can_destroy(tcb: *mut TCB) -> bool {
+    (*(*tcb).process()).tid() > ROOT_TASK_TID && (*tcb).state() != ThreadState::Inactive
+}
+
+/// Destroy the current thread and switch to the next one
+unsafe fn exit_current(tf: &mut TrapFrame, current: *mut TCB) -> u64 {
+    // Switched away from while its TCB is still there
+    crate::scheduler::leave_current();
+    destroy(current);
+
+    let next = crate::scheduler::schedule();
+    if next.is_null() {
+        // Cannot happen while the idle thread exists
+        panic!("[syscall] process_exit: nothing left to run");
+    }
+
+    super::switch_to(tf, next);
+
+    // Not seen by anyone: `tf` now belongs to the next thread
+    0
+}
+
+/// Tear down a process and return its frames to the frame allocator
+///
+/// `tcb` may be any of the process's threads: all of them go.
+///
+/// # Safety
+/// - `tcb` must be a thread of a process created by `SYS_PROCESS_CREATE`
+/// - If `tcb` is the current thread, or another thread of its process is,
+///   the caller must switch away before returning to userspace
+pub unsafe fn destroy(tcb: *mut TCB) {
+    let process = (*tcb).process();
+
+    // Every thread off the queues first, so none runs on what goes next
+    let mut thread = process;
+    while !thread.is_null() {
+        release(thread);
+        thread = (*thread).next_thread();
+    }
+    super::shmem_withdraw((*process).tid());
+
+    // The CSpace: delete every capability, then free its frames
+    let cspace_ptr = (*process).cspace_root() as *mut CNodeCdt;
+    if !cspace_ptr.is_null() {
+        let cspace = &mut *cspace_ptr;
+        for slot in 0..cspace.num_slots() {
+            if let Some(cap) = cspace.lookup(slot) {
+                // A call still queued on one of our endpoints
+                let mut thread = process;
+                while !thread.is_null() {
+                    match cap.cap_type() {
+                        CapType::Endpoint => {
+                            (*(cap.object_ptr() as *mut Endpoint)).dequeue_specific_sender(thread);
+                        }
+                        CapType::Notification => {
+                            (*(cap.object_ptr() as *mut Notification)).remove_waiter(thread);
+                        }
+                        _ => {}
+                    }
+                    thread = (*thread).next_thread();
+                }
+                // Free the IRQ for a restarted driver to claim
+                if cap.cap_type() == CapType::IrqHandler {
+                    crate::objects::irq_handler::unlink_irq_handler(cap.object_ptr() as *mut IRQHandler);
+                }
+                let _ = cspace.delete(slot);
+            }
+        }
+
+        let slots_end = cspace.slots_paddr().as_usize()
+            + cspace.num_slots() * core::mem::size_of::<Option<*mut crate::objects::CapNode>>();
+        free_frames(cspace_ptr as usize, slots_end);
+    }
+
+    // The VSpace: the table tree, then the root. Its ASID is released
+    // (flushing its TLB entries) before the tables can be reused.
+    let vspace_root = (*process).vspace_root();
+    if vspace_root != 0 {
+        PageMapper::new(&mut *(vspace_root as *mut PageTable)).free_tables();
+        crate::memory::asid::release(vspace_root as u64);
+        dealloc_frame(PageFrameNumber::from_phys_addr(PhysAddr::new(vspace_root)));
+    }
+
+    // Last, the TCB frames themselves. No server's `reply_to` names them
+    // any more (see `cancel_ipc`).
+    let mut thread = process;
+    while !thread.is_null() {
+        let next = (*thread).next_thread();
+        dealloc_frame(PageFrameNumber::from_phys_addr(PhysAddr::new(thread as usize)));
+        thread = next;
+    }
+}
+
+/// Tear down a worker thread, leaving the rest of its process running
+///
+/// # Safety
+/// - `tcb` must be a worker thread created by `SYS_THREAD_CREATE`
+/// - If `tcb` is the current thread, the caller must switch away before
+///   returning to userspace
+pub unsafe fn destroy_worker(tcb: *mut TCB) {
+    release(tcb);
+
+    // Leave the process's list of workers
+    let mut link = (*tcb).process();
+    while !link.is_null() {
+        if (*link).next_thread() == tcb {
+            (*link).set_next_thread((*tcb).next_thread());
+            break;
+        }
+        link = (*link).next_thread();
+    }
+
+    dealloc_frame(PageFrameNumber::from_phys_addr(PhysAddr::new(tcb as usize)));
+}
+
+/// Stop a thread: off the scheduler and every queue it waits in, Inactive
+unsafe fn release(tcb: *mut TCB) {
+    cancel_ipc(tcb);
+    let bound = (*tcb).bound_notification();
+    if !bound.is_null() {
+        (*bound).unbind();
+    }
+    crate::scheduler::stats::unregister(tcb);
+    (*tcb).set_state(ThreadState::Inactive);
+}
+
+/// Take a thread off every queue it is waiting in
+unsafe fn cancel_ipc(tcb: *mut TCB) {
+    let thread = &mut *tcb;
+    match thread.state() {
+        ThreadState::Runnable => crate::scheduler::dequeue(tcb),
+        ThreadState::BlockedOnSend { endpoint } | ThreadState::BlockedOnFault { endpoint } => {
+            (*(endpoint as *mut Endpoint)).dequeue_specific_sender(tcb);
+        }
+        ThreadState::BlockedOnReceive { endpoint } => {
+            (*(endpoint as *mut Endpoint)).dequeue_specific_receiver(tcb);
+        }
+        ThreadState::BlockedOnNotification { notification } => {
+            (*(notification as *mut Notification)).remove_waiter(tcb);
+        }
+        // A call still queued on an endpoint is removed with the CSpace
+        ThreadState::BlockedOnReply | ThreadState::Running | ThreadState::Inactive => {}
+    }
+
+    // A server that received our call no longer owes us a reply
+    thread.clear_replying_server();
+
+    // A client waiting for our reply gets an error instead
+    let caller = thread.reply_to();
+    if !caller.is_null() {
+        thread.set_reply_to(core::ptr::null_mut());
+        if (*caller).state() == ThreadState::BlockedOnReply {
+            (*caller).context_mut().x0 = u64::MAX;
+            (*caller).set_state(ThreadState::Runnable);
+            crate::scheduler::enqueue(caller);
+        }
+    }
+}
+
+/// Return the frames covering [start, end) to the frame allocator
+unsafe fn free_frames(start: usize, end: usize) {
+    for addr in (start..end).step_by(PAGE_SIZE) {
+        dealloc_frame(PageFrameNumber::from_phys_addr(PhysAddr::new(addr)));
+    }
+}
@@ -0,0 +1,154 @@
+//! Worker Threads
+//!
+//! A process created with `SYS_PROCESS_CREATE` starts with a single thread,
+//! its main thread. `SYS_THREAD_CREATE` adds worker threads to the calling
+//! process: TCBs of their own, with their own stack, IPC buffer and
+//! priority, but running in the process's VSpace with its CSpace. The
+//! process's virtual address and capability slot allocators are the main
+//! thread's, so every thread allocates from the same ones.
+//!
+//! The main thread keeps the list of workers (`TCB::next_thread`). They go
+//! with the process: tearing it down (see `process::destroy`) tears down
+//! all of its threads. A crash or `SYS_PROCESS_EXIT` in a worker is the
+//! process's, reported to the fault endpoint under the process's TID; a
+//! worker that is done ends on its own with `SYS_THREAD_EXIT`.
+//!
+//! The kernel allocates a TCB frame for each worker, so a process may have
+//! at most [`MAX_WORKERS`] at a time.
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::memory::{alloc_frame, VirtAddr, PAGE_SIZE};
+use crate::objects::{ThreadState, TCB};
+use crate::ksyscall_debug;
+
+/// Most worker threads a process may have at a time
+pub const MAX_WORKERS: usize = 15;
+
+/// Create a worker thread in the calling process
+///
+/// # Arguments
+/// * `entry_point` - Where the thread starts
+/// * `stack_pointer` - Top of its stack, mapped by the caller
+/// * `ipc_buffer` - Its IPC buffer, page-aligned and mapped by the caller
+///   (0 for none)
+/// * `priority` - Its priority, clamped to the caller's
+/// * `arg0`, `arg1` - Its first arguments (x0, x1)
+///
+/// # Returns
+/// TID of the new thread, or u64::MAX on error
+pub fn sys_thread_create(
+    tf: &mut TrapFrame,
+    entry_point: u64,
+    stack_pointer: u64,
+    ipc_buffer: u64,
+    priority: u64,
+    arg0: u64,
+    arg1: u64,
+) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() {
+            return u64::MAX;
+        }
+
+        if entry_point == 0 || stack_pointer == 0 || stack_pointer % 16 != 0 {
+            ksyscall_debug!("[syscall] thread_create: bad entry point or stack pointer");
+            return u64::MAX;
+        }
+        if ipc_buffer as usize % PAGE_SIZE != 0 {
+            ksyscall_debug!("[syscall] thread_create: IPC buffer {:#x} not page-aligned", ipc_buffer);
+            return u64::MAX;
+        }
+
+        let process = (*current).process();
+        if workers(process) >= MAX_WORKERS {
+            ksyscall_debug!("[syscall] thread_create: TID {:#x} has {} workers already",
+                            (*process).tid(), MAX_WORKERS);
+            return u64::MAX;
+        }
+
+        let tcb_frame = match alloc_frame() {
+            Some(pfn) => pfn.phys_addr(),
+            None => {
+                ksyscall_debug!("[syscall] thread_create: out of memory (TCB)");
+                return u64::MAX;
+            }
+        };
+
+        // TID == TCB address, as for processes
+        let tid = tcb_frame.as_usize();
+        let tcb_ptr = tid as *mut TCB;
+        let tcb = TCB::new(
+            tid,
+            (*process).cspace_root(),
+            (*process).vspace_root(),
+            VirtAddr::new(ipc_buffer as usize),
+            entry_point,
+            stack_pointer,
+            (*current).capabilities(),
+        );
+        core::ptr::write(tcb_ptr, tcb);
+
+        let thread = &mut *tcb_ptr;
+        thread.set_arguments(arg0, arg1, 0);
+        thread.context_mut().saved_ttbr0 = tf.saved_ttbr0 & crate::memory::asid::TTBR_BADDR_MASK;
+
+        // Lower numbers run first: a worker may not outrank its creator
+        thread.set_priority((priority as u8).max((*current).priority()));
+        thread.set_domain((*current).domain());
+        thread.set_fault_endpoint((*process).fault_endpoint());
+
+        // Join the process's list of workers
+        thread.set_process(process);
+        thread.set_next_thread((*process).next_thread());
+        (*process).set_next_thread(tcb_ptr);
+
+        thread.set_state(ThreadState::Runnable);
+        crate::scheduler::enqueue(tcb_ptr);
+        crate::scheduler::stats::register(tcb_ptr);
+
+        crate::kprintln!("[syscall] thread_create: TID {:#x} started worker TID {:#x}",
+                         (*process).tid(), tid);
+        tid as u64
+    }
+}
+
+/// End the calling worker thread
+///
+/// # Returns
+/// Does not return to the caller; u64::MAX for a process's main thread
+pub fn sys_thread_exit(tf: &mut TrapFrame) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || !(*current).is_worker() {
+            ksyscall_debug!("[syscall] thread_exit: caller is not a worker thread");
+            return u64::MAX;
+        }
+
+        // Switched away from while its TCB is still there
+        crate::scheduler::leave_current();
+        super::process::destroy_worker(current);
+
+        let next = crate::scheduler::schedule();
+        if next.is_null() {
+            // Cannot happen while the idle thread exists
+            panic!("[syscall] thread_exit: nothing left to run");
+        }
+
+        super::switch_to(tf, next);
+
+        // Not seen by anyone: `tf` now belongs to the next thread
+        0
+    }
+}
+
+/// Number of worker threads of a process's main thread
+unsafe fn workers(process: *mut TCB) -> usize {
+    let mut count = 0;
+    let mut thread = (*process).next_thread();
+    while !thread.is_null() {
+        count += 1;
+        thread = (*thread).next_thread();
+    }
+    count
+}
@@ -0,0 +1,283 @@
+//! Syscall Tracing
+//!
+//! Built with the `syscall-trace` feature, the kernel records every syscall
+//! into a ring buffer: caller TID, number, the first six arguments, return
+//! value, entry timestamp and duration. Recording is off until enabled with
+//! `SYS_TRACE_CTL`, and can be restricted to a handful of TIDs, so the
+//! interaction between a few components can be followed without the rest
+//! of the system drowning it out.
+//!
+//! Records are numbered by a sequence number counting from 0 at boot. Once
+//! more than `TRACE_RECORDS` are taken the oldest are overwritten, and a
+//! reader asking for an overwritten one continues from the oldest still
+//! held (as with the kernel log).
+//!
+//! `SYS_TRACE_CTL` calls themselves are never traced, so a reader polling
+//! the buffer does not fill it.
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::objects::TCB;
+use crate::ksyscall_debug;
+use super::numbers;
+
+/// Number of records kept
+pub const TRACE_RECORDS: usize = 256;
+
+/// Maximum number of TIDs in the filter
+pub const MAX_FILTER_TIDS: usize = 8;
+
+// `SYS_TRACE_CTL` operations
+pub const TRACE_CTL_ENABLE: u64 = 0;
+pub const TRACE_CTL_DISABLE: u64 = 1;
+pub const TRACE_CTL_CLEAR: u64 = 2;
+pub const TRACE_CTL_FILTER_ADD: u64 = 3;
+pub const TRACE_CTL_FILTER_CLEAR: u64 = 4;
+pub const TRACE_CTL_READ: u64 = 5;
+
+/// Record flag: completed by the IPC fastpath
+pub const FLAG_FASTPATH: u64 = 1 << 0;
+
+/// Record flag: the caller blocked or exited; its return value is
+/// delivered later and `ret` is 0
+pub const FLAG_SWITCHED: u64 = 1 << 1;
+
+/// One traced syscall, as delivered to userspace (little-endian, 13 × u64)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Sequence number
+    pub seq: u64,
+    /// Entry time in nanoseconds since the counter started
+    pub timestamp_ns: u64,
+    /// Time spent in the kernel in nanoseconds
+    pub duration_ns: u64,
+    /// Caller TID
+    pub tid: u64,
+    /// Syscall number
+    pub number: u64,
+    /// Arguments x0-x5
+    pub args: [u64; 6],
+    /// Return value (x0)
+    pub ret: u64,
+    /// `FLAG_*` bits
+    pub flags: u64,
+}
+
+impl TraceRecord {
+    /// Size of the encoded record in bytes
+    pub const SIZE: usize = 13 * 8;
+
+    /// Encode the record as it is delivered to userspace
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        let a = self.args;
+        let words = [
+            self.seq, self.timestamp_ns, self.duration_ns, self.tid, self.number,
+            a[0], a[1], a[2], a[3], a[4], a[5], self.ret, self.flags,
+        ];
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// A syscall being traced, from `enter` to `exit`
+pub struct TraceEntry {
+    caller: *mut TCB,
+    start: u64,
+    record: TraceRecord,
+}
+
+static mut RECORDS: [TraceRecord; TRACE_RECORDS] = [TraceRecord {
+    seq: 0, timestamp_ns: 0, duration_ns: 0, tid: 0, number: 0, args: [0; 6], ret: 0, flags: 0,
+}; TRACE_RECORDS];
+
+/// Sequence number of the next record
+static mut NEXT_SEQ: u64 = 0;
+
+/// Whether recording is on
+static mut ENABLED: bool = false;
+
+/// Traced TIDs (empty = everyone)
+static mut FILTER: [usize; MAX_FILTER_TIDS] = [0; MAX_FILTER_TIDS];
+static mut FILTER_LEN: usize = 0;
+
+/// Convert counter ticks to nanoseconds
+fn ticks_to_ns(ticks: u64) -> u64 {
+    let freq = crate::scheduler::timer::timer_frequency().max(1);
+    (ticks as u128 * 1_000_000_000 / freq as u128) as u64
+}
+
+/// Whether a thread passes the filter
+fn filter_matches(filter: &[usize], tid: usize) -> bool {
+    filter.is_empty() || filter.contains(&tid)
+}
+
+/// Range of sequence numbers a read starting at `seq` covers
+///
+/// `seq` is clamped into the retained records; at most `max` are returned.
+fn read_range(seq: u64, max: usize, next: u64) -> (u64, u64) {
+    let oldest = next.saturating_sub(TRACE_RECORDS as u64);
+    let start = seq.clamp(oldest, next);
+    (start, core::cmp::min(next, start + max as u64))
+}
+
+/// Start tracing a syscall, if tracing is on and the caller passes the filter
+///
+/// # Safety
+///
+/// Must be called from the syscall exception path before dispatching.
+pub unsafe fn enter(tf: &TrapFrame) -> Option<TraceEntry> {
+    if !ENABLED || tf.syscall_number() == numbers::SYS_TRACE_CTL {
+        return None;
+    }
+
+    let caller = crate::scheduler::current_thread();
+    if caller.is_null() {
+        return None;
+    }
+
+    let tid = (*caller).tid();
+    let filter = &*core::ptr::addr_of!(FILTER);
+    if !filter_matches(&filter[..FILTER_LEN], tid) {
+        return None;
+    }
+
+    let start = crate::scheduler::timer::read_counter();
+    let args = tf.syscall_args();
+    Some(TraceEntry {
+        caller,
+        start,
+        record: TraceRecord {
+            timestamp_ns: ticks_to_ns(start),
+            tid: tid as u64,
+            number: tf.syscall_number(),
+            args: [args[0], args[1], args[2], args[3], args[4], args[5]],
+            ..Default::default()
+        },
+    })
+}
+
+/// Finish tracing a syscall and store its record
+///
+/// `tf` is the frame the exception returns with; if another thread now
+/// runs, the caller's return value is not known yet.
+///
+/// # Safety
+///
+/// Must be called from the syscall exception path after dispatching.
+pub unsafe fn exit(entry: Option<TraceEntry>, tf: &TrapFrame, fastpath: bool) {
+    let Some(mut entry) = entry else { return };
+
+    let end = crate::scheduler::timer::read_counter();
+    entry.record.duration_ns = ticks_to_ns(end.wrapping_sub(entry.start));
+    if fastpath {
+        entry.record.flags |= FLAG_FASTPATH;
+    }
+    if crate::scheduler::current_thread() == entry.caller {
+        entry.record.ret = tf.x0;
+    } else {
+        entry.record.flags |= FLAG_SWITCHED;
+    }
+
+    entry.record.seq = NEXT_SEQ;
+    let records = &mut *core::ptr::addr_of_mut!(RECORDS);
+    records[(NEXT_SEQ % TRACE_RECORDS as u64) as usize] = entry.record;
+    NEXT_SEQ += 1;
+}
+
+/// Control the syscall trace
+///
+/// # Arguments
+/// * `tf` - Trap frame of the caller (x1 receives the next sequence number
+///   for `TRACE_CTL_READ`)
+/// * `op` - `TRACE_CTL_*` operation
+/// * `arg0`..`arg2` - Operation arguments:
+///   - `TRACE_CTL_FILTER_ADD`: arg0 = TID to trace
+///   - `TRACE_CTL_READ`: arg0 = buffer, arg1 = buffer length, arg2 = first
+///     sequence number to read
+///
+/// # Returns
+/// 0 on success (`TRACE_CTL_READ`: the number of records copied), u64::MAX
+/// on error. Requires CAP_PROCESS.
+pub fn sys_trace_ctl(tf: &mut TrapFrame, op: u64, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+    unsafe {
+        let current = crate::scheduler::current_thread();
+        if current.is_null() || !(*current).has_capability(TCB::CAP_PROCESS) {
+            ksyscall_debug!("[syscall] trace_ctl: caller lacks CAP_PROCESS capability");
+            return u64::MAX;
+        }
+
+        match op {
+            TRACE_CTL_ENABLE => ENABLED = true,
+            TRACE_CTL_DISABLE => ENABLED = false,
+            TRACE_CTL_CLEAR => NEXT_SEQ = 0,
+            TRACE_CTL_FILTER_ADD => {
+                let filter = &mut *core::ptr::addr_of_mut!(FILTER);
+                if filter[..FILTER_LEN].contains(&(arg0 as usize)) {
+                    return 0;
+                }
+                if FILTER_LEN == MAX_FILTER_TIDS {
+                    ksyscall_debug!("[syscall] trace_ctl: filter full");
+                    return u64::MAX;
+                }
+                filter[FILTER_LEN] = arg0 as usize;
+                FILTER_LEN += 1;
+            }
+            TRACE_CTL_FILTER_CLEAR => FILTER_LEN = 0,
+            TRACE_CTL_READ => return read(tf, arg0, arg1, arg2),
+            _ => {
+                ksyscall_debug!("[syscall] trace_ctl: unknown operation {}", op);
+                return u64::MAX;
+            }
+        }
+    }
+    0
+}
+
+/// Copy records starting at sequence number `seq` to userspace
+unsafe fn read(tf: &mut TrapFrame, buf_ptr: u64, buf_len: u64, seq: u64) -> u64 {
+    let max = buf_len as usize / TraceRecord::SIZE;
+    let (start, end) = read_range(seq, max, NEXT_SEQ);
+
+    let records = &*core::ptr::addr_of!(RECORDS);
+    for (i, seq) in (start..end).enumerate() {
+        let bytes = records[(seq % TRACE_RECORDS as u64) as usize].to_bytes();
+        let dest = buf_ptr + (i * TraceRecord::SIZE) as u64;
+        if !super::copy_to_user(&bytes, dest, TraceRecord::SIZE, tf.saved_ttbr0) {
+            ksyscall_debug!("[syscall] trace_ctl: failed to copy record to user");
+            return u64::MAX;
+        }
+    }
+
+    tf.x1 = end;
+    end - start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_range_clamps_to_retained_records() {
+        assert_eq!(read_range(0, 10, 4), (0, 4));
+        assert_eq!(read_range(9, 10, 4), (4, 4));
+
+        // Overwritten records skip to the oldest still held
+        let next = TRACE_RECORDS as u64 + 20;
+        assert_eq!(read_range(0, 10, next), (20, 30));
+    }
+
+    #[test]
+    fn filter_and_encoding() {
+        assert!(filter_matches(&[], 7));
+        assert!(filter_matches(&[3, 7], 7));
+        assert!(!filter_matches(&[3], 7));
+
+        let record = TraceRecord { seq: 5, number: 0x03, ret: 42, flags: FLAG_FASTPATH, ..Default::default() };
+        let bytes = record.to_bytes();
+        assert_eq!(&bytes[32..40], &0x03u64.to_le_bytes());
+        assert_eq!(&bytes[88..96], &42u64.to_le_bytes());
+    }
+}
@@ -0,0 +1,186 @@
+//! User Memory Access
+//!
+//! Syscall handlers never dereference user pointers themselves: every
+//! buffer goes through `copy_from_user` or `copy_to_user`. The kernel is
+//! identity-mapped into every address space, so a user pointer that is
+//! not checked could make the kernel read or overwrite its own memory (or
+//! another component's frames), and an unmapped one would fault in the
+//! kernel.
+//!
+//! Before copying, the whole range is checked against the caller's page
+//! tables: every page must be mapped with EL0 access, and for a write be
+//! writable from EL0 or copy-on-write (broken first, see `memory::cow`).
+//! The copy itself runs at EL1 with the caller's TTBR0 installed.
+
+use crate::arch::aarch64::page_table::{PageTable, PageTableFlags};
+use crate::memory::{PageMapper, VirtAddr};
+
+/// First address above the user (TTBR0) range, 48-bit VAs
+const USER_ADDR_LIMIT: u64 = 1 << 48;
+
+/// Whether a leaf page table entry lets EL0 read, or with `write` write
+fn user_accessible(entry: u64, write: bool) -> bool {
+    let flags = PageTableFlags::from_bits_truncate(entry);
+    // AP[1]: accessible from EL0; AP[2]: read-only
+    let el0 = PageTableFlags::AP_RW_ALL.bits();
+    let read_only = PageTableFlags::AP_RO_EL1.bits();
+
+    flags.contains(PageTableFlags::VALID)
+        && entry & el0 != 0
+        && (!write || entry & read_only == 0 || flags.contains(PageTableFlags::SW_COW))
+}
+
+/// Check that `len` bytes at `user_ptr` are user memory of the address space
+///
+/// # Safety
+/// `caller_ttbr0` must be 0 or the page table root of a user address space
+/// (ASID bits are ignored).
+unsafe fn check_range(caller_ttbr0: u64, user_ptr: u64, len: usize, write: bool) -> bool {
+    let root = caller_ttbr0 & crate::memory::asid::TTBR_BADDR_MASK;
+    let end = match user_ptr.checked_add(len as u64) {
+        Some(end) if end <= USER_ADDR_LIMIT => end,
+        _ => return false,
+    };
+    if root == 0 {
+        return false;
+    }
+
+    let mapper = PageMapper::new(&mut *(root as *mut PageTable));
+    let mut addr = user_ptr;
+    while addr < end {
+        let Some((entry, level)) = mapper.leaf_entry(VirtAddr::new(addr as usize)) else {
+            return false;
+        };
+        if !user_accessible(entry, write) {
+            return false;
+        }
+
+        // On to the next page or block
+        let size = level.block_size() as u64;
+        addr = (addr & !(size - 1)) + size;
+    }
+    true
+}
+
+/// Copy data from userspace to kernel space
+///
+/// Fails without copying anything unless the whole user range is mapped
+/// readable from EL0 in the caller's address space. Temporarily switches
+/// to the caller's TTBR0 for the copy.
+///
+/// # Safety
+/// - len must not exceed buffer sizes
+/// - caller_ttbr0 must be the physical address of a valid page table
+pub(crate) unsafe fn copy_from_user(user_ptr: u64, kernel_buf: &mut [u8], len: usize, caller_ttbr0: u64) -> bool {
+    if len == 0 || len > kernel_buf.len() {
+        return false;
+    }
+
+    if !check_range(caller_ttbr0, user_ptr, len, false) {
+        crate::ksyscall_debug!("[syscall] copy_from_user: {:#x}+{} is not user memory", user_ptr, len);
+        return false;
+    }
+
+    // Save current TTBR0
+    let saved_ttbr0: u64;
+    core::arch::asm!(
+        "mrs {}, ttbr0_el1",
+        out(reg) saved_ttbr0,
+    );
+
+    // Switch to caller's TTBR0 (tagged with its ASID) to access userspace memory
+    core::arch::asm!(
+        "msr ttbr0_el1, {}",
+        "isb",
+        in(reg) crate::memory::asid::ttbr0_for(caller_ttbr0),
+    );
+
+    // Copy data from userspace
+    let user_slice = core::slice::from_raw_parts(user_ptr as *const u8, len);
+    kernel_buf[..len].copy_from_slice(user_slice);
+
+    // Restore kernel's TTBR0
+    core::arch::asm!(
+        "msr ttbr0_el1, {}",
+        "isb",
+        in(reg) saved_ttbr0,
+    );
+
+    true
+}
+
+/// Copy data from kernel space to userspace
+///
+/// Fails without copying anything unless the whole user range is mapped
+/// writable (or copy-on-write) from EL0 in the caller's address space.
+/// Temporarily switches to the caller's TTBR0 for the copy.
+///
+/// # Safety
+/// - len must not exceed buffer sizes
+/// - caller_ttbr0 must be the physical address of a valid page table
+pub(crate) unsafe fn copy_to_user(kernel_buf: &[u8], user_ptr: u64, len: usize, caller_ttbr0: u64) -> bool {
+    if len == 0 || len > kernel_buf.len() {
+        return false;
+    }
+
+    if !check_range(caller_ttbr0, user_ptr, len, true) {
+        crate::ksyscall_debug!("[syscall] copy_to_user: {:#x}+{} is not writable user memory", user_ptr, len);
+        return false;
+    }
+
+    // Save current TTBR0
+    let saved_ttbr0: u64;
+    core::arch::asm!(
+        "mrs {}, ttbr0_el1",
+        out(reg) saved_ttbr0,
+    );
+
+    // The write goes through the user mapping: copy-on-write pages must
+    // become private copies first
+    if !crate::memory::cow::prepare_write(caller_ttbr0, user_ptr, len) {
+        return false;
+    }
+
+    // Switch to caller's TTBR0 (tagged with its ASID) to access userspace memory
+    core::arch::asm!(
+        "msr ttbr0_el1, {}",
+        "isb",
+        in(reg) crate::memory::asid::ttbr0_for(caller_ttbr0),
+    );
+
+    // Copy data to userspace
+    let user_slice = core::slice::from_raw_parts_mut(user_ptr as *mut u8, len);
+    user_slice.copy_from_slice(&kernel_buf[..len]);
+
+    // Restore kernel's TTBR0
+    core::arch::asm!(
+        "msr ttbr0_el1, {}",
+        "isb",
+        in(reg) saved_ttbr0,
+    );
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_accessible() {
+        let data = PageTableFlags::USER_DATA.bits();
+        assert!(user_accessible(data, false));
+        assert!(user_accessible(data, true));
+
+        // Copy-on-write pages are read-only but may be written by the kernel
+        let cow = PageTableFlags::USER_COW.bits();
+        assert!(user_accessible(cow, true));
+        let read_only = cow & !PageTableFlags::SW_COW.bits();
+        assert!(user_accessible(read_only, false));
+        assert!(!user_accessible(read_only, true));
+
+        // Kernel mappings and invalid entries
+        assert!(!user_accessible(PageTableFlags::KERNEL_DATA.bits(), false));
+        assert!(!user_accessible(data & !PageTableFlags::VALID.bits(), false));
+    }
+}
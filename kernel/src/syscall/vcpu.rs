@@ -0,0 +1,210 @@
+//! VCPU Syscalls
+//!
+//! Running guests under stage-2 translation, built with the `hypervisor`
+//! feature. See `objects::vcpu` for the model: a VMM creates a VCPU, maps
+//! the guest's memory, sets its registers and runs it, handling each exit
+//! before running it again.
+
+use crate::arch::aarch64::context::TrapFrame;
+use crate::arch::aarch64::hyp;
+use crate::arch::aarch64::page_table::PageTable;
+use crate::ksyscall_debug;
+use crate::memory::{alloc_frame, dealloc_frame};
+use crate::objects::cnode_cdt::CNodeCdt;
+use crate::objects::{CapRights, CapType, Capability, Vcpu, TCB};
+use core::ptr;
+
+/// Look up a VCPU capability with `rights` in the caller's CSpace
+unsafe fn lookup_vcpu(cptr: u64, rights: CapRights) -> Option<&'static mut Vcpu> {
+    let current_tcb = crate::scheduler::current_thread();
+    if current_tcb.is_null() || (*current_tcb).cspace_root().is_null() {
+        return None;
+    }
+
+    let cspace = &*((*current_tcb).cspace_root() as *const CNodeCdt);
+    match cspace.lookup_cptr(cptr) {
+        Some(cap) if cap.cap_type() == CapType::Vcpu && cap.rights().contains(rights) => {
+            Some(&mut *(cap.object_ptr() as *mut Vcpu))
+        }
+        _ => {
+            ksyscall_debug!("[syscall] vcpu: cap_slot {} is not a VCPU with {:?}", cptr, rights);
+            None
+        }
+    }
+}
+
+/// Create a VCPU with an empty guest physical address space
+///
+/// Returns: VCPU capability slot, or u64::MAX on error
+pub(super) fn sys_vcpu_create() -> u64 {
+    if !hyp::installed() {
+        ksyscall_debug!("[syscall] vcpu_create: kernel not entered at EL2, no guests");
+        return u64::MAX;
+    }
+
+    unsafe {
+        let slot = super::sys_cap_allocate();
+        if slot == u64::MAX {
+            return u64::MAX;
+        }
+
+        let (vcpu_frame, root_frame) = match (alloc_frame(), alloc_frame()) {
+            (Some(vcpu), Some(root)) => (vcpu, root),
+            (vcpu, root) => {
+                vcpu.into_iter().chain(root).for_each(|frame| dealloc_frame(frame));
+                ksyscall_debug!("[syscall] vcpu_create: out of memory");
+                return u64::MAX;
+            }
+        };
+        let free_frames = || {
+            dealloc_frame(vcpu_frame);
+            dealloc_frame(root_frame);
+        };
+
+        let root = root_frame.phys_addr().as_usize() as *mut PageTable;
+        (*root).zero();
+        let vcpu = match Vcpu::new(root) {
+            Some(vcpu) => vcpu,
+            None => {
+                ksyscall_debug!("[syscall] vcpu_create: out of VMIDs");
+                free_frames();
+                return u64::MAX;
+            }
+        };
+        let vcpu_ptr = vcpu_frame.phys_addr().as_usize() as *mut Vcpu;
+        ptr::write(vcpu_ptr, vcpu);
+
+        // sys_cap_allocate succeeded, so there is a current thread with a CSpace
+        let cnode = &mut *((*crate::scheduler::current_thread()).cspace_root() as *mut CNodeCdt);
+        if cnode.insert_root(slot as usize, Capability::new(CapType::Vcpu, vcpu_ptr as usize)).is_err() {
+            ksyscall_debug!("[syscall] vcpu_create: failed to insert at cap_slot {}", slot);
+            crate::objects::vcpu::destroy(vcpu_ptr);
+            dealloc_frame(vcpu_frame);
+            return u64::MAX;
+        }
+
+        ksyscall_debug!("[syscall] vcpu_create: SUCCESS -> cap_slot={}", slot);
+        slot
+    }
+}
+
+/// Map physical memory into a guest's physical address space
+///
+/// Args:
+/// - vcpu_cap: VCPU capability slot
+/// - phys_addr, size: Memory to map (size rounded up to the page size)
+/// - ipa: Guest physical address
+/// - permissions: 1=read, 2=write, 4=exec, plus the page size in bits 8-9
+///   (`numbers::MAP_PAGE_*`), as for `SYS_MEMORY_MAP`
+///
+/// Returns: 0 on success, u64::MAX on error
+pub(super) fn sys_vcpu_map(vcpu_cap: u64, phys_addr: u64, size: u64, ipa: u64, permissions: u64) -> u64 {
+    unsafe {
+        let current_tcb = crate::scheduler::current_thread();
+        if current_tcb.is_null() || !(*current_tcb).has_capability(TCB::CAP_MEMORY) {
+            ksyscall_debug!("[syscall] vcpu_map: caller lacks CAP_MEMORY capability");
+            return u64::MAX;
+        }
+
+        let Some(page_size) = super::map_page_size(permissions) else {
+            ksyscall_debug!("[syscall] vcpu_map: invalid page size in permissions {:#x}", permissions);
+            return u64::MAX;
+        };
+        let Some(vcpu) = lookup_vcpu(vcpu_cap, CapRights::WRITE) else {
+            return u64::MAX;
+        };
+
+        let size = size.next_multiple_of(page_size.bytes() as u64);
+        match vcpu.map(ipa, phys_addr, size, permissions, page_size) {
+            Ok(()) => 0,
+            Err(_e) => {
+                ksyscall_debug!("[syscall] vcpu_map: ipa={:#x} -> phys={:#x}, size={:#x} failed: {:?}",
+                         ipa, phys_addr, size, _e);
+                u64::MAX
+            }
+        }
+    }
+}
+
+/// Run a guest until it exits
+///
+/// Args:
+/// - vcpu_cap: VCPU capability slot
+///
+/// Returns: exit reason (`hyp::VCPU_EXIT_*`) in x0; for a trap, ESR_EL2,
+/// FAR_EL2 and the faulting IPA in x1-x3. u64::MAX on error.
+pub(super) fn sys_vcpu_run(tf: &mut TrapFrame, vcpu_cap: u64) -> u64 {
+    unsafe {
+        let Some(vcpu) = lookup_vcpu(vcpu_cap, CapRights::WRITE) else {
+            return u64::MAX;
+        };
+
+        let Some(reason) = vcpu.run() else {
+            ksyscall_debug!("[syscall] vcpu_run: guest PSTATE is not EL0/EL1");
+            return u64::MAX;
+        };
+
+        if reason == hyp::VCPU_EXIT_TRAP {
+            (tf.x1, tf.x2, tf.x3) = vcpu.exit_syndrome();
+        }
+        reason
+    }
+}
+
+/// Read a guest register
+///
+/// Args:
+/// - vcpu_cap: VCPU capability slot (READ)
+/// - reg: Register number (`objects::vcpu::VCPU_REG_*`)
+///
+/// Returns: 0 with the value in x1, u64::MAX on error
+pub(super) fn sys_vcpu_read_reg(tf: &mut TrapFrame, vcpu_cap: u64, reg: u64) -> u64 {
+    unsafe {
+        let Some(vcpu) = lookup_vcpu(vcpu_cap, CapRights::READ) else {
+            return u64::MAX;
+        };
+        match vcpu.reg_mut(reg) {
+            Some(value) => {
+                tf.x1 = *value;
+                0
+            }
+            None => u64::MAX,
+        }
+    }
+}
+
+/// Write a guest register
+///
+/// Args:
+/// - vcpu_cap: VCPU capability slot
+/// - reg: Register number (`objects::vcpu::VCPU_REG_*`)
+/// - value: New value
+///
+/// Returns: 0 on success, u64::MAX on error
+pub(super) fn sys_vcpu_write_reg(vcpu_cap: u64, reg: u64, value: u64) -> u64 {
+    unsafe {
+        let Some(vcpu) = lookup_vcpu(vcpu_cap, CapRights::WRITE) else {
+            return u64::MAX;
+        };
+        match vcpu.reg_mut(reg) {
+            Some(slot) => {
+                *slot = value;
+                0
+            }
+            None => u64::MAX,
+        }
+    }
+}
+
+/// Raise (level != 0) or lower a guest's IRQ line
+///
+/// Returns: 0 on success, u64::MAX on error
+pub(super) fn sys_vcpu_inject_irq(vcpu_cap: u64, level: u64) -> u64 {
+    unsafe {
+        let Some(vcpu) = lookup_vcpu(vcpu_cap, CapRights::WRITE) else {
+            return u64::MAX;
+        };
+        vcpu.set_irq(level != 0);
+        0
+    }
+}
@@ -9,11 +9,14 @@
 pub const BOOT_INFO_MAGIC: u32 = 0x4B41414C;
 
 /// Boot info structure version
-pub const BOOT_INFO_VERSION: u32 = 1;
+pub const BOOT_INFO_VERSION: u32 = 3;
 
 /// Fixed virtual address where kernel maps boot info
 pub const BOOT_INFO_VADDR: usize = 0x7FFF_F000;
 
+/// Maximum kernel command line length
+pub const MAX_CMDLINE_LEN: usize = 256;
+
 /// Untyped memory region descriptor
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -29,10 +32,42 @@ pub struct UntypedRegion {
 }
 
 impl UntypedRegion {
+    /// Create a region descriptor
+    pub const fn new(paddr: u64, size_bits: u8, is_device: bool) -> Self {
+        Self {
+            paddr,
+            size_bits,
+            is_device,
+            _reserved: [0; 6],
+        }
+    }
+
     /// Get the size in bytes
     pub fn size(&self) -> usize {
         1 << self.size_bits
     }
+
+    /// Carve `size` bytes at a physical address aligned to `align`
+    ///
+    /// `watermark` is the number of bytes of the region already handed out
+    /// and is advanced past the allocation; the gap left by aligning is
+    /// skipped, not reused. `align` must be a power of two. Returns the
+    /// physical address, or `None` (leaving `watermark` alone) if the
+    /// region has no room left.
+    pub fn allocate(&self, watermark: &mut usize, size: usize, align: usize) -> Option<u64> {
+        if size == 0 || !align.is_power_of_two() {
+            return None;
+        }
+        let align = align as u64;
+        let start = self.paddr.checked_add(*watermark as u64)?;
+        let paddr = start.checked_add(align - 1)? & !(align - 1);
+        let end = paddr.checked_add(size as u64)?;
+        if end > self.paddr + self.size() as u64 {
+            return None;
+        }
+        *watermark = (end - self.paddr) as usize;
+        Some(paddr)
+    }
 }
 
 /// Device region descriptor
@@ -117,6 +152,18 @@ pub struct BootInfo {
     pub kernel_virt_base: u64,
     /// User virtual address space start
     pub user_virt_start: u64,
+    /// IRQControl capability physical address
+    pub irq_control_paddr: u64,
+    /// Physical address of the device tree blob (0 if none)
+    pub dtb_paddr: u64,
+    /// Size of the device tree blob in bytes
+    pub dtb_size: u64,
+    /// Length of the kernel command line in bytes
+    pub cmdline_len: u32,
+    /// Reserved
+    _reserved_cmdline: u32,
+    /// Kernel command line (`/chosen/bootargs`)
+    pub cmdline: [u8; MAX_CMDLINE_LEN],
     /// Untyped memory regions (max 128)
     pub untyped_regions: [UntypedRegion; 128],
     /// Device regions (max 32)
@@ -167,4 +214,10 @@ impl BootInfo {
     pub fn find_device(&self, device_type: u32) -> Option<&DeviceRegion> {
         self.device_regions().find(|d| d.device_type == device_type)
     }
+
+    /// Get the kernel command line (empty if none was passed)
+    pub fn cmdline(&self) -> &str {
+        let len = (self.cmdline_len as usize).min(MAX_CMDLINE_LEN);
+        core::str::from_utf8(&self.cmdline[..len]).unwrap_or("")
+    }
 }
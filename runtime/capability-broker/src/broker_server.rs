@@ -0,0 +1,1070 @@
+//! Broker Server
+//!
+//! Serves the broker to other components over IPC, so only the root task
+//! links it. Components call a badged copy of the server's endpoint; the
+//! badge identifies the caller, and the server acts on its behalf with the
+//! TCB and CNode capabilities the root task registered for it: device
+//! MMIO and memory are mapped into the caller's address space, and
+//! capabilities copied into its CSpace, at addresses and slots the caller
+//! picks.
+//!
+//! Messages are little-endian and fit the kernel's 256-byte IPC limit. A
+//! request is an opcode and its arguments ([`Request`]); a reply is a tag
+//! and its results, or an error code and value ([`Reply`]).
+//!
+//! Setup, in the root task:
+//!
+//! ```rust,no_run
+//! use capability_broker::broker_server::{BrokerServer, Client};
+//!
+//! let endpoint = broker.create_endpoint()?;
+//! let mut server = BrokerServer::new(endpoint);
+//! // For each component: mint a copy of `endpoint` badged `badge` into
+//! // its CSpace, then
+//! server.register_client(badge, Client { pid, tcb_cap, cnode_cap });
+//! server.run(&mut broker);
+//! ```
+//!
+//! And in a component, with the badged endpoint in slot `broker_slot`:
+//!
+//! ```rust,no_run
+//! use capability_broker::broker_server::BrokerClient;
+//!
+//! let client = BrokerClient::new(Endpoint { cap_slot: broker_slot, id: 0 });
+//! let uart = client.request_device(DeviceId::Uart(0), 0x8000_0000, MemoryType::Device, None)?;
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::hotplug::{self, HotplugSubscription};
+use crate::{
+    copy_cap, BrokerError, CapabilityBroker, DeviceId, DeviceResource, Endpoint, MemoryType, Result,
+    ServiceMetadata,
+};
+
+/// Largest message, request or reply (the kernel's IPC limit)
+pub const MAX_MESSAGE: usize = 256;
+
+/// Longest device tree node name in a request
+const MAX_NAME: usize = 64;
+
+/// Request opcodes
+const OP_REQUEST_DEVICE: u32 = 1;
+const OP_RELEASE_DEVICE: u32 = 2;
+const OP_ALLOCATE_MEMORY: u32 = 3;
+const OP_CREATE_CHANNEL: u32 = 4;
+const OP_SUBSCRIBE_HOTPLUG: u32 = 5;
+const OP_UNSUBSCRIBE_HOTPLUG: u32 = 6;
+const OP_REGISTER_SERVICE: u32 = 7;
+const OP_LOOKUP_SERVICE: u32 = 8;
+
+/// Reply tags
+const REPLY_ERROR: u32 = 0;
+const REPLY_DEVICE: u32 = 1;
+const REPLY_RELEASED: u32 = 2;
+const REPLY_MEMORY: u32 = 3;
+const REPLY_CHANNEL: u32 = 4;
+const REPLY_HOTPLUG: u32 = 5;
+const REPLY_UNSUBSCRIBED: u32 = 6;
+const REPLY_SERVICE: u32 = 7;
+
+/// Device kinds on the wire
+const DEVICE_UART: u32 = 0;
+const DEVICE_TIMER: u32 = 1;
+const DEVICE_RTC: u32 = 2;
+const DEVICE_CUSTOM: u32 = 3;
+const DEVICE_PLATFORM: u32 = 4;
+const DEVICE_PCI: u32 = 5;
+
+/// "No such value" on the wire: no mapping, no slot, no IRQ
+const NONE: u64 = u64::MAX;
+
+/// Badge the server signals hot-plug notifications with
+const HOTPLUG_BADGE: u64 = 1;
+
+/// A device as named in a request
+///
+/// Like [`DeviceId`], but a device tree node name borrows the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireDevice<'a> {
+    /// UART device (port number)
+    Uart(u32),
+    /// Timer device
+    Timer,
+    /// RTC device
+    Rtc,
+    /// Custom device (device_type from boot info)
+    Custom(u32),
+    /// Device tree node, by name
+    Platform(&'a str),
+    /// PCI function, by vendor and device ID
+    Pci {
+        /// Vendor ID
+        vendor: u16,
+        /// Device ID
+        device: u16,
+    },
+}
+
+impl From<DeviceId> for WireDevice<'static> {
+    fn from(id: DeviceId) -> Self {
+        match id {
+            DeviceId::Uart(port) => Self::Uart(port as u32),
+            DeviceId::Timer => Self::Timer,
+            DeviceId::Rtc => Self::Rtc,
+            DeviceId::Custom(device_type) => Self::Custom(device_type),
+            DeviceId::Platform { name } => Self::Platform(name),
+            DeviceId::Pci { vendor, device } => Self::Pci { vendor, device },
+        }
+    }
+}
+
+/// A request to the broker server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request<'a> {
+    /// Claim a device for the caller
+    RequestDevice {
+        /// Device to claim
+        device: WireDevice<'a>,
+        /// Where to map the MMIO region in the caller (0: don't map)
+        map_at: u64,
+        /// How to map it
+        memory_type: MemoryType,
+        /// Caller's slot for the IRQ capability (`u64::MAX`: none wanted)
+        irq_slot: u64,
+    },
+    /// Release a device claimed with `RequestDevice`
+    ReleaseDevice {
+        /// Handle from the `Device` reply
+        handle: u64,
+    },
+    /// Allocate physically contiguous memory
+    AllocateMemory {
+        /// Size in bytes
+        size: u64,
+        /// Where to map it in the caller, read/write (0: don't map)
+        map_at: u64,
+    },
+    /// Create an IPC endpoint owned by the caller
+    CreateChannel {
+        /// Caller's slot for the endpoint capability
+        slot: u64,
+    },
+    /// Subscribe to hot-plug events
+    SubscribeHotplug {
+        /// Caller's slot for the notification signaled on each event
+        notification_slot: u64,
+        /// Where to map the event ring in the caller (0: don't map)
+        map_at: u64,
+    },
+    /// Cancel a hot-plug subscription
+    UnsubscribeHotplug {
+        /// Subscription ID from the `Hotplug` reply
+        id: u64,
+    },
+    /// Create an endpoint owned by the caller and register it as a service
+    RegisterService {
+        /// Service name
+        name: &'a str,
+        /// Caller's slot for the endpoint capability
+        slot: u64,
+    },
+    /// Connect to a registered service
+    LookupService {
+        /// Service name
+        name: &'a str,
+        /// Caller's slot for its badged copy of the service endpoint
+        slot: u64,
+    },
+}
+
+/// A claimed device, as granted to a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceGrant {
+    /// Handle for `ReleaseDevice`
+    pub handle: u64,
+    /// MMIO base address (physical)
+    pub mmio_base: usize,
+    /// MMIO size in bytes
+    pub mmio_size: usize,
+    /// Where the MMIO region is mapped in the client, if it asked
+    pub mmio_vaddr: Option<usize>,
+    /// IRQ number, if the device has one
+    pub irq: Option<u32>,
+    /// Client slot holding the IRQ capability, if one was transferred
+    pub irq_cap: Option<usize>,
+}
+
+/// Memory allocated for a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryGrant {
+    /// Physical address
+    pub phys_addr: usize,
+    /// Size in bytes
+    pub size: usize,
+    /// Where it is mapped in the client, if it asked
+    pub vaddr: Option<usize>,
+}
+
+/// A hot-plug subscription, as granted to a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotplugGrant {
+    /// Subscription ID, for `UnsubscribeHotplug`
+    pub id: u64,
+    /// Physical address of the event ring (`hotplug::RING_SIZE` bytes)
+    pub phys_addr: usize,
+    /// Where the ring is mapped in the client, if it asked
+    pub vaddr: Option<usize>,
+}
+
+/// A reply from the broker server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// The request failed
+    Error(BrokerError),
+    /// Device claimed
+    Device(DeviceGrant),
+    /// Device released
+    Released,
+    /// Memory allocated
+    Memory(MemoryGrant),
+    /// Endpoint created, in the client's slot
+    Channel {
+        /// Client slot holding the endpoint capability
+        slot: usize,
+    },
+    /// Subscribed to hot-plug events
+    Hotplug(HotplugGrant),
+    /// Hot-plug subscription cancelled
+    Unsubscribed,
+    /// Connected to a service
+    Service {
+        /// Client slot holding the badged endpoint capability
+        slot: usize,
+        /// Badge the service sees on the client's messages
+        badge: u64,
+    },
+}
+
+/// Little-endian message writer
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// A length-prefixed name, or `None` if it is over 64 bytes
+    fn name(&mut self, name: &str) -> Option<&mut Self> {
+        (name.len() <= MAX_NAME).then(|| self.u32(name.len() as u32).bytes(name.as_bytes()))
+    }
+}
+
+/// Little-endian message reader
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn name(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        if len > MAX_NAME {
+            return None;
+        }
+        core::str::from_utf8(self.bytes(len)?).ok()
+    }
+}
+
+/// Encode an optional value, `NONE` for `None`
+fn optional(value: Option<u64>) -> u64 {
+    value.unwrap_or(NONE)
+}
+
+/// Decode an optional value
+fn present(value: u64) -> Option<u64> {
+    (value != NONE).then_some(value)
+}
+
+impl<'a> Request<'a> {
+    /// Encode into `buf`, returning the message length
+    ///
+    /// Fails with `InvalidCapability` for a device name over 64 bytes.
+    pub fn encode(&self, buf: &mut [u8; MAX_MESSAGE]) -> Result<usize> {
+        let mut w = Writer { buf, len: 0 };
+        match *self {
+            Request::RequestDevice { device, map_at, memory_type, irq_slot } => {
+                w.u32(OP_REQUEST_DEVICE);
+                match device {
+                    WireDevice::Uart(port) => w.u32(DEVICE_UART).u32(port),
+                    WireDevice::Timer => w.u32(DEVICE_TIMER),
+                    WireDevice::Rtc => w.u32(DEVICE_RTC),
+                    WireDevice::Custom(device_type) => w.u32(DEVICE_CUSTOM).u32(device_type),
+                    WireDevice::Platform(name) => {
+                        w.u32(DEVICE_PLATFORM).name(name).ok_or(BrokerError::InvalidCapability)?
+                    }
+                    WireDevice::Pci { vendor, device } => {
+                        w.u32(DEVICE_PCI).u32(vendor as u32).u32(device as u32)
+                    }
+                };
+                w.u64(map_at).u32(memory_type as u32).u64(irq_slot);
+            }
+            Request::ReleaseDevice { handle } => {
+                w.u32(OP_RELEASE_DEVICE).u64(handle);
+            }
+            Request::AllocateMemory { size, map_at } => {
+                w.u32(OP_ALLOCATE_MEMORY).u64(size).u64(map_at);
+            }
+            Request::CreateChannel { slot } => {
+                w.u32(OP_CREATE_CHANNEL).u64(slot);
+            }
+            Request::SubscribeHotplug { notification_slot, map_at } => {
+                w.u32(OP_SUBSCRIBE_HOTPLUG).u64(notification_slot).u64(map_at);
+            }
+            Request::UnsubscribeHotplug { id } => {
+                w.u32(OP_UNSUBSCRIBE_HOTPLUG).u64(id);
+            }
+            Request::RegisterService { name, slot } => {
+                w.u32(OP_REGISTER_SERVICE).name(name).ok_or(BrokerError::InvalidCapability)?.u64(slot);
+            }
+            Request::LookupService { name, slot } => {
+                w.u32(OP_LOOKUP_SERVICE).name(name).ok_or(BrokerError::InvalidCapability)?.u64(slot);
+            }
+        }
+        Ok(w.len)
+    }
+
+    /// Decode a request, or `None` if it is malformed
+    pub fn decode(message: &'a [u8]) -> Option<Self> {
+        let mut r = Reader { buf: message, pos: 0 };
+        let request = match r.u32()? {
+            OP_REQUEST_DEVICE => {
+                let device = match r.u32()? {
+                    DEVICE_UART => WireDevice::Uart(r.u32()?),
+                    DEVICE_TIMER => WireDevice::Timer,
+                    DEVICE_RTC => WireDevice::Rtc,
+                    DEVICE_CUSTOM => WireDevice::Custom(r.u32()?),
+                    DEVICE_PLATFORM => WireDevice::Platform(r.name()?),
+                    DEVICE_PCI => WireDevice::Pci {
+                        vendor: r.u32()? as u16,
+                        device: r.u32()? as u16,
+                    },
+                    _ => return None,
+                };
+                Request::RequestDevice {
+                    device,
+                    map_at: r.u64()?,
+                    memory_type: memory_type(r.u32()?)?,
+                    irq_slot: r.u64()?,
+                }
+            }
+            OP_RELEASE_DEVICE => Request::ReleaseDevice { handle: r.u64()? },
+            OP_ALLOCATE_MEMORY => Request::AllocateMemory { size: r.u64()?, map_at: r.u64()? },
+            OP_CREATE_CHANNEL => Request::CreateChannel { slot: r.u64()? },
+            OP_SUBSCRIBE_HOTPLUG => Request::SubscribeHotplug {
+                notification_slot: r.u64()?,
+                map_at: r.u64()?,
+            },
+            OP_UNSUBSCRIBE_HOTPLUG => Request::UnsubscribeHotplug { id: r.u64()? },
+            OP_REGISTER_SERVICE => Request::RegisterService { name: r.name()?, slot: r.u64()? },
+            OP_LOOKUP_SERVICE => Request::LookupService { name: r.name()?, slot: r.u64()? },
+            _ => return None,
+        };
+        Some(request)
+    }
+}
+
+/// Memory type for its wire value
+fn memory_type(value: u32) -> Option<MemoryType> {
+    match value {
+        0 => Some(MemoryType::Normal),
+        1 => Some(MemoryType::Device),
+        2 => Some(MemoryType::NonCacheable),
+        3 => Some(MemoryType::WriteCombining),
+        _ => None,
+    }
+}
+
+/// Error code and value of an error on the wire
+fn error_code(error: BrokerError) -> (u32, u64) {
+    match error {
+        BrokerError::OutOfCapabilitySlots => (1, 0),
+        BrokerError::DeviceNotFound => (2, 0),
+        BrokerError::OutOfMemory => (3, 0),
+        BrokerError::InvalidCapability => (4, 0),
+        BrokerError::SyscallFailed(value) => (5, value as u64),
+        BrokerError::ResourceInUse => (6, 0),
+        BrokerError::InvalidDeviceTree => (7, 0),
+        BrokerError::DeviceBusy => (8, 0),
+    }
+}
+
+/// The error for a wire error code (unknown codes read as
+/// `InvalidCapability`)
+fn error_from_code(code: u32, value: u64) -> BrokerError {
+    match code {
+        1 => BrokerError::OutOfCapabilitySlots,
+        2 => BrokerError::DeviceNotFound,
+        3 => BrokerError::OutOfMemory,
+        5 => BrokerError::SyscallFailed(value as usize),
+        6 => BrokerError::ResourceInUse,
+        7 => BrokerError::InvalidDeviceTree,
+        8 => BrokerError::DeviceBusy,
+        _ => BrokerError::InvalidCapability,
+    }
+}
+
+impl Reply {
+    /// Encode into `buf`, returning the message length
+    pub fn encode(&self, buf: &mut [u8; MAX_MESSAGE]) -> usize {
+        let mut w = Writer { buf, len: 0 };
+        match *self {
+            Reply::Error(error) => {
+                let (code, value) = error_code(error);
+                w.u32(REPLY_ERROR).u32(code).u64(value);
+            }
+            Reply::Device(grant) => {
+                w.u32(REPLY_DEVICE)
+                    .u64(grant.handle)
+                    .u64(grant.mmio_base as u64)
+                    .u64(grant.mmio_size as u64)
+                    .u64(optional(grant.mmio_vaddr.map(|v| v as u64)))
+                    .u64(optional(grant.irq.map(u64::from)))
+                    .u64(optional(grant.irq_cap.map(|s| s as u64)));
+            }
+            Reply::Released => {
+                w.u32(REPLY_RELEASED);
+            }
+            Reply::Memory(grant) => {
+                w.u32(REPLY_MEMORY)
+                    .u64(grant.phys_addr as u64)
+                    .u64(grant.size as u64)
+                    .u64(optional(grant.vaddr.map(|v| v as u64)));
+            }
+            Reply::Channel { slot } => {
+                w.u32(REPLY_CHANNEL).u64(slot as u64);
+            }
+            Reply::Hotplug(grant) => {
+                w.u32(REPLY_HOTPLUG)
+                    .u64(grant.id)
+                    .u64(grant.phys_addr as u64)
+                    .u64(optional(grant.vaddr.map(|v| v as u64)));
+            }
+            Reply::Unsubscribed => {
+                w.u32(REPLY_UNSUBSCRIBED);
+            }
+            Reply::Service { slot, badge } => {
+                w.u32(REPLY_SERVICE).u64(slot as u64).u64(badge);
+            }
+        }
+        w.len
+    }
+
+    /// Decode a reply, or `None` if it is malformed
+    pub fn decode(message: &[u8]) -> Option<Self> {
+        let mut r = Reader { buf: message, pos: 0 };
+        let reply = match r.u32()? {
+            REPLY_ERROR => Reply::Error(error_from_code(r.u32()?, r.u64()?)),
+            REPLY_DEVICE => Reply::Device(DeviceGrant {
+                handle: r.u64()?,
+                mmio_base: r.u64()? as usize,
+                mmio_size: r.u64()? as usize,
+                mmio_vaddr: present(r.u64()?).map(|v| v as usize),
+                irq: present(r.u64()?).map(|irq| irq as u32),
+                irq_cap: present(r.u64()?).map(|s| s as usize),
+            }),
+            REPLY_RELEASED => Reply::Released,
+            REPLY_MEMORY => Reply::Memory(MemoryGrant {
+                phys_addr: r.u64()? as usize,
+                size: r.u64()? as usize,
+                vaddr: present(r.u64()?).map(|v| v as usize),
+            }),
+            REPLY_CHANNEL => Reply::Channel { slot: r.u64()? as usize },
+            REPLY_HOTPLUG => Reply::Hotplug(HotplugGrant {
+                id: r.u64()?,
+                phys_addr: r.u64()? as usize,
+                vaddr: present(r.u64()?).map(|v| v as usize),
+            }),
+            REPLY_UNSUBSCRIBED => Reply::Unsubscribed,
+            REPLY_SERVICE => Reply::Service { slot: r.u64()? as usize, badge: r.u64()? },
+            _ => return None,
+        };
+        Some(reply)
+    }
+}
+
+/// What the server needs to act for a component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Client {
+    /// Process ID, recorded as the owner of its devices
+    pub pid: usize,
+    /// Slot of the component's TCB capability (for mapping into it)
+    pub tcb_cap: usize,
+    /// Slot of the component's CSpace root CNode capability (for
+    /// transferring capabilities into it)
+    pub cnode_cap: usize,
+}
+
+/// A device claimed by a client
+struct Lease {
+    badge: u64,
+    handle: u64,
+    device: DeviceResource,
+}
+
+/// A client's hot-plug subscription
+struct Watch {
+    badge: u64,
+    subscription: HotplugSubscription,
+    /// Broker slot of the notification the client was given a copy of
+    notification: usize,
+}
+
+/// The broker's IPC server
+pub struct BrokerServer {
+    endpoint: Endpoint,
+    /// Registered clients, by badge
+    clients: Vec<(u64, Client)>,
+    /// Devices claimed through the server
+    leases: Vec<Lease>,
+    /// Hot-plug subscriptions made through the server
+    watches: Vec<Watch>,
+    next_handle: u64,
+}
+
+impl BrokerServer {
+    /// Create a server receiving on `endpoint`
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            clients: Vec::new(),
+            leases: Vec::new(),
+            watches: Vec::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Serve calls badged `badge` (non-zero) on behalf of `client`
+    pub fn register_client(&mut self, badge: u64, client: Client) {
+        self.clients.retain(|&(b, _)| b != badge);
+        self.clients.push((badge, client));
+    }
+
+    /// Stop serving `badge`, releasing the devices and hot-plug
+    /// subscriptions it still holds
+    pub fn unregister_client(&mut self, broker: &mut CapabilityBroker, badge: u64) {
+        self.clients.retain(|&(b, _)| b != badge);
+        let (gone, kept) = core::mem::take(&mut self.leases).into_iter().partition(|l| l.badge == badge);
+        self.leases = kept;
+        for lease in gone {
+            // A device claimed away since is already released
+            let _ = broker.release_device(lease.device);
+        }
+        let (gone, kept) = core::mem::take(&mut self.watches).into_iter().partition(|w| w.badge == badge);
+        self.watches = kept;
+        for watch in gone {
+            drop_watch(broker, watch);
+        }
+    }
+
+    /// A component has died: stop serving its badges and reclaim what the
+    /// broker handed it (see [`CapabilityBroker::reclaim_component`])
+    pub fn component_exited(&mut self, broker: &mut CapabilityBroker, pid: usize) -> Result<usize> {
+        let badges: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.pid == pid)
+            .map(|&(badge, _)| badge)
+            .collect();
+        for badge in badges {
+            self.unregister_client(broker, badge);
+        }
+        broker.reclaim_component(pid)
+    }
+
+    /// The client badged `badge`
+    fn client(&self, badge: u64) -> Option<Client> {
+        self.clients.iter().find(|&&(b, _)| b == badge).map(|&(_, client)| client)
+    }
+
+    /// Serve requests forever
+    pub fn run(&mut self, broker: &mut CapabilityBroker) -> ! {
+        let mut request = [0u8; MAX_MESSAGE];
+        let mut reply = [0u8; MAX_MESSAGE];
+        let mut owed = None;
+
+        loop {
+            let received = match owed.take() {
+                Some(len) => reply_recv(self.endpoint.cap_slot, &mut request, &reply[..len]),
+                None => recv(self.endpoint.cap_slot, &mut request),
+            };
+            let Some((len, badge)) = received else {
+                continue;
+            };
+            let response = self.handle(broker, badge, &request[..len.min(MAX_MESSAGE)]);
+            owed = Some(response.encode(&mut reply));
+        }
+    }
+
+    /// Serve one request from the client badged `badge`
+    pub fn handle(&mut self, broker: &mut CapabilityBroker, badge: u64, message: &[u8]) -> Reply {
+        let Some(client) = self.client(badge) else {
+            return Reply::Error(BrokerError::InvalidCapability);
+        };
+        let Some(request) = Request::decode(message) else {
+            return Reply::Error(BrokerError::InvalidCapability);
+        };
+
+        let result = match request {
+            Request::RequestDevice { device, map_at, memory_type, irq_slot } => {
+                self.request_device(broker, badge, device, map_at, memory_type, irq_slot)
+            }
+            Request::ReleaseDevice { handle } => self.release_device(broker, badge, handle),
+            Request::AllocateMemory { size, map_at } => allocate_memory(broker, client, size, map_at),
+            Request::CreateChannel { slot } => create_channel(broker, client, slot),
+            Request::SubscribeHotplug { notification_slot, map_at } => {
+                self.subscribe_hotplug(broker, badge, notification_slot, map_at)
+            }
+            Request::UnsubscribeHotplug { id } => self.unsubscribe_hotplug(broker, badge, id),
+            Request::RegisterService { name, slot } => register_service(broker, client, name, slot),
+            Request::LookupService { name, slot } => lookup_service(broker, client, name, slot),
+        };
+        result.unwrap_or_else(Reply::Error)
+    }
+
+    fn request_device(
+        &mut self,
+        broker: &mut CapabilityBroker,
+        badge: u64,
+        device: WireDevice,
+        map_at: u64,
+        memory_type: MemoryType,
+        irq_slot: u64,
+    ) -> Result<Reply> {
+        let client = self.client(badge).ok_or(BrokerError::InvalidCapability)?;
+        let device_id = match device {
+            WireDevice::Uart(port) => DeviceId::Uart(port as usize),
+            WireDevice::Timer => DeviceId::Timer,
+            WireDevice::Rtc => DeviceId::Rtc,
+            WireDevice::Custom(device_type) => DeviceId::Custom(device_type),
+            // The registry's name lives as long as the broker
+            WireDevice::Platform(name) => DeviceId::Platform {
+                name: broker
+                    .device_tree()
+                    .and_then(|tree| tree.find(name))
+                    .ok_or(BrokerError::DeviceNotFound)?
+                    .name,
+            },
+            WireDevice::Pci { vendor, device } => DeviceId::Pci { vendor, device },
+        };
+        let device = broker.request_device(device_id, client.pid)?;
+
+        let mut mmio_vaddr = None;
+        if map_at != 0 && device.mmio_size > 0 {
+            let offset = device.mmio_base % 4096;
+            let size = (offset + device.mmio_size).next_multiple_of(4096);
+            let (phys, vaddr) = (device.mmio_base - offset, map_at as usize);
+            if let Err(e) = map_into(client.tcb_cap, phys, size, vaddr, memory_type) {
+                let _ = broker.release_device(device);
+                return Err(e);
+            }
+            mmio_vaddr = Some(map_at as usize + offset);
+        }
+
+        // The broker's IRQ slot is only filled once a handler is bound
+        let irq_cap = match (present(irq_slot), device.irq_cap) {
+            (Some(dest), Some(src)) => copy_cap(src, client.cnode_cap, dest as usize).ok().map(|()| dest as usize),
+            _ => None,
+        };
+
+        let grant = DeviceGrant {
+            handle: self.next_handle,
+            mmio_base: device.mmio_base,
+            mmio_size: device.mmio_size,
+            mmio_vaddr,
+            irq: device.irq,
+            irq_cap,
+        };
+        self.next_handle += 1;
+        self.leases.push(Lease { badge, handle: grant.handle, device });
+        Ok(Reply::Device(grant))
+    }
+
+    fn release_device(&mut self, broker: &mut CapabilityBroker, badge: u64, handle: u64) -> Result<Reply> {
+        let index = self
+            .leases
+            .iter()
+            .position(|l| l.badge == badge && l.handle == handle)
+            .ok_or(BrokerError::InvalidCapability)?;
+        let lease = self.leases.swap_remove(index);
+        broker.release_device(lease.device)?;
+        Ok(Reply::Released)
+    }
+
+    fn subscribe_hotplug(
+        &mut self,
+        broker: &mut CapabilityBroker,
+        badge: u64,
+        notification_slot: u64,
+        map_at: u64,
+    ) -> Result<Reply> {
+        let client = self.client(badge).ok_or(BrokerError::InvalidCapability)?;
+        let notification = broker.create_notification()?;
+        let subscription = match broker.subscribe_hotplug(notification, HOTPLUG_BADGE, client.pid) {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                crate::delete_cap(notification);
+                return Err(e);
+            }
+        };
+        let watch = Watch { badge, subscription, notification };
+
+        let copied = copy_cap(notification, client.cnode_cap, notification_slot as usize);
+        let mapped = copied.and_then(|()| match map_at {
+            0 => Ok(None),
+            vaddr => {
+                let phys = watch.subscription.phys_addr;
+                map_into(client.tcb_cap, phys, hotplug::RING_SIZE, vaddr as usize, MemoryType::Normal)
+                    .map(|()| Some(vaddr as usize))
+            }
+        });
+        let vaddr = match mapped {
+            Ok(vaddr) => vaddr,
+            Err(e) => {
+                drop_watch(broker, watch);
+                return Err(e);
+            }
+        };
+
+        let grant = HotplugGrant {
+            id: watch.subscription.id,
+            phys_addr: watch.subscription.phys_addr,
+            vaddr,
+        };
+        self.watches.push(watch);
+        Ok(Reply::Hotplug(grant))
+    }
+
+    fn unsubscribe_hotplug(&mut self, broker: &mut CapabilityBroker, badge: u64, id: u64) -> Result<Reply> {
+        let index = self
+            .watches
+            .iter()
+            .position(|w| w.badge == badge && w.subscription.id == id)
+            .ok_or(BrokerError::InvalidCapability)?;
+        drop_watch(broker, self.watches.swap_remove(index));
+        Ok(Reply::Unsubscribed)
+    }
+}
+
+/// Cancel a client's hot-plug subscription and delete its notification
+///
+/// The client's copy of the notification stays in its CSpace, never
+/// signaled again.
+fn drop_watch(broker: &mut CapabilityBroker, watch: Watch) {
+    // Already gone if the client's component was reclaimed
+    let _ = broker.unsubscribe_hotplug(watch.subscription);
+    crate::delete_cap(watch.notification);
+}
+
+fn allocate_memory(broker: &mut CapabilityBroker, client: Client, size: u64, map_at: u64) -> Result<Reply> {
+    let size = (size as usize).next_multiple_of(4096);
+    let memory = broker.allocate_memory(size)?;
+    let vaddr = if map_at != 0 {
+        map_into(client.tcb_cap, memory.phys_addr, size, map_at as usize, MemoryType::Normal)?;
+        Some(map_at as usize)
+    } else {
+        None
+    };
+    Ok(Reply::Memory(MemoryGrant {
+        phys_addr: memory.phys_addr,
+        size,
+        vaddr,
+    }))
+}
+
+fn create_channel(broker: &mut CapabilityBroker, client: Client, slot: u64) -> Result<Reply> {
+    // The broker keeps the original, so it can revoke the client's copy
+    let endpoint = broker.create_endpoint()?;
+    copy_cap(endpoint.cap_slot, client.cnode_cap, slot as usize)?;
+    Ok(Reply::Channel { slot: slot as usize })
+}
+
+fn register_service(broker: &mut CapabilityBroker, client: Client, name: &str, slot: u64) -> Result<Reply> {
+    // The broker keeps the original, to mint clients' copies from
+    let endpoint = broker.create_endpoint()?;
+    copy_cap(endpoint.cap_slot, client.cnode_cap, slot as usize)?;
+    broker.register_service(name, endpoint, client.pid, ServiceMetadata::default())?;
+    Ok(Reply::Channel { slot: slot as usize })
+}
+
+fn lookup_service(broker: &mut CapabilityBroker, client: Client, name: &str, slot: u64) -> Result<Reply> {
+    let connection = broker.lookup_service(name, client.cnode_cap, slot as usize)?;
+    Ok(Reply::Service {
+        slot: connection.endpoint.cap_slot,
+        badge: connection.badge,
+    })
+}
+
+/// Client side of the broker server
+pub struct BrokerClient {
+    endpoint: Endpoint,
+}
+
+impl BrokerClient {
+    /// Talk to the broker through `endpoint` (the caller's badged copy)
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self { endpoint }
+    }
+
+    fn call(&self, request: Request) -> Result<Reply> {
+        let mut message = [0u8; MAX_MESSAGE];
+        let len = request.encode(&mut message)?;
+        let mut reply = [0u8; MAX_MESSAGE];
+        let reply_len = self.endpoint.call(&message[..len], &mut reply)?;
+        match Reply::decode(&reply[..reply_len.min(MAX_MESSAGE)]) {
+            Some(Reply::Error(error)) => Err(error),
+            Some(reply) => Ok(reply),
+            None => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Claim a device, mapping its MMIO region at `map_at` (0: don't) as
+    /// `memory_type` and putting its IRQ capability, if there is one, in
+    /// `irq_slot`
+    pub fn request_device(
+        &self,
+        device: DeviceId,
+        map_at: usize,
+        memory_type: MemoryType,
+        irq_slot: Option<usize>,
+    ) -> Result<DeviceGrant> {
+        let request = Request::RequestDevice {
+            device: device.into(),
+            map_at: map_at as u64,
+            memory_type,
+            irq_slot: optional(irq_slot.map(|s| s as u64)),
+        };
+        match self.call(request)? {
+            Reply::Device(grant) => Ok(grant),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Release a device claimed with `request_device`
+    pub fn release_device(&self, grant: DeviceGrant) -> Result<()> {
+        match self.call(Request::ReleaseDevice { handle: grant.handle })? {
+            Reply::Released => Ok(()),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Allocate physically contiguous memory, mapped read/write at
+    /// `map_at` (0: don't map)
+    pub fn allocate_memory(&self, size: usize, map_at: usize) -> Result<MemoryGrant> {
+        let request = Request::AllocateMemory { size: size as u64, map_at: map_at as u64 };
+        match self.call(request)? {
+            Reply::Memory(grant) => Ok(grant),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Create an IPC endpoint, its capability in `slot`
+    pub fn create_channel(&self, slot: usize) -> Result<Endpoint> {
+        match self.call(Request::CreateChannel { slot: slot as u64 })? {
+            Reply::Channel { slot } => Ok(Endpoint { cap_slot: slot, id: 0 }),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Subscribe to hot-plug events, with the notification signaled on
+    /// each one in `notification_slot` and the event ring mapped at
+    /// `map_at` (0: don't map; read it with `HotplugReader`)
+    pub fn subscribe_hotplug(&self, notification_slot: usize, map_at: usize) -> Result<HotplugGrant> {
+        let request = Request::SubscribeHotplug {
+            notification_slot: notification_slot as u64,
+            map_at: map_at as u64,
+        };
+        match self.call(request)? {
+            Reply::Hotplug(grant) => Ok(grant),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Provide a service: create an endpoint in `slot` to receive on and
+    /// register it as `name`
+    pub fn register_service(&self, name: &str, slot: usize) -> Result<Endpoint> {
+        match self.call(Request::RegisterService { name, slot: slot as u64 })? {
+            Reply::Channel { slot } => Ok(Endpoint { cap_slot: slot, id: 0 }),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Connect to the service registered as `name`, with the endpoint to
+    /// call it through in `slot`
+    pub fn lookup_service(&self, name: &str, slot: usize) -> Result<Endpoint> {
+        match self.call(Request::LookupService { name, slot: slot as u64 })? {
+            Reply::Service { slot, .. } => Ok(Endpoint { cap_slot: slot, id: 0 }),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+
+    /// Cancel a subscription made with `subscribe_hotplug`
+    pub fn unsubscribe_hotplug(&self, grant: HotplugGrant) -> Result<()> {
+        match self.call(Request::UnsubscribeHotplug { id: grant.id })? {
+            Reply::Unsubscribed => Ok(()),
+            _ => Err(BrokerError::InvalidCapability),
+        }
+    }
+}
+
+/// Receive on `endpoint`: (length, badge)
+fn recv(endpoint: usize, buffer: &mut [u8]) -> Option<(usize, u64)> {
+    let (len, badge): (usize, u64);
+    unsafe {
+        core::arch::asm!(
+            "svc #0",
+            in("x8") 0x03u64, // SYS_RECV
+            inlateout("x0") endpoint => len,
+            inlateout("x1") buffer.as_mut_ptr() => badge,
+            inlateout("x2") buffer.len() => _,
+        );
+    }
+    (len != usize::MAX).then_some((len, badge))
+}
+
+/// Reply to the last caller and receive on `endpoint`: (length, badge)
+fn reply_recv(endpoint: usize, buffer: &mut [u8], reply: &[u8]) -> Option<(usize, u64)> {
+    let (len, badge): (usize, u64);
+    unsafe {
+        core::arch::asm!(
+            "svc #0",
+            in("x8") 0x06u64, // SYS_REPLY_RECV
+            inlateout("x0") endpoint => len,
+            inlateout("x1") buffer.as_mut_ptr() => badge,
+            inlateout("x2") buffer.len() => _,
+            inlateout("x3") reply.as_ptr() => _,
+            inlateout("x4") reply.len() => _,
+        );
+    }
+    (len != usize::MAX).then_some((len, badge))
+}
+
+/// Map physical memory read/write into the process of `tcb_cap`
+fn map_into(tcb_cap: usize, phys: usize, size: usize, vaddr: usize, memory_type: MemoryType) -> Result<()> {
+    let result: usize;
+    unsafe {
+        core::arch::asm!(
+            "svc #0",
+            in("x8") 0x1Bu64, // SYS_MEMORY_MAP_INTO
+            inlateout("x0") tcb_cap => result,
+            inlateout("x1") phys => _,
+            inlateout("x2") size => _,
+            inlateout("x3") vaddr => _,
+            inlateout("x4") 0x3 | memory_type.map_bits() => _, // read | write
+        );
+    }
+    if result == usize::MAX {
+        Err(BrokerError::SyscallFailed(result))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(request: Request) {
+        let mut buf = [0u8; MAX_MESSAGE];
+        let len = request.encode(&mut buf).unwrap();
+        assert_eq!(Request::decode(&buf[..len]), Some(request));
+        // Truncated messages are rejected, not misread
+        assert_eq!(Request::decode(&buf[..len - 1]), None);
+    }
+
+    #[test]
+    fn test_requests() {
+        round_trip(Request::RequestDevice {
+            device: WireDevice::Platform("pl031@9010000"),
+            map_at: 0x8000_0000,
+            memory_type: MemoryType::Device,
+            irq_slot: NONE,
+        });
+        round_trip(Request::RequestDevice {
+            device: WireDevice::from(DeviceId::Pci { vendor: 0x1af4, device: 0x1041 }),
+            map_at: 0,
+            memory_type: MemoryType::WriteCombining,
+            irq_slot: 12,
+        });
+        round_trip(Request::ReleaseDevice { handle: 3 });
+        round_trip(Request::AllocateMemory { size: 8192, map_at: 0x9000_0000 });
+        round_trip(Request::CreateChannel { slot: 40 });
+        round_trip(Request::SubscribeHotplug { notification_slot: 41, map_at: 0x9100_0000 });
+        round_trip(Request::UnsubscribeHotplug { id: 2 });
+        round_trip(Request::RegisterService { name: "kaal.uart.output", slot: 42 });
+        round_trip(Request::LookupService { name: "kaal.uart.output", slot: 43 });
+
+        let long = Request::RequestDevice {
+            device: WireDevice::Platform(core::str::from_utf8(&[b'a'; 65]).unwrap()),
+            map_at: 0,
+            memory_type: MemoryType::Device,
+            irq_slot: NONE,
+        };
+        assert_eq!(long.encode(&mut [0; MAX_MESSAGE]), Err(BrokerError::InvalidCapability));
+        assert_eq!(Request::decode(&[9, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_replies() {
+        let replies = [
+            Reply::Error(BrokerError::DeviceBusy),
+            Reply::Error(BrokerError::SyscallFailed(7)),
+            Reply::Device(DeviceGrant {
+                handle: 1,
+                mmio_base: 0x900_0000,
+                mmio_size: 0x1000,
+                mmio_vaddr: Some(0x8000_0000),
+                irq: Some(33),
+                irq_cap: None,
+            }),
+            Reply::Released,
+            Reply::Memory(MemoryGrant { phys_addr: 0x4100_0000, size: 0x2000, vaddr: None }),
+            Reply::Channel { slot: 40 },
+            Reply::Hotplug(HotplugGrant { id: 2, phys_addr: 0x4200_0000, vaddr: Some(0x9100_0000) }),
+            Reply::Unsubscribed,
+            Reply::Service { slot: 43, badge: 2 },
+        ];
+        for reply in replies {
+            let mut buf = [0u8; MAX_MESSAGE];
+            let len = reply.encode(&mut buf);
+            assert_eq!(Reply::decode(&buf[..len]), Some(reply));
+        }
+    }
+}
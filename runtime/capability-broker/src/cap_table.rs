@@ -0,0 +1,119 @@
+//! Capability Table
+//!
+//! The broker's record of what each slot of its own CSpace holds. Records
+//! are kept in chunks of 64 slots, indexed by slot number: a chunk is
+//! allocated when the first slot in its range is recorded and dropped when
+//! the last one is removed. The table grows with the CSpace instead of
+//! stopping at a fixed number of records, and a freed slot's record goes
+//! with it, so long-running systems keep accurate counts.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::CapabilityType;
+
+/// Slots per chunk
+const CHUNK_SLOTS: usize = 64;
+
+/// Records of `CHUNK_SLOTS` consecutive slots
+struct Chunk {
+    types: [Option<CapabilityType>; CHUNK_SLOTS],
+    /// Number of recorded slots
+    used: usize,
+}
+
+/// Capability records, by slot
+pub(crate) struct CapTable {
+    /// Chunk `i` covers slots `i * CHUNK_SLOTS..(i + 1) * CHUNK_SLOTS`
+    chunks: Vec<Option<Box<Chunk>>>,
+    /// Number of recorded slots
+    len: usize,
+}
+
+impl CapTable {
+    pub(crate) fn new() -> Self {
+        Self {
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Record `slot` as holding a `cap_type` capability, replacing any
+    /// earlier record
+    pub(crate) fn insert(&mut self, slot: usize, cap_type: CapabilityType) {
+        let (index, offset) = (slot / CHUNK_SLOTS, slot % CHUNK_SLOTS);
+        if index >= self.chunks.len() {
+            self.chunks.resize_with(index + 1, || None);
+        }
+        let chunk = self.chunks[index].get_or_insert_with(|| {
+            Box::new(Chunk {
+                types: [None; CHUNK_SLOTS],
+                used: 0,
+            })
+        });
+        if chunk.types[offset].replace(cap_type).is_none() {
+            chunk.used += 1;
+            self.len += 1;
+        }
+    }
+
+    /// Drop the record of `slot`, returning what it held
+    pub(crate) fn remove(&mut self, slot: usize) -> Option<CapabilityType> {
+        let (index, offset) = (slot / CHUNK_SLOTS, slot % CHUNK_SLOTS);
+        let chunk = self.chunks.get_mut(index)?.as_mut()?;
+        let cap_type = chunk.types[offset].take()?;
+        chunk.used -= 1;
+        self.len -= 1;
+
+        if chunk.used == 0 {
+            self.chunks[index] = None;
+            while self.chunks.last().is_some_and(Option::is_none) {
+                self.chunks.pop();
+            }
+        }
+        Some(cap_type)
+    }
+
+    /// Number of recorded slots
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Recorded capability types, in slot order
+    pub(crate) fn types(&self) -> impl Iterator<Item = CapabilityType> + '_ {
+        self.chunks.iter().flatten().flat_map(|chunk| chunk.types.iter().flatten().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grows_and_shrinks() {
+        let mut table = CapTable::new();
+
+        // Well past the old fixed limit of 256 records
+        for slot in 100..1100 {
+            table.insert(slot, CapabilityType::Memory);
+        }
+        table.insert(4000, CapabilityType::Endpoint);
+        assert_eq!(table.len(), 1001);
+        assert_eq!(table.remove(1100), None);
+
+        // Re-recording a slot replaces its type without counting it twice
+        table.insert(100, CapabilityType::Device);
+        assert_eq!(table.len(), 1001);
+        assert_eq!(table.types().filter(|&t| t == CapabilityType::Device).count(), 1);
+
+        assert_eq!(table.remove(4000), Some(CapabilityType::Endpoint));
+        assert_eq!(table.remove(4000), None);
+        assert_eq!(table.chunks.len(), 1100_usize.div_ceil(CHUNK_SLOTS));
+
+        for slot in 100..1100 {
+            assert!(table.remove(slot).is_some());
+        }
+        assert_eq!(table.len(), 0);
+        assert!(table.chunks.is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! CSpace Slot Allocator
+//!
+//! Hands out the free slots of the broker's own CSpace. Slots are tracked
+//! in a bitmap, one bit per slot, with a second-level bitmap marking the
+//! words that are full so a search skips crowded stretches of the CSpace
+//! 4096 slots at a time. Allocation always takes the lowest free slot,
+//! which keeps the CSpace dense, and a run of consecutive slots can be
+//! taken at once for objects retyped side by side.
+//!
+//! [`CSpaceStats`] reports how fragmented the allocated part has become.
+
+use core::ops::Range;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Bits per bitmap word
+const WORD_BITS: usize = 64;
+
+/// How the broker's CSpace slots are used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CSpaceStats {
+    /// Slots the allocator manages
+    pub capacity: usize,
+    /// Slots in use
+    pub allocated: usize,
+    /// One past the highest slot in use (the first managed slot if none is)
+    pub high_water: usize,
+    /// Free slots below `high_water`: what compacting would give back
+    pub holes: usize,
+    /// Runs of free slots below `high_water`
+    pub hole_runs: usize,
+    /// Longest run of free slots, the largest range that can be allocated
+    pub largest_free_run: usize,
+}
+
+/// Bitmap allocator for the slots `base..base + len`
+pub(crate) struct CSpaceAllocator {
+    base: usize,
+    len: usize,
+    /// Bit set: slot in use. Bits past `len` are set.
+    used: Vec<u64>,
+    /// Bit `i` set: `used[i]` is full. Bits past the last word are set.
+    full: Vec<u64>,
+    allocated: usize,
+}
+
+impl CSpaceAllocator {
+    /// Manage the slots in `slots`
+    pub(crate) fn new(slots: Range<usize>) -> Self {
+        let len = slots.len();
+        let words = len.div_ceil(WORD_BITS);
+        let mut allocator = Self {
+            base: slots.start,
+            len,
+            used: vec![0; words],
+            full: vec![0; words.div_ceil(WORD_BITS)],
+            allocated: 0,
+        };
+
+        // Padding never looks free
+        if !len.is_multiple_of(WORD_BITS) {
+            allocator.used[words - 1] = !0 << (len % WORD_BITS);
+        }
+        if !words.is_multiple_of(WORD_BITS) {
+            *allocator.full.last_mut().unwrap() = !0 << (words % WORD_BITS);
+        }
+        allocator
+    }
+
+    /// Take the lowest free slot
+    pub(crate) fn allocate(&mut self) -> Option<usize> {
+        let index = self.find(0, true)?;
+        self.mark(index..index + 1);
+        Some(self.base + index)
+    }
+
+    /// Take the lowest `count` consecutive free slots
+    pub(crate) fn allocate_range(&mut self, count: usize) -> Option<Range<usize>> {
+        if count == 0 {
+            return None;
+        }
+        let mut from = 0;
+        while let Some(run) = self.free_run(from) {
+            if run.len() >= count {
+                let range = run.start..run.start + count;
+                self.mark(range.clone());
+                return Some(self.base + range.start..self.base + range.end);
+            }
+            from = run.end;
+        }
+        None
+    }
+
+    /// Mark `slot` as in use, for slots filled by someone else (the
+    /// kernel picks the slot of a new endpoint); false if it was already
+    /// in use or is not managed here
+    pub(crate) fn reserve(&mut self, slot: usize) -> bool {
+        match self.index(slot) {
+            Some(index) if !self.is_used(index) => {
+                self.mark(index..index + 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Give `slot` back; false if it was not in use
+    pub(crate) fn free(&mut self, slot: usize) -> bool {
+        let Some(index) = self.index(slot).filter(|&i| self.is_used(i)) else {
+            return false;
+        };
+        let word = index / WORD_BITS;
+        self.used[word] &= !(1 << (index % WORD_BITS));
+        self.full[word / WORD_BITS] &= !(1 << (word % WORD_BITS));
+        self.allocated -= 1;
+        true
+    }
+
+    pub(crate) fn stats(&self) -> CSpaceStats {
+        let mut stats = CSpaceStats {
+            capacity: self.len,
+            allocated: self.allocated,
+            high_water: self.base + self.len,
+            holes: 0,
+            hole_runs: 0,
+            largest_free_run: 0,
+        };
+
+        let mut from = 0;
+        while let Some(run) = self.free_run(from) {
+            stats.largest_free_run = stats.largest_free_run.max(run.len());
+            if run.end < self.len {
+                stats.holes += run.len();
+                stats.hole_runs += 1;
+            } else {
+                // Nothing in use past the last run
+                stats.high_water = self.base + run.start;
+            }
+            from = run.end;
+        }
+        stats
+    }
+
+    /// Index of `slot` in the bitmap, if managed here
+    fn index(&self, slot: usize) -> Option<usize> {
+        slot.checked_sub(self.base).filter(|&i| i < self.len)
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.used[index / WORD_BITS] & (1 << (index % WORD_BITS)) != 0
+    }
+
+    /// Mark the slots at `indices` as in use
+    fn mark(&mut self, indices: Range<usize>) {
+        for index in indices {
+            let word = index / WORD_BITS;
+            self.used[word] |= 1 << (index % WORD_BITS);
+            if self.used[word] == !0 {
+                self.full[word / WORD_BITS] |= 1 << (word % WORD_BITS);
+            }
+            self.allocated += 1;
+        }
+    }
+
+    /// First index at or after `from` that is free (`free`) or in use
+    fn find(&self, from: usize, free: bool) -> Option<usize> {
+        let mut word = from / WORD_BITS;
+        let mut mask = !0u64 << (from % WORD_BITS);
+        while word < self.used.len() {
+            // A full second-level word covers 64 full words
+            if free && word.is_multiple_of(WORD_BITS) && mask == !0 && self.full[word / WORD_BITS] == !0 {
+                word += WORD_BITS;
+                continue;
+            }
+            let bits = if free { !self.used[word] } else { self.used[word] } & mask;
+            if bits != 0 {
+                return Some(word * WORD_BITS + bits.trailing_zeros() as usize);
+            }
+            word += 1;
+            mask = !0;
+        }
+        None
+    }
+
+    /// The first run of free indices at or after `from`
+    fn free_run(&self, from: usize) -> Option<Range<usize>> {
+        let start = self.find(from, true)?;
+        let end = self.find(start, false).map_or(self.len, |end| end.min(self.len));
+        Some(start..end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lowest_first_and_ranges() {
+        let mut cspace = CSpaceAllocator::new(100..300);
+        assert_eq!(cspace.allocate(), Some(100));
+        assert_eq!(cspace.allocate(), Some(101));
+        assert_eq!(cspace.allocate_range(70), Some(102..172));
+
+        assert!(cspace.free(101));
+        assert!(!cspace.free(101));
+        assert!(!cspace.free(99));
+        // Too small a hole is skipped; a single slot fills it
+        assert_eq!(cspace.allocate_range(2), Some(172..174));
+        assert_eq!(cspace.allocate(), Some(101));
+
+        // The kernel filled a slot for us
+        assert!(cspace.reserve(174));
+        assert!(!cspace.reserve(174));
+        assert_eq!(cspace.allocate(), Some(175));
+
+        assert_eq!(cspace.allocate_range(125), None);
+        assert_eq!(cspace.allocate_range(124), Some(176..300));
+        assert_eq!(cspace.allocate(), None);
+        assert_eq!(cspace.stats().allocated, 200);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut cspace = CSpaceAllocator::new(0..10_000);
+        let empty = cspace.stats();
+        assert_eq!((empty.high_water, empty.holes, empty.largest_free_run), (0, 0, 10_000));
+
+        // Past the first 4096 slots, a search skips a full second-level word
+        assert_eq!(cspace.allocate_range(4999), Some(0..4999));
+        assert_eq!(cspace.allocate(), Some(4999));
+        for slot in (10..20).chain(30..31) {
+            cspace.free(slot);
+        }
+        assert_eq!(cspace.allocate_range(11), Some(5000..5011));
+
+        assert_eq!(
+            cspace.stats(),
+            CSpaceStats {
+                capacity: 10_000,
+                allocated: 5000,
+                high_water: 5011,
+                holes: 11,
+                hole_runs: 2,
+                largest_free_run: 10_000 - 5011,
+            }
+        );
+        assert_eq!(cspace.allocate(), Some(10));
+    }
+}
@@ -0,0 +1,299 @@
+//! Device Descriptors
+//!
+//! A [`DeviceDescriptor`] is a flat, self-contained copy of what a
+//! [`DeviceResource`] tells its driver: where the registers are, which
+//! interrupt to expect and where its DMA pool lives. The root task claims
+//! a device, maps it into the driver and passes the descriptor along in
+//! the driver's boot arguments or a shared page, so the driver starts
+//! with everything it needs instead of asking the broker again.
+//!
+//! Layout (`#[repr(C)]`, 80 bytes, little-endian on the wire):
+//!
+//! | Offset | Contents                                                  |
+//! |--------|-----------------------------------------------------------|
+//! | 0      | `magic` (u32, `DeviceDescriptor::MAGIC`)                  |
+//! | 4      | `version` (u16), `flags` (u16, which fields are present)  |
+//! | 8      | `mmio_base`, `mmio_size`, `mmio_vaddr` (u64 each)         |
+//! | 32     | `dma_phys`, `dma_bus`, `dma_vaddr`, `dma_size` (u64 each) |
+//! | 64     | `irq`, `irq_cap` (u32 each)                               |
+//! | 72     | `pci_location`, `pci_id` (u32 each)                       |
+//!
+//! PCI functions are located as in hot-plug events (bus/device/function,
+//! `bus << 8 | dev << 3 | fn`) and identified by `vendor << 16 | device`.
+//!
+//! Addresses and the IRQ capability slot are as seen by whoever built
+//! the descriptor; the root task replaces them with the driver's view
+//! (see [`DeviceDescriptor::with_mmio_vaddr`] and friends) before handing
+//! it over.
+
+use crate::device_manager::DeviceResource;
+
+/// `mmio_vaddr` is present
+const HAS_MMIO_VADDR: u16 = 1 << 0;
+/// `irq` is present
+const HAS_IRQ: u16 = 1 << 1;
+/// `irq_cap` is present
+const HAS_IRQ_CAP: u16 = 1 << 2;
+/// The `dma_*` fields are present
+const HAS_DMA: u16 = 1 << 3;
+/// `pci_location` and `pci_id` are present
+const HAS_PCI: u16 = 1 << 4;
+
+/// A device bundle, flattened for another component
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceDescriptor {
+    magic: u32,
+    version: u16,
+    flags: u16,
+    mmio_base: u64,
+    mmio_size: u64,
+    mmio_vaddr: u64,
+    dma_phys: u64,
+    dma_bus: u64,
+    dma_vaddr: u64,
+    dma_size: u64,
+    irq: u32,
+    irq_cap: u32,
+    pci_location: u32,
+    pci_id: u32,
+}
+
+const _: () = assert!(core::mem::size_of::<DeviceDescriptor>() == DeviceDescriptor::SIZE);
+
+impl DeviceDescriptor {
+    /// Identifies a descriptor ("KDEV")
+    pub const MAGIC: u32 = 0x5645_444B;
+    /// Layout version
+    pub const VERSION: u16 = 1;
+    /// Encoded size in bytes
+    pub const SIZE: usize = 80;
+
+    /// MMIO base address (physical)
+    pub fn mmio_base(&self) -> usize {
+        self.mmio_base as usize
+    }
+
+    /// MMIO size in bytes
+    pub fn mmio_size(&self) -> usize {
+        self.mmio_size as usize
+    }
+
+    /// Where the MMIO region is mapped, if it is
+    pub fn mmio_vaddr(&self) -> Option<usize> {
+        self.present(HAS_MMIO_VADDR, self.mmio_vaddr as usize)
+    }
+
+    /// IRQ number, if the device has one
+    pub fn irq(&self) -> Option<u32> {
+        self.present(HAS_IRQ, self.irq)
+    }
+
+    /// Slot of the IRQ capability, if one was handed over
+    pub fn irq_cap(&self) -> Option<usize> {
+        self.present(HAS_IRQ_CAP, self.irq_cap as usize)
+    }
+
+    /// DMA pool as (physical address, bus address, vaddr, size), if the
+    /// device has one
+    pub fn dma(&self) -> Option<(usize, usize, usize, usize)> {
+        self.present(
+            HAS_DMA,
+            (
+                self.dma_phys as usize,
+                self.dma_bus as usize,
+                self.dma_vaddr as usize,
+                self.dma_size as usize,
+            ),
+        )
+    }
+
+    /// PCI location (`bus << 8 | dev << 3 | fn`) and `vendor << 16 |
+    /// device`, for PCI functions
+    pub fn pci(&self) -> Option<(u32, u32)> {
+        self.present(HAS_PCI, (self.pci_location, self.pci_id))
+    }
+
+    /// The same device with its MMIO region mapped at `vaddr` (`None`:
+    /// not mapped)
+    pub fn with_mmio_vaddr(mut self, vaddr: Option<usize>) -> Self {
+        self.mmio_vaddr = vaddr.unwrap_or(0) as u64;
+        self.set(HAS_MMIO_VADDR, vaddr.is_some());
+        self
+    }
+
+    /// The same device with its IRQ capability in `slot` (`None`: not
+    /// handed over)
+    pub fn with_irq_cap(mut self, slot: Option<usize>) -> Self {
+        self.irq_cap = slot.unwrap_or(0) as u32;
+        self.set(HAS_IRQ_CAP, slot.is_some());
+        self
+    }
+
+    /// The same device with its DMA pool mapped at `vaddr`
+    pub fn with_dma_vaddr(mut self, vaddr: usize) -> Self {
+        self.dma_vaddr = vaddr as u64;
+        self
+    }
+
+    /// Encode for a boot argument or shared page
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        let mut pos = 0;
+        let mut put = |field: &[u8]| {
+            bytes[pos..pos + field.len()].copy_from_slice(field);
+            pos += field.len();
+        };
+        put(&self.magic.to_le_bytes());
+        put(&self.version.to_le_bytes());
+        put(&self.flags.to_le_bytes());
+        for field in [
+            self.mmio_base,
+            self.mmio_size,
+            self.mmio_vaddr,
+            self.dma_phys,
+            self.dma_bus,
+            self.dma_vaddr,
+            self.dma_size,
+        ] {
+            put(&field.to_le_bytes());
+        }
+        for field in [self.irq, self.irq_cap, self.pci_location, self.pci_id] {
+            put(&field.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode a descriptor, or `None` if `bytes` is not one this layout
+    /// version understands
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        let u16_at = |pos: usize| u16::from_le_bytes([bytes[pos], bytes[pos + 1]]);
+        let u32_at = |pos: usize| u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+        let u64_at = |pos: usize| u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap());
+
+        if u32_at(0) != Self::MAGIC || u16_at(4) != Self::VERSION {
+            return None;
+        }
+        Some(Self {
+            magic: Self::MAGIC,
+            version: Self::VERSION,
+            flags: u16_at(6),
+            mmio_base: u64_at(8),
+            mmio_size: u64_at(16),
+            mmio_vaddr: u64_at(24),
+            dma_phys: u64_at(32),
+            dma_bus: u64_at(40),
+            dma_vaddr: u64_at(48),
+            dma_size: u64_at(56),
+            irq: u32_at(64),
+            irq_cap: u32_at(68),
+            pci_location: u32_at(72),
+            pci_id: u32_at(76),
+        })
+    }
+
+    fn present<T>(&self, flag: u16, value: T) -> Option<T> {
+        (self.flags & flag != 0).then_some(value)
+    }
+
+    fn set(&mut self, flag: u16, present: bool) {
+        if present {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+}
+
+impl DeviceResource {
+    /// Flatten this bundle into a [`DeviceDescriptor`]
+    ///
+    /// Addresses are the broker's view; see the [module
+    /// documentation](crate::descriptor).
+    pub fn descriptor(&self) -> DeviceDescriptor {
+        let mut descriptor = DeviceDescriptor {
+            magic: DeviceDescriptor::MAGIC,
+            version: DeviceDescriptor::VERSION,
+            flags: 0,
+            mmio_base: self.mmio_base as u64,
+            mmio_size: self.mmio_size as u64,
+            mmio_vaddr: 0,
+            dma_phys: 0,
+            dma_bus: 0,
+            dma_vaddr: 0,
+            dma_size: 0,
+            irq: 0,
+            irq_cap: 0,
+            pci_location: 0,
+            pci_id: 0,
+        }
+        .with_mmio_vaddr(self.mmio_vaddr)
+        .with_irq_cap(self.irq_cap);
+
+        if let Some(irq) = self.irq {
+            descriptor.irq = irq;
+            descriptor.flags |= HAS_IRQ;
+        }
+        if let Some(pool) = &self.dma_pool {
+            descriptor.dma_phys = pool.phys_base() as u64;
+            descriptor.dma_bus = pool.bus_base() as u64;
+            descriptor.dma_vaddr = pool.vaddr_base() as u64;
+            descriptor.dma_size = pool.size() as u64;
+            descriptor.flags |= HAS_DMA;
+        }
+        if let Some(pci) = &self.pci {
+            descriptor.pci_location =
+                (pci.bus as u32) << 8 | (pci.device as u32) << 3 | pci.function as u32;
+            descriptor.pci_id = (pci.vendor_id as u32) << 16 | pci.device_id as u32;
+            descriptor.flags |= HAS_PCI;
+        }
+        descriptor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dma_pool::DmaPool;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_descriptor_round_trip() {
+        let uart = DeviceResource {
+            mmio_base: 0x0900_0000,
+            mmio_size: 0x1000,
+            mmio_vaddr: Some(0x8000_0000),
+            irq: Some(33),
+            irq_cap: None,
+            dma_cap: None,
+            dma_pool: Some(DmaPool::new(0x4800_0000, 0x1_0000, 0x9000_0000, 0x4000)),
+            pci: None,
+            gpios: Vec::new(),
+            pinmux: Vec::new(),
+            claim: 1,
+        };
+
+        // As the driver sees it
+        let descriptor = uart
+            .descriptor()
+            .with_mmio_vaddr(Some(0x2000_0000))
+            .with_irq_cap(Some(12))
+            .with_dma_vaddr(0x2010_0000);
+        let decoded = DeviceDescriptor::from_bytes(&descriptor.to_bytes()).unwrap();
+        assert_eq!(decoded, descriptor);
+        assert_eq!(decoded.mmio_base(), 0x0900_0000);
+        assert_eq!(decoded.mmio_vaddr(), Some(0x2000_0000));
+        assert_eq!((decoded.irq(), decoded.irq_cap()), (Some(33), Some(12)));
+        assert_eq!(decoded.dma(), Some((0x4800_0000, 0x1_0000, 0x2010_0000, 0x4000)));
+        assert_eq!(decoded.pci(), None);
+
+        let unmapped = descriptor.with_mmio_vaddr(None);
+        assert_eq!(unmapped.mmio_vaddr(), None);
+
+        let mut bytes = descriptor.to_bytes();
+        bytes[4] = 2;
+        assert_eq!(DeviceDescriptor::from_bytes(&bytes), None);
+        assert_eq!(DeviceDescriptor::from_bytes(&bytes[..40]), None);
+    }
+}
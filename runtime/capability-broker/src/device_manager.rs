@@ -1,8 +1,29 @@
 //! Device Manager
 //!
 //! Manages device resource allocation (MMIO regions, IRQs, DMA buffers).
+//!
+//! Devices come from the platform's device tree when the kernel passes one
+//! (see [`crate::device_tree`]), PCI functions from enumerating its ECAM
+//! host bridges (see [`crate::pci`]); the fixed device regions in boot
+//! info still back the `Uart`/`Rtc`/`Timer`/`Custom` identifiers. Devices
+//! behind an SMMUv3 get their DMA translated (see [`crate::iommu`]).
+//! Device tree devices come with their GPIO lines and pin configurations,
+//! held exclusively by their claim (see [`crate::gpio`]).
+
+use alloc::vec::Vec;
 
-use crate::{BrokerError, Result, boot_info::BootInfo};
+use crate::{
+    BrokerError, Result,
+    boot_info::BootInfo,
+    device_tree::DeviceTree,
+    dma_pool::DmaPool,
+    gpio::{self, GpioLine, Pin, PinGroup},
+    iommu::{self, IoDomain, Smmu},
+    memory_manager::{self, MemoryType},
+    pci::{PciBus, PciFunction},
+};
+
+const PAGE_SIZE: usize = 4096;
 
 /// Device identifier
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,6 +36,19 @@ pub enum DeviceId {
     Rtc,
     /// Custom device (device_type from boot info)
     Custom(u32),
+    /// Device tree node, by name with or without unit address
+    /// (e.g. `pl011@9000000` or `pl011`)
+    Platform {
+        /// Node name
+        name: &'static str,
+    },
+    /// PCI function, by vendor and device ID (first match)
+    Pci {
+        /// Vendor ID
+        vendor: u16,
+        /// Device ID
+        device: u16,
+    },
 }
 
 /// Device resource bundle
@@ -24,38 +58,327 @@ pub struct DeviceResource {
     pub mmio_base: usize,
     /// MMIO size in bytes
     pub mmio_size: usize,
+    /// Where the MMIO region is mapped in the broker's address space
+    /// (read/write), if the device has one
+    pub mmio_vaddr: Option<usize>,
+    /// IRQ number (if the device has one)
+    pub irq: Option<u32>,
     /// IRQ capability slot (if applicable)
     pub irq_cap: Option<usize>,
     /// DMA buffer capability slot (if applicable)
     pub dma_cap: Option<usize>,
+    /// DMA buffers for the device (see `CapabilityBroker::attach_dma_pool`)
+    pub dma_pool: Option<DmaPool>,
+    /// PCI function with all its BARs (PCI devices only)
+    pub pci: Option<PciFunction>,
+    /// GPIO lines the device uses (device tree devices only)
+    pub gpios: Vec<GpioLine>,
+    /// Pin configurations, for every pin state (device tree devices only)
+    pub pinmux: Vec<PinGroup>,
+    /// Claim this bundle was handed out under
+    pub(crate) claim: usize,
+}
+
+/// A device handed out by `request_device` and not yet released
+#[derive(Debug)]
+pub(crate) struct Claim {
+    pub(crate) id: usize,
+    device_id: DeviceId,
+    owner_pid: usize,
+    mmio_base: usize,
+    mmio_size: usize,
+    mmio_vaddr: Option<usize>,
+    pci: Option<PciFunction>,
+    /// IOMMU streams of the device's DMA
+    streams: Vec<u32>,
+    /// The device's I/O address space, once it has DMA memory
+    domain: Option<IoDomain>,
+    /// Broker mapping of the DMA pool (vaddr, size)
+    dma_mapping: Option<(usize, usize)>,
+    /// IRQ and DMA capability slots
+    pub(crate) caps: [Option<usize>; 2],
+    /// GPIO lines and muxed pins, no other claim may hold them
+    pins: Vec<Pin>,
+}
+
+impl Claim {
+    /// Does this claim cover `device_id`, which resolved to `resource`?
+    ///
+    /// Names can alias (`Uart(0)` and the `pl011` node are the same device),
+    /// so a shared MMIO base counts too.
+    fn covers(&self, device_id: DeviceId, resource: &DeviceResource) -> bool {
+        self.device_id == device_id
+            || (self.mmio_size > 0
+                && resource.mmio_size > 0
+                && self.mmio_base == resource.mmio_base)
+    }
 }
 
 /// Device Manager
 pub struct DeviceManager {
     /// Copy of boot info for device lookups
     boot_info: Option<&'static BootInfo>,
+    /// Devices from the device tree, if the kernel passed one
+    device_tree: Option<DeviceTree>,
+    /// PCI functions behind the device tree's host bridges
+    pci_bus: PciBus,
+    /// SMMUv3 translating device DMA, if there is one
+    iommu: Option<Smmu>,
+    /// Devices currently handed out
+    claims: Vec<Claim>,
+    /// Next claim ID
+    next_claim: usize,
 }
 
 impl DeviceManager {
     /// Create a new Device Manager from boot info
     pub(crate) fn new_from_boot_info(boot_info: &'static BootInfo) -> Self {
+        let device_tree = DeviceTree::from_boot_info(boot_info);
+        let pci_bus = device_tree.as_ref().map_or_else(PciBus::empty, PciBus::probe);
+        let iommu = device_tree.as_ref().and_then(|tree| Smmu::probe(tree, &pci_bus));
         Self {
             boot_info: Some(boot_info),
+            device_tree,
+            pci_bus,
+            iommu,
+            claims: Vec::new(),
+            next_claim: 1,
         }
     }
 
     /// Create a new Device Manager (legacy, for tests)
     #[allow(dead_code)]
     pub(crate) fn new() -> Self {
-        Self { boot_info: None }
+        Self {
+            boot_info: None,
+            device_tree: None,
+            pci_bus: PciBus::empty(),
+            iommu: None,
+            claims: Vec::new(),
+            next_claim: 1,
+        }
+    }
+
+    /// Devices from the device tree
+    pub(crate) fn device_tree(&self) -> Option<&DeviceTree> {
+        self.device_tree.as_ref()
+    }
+
+    /// PCI functions
+    pub(crate) fn pci_bus(&self) -> &PciBus {
+        &self.pci_bus
     }
 
-    /// Request a device
+    /// Claim currently holding a device, if any
+    pub(crate) fn holder(&self, device_id: DeviceId) -> Result<Option<&Claim>> {
+        let resource = self.find_device(device_id, None)?;
+        Ok(self.claims.iter().find(|c| c.covers(device_id, &resource)))
+    }
+
+    /// Owner of a device, if it is handed out
+    pub(crate) fn owner(&self, device_id: DeviceId) -> Option<usize> {
+        self.holder(device_id).ok().flatten().map(|c| c.owner_pid)
+    }
+
+    /// Request a device for `owner_pid`, mapping its MMIO region as
+    /// `memory_type`
+    ///
+    /// Fails with `DeviceBusy` while another claim holds the device, and
+    /// with `ResourceInUse` if one holds any of its pins.
     pub(crate) fn request_device(
         &mut self,
         device_id: DeviceId,
         irq_cap: Option<usize>,
+        owner_pid: usize,
+        memory_type: MemoryType,
     ) -> Result<DeviceResource> {
+        let mut resource = self.find_device(device_id, irq_cap)?;
+        if self.claims.iter().any(|c| c.covers(device_id, &resource)) {
+            return Err(BrokerError::DeviceBusy);
+        }
+        let pins = gpio::pins(&resource.gpios, &resource.pinmux);
+        if self.claims.iter().any(|c| c.pins.iter().any(|p| pins.contains(p))) {
+            return Err(BrokerError::ResourceInUse);
+        }
+
+        if resource.mmio_size > 0 {
+            let (page, len) = page_span(resource.mmio_base, resource.mmio_size);
+            let permissions = 0x3 | memory_type.map_bits(); // read | write
+            let vaddr = memory_manager::map_physical(page, len, permissions)
+                .ok_or(BrokerError::SyscallFailed(usize::MAX))?;
+            resource.mmio_vaddr = Some(vaddr + (resource.mmio_base - page));
+        }
+        if let Some(function) = &resource.pci {
+            function.enable();
+        }
+        let streams = self.streams(device_id, &resource);
+
+        resource.claim = self.next_claim;
+        self.next_claim += 1;
+        self.claims.push(Claim {
+            id: resource.claim,
+            device_id,
+            owner_pid,
+            mmio_base: resource.mmio_base,
+            mmio_size: resource.mmio_size,
+            mmio_vaddr: resource.mmio_vaddr,
+            pci: resource.pci.clone(),
+            streams,
+            domain: None,
+            dma_mapping: None,
+            caps: [resource.irq_cap, resource.dma_cap],
+            pins,
+        });
+        Ok(resource)
+    }
+
+    /// IOMMU streams of a device, if it sits behind the SMMU
+    fn streams(&self, device_id: DeviceId, resource: &DeviceResource) -> Vec<u32> {
+        let Some(smmu) = &self.iommu else {
+            return Vec::new();
+        };
+        let streams = match device_id {
+            DeviceId::Platform { name } => self
+                .device_tree
+                .as_ref()
+                .and_then(|tree| tree.find(name))
+                .map(iommu::platform_streams)
+                .unwrap_or_default(),
+            _ => resource.pci.iter().filter_map(|f| f.stream_id).collect(),
+        };
+        streams
+            .iter()
+            .filter(|s| smmu.handles(s))
+            .map(|s| s.id)
+            .collect()
+    }
+
+    /// Make DMA memory (capability `slot`, mapped into the broker at
+    /// `vaddr`; both freed with the claim) reachable by a claimed device
+    ///
+    /// Returns the bus address the device should use: an IOVA in the
+    /// device's own domain behind the SMMU, else the physical address.
+    pub(crate) fn map_dma(
+        &mut self,
+        id: usize,
+        slot: usize,
+        phys: usize,
+        vaddr: usize,
+        size: usize,
+    ) -> Result<usize> {
+        let claim = self
+            .claims
+            .iter_mut()
+            .find(|c| c.id == id)
+            .ok_or(BrokerError::InvalidCapability)?;
+
+        let bus = match &mut self.iommu {
+            Some(smmu) if !claim.streams.is_empty() => {
+                let domain = match &mut claim.domain {
+                    Some(domain) => domain,
+                    None => claim.domain.insert(smmu.attach(&claim.streams)?),
+                };
+                smmu.map(domain, phys, size)? as usize
+            }
+            _ => phys,
+        };
+        claim.caps[1] = Some(slot);
+        claim.dma_mapping = Some((vaddr, size));
+        Ok(bus)
+    }
+
+    /// Undo `request_device` for a dropped claim: stop a PCI function's
+    /// DMA, fence the device off behind the SMMU and unmap the MMIO region
+    /// and DMA pool
+    ///
+    /// Every step is attempted; the first failure is returned.
+    pub(crate) fn teardown(&mut self, claim: &mut Claim) -> Result<()> {
+        if let Some(function) = &claim.pci {
+            function.disable();
+        }
+        let detached = match (&mut self.iommu, claim.domain.take()) {
+            (Some(smmu), Some(domain)) => smmu.detach(domain),
+            _ => Ok(()),
+        };
+        let unmapped = match claim.mmio_vaddr {
+            Some(vaddr) => {
+                let (page, len) = page_span(vaddr, claim.mmio_size);
+                memory_manager::unmap_virtual(page, len)
+            }
+            None => Ok(()),
+        };
+        let dma_unmapped = match claim.dma_mapping.take() {
+            Some((vaddr, size)) => memory_manager::unmap_virtual(vaddr, size),
+            None => Ok(()),
+        };
+        detached.and(unmapped).and(dma_unmapped)
+    }
+
+    /// Claims held by `owner_pid`
+    pub(crate) fn claims_of(&self, owner_pid: usize) -> Vec<usize> {
+        self.claims
+            .iter()
+            .filter(|c| c.owner_pid == owner_pid)
+            .map(|c| c.id)
+            .collect()
+    }
+
+    /// Drop a claim, returning it for teardown
+    ///
+    /// Returns `None` if it is gone already (the device was claimed away).
+    pub(crate) fn take_claim(&mut self, id: usize) -> Option<Claim> {
+        let index = self.claims.iter().position(|c| c.id == id)?;
+        Some(self.claims.swap_remove(index))
+    }
+
+    /// Look up the resources for a device
+    fn find_device(
+        &self,
+        device_id: DeviceId,
+        irq_cap: Option<usize>,
+    ) -> Result<DeviceResource> {
+        if let DeviceId::Platform { name } = device_id {
+            let tree = self.device_tree.as_ref().ok_or(BrokerError::DeviceNotFound)?;
+            let device = tree.find(name).ok_or(BrokerError::DeviceNotFound)?;
+            let mmio = device.mmio.first();
+
+            return Ok(DeviceResource {
+                mmio_base: mmio.map_or(0, |m| m.base as usize),
+                mmio_size: mmio.map_or(0, |m| m.size as usize),
+                mmio_vaddr: None,
+                irq: device.irqs.first().copied(),
+                irq_cap,
+                dma_cap: None,
+                dma_pool: None,
+                pci: None,
+                gpios: gpio::gpio_lines(tree, device)?,
+                pinmux: gpio::pin_groups(tree, device)?,
+                claim: 0,
+            });
+        }
+
+        if let DeviceId::Pci { vendor, device } = device_id {
+            let function = self
+                .pci_bus
+                .find(vendor, device)
+                .ok_or(BrokerError::DeviceNotFound)?;
+            let bar = function.first_bar();
+
+            return Ok(DeviceResource {
+                mmio_base: bar.map_or(0, |b| b.base as usize),
+                mmio_size: bar.map_or(0, |b| b.size as usize),
+                mmio_vaddr: None,
+                irq: function.irq,
+                irq_cap,
+                dma_cap: None,
+                dma_pool: None,
+                pci: Some(function.clone()),
+                gpios: Vec::new(),
+                pinmux: Vec::new(),
+                claim: 0,
+            });
+        }
+
         let boot_info = self.boot_info.ok_or(BrokerError::DeviceNotFound)?;
 
         // Map DeviceId to device_type from boot info
@@ -76,8 +399,21 @@ impl DeviceManager {
         Ok(DeviceResource {
             mmio_base: device.paddr as usize,
             mmio_size: device.size as usize,
+            mmio_vaddr: None,
+            irq: (device.irq != 0xFFFFFFFF).then_some(device.irq),
             irq_cap,
-            dma_cap: None, // DMA not implemented yet
+            dma_cap: None,
+            dma_pool: None,
+            pci: None,
+            gpios: Vec::new(),
+            pinmux: Vec::new(),
+            claim: 0,
         })
     }
 }
+
+/// Page-aligned start and length covering `size` bytes at `addr`
+fn page_span(addr: usize, size: usize) -> (usize, usize) {
+    let page = addr & !(PAGE_SIZE - 1);
+    (page, (addr - page + size).next_multiple_of(PAGE_SIZE))
+}
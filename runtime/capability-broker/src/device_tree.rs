@@ -0,0 +1,877 @@
+//! Device Tree Registry
+//!
+//! Builds the broker's device registry from the flattened device tree the
+//! bootloader passed to the kernel (`BootInfo::dtb_paddr`), so platform
+//! devices can be requested by node name instead of from a fixed table.
+//!
+//! Every enabled node with MMIO ranges or interrupts becomes a
+//! [`DeviceEntry`]:
+//! - `reg` ranges are translated to physical addresses through the parent
+//!   buses' `ranges` (nodes behind a bus without `ranges` get no MMIO)
+//! - `interrupts` are decoded against the node's interrupt parent; GIC
+//!   specifiers become interrupt IDs (SPI n = 32 + n, PPI n = 16 + n),
+//!   other interrupt parents (GPIO controllers, nexus nodes) are skipped
+//! - `compatible` strings are kept for matching drivers to devices
+//!
+//! Nodes with a `phandle` are also kept as providers, with their
+//! `#...-cells` counts, so phandle lists such as `clocks`, `resets` or
+//! `power-domains` can be decoded into [`Specifier`]s.
+
+use alloc::vec::Vec;
+
+use crate::{boot_info::BootInfo, memory_manager, BrokerError, Result};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// GIC interrupt specifier types (first cell)
+const GIC_SPI: u32 = 0;
+const GIC_PPI: u32 = 1;
+
+/// An MMIO range of a device, in physical addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioRange {
+    /// Physical base address
+    pub base: u64,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// One entry of a phandle list such as `clocks = <&ccu 12>, <&osc>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Specifier {
+    /// Phandle of the provider node
+    pub provider: u32,
+    /// Specifier cells, as many as the provider's `#...-cells`
+    pub cells: Vec<u32>,
+}
+
+/// A device found in the device tree
+#[derive(Debug, Clone)]
+pub struct DeviceEntry {
+    /// Node name with unit address (e.g. `pl011@9000000`)
+    pub name: &'static str,
+    /// MMIO ranges, in `reg` order
+    pub mmio: Vec<MmioRange>,
+    /// GIC interrupt IDs, in `interrupts` order
+    pub irqs: Vec<u32>,
+    /// Raw `compatible` property: NUL-separated, most specific first
+    compatible: &'static [u8],
+    /// All properties of the node, in blob order
+    properties: Vec<(&'static str, &'static [u8])>,
+    /// `#address-cells` of the parent bus
+    parent_address_cells: usize,
+}
+
+impl DeviceEntry {
+    /// Node name without the unit address (e.g. `pl011`)
+    pub fn base_name(&self) -> &'static str {
+        self.name.split('@').next().unwrap_or(self.name)
+    }
+
+    /// The node's `compatible` strings, most specific first
+    pub fn compatible(&self) -> impl Iterator<Item = &'static str> {
+        self.compatible
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| core::str::from_utf8(s).ok())
+    }
+
+    /// Whether the device is compatible with `compatible`
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|c| c == compatible)
+    }
+
+    /// Raw value of a property
+    pub fn property(&self, name: &str) -> Option<&'static [u8]> {
+        self.properties.iter().find(|(n, _)| *n == name).map(|&(_, value)| value)
+    }
+
+    /// All properties, as (name, raw value), in blob order
+    pub fn properties(&self) -> impl Iterator<Item = (&'static str, &'static [u8])> + '_ {
+        self.properties.iter().copied()
+    }
+
+    /// A single-cell property such as `#address-cells`, or `default`
+    pub fn cell_property(&self, name: &str, default: u32) -> u32 {
+        self.property(name).and_then(|v| be32(v, 0)).unwrap_or(default)
+    }
+
+    /// `#address-cells` of the bus the device sits on
+    pub fn parent_address_cells(&self) -> usize {
+        self.parent_address_cells
+    }
+}
+
+/// Devices parsed from a flattened device tree
+pub struct DeviceTree {
+    entries: Vec<DeviceEntry>,
+    controllers: Vec<Controller>,
+    providers: Vec<Provider>,
+}
+
+impl DeviceTree {
+    /// Map the device tree described by boot info and parse it
+    ///
+    /// Returns `None` if the kernel passed no device tree, it cannot be
+    /// mapped, or it does not parse.
+    pub(crate) fn from_boot_info(boot_info: &BootInfo) -> Option<Self> {
+        if boot_info.dtb_paddr == 0 || boot_info.dtb_size == 0 {
+            return None;
+        }
+
+        let paddr = boot_info.dtb_paddr as usize;
+        let offset = paddr & 0xfff;
+        let size = boot_info.dtb_size as usize;
+        let vaddr = memory_manager::map_physical(paddr - offset, offset + size, 0x1)?;
+
+        // SAFETY: the mapping stays in place for the broker's lifetime
+        let blob = unsafe { core::slice::from_raw_parts((vaddr + offset) as *const u8, size) };
+        Self::parse(blob).ok()
+    }
+
+    /// Parse a flattened device tree blob
+    pub fn parse(blob: &'static [u8]) -> Result<Self> {
+        let fdt = Fdt::new(blob)?;
+        let controllers = interrupt_controllers(&fdt)?;
+        let providers = providers(&fdt)?;
+
+        let mut entries = Vec::new();
+        let mut stack: Vec<Node> = Vec::new();
+        for token in fdt.tokens() {
+            match token? {
+                Token::BeginNode(name) => {
+                    // A node's properties come before its children
+                    if let Some(parent) = stack.last_mut() {
+                        if !parent.finished {
+                            parent.finished = true;
+                            collect(&stack, &controllers, &mut entries);
+                        }
+                    }
+                    let interrupt_parent = stack.last().and_then(|p| p.interrupt_parent);
+                    stack.push(Node::new(name, interrupt_parent));
+                }
+                Token::Property(name, value) => {
+                    let node = stack.last_mut().ok_or(BrokerError::InvalidDeviceTree)?;
+                    node.property(name, value);
+                }
+                Token::EndNode => {
+                    if !stack.last().ok_or(BrokerError::InvalidDeviceTree)?.finished {
+                        collect(&stack, &controllers, &mut entries);
+                    }
+                    stack.pop();
+                }
+            }
+        }
+
+        Ok(Self { entries, controllers, providers })
+    }
+
+    /// All devices, in device tree order
+    pub fn entries(&self) -> &[DeviceEntry] {
+        &self.entries
+    }
+
+    /// Find a device by node name
+    ///
+    /// `name` is matched against the full node name (`pl011@9000000`),
+    /// then against names without unit address (`pl011`, first in tree
+    /// order).
+    pub fn find(&self, name: &str) -> Option<&DeviceEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name)
+            .or_else(|| self.entries.iter().find(|e| e.base_name() == name))
+    }
+
+    /// Devices compatible with `compatible`, in device tree order
+    pub fn find_compatible<'a>(&'a self, compatible: &'a str) -> impl Iterator<Item = &'a DeviceEntry> {
+        self.entries.iter().filter(move |e| e.is_compatible(compatible))
+    }
+
+    /// Decode the phandle list `property` of `entry`
+    ///
+    /// `cells` names the providers' specifier size (`#clock-cells` for
+    /// `clocks`). Returns `None` if the property is absent or refers to a
+    /// node without `cells`, since the rest of the list cannot be decoded.
+    pub fn specifiers(&self, entry: &DeviceEntry, property: &str, cells: &str) -> Option<Vec<Specifier>> {
+        let list = entry.property(property)?;
+        let mut specifiers = Vec::new();
+        let mut cell = 0;
+        while cell < list.len() / 4 {
+            let provider = be32(list, cell)?;
+            let count = self.provider(provider)?.cells(cells)? as usize;
+            specifiers.push(Specifier {
+                provider,
+                cells: (cell + 1..cell + 1 + count).map(|i| be32(list, i)).collect::<Option<_>>()?,
+            });
+            cell += 1 + count;
+        }
+        Some(specifiers)
+    }
+
+    /// Whether the node with `phandle` is compatible with `compatible`
+    pub fn provider_is_compatible(&self, phandle: u32, compatible: &str) -> bool {
+        self.provider(phandle).is_some_and(|p| {
+            p.compatible.split(|&b| b == 0).any(|c| c == compatible.as_bytes())
+        })
+    }
+
+    /// Raw value of a property of the node with `phandle`
+    pub fn provider_property(&self, phandle: u32, name: &str) -> Option<&'static [u8]> {
+        let provider = self.provider(phandle)?;
+        provider.properties.iter().find(|(n, _)| *n == name).map(|&(_, value)| value)
+    }
+
+    /// Phandle of the parent of the node with `phandle`, if it has one
+    ///
+    /// Pin configuration nodes sit under their pin controller, which is
+    /// how a `pinctrl-0` entry leads to the controller.
+    pub fn provider_parent(&self, phandle: u32) -> Option<u32> {
+        self.provider(phandle)?.parent
+    }
+
+    fn provider(&self, phandle: u32) -> Option<&Provider> {
+        self.providers.iter().find(|p| p.phandle == phandle)
+    }
+
+    /// Resolve an interrupt of a child of `nexus` through its `interrupt-map`
+    ///
+    /// `unit` is the child's unit address and `spec` its interrupt
+    /// specifier, in the nexus' `#address-cells` / `#interrupt-cells`
+    /// (for a PCI host bridge: the function's `phys.hi` cell with two
+    /// zero cells, and its interrupt pin). Returns the GIC interrupt ID.
+    pub fn map_interrupt(&self, nexus: &DeviceEntry, unit: &[u32], spec: &[u32]) -> Option<u32> {
+        let address_cells = nexus.cell_property("#address-cells", 2) as usize;
+        let interrupt_cells = nexus.cell_property("#interrupt-cells", 1) as usize;
+        if unit.len() != address_cells || spec.len() != interrupt_cells {
+            return None;
+        }
+
+        let child_cells = address_cells + interrupt_cells;
+        let mask = nexus.property("interrupt-map-mask");
+        let masked = |i: usize, value: u32| value & mask.and_then(|m| be32(m, i)).unwrap_or(u32::MAX);
+        let child: Vec<u32> = unit.iter().chain(spec).enumerate().map(|(i, &v)| masked(i, v)).collect();
+
+        let map = nexus.property("interrupt-map")?;
+        let mut cell = 0;
+        while cell + child_cells < map.len() / 4 {
+            let matches = (0..child_cells).all(|i| be32(map, cell + i) == Some(child[i]));
+            let phandle = be32(map, cell + child_cells)?;
+            let parent = self.controllers.iter().find(|c| c.phandle == phandle)?;
+            let parent_spec = cell + child_cells + 1 + parent.address_cells;
+            cell = parent_spec + parent.cells;
+
+            if matches {
+                let spec = map.get(parent_spec * 4..cell * 4)?;
+                return if parent.gic { gic_interrupt(spec) } else { None };
+            }
+        }
+        None
+    }
+}
+
+/// Interrupt controller: phandle, `#interrupt-cells`, `#address-cells`
+/// (0 if absent, as for `interrupt-map` parents), whether it is a GIC
+#[derive(Clone, Copy)]
+struct Controller {
+    phandle: u32,
+    cells: usize,
+    address_cells: usize,
+    gic: bool,
+}
+
+/// Find the interrupt controllers, so specifiers can be decoded
+fn interrupt_controllers(fdt: &Fdt) -> Result<Vec<Controller>> {
+    #[derive(Default)]
+    struct Props {
+        phandle: Option<u32>,
+        cells: Option<usize>,
+        address_cells: usize,
+        controller: bool,
+        gic: bool,
+    }
+
+    let mut controllers = Vec::new();
+    let mut stack: Vec<Props> = Vec::new();
+    for token in fdt.tokens() {
+        match token? {
+            Token::BeginNode(_) => stack.push(Props::default()),
+            Token::Property(name, value) => {
+                let node = stack.last_mut().ok_or(BrokerError::InvalidDeviceTree)?;
+                match name {
+                    "phandle" | "linux,phandle" => node.phandle = be32(value, 0),
+                    "#interrupt-cells" => node.cells = be32(value, 0).map(|c| c as usize),
+                    "#address-cells" => node.address_cells = be32(value, 0).unwrap_or(0) as usize,
+                    "interrupt-controller" => node.controller = true,
+                    "compatible" => node.gic = is_gic(value),
+                    _ => {}
+                }
+            }
+            Token::EndNode => {
+                let node = stack.pop().ok_or(BrokerError::InvalidDeviceTree)?;
+                if let (Some(phandle), Some(cells), true) = (node.phandle, node.cells, node.controller) {
+                    controllers.push(Controller {
+                        phandle,
+                        cells,
+                        address_cells: node.address_cells,
+                        gic: node.gic,
+                    });
+                }
+            }
+        }
+    }
+    Ok(controllers)
+}
+
+/// A node other nodes refer to by phandle
+struct Provider {
+    phandle: u32,
+    /// Phandle of the parent node, if it has one
+    parent: Option<u32>,
+    compatible: &'static [u8],
+    /// `#...-cells` properties, other than `#address-cells` and `#size-cells`
+    cells: Vec<(&'static str, u32)>,
+    properties: Vec<(&'static str, &'static [u8])>,
+}
+
+impl Provider {
+    fn cells(&self, name: &str) -> Option<u32> {
+        self.cells.iter().find(|(n, _)| *n == name).map(|&(_, c)| c)
+    }
+}
+
+/// Find the nodes with a phandle, so phandle lists can be decoded
+fn providers(fdt: &Fdt) -> Result<Vec<Provider>> {
+    let mut providers = Vec::new();
+    let mut stack: Vec<(Option<u32>, Provider)> = Vec::new();
+    for token in fdt.tokens() {
+        match token? {
+            Token::BeginNode(_) => {
+                let parent = stack.last().and_then(|&(phandle, _)| phandle);
+                stack.push((
+                    None,
+                    Provider {
+                        phandle: 0,
+                        parent,
+                        compatible: &[],
+                        cells: Vec::new(),
+                        properties: Vec::new(),
+                    },
+                ));
+            }
+            Token::Property(name, value) => {
+                let (phandle, node) = stack.last_mut().ok_or(BrokerError::InvalidDeviceTree)?;
+                node.properties.push((name, value));
+                match name {
+                    "phandle" | "linux,phandle" => *phandle = be32(value, 0),
+                    "compatible" => node.compatible = value,
+                    "#address-cells" | "#size-cells" => {}
+                    _ if name.starts_with('#') && name.ends_with("-cells") => {
+                        node.cells.push((name, be32(value, 0).unwrap_or(0)));
+                    }
+                    _ => {}
+                }
+            }
+            Token::EndNode => {
+                if let (Some(phandle), node) = stack.pop().ok_or(BrokerError::InvalidDeviceTree)? {
+                    providers.push(Provider { phandle, ..node });
+                }
+            }
+        }
+    }
+    Ok(providers)
+}
+
+/// Interrupt ID of a GIC interrupt specifier (type, number, flags)
+fn gic_interrupt(spec: &[u8]) -> Option<u32> {
+    match (be32(spec, 0)?, be32(spec, 1)?) {
+        (GIC_SPI, n) => Some(32 + n),
+        (GIC_PPI, n) => Some(16 + n),
+        _ => None,
+    }
+}
+
+/// Whether a `compatible` value names an ARM GIC
+fn is_gic(compatible: &[u8]) -> bool {
+    compatible.split(|&b| b == 0).any(|c| {
+        c.starts_with(b"arm,gic")
+            || c.starts_with(b"arm,cortex-a")
+            || c == b"qcom,msm-qgic2"
+    })
+}
+
+/// A node being walked, with the properties the registry needs
+struct Node {
+    name: &'static str,
+    /// `#address-cells` / `#size-cells` for the node's children
+    address_cells: usize,
+    size_cells: usize,
+    reg: &'static [u8],
+    /// None: no `ranges` (children not translatable); empty: identity
+    ranges: Option<&'static [u8]>,
+    interrupts: &'static [u8],
+    interrupt_parent: Option<u32>,
+    compatible: &'static [u8],
+    enabled: bool,
+    memory: bool,
+    properties: Vec<(&'static str, &'static [u8])>,
+    /// Properties done, entry (if any) collected
+    finished: bool,
+}
+
+impl Node {
+    fn new(name: &'static str, interrupt_parent: Option<u32>) -> Self {
+        Self {
+            name,
+            address_cells: 2,
+            size_cells: 1,
+            reg: &[],
+            ranges: None,
+            interrupts: &[],
+            interrupt_parent,
+            compatible: &[],
+            enabled: true,
+            memory: false,
+            properties: Vec::new(),
+            finished: false,
+        }
+    }
+
+    fn property(&mut self, name: &'static str, value: &'static [u8]) {
+        self.properties.push((name, value));
+        match name {
+            "#address-cells" => self.address_cells = be32(value, 0).unwrap_or(2) as usize,
+            "#size-cells" => self.size_cells = be32(value, 0).unwrap_or(1) as usize,
+            "reg" => self.reg = value,
+            "ranges" => self.ranges = Some(value),
+            "interrupts" => self.interrupts = value,
+            "interrupt-parent" => self.interrupt_parent = be32(value, 0),
+            "compatible" => self.compatible = value,
+            "status" => self.enabled = matches!(value, b"okay\0" | b"ok\0"),
+            "device_type" => self.memory = value == b"memory\0",
+            _ => {}
+        }
+    }
+}
+
+/// Turn the innermost node of `stack` into a registry entry, if it is one
+fn collect(stack: &[Node], controllers: &[Controller], entries: &mut Vec<DeviceEntry>) {
+    let depth = stack.len() - 1;
+    let node = &stack[depth];
+    if depth == 0 || !node.enabled || node.memory {
+        return;
+    }
+
+    let parent = &stack[depth - 1];
+    let mut mmio = Vec::new();
+    if parent.size_cells > 0 {
+        let stride = (parent.address_cells + parent.size_cells) * 4;
+        for entry in node.reg.chunks_exact(stride) {
+            let addr = read_cells(entry, 0, parent.address_cells);
+            let size = read_cells(entry, parent.address_cells, parent.size_cells);
+            if let Some(base) = translate(&stack[..depth], addr) {
+                if size > 0 {
+                    mmio.push(MmioRange { base, size });
+                }
+            }
+        }
+    }
+
+    let mut irqs = Vec::new();
+    let controller = node
+        .interrupt_parent
+        .and_then(|phandle| controllers.iter().find(|c| c.phandle == phandle));
+    if let Some(controller) = controller.filter(|c| c.gic && c.cells >= 2) {
+        irqs.extend(node.interrupts.chunks_exact(controller.cells * 4).filter_map(gic_interrupt));
+    }
+
+    if !mmio.is_empty() || !irqs.is_empty() {
+        entries.push(DeviceEntry {
+            name: node.name,
+            mmio,
+            irqs,
+            compatible: node.compatible,
+            properties: node.properties.clone(),
+            parent_address_cells: parent.address_cells,
+        });
+    }
+}
+
+/// Translate a bus address of a child of `ancestors.last()` to physical
+fn translate(ancestors: &[Node], mut addr: u64) -> Option<u64> {
+    // The root's address space is the physical one
+    for level in (1..ancestors.len()).rev() {
+        let bus = &ancestors[level];
+        let parent = &ancestors[level - 1];
+        let ranges = bus.ranges?;
+        if ranges.is_empty() {
+            continue;
+        }
+
+        let stride = (bus.address_cells + parent.address_cells + bus.size_cells) * 4;
+        addr = ranges.chunks_exact(stride).find_map(|range| {
+            let child = read_cells(range, 0, bus.address_cells);
+            let parent_addr = read_cells(range, bus.address_cells, parent.address_cells);
+            let len = read_cells(range, bus.address_cells + parent.address_cells, bus.size_cells);
+            (addr >= child && addr - child < len).then(|| parent_addr + (addr - child))
+        })?;
+    }
+    Some(addr)
+}
+
+/// Read a big-endian number of `cells` cells at cell `index`
+///
+/// Addresses wider than two cells (PCI) keep their low 64 bits.
+fn read_cells(data: &[u8], index: usize, cells: usize) -> u64 {
+    (index..index + cells).fold(0, |acc, i| (acc << 32) | be32(data, i).unwrap_or(0) as u64)
+}
+
+/// Big-endian u32 at cell `index`
+fn be32(data: &[u8], index: usize) -> Option<u32> {
+    let bytes = data.get(index * 4..index * 4 + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A flattened device tree blob with a checked header
+struct Fdt {
+    structure: &'static [u8],
+    strings: &'static [u8],
+}
+
+impl Fdt {
+    fn new(blob: &'static [u8]) -> Result<Self> {
+        let header = |i| be32(blob, i).ok_or(BrokerError::InvalidDeviceTree);
+        if header(0)? != FDT_MAGIC {
+            return Err(BrokerError::InvalidDeviceTree);
+        }
+
+        let total = (header(1)? as usize).min(blob.len());
+        let struct_off = header(2)? as usize;
+        let strings_off = header(3)? as usize;
+        let strings_size = header(8)? as usize;
+        let struct_size = header(9)? as usize;
+        let slice = |off: usize, len: usize| blob[..total].get(off..off.checked_add(len)?);
+
+        Ok(Self {
+            structure: slice(struct_off, struct_size).ok_or(BrokerError::InvalidDeviceTree)?,
+            strings: slice(strings_off, strings_size).ok_or(BrokerError::InvalidDeviceTree)?,
+        })
+    }
+
+    fn tokens(&self) -> Tokens {
+        Tokens {
+            structure: self.structure,
+            strings: self.strings,
+            offset: 0,
+            done: false,
+        }
+    }
+}
+
+/// Structure block token
+enum Token {
+    BeginNode(&'static str),
+    Property(&'static str, &'static [u8]),
+    EndNode,
+}
+
+/// Iterator over the structure block, skipping NOPs
+struct Tokens {
+    structure: &'static [u8],
+    strings: &'static [u8],
+    offset: usize,
+    done: bool,
+}
+
+impl Tokens {
+    fn cell(&mut self) -> Result<u32> {
+        let value = be32(&self.structure[self.offset..], 0).ok_or(BrokerError::InvalidDeviceTree)?;
+        self.offset += 4;
+        Ok(value)
+    }
+
+    /// NUL-terminated string at `offset` of `data`
+    fn c_str(data: &'static [u8], offset: usize) -> Result<&'static str> {
+        let rest = data.get(offset..).ok_or(BrokerError::InvalidDeviceTree)?;
+        let len = rest.iter().position(|&b| b == 0).ok_or(BrokerError::InvalidDeviceTree)?;
+        core::str::from_utf8(&rest[..len]).map_err(|_| BrokerError::InvalidDeviceTree)
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>> {
+        loop {
+            match self.cell()? {
+                FDT_BEGIN_NODE => {
+                    let name = Self::c_str(self.structure, self.offset)?;
+                    self.offset = (self.offset + name.len() + 1).next_multiple_of(4);
+                    return Ok(Some(Token::BeginNode(name)));
+                }
+                FDT_PROP => {
+                    let len = self.cell()? as usize;
+                    let name_off = self.cell()? as usize;
+                    let value = self
+                        .structure
+                        .get(self.offset..self.offset + len)
+                        .ok_or(BrokerError::InvalidDeviceTree)?;
+                    self.offset = (self.offset + len).next_multiple_of(4);
+                    return Ok(Some(Token::Property(Self::c_str(self.strings, name_off)?, value)));
+                }
+                FDT_END_NODE => return Ok(Some(Token::EndNode)),
+                FDT_NOP => {}
+                FDT_END => return Ok(None),
+                _ => return Err(BrokerError::InvalidDeviceTree),
+            }
+        }
+    }
+}
+
+impl Iterator for Tokens {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let token = self.next_token().transpose();
+        self.done = !matches!(token, Some(Ok(_)));
+        token
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::vec;
+
+    /// Builds a flattened device tree blob
+    pub(crate) struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        pub(crate) fn new() -> Self {
+            Self { structure: vec![], strings: vec![] }
+        }
+
+        fn cell(&mut self, value: u32) {
+            self.structure.extend_from_slice(&value.to_be_bytes());
+        }
+
+        fn pad(&mut self) {
+            while self.structure.len() % 4 != 0 {
+                self.structure.push(0);
+            }
+        }
+
+        pub(crate) fn begin(&mut self, name: &str) {
+            self.cell(FDT_BEGIN_NODE);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+        }
+
+        pub(crate) fn prop(&mut self, name: &str, value: &[u8]) {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            self.cell(FDT_PROP);
+            self.cell(value.len() as u32);
+            self.cell(name_off);
+            self.structure.extend_from_slice(value);
+            self.pad();
+        }
+
+        pub(crate) fn cells(&mut self, name: &str, cells: &[u32]) {
+            let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+            self.prop(name, &value);
+        }
+
+        pub(crate) fn end(&mut self) {
+            self.cell(FDT_END_NODE);
+        }
+
+        pub(crate) fn finish(mut self) -> &'static [u8] {
+            self.cell(FDT_END);
+            let struct_off = 40;
+            let strings_off = struct_off + self.structure.len();
+            let total = strings_off + self.strings.len();
+            let header = [
+                FDT_MAGIC, total as u32, struct_off as u32, strings_off as u32, 0, 17, 16, 0,
+                self.strings.len() as u32, self.structure.len() as u32,
+            ];
+            let mut blob: Vec<u8> = header.iter().flat_map(|c| c.to_be_bytes()).collect();
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            Box::leak(blob.into_boxed_slice())
+        }
+    }
+
+    #[test]
+    fn test_parse_devices() {
+        let mut b = Builder { structure: vec![], strings: vec![] };
+        b.begin("");
+        b.cells("#address-cells", &[2]);
+        b.cells("#size-cells", &[2]);
+        b.cells("interrupt-parent", &[1]);
+
+        b.begin("intc@8000000");
+        b.prop("compatible", b"arm,cortex-a15-gic\0");
+        b.prop("interrupt-controller", b"");
+        b.cells("#interrupt-cells", &[3]);
+        b.cells("phandle", &[1]);
+        b.cells("reg", &[0, 0x0800_0000, 0, 0x10000, 0, 0x0801_0000, 0, 0x10000]);
+        b.end();
+
+        b.begin("pl011@9000000");
+        b.prop("compatible", b"arm,pl011\0arm,primecell\0");
+        b.cells("reg", &[0, 0x0900_0000, 0, 0x1000]);
+        b.cells("interrupts", &[GIC_SPI, 1, 4]);
+        b.end();
+
+        b.begin("memory@40000000");
+        b.prop("device_type", b"memory\0");
+        b.cells("reg", &[0, 0x4000_0000, 0, 0x800_0000]);
+        b.end();
+
+        // A bus at 0xfe000000 with 32-bit child addresses
+        b.begin("soc");
+        b.cells("#address-cells", &[1]);
+        b.cells("#size-cells", &[1]);
+        b.cells("ranges", &[0x7e00_0000, 0, 0xfe00_0000, 0x180_0000]);
+
+        b.begin("serial@7e201000");
+        b.prop("compatible", b"arm,pl011\0");
+        b.cells("reg", &[0x7e20_1000, 0x200]);
+        b.cells("interrupts", &[GIC_SPI, 121, 4]);
+        b.end();
+
+        b.begin("gpio@7e200000");
+        b.prop("status", b"disabled\0");
+        b.cells("reg", &[0x7e20_0000, 0x100]);
+        b.end();
+        b.end();
+
+        b.begin("timer");
+        b.cells("interrupts", &[GIC_PPI, 13, 4, GIC_PPI, 14, 4]);
+        b.end();
+        b.end();
+
+        let tree = DeviceTree::parse(b.finish()).unwrap();
+        let names: Vec<_> = tree.entries().iter().map(|e| e.name).collect();
+        assert_eq!(names, ["intc@8000000", "pl011@9000000", "serial@7e201000", "timer"]);
+
+        let uart = tree.find("pl011").unwrap();
+        assert_eq!(uart.mmio, [MmioRange { base: 0x0900_0000, size: 0x1000 }]);
+        assert_eq!(uart.irqs, [33]);
+        assert!(uart.is_compatible("arm,primecell"));
+
+        let serial = tree.find("serial@7e201000").unwrap();
+        assert_eq!(serial.mmio, [MmioRange { base: 0xfe20_1000, size: 0x200 }]);
+        assert_eq!(serial.irqs, [153]);
+
+        assert_eq!(tree.find("timer").unwrap().irqs, [29, 30]);
+        assert_eq!(tree.find("intc").unwrap().mmio.len(), 2);
+        assert_eq!(tree.find_compatible("arm,pl011").count(), 2);
+        assert!(tree.find("gpio").is_none());
+    }
+
+    #[test]
+    fn test_interrupt_map() {
+        let mut b = Builder { structure: vec![], strings: vec![] };
+        b.begin("");
+        b.cells("#address-cells", &[2]);
+        b.cells("#size-cells", &[2]);
+        b.cells("interrupt-parent", &[1]);
+
+        b.begin("intc@8000000");
+        b.prop("compatible", b"arm,cortex-a15-gic\0");
+        b.prop("interrupt-controller", b"");
+        b.cells("#interrupt-cells", &[3]);
+        b.cells("#address-cells", &[2]);
+        b.cells("phandle", &[1]);
+        b.cells("reg", &[0, 0x0800_0000, 0, 0x10000]);
+        b.end();
+
+        // INTA of slots 0 and 1, GIC parent unit address of two cells
+        b.begin("pcie@10000000");
+        b.prop("compatible", b"pci-host-ecam-generic\0");
+        b.cells("#address-cells", &[3]);
+        b.cells("#size-cells", &[2]);
+        b.cells("#interrupt-cells", &[1]);
+        b.cells("reg", &[0x40, 0x1000_0000, 0, 0x1000_0000]);
+        b.cells("interrupt-map-mask", &[0x1800, 0, 0, 7]);
+        b.cells("interrupt-map", &[
+            0x0000, 0, 0, 1, 1, 0, 0, GIC_SPI, 3, 4,
+            0x0800, 0, 0, 1, 1, 0, 0, GIC_SPI, 4, 4,
+        ]);
+        b.end();
+        b.end();
+
+        let tree = DeviceTree::parse(b.finish()).unwrap();
+        let host = tree.find("pcie").unwrap();
+        assert_eq!(host.cell_property("#interrupt-cells", 0), 1);
+        assert_eq!(host.parent_address_cells(), 2);
+
+        // Bus 0 device 1 function 2: the function bits are masked off
+        assert_eq!(tree.map_interrupt(host, &[(1 << 11) | (2 << 8), 0, 0], &[1]), Some(36));
+        assert_eq!(tree.map_interrupt(host, &[0, 0, 0], &[1]), Some(35));
+        assert_eq!(tree.map_interrupt(host, &[0, 0, 0], &[2]), None);
+        assert_eq!(tree.map_interrupt(host, &[0, 0], &[1]), None);
+    }
+
+    #[test]
+    fn test_phandle_lists() {
+        let mut b = Builder { structure: vec![], strings: vec![] };
+        b.begin("");
+        b.cells("#address-cells", &[1]);
+        b.cells("#size-cells", &[1]);
+
+        b.begin("osc24m");
+        b.prop("compatible", b"fixed-clock\0");
+        b.cells("#clock-cells", &[0]);
+        b.cells("phandle", &[1]);
+        b.end();
+
+        b.begin("clock-controller@1c20000");
+        b.cells("reg", &[0x01c2_0000, 0x400]);
+        b.cells("#clock-cells", &[1]);
+        b.cells("#reset-cells", &[1]);
+        b.cells("phandle", &[2]);
+        b.end();
+
+        b.begin("serial@1c28000");
+        b.cells("reg", &[0x01c2_8000, 0x400]);
+        b.cells("clocks", &[2, 62, 1]);
+        b.cells("resets", &[2, 49]);
+        b.cells("power-domains", &[3, 0]);
+        b.end();
+        b.end();
+
+        let tree = DeviceTree::parse(b.finish()).unwrap();
+        let uart = tree.find("serial").unwrap();
+        let clocks = tree.specifiers(uart, "clocks", "#clock-cells").unwrap();
+        assert_eq!(
+            clocks,
+            [
+                Specifier { provider: 2, cells: vec![62] },
+                Specifier { provider: 1, cells: vec![] },
+            ]
+        );
+        assert_eq!(
+            tree.specifiers(uart, "resets", "#reset-cells").unwrap(),
+            [Specifier { provider: 2, cells: vec![49] }]
+        );
+        assert!(tree.provider_is_compatible(1, "fixed-clock"));
+        assert!(!tree.provider_is_compatible(2, "fixed-clock"));
+
+        // No such provider, no such property
+        assert_eq!(tree.specifiers(uart, "power-domains", "#power-domain-cells"), None);
+        assert_eq!(tree.specifiers(uart, "dmas", "#dma-cells"), None);
+    }
+}
@@ -0,0 +1,273 @@
+//! DMA Pool
+//!
+//! Hands out DMA buffers from one physically contiguous region. Free space
+//! is kept as a sorted list of blocks, first-fit on allocation and merged
+//! with its neighbours on free, so drivers that recycle buffers (network
+//! RX rings) can run indefinitely without exhausting their pool.
+
+use alloc::vec::Vec;
+
+use crate::{BrokerError, Result};
+
+/// Allocation granule: buffers are rounded up to, and aligned to at least,
+/// a cache line so no two buffers share one
+pub const DMA_GRANULE: usize = 64;
+
+/// A buffer allocated from a [`DmaPool`]
+///
+/// Not `Clone`: hand it back to [`DmaPool::free`] exactly once.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DmaRegion {
+    /// Physical address
+    pub phys_addr: usize,
+    /// Address to program into the device: an IOVA behind an IOMMU, the
+    /// physical address otherwise
+    pub bus_addr: usize,
+    /// Where the driver sees the buffer
+    pub vaddr: usize,
+    /// Size in bytes (rounded up to `DMA_GRANULE`)
+    pub size: usize,
+}
+
+/// A free block, as an offset into the pool
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    offset: usize,
+    size: usize,
+}
+
+/// DMA Pool
+#[derive(Debug)]
+pub struct DmaPool {
+    /// Physical address of the pool
+    phys_base: usize,
+    /// Bus address of the pool, as the device sees it
+    bus_base: usize,
+    /// Virtual address of the pool in the broker's address space
+    vaddr_base: usize,
+    /// Pool size in bytes
+    size: usize,
+    /// Free blocks, sorted by offset, never adjacent
+    free: Vec<Block>,
+}
+
+impl DmaPool {
+    /// Manage `size` bytes of contiguous memory at `phys_base`, mapped at
+    /// `vaddr_base` and visible to the device at `bus_base`
+    pub(crate) fn new(phys_base: usize, bus_base: usize, vaddr_base: usize, size: usize) -> Self {
+        let size = size - size % DMA_GRANULE;
+        let mut free = Vec::new();
+        if size > 0 {
+            free.push(Block { offset: 0, size });
+        }
+        Self {
+            phys_base,
+            bus_base,
+            vaddr_base,
+            size,
+            free,
+        }
+    }
+
+    /// Allocate `size` bytes aligned to `align` (a power of two; anything
+    /// below `DMA_GRANULE` gets `DMA_GRANULE`)
+    pub fn allocate(&mut self, size: usize, align: usize) -> Result<DmaRegion> {
+        if size == 0 || !align.is_power_of_two() {
+            return Err(BrokerError::InvalidCapability);
+        }
+        let size = size.next_multiple_of(DMA_GRANULE);
+        let align = align.max(DMA_GRANULE);
+
+        for index in 0..self.free.len() {
+            let block = self.free[index];
+            // Align the bus address; it is what the device sees
+            let start = (self.bus_base + block.offset).next_multiple_of(align) - self.bus_base;
+            let head = start - block.offset;
+            if head + size > block.size {
+                continue;
+            }
+
+            let tail = block.size - head - size;
+            match (head, tail) {
+                (0, 0) => {
+                    self.free.remove(index);
+                }
+                (0, _) => {
+                    self.free[index] = Block { offset: start + size, size: tail };
+                }
+                (_, 0) => {
+                    self.free[index].size = head;
+                }
+                _ => {
+                    self.free[index].size = head;
+                    self.free.insert(index + 1, Block { offset: start + size, size: tail });
+                }
+            }
+
+            return Ok(DmaRegion {
+                phys_addr: self.phys_base + start,
+                bus_addr: self.bus_base + start,
+                vaddr: self.vaddr_base + start,
+                size,
+            });
+        }
+
+        Err(BrokerError::OutOfMemory)
+    }
+
+    /// Return a buffer to the pool
+    ///
+    /// Fails with `InvalidCapability` for a region that did not come from
+    /// this pool or overlaps free space (a double free).
+    pub fn free(&mut self, region: DmaRegion) -> Result<()> {
+        let offset = region.phys_addr.wrapping_sub(self.phys_base);
+        if region.size == 0
+            || !offset.is_multiple_of(DMA_GRANULE)
+            || !region.size.is_multiple_of(DMA_GRANULE)
+            || offset.checked_add(region.size).is_none_or(|end| end > self.size)
+            || region.bus_addr != self.bus_base + offset
+            || region.vaddr != self.vaddr_base + offset
+        {
+            return Err(BrokerError::InvalidCapability);
+        }
+
+        let index = self.free.partition_point(|b| b.offset < offset);
+        let overlaps_prev = index > 0 && {
+            let prev = self.free[index - 1];
+            prev.offset + prev.size > offset
+        };
+        let overlaps_next = self
+            .free
+            .get(index)
+            .is_some_and(|next| offset + region.size > next.offset);
+        if overlaps_prev || overlaps_next {
+            return Err(BrokerError::InvalidCapability);
+        }
+
+        let merge_prev = index > 0 && {
+            let prev = self.free[index - 1];
+            prev.offset + prev.size == offset
+        };
+        let merge_next = self
+            .free
+            .get(index)
+            .is_some_and(|next| offset + region.size == next.offset);
+
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                let next = self.free.remove(index);
+                self.free[index - 1].size += region.size + next.size;
+            }
+            (true, false) => self.free[index - 1].size += region.size,
+            (false, true) => {
+                self.free[index].offset = offset;
+                self.free[index].size += region.size;
+            }
+            (false, false) => self.free.insert(index, Block { offset, size: region.size }),
+        }
+        Ok(())
+    }
+
+    /// Free bytes in the pool
+    pub fn available(&self) -> usize {
+        self.free.iter().map(|b| b.size).sum()
+    }
+
+    /// Largest buffer that can currently be allocated (at `DMA_GRANULE`
+    /// alignment)
+    pub fn largest_free(&self) -> usize {
+        self.free.iter().map(|b| b.size).max().unwrap_or(0)
+    }
+
+    /// Pool size in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Physical address of the pool
+    pub fn phys_base(&self) -> usize {
+        self.phys_base
+    }
+
+    /// Bus address of the pool, as the device sees it
+    pub fn bus_base(&self) -> usize {
+        self.bus_base
+    }
+
+    /// Virtual address of the pool in the broker's address space
+    pub fn vaddr_base(&self) -> usize {
+        self.vaddr_base
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHYS: usize = 0x4000_0000;
+    const VIRT: usize = 0x8000_0000;
+
+    #[test]
+    fn test_allocate_and_free() {
+        let mut pool = DmaPool::new(PHYS, PHYS, VIRT, 0x4000);
+
+        let a = pool.allocate(100, 1).unwrap();
+        assert_eq!((a.phys_addr, a.vaddr, a.size), (PHYS, VIRT, 128));
+        let b = pool.allocate(0x1000, 0x1000).unwrap();
+        assert_eq!(b.phys_addr, PHYS + 0x1000);
+        assert_eq!(pool.available(), 0x4000 - 128 - 0x1000);
+
+        // The alignment gap before `b` is still usable
+        let c = pool.allocate(64, 64).unwrap();
+        assert_eq!(c.phys_addr, PHYS + 128);
+
+        pool.free(b).unwrap();
+        pool.free(a).unwrap();
+        pool.free(c).unwrap();
+        assert_eq!(pool.available(), 0x4000);
+        assert_eq!(pool.largest_free(), 0x4000);
+    }
+
+    #[test]
+    fn test_bus_addresses() {
+        // Behind an IOMMU: alignment is of the IOVA, not the physical address
+        let iova = 0x4000_0000;
+        let mut pool = DmaPool::new(PHYS + 0x1000, iova, VIRT, 0x4000);
+
+        let a = pool.allocate(64, 64).unwrap();
+        let b = pool.allocate(64, 0x2000).unwrap();
+        assert_eq!((a.bus_addr, a.phys_addr), (iova, PHYS + 0x1000));
+        assert_eq!((b.bus_addr, b.phys_addr, b.vaddr), (iova + 0x2000, PHYS + 0x3000, VIRT + 0x2000));
+    }
+
+    #[test]
+    fn test_churn_does_not_exhaust() {
+        let mut pool = DmaPool::new(PHYS, PHYS, VIRT, 0x2000);
+
+        for _ in 0..10_000 {
+            let rx: Vec<_> = (0..4).map(|_| pool.allocate(2048, 64).unwrap()).collect();
+            assert!(pool.allocate(64, 64).is_err());
+            rx.into_iter().rev().for_each(|r| pool.free(r).unwrap());
+        }
+        assert_eq!(pool.largest_free(), 0x2000);
+    }
+
+    #[test]
+    fn test_bad_free() {
+        let mut pool = DmaPool::new(PHYS, PHYS, VIRT, 0x1000);
+        let a = pool.allocate(64, 64).unwrap();
+
+        let double = DmaRegion { ..a };
+        pool.free(a).unwrap();
+        assert_eq!(pool.free(double), Err(BrokerError::InvalidCapability));
+
+        let foreign = DmaRegion {
+            phys_addr: PHYS + 0x1000,
+            bus_addr: PHYS + 0x1000,
+            vaddr: VIRT + 0x1000,
+            size: 64,
+        };
+        assert_eq!(pool.free(foreign), Err(BrokerError::InvalidCapability));
+        assert_eq!(pool.available(), 0x1000);
+    }
+}
@@ -7,7 +7,7 @@ use crate::Result;
 /// IPC Endpoint
 ///
 /// Represents an IPC endpoint for communication between components.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Endpoint {
     /// Capability slot for this endpoint
     pub cap_slot: usize,
@@ -159,19 +159,17 @@ impl EndpointManager {
 
     /// Create a new IPC endpoint
     ///
-    /// Allocates a capability slot and creates an endpoint in the kernel.
-    pub(crate) fn create_endpoint(&mut self, cap_slot: usize) -> Result<Endpoint> {
+    /// The kernel creates the endpoint and picks the capability slot.
+    pub(crate) fn create_endpoint(&mut self) -> Result<Endpoint> {
         // Make syscall to kernel to create IPC endpoint
         let result_slot = unsafe {
             let mut slot: usize;
             core::arch::asm!(
                 "mov x8, {syscall_num}",
                 "svc #0",
-                "mov {result}, x0",
                 syscall_num = in(reg) 0x13u64, // SYS_ENDPOINT_CREATE
-                result = out(reg) slot,
+                inlateout("x0") 0usize => slot, // a new slot
                 out("x8") _,
-                out("x0") _,
             );
             slot
         };
@@ -184,6 +182,31 @@ impl EndpointManager {
         let id = self.next_endpoint_id;
         self.next_endpoint_id += 1;
 
-        Ok(Endpoint { cap_slot, id })
+        Ok(Endpoint {
+            cap_slot: result_slot,
+            id,
+        })
+    }
+}
+
+/// Create a notification object
+///
+/// The kernel picks the capability slot, which is returned.
+pub(crate) fn create_notification() -> Result<usize> {
+    let slot: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) 0x17u64, // SYS_NOTIFICATION_CREATE
+            inlateout("x0") 0usize => slot, // a new slot
+            out("x8") _,
+        );
+    }
+
+    if slot == usize::MAX {
+        Err(crate::BrokerError::SyscallFailed(slot))
+    } else {
+        Ok(slot)
     }
 }
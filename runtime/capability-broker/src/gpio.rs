@@ -0,0 +1,240 @@
+//! GPIO Lines and Pin Multiplexing
+//!
+//! Besides its registers and interrupt, a platform device often needs
+//! pins: a card-detect line (`cd-gpios`), a chip select (`cs-gpios`), or
+//! its signals muxed out to the package (`pinctrl-0`). These are taken
+//! from the device tree into the device's [`DeviceResource`] and held by
+//! its claim, so two drivers cannot drive the same line or mux the same
+//! pin to different functions; a second claim fails with
+//! `ResourceInUse`.
+//!
+//! The broker does not program GPIO or pin controllers itself, that is
+//! up to the driver holding the pins. Pins are named as in the generic
+//! pinctrl binding (`pins`, `groups`, `function`); vendor encodings such
+//! as `pinmux` or `fsl,pins` are handed over in the raw but not checked
+//! for conflicts.
+//!
+//! [`DeviceResource`]: crate::DeviceResource
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::device_tree::{DeviceEntry, DeviceTree};
+use crate::{BrokerError, Result};
+
+/// `GPIO_ACTIVE_LOW` in the flags cell
+const GPIO_ACTIVE_LOW: u32 = 1 << 0;
+
+/// A GPIO line used by a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpioLine {
+    /// What the line is for: `cd` for `cd-gpios`, empty for `gpios`
+    pub name: &'static str,
+    /// Phandle of the GPIO controller
+    pub controller: u32,
+    /// Specifier cells, as many as the controller's `#gpio-cells`
+    pub cells: Vec<u32>,
+}
+
+impl GpioLine {
+    /// Cells naming the line on its controller (all but the flags cell)
+    pub fn line(&self) -> &[u32] {
+        match self.cells.len() {
+            0 | 1 => &self.cells,
+            n => &self.cells[..n - 1],
+        }
+    }
+
+    /// Flags cell, 0 if the controller has none
+    pub fn flags(&self) -> u32 {
+        if self.cells.len() < 2 {
+            return 0;
+        }
+        self.cells.last().copied().unwrap_or(0)
+    }
+
+    /// Whether the line is asserted low
+    pub fn active_low(&self) -> bool {
+        self.flags() & GPIO_ACTIVE_LOW != 0
+    }
+}
+
+/// A pin configuration of a device, from `pinctrl-N`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinGroup {
+    /// State it belongs to (`default`, `sleep`, ...; empty without
+    /// `pinctrl-names`)
+    pub state: &'static str,
+    /// Phandle of the pin controller
+    pub controller: u32,
+    /// Phandle of the configuration node
+    pub config: u32,
+    /// Pins (`pins`) or pin groups (`groups`) it muxes
+    pub pins: Vec<&'static str>,
+    /// Function the pins are switched to, if named
+    pub function: Option<&'static str>,
+}
+
+impl PinGroup {
+    /// Raw value of a property of the configuration node, for vendor
+    /// bindings (`pinmux`, `drive-strength`, ...)
+    pub fn property(&self, tree: &DeviceTree, name: &str) -> Option<&'static [u8]> {
+        tree.provider_property(self.config, name)
+    }
+}
+
+/// A pin as far as conflicts go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Pin {
+    /// GPIO line: controller and the cells naming the line
+    Line(u32, Vec<u32>),
+    /// Pin or pin group of a pin controller, by name
+    Mux(u32, &'static str),
+}
+
+/// GPIO lines of `entry` (`gpios` and `*-gpios`), in property order
+///
+/// Fails with `InvalidDeviceTree` if a list cannot be decoded.
+pub(crate) fn gpio_lines(tree: &DeviceTree, entry: &DeviceEntry) -> Result<Vec<GpioLine>> {
+    let mut lines = Vec::new();
+    for (property, _) in entry.properties() {
+        let name = match property.strip_suffix("gpios") {
+            Some("") => "",
+            Some(prefix) => match prefix.strip_suffix('-') {
+                Some(name) => name,
+                None => continue,
+            },
+            None => continue,
+        };
+        let specifiers = tree
+            .specifiers(entry, property, "#gpio-cells")
+            .ok_or(BrokerError::InvalidDeviceTree)?;
+        lines.extend(specifiers.into_iter().map(|s| GpioLine {
+            name,
+            controller: s.provider,
+            cells: s.cells,
+        }));
+    }
+    Ok(lines)
+}
+
+/// Pin configurations of `entry`, state by state (`pinctrl-0`, `pinctrl-1`, ...)
+///
+/// Fails with `InvalidDeviceTree` if a state refers to a node that is not
+/// under a pin controller.
+pub(crate) fn pin_groups(tree: &DeviceTree, entry: &DeviceEntry) -> Result<Vec<PinGroup>> {
+    let names: Vec<&'static str> = entry.property("pinctrl-names").map(strings).unwrap_or_default();
+    let mut groups = Vec::new();
+    for state in 0.. {
+        let Some(configs) = entry.property(&format!("pinctrl-{state}")) else {
+            break;
+        };
+        for config in configs.as_chunks::<4>().0 {
+            let config = u32::from_be_bytes(*config);
+            let controller = tree.provider_parent(config).ok_or(BrokerError::InvalidDeviceTree)?;
+            let named = |property| tree.provider_property(config, property).map(strings);
+            groups.push(PinGroup {
+                state: names.get(state).copied().unwrap_or(""),
+                controller,
+                config,
+                pins: named("pins").or_else(|| named("groups")).unwrap_or_default(),
+                function: named("function").and_then(|f| f.first().copied()),
+            });
+        }
+    }
+    Ok(groups)
+}
+
+/// Everything `gpios` and `pinmux` hold, once each
+pub(crate) fn pins(gpios: &[GpioLine], pinmux: &[PinGroup]) -> Vec<Pin> {
+    let lines = gpios.iter().map(|g| Pin::Line(g.controller, g.line().into()));
+    let muxed = pinmux
+        .iter()
+        .flat_map(|g| g.pins.iter().map(|&pin| Pin::Mux(g.controller, pin)));
+
+    let mut pins = Vec::new();
+    for pin in lines.chain(muxed) {
+        if !pins.contains(&pin) {
+            pins.push(pin);
+        }
+    }
+    pins
+}
+
+/// NUL-separated strings of a string-list property
+fn strings(value: &'static [u8]) -> Vec<&'static str> {
+    value
+        .split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| core::str::from_utf8(s).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_tree::tests::Builder;
+    use alloc::vec;
+
+    #[test]
+    fn test_gpios_and_pinmux() {
+        let mut b = Builder::new();
+        b.begin("");
+        b.cells("#address-cells", &[1]);
+        b.cells("#size-cells", &[1]);
+
+        b.begin("pinctrl@1c20800");
+        b.cells("reg", &[0x01c2_0800, 0x400]);
+        b.prop("gpio-controller", &[]);
+        b.cells("#gpio-cells", &[3]);
+        b.cells("phandle", &[1]);
+        b.begin("mmc0-pins");
+        b.prop("pins", b"PF0\0PF1\0PF2\0");
+        b.prop("function", b"mmc0\0");
+        b.cells("phandle", &[2]);
+        b.end();
+        b.begin("mmc0-sleep");
+        b.prop("pins", b"PF0\0PF1\0PF2\0");
+        b.prop("function", b"gpio_in\0");
+        b.cells("phandle", &[3]);
+        b.end();
+        b.end();
+
+        b.begin("mmc@1c0f000");
+        b.cells("reg", &[0x01c0_f000, 0x1000]);
+        b.prop("pinctrl-names", b"default\0sleep\0");
+        b.cells("pinctrl-0", &[2]);
+        b.cells("pinctrl-1", &[3]);
+        b.cells("cd-gpios", &[1, 5, 6, GPIO_ACTIVE_LOW]);
+        b.end();
+
+        b.begin("spi@1c68000");
+        b.cells("reg", &[0x01c6_8000, 0x1000]);
+        b.cells("cs-gpios", &[1, 2, 3, 0, 1, 5, 6, 0]);
+        b.prop("gpio-ranges", &[]);
+        b.end();
+        b.end();
+
+        let tree = DeviceTree::parse(b.finish()).unwrap();
+        let mmc = tree.find("mmc").unwrap();
+        let cd = GpioLine { name: "cd", controller: 1, cells: vec![5, 6, GPIO_ACTIVE_LOW] };
+        assert_eq!(gpio_lines(&tree, mmc).unwrap(), [cd.clone()]);
+        assert_eq!((cd.line(), cd.active_low()), (&[5, 6][..], true));
+
+        let groups = pin_groups(&tree, mmc).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!((groups[0].state, groups[0].controller), ("default", 1));
+        assert_eq!(groups[0].pins, ["PF0", "PF1", "PF2"]);
+        assert_eq!((groups[1].state, groups[1].function), ("sleep", Some("gpio_in")));
+
+        // Both states mux the same pins, which count once
+        let mmc_pins = pins(&[cd], &groups);
+        assert_eq!(mmc_pins.len(), 4);
+
+        // The SPI controller's second chip select is the card-detect line
+        let spi = tree.find("spi").unwrap();
+        let cs = gpio_lines(&tree, spi).unwrap();
+        assert_eq!(cs.len(), 2);
+        assert!(pins(&cs, &[]).iter().any(|p| mmc_pins.contains(p)));
+    }
+}
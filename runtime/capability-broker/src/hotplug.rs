@@ -0,0 +1,463 @@
+//! Hot-plug Events
+//!
+//! Devices that come and go after boot (PCIe or virtio hot-plug, USB
+//! attach) are reported to the broker by the bus driver that notices them
+//! and passed on to every subscriber, so drivers can bind when their
+//! device shows up instead of only at boot.
+//!
+//! Each subscriber has a page of shared memory holding a ring of event
+//! records, and a notification the broker signals with the subscriber's
+//! badge after writing one. The broker never waits for a subscriber: one
+//! that falls a whole ring behind loses the oldest events, and its
+//! [`HotplugReader`] counts them.
+//!
+//! Ring layout (little-endian):
+//!
+//! | Offset | Contents                                             |
+//! |--------|------------------------------------------------------|
+//! | 0      | `head`: sequence number of the newest record (u64)   |
+//! | 8      | `capacity`: number of record slots (u64)             |
+//! | 64     | `capacity` records of 64 bytes                       |
+//!
+//! Records are numbered from 1; record `seq` lives in slot
+//! `(seq - 1) % capacity`. A record is, at byte offsets: `seq` (u64, 0),
+//! `kind` (u32, 8: 1 added, 2 removed), `bus` (u32, 12: 1 PCI, 2 virtio,
+//! 3 USB, 4 platform), `location` (u64, 16), `vendor_id` (u16, 24),
+//! `device_id` (u16, 26), `class` (u32, 28), `mmio_base` (u64, 32),
+//! `mmio_size` (u64, 40) and `irq` (u32, 48, `u32::MAX` for none). The
+//! broker clears `seq` before rewriting a slot and sets it last, so a
+//! record whose `seq` reads the same before and after copying it is
+//! whole.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{Ordering, fence};
+
+use crate::pci::PciFunction;
+use crate::{BrokerError, Result};
+
+/// Size of a subscriber's ring
+pub const RING_SIZE: usize = 4096;
+
+/// Offset of the first record
+const RECORDS: usize = 64;
+
+/// Size of a record
+const RECORD_SIZE: usize = core::mem::size_of::<Record>();
+
+const _: () = assert!(RECORD_SIZE == 64);
+
+/// What happened to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugKind {
+    /// The device appeared
+    Added = 1,
+    /// The device went away
+    Removed = 2,
+}
+
+/// Bus a device sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugBus {
+    /// PCI or PCIe
+    Pci = 1,
+    /// virtio-mmio
+    Virtio = 2,
+    /// USB
+    Usb = 3,
+    /// Other memory-mapped device
+    Platform = 4,
+}
+
+/// A device appearing or going away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotplugEvent {
+    /// What happened
+    pub kind: HotplugKind,
+    /// Bus the device sits on
+    pub bus: HotplugBus,
+    /// Where on the bus: bus/device/function (`bus << 8 | dev << 3 | fn`)
+    /// for PCI, hub port path for USB, MMIO base otherwise
+    pub location: u64,
+    /// Vendor ID (PCI, USB) or virtio device ID
+    pub vendor_id: u16,
+    /// Device ID
+    pub device_id: u16,
+    /// Class code (PCI class/subclass/interface, USB class)
+    pub class: u32,
+    /// MMIO base address (physical), 0 if none
+    pub mmio_base: u64,
+    /// MMIO size in bytes
+    pub mmio_size: u64,
+    /// IRQ number, if the device has one
+    pub irq: Option<u32>,
+}
+
+impl HotplugEvent {
+    /// Event for a PCI function, with its first memory BAR
+    pub fn pci(kind: HotplugKind, function: &PciFunction) -> Self {
+        let bar = function.first_bar();
+        Self {
+            kind,
+            bus: HotplugBus::Pci,
+            location: ((function.bus as u64) << 8)
+                | ((function.device as u64) << 3)
+                | function.function as u64,
+            vendor_id: function.vendor_id,
+            device_id: function.device_id,
+            class: function.class,
+            mmio_base: bar.map_or(0, |b| b.base),
+            mmio_size: bar.map_or(0, |b| b.size),
+            irq: function.irq,
+        }
+    }
+}
+
+/// A record as laid out in the ring
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Record {
+    seq: u64,
+    kind: u32,
+    bus: u32,
+    location: u64,
+    vendor_id: u16,
+    device_id: u16,
+    class: u32,
+    mmio_base: u64,
+    mmio_size: u64,
+    irq: u32,
+    reserved: [u32; 3],
+}
+
+impl Record {
+    fn new(seq: u64, event: &HotplugEvent) -> Self {
+        Self {
+            seq,
+            kind: event.kind as u32,
+            bus: event.bus as u32,
+            location: event.location,
+            vendor_id: event.vendor_id,
+            device_id: event.device_id,
+            class: event.class,
+            mmio_base: event.mmio_base,
+            mmio_size: event.mmio_size,
+            irq: event.irq.unwrap_or(u32::MAX),
+            reserved: [0; 3],
+        }
+    }
+
+    /// The event, or `None` for unknown kinds or buses
+    fn event(&self) -> Option<HotplugEvent> {
+        let kind = match self.kind {
+            1 => HotplugKind::Added,
+            2 => HotplugKind::Removed,
+            _ => return None,
+        };
+        let bus = match self.bus {
+            1 => HotplugBus::Pci,
+            2 => HotplugBus::Virtio,
+            3 => HotplugBus::Usb,
+            4 => HotplugBus::Platform,
+            _ => return None,
+        };
+        Some(HotplugEvent {
+            kind,
+            bus,
+            location: self.location,
+            vendor_id: self.vendor_id,
+            device_id: self.device_id,
+            class: self.class,
+            mmio_base: self.mmio_base,
+            mmio_size: self.mmio_size,
+            irq: (self.irq != u32::MAX).then_some(self.irq),
+        })
+    }
+}
+
+/// Pointers into a ring at `vaddr`
+fn head_ptr(vaddr: usize) -> *mut u64 {
+    vaddr as *mut u64
+}
+
+fn capacity_ptr(vaddr: usize) -> *mut u64 {
+    (vaddr + 8) as *mut u64
+}
+
+fn slot_ptr(vaddr: usize, capacity: u64, seq: u64) -> *mut Record {
+    (vaddr + RECORDS + ((seq - 1) % capacity) as usize * RECORD_SIZE) as *mut Record
+}
+
+/// The broker's side of a ring
+struct EventRing {
+    vaddr: usize,
+    capacity: u64,
+    head: u64,
+}
+
+impl EventRing {
+    /// Lay out an empty ring in `size` bytes at `vaddr`
+    ///
+    /// # Safety
+    ///
+    /// `vaddr` must be mapped writable for `size` bytes, 8-byte aligned,
+    /// and used for nothing else while the ring lives.
+    unsafe fn init(vaddr: usize, size: usize) -> Self {
+        let capacity = (size.saturating_sub(RECORDS) / RECORD_SIZE) as u64;
+        unsafe {
+            core::ptr::write_bytes(vaddr as *mut u8, 0, size);
+            core::ptr::write_volatile(capacity_ptr(vaddr), capacity);
+        }
+        Self { vaddr, capacity, head: 0 }
+    }
+
+    /// Append an event, overwriting the oldest record once the ring is full
+    fn push(&mut self, event: &HotplugEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        let seq = self.head + 1;
+        let record = slot_ptr(self.vaddr, self.capacity, seq);
+        unsafe {
+            core::ptr::write_volatile(&raw mut (*record).seq, 0);
+            fence(Ordering::Release);
+            core::ptr::write_volatile(record, Record { seq: 0, ..Record::new(seq, event) });
+            fence(Ordering::Release);
+            core::ptr::write_volatile(&raw mut (*record).seq, seq);
+            fence(Ordering::Release);
+            core::ptr::write_volatile(head_ptr(self.vaddr), seq);
+        }
+        self.head = seq;
+    }
+}
+
+/// Reads events from a subscription's ring
+///
+/// Starts from the oldest record still in the ring.
+pub struct HotplugReader {
+    vaddr: usize,
+    next: u64,
+    missed: u64,
+}
+
+impl HotplugReader {
+    /// Read the ring mapped at `vaddr`
+    ///
+    /// # Safety
+    ///
+    /// `vaddr` must map a subscription's ring (at least readable) for as
+    /// long as the reader is used.
+    pub unsafe fn new(vaddr: usize) -> Self {
+        Self {
+            vaddr,
+            next: 1,
+            missed: 0,
+        }
+    }
+
+    /// The next event, or `None` once the reader has caught up
+    ///
+    /// Call until `None` after each signal: one signal can stand for
+    /// several events.
+    pub fn next_event(&mut self) -> Option<HotplugEvent> {
+        loop {
+            let (head, capacity) = unsafe {
+                let head = core::ptr::read_volatile(head_ptr(self.vaddr));
+                (head, core::ptr::read_volatile(capacity_ptr(self.vaddr)))
+            };
+            fence(Ordering::Acquire);
+            if self.next > head || capacity == 0 {
+                return None;
+            }
+
+            // Overwritten records are gone
+            let oldest = head.saturating_sub(capacity) + 1;
+            if self.next < oldest {
+                self.missed += oldest - self.next;
+                self.next = oldest;
+            }
+
+            let seq = self.next;
+            let record = slot_ptr(self.vaddr, capacity, seq);
+            let (copy, after) = unsafe {
+                let copy = core::ptr::read_volatile(record);
+                fence(Ordering::Acquire);
+                (copy, core::ptr::read_volatile(&raw const (*record).seq))
+            };
+            if copy.seq != seq || after != seq {
+                // Rewritten while we read it: start over from the new head
+                continue;
+            }
+
+            self.next += 1;
+            match copy.event() {
+                Some(event) => return Some(event),
+                None => self.missed += 1,
+            }
+        }
+    }
+
+    /// Events lost to the ring overflowing (or unreadable)
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+/// A hot-plug subscription
+///
+/// Not `Clone`: hand it back to `CapabilityBroker::unsubscribe_hotplug`
+/// once.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HotplugSubscription {
+    /// Subscription ID
+    pub id: u64,
+    /// Physical address of the ring, for mapping it into the subscriber
+    pub phys_addr: usize,
+    /// Where the ring is mapped in the broker
+    pub vaddr: usize,
+    /// Process that subscribed
+    pub owner_pid: usize,
+}
+
+/// A subscriber's state
+struct Subscriber {
+    id: u64,
+    owner_pid: usize,
+    notification_cap: usize,
+    badge: u64,
+    ring: EventRing,
+    /// Slot reserved for the ring's memory
+    memory_cap: usize,
+}
+
+/// Hot-plug subscribers
+pub(crate) struct HotplugManager {
+    subscribers: Vec<Subscriber>,
+    next_id: u64,
+}
+
+impl HotplugManager {
+    pub(crate) fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Add a subscriber whose ring is mapped at `vaddr`
+    ///
+    /// # Safety
+    ///
+    /// As for [`EventRing::init`], with `RING_SIZE` bytes.
+    pub(crate) unsafe fn subscribe(
+        &mut self,
+        vaddr: usize,
+        memory_cap: usize,
+        notification_cap: usize,
+        badge: u64,
+        owner_pid: usize,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.push(Subscriber {
+            id,
+            owner_pid,
+            notification_cap,
+            badge,
+            ring: unsafe { EventRing::init(vaddr, RING_SIZE) },
+            memory_cap,
+        });
+        id
+    }
+
+    /// Remove a subscriber, returning its ring's mapping and memory slot
+    pub(crate) fn unsubscribe(&mut self, id: u64) -> Result<(usize, usize)> {
+        let index = self
+            .subscribers
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or(BrokerError::InvalidCapability)?;
+        let subscriber = self.subscribers.swap_remove(index);
+        Ok((subscriber.ring.vaddr, subscriber.memory_cap))
+    }
+
+    /// Subscriptions held by `owner_pid`
+    pub(crate) fn subscriptions_of(&self, owner_pid: usize) -> Vec<u64> {
+        self.subscribers
+            .iter()
+            .filter(|s| s.owner_pid == owner_pid)
+            .map(|s| s.id)
+            .collect()
+    }
+
+    /// Record an event in every ring and signal the subscribers; returns
+    /// how many were signaled
+    pub(crate) fn publish(&mut self, event: &HotplugEvent) -> usize {
+        let mut signaled = 0;
+        for subscriber in &mut self.subscribers {
+            subscriber.ring.push(event);
+            if signal(subscriber.notification_cap, subscriber.badge) {
+                signaled += 1;
+            }
+        }
+        signaled
+    }
+}
+
+/// Signal a notification in the broker's CSpace
+fn signal(notification_cap: usize, badge: u64) -> bool {
+    let result: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) 0x18u64, // SYS_SIGNAL
+            inlateout("x0") notification_cap => result,
+            inlateout("x1") badge => _,
+            out("x8") _,
+        );
+    }
+    result != usize::MAX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(location: u64) -> HotplugEvent {
+        HotplugEvent {
+            kind: HotplugKind::Added,
+            bus: HotplugBus::Usb,
+            location,
+            vendor_id: 0x046d,
+            device_id: 0xc077,
+            class: 3,
+            mmio_base: 0,
+            mmio_size: 0,
+            irq: None,
+        }
+    }
+
+    #[test]
+    fn test_ring_wraps_and_counts_missed() {
+        // Header and three records
+        let mut memory = alloc::vec![0u64; (RECORDS + 3 * RECORD_SIZE) / 8];
+        let vaddr = memory.as_mut_ptr() as usize;
+        let mut ring = unsafe { EventRing::init(vaddr, memory.len() * 8) };
+        let mut reader = unsafe { HotplugReader::new(vaddr) };
+        assert_eq!(reader.next_event(), None);
+
+        ring.push(&event(1));
+        ring.push(&event(2));
+        assert_eq!(reader.next_event(), Some(event(1)));
+
+        for location in 3..=6 {
+            ring.push(&event(location));
+        }
+        // 2 was overwritten; 4, 5 and 6 remain
+        assert_eq!(reader.next_event(), Some(event(4)));
+        assert_eq!(reader.missed(), 2);
+        assert_eq!(reader.next_event(), Some(event(5)));
+        assert_eq!(reader.next_event(), Some(event(6)));
+        assert_eq!(reader.next_event(), None);
+    }
+}
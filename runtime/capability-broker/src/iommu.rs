@@ -0,0 +1,519 @@
+//! IOMMU
+//!
+//! Gives devices behind an Arm SMMUv3 (`arm,smmu-v3`) their own I/O
+//! virtual address space. A device bundle's DMA pool is mapped into it and
+//! handed out by IOVA, so a misbehaving device can reach its pool and
+//! nothing else.
+//!
+//! - Stream IDs come from `iommus` (platform devices) and the host
+//!   bridge's `iommu-map` (PCI functions, by requester ID)
+//! - Streams nobody has claimed bypass translation, as they would with the
+//!   SMMU off; a claimed stream translates through its domain, and aborts
+//!   once the device is released
+//! - Domains use stage 1: a context descriptor per domain and a 39-bit,
+//!   4 KiB-granule table in the CPU's own format
+//!
+//! SMMUv2 (`arm,mmu-500` and friends) is not driven: devices behind one
+//! keep getting physical addresses.
+
+use alloc::vec::Vec;
+
+use crate::device_tree::{DeviceEntry, DeviceTree};
+use crate::pci::PciBus;
+use crate::memory_manager::{self, MemoryType};
+use crate::{BrokerError, Result};
+
+const PAGE_SIZE: usize = 4096;
+
+/// Register offsets (page 0)
+const SMMU_IDR0: usize = 0x00;
+const SMMU_IDR1: usize = 0x04;
+const SMMU_IDR5: usize = 0x14;
+const SMMU_CR0: usize = 0x20;
+const SMMU_CR0ACK: usize = 0x24;
+const SMMU_CR1: usize = 0x28;
+const SMMU_CR2: usize = 0x2C;
+const SMMU_STRTAB_BASE: usize = 0x80;
+const SMMU_STRTAB_BASE_CFG: usize = 0x88;
+const SMMU_CMDQ_BASE: usize = 0x90;
+const SMMU_CMDQ_PROD: usize = 0x98;
+const SMMU_CMDQ_CONS: usize = 0x9C;
+
+/// IDR0 bits
+const IDR0_S1P: u32 = 1 << 1;
+const IDR0_TTF_AARCH64: u32 = 1 << 3;
+const IDR0_COHACC: u32 = 1 << 4;
+const IDR0_ASID16: u32 = 1 << 12;
+/// IDR5 bits
+const IDR5_GRAN4K: u32 = 1 << 4;
+
+/// CR0 bits
+const CR0_SMMUEN: u32 = 1 << 0;
+const CR0_CMDQEN: u32 = 1 << 3;
+/// CR1: tables and queues inner-shareable write-back (coherent SMMUs)
+const CR1_CACHEABLE: u32 = 0xD75;
+/// CR2: record invalid stream IDs, private TLB maintenance
+const CR2_RECINVSID: u32 = 1 << 1;
+const CR2_PTM: u32 = 1 << 2;
+
+/// Read-allocate hint for table and queue base registers
+const BASE_RA: u64 = 1 << 62;
+
+/// Stream table entry configs (STE word 0, bits 1-3) and bits
+const STE_V: u64 = 1 << 0;
+const STE_CFG_ABORT: u64 = 0b000 << 1;
+const STE_CFG_BYPASS: u64 = 0b100 << 1;
+const STE_CFG_S1: u64 = 0b101 << 1;
+/// Word 1: context descriptor fetches write-back, inner-shareable
+const STE_S1_CD_ATTRS: u64 = (1 << 2) | (1 << 4) | (3 << 6);
+/// Word 1: use the incoming shareability when bypassing
+const STE_SHCFG_INCOMING: u64 = 1 << 44;
+
+/// Context descriptor word 0: 39-bit input (T0SZ = 25), 4 KiB granule,
+/// write-back inner-shareable walks, TTB1 disabled, valid, AArch64,
+/// faults recorded and aborted, ASID private to the SMMU
+const CD_TCR: u64 = 25 | (1 << 8) | (1 << 10) | (3 << 12) | (1 << 30) | (1 << 31);
+const CD_FLAGS: u64 = (1 << 41) | (1 << 45) | (1 << 46) | (1 << 47);
+/// MAIR: attribute 0 is normal write-back memory
+const CD_MAIR: u64 = 0xFF;
+
+/// Stage 1 descriptors
+const PTE_TABLE: u64 = 0b11;
+const PTE_PAGE: u64 = 0b11;
+/// Read/write at any privilege, inner-shareable, accessed, never executable
+const PTE_DMA_ATTRS: u64 = (1 << 6) | (3 << 8) | (1 << 10) | (1 << 53) | (1 << 54);
+const PTE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Command opcodes
+const CMD_CFGI_STE: u64 = 0x03;
+const CMD_CFGI_ALL: u64 = 0x04;
+const CMD_TLBI_NH_ASID: u64 = 0x11;
+const CMD_TLBI_NSNH_ALL: u64 = 0x30;
+const CMD_SYNC: u64 = 0x46;
+
+/// Command queue: 256 entries of 16 bytes
+const CMDQ_LOG2: u32 = 8;
+
+/// Where IOVA allocation starts in each domain (below stays unmapped, so
+/// a device handed a null or small address faults)
+const IOVA_BASE: u64 = 1 << 30;
+/// Top of the 39-bit IOVA space
+const IOVA_END: u64 = 1 << 39;
+
+/// Polls before an acknowledgement or command counts as lost
+const POLL_LIMIT: usize = 1_000_000;
+
+/// A device's stream on an IOMMU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamId {
+    /// phandle of the IOMMU node
+    pub iommu: u32,
+    /// Stream ID on that IOMMU
+    pub id: u32,
+}
+
+/// Streams of a platform device, from `iommus` (one specifier cell each,
+/// as for SMMUv3)
+pub(crate) fn platform_streams(entry: &DeviceEntry) -> Vec<StreamId> {
+    let Some(iommus) = entry.property("iommus") else {
+        return Vec::new();
+    };
+    let cells = cells(iommus);
+    cells
+        .as_chunks::<2>()
+        .0
+        .iter()
+        .map(|&[iommu, id]| StreamId { iommu, id })
+        .collect()
+}
+
+/// Stream of a PCI function with requester ID `rid`, from the host
+/// bridge's `iommu-map` and `iommu-map-mask`
+pub(crate) fn pci_stream(host: &DeviceEntry, rid: u32) -> Option<StreamId> {
+    let map = cells(host.property("iommu-map")?);
+    let mask = host.cell_property("iommu-map-mask", u32::MAX);
+    map_rid(&map, rid & mask)
+}
+
+/// Look `rid` up in `iommu-map` entries (rid-base, phandle, sid-base,
+/// length)
+fn map_rid(map: &[u32], rid: u32) -> Option<StreamId> {
+    map.as_chunks::<4>().0.iter().find_map(|&[rid_base, iommu, sid_base, len]| {
+        (rid >= rid_base && rid - rid_base < len).then(|| StreamId {
+            iommu,
+            id: sid_base + (rid - rid_base),
+        })
+    })
+}
+
+/// A property as big-endian cells
+fn cells(value: &[u8]) -> Vec<u32> {
+    value.as_chunks::<4>().0.iter().map(|&c| u32::from_be_bytes(c)).collect()
+}
+
+/// Physically contiguous memory mapped into the broker
+#[derive(Debug, Clone, Copy)]
+struct Table {
+    phys: usize,
+    vaddr: usize,
+}
+
+impl Table {
+    /// `size` bytes aligned to `align`, zeroed
+    fn allocate(size: usize, align: usize) -> Result<Self> {
+        // The kernel only promises page alignment: over-allocate for more
+        let span = if align > PAGE_SIZE { size + align } else { size };
+        let base = memory_manager::allocate_physical(span).ok_or(BrokerError::OutOfMemory)?;
+        let phys = base.next_multiple_of(align);
+        let vaddr = memory_manager::map_physical(phys, size, 0x3)
+            .ok_or(BrokerError::SyscallFailed(usize::MAX))?;
+        // SAFETY: freshly mapped, `size` bytes, ours alone
+        unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, size) };
+        Ok(Self { phys, vaddr })
+    }
+
+    fn word(&self, index: usize) -> *mut u64 {
+        (self.vaddr + index * 8) as *mut u64
+    }
+
+    fn read(&self, index: usize) -> u64 {
+        // SAFETY: callers stay within the table
+        unsafe { core::ptr::read_volatile(self.word(index)) }
+    }
+
+    fn write(&self, index: usize, value: u64) {
+        // SAFETY: callers stay within the table
+        unsafe { core::ptr::write_volatile(self.word(index), value) }
+    }
+}
+
+/// Make table writes visible to the SMMU before telling it about them
+fn publish() {
+    // SAFETY: a barrier, no memory access of its own
+    unsafe { core::arch::asm!("dsb ishst", options(nostack, preserves_flags)) };
+}
+
+/// A device's I/O virtual address space
+#[derive(Debug)]
+pub struct IoDomain {
+    asid: u16,
+    streams: Vec<u32>,
+    /// Context descriptor page
+    cd: Table,
+    /// Translation tables; the first is the root (level 1)
+    tables: Vec<Table>,
+    /// Next free IOVA
+    next_iova: u64,
+}
+
+impl IoDomain {
+    /// Streams translated through this domain
+    pub fn streams(&self) -> &[u32] {
+        &self.streams
+    }
+}
+
+/// An Arm SMMUv3
+#[derive(Debug)]
+pub struct Smmu {
+    /// phandle of the SMMU node, matched against `StreamId::iommu`
+    phandle: u32,
+    /// Registers, mapped
+    regs: usize,
+    /// Stream table (linear, `1 << sid_bits` entries)
+    strtab: Table,
+    sid_bits: u32,
+    cmdq: Table,
+    cmdq_log2: u32,
+    cmdq_prod: u32,
+    /// Output address size, as CD.IPS
+    ips: u64,
+    asid_limit: u32,
+    next_asid: u16,
+    free_asids: Vec<u16>,
+    /// Tables of detached domains, kept for reuse (the kernel cannot take
+    /// memory back)
+    spare: Vec<Table>,
+}
+
+impl Smmu {
+    /// Find and enable the first SMMUv3 in the device tree, sizing its
+    /// stream table for the devices that reference it
+    ///
+    /// Returns `None` if there is none, or it lacks what this driver needs
+    /// (stage 1, AArch64 tables, 4 KiB granule) or does not come up.
+    pub(crate) fn probe(tree: &DeviceTree, pci: &PciBus) -> Option<Self> {
+        let node = tree.find_compatible("arm,smmu-v3").next()?;
+        let phandle = node.cell_property("phandle", 0);
+        let mmio = node.mmio.first()?;
+        let permissions = 0x3 | MemoryType::Device.map_bits();
+        let regs = memory_manager::map_physical(mmio.base as usize, mmio.size as usize, permissions)?;
+
+        let read = |offset: usize| unsafe { core::ptr::read_volatile((regs + offset) as *const u32) };
+        let (idr0, idr1, idr5) = (read(SMMU_IDR0), read(SMMU_IDR1), read(SMMU_IDR5));
+        if idr0 & IDR0_S1P == 0 || idr0 & IDR0_TTF_AARCH64 == 0 || idr5 & IDR5_GRAN4K == 0 {
+            return None;
+        }
+
+        // Only as many stream table entries as there are known streams
+        let max_sid = tree
+            .entries()
+            .iter()
+            .flat_map(platform_streams)
+            .chain(pci.functions().iter().filter_map(|f| f.stream_id))
+            .filter(|s| s.iommu == phandle)
+            .map(|s| s.id)
+            .max()
+            .unwrap_or(0);
+        let sid_bits = (u32::BITS - max_sid.leading_zeros()).min(idr1 & 0x3f);
+
+        let strtab_size = 64 << sid_bits;
+        let cmdq_log2 = CMDQ_LOG2.min((idr1 >> 21) & 0x1f);
+        let mut smmu = Self {
+            phandle,
+            regs,
+            strtab: Table::allocate(strtab_size, strtab_size.max(64)).ok()?,
+            sid_bits,
+            cmdq: Table::allocate(16 << cmdq_log2, PAGE_SIZE).ok()?,
+            cmdq_log2,
+            cmdq_prod: 0,
+            ips: (idr5 & 0b111).min(0b101) as u64,
+            asid_limit: if idr0 & IDR0_ASID16 != 0 { 1 << 16 } else { 1 << 8 },
+            next_asid: 1,
+            free_asids: Vec::new(),
+            spare: Vec::new(),
+        };
+        for sid in 0..1u32 << sid_bits {
+            smmu.write_ste(sid, STE_V | STE_CFG_BYPASS, STE_SHCFG_INCOMING);
+        }
+        smmu.enable(idr0).ok()?;
+        Some(smmu)
+    }
+
+    /// Whether `stream` is on this SMMU
+    pub(crate) fn handles(&self, stream: &StreamId) -> bool {
+        stream.iommu == self.phandle && stream.id >> self.sid_bits == 0
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: `regs` maps the SMMU's register pages
+        unsafe { core::ptr::read_volatile((self.regs + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        // SAFETY: `regs` maps the SMMU's register pages
+        unsafe { core::ptr::write_volatile((self.regs + offset) as *mut u32, value) }
+    }
+
+    fn write64(&self, offset: usize, value: u64) {
+        // SAFETY: `regs` maps the SMMU's register pages
+        unsafe { core::ptr::write_volatile((self.regs + offset) as *mut u64, value) }
+    }
+
+    /// Write CR0 and wait for the SMMU to acknowledge it
+    fn set_cr0(&self, value: u32) -> Result<()> {
+        self.write(SMMU_CR0, value);
+        (0..POLL_LIMIT)
+            .any(|_| self.read(SMMU_CR0ACK) == value)
+            .then_some(())
+            .ok_or(BrokerError::SyscallFailed(SMMU_CR0ACK))
+    }
+
+    /// Point the SMMU at our stream table and command queue and turn it on
+    fn enable(&mut self, idr0: u32) -> Result<()> {
+        self.set_cr0(0)?;
+
+        self.write(SMMU_CR1, if idr0 & IDR0_COHACC != 0 { CR1_CACHEABLE } else { 0 });
+        self.write(SMMU_CR2, CR2_RECINVSID | CR2_PTM);
+        self.write64(SMMU_STRTAB_BASE, BASE_RA | self.strtab.phys as u64);
+        self.write(SMMU_STRTAB_BASE_CFG, self.sid_bits);
+        self.write64(SMMU_CMDQ_BASE, BASE_RA | self.cmdq.phys as u64 | self.cmdq_log2 as u64);
+        self.write(SMMU_CMDQ_PROD, 0);
+        self.write(SMMU_CMDQ_CONS, 0);
+        publish();
+
+        self.set_cr0(CR0_CMDQEN)?;
+        self.submit(&[[CMD_CFGI_ALL, 31], [CMD_TLBI_NSNH_ALL, 0]])?;
+        self.set_cr0(CR0_CMDQEN | CR0_SMMUEN)
+    }
+
+    /// Run commands and wait for them to complete
+    fn submit(&mut self, commands: &[[u64; 2]]) -> Result<()> {
+        let entries = 1u32 << self.cmdq_log2;
+        // Index plus wrap bit
+        let wrap_mask = (entries << 1) - 1;
+
+        // The queue is drained after every batch, so a batch only has to fit
+        for batch in commands.chunks(entries as usize - 1) {
+            for command in batch.iter().chain(&[[CMD_SYNC, 0]]) {
+                let slot = (self.cmdq_prod & (entries - 1)) as usize;
+                self.cmdq.write(slot * 2, command[0]);
+                self.cmdq.write(slot * 2 + 1, command[1]);
+                self.cmdq_prod = (self.cmdq_prod + 1) & wrap_mask;
+            }
+            publish();
+            self.write(SMMU_CMDQ_PROD, self.cmdq_prod);
+
+            let mut drained = false;
+            for _ in 0..POLL_LIMIT {
+                let cons = self.read(SMMU_CMDQ_CONS);
+                if (cons >> 24) & 0x7f != 0 {
+                    return Err(BrokerError::SyscallFailed(cons as usize));
+                }
+                if cons & wrap_mask == self.cmdq_prod {
+                    drained = true;
+                    break;
+                }
+            }
+            if !drained {
+                return Err(BrokerError::SyscallFailed(SMMU_CMDQ_CONS));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a stream table entry, valid word last
+    fn write_ste(&self, sid: u32, word0: u64, word1: u64) {
+        let base = sid as usize * 8;
+        self.strtab.write(base + 1, word1);
+        publish();
+        self.strtab.write(base, word0);
+    }
+
+    /// A zeroed table page, reusing a detached domain's when there is one
+    fn table(&mut self) -> Result<Table> {
+        match self.spare.pop() {
+            Some(table) => {
+                // SAFETY: spare tables are ours and no longer walked
+                unsafe { core::ptr::write_bytes(table.vaddr as *mut u8, 0, PAGE_SIZE) };
+                Ok(table)
+            }
+            None => Table::allocate(PAGE_SIZE, PAGE_SIZE),
+        }
+    }
+
+    /// Create a domain translating `streams`
+    ///
+    /// Nothing is mapped yet: until `map`, the device's DMA faults.
+    pub(crate) fn attach(&mut self, streams: &[u32]) -> Result<IoDomain> {
+        if streams.iter().any(|&sid| sid >> self.sid_bits != 0) {
+            return Err(BrokerError::InvalidCapability);
+        }
+        let asid = match self.free_asids.pop() {
+            Some(asid) => asid,
+            None if (self.next_asid as u32) < self.asid_limit - 1 => {
+                let asid = self.next_asid;
+                self.next_asid += 1;
+                asid
+            }
+            None => return Err(BrokerError::OutOfCapabilitySlots),
+        };
+
+        let (cd, root) = match (self.table(), self.table()) {
+            (Ok(cd), Ok(root)) => (cd, root),
+            (cd, root) => {
+                self.spare.extend(cd.into_iter().chain(root));
+                self.free_asids.push(asid);
+                return Err(BrokerError::OutOfMemory);
+            }
+        };
+        cd.write(1, root.phys as u64 & PTE_ADDR_MASK);
+        cd.write(3, CD_MAIR);
+        publish();
+        cd.write(0, CD_TCR | (self.ips << 32) | CD_FLAGS | (asid as u64) << 48);
+
+        for &sid in streams {
+            self.write_ste(sid, STE_V | STE_CFG_S1 | cd.phys as u64, STE_S1_CD_ATTRS);
+        }
+        let invalidate: Vec<[u64; 2]> = streams
+            .iter()
+            .map(|&sid| [CMD_CFGI_STE | (sid as u64) << 32, 1])
+            .collect();
+        self.submit(&invalidate)?;
+
+        Ok(IoDomain {
+            asid,
+            streams: streams.to_vec(),
+            cd,
+            tables: alloc::vec![root],
+            next_iova: IOVA_BASE,
+        })
+    }
+
+    /// Map `size` bytes of physical memory at `phys` into `domain`,
+    /// returning the IOVA the device should use
+    pub(crate) fn map(&mut self, domain: &mut IoDomain, phys: usize, size: usize) -> Result<u64> {
+        let size = size.next_multiple_of(PAGE_SIZE) as u64;
+        let iova = domain.next_iova;
+        if iova + size > IOVA_END {
+            return Err(BrokerError::OutOfMemory);
+        }
+
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let addr = iova + offset;
+            let mut table = domain.tables[0];
+            for shift in [30, 21] {
+                let index = ((addr >> shift) & 0x1ff) as usize;
+                let entry = table.read(index);
+                table = if entry & PTE_TABLE == PTE_TABLE {
+                    let next = (entry & PTE_ADDR_MASK) as usize;
+                    *domain
+                        .tables
+                        .iter()
+                        .find(|t| t.phys == next)
+                        .ok_or(BrokerError::InvalidCapability)?
+                } else {
+                    let next = self.table()?;
+                    domain.tables.push(next);
+                    publish();
+                    table.write(index, next.phys as u64 | PTE_TABLE);
+                    next
+                };
+            }
+            let index = ((addr >> 12) & 0x1ff) as usize;
+            table.write(index, (phys as u64 + offset) | PTE_DMA_ATTRS | PTE_PAGE);
+        }
+        publish();
+
+        // A guard page between mappings
+        domain.next_iova = iova + size + PAGE_SIZE as u64;
+        Ok(iova)
+    }
+
+    /// Tear a domain down: its streams abort from now on
+    pub(crate) fn detach(&mut self, domain: IoDomain) -> Result<()> {
+        for &sid in &domain.streams {
+            self.write_ste(sid, STE_V | STE_CFG_ABORT, 0);
+        }
+        let mut commands: Vec<[u64; 2]> = domain
+            .streams
+            .iter()
+            .map(|&sid| [CMD_CFGI_STE | (sid as u64) << 32, 1])
+            .collect();
+        commands.push([CMD_TLBI_NH_ASID | (domain.asid as u64) << 48, 0]);
+        let result = self.submit(&commands);
+
+        // Only reuse what the SMMU has provably stopped walking
+        if result.is_ok() {
+            self.free_asids.push(domain.asid);
+            self.spare.push(domain.cd);
+            self.spare.extend(domain.tables);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_rid() {
+        // Two host bridge segments on different SMMUs
+        let map = [0x0, 1, 0x0, 0x100, 0x100, 2, 0x1000, 0x100];
+
+        assert_eq!(map_rid(&map, 0x08), Some(StreamId { iommu: 1, id: 0x08 }));
+        assert_eq!(map_rid(&map, 0x1ff), Some(StreamId { iommu: 2, id: 0x10ff }));
+        assert_eq!(map_rid(&map, 0x200), None);
+    }
+}
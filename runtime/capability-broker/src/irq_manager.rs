@@ -0,0 +1,391 @@
+//! IRQ Manager
+//!
+//! Binds interrupt lines to drivers' notifications. A line has either one
+//! exclusive handler or any number of shared ones: legacy PCI INTx lines
+//! are wired together on most boards, and a monitor may want to observe a
+//! driver's interrupt alongside it. Every shared subscriber has its own
+//! IRQHandler capability and badge and is signaled on each interrupt; the
+//! kernel keeps the line masked until all of them have acknowledged it.
+//!
+//! A driver services its lines with an [`IrqDispatcher`]: it registers a
+//! closure per subscription, and each [`wait`](IrqDispatcher::wait) blocks
+//! on the notification, runs the closures whose badge bits were signaled
+//! and acknowledges their lines, so no interrupt is left masked.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::{BrokerError, Result};
+
+/// CapType::IrqControl in the kernel
+const CAP_TYPE_IRQ_CONTROL: usize = 10;
+
+/// How a subscriber holds its IRQ line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqMode {
+    /// The only handler. Signals `1 << (irq % 64)`
+    Exclusive,
+    /// One of any number of shared handlers
+    Shared {
+        /// Bits signaled on the subscriber's notification (non-zero)
+        badge: u64,
+    },
+}
+
+/// A subscription to an IRQ line
+///
+/// Not `Clone`: hand it back to `CapabilityBroker::release_irq` once.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IrqHandle {
+    /// IRQ number
+    pub irq: u32,
+    /// Slot of the IRQHandler capability
+    pub handler_cap: usize,
+    /// Bits signaled on the subscriber's notification when the IRQ fires
+    pub badge: u64,
+    /// Process that subscribed
+    pub owner_pid: usize,
+}
+
+impl IrqHandle {
+    /// Acknowledge the interrupt once the device has been serviced
+    ///
+    /// On a shared line every subscriber must acknowledge, including those
+    /// whose device did not raise it; the line stays masked until then.
+    pub fn ack(&self) -> Result<()> {
+        let result: usize;
+        unsafe {
+            core::arch::asm!(
+                "mov x8, {syscall_num}",
+                "svc #0",
+                syscall_num = in(reg) 0x41u64, // SYS_IRQ_HANDLER_ACK
+                inlateout("x0") self.handler_cap => result,
+                out("x8") _,
+            );
+        }
+        check(result)
+    }
+}
+
+/// A driver's handler for one interrupt line
+type Handler<'a> = Box<dyn FnMut(&IrqHandle) + 'a>;
+
+/// Runs a driver's interrupt handlers off one notification
+///
+/// Every subscription registered here must signal the dispatcher's
+/// notification, with badge bits no other subscription on it uses.
+///
+/// ```rust,no_run
+/// let uart = broker.request_irq(33, notification, IrqMode::Exclusive, pid)?;
+/// let mut irqs = IrqDispatcher::new(notification);
+/// irqs.register(uart, |_| driver.service_rx());
+/// loop {
+///     irqs.wait()?;
+/// }
+/// ```
+pub struct IrqDispatcher<'a> {
+    notification_cap: usize,
+    handlers: Vec<(IrqHandle, Handler<'a>)>,
+}
+
+impl<'a> IrqDispatcher<'a> {
+    /// Create a dispatcher waiting on the notification in `notification_cap`
+    pub fn new(notification_cap: usize) -> Self {
+        Self {
+            notification_cap,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Run `handler` each time `handle`'s interrupt is signaled
+    ///
+    /// The handler services the device; the dispatcher acknowledges the
+    /// line after it returns.
+    pub fn register(&mut self, handle: IrqHandle, handler: impl FnMut(&IrqHandle) + 'a) {
+        self.handlers.push((handle, Box::new(handler)));
+    }
+
+    /// Stop handling `irq`, giving its subscription back for
+    /// `CapabilityBroker::release_irq`
+    pub fn unregister(&mut self, irq: u32) -> Option<IrqHandle> {
+        let index = self.handlers.iter().position(|(h, _)| h.irq == irq)?;
+        Some(self.handlers.swap_remove(index).0)
+    }
+
+    /// Block until an interrupt is signaled, then handle and acknowledge it
+    ///
+    /// Returns the number of handlers run. Every handler whose badge bits
+    /// were signaled runs, even if an acknowledgement fails; the first
+    /// failure is returned.
+    pub fn wait(&mut self) -> Result<usize> {
+        let result: usize;
+        unsafe {
+            core::arch::asm!(
+                "mov x8, {syscall_num}",
+                "svc #0",
+                syscall_num = in(reg) 0x19u64, // SYS_WAIT
+                inlateout("x0") self.notification_cap => result,
+                out("x8") _,
+            );
+        }
+        check(result)?;
+        self.dispatch(result as u64, IrqHandle::ack)
+    }
+
+    /// Run the handlers whose badge bits are in `signals`, acknowledging
+    /// each with `ack` once it returns
+    fn dispatch(&mut self, signals: u64, ack: impl Fn(&IrqHandle) -> Result<()>) -> Result<usize> {
+        let mut handled = 0;
+        let mut result = Ok(());
+        for (handle, handler) in &mut self.handlers {
+            if signals & handle.badge != 0 {
+                handler(handle);
+                result = result.and(ack(handle));
+                handled += 1;
+            }
+        }
+        result.map(|()| handled)
+    }
+}
+
+/// Subscribers of one IRQ line
+struct Line {
+    irq: u32,
+    shared: bool,
+    /// IRQHandler capability slots and their subscribers' process IDs
+    handlers: Vec<(usize, usize)>,
+}
+
+/// IRQ Manager
+pub(crate) struct IrqManager {
+    /// IRQControl, for installing into the broker's CSpace on first use
+    irq_control_paddr: usize,
+    /// Broker slot holding IRQControl, once installed
+    irq_control: Option<usize>,
+    /// Lines with subscribers
+    lines: Vec<Line>,
+}
+
+impl IrqManager {
+    pub(crate) fn new(irq_control_paddr: usize) -> Self {
+        Self {
+            irq_control_paddr,
+            irq_control: None,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Broker slot holding IRQControl, if installed
+    pub(crate) fn irq_control(&self) -> Option<usize> {
+        self.irq_control
+    }
+
+    /// Install IRQControl in `slot` of the broker's CSpace
+    pub(crate) fn install_irq_control(&mut self, slot: usize) -> Result<()> {
+        if self.irq_control_paddr == 0 {
+            return Err(BrokerError::InvalidCapability);
+        }
+        let result: usize;
+        unsafe {
+            core::arch::asm!(
+                "mov x8, {syscall_num}",
+                "svc #0",
+                syscall_num = in(reg) 0x1Du64, // SYS_CAP_INSERT_SELF
+                inlateout("x0") slot => result,
+                inlateout("x1") CAP_TYPE_IRQ_CONTROL => _,
+                inlateout("x2") self.irq_control_paddr => _,
+                out("x8") _,
+            );
+        }
+        check(result)?;
+        self.irq_control = Some(slot);
+        Ok(())
+    }
+
+    /// Whether `irq` takes another handler in `mode`
+    pub(crate) fn admits(&self, irq: u32, mode: IrqMode) -> Result<()> {
+        if mode == (IrqMode::Shared { badge: 0 }) {
+            return Err(BrokerError::InvalidCapability);
+        }
+        match self.lines.iter().find(|l| l.irq == irq) {
+            None => Ok(()),
+            Some(line) if line.shared && mode != IrqMode::Exclusive => Ok(()),
+            Some(_) => Err(BrokerError::ResourceInUse),
+        }
+    }
+
+    /// Bind `irq` to the notification in `notification_cap` for
+    /// `owner_pid`, putting the IRQHandler capability in `handler_slot`;
+    /// returns the badge
+    pub(crate) fn subscribe(
+        &mut self,
+        irq: u32,
+        notification_cap: usize,
+        handler_slot: usize,
+        mode: IrqMode,
+        owner_pid: usize,
+    ) -> Result<u64> {
+        self.admits(irq, mode)?;
+        let control = self.irq_control.ok_or(BrokerError::InvalidCapability)?;
+
+        let (syscall, badge) = match mode {
+            IrqMode::Exclusive => (0x40u64, 0), // SYS_IRQ_HANDLER_GET
+            IrqMode::Shared { badge } => (0x43u64, badge), // SYS_IRQ_HANDLER_GET_SHARED
+        };
+        let result: usize;
+        unsafe {
+            core::arch::asm!(
+                "mov x8, {syscall_num}",
+                "svc #0",
+                syscall_num = in(reg) syscall,
+                inlateout("x0") control => result,
+                inlateout("x1") irq as usize => _,
+                inlateout("x2") notification_cap => _,
+                inlateout("x3") handler_slot => _,
+                inlateout("x4") badge => _,
+                out("x8") _,
+            );
+        }
+        check(result)?;
+
+        self.record(irq, handler_slot, owner_pid, mode);
+        Ok(match mode {
+            IrqMode::Exclusive => 1 << (irq % 64),
+            IrqMode::Shared { badge } => badge,
+        })
+    }
+
+    /// Unbind the handler in `handler_cap` from `irq`
+    ///
+    /// The capability itself stays for the caller to delete.
+    pub(crate) fn unsubscribe(&mut self, irq: u32, handler_cap: usize) -> Result<()> {
+        if !self.forget(irq, handler_cap) {
+            return Err(BrokerError::InvalidCapability);
+        }
+        let result: usize;
+        unsafe {
+            core::arch::asm!(
+                "mov x8, {syscall_num}",
+                "svc #0",
+                syscall_num = in(reg) 0x44u64, // SYS_IRQ_HANDLER_CLEAR
+                inlateout("x0") handler_cap => result,
+                out("x8") _,
+            );
+        }
+        check(result)
+    }
+
+    /// Number of handlers subscribed to `irq`
+    pub(crate) fn subscribers(&self, irq: u32) -> usize {
+        self.lines.iter().find(|l| l.irq == irq).map_or(0, |l| l.handlers.len())
+    }
+
+    /// Subscriptions held by `owner_pid`, as (irq, handler capability)
+    pub(crate) fn subscriptions_of(&self, owner_pid: usize) -> Vec<(u32, usize)> {
+        self.lines
+            .iter()
+            .flat_map(|l| l.handlers.iter().map(move |&(cap, pid)| (l.irq, cap, pid)))
+            .filter(|&(_, _, pid)| pid == owner_pid)
+            .map(|(irq, cap, _)| (irq, cap))
+            .collect()
+    }
+
+    fn record(&mut self, irq: u32, handler_cap: usize, owner_pid: usize, mode: IrqMode) {
+        match self.lines.iter_mut().find(|l| l.irq == irq) {
+            Some(line) => line.handlers.push((handler_cap, owner_pid)),
+            None => self.lines.push(Line {
+                irq,
+                shared: matches!(mode, IrqMode::Shared { .. }),
+                handlers: alloc::vec![(handler_cap, owner_pid)],
+            }),
+        }
+    }
+
+    /// Drop a handler from the books; false if it is not subscribed
+    fn forget(&mut self, irq: u32, handler_cap: usize) -> bool {
+        let Some(index) = self.lines.iter().position(|l| l.irq == irq) else {
+            return false;
+        };
+        let line = &mut self.lines[index];
+        let Some(pos) = line.handlers.iter().position(|&(h, _)| h == handler_cap) else {
+            return false;
+        };
+        line.handlers.swap_remove(pos);
+        if line.handlers.is_empty() {
+            self.lines.swap_remove(index);
+        }
+        true
+    }
+}
+
+fn check(result: usize) -> Result<()> {
+    if result == usize::MAX {
+        Err(BrokerError::SyscallFailed(result))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+
+    #[test]
+    fn test_line_sharing() {
+        let mut irqs = IrqManager::new(0);
+        let shared = |badge| IrqMode::Shared { badge };
+
+        irqs.record(35, 200, 7, shared(0x1));
+        irqs.record(35, 201, 8, shared(0x2));
+        assert_eq!(irqs.subscribers(35), 2);
+        assert_eq!(irqs.admits(35, shared(0x4)), Ok(()));
+        assert_eq!(irqs.admits(35, IrqMode::Exclusive), Err(BrokerError::ResourceInUse));
+        assert_eq!(irqs.admits(35, shared(0)), Err(BrokerError::InvalidCapability));
+
+        irqs.record(33, 202, 7, IrqMode::Exclusive);
+        assert_eq!(irqs.admits(33, shared(0x1)), Err(BrokerError::ResourceInUse));
+        assert_eq!(irqs.subscriptions_of(7), alloc::vec![(35, 200), (33, 202)]);
+
+        assert!(irqs.forget(35, 200));
+        assert!(!irqs.forget(35, 200));
+        assert!(irqs.forget(35, 201));
+        assert_eq!(irqs.subscribers(35), 0);
+        assert_eq!(irqs.admits(35, IrqMode::Exclusive), Ok(()));
+    }
+
+    #[test]
+    fn test_dispatch_runs_and_acks_signaled_handlers() {
+        let handle = |irq, badge| IrqHandle {
+            irq,
+            handler_cap: 200 + irq as usize,
+            badge,
+            owner_pid: 7,
+        };
+        let serviced = RefCell::new(Vec::new());
+        let acked = RefCell::new(Vec::new());
+        let ack = |h: &IrqHandle| {
+            acked.borrow_mut().push(h.irq);
+            match h.irq {
+                34 => Err(BrokerError::SyscallFailed(usize::MAX)),
+                _ => Ok(()),
+            }
+        };
+
+        let mut dispatcher = IrqDispatcher::new(50);
+        for (irq, badge) in [(33, 1 << 33), (34, 0x1), (35, 0x2)] {
+            dispatcher.register(handle(irq, badge), |h| serviced.borrow_mut().push(h.irq));
+        }
+
+        assert_eq!(dispatcher.dispatch(1 << 33 | 0x2, ack), Ok(2));
+        // A failed ack is reported, but the other lines are still handled
+        assert_eq!(dispatcher.dispatch(0x3, ack), Err(BrokerError::SyscallFailed(usize::MAX)));
+        assert_eq!(dispatcher.dispatch(0x4, ack), Ok(0));
+
+        assert_eq!(dispatcher.unregister(35).map(|h| h.handler_cap), Some(235));
+        assert_eq!(dispatcher.dispatch(0x2, ack), Ok(0));
+
+        assert_eq!(*serviced.borrow(), [33, 35, 34, 35]);
+        assert_eq!(*acked.borrow(), [33, 35, 34, 35]);
+    }
+}
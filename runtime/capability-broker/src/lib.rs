@@ -25,10 +25,21 @@
 //!
 //! # Features
 //!
-//! - **Device Management**: Allocate MMIO regions, IRQs, DMA buffers
+//! - **Device Management**: Allocate MMIO regions, IRQs, DMA buffers,
+//!   GPIO lines and pins
 //! - **Memory Management**: Request physical/virtual memory from kernel
 //! - **Endpoint Management**: Create IPC endpoints for communication
 //! - **Capability Tracking**: Track and manage capability slots
+//! - **Resource Transactions**: Request a device, its IRQ, DMA pool and
+//!   slots all at once, or none of them ([`transaction`])
+//! - **Hot-plug Events**: Notify subscribers of devices appearing and
+//!   going away after boot ([`hotplug`])
+//! - **Power Management**: Run a device's clocks, resets and power
+//!   domains through SoC-specific controllers ([`power`])
+//! - **Service Registry**: Name, describe and discover IPC services;
+//!   services of exited or faulted components are unregistered
+//! - **Broker Server**: Serve the above to other components over IPC
+//!   ([`broker_server`])
 //!
 //! # Usage
 //!
@@ -39,9 +50,12 @@
 //! let mut broker = CapabilityBroker::init()?;
 //!
 //! // Request a device (e.g., UART)
-//! let uart_device = broker.request_device(DeviceId::Uart(0))?;
+//! let uart_device = broker.request_device(DeviceId::Uart(0), 42)?;
 //! // uart_device now contains MMIO region, IRQ capability, etc.
 //!
+//! // Or any device in the device tree, by node name
+//! let rtc = broker.request_device(DeviceId::Platform { name: "pl031" }, 42)?;
+//!
 //! // Allocate memory
 //! let mem_region = broker.allocate_memory(4096)?;
 //!
@@ -55,25 +69,58 @@
 
 extern crate alloc;
 
+use core::ops::Range;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
 pub mod boot_info;
 
+pub mod broker_server;
+pub mod cap_table;
+pub mod cspace;
+pub mod descriptor;
 pub mod device_manager;
+pub mod device_tree;
+pub mod dma_pool;
+pub mod gpio;
 pub mod endpoint_manager;
+pub mod hotplug;
+pub mod iommu;
+pub mod irq_manager;
 pub mod memory_manager;
+pub mod pci;
+pub mod power;
 pub mod service_registry;
 pub mod shmem_registry;
+pub mod transaction;
 
+pub use broker_server::{BrokerClient, BrokerServer};
+pub use cspace::CSpaceStats;
+pub use descriptor::DeviceDescriptor;
 pub use device_manager::{DeviceId, DeviceResource};
+pub use device_tree::{DeviceEntry, DeviceTree};
+pub use dma_pool::{DmaPool, DmaRegion};
 pub use endpoint_manager::Endpoint;
-pub use memory_manager::MemoryRegion;
+pub use gpio::{GpioLine, PinGroup};
+pub use hotplug::{HotplugBus, HotplugEvent, HotplugKind, HotplugReader, HotplugSubscription};
+pub use iommu::StreamId;
+pub use irq_manager::{IrqDispatcher, IrqHandle, IrqMode};
+pub use memory_manager::{
+    FrameCap, FrameSize, MemoryRegion, MemoryType, UntypedAllocation, UntypedKind,
+};
+pub use pci::{PciBus, PciFunction};
+pub use power::{DevicePower, PowerController};
+pub use service_registry::{ServiceConnection, ServiceInfo, ServiceMetadata};
 pub use shmem_registry::{ShmemEntry, ShmemRegistry};
+pub use transaction::{ResourceGrant, ResourceTransaction};
 
 /// Errors that can occur in the Capability Broker
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BrokerError {
     /// Capability slot allocation failed (out of slots)
     OutOfCapabilitySlots,
-    /// Requested device not found or already allocated
+    /// Requested device not found
     DeviceNotFound,
     /// Memory allocation failed (out of memory)
     OutOfMemory,
@@ -83,22 +130,15 @@ pub enum BrokerError {
     SyscallFailed(usize),
     /// Resource already in use
     ResourceInUse,
+    /// Device tree blob is malformed
+    InvalidDeviceTree,
+    /// Device is held by another component (see `claim_device`)
+    DeviceBusy,
 }
 
 /// Result type for Capability Broker operations
 pub type Result<T> = core::result::Result<T, BrokerError>;
 
-/// Capability allocation record
-#[derive(Debug, Clone, Copy)]
-struct CapabilityRecord {
-    /// Capability slot number
-    slot: usize,
-    /// Type of capability
-    cap_type: CapabilityType,
-    /// Is this capability currently allocated?
-    allocated: bool,
-}
-
 /// Type of capability
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CapabilityType {
@@ -108,31 +148,33 @@ enum CapabilityType {
     Device,
     /// IPC endpoint capability
     Endpoint,
+    /// Notification capability
+    Notification,
     /// Untyped/free slot
     Untyped,
 }
 
-const MAX_CAPABILITY_RECORDS: usize = 256;
-
 /// The Capability Broker
 ///
 /// This is the main entry point for managing kernel capabilities in userspace.
 /// It provides a clean API for device allocation, memory management, and IPC.
 pub struct CapabilityBroker {
-    /// Next free capability slot
-    next_cap_slot: usize,
-    /// Maximum capability slot
-    max_cap_slot: usize,
-    /// Capability allocation records
-    cap_records: [Option<CapabilityRecord>; MAX_CAPABILITY_RECORDS],
-    /// Number of allocated capabilities
-    num_allocated_caps: usize,
+    /// Free and used slots of the broker's CSpace
+    cspace: cspace::CSpaceAllocator,
+    /// What each allocated slot holds
+    caps: cap_table::CapTable,
     /// Device manager
     device_manager: device_manager::DeviceManager,
     /// Memory manager
     memory_manager: memory_manager::MemoryManager,
     /// Endpoint manager
     endpoint_manager: endpoint_manager::EndpointManager,
+    /// IRQ manager
+    irq_manager: irq_manager::IrqManager,
+    /// Hot-plug subscribers
+    hotplug: hotplug::HotplugManager,
+    /// Clock, reset and power domain controllers
+    power: power::PowerManager,
     /// Service registry for IPC discovery
     service_registry: service_registry::ServiceRegistry,
 }
@@ -160,7 +202,7 @@ impl CapabilityBroker {
             unsafe { boot_info::BootInfo::read().ok_or(BrokerError::SyscallFailed(0))? };
 
         // Start capability slots after initial caps
-        let next_cap_slot = if boot_info.num_initial_caps > 0 {
+        let first_cap_slot = if boot_info.num_initial_caps > 0 {
             (boot_info.num_initial_caps as usize) + 100
         } else {
             100
@@ -168,13 +210,14 @@ impl CapabilityBroker {
         let max_cap_slot = 4096;
 
         Ok(Self {
-            next_cap_slot,
-            max_cap_slot,
-            cap_records: [None; MAX_CAPABILITY_RECORDS],
-            num_allocated_caps: 0,
+            cspace: cspace::CSpaceAllocator::new(first_cap_slot..max_cap_slot),
+            caps: cap_table::CapTable::new(),
             device_manager: device_manager::DeviceManager::new_from_boot_info(boot_info),
             memory_manager: memory_manager::MemoryManager::new_from_boot_info(boot_info),
             endpoint_manager: endpoint_manager::EndpointManager::new(),
+            irq_manager: irq_manager::IrqManager::new(boot_info.irq_control_paddr as usize),
+            hotplug: hotplug::HotplugManager::new(),
+            power: power::PowerManager::new(),
             service_registry: service_registry::ServiceRegistry::new(),
         })
     }
@@ -183,54 +226,69 @@ impl CapabilityBroker {
     ///
     /// Returns the next available capability slot number, or an error if no slots are available.
     fn allocate_cap_slot(&mut self, cap_type: CapabilityType) -> Result<usize> {
-        if self.next_cap_slot >= self.max_cap_slot {
-            return Err(BrokerError::OutOfCapabilitySlots);
-        }
+        let slot = self.cspace.allocate().ok_or(BrokerError::OutOfCapabilitySlots)?;
+        self.caps.insert(slot, cap_type);
+        Ok(slot)
+    }
 
-        let slot = self.next_cap_slot;
-        self.next_cap_slot += 1;
-
-        // Record the capability allocation
-        if self.num_allocated_caps < MAX_CAPABILITY_RECORDS {
-            self.cap_records[self.num_allocated_caps] = Some(CapabilityRecord {
-                slot,
-                cap_type,
-                allocated: true,
-            });
-            self.num_allocated_caps += 1;
+    /// Allocate `count` consecutive capability slots
+    fn allocate_cap_range(&mut self, count: usize, cap_type: CapabilityType) -> Result<Range<usize>> {
+        let slots = self
+            .cspace
+            .allocate_range(count)
+            .ok_or(BrokerError::OutOfCapabilitySlots)?;
+        for slot in slots.clone() {
+            self.caps.insert(slot, cap_type);
         }
+        Ok(slots)
+    }
 
-        Ok(slot)
+    /// Record a capability in a slot the kernel picked
+    fn record_cap(&mut self, slot: usize, cap_type: CapabilityType) {
+        // Keep the allocator from handing the slot out again
+        self.cspace.reserve(slot);
+        self.caps.insert(slot, cap_type);
+    }
+
+    /// Return a capability slot to the allocator
+    ///
+    /// The slot must already be empty (or about to be overwritten by its
+    /// next owner); the kernel object it named is not touched. Freeing a
+    /// slot that is not allocated does nothing.
+    fn free_cap_slot(&mut self, slot: usize) {
+        if self.caps.remove(slot).is_some() {
+            self.cspace.free(slot);
+        }
     }
 
     /// Get statistics about capability usage
     ///
     /// Returns (allocated_count, total_capacity)
     pub fn capability_stats(&self) -> (usize, usize) {
-        let allocated = self.cap_records[..self.num_allocated_caps]
-            .iter()
-            .filter(|r| r.map(|rec| rec.allocated).unwrap_or(false))
-            .count();
-        (allocated, self.max_cap_slot)
+        (self.caps.len(), self.cspace.stats().capacity)
+    }
+
+    /// Occupancy and fragmentation of the broker's CSpace slots
+    pub fn cspace_stats(&self) -> CSpaceStats {
+        self.cspace.stats()
     }
 
     /// Get capability usage by type
     ///
-    /// Returns (memory_caps, device_caps, endpoint_caps, untyped_caps)
+    /// Returns (memory_caps, device_caps, endpoint_caps, untyped_caps);
+    /// notifications count as endpoints
     pub fn capability_usage_by_type(&self) -> (usize, usize, usize, usize) {
         let mut memory = 0;
         let mut device = 0;
         let mut endpoint = 0;
         let mut untyped = 0;
 
-        for rec in self.cap_records[..self.num_allocated_caps].iter().flatten() {
-            if rec.allocated {
-                match rec.cap_type {
-                    CapabilityType::Memory => memory += 1,
-                    CapabilityType::Device => device += 1,
-                    CapabilityType::Endpoint => endpoint += 1,
-                    CapabilityType::Untyped => untyped += 1,
-                }
+        for cap_type in self.caps.types() {
+            match cap_type {
+                CapabilityType::Memory => memory += 1,
+                CapabilityType::Device => device += 1,
+                CapabilityType::Endpoint | CapabilityType::Notification => endpoint += 1,
+                CapabilityType::Untyped => untyped += 1,
             }
         }
 
@@ -239,15 +297,21 @@ impl CapabilityBroker {
 
     /// Request a device resource
     ///
-    /// Allocates all resources needed for the specified device (MMIO, IRQ, DMA).
+    /// Allocates all resources needed for the specified device (MMIO, IRQ, DMA)
+    /// and records `owner_pid` as its owner until it is released.
     ///
     /// # Arguments
     ///
     /// * `device_id` - Identifier for the device to allocate
+    /// * `owner_pid` - Process ID of the driver that will own it
     ///
     /// # Returns
     ///
-    /// Returns a `DeviceResource` containing all allocated resources, or an error.
+    /// Returns a `DeviceResource` containing all allocated resources, or
+    /// `DeviceBusy` if another component holds the device.
+    ///
+    /// The MMIO region is mapped as device memory (Device-nGnRnE); see
+    /// `request_device_mapped` for other memory types.
     ///
     /// # Example
     ///
@@ -255,18 +319,379 @@ impl CapabilityBroker {
     /// use capability_broker::{CapabilityBroker, DeviceId};
     ///
     /// let mut broker = CapabilityBroker::init()?;
-    /// let uart = broker.request_device(DeviceId::Uart(0))?;
+    /// let uart = broker.request_device(DeviceId::Uart(0), 42)?;
     /// // Use uart.mmio_base, uart.irq_cap, etc.
     /// ```
-    pub fn request_device(&mut self, device_id: DeviceId) -> Result<DeviceResource> {
+    pub fn request_device(
+        &mut self,
+        device_id: DeviceId,
+        owner_pid: usize,
+    ) -> Result<DeviceResource> {
+        self.request_device_mapped(device_id, owner_pid, MemoryType::Device)
+    }
+
+    /// Request a device, mapping its MMIO region as `memory_type`
+    ///
+    /// For regions that are memory rather than registers: a framebuffer
+    /// wants `MemoryType::WriteCombining`, on-device descriptor memory
+    /// `MemoryType::NonCacheable`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, DeviceId, MemoryType};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let fb = broker.request_device_mapped(
+    ///     DeviceId::Platform { name: "framebuffer" },
+    ///     42,
+    ///     MemoryType::WriteCombining,
+    /// )?;
+    /// ```
+    pub fn request_device_mapped(
+        &mut self,
+        device_id: DeviceId,
+        owner_pid: usize,
+        memory_type: MemoryType,
+    ) -> Result<DeviceResource> {
         // Allocate IRQ capability slot if needed
         let irq_cap = self.allocate_cap_slot(CapabilityType::Device).ok();
-        self.device_manager.request_device(device_id, irq_cap)
+        let result = self
+            .device_manager
+            .request_device(device_id, irq_cap, owner_pid, memory_type);
+        if result.is_err() {
+            irq_cap.into_iter().for_each(|slot| self.free_cap_slot(slot));
+        }
+        result
+    }
+
+    /// Take a device away from its current owner and hand it to `owner_pid`
+    ///
+    /// For a device manager restarting a hung driver. The previous owner's
+    /// bundle is torn down as by `release_device` and goes stale: releasing
+    /// it later fails with `InvalidCapability`.
+    pub fn claim_device(
+        &mut self,
+        device_id: DeviceId,
+        owner_pid: usize,
+    ) -> Result<DeviceResource> {
+        while let Some(id) = self.device_manager.holder(device_id)?.map(|c| c.id) {
+            if let Some(claim) = self.device_manager.take_claim(id) {
+                self.revoke(claim)?;
+            }
+        }
+        self.request_device(device_id, owner_pid)
+    }
+
+    /// Current owner of a device, if it is handed out
+    pub fn device_owner(&self, device_id: DeviceId) -> Option<usize> {
+        self.device_manager.owner(device_id)
+    }
+
+    /// Release a device resource
+    ///
+    /// Unmaps the device's MMIO region and DMA pool, deletes whatever the
+    /// driver put in its IRQ and DMA capability slots (dropping the IRQ
+    /// handler) and returns those slots to the allocator, so the device can
+    /// be requested again by a restarted driver.
+    ///
+    /// Every step is attempted; the first failure is returned. A bundle
+    /// whose device was taken with `claim_device` fails with
+    /// `InvalidCapability`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, DeviceId};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let uart = broker.request_device(DeviceId::Uart(0), 42)?;
+    /// // ... driver runs, then stops
+    /// broker.release_device(uart)?;
+    /// ```
+    pub fn release_device(&mut self, device: DeviceResource) -> Result<()> {
+        let claim = self
+            .device_manager
+            .take_claim(device.claim)
+            .ok_or(BrokerError::InvalidCapability)?;
+        self.revoke(claim)
+    }
+
+    /// Give a device a DMA pool of `size` bytes
+    ///
+    /// The pool is physically contiguous memory from the kernel, mapped
+    /// read/write into the broker as `memory_type`: `MemoryType::Normal`
+    /// for cache-coherent devices, `MemoryType::NonCacheable` for devices
+    /// that do not snoop the caches (or for descriptor rings that must
+    /// not need cache maintenance); buffers are handed out with
+    /// [`DmaPool::allocate`] and returned with [`DmaPool::free`]. It lives
+    /// until the device is released.
+    ///
+    /// Behind an SMMUv3 the pool is mapped into the device's own I/O
+    /// address space, and is all the device can reach: program it with
+    /// [`DmaRegion::bus_addr`], never the physical address.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, DeviceId, MemoryType};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let mut nic = broker.request_device(DeviceId::Pci { vendor: 0x8086, device: 0x100e }, 42)?;
+    /// broker.attach_dma_pool(&mut nic, 64 * 1024, MemoryType::NonCacheable)?;
+    /// let pool = nic.dma_pool.as_mut().unwrap();
+    /// let rx = pool.allocate(2048, 16)?;
+    /// // ... device fills rx.bus_addr, driver reads rx.vaddr
+    /// pool.free(rx)?;
+    /// ```
+    pub fn attach_dma_pool(
+        &mut self,
+        device: &mut DeviceResource,
+        size: usize,
+        memory_type: MemoryType,
+    ) -> Result<()> {
+        if device.dma_pool.is_some() {
+            return Err(BrokerError::ResourceInUse);
+        }
+
+        let size = size.next_multiple_of(4096);
+        let memory = self.allocate_memory(size)?;
+        let permissions = 0x3 | memory_type.map_bits();
+        let Some(vaddr) = memory_manager::map_physical(memory.phys_addr, size, permissions) else {
+            self.free_cap_slot(memory.cap_slot);
+            return Err(BrokerError::SyscallFailed(usize::MAX));
+        };
+        let bus = match self
+            .device_manager
+            .map_dma(device.claim, memory.cap_slot, memory.phys_addr, vaddr, size)
+        {
+            Ok(bus) => bus,
+            Err(e) => {
+                // Unmapping a mapping we just made cannot fail
+                let _ = memory_manager::unmap_virtual(vaddr, size);
+                self.free_cap_slot(memory.cap_slot);
+                return Err(e);
+            }
+        };
+
+        device.dma_cap = Some(memory.cap_slot);
+        device.dma_pool = Some(DmaPool::new(memory.phys_addr, bus, vaddr, size));
+        Ok(())
+    }
+
+    /// Subscribe a notification to an IRQ line
+    ///
+    /// Binds `irq` to the notification in `notification_cap` (a slot in
+    /// the broker's CSpace) and returns the subscription, whose IRQHandler
+    /// capability the driver acknowledges interrupts with. In
+    /// `IrqMode::Shared` any number of subscribers can hold the line, each
+    /// signaled with its own badge: drivers of devices on a shared INTx
+    /// line, or a monitor observing a driver's interrupt. An exclusive
+    /// subscriber is refused a line with any other subscriber, and the
+    /// other way round (`ResourceInUse`).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, IrqMode};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let nic = broker.request_irq(36, nic_notification, IrqMode::Shared { badge: 0x1 }, 42)?;
+    /// let usb = broker.request_irq(36, usb_notification, IrqMode::Shared { badge: 0x2 }, 43)?;
+    /// // each driver: wait on its notification, service the device, then
+    /// nic.ack()?;
+    /// ```
+    pub fn request_irq(
+        &mut self,
+        irq: u32,
+        notification_cap: usize,
+        mode: IrqMode,
+        owner_pid: usize,
+    ) -> Result<IrqHandle> {
+        self.irq_manager.admits(irq, mode)?;
+        if self.irq_manager.irq_control().is_none() {
+            let slot = self.allocate_cap_slot(CapabilityType::Device)?;
+            if let Err(e) = self.irq_manager.install_irq_control(slot) {
+                self.free_cap_slot(slot);
+                return Err(e);
+            }
+        }
+
+        let handler_cap = self.allocate_cap_slot(CapabilityType::Device)?;
+        match self.irq_manager.subscribe(irq, notification_cap, handler_cap, mode, owner_pid) {
+            Ok(badge) => Ok(IrqHandle {
+                irq,
+                handler_cap,
+                badge,
+                owner_pid,
+            }),
+            Err(e) => {
+                self.free_cap_slot(handler_cap);
+                Err(e)
+            }
+        }
+    }
+
+    /// Unsubscribe from an IRQ line
+    ///
+    /// The handler stops being signaled and, on a shared line, the other
+    /// subscribers stop waiting for its acknowledgement. Its IRQHandler
+    /// capability is deleted and the slot reused.
+    pub fn release_irq(&mut self, handle: IrqHandle) -> Result<()> {
+        self.drop_irq(handle.irq, handle.handler_cap)
+    }
+
+    fn drop_irq(&mut self, irq: u32, handler_cap: usize) -> Result<()> {
+        self.irq_manager.unsubscribe(irq, handler_cap)?;
+        delete_cap(handler_cap);
+        self.free_cap_slot(handler_cap);
+        Ok(())
+    }
+
+    /// Number of subscribers to an IRQ line
+    pub fn irq_subscribers(&self, irq: u32) -> usize {
+        self.irq_manager.subscribers(irq)
+    }
+
+    /// Subscribe to hot-plug events
+    ///
+    /// Gives the subscriber a ring of event records in a page of memory
+    /// (map [`HotplugSubscription::phys_addr`] into it and read with
+    /// [`HotplugReader`]) and signals the notification in
+    /// `notification_cap` (a slot in the broker's CSpace) with `badge`
+    /// whenever an event is added. Events reported before subscribing are
+    /// not replayed: walk [`pci_bus`](Self::pci_bus) and the device tree
+    /// for devices present already.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, HotplugReader};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let sub = broker.subscribe_hotplug(usb_notification, 0x1, 42)?;
+    /// let mut events = unsafe { HotplugReader::new(sub.vaddr) };
+    /// // on each signal
+    /// while let Some(event) = events.next_event() {
+    ///     // bind or unbind a driver
+    /// }
+    /// ```
+    pub fn subscribe_hotplug(
+        &mut self,
+        notification_cap: usize,
+        badge: u64,
+        owner_pid: usize,
+    ) -> Result<HotplugSubscription> {
+        let memory = self.allocate_memory(hotplug::RING_SIZE)?;
+        let Some(vaddr) = memory_manager::map_physical(memory.phys_addr, hotplug::RING_SIZE, 0x3)
+        else {
+            self.free_cap_slot(memory.cap_slot);
+            return Err(BrokerError::SyscallFailed(usize::MAX));
+        };
+
+        // SAFETY: the page was just mapped read/write for the ring alone
+        let id = unsafe {
+            self.hotplug
+                .subscribe(vaddr, memory.cap_slot, notification_cap, badge, owner_pid)
+        };
+        Ok(HotplugSubscription {
+            id,
+            phys_addr: memory.phys_addr,
+            vaddr,
+            owner_pid,
+        })
+    }
+
+    /// Cancel a hot-plug subscription
+    ///
+    /// The broker's mapping of the ring goes away; unmap it from the
+    /// subscriber first.
+    pub fn unsubscribe_hotplug(&mut self, subscription: HotplugSubscription) -> Result<()> {
+        self.drop_hotplug(subscription.id)
+    }
+
+    fn drop_hotplug(&mut self, id: u64) -> Result<()> {
+        let (vaddr, memory_cap) = self.hotplug.unsubscribe(id)?;
+        self.free_cap_slot(memory_cap);
+        memory_manager::unmap_virtual(vaddr, hotplug::RING_SIZE)
+    }
+
+    /// Report a device appearing or going away
+    ///
+    /// Called by the driver of the bus it sits on: a PCIe or virtio
+    /// hot-plug controller, a USB host controller. The event is added to
+    /// every subscriber's ring; returns how many subscribers were signaled.
+    /// A device that went away stays claimed until its driver releases it.
+    pub fn report_hotplug(&mut self, event: HotplugEvent) -> usize {
+        self.hotplug.publish(&event)
+    }
+
+    /// Reclaim everything handed to a component that has died
+    ///
+    /// The component spawner calls this once `owner_pid`'s TCB is
+    /// destroyed: every device it holds is released as with
+    /// [`release_device`](Self::release_device) (MMIO mapping, DMA pool,
+    /// IOMMU domain and capabilities), every IRQ and hot-plug
+    /// subscription dropped as with [`release_irq`](Self::release_irq) and
+    /// [`unsubscribe_hotplug`](Self::unsubscribe_hotplug), and the services
+    /// it registered are unregistered. Bundles and handles the caller still
+    /// holds for them go stale.
+    ///
+    /// Every resource is attempted; returns how many were reclaimed, or
+    /// the first failure.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, DeviceId};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let uart = broker.request_device(DeviceId::Uart(0), 42)?;
+    /// // ... driver 42 crashes and its TCB is destroyed
+    /// broker.reclaim_component(42)?;
+    /// ```
+    pub fn reclaim_component(&mut self, owner_pid: usize) -> Result<usize> {
+        let mut reclaimed = 0;
+        let mut result = Ok(());
+
+        for id in self.device_manager.claims_of(owner_pid) {
+            if let Some(claim) = self.device_manager.take_claim(id) {
+                result = result.and(self.revoke(claim));
+                reclaimed += 1;
+            }
+        }
+        for (irq, handler_cap) in self.irq_manager.subscriptions_of(owner_pid) {
+            result = result.and(self.drop_irq(irq, handler_cap));
+            reclaimed += 1;
+        }
+        for id in self.hotplug.subscriptions_of(owner_pid) {
+            result = result.and(self.drop_hotplug(id));
+            reclaimed += 1;
+        }
+        reclaimed += self.service_registry.unregister_owner(owner_pid);
+
+        result.map(|()| reclaimed)
+    }
+
+    /// Tear down a claim and give its capability slots back
+    fn revoke(&mut self, mut claim: device_manager::Claim) -> Result<()> {
+        let result = self.device_manager.teardown(&mut claim);
+
+        for slot in claim.caps.into_iter().flatten() {
+            delete_cap(slot);
+            self.free_cap_slot(slot);
+        }
+
+        result
     }
 
     /// Allocate a memory region
     ///
     /// Requests the specified amount of physical memory from the kernel.
+    /// The region is contiguous but has no capability behind it: its
+    /// `cap_slot` is reserved and left empty. Memory that is shared with
+    /// other components should come from
+    /// [`allocate_frames`](Self::allocate_frames).
     ///
     /// # Arguments
     ///
@@ -289,6 +714,80 @@ impl CapabilityBroker {
         self.memory_manager.allocate(size, cap_slot)
     }
 
+    /// Allocate page frames backed by real capabilities
+    ///
+    /// Retypes `count` pages of `size` out of the broker's untyped memory.
+    /// Unlike [`allocate_memory`](Self::allocate_memory), whose `cap_slot`
+    /// is only reserved, each frame comes with a page capability that can
+    /// be copied to another component to share it. The capabilities sit in
+    /// consecutive slots; the frames are not guaranteed to be physically
+    /// contiguous.
+    ///
+    /// If any retype fails, the frames already created are deleted and the
+    /// error returned; the kernel does not reclaim their memory until the
+    /// untyped is revoked.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::{CapabilityBroker, FrameSize};
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let ring = broker.allocate_frames(4, FrameSize::Small)?;
+    /// ```
+    pub fn allocate_frames(&mut self, count: usize, size: FrameSize) -> Result<Vec<FrameCap>> {
+        let slots = self.allocate_cap_range(count, CapabilityType::Memory)?;
+        let mut frames = Vec::with_capacity(count);
+        for cap_slot in slots.clone() {
+            match self.memory_manager.retype_frame(size, cap_slot) {
+                Ok(phys_addr) => frames.push(FrameCap {
+                    cap_slot,
+                    phys_addr,
+                    size,
+                }),
+                Err(e) => {
+                    // Frames made so far are deleted; the rest of the
+                    // slots are still empty
+                    self.free_frames(frames);
+                    slots.for_each(|slot| self.free_cap_slot(slot));
+                    return Err(e);
+                }
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Delete frames from [`allocate_frames`](Self::allocate_frames)
+    ///
+    /// Copies handed to other components are not affected.
+    pub fn free_frames(&mut self, frames: Vec<FrameCap>) {
+        for frame in frames {
+            delete_cap(frame.cap_slot);
+            self.free_cap_slot(frame.cap_slot);
+        }
+    }
+
+    /// Reserve physical memory from the boot untyped regions
+    ///
+    /// Unlike [`allocate_memory`](Self::allocate_memory), the range comes
+    /// from an untyped region of the requested kind and is aligned to
+    /// `align`, for objects that need a naturally aligned backing such as
+    /// 2MB block mappings or page tables. `size` is rounded up to whole
+    /// pages; `align` must be a power of two.
+    pub fn allocate_untyped(
+        &mut self,
+        size: usize,
+        align: usize,
+        kind: UntypedKind,
+    ) -> Result<UntypedAllocation> {
+        self.memory_manager.allocate_untyped(size, align, kind)
+    }
+
+    /// Bytes of untyped memory of the given kind not yet reserved
+    pub fn untyped_available(&self, kind: UntypedKind) -> usize {
+        self.memory_manager.untyped_available(kind)
+    }
+
     /// Create an IPC endpoint
     ///
     /// Creates a new IPC endpoint for communication between components.
@@ -307,8 +806,21 @@ impl CapabilityBroker {
     /// // Use endpoint for send/recv operations
     /// ```
     pub fn create_endpoint(&mut self) -> Result<Endpoint> {
-        let cap_slot = self.allocate_cap_slot(CapabilityType::Endpoint)?;
-        self.endpoint_manager.create_endpoint(cap_slot)
+        // The kernel picks the slot; it is recorded, never reused
+        let endpoint = self.endpoint_manager.create_endpoint()?;
+        self.record_cap(endpoint.cap_slot, CapabilityType::Endpoint);
+        Ok(endpoint)
+    }
+
+    /// Create a notification object in the broker's CSpace
+    ///
+    /// Returns its capability slot, for [`request_irq`](Self::request_irq)
+    /// or [`subscribe_hotplug`](Self::subscribe_hotplug).
+    pub fn create_notification(&mut self) -> Result<usize> {
+        // The kernel picks the slot; it is recorded, never reused
+        let slot = endpoint_manager::create_notification()?;
+        self.record_cap(slot, CapabilityType::Notification);
+        Ok(slot)
     }
 
     /// Register a service with the broker
@@ -320,7 +832,10 @@ impl CapabilityBroker {
     ///
     /// * `name` - Service name (must be unique, max 32 characters)
     /// * `endpoint` - IPC endpoint for this service
-    /// * `owner_pid` - Process ID of the service provider
+    /// * `owner_pid` - Process ID of the service provider; the service is
+    ///   unregistered once no thread with this TID is alive
+    /// * `metadata` - Version, protocol and message size the service
+    ///   speaks, reported by [`list_services`](Self::list_services)
     ///
     /// # Returns
     ///
@@ -332,45 +847,80 @@ impl CapabilityBroker {
     /// # Example
     ///
     /// ```rust,no_run
-    /// use capability_broker::CapabilityBroker;
+    /// use capability_broker::{CapabilityBroker, ServiceMetadata};
     ///
     /// let mut broker = CapabilityBroker::init()?;
     /// let endpoint = broker.create_endpoint()?;
-    /// broker.register_service("printer", endpoint, 42)?;
+    /// let metadata = ServiceMetadata { version: 1, protocol_id: 0x5052, max_message_size: 256 };
+    /// broker.register_service("printer", endpoint, 42, metadata)?;
     /// ```
     pub fn register_service(
         &mut self,
         name: &str,
         endpoint: Endpoint,
         owner_pid: usize,
+        metadata: ServiceMetadata,
     ) -> Result<()> {
+        // A restarted component takes over the name its dead instance held
+        self.reap_dead_owner(name);
         self.service_registry
-            .register_service(name, endpoint, owner_pid)
+            .register_service(name, endpoint, owner_pid, metadata)
     }
 
-    /// Lookup a service by name
+    /// Lookup a service by name and connect a client to it
     ///
     /// Allows a consumer (client) to discover a service provider by name.
+    /// The kernel mints a copy of the service's endpoint with a badge
+    /// unique among the service's clients, and it is transferred into the
+    /// client's CSpace, ready to call.
     ///
     /// # Arguments
     ///
     /// * `name` - Service name to lookup
+    /// * `cnode_cap` - Broker slot of the client's CSpace root CNode
+    ///   (0: the broker's own CSpace)
+    /// * `slot` - Slot in the client's CSpace for the endpoint
     ///
     /// # Returns
     ///
-    /// The service's endpoint on success, or an error if not found.
+    /// The client's connection on success, or an error if not found or its
+    /// provider has exited or faulted (the service is unregistered then).
     ///
     /// # Example
     ///
     /// ```rust,no_run
     /// use capability_broker::CapabilityBroker;
     ///
-    /// let broker = CapabilityBroker::init()?;
-    /// let printer_endpoint = broker.lookup_service("printer")?;
-    /// // Use endpoint to communicate with printer service
+    /// let mut broker = CapabilityBroker::init()?;
+    /// let printer = broker.lookup_service("printer", client_cnode, 12)?;
+    /// // The client calls printer.endpoint; the printer sees printer.badge
     /// ```
-    pub fn lookup_service(&self, name: &str) -> Result<Endpoint> {
-        self.service_registry.lookup_service(name)
+    pub fn lookup_service(
+        &mut self,
+        name: &str,
+        cnode_cap: usize,
+        slot: usize,
+    ) -> Result<ServiceConnection> {
+        self.reap_dead_owner(name);
+        let (endpoint, badge) = self.service_registry.connect(name)?;
+
+        // Mint in our CSpace, then transfer: the kernel mints within one CNode
+        let minted = self.allocate_cap_slot(CapabilityType::Endpoint)?;
+        let result = mint_cap(endpoint.cap_slot, minted, badge)
+            .and_then(|()| copy_cap(minted, cnode_cap, slot));
+        // The transferred copy shares the original as its parent, so it
+        // outlives the minted one
+        delete_cap(minted);
+        self.free_cap_slot(minted);
+        result?;
+
+        Ok(ServiceConnection {
+            endpoint: Endpoint {
+                cap_slot: slot,
+                id: endpoint.id,
+            },
+            badge,
+        })
     }
 
     /// Unregister a service
@@ -388,10 +938,204 @@ impl CapabilityBroker {
         self.service_registry.unregister_service(name)
     }
 
+    /// Unregister the services of components that have exited or faulted
+    ///
+    /// A component that called `SYS_PROCESS_EXIT`, was destroyed, or is
+    /// stopped on a fault leaves its services' endpoints with nobody
+    /// receiving, and a client calling one would block forever. Owners are
+    /// checked against the kernel's live threads (a process's PID is its
+    /// thread's TID). Registration and lookup check the one service they
+    /// touch; call this to sweep the whole registry.
+    ///
+    /// Returns the number of services unregistered.
+    pub fn reap_dead_services(&mut self) -> usize {
+        let live = live_threads();
+        self.service_registry.unregister_dead(|pid| live.contains(&pid))
+    }
+
+    /// Unregister the services of `name`'s owner if it is no longer alive
+    fn reap_dead_owner(&mut self, name: &str) {
+        if let Some(owner) = self.service_registry.owner_of(name) {
+            if !live_threads().contains(&owner) {
+                self.service_registry.unregister_owner(owner);
+            }
+        }
+    }
+
     /// Get number of registered services
     pub fn num_services(&self) -> usize {
         self.service_registry.num_services()
     }
+
+    /// List the registered services whose names start with `prefix`
+    ///
+    /// A trailing `*` is ignored, so "kaal.uart.*" and "kaal.uart." both
+    /// list every UART service; "*" lists them all.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::CapabilityBroker;
+    ///
+    /// let broker = CapabilityBroker::init()?;
+    /// for service in broker.list_services("kaal.uart.*") {
+    ///     // service.name, service.owner_pid, service.metadata.version
+    /// }
+    /// ```
+    pub fn list_services<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = ServiceInfo<'a>> + 'a {
+        self.service_registry.list_services(prefix)
+    }
+
+    /// Devices found in the platform's device tree
+    ///
+    /// `None` if the kernel passed no usable device tree; only the boot
+    /// info's fixed device regions are available then.
+    pub fn device_tree(&self) -> Option<&DeviceTree> {
+        self.device_manager.device_tree()
+    }
+
+    /// PCI functions found on the platform's ECAM host bridges
+    pub fn pci_bus(&self) -> &PciBus {
+        self.device_manager.pci_bus()
+    }
+
+    /// Register the driver of a clock, reset or power domain controller
+    ///
+    /// `phandle` is the controller node's phandle in the device tree;
+    /// devices whose `clocks`, `resets` or `power-domains` refer to it
+    /// are powered through `controller`. Fails with `ResourceInUse` if
+    /// the node already has a controller.
+    pub fn register_power_controller(
+        &mut self,
+        phandle: u32,
+        controller: Box<dyn PowerController>,
+    ) -> Result<()> {
+        self.power.register(phandle, controller)
+    }
+
+    /// Power up a device tree device: power domains on, clocks ungated,
+    /// resets released
+    ///
+    /// `name` is matched as by [`DeviceTree::find`]. If a step fails, the
+    /// steps already taken are undone and the device is left off.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use capability_broker::CapabilityBroker;
+    ///
+    /// let mut broker = CapabilityBroker::init()?;
+    /// broker.register_power_controller(ccu_phandle, Box::new(ccu))?;
+    /// broker.power_on("serial@1c28000")?;
+    /// let uart = broker.request_device(DeviceId::Platform { name: "serial@1c28000" }, 42)?;
+    /// ```
+    pub fn power_on(&mut self, name: &str) -> Result<()> {
+        let power = self.device_power(name)?;
+        self.power.power_on(&power)
+    }
+
+    /// Power down a device tree device: resets asserted, clocks gated,
+    /// power domains off
+    pub fn power_off(&mut self, name: &str) -> Result<()> {
+        let power = self.device_power(name)?;
+        self.power.power_off(&power)
+    }
+
+    /// What device `name` needs powered
+    fn device_power(&self, name: &str) -> Result<DevicePower> {
+        let tree = self.device_tree().ok_or(BrokerError::DeviceNotFound)?;
+        let entry = tree.find(name).ok_or(BrokerError::DeviceNotFound)?;
+        DevicePower::of(tree, entry)
+    }
+}
+
+/// Delete the capability in `slot` of the broker's own CSpace
+///
+/// The kernel refuses an empty slot, which callers treat as nothing to drop.
+fn delete_cap(slot: usize) {
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) 0x22u64, // SYS_CAP_DELETE
+            inlateout("x0") 0usize => _, // own CSpace
+            inlateout("x1") slot => _,
+            out("x8") _,
+        );
+    }
+}
+
+/// Copy a capability from the broker's CSpace into a client's
+fn copy_cap(src_slot: usize, dest_cnode: usize, dest_slot: usize) -> Result<()> {
+    let result: usize;
+    unsafe {
+        core::arch::asm!(
+            "svc #0",
+            in("x8") 0x21u64, // SYS_CAP_COPY
+            inlateout("x0") 0usize => result, // own CSpace
+            inlateout("x1") src_slot => _,
+            inlateout("x2") dest_cnode => _,
+            inlateout("x3") dest_slot => _,
+        );
+    }
+    if result == usize::MAX {
+        Err(BrokerError::SyscallFailed(result))
+    } else {
+        Ok(())
+    }
+}
+
+/// Mint a copy of the endpoint or notification in `src_slot` badged
+/// `badge` into `dest_slot`, both in the broker's CSpace
+fn mint_cap(src_slot: usize, dest_slot: usize, badge: u64) -> Result<()> {
+    let result: usize;
+    unsafe {
+        core::arch::asm!(
+            "svc #0",
+            in("x8") 0x20u64, // SYS_CAP_MINT
+            inlateout("x0") 0usize => result, // own CSpace
+            inlateout("x1") src_slot => _,
+            inlateout("x2") dest_slot => _,
+            inlateout("x3") badge => _,
+        );
+    }
+    if result == usize::MAX {
+        Err(BrokerError::SyscallFailed(result))
+    } else {
+        Ok(())
+    }
+}
+
+/// `SYS_THREAD_STATS` state of a thread stopped on a fault
+const THREAD_BLOCKED_FAULT: u64 = 7;
+
+/// TIDs of the threads that are alive and not stopped on a fault
+fn live_threads() -> Vec<usize> {
+    // ThreadStats: tid, priority, state, then counters (8 × u64)
+    let mut stats = [0u64; 8];
+    let mut live = Vec::new();
+    for index in 0usize.. {
+        let result: usize;
+        unsafe {
+            core::arch::asm!(
+                "svc #0",
+                in("x8") 0x2Cu64, // SYS_THREAD_STATS
+                inlateout("x0") index => result,
+                inlateout("x1") stats.as_mut_ptr() as usize => _,
+                inlateout("x2") core::mem::size_of_val(&stats) => _,
+            );
+        }
+        if result == usize::MAX {
+            break;
+        }
+        if stats[2] != THREAD_BLOCKED_FAULT {
+            live.push(stats[0] as usize);
+        }
+    }
+    live
 }
 
 #[cfg(test)]
@@ -408,4 +1152,25 @@ mod tests {
         assert_eq!(slot1, 100);
         assert_eq!(slot2, 101);
     }
+
+    #[test]
+    fn test_free_cap_slot_reuse() {
+        let mut broker = CapabilityBroker::init().unwrap();
+
+        let slot1 = broker.allocate_cap_slot(CapabilityType::Device).unwrap();
+        let slot2 = broker.allocate_cap_slot(CapabilityType::Device).unwrap();
+        broker.free_cap_slot(slot1);
+        assert_eq!(broker.capability_usage_by_type().1, 1);
+
+        let slot3 = broker.allocate_cap_slot(CapabilityType::Memory).unwrap();
+        assert_eq!(slot3, slot1);
+        assert_eq!(broker.capability_usage_by_type(), (1, 1, 0, 0));
+        assert_eq!(broker.allocate_cap_slot(CapabilityType::Device).unwrap(), slot2 + 1);
+
+        // A second free of the same slot must not hand it out twice
+        broker.free_cap_slot(slot2);
+        broker.free_cap_slot(slot2);
+        assert_eq!(broker.allocate_cap_slot(CapabilityType::Device).unwrap(), slot2);
+        assert_eq!(broker.allocate_cap_slot(CapabilityType::Device).unwrap(), slot2 + 2);
+    }
 }
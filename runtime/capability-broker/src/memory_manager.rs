@@ -2,7 +2,23 @@
 //!
 //! Manages memory allocation from untyped regions.
 
-use crate::{BrokerError, Result, boot_info::BootInfo};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{
+    BrokerError, Result,
+    boot_info::{BootInfo, CapabilityType, UntypedRegion},
+};
+
+/// Smallest unit carved from untyped memory
+const PAGE_SIZE: usize = 4096;
+
+/// Slot of the root task's UntypedMemory capability, if boot info does not
+/// list one
+const ROOT_UNTYPED_SLOT: usize = 1;
+
+/// SYS_RETYPE object type for a page
+const OBJECT_TYPE_PAGE: usize = 8;
 
 /// Memory region
 #[derive(Debug)]
@@ -15,59 +31,338 @@ pub struct MemoryRegion {
     pub cap_slot: usize,
 }
 
+/// Which untyped regions an allocation may come from
+///
+/// Device untypeds cover MMIO windows: retyping frames out of them for
+/// ordinary use would hand a component device registers instead of RAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UntypedKind {
+    /// Regular RAM
+    Ram,
+    /// Device memory
+    Device,
+}
+
+impl UntypedKind {
+    fn of(region: &UntypedRegion) -> Self {
+        if region.is_device {
+            Self::Device
+        } else {
+            Self::Ram
+        }
+    }
+}
+
+/// Memory carved from an untyped region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UntypedAllocation {
+    /// Index of the region in the boot info's untyped list
+    pub region: usize,
+    /// Physical address
+    pub phys_addr: usize,
+    /// Size in bytes (a whole number of pages)
+    pub size: usize,
+}
+
+/// Size of a page object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSize {
+    /// 4KB page
+    Small,
+    /// 2MB block
+    Large,
+    /// 1GB block
+    Huge,
+}
+
+impl FrameSize {
+    /// log2 of the size in bytes
+    pub const fn size_bits(self) -> usize {
+        match self {
+            Self::Small => 12,
+            Self::Large => 21,
+            Self::Huge => 30,
+        }
+    }
+
+    /// Size in bytes
+    pub const fn bytes(self) -> usize {
+        1 << self.size_bits()
+    }
+}
+
+/// A page capability retyped from untyped memory
+///
+/// The capability lives in the broker's CSpace; copy it to share the frame
+/// or map `phys_addr` into a component.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameCap {
+    /// Capability slot holding the page
+    pub cap_slot: usize,
+    /// Physical address, aligned to `size`
+    pub phys_addr: usize,
+    /// Page size
+    pub size: FrameSize,
+}
+
 /// Memory Manager
 pub struct MemoryManager {
-    /// Copy of boot info for untyped regions
-    boot_info: Option<&'static BootInfo>,
-    /// Next untyped region to allocate from
-    next_untyped_idx: usize,
+    /// Slot of the UntypedMemory capability frames are retyped from
+    untyped_cap: usize,
+    /// Untyped regions from boot info
+    untyped: &'static [UntypedRegion],
+    /// Bytes handed out of each untyped region
+    watermarks: Vec<usize>,
 }
 
 impl MemoryManager {
     /// Create from boot info
     pub(crate) fn new_from_boot_info(boot_info: &'static BootInfo) -> Self {
-        Self {
-            boot_info: Some(boot_info),
-            next_untyped_idx: 0,
+        let untyped =
+            &boot_info.untyped_regions[..boot_info.num_untyped_regions as usize];
+        let mut manager = Self::with_untyped(untyped);
+        if let Some(cap) = boot_info
+            .initial_caps()
+            .find(|cap| cap.cap_type == CapabilityType::Untyped)
+        {
+            manager.untyped_cap = cap.slot as usize;
         }
+        manager
     }
 
     /// Create new (legacy, for tests)
     #[allow(dead_code)]
     pub(crate) fn new() -> Self {
+        Self::with_untyped(&[])
+    }
+
+    fn with_untyped(untyped: &'static [UntypedRegion]) -> Self {
         Self {
-            boot_info: None,
-            next_untyped_idx: 0,
+            untyped_cap: ROOT_UNTYPED_SLOT,
+            untyped,
+            watermarks: vec![0; untyped.len()],
         }
     }
 
     /// Allocate memory
     pub(crate) fn allocate(&mut self, size: usize, cap_slot: usize) -> Result<MemoryRegion> {
-        // Make syscall to kernel
-        let phys_addr = unsafe {
-            let mut addr: usize;
+        let phys_addr = allocate_physical(size).ok_or(BrokerError::OutOfMemory)?;
+
+        Ok(MemoryRegion {
+            phys_addr,
+            size,
+            cap_slot,
+        })
+    }
+
+    /// Retype a page of `size` from untyped memory into `cap_slot`
+    ///
+    /// The kernel aligns the page to its size. Returns the physical address.
+    pub(crate) fn retype_frame(&mut self, size: FrameSize, cap_slot: usize) -> Result<usize> {
+        let result: usize;
+        unsafe {
             core::arch::asm!(
                 "mov x8, {syscall_num}",
-                "mov x0, {size}",
                 "svc #0",
-                "mov {result}, x0",
-                syscall_num = in(reg) 0x11u64, // SYS_MEMORY_ALLOCATE
-                size = in(reg) size,
-                result = out(reg) addr,
+                syscall_num = in(reg) 0x26u64, // SYS_RETYPE
+                inlateout("x0") self.untyped_cap => result,
+                inlateout("x1") OBJECT_TYPE_PAGE => _,
+                inlateout("x2") size.size_bits() => _,
+                inlateout("x3") 0usize => _, // own CSpace
+                inlateout("x4") cap_slot => _,
                 out("x8") _,
-                out("x0") _,
             );
-            addr
-        };
+        }
+
+        if result == usize::MAX {
+            Err(BrokerError::OutOfMemory)
+        } else {
+            Ok(result)
+        }
+    }
 
-        if phys_addr == usize::MAX {
+    /// Carve `size` bytes aligned to `align` out of an untyped region of
+    /// the given kind
+    ///
+    /// `size` is rounded up to whole pages and `align` raised to at least a
+    /// page; `align` must be a power of two. Regions are tried in boot info
+    /// order, and memory is never handed out twice.
+    pub(crate) fn allocate_untyped(
+        &mut self,
+        size: usize,
+        align: usize,
+        kind: UntypedKind,
+    ) -> Result<UntypedAllocation> {
+        if size == 0 || !align.is_power_of_two() {
             return Err(BrokerError::OutOfMemory);
         }
+        let size = size.checked_next_multiple_of(PAGE_SIZE).ok_or(BrokerError::OutOfMemory)?;
+        let align = align.max(PAGE_SIZE);
 
-        Ok(MemoryRegion {
-            phys_addr,
-            size,
-            cap_slot,
-        })
+        for (index, region) in self.untyped.iter().enumerate() {
+            if UntypedKind::of(region) != kind {
+                continue;
+            }
+            if let Some(paddr) = region.allocate(&mut self.watermarks[index], size, align) {
+                return Ok(UntypedAllocation {
+                    region: index,
+                    phys_addr: paddr as usize,
+                    size,
+                });
+            }
+        }
+        Err(BrokerError::OutOfMemory)
+    }
+
+    /// Bytes of untyped memory of the given kind not yet handed out
+    pub(crate) fn untyped_available(&self, kind: UntypedKind) -> usize {
+        self.untyped
+            .iter()
+            .zip(&self.watermarks)
+            .filter(|(region, _)| UntypedKind::of(region) == kind)
+            .map(|(region, used)| region.size() - used)
+            .sum()
+    }
+}
+
+/// Allocate physically contiguous memory from the kernel
+///
+/// Returns the physical address (page-aligned), or `None` if the kernel is
+/// out of memory.
+pub(crate) fn allocate_physical(size: usize) -> Option<usize> {
+    // Make syscall to kernel
+    let phys_addr = unsafe {
+        let mut addr: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "mov x0, {size}",
+            "svc #0",
+            "mov {result}, x0",
+            syscall_num = in(reg) 0x11u64, // SYS_MEMORY_ALLOCATE
+            size = in(reg) size,
+            result = out(reg) addr,
+            out("x8") _,
+            out("x0") _,
+        );
+        addr
+    };
+
+    if phys_addr == usize::MAX {
+        None
+    } else {
+        Some(phys_addr)
+    }
+}
+
+/// Memory type of a mapping
+///
+/// Selects the memory attributes the kernel puts in the page table entries
+/// (bits 4-5 of the SYS_MEMORY_MAP permissions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryType {
+    /// Normal memory, write-back cacheable: RAM
+    #[default]
+    Normal,
+    /// Device-nGnRnE: MMIO registers. Every access reaches the device, in
+    /// program order
+    Device,
+    /// Normal memory, non-cacheable: memory shared with devices that do not
+    /// snoop the caches, such as DMA descriptor rings
+    NonCacheable,
+    /// Device-GRE, write-combining: framebuffers. Writes may be merged and
+    /// reordered; accesses must be aligned
+    WriteCombining,
+}
+
+impl MemoryType {
+    /// SYS_MEMORY_MAP permission bits selecting this type
+    pub const fn map_bits(self) -> usize {
+        (self as usize) << 4
+    }
+}
+
+/// Map physical memory into the broker's address space
+///
+/// `permissions` takes the SYS_MEMORY_MAP bits (read=0x1, write=0x2,
+/// exec=0x4, [`MemoryType::map_bits`], 0x100 for 2MB blocks). Returns the
+/// virtual address, or `None` if the kernel refused the mapping.
+pub(crate) fn map_physical(paddr: usize, size: usize, permissions: usize) -> Option<usize> {
+    let vaddr = unsafe {
+        let mut addr: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) 0x15u64, // SYS_MEMORY_MAP
+            inlateout("x0") paddr => addr,
+            inlateout("x1") size => _,
+            inlateout("x2") permissions => _,
+            out("x8") _,
+        );
+        addr
+    };
+
+    if vaddr == usize::MAX {
+        None
+    } else {
+        Some(vaddr)
+    }
+}
+
+/// Unmap a range mapped with [`map_physical`]
+///
+/// The physical memory itself is left alone; only the broker's mapping of
+/// it goes away.
+pub(crate) fn unmap_virtual(vaddr: usize, size: usize) -> Result<()> {
+    let result = unsafe {
+        let mut result: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) 0x16u64, // SYS_MEMORY_UNMAP
+            inlateout("x0") vaddr => result,
+            inlateout("x1") size => _,
+            out("x8") _,
+        );
+        result
+    };
+
+    if result == usize::MAX {
+        Err(BrokerError::SyscallFailed(result))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untyped_alignment_and_kind() {
+        let regions = alloc::vec![
+            UntypedRegion::new(0x0900_0000, 16, true),
+            UntypedRegion::new(0x4010_1000, 20, false),
+        ];
+        let mut memory = MemoryManager::with_untyped(regions.leak());
+
+        let ram = memory.allocate_untyped(100, 1, UntypedKind::Ram).unwrap();
+        assert_eq!(ram, UntypedAllocation { region: 1, phys_addr: 0x4010_1000, size: 4096 });
+
+        let aligned = memory.allocate_untyped(0x4000, 0x10000, UntypedKind::Ram).unwrap();
+        assert_eq!(aligned.phys_addr, 0x4011_0000);
+        assert_eq!(memory.untyped_available(UntypedKind::Ram), 0x10_1000 - 0x1_4000);
+
+        let device = memory.allocate_untyped(4096, 4096, UntypedKind::Device).unwrap();
+        assert_eq!(device.region, 0);
+        assert_eq!(device.phys_addr, 0x0900_0000);
+
+        assert_eq!(
+            memory.allocate_untyped(0x10_0000, 4096, UntypedKind::Ram),
+            Err(BrokerError::OutOfMemory)
+        );
+        assert_eq!(
+            memory.allocate_untyped(4096, 3, UntypedKind::Ram),
+            Err(BrokerError::OutOfMemory)
+        );
     }
 }
@@ -0,0 +1,365 @@
+//! PCI Enumeration
+//!
+//! Finds PCI functions by scanning the ECAM configuration space of the
+//! host bridges in the device tree (`pci-host-ecam-generic`), so
+//! `DeviceId::Pci { vendor, device }` resolves to the function's real BARs
+//! and interrupt.
+//!
+//! - Memory BARs are sized; BARs firmware left unassigned are given
+//!   addresses from the bridge's `ranges` windows (64-bit BARs prefer the
+//!   64-bit window) and memory decoding is enabled
+//! - I/O BARs are not assigned (no port I/O on AArch64)
+//! - The interrupt pin is resolved through the bridge's `interrupt-map`,
+//!   the IOMMU stream through its `iommu-map`
+//!
+//! Buses are scanned flat over the bridge's `bus-range`: PCI-to-PCI
+//! bridges must already have their bus numbers configured.
+
+use alloc::vec::Vec;
+
+use crate::device_tree::{DeviceEntry, DeviceTree, MmioRange};
+use crate::iommu::{self, StreamId};
+use crate::memory_manager::{self, MemoryType};
+
+/// Configuration space register offsets
+const PCI_VENDOR_ID: usize = 0x00;
+const PCI_COMMAND: usize = 0x04;
+const PCI_CLASS_REVISION: usize = 0x08;
+const PCI_HEADER_TYPE: usize = 0x0C; // byte 2 of the dword
+const PCI_BAR0: usize = 0x10;
+const PCI_INTERRUPT: usize = 0x3C; // line in byte 0, pin in byte 1
+
+/// Command register bits
+const COMMAND_MEMORY: u32 = 1 << 1;
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+
+/// Header type bits
+const HEADER_MULTIFUNCTION: u32 = 0x80;
+const HEADER_TYPE_MASK: u32 = 0x7f;
+
+/// `ranges` space codes (bits 24-25 of `phys.hi`)
+const SPACE_MEM32: u32 = 0b10;
+const SPACE_MEM64: u32 = 0b11;
+
+/// ECAM space per bus
+const ECAM_BUS_SIZE: usize = 1 << 20;
+
+/// A PCI function found by enumeration
+#[derive(Debug, Clone)]
+pub struct PciFunction {
+    /// Bus number
+    pub bus: u8,
+    /// Device number (0-31)
+    pub device: u8,
+    /// Function number (0-7)
+    pub function: u8,
+    /// Vendor ID
+    pub vendor_id: u16,
+    /// Device ID
+    pub device_id: u16,
+    /// Class code, subclass and programming interface (24 bits)
+    pub class: u32,
+    /// Memory BARs by index, in physical addresses (`None` for unused,
+    /// I/O and unassignable BARs, and the upper half of a 64-bit BAR)
+    pub bars: [Option<MmioRange>; 6],
+    /// Legacy interrupt (INTx) as a GIC interrupt ID
+    pub irq: Option<u32>,
+    /// IOMMU stream of the function's DMA, if it sits behind one
+    pub stream_id: Option<StreamId>,
+    /// Configuration space of the function, mapped
+    config: usize,
+}
+
+impl PciFunction {
+    /// Enable memory decoding and bus mastering (DMA)
+    pub fn enable(&self) {
+        let command = self.read(PCI_COMMAND);
+        self.write(PCI_COMMAND, command | COMMAND_MEMORY | COMMAND_BUS_MASTER);
+    }
+
+    /// Stop bus mastering, leaving memory decoding on as enumeration left it
+    pub fn disable(&self) {
+        let command = self.read(PCI_COMMAND);
+        self.write(PCI_COMMAND, command & !COMMAND_BUS_MASTER);
+    }
+
+    /// First memory BAR, for devices with a single register block
+    pub fn first_bar(&self) -> Option<MmioRange> {
+        self.bars.iter().flatten().next().copied()
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: `config` maps this function's 4 KiB of ECAM space
+        unsafe { core::ptr::read_volatile((self.config + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        // SAFETY: as for `read`
+        unsafe { core::ptr::write_volatile((self.config + offset) as *mut u32, value) }
+    }
+}
+
+/// A host bridge address window (`ranges` entry)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    /// PCI bus address
+    pci: u64,
+    /// CPU physical address
+    cpu: u64,
+    size: u64,
+    mem64: bool,
+    /// Next free PCI address for BAR assignment
+    next: u64,
+}
+
+impl Window {
+    fn contains(&self, pci: u64) -> bool {
+        pci >= self.pci && pci - self.pci < self.size
+    }
+
+    fn cpu_address(&self, pci: u64) -> u64 {
+        self.cpu + (pci - self.pci)
+    }
+
+    /// Allocate `size` bytes (a power of two), aligned to `size`
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        let base = self.next.checked_next_multiple_of(size)?;
+        if base.checked_add(size)? > self.pci + self.size {
+            return None;
+        }
+        self.next = base + size;
+        Some(base)
+    }
+}
+
+/// Parse a host bridge's memory windows from its `ranges`
+fn parse_windows(ranges: &[u8], parent_address_cells: usize, size_cells: usize) -> Vec<Window> {
+    let cell = |entry: &[u8], i: usize| {
+        u32::from_be_bytes([entry[i * 4], entry[i * 4 + 1], entry[i * 4 + 2], entry[i * 4 + 3]])
+    };
+    let read = |entry: &[u8], start: usize, count: usize| {
+        (start..start + count).fold(0u64, |acc, i| (acc << 32) | cell(entry, i) as u64)
+    };
+
+    let stride = (3 + parent_address_cells + size_cells) * 4;
+    ranges
+        .chunks_exact(stride)
+        .filter_map(|entry| {
+            let space = (cell(entry, 0) >> 24) & 0b11;
+            let pci = read(entry, 1, 2);
+            let window = Window {
+                pci,
+                cpu: read(entry, 3, parent_address_cells),
+                size: read(entry, 3 + parent_address_cells, size_cells),
+                mem64: space == SPACE_MEM64,
+                next: pci,
+            };
+            matches!(space, SPACE_MEM32 | SPACE_MEM64).then_some(window)
+        })
+        .collect()
+}
+
+/// PCI functions of all ECAM host bridges
+pub struct PciBus {
+    functions: Vec<PciFunction>,
+}
+
+impl PciBus {
+    /// Enumerate the host bridges described by the device tree
+    pub(crate) fn probe(tree: &DeviceTree) -> Self {
+        let mut functions = Vec::new();
+        for host in tree.find_compatible("pci-host-ecam-generic") {
+            probe_host(tree, host, &mut functions);
+        }
+        Self { functions }
+    }
+
+    /// An empty bus, when there is no device tree
+    pub(crate) fn empty() -> Self {
+        Self { functions: Vec::new() }
+    }
+
+    /// All functions, in bus/device/function order per host bridge
+    pub fn functions(&self) -> &[PciFunction] {
+        &self.functions
+    }
+
+    /// Find the first function with the given vendor and device ID
+    pub fn find(&self, vendor_id: u16, device_id: u16) -> Option<&PciFunction> {
+        self.functions
+            .iter()
+            .find(|f| f.vendor_id == vendor_id && f.device_id == device_id)
+    }
+}
+
+/// Map a host bridge's ECAM space and scan its buses
+fn probe_host(tree: &DeviceTree, host: &DeviceEntry, functions: &mut Vec<PciFunction>) {
+    let Some(ecam) = host.mmio.first() else {
+        return;
+    };
+    let max_buses = (ecam.size as usize / ECAM_BUS_SIZE).clamp(1, 256);
+    let (bus_start, bus_end) = match host.property("bus-range") {
+        Some(range) if range.len() >= 8 => (
+            u32::from_be_bytes([range[0], range[1], range[2], range[3]]) as usize,
+            u32::from_be_bytes([range[4], range[5], range[6], range[7]]) as usize,
+        ),
+        _ => (0, max_buses - 1),
+    };
+    let buses = (bus_end + 1).saturating_sub(bus_start).min(max_buses);
+
+    // 2MB blocks when possible: a full ECAM region is 256MB
+    let size = buses * ECAM_BUS_SIZE;
+    let block = if ecam.base.is_multiple_of(0x20_0000) && size.is_multiple_of(0x20_0000) { 0x100 } else { 0 };
+    let permissions = 0x3 | MemoryType::Device.map_bits() | block;
+    let Some(ecam_virt) = memory_manager::map_physical(ecam.base as usize, size, permissions) else {
+        return;
+    };
+
+    let size_cells = host.cell_property("#size-cells", 2) as usize;
+    let mut windows = host
+        .property("ranges")
+        .map(|r| parse_windows(r, host.parent_address_cells(), size_cells))
+        .unwrap_or_default();
+
+    for bus in 0..buses {
+        for device in 0..32 {
+            for function in 0..8 {
+                let config = ecam_virt + (bus << 20) + (device << 15) + (function << 12);
+                let id = unsafe { core::ptr::read_volatile((config + PCI_VENDOR_ID) as *const u32) };
+                if id & 0xffff == 0xffff {
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+
+                let mut func = PciFunction {
+                    bus: (bus_start + bus) as u8,
+                    device: device as u8,
+                    function: function as u8,
+                    vendor_id: id as u16,
+                    device_id: (id >> 16) as u16,
+                    class: 0,
+                    bars: [None; 6],
+                    irq: None,
+                    stream_id: None,
+                    config,
+                };
+                func.class = func.read(PCI_CLASS_REVISION) >> 8;
+
+                let header = (func.read(PCI_HEADER_TYPE) >> 16) & 0xff;
+                if header & HEADER_TYPE_MASK == 0 {
+                    size_bars(&mut func, &mut windows);
+                }
+
+                let pin = (func.read(PCI_INTERRUPT) >> 8) & 0xff;
+                if pin != 0 {
+                    let bdf = ((func.bus as u32) << 16) | ((device as u32) << 11) | ((function as u32) << 8);
+                    func.irq = tree.map_interrupt(host, &[bdf, 0, 0], &[pin]);
+                }
+
+                let rid = ((func.bus as u32) << 8) | ((device as u32) << 3) | function as u32;
+                func.stream_id = iommu::pci_stream(host, rid);
+
+                functions.push(func);
+                if function == 0 && header & HEADER_MULTIFUNCTION == 0 {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Size a type 0 function's memory BARs, assigning unassigned ones
+fn size_bars(func: &mut PciFunction, windows: &mut [Window]) {
+    // No decoding while the BARs are probed
+    let command = func.read(PCI_COMMAND);
+    func.write(PCI_COMMAND, command & !(COMMAND_MEMORY | COMMAND_BUS_MASTER));
+
+    let mut index = 0;
+    while index < 6 {
+        let offset = PCI_BAR0 + index * 4;
+        let original = func.read(offset);
+        if original & 1 != 0 {
+            // I/O BAR
+            index += 1;
+            continue;
+        }
+
+        let mem64 = (original >> 1) & 0b11 == 0b10;
+        func.write(offset, u32::MAX);
+        let mut mask = (func.read(offset) & !0xf) as u64 | 0xffff_ffff_0000_0000;
+        func.write(offset, original);
+        let mut address = (original & !0xf) as u64;
+        if mem64 && index < 5 {
+            let upper = func.read(offset + 4);
+            func.write(offset + 4, u32::MAX);
+            mask = (mask & 0xffff_ffff) | (func.read(offset + 4) as u64) << 32;
+            func.write(offset + 4, upper);
+            address |= (upper as u64) << 32;
+        }
+
+        let size = (!mask).wrapping_add(1);
+        if mask as u32 != 0 && size != 0 {
+            func.bars[index] = assign_bar(func, offset, mem64, address, size, windows);
+        }
+        index += if mem64 { 2 } else { 1 };
+    }
+
+    func.write(PCI_COMMAND, command | COMMAND_MEMORY);
+}
+
+/// Translate an assigned BAR, or assign one from the bridge's windows
+fn assign_bar(
+    func: &PciFunction,
+    offset: usize,
+    mem64: bool,
+    address: u64,
+    size: u64,
+    windows: &mut [Window],
+) -> Option<MmioRange> {
+    if address != 0 {
+        let window = windows.iter().find(|w| w.contains(address))?;
+        return Some(MmioRange { base: window.cpu_address(address), size });
+    }
+
+    // 64-bit BARs go above 4 GiB when there is room, 32-bit BARs below
+    let mut candidates: Vec<usize> = (0..windows.len()).filter(|&i| mem64 || !windows[i].mem64).collect();
+    candidates.sort_by_key(|&i| windows[i].mem64 != mem64);
+    let (window, pci) = candidates
+        .into_iter()
+        .find_map(|i| Some((i, windows[i].allocate(size)?)))?;
+
+    func.write(offset, pci as u32 | if mem64 { 0b100 } else { 0 });
+    if mem64 {
+        func.write(offset + 4, (pci >> 32) as u32);
+    }
+    Some(MmioRange { base: windows[window].cpu_address(pci), size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows() {
+        // QEMU virt: I/O, 32-bit and 64-bit memory windows
+        let cells: [u32; 21] = [
+            0x0100_0000, 0, 0, 0, 0x3eff_0000, 0, 0x1_0000,
+            0x0200_0000, 0, 0x1000_0000, 0, 0x1000_0000, 0, 0x2eff_0000,
+            0x0300_0000, 0x80, 0, 0x80, 0, 0x80, 0,
+        ];
+        let ranges: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+        let mut windows = parse_windows(&ranges, 2, 2);
+        assert_eq!(windows.len(), 2);
+        assert!(!windows[0].mem64);
+        assert_eq!(windows[1].cpu, 0x80_0000_0000);
+
+        let window = &mut windows[0];
+        assert_eq!(window.allocate(0x1000), Some(0x1000_0000));
+        assert_eq!(window.allocate(0x4000), Some(0x1000_4000));
+        assert_eq!(window.allocate(0x1000), Some(0x1000_8000));
+        assert_eq!(window.allocate(0x8000_0000), None);
+        assert_eq!(window.cpu_address(0x1000_8000), 0x1000_8000);
+    }
+}
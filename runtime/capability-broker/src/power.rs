@@ -0,0 +1,221 @@
+//! Power Management
+//!
+//! SoC peripherals are dead until their clocks run, their power domain is
+//! up and their reset is released. The device tree says which ones a
+//! device needs (`clocks`, `resets`, `power-domains`); the controllers
+//! that provide them are SoC-specific, so their drivers implement
+//! [`PowerController`] and register it with the broker under the
+//! controller node's phandle.
+//!
+//! Powering a device up goes domains, then clocks, then resets; powering
+//! it down goes the other way round. Fixed clocks (`fixed-clock`,
+//! `fixed-factor-clock`) always run and need no controller.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::device_tree::{DeviceEntry, DeviceTree, Specifier};
+use crate::{BrokerError, Result};
+
+/// Clock, reset or power domain controller of the SoC
+///
+/// `cells` is the specifier from the device tree, without the phandle
+/// (`<&ccu 62>` gives `[62]`). Operations the controller does not provide
+/// fail with `DeviceNotFound`.
+pub trait PowerController {
+    /// Ungate (`true`) or gate a clock
+    fn set_clock(&mut self, cells: &[u32], enabled: bool) -> Result<()> {
+        let _ = (cells, enabled);
+        Err(BrokerError::DeviceNotFound)
+    }
+
+    /// Assert (`true`) or deassert a reset line
+    fn set_reset(&mut self, cells: &[u32], asserted: bool) -> Result<()> {
+        let _ = (cells, asserted);
+        Err(BrokerError::DeviceNotFound)
+    }
+
+    /// Power a domain up (`true`) or down
+    fn set_power_domain(&mut self, cells: &[u32], on: bool) -> Result<()> {
+        let _ = (cells, on);
+        Err(BrokerError::DeviceNotFound)
+    }
+}
+
+/// What a device needs to run, from its device tree node
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DevicePower {
+    /// Power domains, in `power-domains` order
+    pub domains: Vec<Specifier>,
+    /// Gateable clocks, in `clocks` order (fixed clocks left out)
+    pub clocks: Vec<Specifier>,
+    /// Reset lines, in `resets` order
+    pub resets: Vec<Specifier>,
+}
+
+impl DevicePower {
+    /// Read `entry`'s power requirements
+    ///
+    /// Fails with `InvalidDeviceTree` if a list refers to a node that
+    /// does not say how long its specifiers are.
+    pub fn of(tree: &DeviceTree, entry: &DeviceEntry) -> Result<Self> {
+        let list = |property: &str, cells: &str| match entry.property(property) {
+            None => Ok(Vec::new()),
+            Some(_) => tree.specifiers(entry, property, cells).ok_or(BrokerError::InvalidDeviceTree),
+        };
+        let fixed = |s: &Specifier| {
+            tree.provider_is_compatible(s.provider, "fixed-clock")
+                || tree.provider_is_compatible(s.provider, "fixed-factor-clock")
+        };
+
+        let mut clocks = list("clocks", "#clock-cells")?;
+        clocks.retain(|s| !fixed(s));
+        Ok(Self {
+            domains: list("power-domains", "#power-domain-cells")?,
+            clocks,
+            resets: list("resets", "#reset-cells")?,
+        })
+    }
+}
+
+/// A step of powering a device up, undone in reverse to power it down
+#[derive(Clone, Copy)]
+enum Step {
+    Domain,
+    Clock,
+    Reset,
+}
+
+/// Registered power controllers, by phandle
+pub(crate) struct PowerManager {
+    controllers: Vec<(u32, Box<dyn PowerController>)>,
+}
+
+impl PowerManager {
+    pub(crate) fn new() -> Self {
+        Self {
+            controllers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn register(&mut self, phandle: u32, controller: Box<dyn PowerController>) -> Result<()> {
+        if self.controllers.iter().any(|(p, _)| *p == phandle) {
+            return Err(BrokerError::ResourceInUse);
+        }
+        self.controllers.push((phandle, controller));
+        Ok(())
+    }
+
+    /// Bring up a device's domains, clocks and resets, in that order
+    ///
+    /// On failure the steps already taken are undone.
+    pub(crate) fn power_on(&mut self, power: &DevicePower) -> Result<()> {
+        let steps = Self::steps(power);
+        for (done, &(step, specifier)) in steps.iter().enumerate() {
+            if let Err(e) = self.apply(step, specifier, true) {
+                for &(step, specifier) in steps[..done].iter().rev() {
+                    let _ = self.apply(step, specifier, false);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Assert a device's resets, gate its clocks and drop its domains
+    ///
+    /// Every step is attempted; the first failure is returned.
+    pub(crate) fn power_off(&mut self, power: &DevicePower) -> Result<()> {
+        let mut result = Ok(());
+        for &(step, specifier) in Self::steps(power).iter().rev() {
+            result = result.and(self.apply(step, specifier, false));
+        }
+        result
+    }
+
+    fn steps(power: &DevicePower) -> Vec<(Step, &Specifier)> {
+        let tag = |step| move |s| (step, s);
+        power
+            .domains
+            .iter()
+            .map(tag(Step::Domain))
+            .chain(power.clocks.iter().map(tag(Step::Clock)))
+            .chain(power.resets.iter().map(tag(Step::Reset)))
+            .collect()
+    }
+
+    /// Take `step` towards running (`up`) or stopped
+    fn apply(&mut self, step: Step, specifier: &Specifier, up: bool) -> Result<()> {
+        let (_, controller) = self
+            .controllers
+            .iter_mut()
+            .find(|(p, _)| *p == specifier.provider)
+            .ok_or(BrokerError::DeviceNotFound)?;
+        let cells = &specifier.cells;
+        match step {
+            Step::Domain => controller.set_power_domain(cells, up),
+            Step::Clock => controller.set_clock(cells, up),
+            Step::Reset => controller.set_reset(cells, !up),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use core::cell::RefCell;
+
+    /// Logs (kind, id, on) and fails on clock 13
+    struct Ccu(Rc<RefCell<Vec<(char, u32, bool)>>>);
+
+    impl PowerController for Ccu {
+        fn set_clock(&mut self, cells: &[u32], enabled: bool) -> Result<()> {
+            if cells[0] == 13 && enabled {
+                return Err(BrokerError::SyscallFailed(0));
+            }
+            self.0.borrow_mut().push(('c', cells[0], enabled));
+            Ok(())
+        }
+
+        fn set_reset(&mut self, cells: &[u32], asserted: bool) -> Result<()> {
+            self.0.borrow_mut().push(('r', cells[0], !asserted));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_power_sequencing() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut power = PowerManager::new();
+        power.register(2, Box::new(Ccu(log.clone()))).unwrap();
+        assert_eq!(power.register(2, Box::new(Ccu(log.clone()))), Err(BrokerError::ResourceInUse));
+
+        let at = |cell| Specifier { provider: 2, cells: vec![cell] };
+        let uart = DevicePower {
+            domains: vec![],
+            clocks: vec![at(62), at(63)],
+            resets: vec![at(49)],
+        };
+        power.power_on(&uart).unwrap();
+        power.power_off(&uart).unwrap();
+        assert_eq!(
+            *log.borrow(),
+            [
+                ('c', 62, true), ('c', 63, true), ('r', 49, true),
+                ('r', 49, false), ('c', 63, false), ('c', 62, false),
+            ]
+        );
+
+        // A failing clock rolls back the ones already running
+        log.borrow_mut().clear();
+        let broken = DevicePower { clocks: vec![at(62), at(13)], ..uart.clone() };
+        assert_eq!(power.power_on(&broken), Err(BrokerError::SyscallFailed(0)));
+        assert_eq!(*log.borrow(), [('c', 62, true), ('c', 62, false)]);
+
+        // The domain's controller was never registered
+        let domain = DevicePower { domains: vec![Specifier { provider: 3, cells: vec![0] }], ..uart };
+        assert_eq!(power.power_on(&domain), Err(BrokerError::DeviceNotFound));
+    }
+}
@@ -3,6 +3,19 @@
 //! Manages service registration and discovery for IPC.
 //! Allows producers (servers) to register services by name,
 //! and consumers (clients) to discover them.
+//!
+//! Discovery hands the consumer a connection, not just a name lookup: each
+//! consumer gets its own copy of the service's endpoint, minted with a badge
+//! the service has not handed out before, so the service can tell its
+//! clients apart by the badge its receives return.
+//!
+//! A service lives only as long as the component providing it: the broker
+//! unregisters it once its owner has exited or stopped on a fault, rather
+//! than connect clients to an endpoint nobody receives on.
+//!
+//! Services are named hierarchically ("kaal.uart.output") and carry
+//! metadata describing their protocol, so a component can enumerate a
+//! family of services with a prefix query and pick the ones it speaks.
 
 use crate::{Endpoint, Result, BrokerError};
 
@@ -12,6 +25,38 @@ const MAX_SERVICES: usize = 32;
 /// Maximum service name length
 const MAX_NAME_LEN: usize = 32;
 
+/// A client's connection to a service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceConnection {
+    /// The client's badged copy of the service endpoint
+    pub endpoint: Endpoint,
+    /// Badge the service sees on the client's messages
+    pub badge: u64,
+}
+
+/// What a service speaks, as declared by its provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServiceMetadata {
+    /// Version of the service's protocol
+    pub version: u32,
+    /// Protocol identifier, agreed between the service and its clients
+    /// (0: unspecified)
+    pub protocol_id: u32,
+    /// Largest message the service accepts, in bytes (0: unspecified)
+    pub max_message_size: usize,
+}
+
+/// A registered service, as listed by a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceInfo<'a> {
+    /// Service name
+    pub name: &'a str,
+    /// Process ID of the service provider
+    pub owner_pid: usize,
+    /// What the service speaks
+    pub metadata: ServiceMetadata,
+}
+
 /// A registered service
 #[derive(Debug, Clone, Copy)]
 pub struct ServiceRecord {
@@ -23,6 +68,10 @@ pub struct ServiceRecord {
     endpoint: Endpoint,
     /// Process ID that registered this service
     owner_pid: usize,
+    /// Protocol metadata declared at registration
+    metadata: ServiceMetadata,
+    /// Badge for the next client to connect
+    next_badge: u64,
     /// Is this slot allocated?
     allocated: bool,
 }
@@ -34,6 +83,8 @@ impl ServiceRecord {
             name_len: 0,
             endpoint: Endpoint { cap_slot: 0, id: 0 },
             owner_pid: 0,
+            metadata: ServiceMetadata::default(),
+            next_badge: 1,
             allocated: false,
         }
     }
@@ -80,6 +131,7 @@ impl ServiceRegistry {
     /// * `name` - Service name (must be unique)
     /// * `endpoint` - IPC endpoint for the service
     /// * `owner_pid` - Process ID of the service provider
+    /// * `metadata` - What the service speaks
     ///
     /// # Returns
     ///
@@ -92,6 +144,7 @@ impl ServiceRegistry {
         name: &str,
         endpoint: Endpoint,
         owner_pid: usize,
+        metadata: ServiceMetadata,
     ) -> Result<()> {
         // Validate name length
         if name.is_empty() || name.len() > MAX_NAME_LEN {
@@ -112,6 +165,8 @@ impl ServiceRegistry {
                 service.name_len = name.len();
                 service.endpoint = endpoint;
                 service.owner_pid = owner_pid;
+                service.metadata = metadata;
+                service.next_badge = 1;
                 service.allocated = true;
                 self.num_services += 1;
                 return Ok(());
@@ -121,22 +176,17 @@ impl ServiceRegistry {
         Err(BrokerError::OutOfCapabilitySlots)
     }
 
-    /// Lookup a service by name
-    ///
-    /// # Arguments
-    ///
-    /// * `name` - Service name to lookup
-    ///
-    /// # Returns
-    ///
-    /// The service's endpoint, or an error if not found.
-    pub(crate) fn lookup_service(&self, name: &str) -> Result<Endpoint> {
-        for service in &self.services {
-            if service.matches(name) {
-                return Ok(service.endpoint);
-            }
-        }
-        Err(BrokerError::DeviceNotFound)
+    /// Lookup a service for a new client, returning its endpoint and a
+    /// badge no earlier client of the service was given
+    pub(crate) fn connect(&mut self, name: &str) -> Result<(Endpoint, u64)> {
+        let service = self
+            .services
+            .iter_mut()
+            .find(|s| s.matches(name))
+            .ok_or(BrokerError::DeviceNotFound)?;
+        let badge = service.next_badge;
+        service.next_badge += 1;
+        Ok((service.endpoint, badge))
     }
 
     /// Unregister a service
@@ -159,18 +209,132 @@ impl ServiceRegistry {
         Err(BrokerError::DeviceNotFound)
     }
 
+    /// Unregister every service `owner_pid` registered, returning how many
+    pub(crate) fn unregister_owner(&mut self, owner_pid: usize) -> usize {
+        let mut count = 0;
+        for service in &mut self.services {
+            if service.allocated && service.owner_pid == owner_pid {
+                service.allocated = false;
+                count += 1;
+            }
+        }
+        self.num_services -= count;
+        count
+    }
+
+    /// Unregister every service whose owner `alive` rejects, returning how
+    /// many
+    pub(crate) fn unregister_dead(&mut self, alive: impl Fn(usize) -> bool) -> usize {
+        let mut count = 0;
+        for service in &mut self.services {
+            if service.allocated && !alive(service.owner_pid) {
+                service.allocated = false;
+                count += 1;
+            }
+        }
+        self.num_services -= count;
+        count
+    }
+
+    /// Process ID that registered `name`
+    pub(crate) fn owner_of(&self, name: &str) -> Option<usize> {
+        self.services.iter().find(|s| s.matches(name)).map(|s| s.owner_pid)
+    }
+
     /// Get number of registered services
     pub(crate) fn num_services(&self) -> usize {
         self.num_services
     }
 
-    /// List all registered services
+    /// List the services whose names start with `prefix`
     ///
-    /// Returns an iterator over (name, endpoint) pairs.
-    pub(crate) fn list_services(&self) -> impl Iterator<Item = (&str, Endpoint)> {
-        self.services
-            .iter()
-            .filter(|s| s.allocated)
-            .filter_map(|s| s.name_str().map(|name| (name, s.endpoint)))
+    /// A trailing `*` is ignored, so "kaal.uart.*" lists the same services
+    /// as "kaal.uart."; "*" or "" lists them all.
+    pub(crate) fn list_services<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = ServiceInfo<'a>> + 'a {
+        let prefix = prefix.strip_suffix('*').unwrap_or(prefix);
+        self.services.iter().filter_map(move |s| {
+            let name = s.name_str()?;
+            name.starts_with(prefix).then_some(ServiceInfo {
+                name,
+                owner_pid: s.owner_pid,
+                metadata: s.metadata,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_badges() {
+        let mut registry = ServiceRegistry::new();
+        let endpoint = Endpoint { cap_slot: 120, id: 3 };
+        let metadata = ServiceMetadata::default();
+        registry.register_service("kaal.uart", endpoint, 7, metadata).unwrap();
+        registry
+            .register_service("kaal.fs", Endpoint { cap_slot: 121, id: 4 }, 8, metadata)
+            .unwrap();
+
+        assert_eq!(registry.connect("kaal.uart").unwrap().1, 1);
+        assert_eq!(registry.connect("kaal.uart").unwrap().1, 2);
+        assert_eq!(registry.connect("kaal.fs").unwrap().1, 1);
+        assert_eq!(registry.connect("kaal.net"), Err(BrokerError::DeviceNotFound));
+
+        assert_eq!(registry.unregister_owner(7), 1);
+        assert_eq!(registry.num_services(), 1);
+        assert_eq!(registry.connect("kaal.uart"), Err(BrokerError::DeviceNotFound));
+
+        // A service registered again starts its badges over
+        registry.register_service("kaal.uart", endpoint, 9, metadata).unwrap();
+        assert_eq!(registry.connect("kaal.uart").unwrap(), (endpoint, 1));
+    }
+
+    #[test]
+    fn test_list_by_prefix() {
+        let mut registry = ServiceRegistry::new();
+        let uart = ServiceMetadata { version: 2, protocol_id: 0x55, max_message_size: 4096 };
+        let endpoint = |cap_slot| Endpoint { cap_slot, id: 0 };
+        registry.register_service("kaal.uart.output", endpoint(120), 7, uart).unwrap();
+        registry.register_service("kaal.uart.input", endpoint(121), 7, uart).unwrap();
+        registry.register_service("kaal.uartx", endpoint(122), 8, uart).unwrap();
+        registry
+            .register_service("kaal.fs", endpoint(123), 9, ServiceMetadata::default())
+            .unwrap();
+
+        let names = |prefix| {
+            registry.list_services(prefix).map(|s| s.name).collect::<alloc::vec::Vec<_>>()
+        };
+        assert_eq!(names("kaal.uart.*"), ["kaal.uart.output", "kaal.uart.input"]);
+        assert_eq!(names("kaal.uart.*"), names("kaal.uart."));
+        assert_eq!(names("*").len(), 4);
+        assert_eq!(names("kaal.fs"), ["kaal.fs"]);
+        assert!(names("kaal.net.*").is_empty());
+
+        let output = registry.list_services("kaal.uart.output").next().unwrap();
+        assert_eq!(output, ServiceInfo { name: "kaal.uart.output", owner_pid: 7, metadata: uart });
+    }
+
+    #[test]
+    fn test_unregister_dead() {
+        let mut registry = ServiceRegistry::new();
+        let metadata = ServiceMetadata::default();
+        let endpoint = |cap_slot| Endpoint { cap_slot, id: 0 };
+        registry.register_service("kaal.uart.output", endpoint(120), 7, metadata).unwrap();
+        registry.register_service("kaal.uart.input", endpoint(121), 7, metadata).unwrap();
+        registry.register_service("kaal.fs", endpoint(122), 8, metadata).unwrap();
+
+        // Owner 7 exited: both its services go, the other stays
+        assert_eq!(registry.unregister_dead(|pid| pid != 7), 2);
+        assert_eq!(registry.owner_of("kaal.uart.output"), None);
+        assert_eq!(registry.owner_of("kaal.fs"), Some(8));
+        assert_eq!(registry.num_services(), 1);
+
+        // The name is free for the restarted component
+        registry.register_service("kaal.uart.output", endpoint(123), 9, metadata).unwrap();
     }
 }
@@ -0,0 +1,181 @@
+//! Resource Transactions
+//!
+//! A driver usually needs several resources before it can run: its
+//! device, the device's interrupt, a DMA pool, a few slots for the
+//! capabilities it will make. Requested one by one, a failure halfway
+//! leaves the earlier ones allocated, and it is on the caller to undo
+//! them in the right order. A [`ResourceTransaction`] stages them all and
+//! [`commit`](ResourceTransaction::commit)s them in one go: either every
+//! resource is granted, or whatever was taken is given back and the
+//! broker is left as it was.
+//!
+//! ```rust,no_run
+//! use capability_broker::{CapabilityBroker, DeviceId, IrqMode, MemoryType, ResourceTransaction};
+//!
+//! let mut broker = CapabilityBroker::init()?;
+//! let nic = ResourceTransaction::new(42)
+//!     .device(DeviceId::Pci { vendor: 0x8086, device: 0x100e }, MemoryType::Device)
+//!     .irq(nic_notification, IrqMode::Exclusive)
+//!     .dma_pool(64 * 1024, MemoryType::NonCacheable)
+//!     .slots(4)
+//!     .commit(&mut broker)?;
+//! ```
+
+use core::ops::Range;
+
+use crate::{
+    delete_cap, BrokerError, CapabilityBroker, CapabilityType, DeviceId, DeviceResource,
+    IrqHandle, IrqMode, MemoryType, Result,
+};
+
+/// Resources to request together
+#[derive(Debug, Clone)]
+pub struct ResourceTransaction {
+    owner_pid: usize,
+    device: Option<(DeviceId, MemoryType)>,
+    /// Notification to subscribe to the device's interrupt
+    irq: Option<(usize, IrqMode)>,
+    /// DMA pool size and memory type
+    dma_pool: Option<(usize, MemoryType)>,
+    slots: usize,
+}
+
+/// Everything a committed [`ResourceTransaction`] was granted
+#[derive(Debug, Default)]
+pub struct ResourceGrant {
+    /// The device, with its DMA pool if one was staged
+    pub device: Option<DeviceResource>,
+    /// Subscription to the device's interrupt
+    pub irq: Option<IrqHandle>,
+    /// Empty capability slots, consecutive
+    pub slots: Option<Range<usize>>,
+}
+
+impl ResourceTransaction {
+    /// Start a transaction for the component `owner_pid`
+    pub fn new(owner_pid: usize) -> Self {
+        Self {
+            owner_pid,
+            device: None,
+            irq: None,
+            dma_pool: None,
+            slots: 0,
+        }
+    }
+
+    /// Request a device, mapping its MMIO region as `memory_type`
+    pub fn device(mut self, device_id: DeviceId, memory_type: MemoryType) -> Self {
+        self.device = Some((device_id, memory_type));
+        self
+    }
+
+    /// Subscribe the notification in `notification_cap` to the device's
+    /// interrupt
+    pub fn irq(mut self, notification_cap: usize, mode: IrqMode) -> Self {
+        self.irq = Some((notification_cap, mode));
+        self
+    }
+
+    /// Give the device a DMA pool of `size` bytes
+    pub fn dma_pool(mut self, size: usize, memory_type: MemoryType) -> Self {
+        self.dma_pool = Some((size, memory_type));
+        self
+    }
+
+    /// Reserve `count` consecutive empty capability slots
+    pub fn slots(mut self, count: usize) -> Self {
+        self.slots = count;
+        self
+    }
+
+    /// Allocate everything staged, or nothing
+    ///
+    /// Resources are taken in the order device, DMA pool, interrupt,
+    /// slots; on failure those already taken are released in reverse and
+    /// the first error is returned. An interrupt or DMA pool staged
+    /// without a device fails with `DeviceNotFound` before anything is
+    /// allocated, an interrupt for a device that has none after.
+    pub fn commit(self, broker: &mut CapabilityBroker) -> Result<ResourceGrant> {
+        self.check()?;
+        let mut grant = ResourceGrant::default();
+        match self.apply(broker, &mut grant) {
+            Ok(()) => Ok(grant),
+            Err(e) => {
+                let _ = grant.release(broker);
+                Err(e)
+            }
+        }
+    }
+
+    /// Catch what is sure to fail before allocating anything
+    fn check(&self) -> Result<()> {
+        if self.device.is_none() && (self.irq.is_some() || self.dma_pool.is_some()) {
+            return Err(BrokerError::DeviceNotFound);
+        }
+        Ok(())
+    }
+
+    fn apply(&self, broker: &mut CapabilityBroker, grant: &mut ResourceGrant) -> Result<()> {
+        if let Some((device_id, memory_type)) = self.device {
+            let device = grant.device.insert(broker.request_device_mapped(
+                device_id,
+                self.owner_pid,
+                memory_type,
+            )?);
+            if let Some((size, memory_type)) = self.dma_pool {
+                broker.attach_dma_pool(device, size, memory_type)?;
+            }
+            if let Some((notification_cap, mode)) = self.irq {
+                let irq = device.irq.ok_or(BrokerError::DeviceNotFound)?;
+                grant.irq = Some(broker.request_irq(irq, notification_cap, mode, self.owner_pid)?);
+            }
+        }
+        if self.slots > 0 {
+            grant.slots = Some(broker.allocate_cap_range(self.slots, CapabilityType::Untyped)?);
+        }
+        Ok(())
+    }
+}
+
+impl ResourceGrant {
+    /// Give everything back: delete whatever was put in the slots and free
+    /// them, unsubscribe the interrupt, release the device
+    ///
+    /// Every step is attempted; the first failure is returned.
+    pub fn release(self, broker: &mut CapabilityBroker) -> Result<()> {
+        for slot in self.slots.into_iter().flatten() {
+            delete_cap(slot);
+            broker.free_cap_slot(slot);
+        }
+        let unsubscribed = match self.irq {
+            Some(handle) => broker.release_irq(handle),
+            None => Ok(()),
+        };
+        let released = match self.device {
+            Some(device) => broker.release_device(device),
+            None => Ok(()),
+        };
+        unsubscribed.and(released)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_needs_device() {
+        let irq_only = ResourceTransaction::new(42).irq(7, IrqMode::Exclusive);
+        assert_eq!(irq_only.check(), Err(BrokerError::DeviceNotFound));
+
+        let dma_only = ResourceTransaction::new(42).dma_pool(4096, MemoryType::Normal);
+        assert_eq!(dma_only.check(), Err(BrokerError::DeviceNotFound));
+
+        let full = dma_only
+            .device(DeviceId::Uart(0), MemoryType::Device)
+            .irq(7, IrqMode::Exclusive)
+            .slots(4);
+        assert_eq!(full.check(), Ok(()));
+        assert_eq!(ResourceTransaction::new(42).slots(2).check(), Ok(()));
+    }
+}
@@ -1,6 +1,31 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
 fn main() {
-    // Tell cargo to rerun if kernel or roottask objects change
-    println!("cargo:rerun-if-changed=../build/kernel.o");
-    println!("cargo:rerun-if-changed=../build/roottask.o");
+    // Tell cargo to rerun if the payload archive object changes
+    println!("cargo:rerun-if-changed=../build/payload.o");
     println!("cargo:rerun-if-changed=linker.ld");
+
+    if env::var_os("CARGO_FEATURE_SECURE_BOOT").is_some() {
+        embed_secure_boot_key();
+    }
+}
+
+/// Copy the raw Ed25519 public key named by KAAL_SECURE_BOOT_PUBKEY into
+/// OUT_DIR, where `payload::signature` includes it
+fn embed_secure_boot_key() {
+    println!("cargo:rerun-if-env-changed=KAAL_SECURE_BOOT_PUBKEY");
+
+    let path = env::var("KAAL_SECURE_BOOT_PUBKEY")
+        .expect("secure-boot feature requires KAAL_SECURE_BOOT_PUBKEY (path to a raw 32-byte Ed25519 public key)");
+    println!("cargo:rerun-if-changed={}", path);
+
+    let key = fs::read(&path).unwrap_or_else(|e| panic!("Failed to read secure boot key {}: {}", path, e));
+    if key.len() != 32 {
+        panic!("Secure boot key {} must be a raw 32-byte Ed25519 public key (got {} bytes)", path, key.len());
+    }
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("secure_boot_pubkey.bin");
+    fs::write(out, key).expect("Failed to write embedded secure boot key");
 }
@@ -1,9 +1,18 @@
 // ARM64-specific boot code
 
-use core::arch::{asm, naked_asm};
+use core::arch::{asm, global_asm, naked_asm};
+
+use crate::fault::TrapFrame;
+
+/// ELF relocation type applied by the self-relocation loop
+const R_AARCH64_RELATIVE: u64 = 1027;
 
 /// ARM64 entry point - called by boot firmware
 /// x0 = DTB physical address
+///
+/// The loader is linked as a static PIE and may be started at any 4KB
+/// aligned address. Until the relocations are applied only PC-relative
+/// addressing is used.
 #[unsafe(naked)]
 #[no_mangle]
 pub unsafe extern "C" fn _start() -> ! {
@@ -11,31 +20,64 @@ pub unsafe extern "C" fn _start() -> ! {
         // Preserve DTB address in x0
         "mov x19, x0",
 
+        // Load offset = runtime address - link address
+        "adr x9, _start",
+        "ldr x10, ={link_base}",
+        "sub x9, x9, x10",
+
+        // Apply R_AARCH64_RELATIVE entries: *(offset + delta) = addend + delta.
+        // Needed even at the link address: the linker leaves the targets zero.
+        "adrp x10, __rela_start",
+        "add x10, x10, :lo12:__rela_start",
+        "adrp x11, __rela_end",
+        "add x11, x11, :lo12:__rela_end",
+        "1:",
+        "cmp x10, x11",
+        "b.hs 2f",
+        "ldp x12, x13, [x10], #16",
+        "ldr x14, [x10], #8",
+        "cmp x13, #{r_relative}",
+        "b.ne 1b",
+        "add x14, x14, x9",
+        "str x14, [x12, x9]",
+        "b 1b",
+        "2:",
+
         // Set up stack (use end of elfloader as stack base)
-        "ldr x1, =__stack_top",
+        "adrp x1, __stack_top",
+        "add x1, x1, :lo12:__stack_top",
         "mov sp, x1",
 
         // Clear BSS
-        "ldr x1, =__bss_start",
-        "ldr x2, =__bss_end",
-        "1:",
+        "adrp x1, __bss_start",
+        "add x1, x1, :lo12:__bss_start",
+        "adrp x2, __bss_end",
+        "add x2, x2, :lo12:__bss_end",
+        "3:",
         "cmp x1, x2",
-        "b.eq 2f",
+        "b.hs 4f",
         "str xzr, [x1], #8",
-        "b 1b",
-        "2:",
+        "b 3b",
+        "4:",
 
         // Restore DTB address to x0 and jump to Rust
         "mov x0, x19",
         "bl _start_rust",
 
         // Should never return
-        "3:",
+        "5:",
         "wfe",
-        "b 3b",
+        "b 5b",
+        link_base = sym __elfloader_link_base,
+        r_relative = const R_AARCH64_RELATIVE,
     )
 }
 
+extern "C" {
+    /// Link address of `_start` (absolute symbol from the linker script)
+    static __elfloader_link_base: u8;
+}
+
 /// Rust entry point - called from assembly _start
 #[no_mangle]
 extern "C" fn _start_rust(dtb_addr: usize) -> ! {
@@ -78,6 +120,7 @@ pub fn disable_mmu() {
             "bic x1, x1, #1",  // Clear M bit
             "msr sctlr_el1, x1",
             "isb",
+            out("x1") _,
             options(nomem, nostack)
         );
     }
@@ -112,7 +155,8 @@ pub fn enable_mmu(ttbr0: usize, ttbr1: usize, mair: u64, tcr: u64) {
             ttbr1 = in(reg) ttbr1,
             mair = in(reg) mair,
             tcr = in(reg) tcr,
-            options(nomem, nostack)
+            out("x1") _,
+            options(nostack)
         );
     }
 }
@@ -144,3 +188,235 @@ pub fn tlb_invalidate_all() {
         );
     }
 }
+
+/// Invalidate the entire instruction cache (images were written with the
+/// MMU and caches off)
+pub fn icache_invalidate_all() {
+    unsafe {
+        asm!(
+            "dsb ish",
+            "ic iallu",
+            "dsb ish",
+            "isb",
+            options(nostack)
+        );
+    }
+}
+
+/// Stop the current core
+pub fn halt() -> ! {
+    loop {
+        unsafe {
+            asm!("wfe", options(nomem, nostack));
+        }
+    }
+}
+
+/// Read the physical counter (CNTPCT_EL0)
+pub fn read_counter() -> u64 {
+    let ticks: u64;
+    unsafe {
+        asm!("isb", "mrs {}, cntpct_el0", out(reg) ticks, options(nomem, nostack));
+    }
+    ticks
+}
+
+/// Counter frequency in Hz (CNTFRQ_EL0, set by firmware)
+pub fn counter_frequency() -> u64 {
+    let frequency: u64;
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) frequency, options(nomem, nostack));
+    }
+    frequency
+}
+
+/// Register names for the fault dump (`TrapFrame::regs` order)
+pub const GPR_NAMES: [&str; 31] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7",
+    "x8", "x9", "x10", "x11", "x12", "x13", "x14", "x15",
+    "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23",
+    "x24", "x25", "x26", "x27", "x28", "x29", "x30",
+];
+
+/// Names of the status, syndrome and fault address registers
+pub const STATUS_NAME: &str = "spsr";
+pub const CAUSE_NAME: &str = "ESR";
+pub const FAULT_ADDR_NAME: &str = "FAR";
+
+// Exception vector table: 16 entries of 0x80 bytes, 2KB aligned. Every
+// entry saves x0/x1, passes its index in x1 and joins the common path,
+// which completes a `TrapFrame` on the stack and reports it. ELR, SPSR,
+// ESR and FAR are read from EL2 when the loader runs there.
+global_asm!(
+    ".pushsection .text.exception_vectors, \"ax\"",
+    ".balign 2048",
+    "__exception_vectors:",
+    ".irp index, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15",
+    ".balign 0x80",
+    "sub sp, sp, #{frame_size}",
+    "stp x0, x1, [sp, #0]",
+    "mov x1, #\\index",
+    "b __exception_common",
+    ".endr",
+
+    "__exception_common:",
+    "stp x2, x3, [sp, #16]",
+    "stp x4, x5, [sp, #32]",
+    "stp x6, x7, [sp, #48]",
+    "stp x8, x9, [sp, #64]",
+    "stp x10, x11, [sp, #80]",
+    "stp x12, x13, [sp, #96]",
+    "stp x14, x15, [sp, #112]",
+    "stp x16, x17, [sp, #128]",
+    "stp x18, x19, [sp, #144]",
+    "stp x20, x21, [sp, #160]",
+    "stp x22, x23, [sp, #176]",
+    "stp x24, x25, [sp, #192]",
+    "stp x26, x27, [sp, #208]",
+    "stp x28, x29, [sp, #224]",
+    "add x2, sp, #{frame_size}",
+    "stp x30, x2, [sp, #240]",
+    "mrs x2, CurrentEL",
+    "cmp x2, #(2 << 2)",
+    "b.eq 1f",
+    "mrs x2, elr_el1",
+    "mrs x3, spsr_el1",
+    "mrs x4, esr_el1",
+    "mrs x5, far_el1",
+    "b 2f",
+    "1:",
+    "mrs x2, elr_el2",
+    "mrs x3, spsr_el2",
+    "mrs x4, esr_el2",
+    "mrs x5, far_el2",
+    "2:",
+    "stp x2, x3, [sp, #256]",
+    "stp x4, x5, [sp, #272]",
+    "mov x0, sp",
+    "bl {handler}",
+    "3:",
+    "wfe",
+    "b 3b",
+    ".popsection",
+    frame_size = const core::mem::size_of::<TrapFrame>(),
+    handler = sym handle_exception,
+);
+
+/// Point VBAR of the current exception level at the loader's vectors
+pub fn install_exception_vectors() {
+    unsafe {
+        asm!(
+            "adrp {vectors}, __exception_vectors",
+            "add {vectors}, {vectors}, :lo12:__exception_vectors",
+            "mrs {el}, CurrentEL",
+            "cmp {el}, #(2 << 2)",
+            "b.eq 1f",
+            "msr vbar_el1, {vectors}",
+            "b 2f",
+            "1:",
+            "msr vbar_el2, {vectors}",
+            "2:",
+            "isb",
+            vectors = out(reg) _,
+            el = out(reg) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Common exception path: report the frame through the panic handler
+extern "C" fn handle_exception(frame: &TrapFrame, index: usize) -> ! {
+    const KINDS: [&str; 4] = ["Synchronous", "IRQ", "FIQ", "SError"];
+    const SOURCES: [&str; 4] = ["current EL (SP0)", "current EL (SPx)", "lower EL (AArch64)", "lower EL (AArch32)"];
+
+    crate::fault::record_exception(frame);
+    panic!("{} exception from {} at {:#x}", KINDS[index % 4], SOURCES[index / 4], frame.pc)
+}
+
+/// Snapshot the general-purpose registers, SP and LR (as PC) into `frame`
+#[unsafe(naked)]
+pub extern "C" fn capture_registers(frame: &mut TrapFrame) {
+    naked_asm!(
+        "stp x0, x1, [x0, #0]",
+        "stp x2, x3, [x0, #16]",
+        "stp x4, x5, [x0, #32]",
+        "stp x6, x7, [x0, #48]",
+        "stp x8, x9, [x0, #64]",
+        "stp x10, x11, [x0, #80]",
+        "stp x12, x13, [x0, #96]",
+        "stp x14, x15, [x0, #112]",
+        "stp x16, x17, [x0, #128]",
+        "stp x18, x19, [x0, #144]",
+        "stp x20, x21, [x0, #160]",
+        "stp x22, x23, [x0, #176]",
+        "stp x24, x25, [x0, #192]",
+        "stp x26, x27, [x0, #208]",
+        "stp x28, x29, [x0, #224]",
+        "mov x1, sp",
+        "stp x30, x1, [x0, #240]",
+        "mrs x1, nzcv",
+        "stp x30, x1, [x0, #256]",
+        "ldr x1, [x0, #8]",
+        "ret",
+    )
+}
+
+/// Current syndrome and fault address registers (ESR, FAR)
+pub fn read_fault_registers() -> (usize, usize) {
+    let (esr, far): (usize, usize);
+    unsafe {
+        asm!(
+            "mrs {el}, CurrentEL",
+            "cmp {el}, #(2 << 2)",
+            "b.eq 1f",
+            "mrs {esr}, esr_el1",
+            "mrs {far}, far_el1",
+            "b 2f",
+            "1:",
+            "mrs {esr}, esr_el2",
+            "mrs {far}, far_el2",
+            "2:",
+            el = out(reg) _,
+            esr = out(reg) esr,
+            far = out(reg) far,
+            options(nomem, nostack)
+        );
+    }
+    (esr, far)
+}
+
+/// Exception class of an ESR value
+fn exception_class(esr: usize) -> usize {
+    (esr >> 26) & 0x3f
+}
+
+/// Human-readable exception class of an ESR value
+pub fn describe_cause(esr: usize) -> &'static str {
+    match exception_class(esr) {
+        0x00 => "unknown reason",
+        0x01 => "trapped WFI/WFE",
+        0x07 => "SIMD/FP access",
+        0x0e => "illegal execution state",
+        0x15 => "SVC",
+        0x16 => "HVC",
+        0x17 => "SMC",
+        0x18 => "trapped MSR/MRS",
+        0x20 => "instruction abort from lower EL",
+        0x21 => "instruction abort",
+        0x22 => "PC alignment fault",
+        0x24 => "data abort from lower EL",
+        0x25 => "data abort",
+        0x26 => "SP alignment fault",
+        0x2f => "SError",
+        0x3c => "BRK",
+        _ => "other",
+    }
+}
+
+/// Address worth dumping for an exception: FAR for aborts, else the PC
+pub fn fault_address(frame: &TrapFrame) -> usize {
+    match exception_class(frame.cause) {
+        0x20 | 0x21 | 0x22 | 0x24 | 0x25 => frame.fault_addr,
+        _ => frame.pc,
+    }
+}
@@ -5,3 +5,21 @@ pub mod aarch64;
 
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::*;
+
+#[cfg(target_arch = "aarch64")]
+pub mod psci;
+
+#[cfg(target_arch = "aarch64")]
+pub use psci::{boot_secondary_cpus, release_secondary_cpus};
+
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+
+#[cfg(target_arch = "riscv64")]
+pub use riscv64::*;
+
+#[cfg(target_arch = "riscv64")]
+pub mod sbi;
+
+#[cfg(target_arch = "riscv64")]
+pub use sbi::{boot_secondary_cpus, release_secondary_cpus};
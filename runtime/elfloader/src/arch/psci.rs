@@ -0,0 +1,269 @@
+// PSCI (Power State Coordination Interface) and secondary core bring-up
+//
+// Secondary cores are powered on with PSCI CPU_ON into `_secondary_start`,
+// given a per-core boot stack, and then wait in the loader until the boot
+// CPU hands off to the kernel. At that point they are released into the
+// kernel entry point, which decides what to do with them.
+
+use core::arch::{asm, naked_asm};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::uart_println;
+
+/// PSCI function IDs (SMC64 calling convention where applicable)
+const PSCI_VERSION: u32 = 0x8400_0000;
+const PSCI_CPU_ON_64: u32 = 0xC400_0003;
+
+/// Maximum number of cores the loader will bring up (including the boot CPU)
+pub const MAX_CPUS: usize = 8;
+
+/// Boot stack size for each secondary core
+pub const SECONDARY_STACK_SIZE: usize = 16 * 1024;
+
+/// Spin iterations to wait for a secondary to report in after CPU_ON
+const ONLINE_TIMEOUT: usize = 10_000_000;
+
+/// MPIDR affinity fields (Aff3, Aff2, Aff1, Aff0)
+const MPIDR_AFFINITY_MASK: u64 = 0xff_00ff_ffff;
+
+/// Offset of the kernel's secondary-core entry from its entry point
+///
+/// The kernel's `_start` branches over a second branch, which secondary
+/// cores are released into, so it tells them apart from the boot CPU
+/// whatever their MPIDRs.
+pub const SECONDARY_ENTRY_OFFSET: usize = 4;
+
+/// Conduit used to reach the PSCI firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conduit {
+    /// Secure monitor call (firmware at EL3)
+    Smc,
+    /// Hypervisor call (firmware at EL2, e.g. QEMU without EL3)
+    Hvc,
+}
+
+/// PSCI error codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsciError {
+    NotSupported,
+    InvalidParameters,
+    Denied,
+    AlreadyOn,
+    OnPending,
+    InternalFailure,
+    NotPresent,
+    Disabled,
+    InvalidAddress,
+    Unknown(i64),
+}
+
+impl PsciError {
+    fn from_code(code: i64) -> Self {
+        match code {
+            -1 => PsciError::NotSupported,
+            -2 => PsciError::InvalidParameters,
+            -3 => PsciError::Denied,
+            -4 => PsciError::AlreadyOn,
+            -5 => PsciError::OnPending,
+            -6 => PsciError::InternalFailure,
+            -7 => PsciError::NotPresent,
+            -8 => PsciError::Disabled,
+            -9 => PsciError::InvalidAddress,
+            other => PsciError::Unknown(other),
+        }
+    }
+}
+
+/// Per-core boot stacks for secondary cores (index 0 is the first secondary)
+#[repr(C, align(16))]
+struct SecondaryStacks([[u8; SECONDARY_STACK_SIZE]; MAX_CPUS - 1]);
+
+#[no_mangle]
+static mut __secondary_stacks: SecondaryStacks = SecondaryStacks([[0; SECONDARY_STACK_SIZE]; MAX_CPUS - 1]);
+
+/// Set by each secondary once it is running on its own stack
+static CPU_ONLINE: [AtomicBool; MAX_CPUS - 1] = [const { AtomicBool::new(false) }; MAX_CPUS - 1];
+
+/// Kernel entry point published by the boot CPU at handoff (0 = not yet)
+static KERNEL_RELEASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Read the PSCI conduit from the device tree `/psci` node
+pub fn conduit_from_dtb(dtb: &fdt::Fdt) -> Option<Conduit> {
+    let psci = dtb.find_node("/psci")?;
+    match psci.property("method")?.as_str()? {
+        "smc" => Some(Conduit::Smc),
+        "hvc" => Some(Conduit::Hvc),
+        _ => None,
+    }
+}
+
+/// Issue a PSCI call through the given conduit
+fn call(conduit: Conduit, function: u32, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let mut ret = function as u64;
+    unsafe {
+        match conduit {
+            Conduit::Smc => asm!(
+                "smc #0",
+                inout("x0") ret,
+                inout("x1") arg0 => _,
+                inout("x2") arg1 => _,
+                inout("x3") arg2 => _,
+                options(nostack)
+            ),
+            Conduit::Hvc => asm!(
+                "hvc #0",
+                inout("x0") ret,
+                inout("x1") arg0 => _,
+                inout("x2") arg1 => _,
+                inout("x3") arg2 => _,
+                options(nostack)
+            ),
+        }
+    }
+    ret as i64
+}
+
+/// Query the PSCI version (major, minor)
+pub fn version(conduit: Conduit) -> (u16, u16) {
+    let v = call(conduit, PSCI_VERSION, 0, 0, 0) as u32;
+    ((v >> 16) as u16, (v & 0xffff) as u16)
+}
+
+/// Power on a core, starting execution at `entry` with `context_id` in x0
+pub fn cpu_on(conduit: Conduit, target_mpidr: u64, entry: usize, context_id: u64) -> Result<(), PsciError> {
+    match call(conduit, PSCI_CPU_ON_64, target_mpidr, entry as u64, context_id) {
+        0 => Ok(()),
+        code => Err(PsciError::from_code(code)),
+    }
+}
+
+/// Affinity of the current core
+pub fn current_mpidr() -> u64 {
+    let mpidr: u64;
+    unsafe {
+        asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack));
+    }
+    mpidr & MPIDR_AFFINITY_MASK
+}
+
+/// Power on every secondary core listed in the device tree
+///
+/// Cores must use `enable-method = "psci"`. Returns the number of
+/// secondaries that came online and are parked waiting for the kernel.
+pub fn boot_secondary_cpus(dtb: &fdt::Fdt) -> usize {
+    let conduit = match conduit_from_dtb(dtb) {
+        Some(conduit) => conduit,
+        None => {
+            uart_println!("SMP: no /psci node, secondary cores stay offline");
+            return 0;
+        }
+    };
+
+    let (major, minor) = version(conduit);
+    uart_println!("SMP: PSCI v{}.{} via {:?}", major, minor, conduit);
+
+    let boot_mpidr = current_mpidr();
+    let mut online = 0;
+
+    for cpu in dtb.cpus() {
+        let mpidr = cpu.ids().first() as u64 & MPIDR_AFFINITY_MASK;
+        if mpidr == boot_mpidr {
+            continue;
+        }
+
+        let method = cpu.property("enable-method").and_then(|p| p.as_str());
+        if method != Some("psci") {
+            uart_println!("  CPU {:#x}: enable-method {:?} not supported", mpidr, method);
+            continue;
+        }
+
+        if online == MAX_CPUS - 1 {
+            uart_println!("  CPU {:#x}: exceeds MAX_CPUS ({}), skipped", mpidr, MAX_CPUS);
+            continue;
+        }
+
+        let index = online;
+        if let Err(e) = cpu_on(conduit, mpidr, _secondary_start as *const () as usize, index as u64) {
+            uart_println!("  CPU {:#x}: CPU_ON failed: {:?}", mpidr, e);
+            continue;
+        }
+
+        let mut spins = 0;
+        while !CPU_ONLINE[index].load(Ordering::Acquire) && spins < ONLINE_TIMEOUT {
+            core::hint::spin_loop();
+            spins += 1;
+        }
+
+        if CPU_ONLINE[index].load(Ordering::Acquire) {
+            uart_println!("  CPU {:#x}: online (boot stack slot {})", mpidr, index);
+            online += 1;
+        } else {
+            uart_println!("  CPU {:#x}: did not come online", mpidr);
+        }
+    }
+
+    online
+}
+
+/// Release parked secondary cores into the kernel's secondary entry
+/// (`kernel_entry` + [`SECONDARY_ENTRY_OFFSET`])
+///
+/// Must be called by the boot CPU right before it jumps to the kernel.
+pub fn release_secondary_cpus(kernel_entry: usize) {
+    KERNEL_RELEASE.store(kernel_entry + SECONDARY_ENTRY_OFFSET, Ordering::Release);
+    unsafe {
+        asm!("dsb sy", "sev", options(nostack, preserves_flags));
+    }
+}
+
+/// Secondary core entry point (target of PSCI CPU_ON)
+/// x0 = context id = boot stack slot
+#[unsafe(naked)]
+#[no_mangle]
+pub unsafe extern "C" fn _secondary_start() -> ! {
+    naked_asm!(
+        // sp = __secondary_stacks + (slot + 1) * SECONDARY_STACK_SIZE
+        "adrp x1, __secondary_stacks",
+        "add x1, x1, :lo12:__secondary_stacks",
+        "add x2, x0, #1",
+        "mov x3, #{stack_size}",
+        "mul x2, x2, x3",
+        "add x1, x1, x2",
+        "mov sp, x1",
+
+        // x0 still holds the slot
+        "bl _secondary_rust",
+
+        // Should never return
+        "1:",
+        "wfe",
+        "b 1b",
+        stack_size = const SECONDARY_STACK_SIZE,
+    )
+}
+
+/// Rust entry for secondary cores - park until the kernel is entered
+#[no_mangle]
+extern "C" fn _secondary_rust(slot: usize) -> ! {
+    CPU_ONLINE[slot].store(true, Ordering::Release);
+
+    let entry = loop {
+        let entry = KERNEL_RELEASE.load(Ordering::Acquire);
+        if entry != 0 {
+            break entry;
+        }
+        unsafe {
+            asm!("wfe", options(nomem, nostack));
+        }
+    };
+
+    // A higher-half kernel is entered with the boot CPU's translation
+    if let Some(translation) = crate::mmu::kernel_translation() {
+        translation.enable();
+    }
+
+    // Secondaries enter the kernel at its secondary entry, with zeroed
+    // boot parameters
+    let kernel_fn: crate::KernelEntry = unsafe { core::mem::transmute(entry) };
+    kernel_fn(0, 0, 0, 0, 0, 0, 0, 0)
+}
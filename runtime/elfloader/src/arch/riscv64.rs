@@ -0,0 +1,510 @@
+// RISC-V (rv64) specific boot code
+//
+// The loader runs in S-mode under OpenSBI (or any SBI firmware), which
+// enters it with a0 = hart id and a1 = DTB address. Firmware implementing
+// the HSM extension starts a single boot hart; older firmware may start all
+// harts at once, so a lottery picks the boot hart and parks the rest.
+//
+// RISC-V has no S-mode equivalent of MPIDR, so the kernel learns its hart id
+// from `tp`, which the loader sets on every hart before handoff.
+
+use core::arch::{asm, naked_asm};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::fault::TrapFrame;
+use crate::uart_println;
+use crate::utils::{align_down, align_up};
+
+/// sstatus.SIE (supervisor interrupt enable)
+const SSTATUS_SIE: usize = 1 << 1;
+/// sip.SSIP (the only software-writable pending bit in S-mode)
+const SIP_SSIP: usize = 1 << 1;
+
+/// Sv39 translation mode in satp
+const SATP_MODE_SV39: usize = 8 << 60;
+
+/// Sv39 PTE bits
+const PTE_V: u64 = 1 << 0;
+const PTE_R: u64 = 1 << 1;
+const PTE_W: u64 = 1 << 2;
+const PTE_X: u64 = 1 << 3;
+const PTE_G: u64 = 1 << 5;
+const PTE_A: u64 = 1 << 6;
+const PTE_D: u64 = 1 << 7;
+
+/// Size mapped by one Sv39 root-level (gigapage) entry
+const GIGAPAGE_SIZE: usize = 1 << 30;
+
+/// PLIC register layout (as defined by the RISC-V PLIC specification)
+const PLIC_ENABLE_BASE: usize = 0x2000;
+const PLIC_ENABLE_STRIDE: usize = 0x80;
+const PLIC_CONTEXT_BASE: usize = 0x20_0000;
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+const PLIC_CLAIM_OFFSET: usize = 4;
+/// Highest priority threshold supported by all PLICs (masks every source)
+const PLIC_THRESHOLD_MASK_ALL: u32 = 7;
+/// Local interrupt number of the supervisor external interrupt
+const IRQ_S_EXT: u32 = 9;
+
+/// ELF relocation type applied by the self-relocation loop
+const R_RISCV_RELATIVE: u64 = 3;
+
+/// First hart to take the lottery becomes the boot hart (kept in .data so
+/// clearing BSS does not reopen it)
+#[link_section = ".data.boot_lottery"]
+#[no_mangle]
+static mut __boot_lottery: u32 = 0;
+
+/// Hart id of the boot hart
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// RISC-V entry point - called by SBI firmware in S-mode
+/// a0 = hart id, a1 = DTB physical address
+///
+/// Like the ARM64 entry, the loader is a static PIE: only PC-relative
+/// addressing (`lla`) is used until the relocations are applied.
+#[unsafe(naked)]
+#[no_mangle]
+pub unsafe extern "C" fn _start() -> ! {
+    naked_asm!(
+        // No interrupts until the kernel sets up its own handlers
+        "csrw sie, zero",
+
+        // Hart lottery: only the first hart continues
+        "lla t0, __boot_lottery",
+        "li t1, 1",
+        "amoswap.w t1, t1, (t0)",
+        "bnez t1, 3f",
+
+        // Preserve hart id and DTB address
+        "mv s0, a0",
+        "mv s1, a1",
+        "mv tp, a0",
+
+        // Load offset = runtime address - link address
+        "lla t0, _start",
+        "lla t1, 5f",
+        "ld t1, 0(t1)",
+        "sub t0, t0, t1",
+
+        // Apply R_RISCV_RELATIVE entries: *(offset + delta) = addend + delta.
+        // Needed even at the link address: the linker leaves the targets zero.
+        "lla t1, __rela_start",
+        "lla t2, __rela_end",
+        "li t3, {r_relative}",
+        "4:",
+        "bgeu t1, t2, 6f",
+        "ld t4, 0(t1)",
+        "ld t5, 8(t1)",
+        "ld t6, 16(t1)",
+        "addi t1, t1, 24",
+        "bne t5, t3, 4b",
+        "add t4, t4, t0",
+        "add t6, t6, t0",
+        "sd t6, 0(t4)",
+        "j 4b",
+        "6:",
+
+        // Set up stack (use end of elfloader as stack base)
+        "lla sp, __stack_top",
+
+        // Clear BSS
+        "lla t0, __bss_start",
+        "lla t1, __bss_end",
+        "1:",
+        "bgeu t0, t1, 2f",
+        "sd zero, 0(t0)",
+        "addi t0, t0, 8",
+        "j 1b",
+        "2:",
+
+        // Restore hart id and DTB address and jump to Rust
+        "mv a0, s0",
+        "mv a1, s1",
+        "call _start_rust",
+
+        // Losing harts (and a returning boot hart) park here
+        "3:",
+        "wfi",
+        "j 3b",
+
+        // Link address of _start (absolute symbol from the linker script)
+        ".balign 8",
+        "5:",
+        ".8byte __elfloader_link_base",
+        r_relative = const R_RISCV_RELATIVE,
+    )
+}
+
+/// Rust entry point - called from assembly _start
+#[no_mangle]
+extern "C" fn _start_rust(hart_id: usize, dtb_addr: usize) -> ! {
+    BOOT_HART.store(hart_id, Ordering::Relaxed);
+
+    // The SBI boot protocol always passes the DTB in a1
+    crate::elfloader_main(dtb_addr)
+}
+
+/// Hart id of the boot hart
+pub fn boot_hart_id() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+/// Stop the current hart
+pub fn halt() -> ! {
+    loop {
+        unsafe {
+            asm!("wfi", options(nomem, nostack));
+        }
+    }
+}
+
+/// Mask and clear supervisor interrupts on the current hart
+pub fn disable_interrupts() {
+    unsafe {
+        asm!(
+            "csrc sstatus, {sie}",
+            "csrw sie, zero",
+            "csrc sip, {ssip}",
+            sie = in(reg) SSTATUS_SIE,
+            ssip = in(reg) SIP_SSIP,
+            options(nomem, nostack)
+        );
+    }
+}
+
+/// Disable translation (bare mode)
+pub fn disable_mmu() {
+    unsafe {
+        asm!("csrw satp, zero", "sfence.vma", options(nostack));
+    }
+}
+
+/// Enable translation with the given satp value
+pub fn enable_mmu(satp: usize) {
+    unsafe {
+        asm!("sfence.vma", "csrw satp, {}", "sfence.vma", in(reg) satp, options(nostack));
+    }
+}
+
+/// Invalidate all address translation caches
+#[inline(always)]
+pub fn sfence_vma() {
+    unsafe {
+        asm!("sfence.vma", options(nostack));
+    }
+}
+
+/// Full memory and I/O fence
+#[inline(always)]
+pub fn fence() {
+    unsafe {
+        asm!("fence iorw, iorw", options(nostack, preserves_flags));
+    }
+}
+
+/// Read the `time` counter
+pub fn read_counter() -> u64 {
+    let ticks: u64;
+    unsafe {
+        asm!("rdtime {}", out(reg) ticks, options(nomem, nostack));
+    }
+    ticks
+}
+
+/// Counter frequency in Hz; not architecturally visible on RISC-V (it comes
+/// from `/cpus/timebase-frequency`)
+pub fn counter_frequency() -> u64 {
+    0
+}
+
+/// Set the hart id register the kernel reads on entry
+#[inline(always)]
+pub fn set_kernel_hart_id(hart_id: usize) {
+    unsafe {
+        asm!("mv tp, {}", in(reg) hart_id, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Sv39 root page table
+#[repr(C, align(4096))]
+pub struct Sv39PageTable {
+    entries: [u64; 512],
+}
+
+impl Sv39PageTable {
+    pub const fn new() -> Self {
+        Self { entries: [0; 512] }
+    }
+
+    /// Identity map a physical range with RWX gigapages
+    pub fn setup_identity_map(&mut self, start: usize, end: usize) {
+        uart_println!("Setting up Sv39 identity map: {:#x} - {:#x}", start, end);
+
+        let start = align_down(start, GIGAPAGE_SIZE);
+        let end = align_up(end, GIGAPAGE_SIZE);
+
+        for addr in (start..end).step_by(GIGAPAGE_SIZE) {
+            let idx = (addr >> 30) & 0x1ff;
+            let ppn = (addr >> 12) as u64;
+            self.entries[idx] = (ppn << 10) | PTE_V | PTE_R | PTE_W | PTE_X | PTE_G | PTE_A | PTE_D;
+        }
+    }
+
+    /// satp value selecting this table in Sv39 mode (ASID 0)
+    pub fn satp(&self) -> usize {
+        SATP_MODE_SV39 | (self as *const _ as usize >> 12)
+    }
+}
+
+/// Mask every PLIC source for the boot hart's S-mode context
+///
+/// Firmware may leave sources enabled with interrupts pending; the kernel
+/// must start from a quiet PLIC so nothing fires before it installs its
+/// handlers. Pending claims are completed, all enables cleared and the
+/// priority threshold raised to the maximum.
+pub fn quiesce_plic(dtb: &fdt::Fdt) {
+    let plic = match dtb.find_compatible(&["riscv,plic0", "sifive,plic-1.0.0"]) {
+        Some(plic) => plic,
+        None => return,
+    };
+    let base = match plic.reg().and_then(|mut r| r.next()) {
+        Some(reg) => reg.starting_address as usize,
+        None => return,
+    };
+    let sources = plic.property("riscv,ndev").and_then(|p| p.as_usize()).unwrap_or(0);
+
+    let context = match s_mode_context(dtb, plic, boot_hart_id()) {
+        Some(context) => context,
+        None => {
+            uart_println!("PLIC: no S-mode context for hart {}", boot_hart_id());
+            return;
+        }
+    };
+
+    let write = |offset: usize, value: u32| unsafe {
+        core::ptr::write_volatile((base + offset) as *mut u32, value)
+    };
+    let read = |offset: usize| unsafe { core::ptr::read_volatile((base + offset) as *const u32) };
+
+    let context_regs = PLIC_CONTEXT_BASE + context * PLIC_CONTEXT_STRIDE;
+    write(context_regs, PLIC_THRESHOLD_MASK_ALL);
+
+    // Source 0 does not exist; enable words cover sources 0..=ndev
+    let enables = PLIC_ENABLE_BASE + context * PLIC_ENABLE_STRIDE;
+    for word in 0..=(sources / 32) {
+        write(enables + word * 4, 0);
+    }
+
+    // Complete anything firmware claimed but never completed
+    loop {
+        let irq = read(context_regs + PLIC_CLAIM_OFFSET);
+        if irq == 0 {
+            break;
+        }
+        write(context_regs + PLIC_CLAIM_OFFSET, irq);
+    }
+
+    fence();
+    uart_println!("PLIC: {} sources masked for hart {} (context {})", sources, boot_hart_id(), context);
+}
+
+/// Find the PLIC context wired to a hart's supervisor external interrupt
+///
+/// `interrupts-extended` lists one (phandle, irq) pair per context, where the
+/// phandle names the hart's local interrupt controller.
+fn s_mode_context(dtb: &fdt::Fdt, plic: fdt::node::FdtNode, hart_id: usize) -> Option<usize> {
+    let hart_intc = dtb
+        .find_all_nodes("/cpus/cpu")
+        .find(|cpu| cpu.reg().and_then(|mut r| r.next()).map(|r| r.starting_address as usize) == Some(hart_id))?
+        .children()
+        .find(|child| child.name == "interrupt-controller")?
+        .property("phandle")?
+        .as_usize()? as u32;
+
+    let cells = plic.property("interrupts-extended")?.value;
+    cells
+        .chunks_exact(8)
+        .position(|pair| {
+            let phandle = u32::from_be_bytes([pair[0], pair[1], pair[2], pair[3]]);
+            let irq = u32::from_be_bytes([pair[4], pair[5], pair[6], pair[7]]);
+            phandle == hart_intc && irq == IRQ_S_EXT
+        })
+}
+
+/// Register names for the fault dump (`TrapFrame::regs` holds x1-x31)
+pub const GPR_NAMES: [&str; 31] = [
+    "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0",
+    "s1", "a0", "a1", "a2", "a3", "a4", "a5", "a6",
+    "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8",
+    "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// Names of the status, cause and fault address registers
+pub const STATUS_NAME: &str = "sstatus";
+pub const CAUSE_NAME: &str = "scause";
+pub const FAULT_ADDR_NAME: &str = "stval";
+
+/// Interrupt bit of scause
+const SCAUSE_INTERRUPT: usize = 1 << 63;
+
+/// Trap entry (direct mode stvec): build a `TrapFrame` on the stack and
+/// report it. The loader never returns from a trap.
+#[unsafe(naked)]
+extern "C" fn _trap_entry() -> ! {
+    naked_asm!(
+        ".balign 4",
+        "addi sp, sp, -{frame_size}",
+        "sd x1, 0(sp)",
+        "sd x3, 16(sp)",
+        "sd x4, 24(sp)",
+        "sd x5, 32(sp)",
+        "sd x6, 40(sp)",
+        "sd x7, 48(sp)",
+        "sd x8, 56(sp)",
+        "sd x9, 64(sp)",
+        "sd x10, 72(sp)",
+        "sd x11, 80(sp)",
+        "sd x12, 88(sp)",
+        "sd x13, 96(sp)",
+        "sd x14, 104(sp)",
+        "sd x15, 112(sp)",
+        "sd x16, 120(sp)",
+        "sd x17, 128(sp)",
+        "sd x18, 136(sp)",
+        "sd x19, 144(sp)",
+        "sd x20, 152(sp)",
+        "sd x21, 160(sp)",
+        "sd x22, 168(sp)",
+        "sd x23, 176(sp)",
+        "sd x24, 184(sp)",
+        "sd x25, 192(sp)",
+        "sd x26, 200(sp)",
+        "sd x27, 208(sp)",
+        "sd x28, 216(sp)",
+        "sd x29, 224(sp)",
+        "sd x30, 232(sp)",
+        "sd x31, 240(sp)",
+        "addi t0, sp, {frame_size}",
+        "sd t0, 8(sp)",
+        "sd t0, 248(sp)",
+        "csrr t0, sepc",
+        "sd t0, 256(sp)",
+        "csrr t0, sstatus",
+        "sd t0, 264(sp)",
+        "csrr t0, scause",
+        "sd t0, 272(sp)",
+        "csrr t0, stval",
+        "sd t0, 280(sp)",
+        "mv a0, sp",
+        "call {handler}",
+        "1:",
+        "wfi",
+        "j 1b",
+        frame_size = const core::mem::size_of::<TrapFrame>(),
+        handler = sym handle_exception,
+    )
+}
+
+/// Point stvec at the loader's trap entry
+pub fn install_exception_vectors() {
+    unsafe {
+        asm!("csrw stvec, {}", in(reg) _trap_entry as *const () as usize, options(nomem, nostack));
+    }
+}
+
+/// Common trap path: report the frame through the panic handler
+extern "C" fn handle_exception(frame: &TrapFrame) -> ! {
+    crate::fault::record_exception(frame);
+    let kind = if frame.cause & SCAUSE_INTERRUPT != 0 { "Interrupt" } else { "Exception" };
+    panic!("{} ({}) at {:#x}", kind, describe_cause(frame.cause), frame.pc)
+}
+
+/// Snapshot the general-purpose registers, sp and ra (as pc) into `frame`
+#[unsafe(naked)]
+pub extern "C" fn capture_registers(frame: &mut TrapFrame) {
+    naked_asm!(
+        "sd x1, 0(a0)",
+        "sd x2, 8(a0)",
+        "sd x3, 16(a0)",
+        "sd x4, 24(a0)",
+        "sd x5, 32(a0)",
+        "sd x6, 40(a0)",
+        "sd x7, 48(a0)",
+        "sd x8, 56(a0)",
+        "sd x9, 64(a0)",
+        "sd x10, 72(a0)",
+        "sd x11, 80(a0)",
+        "sd x12, 88(a0)",
+        "sd x13, 96(a0)",
+        "sd x14, 104(a0)",
+        "sd x15, 112(a0)",
+        "sd x16, 120(a0)",
+        "sd x17, 128(a0)",
+        "sd x18, 136(a0)",
+        "sd x19, 144(a0)",
+        "sd x20, 152(a0)",
+        "sd x21, 160(a0)",
+        "sd x22, 168(a0)",
+        "sd x23, 176(a0)",
+        "sd x24, 184(a0)",
+        "sd x25, 192(a0)",
+        "sd x26, 200(a0)",
+        "sd x27, 208(a0)",
+        "sd x28, 216(a0)",
+        "sd x29, 224(a0)",
+        "sd x30, 232(a0)",
+        "sd x31, 240(a0)",
+        "sd sp, 248(a0)",
+        "sd ra, 256(a0)",
+        "csrr t0, sstatus",
+        "sd t0, 264(a0)",
+        "ld t0, 32(a0)",
+        "ret",
+    )
+}
+
+/// Current cause and trap value registers (scause, stval)
+pub fn read_fault_registers() -> (usize, usize) {
+    let (scause, stval): (usize, usize);
+    unsafe {
+        asm!("csrr {}, scause", "csrr {}, stval", out(reg) scause, out(reg) stval, options(nomem, nostack));
+    }
+    (scause, stval)
+}
+
+/// Human-readable description of an scause value
+pub fn describe_cause(scause: usize) -> &'static str {
+    if scause & SCAUSE_INTERRUPT != 0 {
+        return match scause & !SCAUSE_INTERRUPT {
+            1 => "supervisor software interrupt",
+            5 => "supervisor timer interrupt",
+            9 => "supervisor external interrupt",
+            _ => "other interrupt",
+        };
+    }
+    match scause {
+        0 => "instruction address misaligned",
+        1 => "instruction access fault",
+        2 => "illegal instruction",
+        3 => "breakpoint",
+        4 => "load address misaligned",
+        5 => "load access fault",
+        6 => "store address misaligned",
+        7 => "store access fault",
+        8 => "ecall from U-mode",
+        9 => "ecall from S-mode",
+        12 => "instruction page fault",
+        13 => "load page fault",
+        15 => "store page fault",
+        _ => "other",
+    }
+}
+
+/// Address worth dumping for a trap: stval for memory faults, else the pc
+pub fn fault_address(frame: &TrapFrame) -> usize {
+    match frame.cause {
+        0 | 1 | 4 | 5 | 6 | 7 | 12 | 13 | 15 => frame.fault_addr,
+        _ => frame.pc,
+    }
+}
@@ -0,0 +1,223 @@
+// SBI (Supervisor Binary Interface) and secondary hart bring-up
+//
+// The RISC-V counterpart of `psci`: secondary harts are started with the SBI
+// HSM extension HART_START into `_secondary_start`, given a per-hart boot
+// stack, and then wait in the loader until the boot hart hands off to the
+// kernel. At that point they are released into the kernel entry point.
+
+use core::arch::{asm, naked_asm};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::uart_println;
+
+/// SBI extension IDs
+const SBI_EXT_BASE: usize = 0x10;
+const SBI_EXT_HSM: usize = 0x48_534D;
+
+/// Base extension function IDs
+const SBI_BASE_GET_SPEC_VERSION: usize = 0;
+const SBI_BASE_PROBE_EXTENSION: usize = 3;
+
+/// HSM extension function IDs
+const SBI_HSM_HART_START: usize = 0;
+
+/// Maximum number of harts the loader will bring up (including the boot hart)
+pub const MAX_CPUS: usize = 8;
+
+/// Boot stack size for each secondary hart
+pub const SECONDARY_STACK_SIZE: usize = 16 * 1024;
+
+/// Spin iterations to wait for a secondary to report in after HART_START
+const ONLINE_TIMEOUT: usize = 10_000_000;
+
+/// SBI error codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbiError {
+    Failed,
+    NotSupported,
+    InvalidParam,
+    Denied,
+    InvalidAddress,
+    AlreadyAvailable,
+    AlreadyStarted,
+    AlreadyStopped,
+    Unknown(isize),
+}
+
+impl SbiError {
+    fn from_code(code: isize) -> Self {
+        match code {
+            -1 => SbiError::Failed,
+            -2 => SbiError::NotSupported,
+            -3 => SbiError::InvalidParam,
+            -4 => SbiError::Denied,
+            -5 => SbiError::InvalidAddress,
+            -6 => SbiError::AlreadyAvailable,
+            -7 => SbiError::AlreadyStarted,
+            -8 => SbiError::AlreadyStopped,
+            other => SbiError::Unknown(other),
+        }
+    }
+}
+
+/// Per-hart boot stacks for secondary harts (index 0 is the first secondary)
+#[repr(C, align(16))]
+struct SecondaryStacks([[u8; SECONDARY_STACK_SIZE]; MAX_CPUS - 1]);
+
+#[no_mangle]
+static mut __secondary_stacks: SecondaryStacks = SecondaryStacks([[0; SECONDARY_STACK_SIZE]; MAX_CPUS - 1]);
+
+/// Set by each secondary once it is running on its own stack
+static CPU_ONLINE: [AtomicBool; MAX_CPUS - 1] = [const { AtomicBool::new(false) }; MAX_CPUS - 1];
+
+/// Kernel entry point published by the boot hart at handoff (0 = not yet)
+static KERNEL_RELEASE: AtomicUsize = AtomicUsize::new(0);
+
+/// Issue an SBI call, returning (error, value)
+fn call(extension: usize, function: usize, arg0: usize, arg1: usize, arg2: usize) -> (isize, usize) {
+    let error: isize;
+    let value: usize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => error,
+            inlateout("a1") arg1 => value,
+            in("a2") arg2,
+            in("a6") function,
+            in("a7") extension,
+            options(nostack)
+        );
+    }
+    (error, value)
+}
+
+/// Query the SBI specification version (major, minor)
+pub fn spec_version() -> (usize, usize) {
+    let (_, v) = call(SBI_EXT_BASE, SBI_BASE_GET_SPEC_VERSION, 0, 0, 0);
+    ((v >> 24) & 0x7f, v & 0xff_ffff)
+}
+
+/// Check whether the firmware implements an extension
+pub fn probe_extension(extension: usize) -> bool {
+    let (error, value) = call(SBI_EXT_BASE, SBI_BASE_PROBE_EXTENSION, extension, 0, 0);
+    error == 0 && value != 0
+}
+
+/// Start a hart at `entry` in S-mode with a0 = hart id and a1 = `opaque`
+pub fn hart_start(hart_id: usize, entry: usize, opaque: usize) -> Result<(), SbiError> {
+    match call(SBI_EXT_HSM, SBI_HSM_HART_START, hart_id, entry, opaque) {
+        (0, _) => Ok(()),
+        (code, _) => Err(SbiError::from_code(code)),
+    }
+}
+
+/// Start every secondary hart listed in the device tree
+///
+/// Requires the SBI HSM extension. Returns the number of secondaries that
+/// came online and are parked waiting for the kernel.
+pub fn boot_secondary_cpus(dtb: &fdt::Fdt) -> usize {
+    let (major, minor) = spec_version();
+    if !probe_extension(SBI_EXT_HSM) {
+        uart_println!("SMP: SBI v{}.{} without HSM, secondary harts stay offline", major, minor);
+        return 0;
+    }
+    uart_println!("SMP: SBI v{}.{} with HSM", major, minor);
+
+    let boot_hart = super::boot_hart_id();
+    let mut online = 0;
+
+    for cpu in dtb.cpus() {
+        let hart_id = cpu.ids().first();
+        if hart_id == boot_hart {
+            continue;
+        }
+
+        let status = cpu.property("status").and_then(|p| p.as_str());
+        if !matches!(status, None | Some("okay") | Some("ok")) {
+            uart_println!("  Hart {}: status {:?}, skipped", hart_id, status);
+            continue;
+        }
+
+        if online == MAX_CPUS - 1 {
+            uart_println!("  Hart {}: exceeds MAX_CPUS ({}), skipped", hart_id, MAX_CPUS);
+            continue;
+        }
+
+        let index = online;
+        if let Err(e) = hart_start(hart_id, _secondary_start as *const () as usize, index) {
+            uart_println!("  Hart {}: HART_START failed: {:?}", hart_id, e);
+            continue;
+        }
+
+        let mut spins = 0;
+        while !CPU_ONLINE[index].load(Ordering::Acquire) && spins < ONLINE_TIMEOUT {
+            core::hint::spin_loop();
+            spins += 1;
+        }
+
+        if CPU_ONLINE[index].load(Ordering::Acquire) {
+            uart_println!("  Hart {}: online (boot stack slot {})", hart_id, index);
+            online += 1;
+        } else {
+            uart_println!("  Hart {}: did not come online", hart_id);
+        }
+    }
+
+    online
+}
+
+/// Release parked secondary harts into the kernel entry point
+///
+/// Must be called by the boot hart right before it jumps to the kernel.
+pub fn release_secondary_cpus(kernel_entry: usize) {
+    KERNEL_RELEASE.store(kernel_entry, Ordering::Release);
+    super::fence();
+}
+
+/// Secondary hart entry point (target of SBI HART_START)
+/// a0 = hart id, a1 = opaque = boot stack slot
+#[unsafe(naked)]
+#[no_mangle]
+pub unsafe extern "C" fn _secondary_start() -> ! {
+    naked_asm!(
+        "csrw sie, zero",
+        "mv tp, a0",
+
+        // sp = __secondary_stacks + (slot + 1) * SECONDARY_STACK_SIZE
+        "lla t0, __secondary_stacks",
+        "addi t1, a1, 1",
+        "li t2, {stack_size}",
+        "mul t1, t1, t2",
+        "add sp, t0, t1",
+
+        // a0 = hart id, a1 = slot
+        "call _secondary_rust",
+
+        // Should never return
+        "1:",
+        "wfi",
+        "j 1b",
+        stack_size = const SECONDARY_STACK_SIZE,
+    )
+}
+
+/// Rust entry for secondary harts - park until the kernel is entered
+#[no_mangle]
+extern "C" fn _secondary_rust(hart_id: usize, slot: usize) -> ! {
+    CPU_ONLINE[slot].store(true, Ordering::Release);
+
+    // Interrupts are masked, so there is no wake-up event to sleep on
+    let entry = loop {
+        let entry = KERNEL_RELEASE.load(Ordering::Acquire);
+        if entry != 0 {
+            break entry;
+        }
+        core::hint::spin_loop();
+    };
+
+    // Secondaries enter the kernel with zeroed boot parameters; the kernel
+    // tells them apart from the boot hart by the hart id in tp.
+    super::set_kernel_hart_id(hart_id);
+    let kernel_fn: crate::KernelEntry = unsafe { core::mem::transmute(entry) };
+    kernel_fn(0, 0, 0, 0, 0, 0, 0, 0)
+}
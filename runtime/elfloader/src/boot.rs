@@ -1,59 +1,160 @@
 //! Boot sequence management - loading kernel and root task
 
+pub mod timing;
+
+use crate::cpio;
+use crate::devicetree::{self, fixup::Reservation};
+use crate::payload::{self, compression, verify};
 use crate::uart_println;
+use crate::utils::{is_aligned, PAGE_SIZE};
 use crate::BootInfo;
 
-/// Symbols provided by linker script for embedded kernel
+use alloc::vec::Vec;
+
+// Symbols provided by linker script for the embedded payload archive
 extern "C" {
-    static __kernel_image_start: u8;
-    static __kernel_image_end: u8;
+    static __payload_archive_start: u8;
+    static __payload_archive_end: u8;
+    static __stack_top: u8;
 }
 
-/// Symbols provided by linker script for embedded root task
-extern "C" {
-    static __user_image_start: u8;
-    static __user_image_end: u8;
+/// Archive directory holding device tree overlays
+pub const OVERLAY_DIR: &str = "overlays/";
+
+/// ELF segment permission flags (`p_flags`)
+pub const PF_X: u32 = 1 << 0;
+pub const PF_W: u32 = 1 << 1;
+pub const PF_R: u32 = 1 << 2;
+
+/// A loaded kernel PT_LOAD segment
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    /// Linked virtual address
+    pub vaddr: usize,
+    /// Physical address the segment was copied to
+    pub paddr: usize,
+    /// Size in memory (including BSS)
+    pub size: usize,
+    /// ELF `p_flags`
+    pub flags: u32,
+}
+
+/// Archive entry holding the kernel ELF
+pub const KERNEL_IMAGE_NAME: &str = "kernel";
+
+/// Archive entry holding the root task ELF
+pub const ROOTSERVER_IMAGE_NAME: &str = "rootserver";
+
+/// Get the CPIO archive linked into the elfloader
+///
+/// The archive bundles the kernel, root task, DTB overlays and any
+/// additional component ELFs; entries are looked up by name.
+pub fn payload_archive() -> cpio::Archive<'static> {
+    let archive = unsafe {
+        let start = &__payload_archive_start as *const u8;
+        let end = &__payload_archive_end as *const u8;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+
+    cpio::Archive::new(archive).expect("Invalid payload archive")
+}
+
+/// Get the kernel command line from `/chosen/bootargs`
+///
+/// The string is copied into the loader heap so it survives independently
+/// of the DTB (which the kernel may reclaim); empty bootargs yield `None`.
+pub fn bootargs(dtb: &fdt::Fdt) -> Option<&'static str> {
+    // fdt's `chosen()` panics when the node is absent, so look it up directly
+    let chosen = dtb.find_node("/chosen")?;
+    let bootargs = chosen.property("bootargs")?.as_str()?.trim();
+    if bootargs.is_empty() {
+        return None;
+    }
+
+    Some(alloc::string::String::from(bootargs).leak())
 }
 
-/// KaaL kernel boot parameters (passed in ARM64 registers x0-x4)
+/// KaaL kernel boot parameters (passed in ARM64 registers x0-x7)
 ///
 /// The KaaL kernel receives boot parameters via ARM64 calling convention:
-/// - x0 = DTB address
-/// - x1 = Root task physical start
-/// - x2 = Root task physical end
+/// - x0 = Root task physical start
+/// - x1 = Root task physical end
+/// - x2 = Physical-virtual offset
 /// - x3 = Root task virtual entry
-/// - x4 = Physical-virtual offset
-
-/// Load kernel and root task, return (kernel_entry, boot_info_for_root_task)
-pub fn load_images(dtb_addr: usize) -> (usize, BootInfo) {
-    uart_println!("Loading embedded images from ELF sections...");
-
-    // Get kernel image from .kernel_elf section
-    let (kernel_start, kernel_end) = unsafe {
-        (
-            &__kernel_image_start as *const u8 as usize,
-            &__kernel_image_end as *const u8 as usize,
-        )
-    };
+/// - x4 = DTB address
+/// - x5 = DTB size
+/// - x6 = Command line address (0 if none)
+/// - x7 = Command line length
 
-    let kernel_size = kernel_end - kernel_start;
-    uart_println!("  Kernel: {:#x} - {:#x} ({} KB)", kernel_start, kernel_end, kernel_size / 1024);
+/// Select the archive to boot from
+///
+/// With the `virtio-blk` feature a payload on a block device takes priority,
+/// falling back to the archive linked into the loader.
+#[cfg_attr(not(feature = "virtio-blk"), allow(unused_variables))]
+fn boot_archive(dtb: &fdt::Fdt) -> cpio::Archive<'static> {
+    #[cfg(feature = "virtio-blk")]
+    {
+        uart_println!("Looking for a disk payload...");
+        if let Some(archive) = payload::disk::load_archive(dtb) {
+            uart_println!("Loading images from disk payload...");
+            return archive;
+        }
+        uart_println!("  No disk payload found");
+    }
 
-    // Get root task from .roottask_data section
-    let (user_start, user_end) = unsafe {
-        (
-            &__user_image_start as *const u8 as usize,
-            &__user_image_end as *const u8 as usize,
-        )
-    };
+    uart_println!("Loading images from payload archive...");
+    payload_archive()
+}
 
-    let user_size = user_end - user_start;
-    uart_println!("  User:   {:#x} - {:#x} ({} KB)", user_start, user_end, user_size / 1024);
+/// Load kernel and root task, return (kernel_entry, boot_info_for_root_task,
+/// kernel_segments)
+///
+/// `dtb_blob` is the tree received from firmware; the boot info points at a
+/// patched copy with the payload's overlays applied and the loaded images
+/// reserved.
+pub fn load_images(dtb: &fdt::Fdt, dtb_blob: &'static [u8]) -> (usize, BootInfo, Vec<Segment>) {
+    let archive = boot_archive(dtb);
+    for entry in archive.entries().filter(|e| e.is_file()) {
+        uart_println!("  {:<24} {:#x} ({} KB)", entry.name, entry.data.as_ptr() as usize, entry.data.len() / 1024);
+    }
+
+    // Check build-time digests before any image is decompressed or parsed
+    verify::verify_or_halt(&archive, &[KERNEL_IMAGE_NAME, ROOTSERVER_IMAGE_NAME]);
+    timing::mark("Payload verify");
+
+    // Kernel image (decompressed if it was packed compressed)
+    let kernel_file = archive.find(KERNEL_IMAGE_NAME)
+        .expect("Payload archive has no kernel image");
+    let kernel_image = compression::decompress("kernel", kernel_file.data)
+        .expect("Failed to decompress kernel image");
+    let kernel_start = kernel_image.as_ptr() as usize;
+
+    // Root task image. The kernel maps the root task straight from these
+    // pages, so the ELF must start on a page boundary: compressed images are
+    // already decompressed into page-aligned memory, archive entries that
+    // are not aligned get copied. Either copy stays in the loader heap,
+    // which lies below the kernel and is reserved by its frame allocator.
+    let rootserver_file = archive.find(ROOTSERVER_IMAGE_NAME)
+        .expect("Payload archive has no rootserver image");
+    let user_image = compression::decompress("root task", rootserver_file.data)
+        .expect("Failed to decompress root task image");
+    let user_image = if is_aligned(user_image.as_ptr() as usize, PAGE_SIZE) {
+        user_image
+    } else {
+        let copy = payload::alloc_image(user_image.len())
+            .expect("Failed to allocate root task image");
+        copy.copy_from_slice(user_image);
+        copy
+    };
+    let user_start = user_image.as_ptr() as usize;
+    let user_end = user_start + user_image.len();
 
     // Parse kernel ELF and load its segments
     // The parse_elf_entry function loads all PT_LOAD segments to their target addresses
-    let kernel_entry = parse_elf_and_load_segments(kernel_start);
+    let (kernel_entry, kernel_segments) = parse_elf_and_load_segments(kernel_start);
     uart_println!("Kernel loaded at entry point: {:#x}", kernel_entry);
+    let kernel_range = kernel_segments.iter().map(|s| s.paddr).min().unwrap_or(0)
+        ..kernel_segments.iter().map(|s| s.paddr + s.size).max().unwrap_or(0);
 
     // Parse root task ELF header to get entry point, but DON'T load segments
     // The kernel will load the root-task into user virtual address space
@@ -64,6 +165,17 @@ pub fn load_images(dtb_addr: usize) -> (usize, BootInfo) {
     uart_println!("Kernel entry: {:#x}", kernel_entry);
     uart_println!("Root task:  {:#x} - {:#x}", user_start, user_end);
     uart_println!("Root entry: {:#x}", user_entry);
+    timing::mark("Image load");
+
+    // The loader range covers its heap (patched DTB, relocated images) and
+    // the boot stacks, which stay in use until the kernel switches away
+    let loader_range = (crate::arch::_start as *const () as usize)..(unsafe { &__stack_top as *const u8 as usize });
+    let dtb_out = prepare_dtb(dtb_blob, &archive, &[
+        Reservation { name: "kaal-elfloader", range: loader_range },
+        Reservation { name: "kaal-kernel", range: kernel_range },
+        Reservation { name: "kaal-rootserver", range: user_start..user_end },
+    ]);
+    timing::mark("DTB fixups");
 
     // Return kernel entry and boot info
     // The kernel expects info about the root task in these parameters
@@ -74,12 +186,54 @@ pub fn load_images(dtb_addr: usize) -> (usize, BootInfo) {
             user_img_end: user_end,          // Physical end of root task ELF
             pv_offset: 0,                    // Physical-virtual offset (identity mapped)
             user_entry,                      // Root task's entry point from its ELF header
-            dtb_addr: 0,                     // Will be filled by caller
-            dtb_size: 0,                     // Will be filled by caller
+            dtb_addr: dtb_out.as_ptr() as usize, // Patched device tree
+            dtb_size: dtb_out.len(),
+            cmdline_addr: 0,                 // Filled by caller from /chosen
+            cmdline_len: 0,
         },
+        kernel_segments,
     )
 }
 
+/// Build the device tree handed to the kernel
+///
+/// Applies every overlay in the archive, then reserves the given ranges and
+/// normalizes `/memory`. The result is flattened into loader memory; if the
+/// firmware tree cannot be unpacked it is passed on unchanged, and a bad
+/// overlay is skipped.
+fn prepare_dtb(blob: &'static [u8], archive: &cpio::Archive<'static>, reservations: &[Reservation]) -> &'static [u8] {
+    uart_println!("Preparing device tree...");
+
+    let mut tree = match devicetree::Tree::from_blob(blob) {
+        Ok(tree) => tree,
+        Err(e) => {
+            uart_println!("  Cannot edit device tree ({}), passing it unchanged", e);
+            return blob;
+        }
+    };
+
+    for entry in archive.files_in(OVERLAY_DIR) {
+        let result = devicetree::Tree::from_blob(entry.data)
+            .and_then(|overlay| devicetree::overlay::apply(&mut tree, &overlay));
+        match result {
+            Ok(fragments) => uart_println!("  Applied {} ({} fragment(s))", entry.name, fragments),
+            Err(e) => uart_println!("  Skipped {}: {}", entry.name, e),
+        }
+    }
+
+    devicetree::fixup::fixup_memory(&mut tree);
+    devicetree::fixup::reserve_memory(&mut tree, reservations);
+    for reservation in reservations {
+        uart_println!("  Reserved {:<16} {:#x} - {:#x}", reservation.name, reservation.range.start, reservation.range.end);
+    }
+
+    let flat = tree.to_blob();
+    let out = payload::alloc_image(flat.len()).expect("Failed to allocate device tree");
+    out.copy_from_slice(&flat);
+    uart_println!("  Device tree: {} -> {} bytes at {:#x}", blob.len(), out.len(), out.as_ptr() as usize);
+    out
+}
+
 /// Parse ELF header and return entry point without loading segments
 fn parse_elf_entry_point(elf_addr: usize) -> usize {
     // Read ELF header
@@ -101,15 +255,19 @@ fn parse_elf_entry_point(elf_addr: usize) -> usize {
     entry
 }
 
-/// Parse ELF and load its segments into memory, return entry point
-fn parse_elf_and_load_segments(elf_addr: usize) -> usize {
+/// Parse ELF and load its segments into memory, return entry point and the
+/// loaded segments
+///
+/// Segments are copied to their physical address (`p_paddr`); for a kernel
+/// linked at its load address this equals `p_vaddr`.
+fn parse_elf_and_load_segments(elf_addr: usize) -> (usize, Vec<Segment>) {
     // Read ELF header
     let elf_header = unsafe { core::slice::from_raw_parts(elf_addr as *const u8, 64) };
 
     // Check ELF magic number
     if &elf_header[0..4] != b"\x7FELF" {
         uart_println!("WARNING: Invalid ELF magic at {:#x}, using base address", elf_addr);
-        return elf_addr;
+        return (elf_addr, Vec::new());
     }
 
     // Read entry point from ELF64 header (offset 0x18, 8 bytes, little-endian)
@@ -119,6 +277,8 @@ fn parse_elf_and_load_segments(elf_addr: usize) -> usize {
         entry_bytes[4], entry_bytes[5], entry_bytes[6], entry_bytes[7],
     ]) as usize;
 
+    let mut segments = Vec::new();
+
     // Read program header offset and count
     let ph_off_bytes = &elf_header[0x20..0x28];
     let ph_off = u64::from_le_bytes([
@@ -154,6 +314,14 @@ fn parse_elf_and_load_segments(elf_addr: usize) -> usize {
                 p_vaddr_bytes[4], p_vaddr_bytes[5], p_vaddr_bytes[6], p_vaddr_bytes[7],
             ]) as usize;
 
+            let p_paddr_bytes = &ph[0x18..0x20];
+            let p_paddr = u64::from_le_bytes([
+                p_paddr_bytes[0], p_paddr_bytes[1], p_paddr_bytes[2], p_paddr_bytes[3],
+                p_paddr_bytes[4], p_paddr_bytes[5], p_paddr_bytes[6], p_paddr_bytes[7],
+            ]) as usize;
+
+            let p_flags = u32::from_le_bytes([ph[4], ph[5], ph[6], ph[7]]);
+
             let p_filesz_bytes = &ph[0x20..0x28];
             let p_filesz = u64::from_le_bytes([
                 p_filesz_bytes[0], p_filesz_bytes[1], p_filesz_bytes[2], p_filesz_bytes[3],
@@ -166,14 +334,15 @@ fn parse_elf_and_load_segments(elf_addr: usize) -> usize {
                 p_memsz_bytes[4], p_memsz_bytes[5], p_memsz_bytes[6], p_memsz_bytes[7],
             ]) as usize;
 
-            uart_println!("  LOAD segment {}: vaddr={:#x}, filesz={:#x}, memsz={:#x}",
-                         i, p_vaddr, p_filesz, p_memsz);
+            uart_println!("  LOAD segment {}: vaddr={:#x}, paddr={:#x}, filesz={:#x}, memsz={:#x}",
+                         i, p_vaddr, p_paddr, p_filesz, p_memsz);
+            segments.push(Segment { vaddr: p_vaddr, paddr: p_paddr, size: p_memsz, flags: p_flags });
 
-            // Copy segment from ELF file to its load address
-            // For identity mapping, physical address = virtual address
+            // Copy segment from ELF file to its physical load address
+            // (the MMU is off, so physical address = pointer)
             unsafe {
                 let src = (elf_addr + p_offset) as *const u8;
-                let dst = p_vaddr as *mut u8;
+                let dst = p_paddr as *mut u8;
 
                 // Copy file contents
                 if p_filesz > 0 {
@@ -182,7 +351,7 @@ fn parse_elf_and_load_segments(elf_addr: usize) -> usize {
 
                 // Zero remaining memory (BSS section)
                 if p_memsz > p_filesz {
-                    let zero_start = (p_vaddr + p_filesz) as *mut u8;
+                    let zero_start = (p_paddr + p_filesz) as *mut u8;
                     let zero_len = p_memsz - p_filesz;
                     core::ptr::write_bytes(zero_start, 0, zero_len);
                 }
@@ -190,7 +359,7 @@ fn parse_elf_and_load_segments(elf_addr: usize) -> usize {
         }
     }
 
-    entry
+    (entry, segments)
 }
 
 /// Update the rootserver structure with DTB information
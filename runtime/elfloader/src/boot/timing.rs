@@ -0,0 +1,81 @@
+//! Boot phase timing
+//!
+//! Each boot phase ends with `mark`, which timestamps it with the
+//! architectural counter (CNTPCT_EL0 on ARM64, `time` on RISC-V). Right
+//! before handoff `report` prints one line per phase, so changes in boot
+//! time show up in the serial log of every boot.
+
+use spin::Mutex;
+
+use crate::arch;
+use crate::uart_println;
+
+/// Maximum number of phases recorded
+const MAX_PHASES: usize = 16;
+
+struct Timeline {
+    /// Counter value when the loader started
+    start: u64,
+    /// Counter frequency in Hz (0 = unknown)
+    frequency: u64,
+    phases: [(&'static str, u64); MAX_PHASES],
+    count: usize,
+}
+
+static TIMELINE: Mutex<Timeline> = Mutex::new(Timeline {
+    start: 0,
+    frequency: 0,
+    phases: [("", 0); MAX_PHASES],
+    count: 0,
+});
+
+/// Record the loader start time (first thing in `elfloader_main`)
+pub fn start() {
+    let mut timeline = TIMELINE.lock();
+    timeline.start = arch::read_counter();
+    timeline.frequency = arch::counter_frequency();
+}
+
+/// Take the counter frequency from `/cpus/timebase-frequency` if the
+/// architecture does not report one (RISC-V)
+pub fn init_frequency(dtb: &fdt::Fdt) {
+    let mut timeline = TIMELINE.lock();
+    if timeline.frequency == 0 {
+        timeline.frequency = dtb
+            .find_node("/cpus")
+            .and_then(|cpus| cpus.property("timebase-frequency"))
+            .and_then(|p| p.as_usize())
+            .unwrap_or(0) as u64;
+    }
+}
+
+/// End the current phase
+pub fn mark(phase: &'static str) {
+    let now = arch::read_counter();
+    let mut timeline = TIMELINE.lock();
+    if timeline.count < MAX_PHASES {
+        let index = timeline.count;
+        timeline.phases[index] = (phase, now);
+        timeline.count += 1;
+    }
+}
+
+/// Print the per-phase summary table
+pub fn report() {
+    let timeline = TIMELINE.lock();
+    let to_us = |ticks: u64| match timeline.frequency {
+        0 => ticks,
+        hz => ((ticks as u128 * 1_000_000) / hz as u128) as u64,
+    };
+    let unit = if timeline.frequency == 0 { "ticks" } else { "us" };
+
+    uart_println!("Boot timing ({} Hz counter):", timeline.frequency);
+    uart_println!("  {:<20} {:>12} {:>12}", "Phase", unit, "total");
+
+    let mut previous = timeline.start;
+    for &(phase, at) in &timeline.phases[..timeline.count] {
+        uart_println!("  {:<20} {:>12} {:>12}",
+            phase, to_us(at.wrapping_sub(previous)), to_us(at.wrapping_sub(timeline.start)));
+        previous = at;
+    }
+}
@@ -0,0 +1,124 @@
+// CPIO archive parsing (SVR4 "newc" format)
+//
+// The build system packs every boot image (kernel, root task, DTB overlays,
+// extra component ELFs) into a single newc archive that is linked into the
+// elfloader. Entries are looked up by name at boot.
+
+/// newc header magic (no checksum)
+const NEWC_MAGIC: &[u8; 6] = b"070701";
+/// newc header magic (with checksum)
+const NEWC_CRC_MAGIC: &[u8; 6] = b"070702";
+/// Size of the fixed newc header
+const HEADER_SIZE: usize = 110;
+/// Name of the archive terminator entry
+const TRAILER: &str = "TRAILER!!!";
+
+/// File type mask and regular file bit in `c_mode`
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+/// A single file in the archive
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    /// Path of the file inside the archive (without leading "./")
+    pub name: &'a str,
+    /// File contents
+    pub data: &'a [u8],
+    /// File mode bits
+    pub mode: u32,
+}
+
+impl<'a> Entry<'a> {
+    /// Check if this entry is a regular file
+    pub fn is_file(&self) -> bool {
+        (self.mode & S_IFMT) == S_IFREG
+    }
+}
+
+/// Read-only view over a newc archive
+#[derive(Clone, Copy)]
+pub struct Archive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    /// Create an archive view, validating the first header magic
+    pub fn new(data: &'a [u8]) -> Result<Self, &'static str> {
+        if data.len() < HEADER_SIZE {
+            return Err("CPIO archive truncated");
+        }
+        if &data[0..6] != NEWC_MAGIC && &data[0..6] != NEWC_CRC_MAGIC {
+            return Err("Not a newc CPIO archive");
+        }
+        Ok(Self { data })
+    }
+
+    /// Iterate over all entries (directories included)
+    pub fn entries(&self) -> Entries<'a> {
+        Entries { data: self.data, offset: 0 }
+    }
+
+    /// Find a regular file by name
+    pub fn find(&self, name: &str) -> Option<Entry<'a>> {
+        self.entries().find(|e| e.is_file() && e.name == name)
+    }
+
+    /// Iterate over regular files under a directory prefix (e.g. "overlays/")
+    pub fn files_in(&self, prefix: &'a str) -> impl Iterator<Item = Entry<'a>> + 'a {
+        self.entries().filter(move |e| e.is_file() && e.name.starts_with(prefix))
+    }
+}
+
+/// Iterator over archive entries, stops at the trailer or on malformed data
+pub struct Entries<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Entry<'a>> {
+        let header = self.data.get(self.offset..self.offset + HEADER_SIZE)?;
+        if &header[0..6] != NEWC_MAGIC && &header[0..6] != NEWC_CRC_MAGIC {
+            return None;
+        }
+
+        let mode = parse_hex(&header[14..22])?;
+        let file_size = parse_hex(&header[54..62])? as usize;
+        let name_size = parse_hex(&header[94..102])? as usize;
+
+        // Name includes a terminating NUL
+        let name_start = self.offset + HEADER_SIZE;
+        let name_bytes = self.data.get(name_start..name_start + name_size.checked_sub(1)?)?;
+        let name = core::str::from_utf8(name_bytes).ok()?;
+        if name == TRAILER {
+            return None;
+        }
+
+        // Header + name and file data are each padded to 4 bytes
+        let data_start = align4(name_start + name_size);
+        let data = self.data.get(data_start..data_start + file_size)?;
+        self.offset = align4(data_start + file_size);
+
+        Some(Entry {
+            name: name.strip_prefix("./").unwrap_or(name),
+            data,
+            mode,
+        })
+    }
+}
+
+/// Parse an 8-character ASCII hex header field
+fn parse_hex(field: &[u8]) -> Option<u32> {
+    let mut value: u32 = 0;
+    for &c in field {
+        let digit = (c as char).to_digit(16)?;
+        value = (value << 4) | digit;
+    }
+    Some(value)
+}
+
+const fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
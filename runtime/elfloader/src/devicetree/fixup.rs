@@ -0,0 +1,59 @@
+//! Memory node fixups for the loaded images
+//!
+//! The tree firmware passes describes all of RAM as free. After loading, the
+//! loader itself (including its heap, which holds the patched tree and any
+//! relocated images), the kernel and the root task occupy parts of it, so
+//! each gets a `no-map` child under `/reserved-memory` that later consumers
+//! of the tree must not hand out.
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::{encode_cells, Tree};
+
+/// A physical range to reserve, named after what occupies it
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub name: &'static str,
+    pub range: Range<usize>,
+}
+
+/// Add a `/reserved-memory` child for every non-empty reservation
+///
+/// The node is created if firmware did not provide one. Its cell sizes
+/// follow the root node, as the binding requires.
+pub fn reserve_memory(tree: &mut Tree, reservations: &[Reservation]) {
+    let (address_cells, size_cells) = tree.root_cells();
+
+    let reserved = tree.root.child_or_insert("reserved-memory");
+    if reserved.property("ranges").is_none() {
+        reserved.set_property_u32("#address-cells", address_cells as u32);
+        reserved.set_property_u32("#size-cells", size_cells as u32);
+        reserved.set_property("ranges", &[]);
+    }
+    let address_cells = reserved.property_u32("#address-cells").map_or(address_cells, |c| c as usize);
+    let size_cells = reserved.property_u32("#size-cells").map_or(size_cells, |c| c as usize);
+
+    for reservation in reservations.iter().filter(|r| !r.range.is_empty()) {
+        let mut reg = Vec::new();
+        encode_cells(&mut reg, reservation.range.start as u64, address_cells);
+        encode_cells(&mut reg, reservation.range.len() as u64, size_cells);
+
+        let node = reserved.child_or_insert(&format!("{}@{:x}", reservation.name, reservation.range.start));
+        node.set_property("reg", &reg);
+        node.set_property("no-map", &[]);
+    }
+}
+
+/// Normalize `/memory` nodes
+///
+/// Some firmware omits `device_type` on `memory@...` nodes; consumers that
+/// look memory up by type would then see no RAM at all.
+pub fn fixup_memory(tree: &mut Tree) {
+    for node in tree.root.children.iter_mut() {
+        if (node.name == "memory" || node.name.starts_with("memory@")) && node.property("device_type").is_none() {
+            node.set_property_str("device_type", "memory");
+        }
+    }
+}
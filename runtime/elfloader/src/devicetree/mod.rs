@@ -0,0 +1,379 @@
+//! Editable flattened device tree
+//!
+//! The `fdt` crate only reads trees in place. Before handoff the loader has
+//! to change the tree it received from firmware (apply overlays packed in
+//! the payload archive, reserve the memory holding the loaded images), so
+//! the blob is unpacked into an owned tree, edited, and flattened again
+//! into fresh loader memory that is handed to the kernel instead.
+
+pub mod fixup;
+pub mod overlay;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// FDT header magic
+const FDT_MAGIC: u32 = 0xd00d_feed;
+/// FDT version written (and the oldest compatible one)
+const FDT_VERSION: u32 = 17;
+const FDT_LAST_COMP_VERSION: u32 = 16;
+/// Size of the version 17 header
+const FDT_HEADER_SIZE: usize = 40;
+
+/// Structure block tokens
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// A property: name and raw (big-endian) value
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+/// A node with its properties and children, in blob order
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    /// Node name including the unit address (empty for the root)
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(name: &str) -> Self {
+        Self { name: String::from(name), ..Self::default() }
+    }
+
+    /// Raw value of a property
+    pub fn property(&self, name: &str) -> Option<&[u8]> {
+        self.properties.iter().find(|p| p.name == name).map(|p| p.value.as_slice())
+    }
+
+    /// Property as a single big-endian u32 cell
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        let value = self.property(name)?;
+        Some(u32::from_be_bytes(value.get(0..4)?.try_into().ok()?))
+    }
+
+    /// Property as a NUL-terminated string
+    pub fn property_str(&self, name: &str) -> Option<&str> {
+        let value = self.property(name)?;
+        core::str::from_utf8(value.strip_suffix(&[0]).unwrap_or(value)).ok()
+    }
+
+    /// Add or replace a property
+    pub fn set_property(&mut self, name: &str, value: &[u8]) {
+        match self.properties.iter_mut().find(|p| p.name == name) {
+            Some(property) => property.value = Vec::from(value),
+            None => self.properties.push(Property { name: String::from(name), value: Vec::from(value) }),
+        }
+    }
+
+    /// Add or replace a string property
+    pub fn set_property_str(&mut self, name: &str, value: &str) {
+        let mut bytes = Vec::from(value.as_bytes());
+        bytes.push(0);
+        self.set_property(name, &bytes);
+    }
+
+    /// Add or replace a single-cell property
+    pub fn set_property_u32(&mut self, name: &str, value: u32) {
+        self.set_property(name, &value.to_be_bytes());
+    }
+
+    pub fn remove_property(&mut self, name: &str) {
+        self.properties.retain(|p| p.name != name);
+    }
+
+    /// Child by name; a name without a unit address matches `name@...`
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        let index = self.child_index(name)?;
+        Some(&self.children[index])
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        let index = self.child_index(name)?;
+        Some(&mut self.children[index])
+    }
+
+    /// Child by exact name, created empty if it does not exist
+    pub fn child_or_insert(&mut self, name: &str) -> &mut Node {
+        let index = match self.children.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::new(name));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    fn child_index(&self, name: &str) -> Option<usize> {
+        self.children.iter().position(|c| c.name == name).or_else(|| {
+            if name.contains('@') {
+                return None;
+            }
+            self.children.iter().position(|c| c.name.split('@').next() == Some(name))
+        })
+    }
+}
+
+/// An owned device tree
+#[derive(Debug, Clone)]
+pub struct Tree {
+    pub root: Node,
+    /// Memory reservation block entries (address, size)
+    pub reservations: Vec<(u64, u64)>,
+    /// Physical id of the boot CPU
+    pub boot_cpuid: u32,
+}
+
+impl Tree {
+    /// Unpack a flattened tree
+    pub fn from_blob(blob: &[u8]) -> Result<Self, &'static str> {
+        let header = |index: usize| -> Result<u32, &'static str> {
+            read_u32(blob, index * 4).ok_or("FDT header truncated")
+        };
+
+        if header(0)? != FDT_MAGIC {
+            return Err("Bad FDT magic");
+        }
+        let total_size = header(1)? as usize;
+        let struct_offset = header(2)? as usize;
+        let strings_offset = header(3)? as usize;
+        let rsvmap_offset = header(4)? as usize;
+        if header(6)? > FDT_VERSION {
+            return Err("Unsupported FDT version");
+        }
+        let boot_cpuid = header(7)?;
+        let strings_size = header(8)? as usize;
+
+        let blob = blob.get(..total_size).ok_or("FDT truncated")?;
+        let strings = blob
+            .get(strings_offset..strings_offset + strings_size)
+            .ok_or("FDT strings block out of bounds")?;
+
+        let mut reservations = Vec::new();
+        let mut offset = rsvmap_offset;
+        loop {
+            let address = read_u64(blob, offset).ok_or("FDT reservation block truncated")?;
+            let size = read_u64(blob, offset + 8).ok_or("FDT reservation block truncated")?;
+            if address == 0 && size == 0 {
+                break;
+            }
+            reservations.push((address, size));
+            offset += 16;
+        }
+
+        let mut parser = Parser { blob, strings, offset: struct_offset };
+        let root = match parser.token()? {
+            FDT_BEGIN_NODE => parser.node()?,
+            _ => return Err("FDT structure block does not start with a node"),
+        };
+        if parser.token()? != FDT_END {
+            return Err("FDT structure block not terminated");
+        }
+
+        Ok(Self { root, reservations, boot_cpuid })
+    }
+
+    /// Flatten the tree into a version 17 blob
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut structure = Vec::new();
+        let mut strings = Vec::new();
+        flatten(&self.root, &mut structure, &mut strings);
+        push_u32(&mut structure, FDT_END);
+
+        let rsvmap_offset = FDT_HEADER_SIZE;
+        let struct_offset = rsvmap_offset + (self.reservations.len() + 1) * 16;
+        let strings_offset = struct_offset + structure.len();
+        let total_size = strings_offset + strings.len();
+
+        let mut blob = Vec::with_capacity(total_size);
+        for field in [
+            FDT_MAGIC,
+            total_size as u32,
+            struct_offset as u32,
+            strings_offset as u32,
+            rsvmap_offset as u32,
+            FDT_VERSION,
+            FDT_LAST_COMP_VERSION,
+            self.boot_cpuid,
+            strings.len() as u32,
+            structure.len() as u32,
+        ] {
+            push_u32(&mut blob, field);
+        }
+        for &(address, size) in self.reservations.iter().chain([(0, 0)].iter()) {
+            blob.extend_from_slice(&address.to_be_bytes());
+            blob.extend_from_slice(&size.to_be_bytes());
+        }
+        blob.extend_from_slice(&structure);
+        blob.extend_from_slice(&strings);
+        blob
+    }
+
+    /// Node at an absolute path
+    pub fn node(&self, path: &str) -> Option<&Node> {
+        path.split('/')
+            .filter(|c| !c.is_empty())
+            .try_fold(&self.root, |node, name| node.child(name))
+    }
+
+    pub fn node_mut(&mut self, path: &str) -> Option<&mut Node> {
+        path.split('/')
+            .filter(|c| !c.is_empty())
+            .try_fold(&mut self.root, |node, name| node.child_mut(name))
+    }
+
+    /// Absolute path of the node carrying `phandle`
+    pub fn phandle_path(&self, phandle: u32) -> Option<String> {
+        fn search(node: &Node, phandle: u32, path: &mut String) -> bool {
+            if node.property_u32("phandle") == Some(phandle) || node.property_u32("linux,phandle") == Some(phandle) {
+                return true;
+            }
+            for child in &node.children {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&child.name);
+                if search(child, phandle, path) {
+                    return true;
+                }
+                path.truncate(len);
+            }
+            false
+        }
+
+        let mut path = String::new();
+        search(&self.root, phandle, &mut path).then(|| if path.is_empty() { String::from("/") } else { path })
+    }
+
+    /// `#address-cells` and `#size-cells` of the root node
+    pub fn root_cells(&self) -> (usize, usize) {
+        (
+            self.root.property_u32("#address-cells").unwrap_or(2) as usize,
+            self.root.property_u32("#size-cells").unwrap_or(1) as usize,
+        )
+    }
+}
+
+/// Structure block reader
+struct Parser<'a> {
+    blob: &'a [u8],
+    strings: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Next token, skipping NOPs
+    fn token(&mut self) -> Result<u32, &'static str> {
+        loop {
+            let token = read_u32(self.blob, self.offset).ok_or("FDT structure block truncated")?;
+            self.offset += 4;
+            if token != FDT_NOP {
+                return Ok(token);
+            }
+        }
+    }
+
+    /// Parse a node whose FDT_BEGIN_NODE token was just consumed
+    fn node(&mut self) -> Result<Node, &'static str> {
+        let name = self.cstr(self.blob, self.offset)?;
+        self.offset = align4(self.offset + name.len() + 1);
+        let mut node = Node::new(name);
+
+        loop {
+            match self.token()? {
+                FDT_PROP => {
+                    let len = read_u32(self.blob, self.offset).ok_or("FDT property truncated")? as usize;
+                    let name_offset = read_u32(self.blob, self.offset + 4).ok_or("FDT property truncated")? as usize;
+                    let start = self.offset + 8;
+                    let value = self.blob.get(start..start + len).ok_or("FDT property value out of bounds")?;
+                    let name = self.cstr(self.strings, name_offset)?;
+                    node.properties.push(Property { name: String::from(name), value: Vec::from(value) });
+                    self.offset = align4(start + len);
+                }
+                FDT_BEGIN_NODE => node.children.push(self.node()?),
+                FDT_END_NODE => return Ok(node),
+                _ => return Err("Unexpected FDT token"),
+            }
+        }
+    }
+
+    fn cstr(&self, data: &'a [u8], offset: usize) -> Result<&'a str, &'static str> {
+        let bytes = data.get(offset..).ok_or("FDT string out of bounds")?;
+        let len = bytes.iter().position(|&b| b == 0).ok_or("FDT string not terminated")?;
+        core::str::from_utf8(&bytes[..len]).map_err(|_| "FDT string is not UTF-8")
+    }
+}
+
+fn flatten(node: &Node, structure: &mut Vec<u8>, strings: &mut Vec<u8>) {
+    push_u32(structure, FDT_BEGIN_NODE);
+    structure.extend_from_slice(node.name.as_bytes());
+    structure.push(0);
+    pad4(structure);
+
+    for property in &node.properties {
+        push_u32(structure, FDT_PROP);
+        push_u32(structure, property.value.len() as u32);
+        push_u32(structure, string_offset(strings, &property.name));
+        structure.extend_from_slice(&property.value);
+        pad4(structure);
+    }
+
+    for child in &node.children {
+        flatten(child, structure, strings);
+    }
+
+    push_u32(structure, FDT_END_NODE);
+}
+
+/// Offset of a property name in the strings block, appending it if new
+fn string_offset(strings: &mut Vec<u8>, name: &str) -> u32 {
+    let mut offset = 0;
+    for existing in strings.split(|&b| b == 0) {
+        if existing == name.as_bytes() && offset < strings.len() {
+            return offset as u32;
+        }
+        offset += existing.len() + 1;
+    }
+
+    let offset = strings.len();
+    strings.extend_from_slice(name.as_bytes());
+    strings.push(0);
+    offset as u32
+}
+
+/// Encode a value as `cells` big-endian 32-bit cells
+pub fn encode_cells(out: &mut Vec<u8>, value: u64, cells: usize) {
+    for i in (0..cells).rev() {
+        let cell = if i < 2 { (value >> (32 * i)) as u32 } else { 0 };
+        push_u32(out, cell);
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn pad4(out: &mut Vec<u8>) {
+    while out.len() % 4 != 0 {
+        out.push(0);
+    }
+}
+
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
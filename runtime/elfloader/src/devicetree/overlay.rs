@@ -0,0 +1,78 @@
+//! Device tree overlay application
+//!
+//! Overlays (`.dtbo`, built with `dtc -@`) are packed into the payload
+//! archive under `overlays/` and applied in archive order. Each fragment
+//! names its target either with `target-path` or with a `target` phandle;
+//! label references (`target = <&uart0>`) are resolved through the overlay's
+//! `__fixups__` and the base tree's `__symbols__`. The fragment's
+//! `__overlay__` node is then merged into the target: properties replace
+//! existing ones, child nodes are merged recursively.
+//!
+//! Phandles defined inside an overlay are not renumbered
+//! (`__local_fixups__` is ignored), so overlays should only add properties
+//! and nodes that are not referenced by phandle.
+
+use alloc::string::String;
+
+use super::{Node, Tree};
+
+/// Apply an overlay to `base`, returning the number of fragments applied
+pub fn apply(base: &mut Tree, overlay: &Tree) -> Result<usize, &'static str> {
+    let mut applied = 0;
+
+    for fragment in &overlay.root.children {
+        let content = match fragment.child("__overlay__") {
+            Some(content) => content,
+            None => continue,
+        };
+
+        let path = target_path(base, overlay, fragment)?;
+        let target = base.node_mut(&path).ok_or("Overlay target node not found")?;
+        merge(target, content);
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+/// Resolve the base tree path a fragment applies to
+fn target_path(base: &Tree, overlay: &Tree, fragment: &Node) -> Result<String, &'static str> {
+    if let Some(path) = fragment.property_str("target-path") {
+        return Ok(String::from(path));
+    }
+
+    if let Some(label) = fixup_label(overlay, &fragment.name) {
+        return base
+            .node("/__symbols__")
+            .and_then(|symbols| symbols.property_str(label))
+            .map(String::from)
+            .ok_or("Overlay target label not in base tree __symbols__");
+    }
+
+    let phandle = fragment.property_u32("target").ok_or("Overlay fragment has no target")?;
+    base.phandle_path(phandle).ok_or("Overlay target phandle not in base tree")
+}
+
+/// Label whose `__fixups__` entry patches `/<fragment>:target:0`
+fn fixup_label<'a>(overlay: &'a Tree, fragment: &str) -> Option<&'a str> {
+    let fixups = overlay.root.child("__fixups__")?;
+    fixups.properties.iter().find_map(|property| {
+        let mut locations = property.value.split(|&b| b == 0).filter(|l| !l.is_empty());
+        locations
+            .any(|location| {
+                let location = core::str::from_utf8(location).unwrap_or("");
+                location.strip_prefix('/').and_then(|l| l.strip_prefix(fragment)) == Some(":target:0")
+            })
+            .then_some(property.name.as_str())
+    })
+}
+
+/// Merge overlay content into a base node
+fn merge(target: &mut Node, content: &Node) {
+    for property in &content.properties {
+        target.set_property(&property.name, &property.value);
+    }
+    for child in &content.children {
+        merge(target.child_or_insert(&child.name), child);
+    }
+}
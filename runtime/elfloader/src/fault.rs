@@ -0,0 +1,165 @@
+//! Fallback panic console
+//!
+//! A panic or an unexpected exception used to leave nothing but a one-line
+//! message (or nothing at all) on the serial console. The panic handler now
+//! follows the message with a register dump, the fault syndrome (ESR/FAR on
+//! ARM64, scause/stval on RISC-V) and a hexdump around the faulting address,
+//! so a bricked boot still produces a report to work from.
+//!
+//! Exceptions taken in the loader arrive through the vectors installed by
+//! `arch::install_exception_vectors`, which record their trap frame here and
+//! panic. For a plain `panic!` the registers are captured in the panic
+//! handler itself and the memory around the stack pointer is dumped.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use crate::arch;
+use crate::uart_println;
+
+/// Bytes shown before and after the faulting address
+const HEXDUMP_WINDOW: usize = 64;
+
+/// Bytes per hexdump line
+const HEXDUMP_LINE: usize = 16;
+
+/// Register state at an exception or panic
+///
+/// The layout is shared with the exception entry code: `regs` holds x0-x30
+/// on ARM64 and x1-x31 on RISC-V.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrapFrame {
+    pub regs: [usize; 31],
+    pub sp: usize,
+    pub pc: usize,
+    /// SPSR on ARM64, sstatus on RISC-V
+    pub status: usize,
+    /// ESR on ARM64, scause on RISC-V
+    pub cause: usize,
+    /// FAR on ARM64, stval on RISC-V
+    pub fault_addr: usize,
+}
+
+/// Frame of the exception being reported, if the panic came from one
+static EXCEPTION: Mutex<Option<TrapFrame>> = Mutex::new(None);
+
+/// Set once the panic handler is running
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Record the frame of an unhandled exception before panicking on it
+pub fn record_exception(frame: &TrapFrame) {
+    *EXCEPTION.lock() = Some(*frame);
+}
+
+/// Mark the start of panic reporting
+///
+/// Returns `false` if a panic is already being reported, e.g. when the
+/// hexdump itself faulted; the caller should then stop immediately.
+pub fn enter_panic() -> bool {
+    !PANICKING.swap(true, Ordering::SeqCst)
+}
+
+/// Print registers, syndrome and a hexdump for the current panic
+pub fn dump() {
+    let (frame, exception) = match EXCEPTION.lock().take() {
+        Some(frame) => (frame, true),
+        None => {
+            let mut frame = TrapFrame::default();
+            arch::capture_registers(&mut frame);
+            (frame, false)
+        }
+    };
+
+    uart_println!();
+    uart_println!("Registers{}:", if exception { " at exception" } else { " in panic handler" });
+    for (row, names) in arch::GPR_NAMES.chunks(4).enumerate() {
+        uart_println!("  {}", RegisterRow { names, values: &frame.regs[row * 4..] });
+    }
+    uart_println!("  {:>4}: {:#018x}   {:>4}: {:#018x}   {:>6}: {:#018x}",
+        "sp", frame.sp, "pc", frame.pc, arch::STATUS_NAME, frame.status);
+
+    // Outside an exception these are whatever the last exception left behind
+    let (cause, fault_addr) = if exception {
+        (frame.cause, frame.fault_addr)
+    } else {
+        arch::read_fault_registers()
+    };
+    uart_println!();
+    uart_println!("{}: {:#018x} ({})", arch::CAUSE_NAME, cause, arch::describe_cause(cause));
+    uart_println!("{}: {:#018x}", arch::FAULT_ADDR_NAME, fault_addr);
+
+    let (label, addr) = if exception {
+        ("faulting address", arch::fault_address(&frame))
+    } else {
+        ("stack pointer", frame.sp)
+    };
+    uart_println!();
+    uart_println!("Memory around {} {:#x}:", label, addr);
+    hexdump(addr);
+}
+
+/// One row of the register dump
+struct RegisterRow<'a> {
+    names: &'a [&'static str],
+    values: &'a [usize],
+}
+
+impl core::fmt::Display for RegisterRow<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, (name, value)) in self.names.iter().zip(self.values).enumerate() {
+            if i > 0 {
+                f.write_str("   ")?;
+            }
+            write!(f, "{:>4}: {:#018x}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Print `HEXDUMP_WINDOW` bytes on either side of `addr`
+///
+/// The loader runs identity mapped, so the address is read directly. An
+/// address outside RAM faults again, which `enter_panic` turns into a short
+/// message instead of a loop.
+fn hexdump(addr: usize) {
+    let start = addr.saturating_sub(HEXDUMP_WINDOW) & !(HEXDUMP_LINE - 1);
+    let end = addr.saturating_add(HEXDUMP_WINDOW);
+
+    for line in (start..end).step_by(HEXDUMP_LINE) {
+        let mut bytes = [0u8; HEXDUMP_LINE];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile((line + i) as *const u8) };
+        }
+
+        let marker = if (line..line + HEXDUMP_LINE).contains(&addr) { '>' } else { ' ' };
+        uart_println!("{} {:#018x}: {}  |{}|", marker, line, HexBytes(&bytes), AsciiBytes(&bytes));
+    }
+}
+
+struct HexBytes<'a>(&'a [u8]);
+
+impl core::fmt::Display for HexBytes<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i == HEXDUMP_LINE / 2 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:02x} ", byte)?;
+        }
+        Ok(())
+    }
+}
+
+struct AsciiBytes<'a>(&'a [u8]);
+
+impl core::fmt::Display for AsciiBytes<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for &byte in self.0 {
+            let c = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 //! KaaL Elfloader - Rust-based bootloader for KaaL kernel
 //!
-//! This bootloader prepares the ARM64 system for running the KaaL microkernel
-//! and root task. It handles:
-//! - ARM64 boot initialization
+//! This bootloader prepares the ARM64 or RISC-V (rv64) system for running
+//! the KaaL microkernel and root task. It handles:
+//! - ARM64 / RISC-V boot initialization
 //! - MMU and page table setup
 //! - ELF image loading
 //! - Device tree processing
@@ -78,10 +78,16 @@ static ALLOCATOR: BumpAllocator = BumpAllocator {
 
 pub mod arch;
 pub mod boot;
+pub mod cpio;
+pub mod devicetree;
+pub mod fault;
+#[cfg(target_arch = "aarch64")]
 pub mod mmu;
 pub mod payload;
 pub mod uart;
 pub mod utils;
+#[cfg(feature = "virtio-blk")]
+pub mod virtio_blk;
 
 /// Boot information passed to the kernel
 #[repr(C)]
@@ -98,28 +104,46 @@ pub struct BootInfo {
     pub dtb_addr: usize,
     /// Device tree size
     pub dtb_size: usize,
+    /// Physical address of the kernel command line (`/chosen/bootargs`), 0 if none
+    pub cmdline_addr: usize,
+    /// Length of the kernel command line in bytes
+    pub cmdline_len: usize,
 }
 
 /// Kernel entry function type
-type KernelEntry = extern "C" fn(usize, usize, usize, usize, usize, usize) -> !;
+pub(crate) type KernelEntry = extern "C" fn(usize, usize, usize, usize, usize, usize, usize, usize) -> !;
 
 /// Main elfloader entry point (called from assembly)
 #[no_mangle]
 pub extern "C" fn elfloader_main(dtb_addr: usize) -> ! {
-    // Initialize UART for debug output
+    boot::timing::start();
+
+    // Early UART (platforms with a known address only)
     uart::init();
-    uart::println!("═══════════════════════════════════════════════════════════");
-    uart::println!("  KaaL Elfloader v0.1.0 - Rust Microkernel Boot Loader");
-    uart::println!("═══════════════════════════════════════════════════════════");
-    uart::println!();
-    uart::println!("DTB address: {:#x}", dtb_addr);
+
+    // Report faults in the loader through the panic console
+    arch::install_exception_vectors();
 
     // Parse device tree
     let dtb = unsafe { fdt::Fdt::from_ptr(dtb_addr as *const u8) }
         .expect("Failed to parse device tree");
 
+    // Switch to the console UART described by the device tree
+    let console = uart::init_from_dtb(&dtb);
+
+    uart::println!("═══════════════════════════════════════════════════════════");
+    uart::println!("  KaaL Elfloader v0.1.0 - Rust Microkernel Boot Loader");
+    uart::println!("═══════════════════════════════════════════════════════════");
+    uart::println!();
+    uart::println!("DTB address: {:#x}", dtb_addr);
     uart::println!("Device tree parsed successfully");
+    match console {
+        Some(uart) => uart::println!("Console: {:?} UART at {:#x}", uart.kind, uart.base),
+        None => uart::println!("Console: no supported UART in device tree, using early UART"),
+    }
     uart::println!("Model: {}", dtb.root().model());
+    boot::timing::init_frequency(&dtb);
+    boot::timing::mark("DTB parse");
 
     // Get memory info from device tree
     let memory = dtb.memory();
@@ -135,39 +159,107 @@ pub extern "C" fn elfloader_main(dtb_addr: usize) -> ! {
     uart::println!();
     uart::println!("Loading images...");
 
-    // Load kernel and user images
-    let (kernel_entry, mut boot_info) = boot::load_images(dtb_addr);
-
-    // Set DTB info in boot_info
-    boot_info.dtb_addr = dtb_addr;
-    boot_info.dtb_size = dtb.total_size();
+    // Load kernel and user images; boot_info points at the patched DTB
+    let dtb_blob = unsafe { core::slice::from_raw_parts(dtb_addr as *const u8, dtb.total_size()) };
+    #[cfg_attr(not(target_arch = "aarch64"), allow(unused_variables))]
+    let (kernel_entry, mut boot_info, kernel_segments) = boot::load_images(&dtb, dtb_blob);
+    let dtb = unsafe { fdt::Fdt::from_ptr(boot_info.dtb_addr as *const u8) }
+        .expect("Failed to parse patched device tree");
+
+    // Command line from /chosen/bootargs (e.g. QEMU -append)
+    if let Some(cmdline) = boot::bootargs(&dtb) {
+        uart::println!("Command line: {}", cmdline);
+        boot_info.cmdline_addr = cmdline.as_ptr() as usize;
+        boot_info.cmdline_len = cmdline.len();
+    }
 
     // Update rootserver structure with DTB information
-    boot::update_rootserver_dtb(kernel_entry, dtb_addr, dtb.total_size());
+    boot::update_rootserver_dtb(kernel_entry, boot_info.dtb_addr, boot_info.dtb_size);
 
     uart::println!("Kernel entry: {:#x}", kernel_entry);
     uart::println!("User image: {:#x} - {:#x}",
         boot_info.user_img_start, boot_info.user_img_end);
     uart::println!("User entry: {:#x}", boot_info.user_entry);
 
+    // Bring up secondary cores; they park in the loader until handoff
+    #[cfg(feature = "smp")]
+    {
+        uart::println!();
+        let secondaries = arch::boot_secondary_cpus(&dtb);
+        uart::println!("SMP: {} secondary core(s) online", secondaries);
+        boot::timing::mark("SMP bring-up");
+    }
+
     uart::println!();
     uart::println!("Setting up page tables...");
 
-    // Set up page tables for kernel
-    let mut pt_mgr = mmu::PageTableManager::new();
-
-    // Identity map elfloader memory
+    // Identity map elfloader memory (up to the boot stack, which stays in
+    // use after the MMU is enabled)
     extern "C" {
         static __elfloader_end: u8;
+        static __stack_top: u8;
     }
     let elfloader_end = unsafe { &__elfloader_end as *const u8 as usize };
-    pt_mgr.setup_identity_map(0x10000000, elfloader_end);
+    let stack_top = unsafe { &__stack_top as *const u8 as usize };
+
+    // Set up page tables for kernel. A kernel linked at its load address
+    // sets up its own tables; a higher-half kernel is mapped here and
+    // entered with the MMU on.
+    #[cfg(target_arch = "aarch64")]
+    let kernel_translation = {
+        let mut pt_mgr = mmu::PageTableManager::new();
+        pt_mgr.setup_identity_map(0x10000000, elfloader_end.max(stack_top));
+
+        let higher_half = kernel_segments.iter().any(|s| s.vaddr != s.paddr);
+        if higher_half {
+            uart::println!("Mapping higher-half kernel:");
+            pt_mgr.map_kernel(&kernel_segments).expect("Failed to map kernel segments");
+            if let Some(uart) = console {
+                pt_mgr.map_device(uart.base, utils::PAGE_SIZE).expect("Failed to map console UART");
+            }
+        }
+
+        uart::println!("Page tables configured");
+        uart::println!("TTBR0: {:#x}", pt_mgr.get_ttbr0());
+        uart::println!("TTBR1: {:#x}", pt_mgr.get_ttbr1());
+
+        higher_half.then(|| pt_mgr.translation())
+    };
 
-    uart::println!("Page tables configured");
-    uart::println!("TTBR0: {:#x}", pt_mgr.get_ttbr0());
+    #[cfg(target_arch = "riscv64")]
+    {
+        static mut SV39_ROOT: arch::Sv39PageTable = arch::Sv39PageTable::new();
+        let root = unsafe { &mut *core::ptr::addr_of_mut!(SV39_ROOT) };
+        root.setup_identity_map(0x8000_0000, elfloader_end.max(stack_top));
+
+        uart::println!("Page tables configured");
+        uart::println!("satp: {:#x}", root.satp());
+
+        // The kernel must not take a stray external interrupt before it has
+        // installed its trap vector
+        arch::quiesce_plic(&dtb);
+        arch::disable_interrupts();
+    }
+    boot::timing::mark("Page tables");
 
     uart::println!();
+    #[cfg(target_arch = "aarch64")]
+    match kernel_translation {
+        Some(_) if arch::get_current_el() != 1 => panic!("Higher-half kernel handoff requires EL1"),
+        Some(_) => uart::println!("Enabling MMU for higher-half kernel"),
+        None => uart::println!("Skipping MMU setup - kernel will handle it"),
+    }
+    #[cfg(not(target_arch = "aarch64"))]
     uart::println!("Skipping MMU setup - kernel will handle it");
+
+    // The console UART is mapped, so output continues with the MMU on.
+    // Secondaries enable the same translation when they are released.
+    #[cfg(target_arch = "aarch64")]
+    if let Some(translation) = kernel_translation {
+        mmu::set_kernel_translation(translation);
+        translation.enable();
+        boot::timing::mark("MMU enable");
+    }
     uart::println!();
     uart::println!("Jumping to KaaL kernel at {:#x}...", kernel_entry);
     uart::println!("  Passing root task info:");
@@ -175,27 +267,46 @@ pub extern "C" fn elfloader_main(dtb_addr: usize) -> ! {
     uart::println!("    user_entry: {:#x}", boot_info.user_entry);
     uart::println!("    pv_offset: {:#x}", boot_info.pv_offset);
     uart::println!("    dtb: {:#x} (size: {})", boot_info.dtb_addr, boot_info.dtb_size);
+    uart::println!("    cmdline: {:#x} (len: {})", boot_info.cmdline_addr, boot_info.cmdline_len);
+    uart::println!();
+    boot::timing::mark("Handoff");
+    boot::timing::report();
     uart::println!("═══════════════════════════════════════════════════════════");
     uart::println!();
 
+    // Secondaries follow the boot CPU into the kernel
+    #[cfg(feature = "smp")]
+    arch::release_secondary_cpus(kernel_entry);
+
+    // RISC-V has no S-mode MPIDR; the kernel reads its hart id from tp
+    #[cfg(target_arch = "riscv64")]
+    arch::set_kernel_hart_id(arch::boot_hart_id());
+
     // Jump to kernel with root task boot info
     let kernel_fn: KernelEntry = unsafe { core::mem::transmute(kernel_entry) };
     kernel_fn(
-        boot_info.user_img_start,   // x0: user physical start
-        boot_info.user_img_end,     // x1: user physical end
-        boot_info.pv_offset,        // x2: physical-virtual offset
-        boot_info.user_entry,       // x3: user entry point
-        boot_info.dtb_addr,         // x4: DTB address
-        boot_info.dtb_size,         // x5: DTB size
+        boot_info.user_img_start,   // x0/a0: user physical start
+        boot_info.user_img_end,     // x1/a1: user physical end
+        boot_info.pv_offset,        // x2/a2: physical-virtual offset
+        boot_info.user_entry,       // x3/a3: user entry point
+        boot_info.dtb_addr,         // x4/a4: DTB address
+        boot_info.dtb_size,         // x5/a5: DTB size
+        boot_info.cmdline_addr,     // x6/a6: command line address
+        boot_info.cmdline_len,      // x7/a7: command line length
     )
 }
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    uart::println!("PANIC: {}", info);
-    loop {
-        core::hint::spin_loop();
+    uart::force_unlock();
+    if !fault::enter_panic() {
+        uart::println!("PANIC while reporting a panic: {}", info);
+        arch::halt();
     }
+
+    uart::println!("PANIC: {}", info);
+    fault::dump();
+    arch::halt()
 }
 
 #[alloc_error_handler]
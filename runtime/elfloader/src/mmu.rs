@@ -1,5 +1,14 @@
 // MMU and page table management for ARM64
+//
+// Tables use the 4KB granule with 39-bit virtual addresses (levels 1-3).
+// TTBR0 covers the low half and holds the identity map of the loader;
+// TTBR1 covers the top 512GB (from KERNEL_VA_BASE) and holds a higher-half
+// kernel at its linked virtual addresses. Tables come from the loader heap,
+// which the kernel inherits as reserved memory.
 
+use alloc::boxed::Box;
+
+use crate::boot::{Segment, PF_W, PF_X};
 use crate::utils::{align_down, align_up, PAGE_SIZE};
 use crate::uart_println;
 
@@ -11,10 +20,64 @@ const PTE_AF: u64 = 1 << 10; // Access flag
 const PTE_SH: u64 = 3 << 8;  // Inner shareable
 const PTE_ATTR_NORMAL: u64 = 0 << 2; // Normal memory
 const PTE_ATTR_DEVICE: u64 = 1 << 2; // Device memory
+const PTE_AP_RO: u64 = 1 << 7; // Read-only (AP[2])
+const PTE_PXN: u64 = 1 << 53; // Privileged execute-never
+const PTE_UXN: u64 = 1 << 54; // Unprivileged execute-never
+
+/// Output address bits of a table or page descriptor
+const PTE_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+/// Permission bits that are merged when two mappings share a page
+const PTE_PERM_MASK: u64 = PTE_AP_RO | PTE_PXN | PTE_UXN;
 
 /// Page table levels
 const PT_LEVELS: usize = 3;
 
+/// Size of a level 2 block
+const BLOCK_SIZE: usize = 0x200000;
+
+/// First virtual address translated through TTBR1 (T1SZ = 25)
+pub const KERNEL_VA_BASE: usize = 0xffff_ff80_0000_0000;
+
+/// Memory attributes of a mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapAttrs {
+    /// Normal memory, read/write/execute (loader identity map)
+    Normal,
+    /// Kernel code: read-only, executable at EL1
+    KernelText,
+    /// Kernel read-only data
+    KernelRodata,
+    /// Kernel data and BSS: read/write, never executable
+    KernelData,
+    /// Device-nGnRnE MMIO, never executable
+    Device,
+}
+
+impl MapAttrs {
+    /// Attributes for a kernel ELF segment with the given `p_flags`
+    pub fn from_elf_flags(flags: u32) -> Self {
+        if flags & PF_X != 0 {
+            MapAttrs::KernelText
+        } else if flags & PF_W != 0 {
+            MapAttrs::KernelData
+        } else {
+            MapAttrs::KernelRodata
+        }
+    }
+
+    fn bits(self) -> u64 {
+        let normal = PTE_ATTR_NORMAL | PTE_SH | PTE_AF;
+        match self {
+            MapAttrs::Normal => normal,
+            MapAttrs::KernelText => normal | PTE_AP_RO | PTE_UXN,
+            MapAttrs::KernelRodata => normal | PTE_AP_RO | PTE_PXN | PTE_UXN,
+            MapAttrs::KernelData => normal | PTE_PXN | PTE_UXN,
+            MapAttrs::Device => PTE_ATTR_DEVICE | PTE_AF | PTE_PXN | PTE_UXN,
+        }
+    }
+}
+
 /// Page table
 #[repr(C, align(4096))]
 pub struct PageTable {
@@ -43,21 +106,64 @@ impl PageTable {
         let idx = (vaddr >> 21) & 0x1ff;
         self.entries[idx] = (paddr as u64 & !(0x1fffff)) | flags | PTE_VALID | PTE_AF;
     }
+
+    /// Next-level table behind entry `idx`, allocated if the entry is empty
+    fn next_table(&mut self, idx: usize) -> Result<&mut PageTable, &'static str> {
+        let entry = self.entries[idx];
+        if entry & PTE_VALID == 0 {
+            let table = Box::leak(Box::new(PageTable::new()));
+            self.entries[idx] = table.as_ptr() as u64 | PTE_TABLE | PTE_VALID;
+            return Ok(table);
+        }
+        if entry & PTE_TABLE == 0 {
+            return Err("Mapping overlaps a block mapping");
+        }
+        Ok(unsafe { &mut *((entry & PTE_ADDR_MASK) as *mut PageTable) })
+    }
 }
 
-/// Simple page table manager
+/// Translation registers for a set of tables
+#[derive(Debug, Clone, Copy)]
+pub struct Translation {
+    pub ttbr0: usize,
+    pub ttbr1: usize,
+    pub mair: u64,
+    pub tcr: u64,
+}
+
+impl Translation {
+    /// Turn the MMU on for the current core
+    pub fn enable(&self) {
+        crate::arch::tlb_invalidate_all();
+        crate::arch::icache_invalidate_all();
+        crate::arch::enable_mmu(self.ttbr0, self.ttbr1, self.mair, self.tcr);
+    }
+}
+
+/// Translation the kernel is entered with, if the loader enables the MMU
+static KERNEL_TRANSLATION: spin::Mutex<Option<Translation>> = spin::Mutex::new(None);
+
+/// Record the translation secondaries must enable before entering the kernel
+pub fn set_kernel_translation(translation: Translation) {
+    *KERNEL_TRANSLATION.lock() = Some(translation);
+}
+
+/// Translation published with `set_kernel_translation`
+pub fn kernel_translation() -> Option<Translation> {
+    *KERNEL_TRANSLATION.lock()
+}
+
+/// Page table manager for the TTBR0 and TTBR1 halves
 pub struct PageTableManager {
-    l1_table: PageTable,
-    l2_table: PageTable,
-    l3_table: PageTable,
+    l1_table: &'static mut PageTable,
+    l1_kernel_table: &'static mut PageTable,
 }
 
 impl PageTableManager {
     pub fn new() -> Self {
         Self {
-            l1_table: PageTable::new(),
-            l2_table: PageTable::new(),
-            l3_table: PageTable::new(),
+            l1_table: Box::leak(Box::new(PageTable::new())),
+            l1_kernel_table: Box::leak(Box::new(PageTable::new())),
         }
     }
 
@@ -69,16 +175,76 @@ impl PageTableManager {
         let end_page = align_up(end, PAGE_SIZE);
 
         // Map using 2MB blocks for simplicity
-        let start_block = align_down(start_page, 0x200000);
-        let end_block = align_up(end_page, 0x200000);
+        let start_block = align_down(start_page, BLOCK_SIZE);
+        let end_block = align_up(end_page, BLOCK_SIZE);
+
+        for addr in (start_block..end_block).step_by(BLOCK_SIZE) {
+            let l2_table = self.l1_table.next_table((addr >> 30) & 0x1ff)
+                .expect("Identity map overlaps an existing mapping");
+            l2_table.map_block(addr, addr, MapAttrs::Normal.bits());
+        }
+    }
 
-        for addr in (start_block..end_block).step_by(0x200000) {
-            self.l2_table.map_block(addr, addr, PTE_ATTR_NORMAL | PTE_SH);
+    /// Identity map an MMIO range (e.g. the console UART) as device memory
+    pub fn map_device(&mut self, paddr: usize, size: usize) -> Result<(), &'static str> {
+        let start = align_down(paddr, PAGE_SIZE);
+        let end = align_up(paddr + size, PAGE_SIZE);
+        for addr in (start..end).step_by(PAGE_SIZE) {
+            self.map_page(addr, addr, MapAttrs::Device)?;
         }
+        Ok(())
+    }
+
+    /// Map every kernel segment at its linked virtual address
+    ///
+    /// Segments in the TTBR1 window go into the kernel tables, others into
+    /// TTBR0. Pages shared by two segments get the union of their
+    /// permissions, so a text/data boundary inside a page stays usable.
+    pub fn map_kernel(&mut self, segments: &[Segment]) -> Result<(), &'static str> {
+        for segment in segments {
+            if (segment.vaddr ^ segment.paddr) & (PAGE_SIZE - 1) != 0 {
+                return Err("Kernel segment vaddr and paddr are not congruent modulo the page size");
+            }
 
-        // Link L1 to L2
-        let l2_addr = self.l2_table.as_ptr();
-        self.l1_table.entries[0] = (l2_addr as u64) | PTE_TABLE | PTE_VALID;
+            let attrs = MapAttrs::from_elf_flags(segment.flags);
+            let vstart = align_down(segment.vaddr, PAGE_SIZE);
+            let vend = align_up(segment.vaddr + segment.size, PAGE_SIZE);
+            let pstart = align_down(segment.paddr, PAGE_SIZE);
+
+            uart_println!("  Kernel {:?}: {:#x} - {:#x} -> {:#x}", attrs, vstart, vend, pstart);
+
+            for offset in (0..vend - vstart).step_by(PAGE_SIZE) {
+                self.map_page(vstart + offset, pstart + offset, attrs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Map one 4KB page, merging permissions with an existing mapping
+    fn map_page(&mut self, vaddr: usize, paddr: usize, attrs: MapAttrs) -> Result<(), &'static str> {
+        let l1_table = if vaddr >= KERNEL_VA_BASE {
+            &mut *self.l1_kernel_table
+        } else if vaddr >> 39 == 0 {
+            &mut *self.l1_table
+        } else {
+            return Err("Virtual address outside the 39-bit TTBR0/TTBR1 ranges");
+        };
+
+        let l2_table = l1_table.next_table((vaddr >> 30) & 0x1ff)?;
+        let l3_table = l2_table.next_table((vaddr >> 21) & 0x1ff)?;
+
+        let idx = (vaddr >> 12) & 0x1ff;
+        let old = l3_table.entries[idx];
+        let mut flags = attrs.bits() | PTE_PAGE;
+        if old & PTE_VALID != 0 {
+            if old & PTE_ADDR_MASK != paddr as u64 & PTE_ADDR_MASK {
+                return Err("Virtual page already mapped to a different frame");
+            }
+            // A restriction only survives if both mappings ask for it
+            flags = (flags & !PTE_PERM_MASK) | (flags & old & PTE_PERM_MASK);
+        }
+        l3_table.map_page(vaddr, paddr, flags);
+        Ok(())
     }
 
     /// Get L1 table address for TTBR0
@@ -86,12 +252,29 @@ impl PageTableManager {
         self.l1_table.as_ptr()
     }
 
+    /// Get L1 table address for TTBR1 (kernel window)
+    pub fn get_ttbr1(&self) -> usize {
+        self.l1_kernel_table.as_ptr()
+    }
+
+    /// Register values selecting these tables
+    pub fn translation(&self) -> Translation {
+        Translation {
+            ttbr0: self.get_ttbr0(),
+            ttbr1: self.get_ttbr1(),
+            mair: Self::get_mair(),
+            tcr: Self::get_tcr(),
+        }
+    }
+
     /// Get default TCR value for 39-bit VA
     pub fn get_tcr() -> u64 {
         // T0SZ = 25 (39-bit VA), T1SZ = 25
         // TG0 = 0 (4KB granule), TG1 = 2 (4KB granule)
-        // IPS = 0 (32-bit PA)
-        (25 << 0) | (25 << 16) | (0 << 14) | (2 << 30) | (0 << 32)
+        // IRGN/ORGN = 1 (write-back), SH = 3 (inner shareable) for both walks
+        // IPS = 2 (40-bit PA)
+        let walk = (1 << 8) | (1 << 10) | (3 << 12);
+        (25 << 0) | (25 << 16) | walk | (walk << 16) | (0 << 14) | (2 << 30) | (2 << 32)
     }
 
     /// Get default MAIR value
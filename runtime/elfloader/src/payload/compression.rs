@@ -0,0 +1,311 @@
+//! Compressed image support
+//!
+//! The kernel and root task images embedded in the elfloader may be stored
+//! compressed to keep the boot image small. The format is detected from the
+//! frame magic at boot, so raw ELF images keep working unchanged:
+//!
+//! - `\x7FELF`      - uncompressed ELF, used in place
+//! - `0x184D2204`   - LZ4 frame (`lz4 --content-size`), feature `compression-lz4`
+//! - `0xFD2FB528`   - Zstandard frame, feature `compression-zstd`
+//!
+//! Both compressed formats must record the decompressed content size in the
+//! frame header. The loader allocates exactly that many bytes (page aligned)
+//! from its heap, so decompressed images stay resident until kernel handoff.
+
+use crate::uart_println;
+
+/// LZ4 frame magic number (little-endian)
+const LZ4_FRAME_MAGIC: u32 = 0x184D_2204;
+
+/// Zstandard frame magic number (little-endian)
+const ZSTD_FRAME_MAGIC: u32 = 0xFD2F_B528;
+
+/// Image compression format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Raw image (no compression)
+    None,
+    /// LZ4 frame format
+    Lz4,
+    /// Zstandard frame format
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression format from the first bytes of an image
+    pub fn detect(data: &[u8]) -> Self {
+        if data.len() < 4 {
+            return Compression::None;
+        }
+
+        match u32::from_le_bytes([data[0], data[1], data[2], data[3]]) {
+            LZ4_FRAME_MAGIC => Compression::Lz4,
+            ZSTD_FRAME_MAGIC => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Human-readable format name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Decompress an embedded image if needed
+///
+/// Returns the image as it should be used by the loader: the original slice
+/// for uncompressed images, or a freshly allocated, page-aligned buffer
+/// holding the decompressed bytes. Decompressed buffers are never freed.
+pub fn decompress(name: &str, data: &'static [u8]) -> Result<&'static [u8], &'static str> {
+    let format = Compression::detect(data);
+    if format == Compression::None {
+        return Ok(data);
+    }
+
+    uart_println!("  Decompressing {} image ({}, {} KB compressed)...",
+        name, format.name(), data.len() / 1024);
+
+    let output: &'static [u8] = match format {
+        Compression::None => unreachable!(),
+        Compression::Lz4 => decompress_lz4(data)?,
+        Compression::Zstd => decompress_zstd(data)?,
+    };
+
+    uart_println!("  {} image: {} KB -> {} KB at {:#x}",
+        name, data.len() / 1024, output.len() / 1024, output.as_ptr() as usize);
+
+    Ok(output)
+}
+
+#[cfg(feature = "compression-lz4")]
+fn decompress_lz4(data: &[u8]) -> Result<&'static [u8], &'static str> {
+    let frame = lz4::FrameHeader::parse(data)?;
+    let content_size = frame.content_size.ok_or("LZ4 frame has no content size (use --content-size)")?;
+
+    let output = super::alloc_image(content_size)?;
+    let written = lz4::decode_frame(&frame, data, output)?;
+    if written != content_size {
+        return Err("LZ4 decompressed size mismatch");
+    }
+
+    Ok(output)
+}
+
+#[cfg(not(feature = "compression-lz4"))]
+fn decompress_lz4(_data: &[u8]) -> Result<&'static [u8], &'static str> {
+    Err("LZ4 image found but elfloader built without compression-lz4")
+}
+
+#[cfg(feature = "compression-zstd")]
+fn decompress_zstd(data: &[u8]) -> Result<&'static [u8], &'static str> {
+    let mut decoder = ruzstd::FrameDecoder::new();
+    let mut header = data;
+    decoder.init(&mut header).map_err(|_| "Invalid zstd frame header")?;
+
+    let content_size = decoder.content_size() as usize;
+    if content_size == 0 {
+        return Err("zstd frame has no content size");
+    }
+
+    let output = super::alloc_image(content_size)?;
+    let written = decoder.decode_all(data, output).map_err(|_| "zstd decompression failed")?;
+    if written != content_size {
+        return Err("zstd decompressed size mismatch");
+    }
+
+    Ok(output)
+}
+
+#[cfg(not(feature = "compression-zstd"))]
+fn decompress_zstd(_data: &[u8]) -> Result<&'static [u8], &'static str> {
+    Err("zstd image found but elfloader built without compression-zstd")
+}
+
+/// Minimal LZ4 frame decoder
+///
+/// Supports the subset of the LZ4 frame format produced by the reference
+/// `lz4` tool: independent or linked blocks, optional block/content
+/// checksums (skipped, not verified) and uncompressed blocks. Dictionaries
+/// are not supported.
+#[cfg(feature = "compression-lz4")]
+mod lz4 {
+    /// FLG: block checksum present
+    const FLG_BLOCK_CHECKSUM: u8 = 1 << 4;
+    /// FLG: content size present
+    const FLG_CONTENT_SIZE: u8 = 1 << 3;
+    /// FLG: content checksum present
+    const FLG_CONTENT_CHECKSUM: u8 = 1 << 2;
+    /// FLG: dictionary ID present
+    const FLG_DICT_ID: u8 = 1 << 0;
+    /// Block size high bit marks an uncompressed block
+    const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+    /// Minimum match length encoded by a sequence
+    const MIN_MATCH: usize = 4;
+
+    /// Parsed LZ4 frame descriptor
+    pub struct FrameHeader {
+        pub flags: u8,
+        pub content_size: Option<usize>,
+        /// Offset of the first data block
+        pub data_offset: usize,
+    }
+
+    impl FrameHeader {
+        pub fn parse(data: &[u8]) -> Result<Self, &'static str> {
+            if data.len() < 7 {
+                return Err("LZ4 frame truncated");
+            }
+
+            let flags = data[4];
+            if flags >> 6 != 0b01 {
+                return Err("Unsupported LZ4 frame version");
+            }
+            if flags & FLG_DICT_ID != 0 {
+                return Err("LZ4 dictionaries are not supported");
+            }
+
+            // Magic (4) + FLG (1) + BD (1)
+            let mut offset = 6;
+            let content_size = if flags & FLG_CONTENT_SIZE != 0 {
+                let bytes = data.get(offset..offset + 8).ok_or("LZ4 frame truncated")?;
+                offset += 8;
+                let mut size = [0u8; 8];
+                size.copy_from_slice(bytes);
+                Some(u64::from_le_bytes(size) as usize)
+            } else {
+                None
+            };
+
+            // Header checksum byte
+            offset += 1;
+
+            Ok(Self { flags, content_size, data_offset: offset })
+        }
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> Result<u32, &'static str> {
+        let bytes = data.get(offset..offset + 4).ok_or("LZ4 frame truncated")?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Decode all blocks of a frame into `output`, returning bytes written
+    pub fn decode_frame(
+        header: &FrameHeader,
+        data: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, &'static str> {
+        let mut offset = header.data_offset;
+        let mut written = 0;
+
+        loop {
+            let block = read_u32(data, offset)?;
+            offset += 4;
+
+            // EndMark
+            if block == 0 {
+                break;
+            }
+
+            let size = (block & !BLOCK_UNCOMPRESSED) as usize;
+            let input = data.get(offset..offset + size).ok_or("LZ4 block truncated")?;
+
+            if block & BLOCK_UNCOMPRESSED != 0 {
+                let dest = output
+                    .get_mut(written..written + size)
+                    .ok_or("LZ4 output overflow")?;
+                dest.copy_from_slice(input);
+                written += size;
+            } else {
+                // Output is contiguous, so linked blocks can reference
+                // history from earlier blocks without extra bookkeeping.
+                written = decode_block(input, output, written)?;
+            }
+
+            offset += size;
+            if header.flags & FLG_BLOCK_CHECKSUM != 0 {
+                offset += 4;
+            }
+        }
+
+        if header.flags & FLG_CONTENT_CHECKSUM != 0 {
+            read_u32(data, offset)?;
+        }
+
+        Ok(written)
+    }
+
+    /// Read an LZ4 extended length (runs of 255 terminated by a smaller byte)
+    fn read_length(input: &[u8], pos: &mut usize, mut len: usize) -> Result<usize, &'static str> {
+        loop {
+            let byte = *input.get(*pos).ok_or("LZ4 length truncated")?;
+            *pos += 1;
+            len += byte as usize;
+            if byte != 255 {
+                return Ok(len);
+            }
+        }
+    }
+
+    /// Decode one compressed block, appending at `out_pos`
+    fn decode_block(input: &[u8], output: &mut [u8], mut out_pos: usize) -> Result<usize, &'static str> {
+        let mut pos = 0;
+
+        while pos < input.len() {
+            let token = input[pos];
+            pos += 1;
+
+            // Literals
+            let mut literal_len = (token >> 4) as usize;
+            if literal_len == 15 {
+                literal_len = read_length(input, &mut pos, literal_len)?;
+            }
+
+            let literals = input.get(pos..pos + literal_len).ok_or("LZ4 literals truncated")?;
+            output
+                .get_mut(out_pos..out_pos + literal_len)
+                .ok_or("LZ4 output overflow")?
+                .copy_from_slice(literals);
+            pos += literal_len;
+            out_pos += literal_len;
+
+            // The last sequence of a block carries literals only
+            if pos == input.len() {
+                break;
+            }
+
+            // Match
+            let match_offset = input
+                .get(pos..pos + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+                .ok_or("LZ4 match offset truncated")?;
+            pos += 2;
+
+            if match_offset == 0 || match_offset > out_pos {
+                return Err("LZ4 match offset out of range");
+            }
+
+            let mut match_len = (token & 0x0f) as usize;
+            if match_len == 15 {
+                match_len = read_length(input, &mut pos, match_len)?;
+            }
+            match_len += MIN_MATCH;
+
+            if out_pos + match_len > output.len() {
+                return Err("LZ4 output overflow");
+            }
+
+            // Byte-wise copy: matches may overlap their own output
+            let src = out_pos - match_offset;
+            for i in 0..match_len {
+                output[out_pos + i] = output[src + i];
+            }
+            out_pos += match_len;
+        }
+
+        Ok(out_pos)
+    }
+}
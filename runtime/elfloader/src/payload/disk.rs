@@ -0,0 +1,135 @@
+//! Payload archive on a block device
+//!
+//! With the `virtio-blk` feature the loader first looks for a boot payload
+//! on a virtio block device, so images can be updated without rebuilding or
+//! reflashing the loader. The disk holds the same CPIO archive that is
+//! otherwise linked in, behind a one-sector header:
+//!
+//! ```text
+//! offset  size  field
+//! 0x00    8     magic "KAALBOOT"
+//! 0x08    8     version (1), little-endian
+//! 0x10    8     archive offset in bytes, from the start of the header
+//! 0x18    8     archive size in bytes
+//! ```
+//!
+//! The header is looked for at the first sector of the disk and, for
+//! MBR-partitioned disks, at the first sector of each primary partition.
+
+use crate::cpio;
+use crate::uart_println;
+use crate::virtio_blk::{self, VirtioBlk, SECTOR_SIZE};
+
+/// Disk payload header magic
+pub const DISK_MAGIC: &[u8; 8] = b"KAALBOOT";
+
+/// Disk payload header version
+pub const DISK_VERSION: u64 = 1;
+
+/// MBR boot signature and partition table location
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_PARTITION_TABLE: usize = 0x1be;
+const MBR_PARTITION_ENTRY_SIZE: usize = 16;
+
+/// Parsed disk payload header
+#[derive(Debug, Clone, Copy)]
+pub struct DiskHeader {
+    /// Archive offset in bytes from the header sector
+    pub archive_offset: u64,
+    /// Archive size in bytes
+    pub archive_size: u64,
+}
+
+impl DiskHeader {
+    /// Parse a header sector, `None` if it carries no KaaL payload
+    pub fn parse(sector: &[u8]) -> Option<Self> {
+        if sector.len() < 32 || &sector[0..8] != DISK_MAGIC {
+            return None;
+        }
+
+        let field = |offset: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&sector[offset..offset + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        if field(0x08) != DISK_VERSION {
+            uart_println!("  Disk payload: unsupported header version {}", field(0x08));
+            return None;
+        }
+
+        Some(Self { archive_offset: field(0x10), archive_size: field(0x18) })
+    }
+}
+
+/// Load the payload archive from the first virtio block device that has one
+///
+/// Returns `None` when there is no block device or no payload on it, so the
+/// caller can fall back to the linked-in archive.
+pub fn load_archive(dtb: &fdt::Fdt) -> Option<cpio::Archive<'static>> {
+    let mut dev = virtio_blk::find_device(dtb)?;
+    uart_println!("  virtio-blk: {} MB", dev.capacity_bytes() / (1024 * 1024));
+
+    let (start_sector, header) = find_header(&mut dev)?;
+    uart_println!("  Disk payload at sector {}: archive {} KB at +{:#x}",
+        start_sector, header.archive_size / 1024, header.archive_offset);
+
+    match read_archive(&mut dev, start_sector, &header) {
+        Ok(archive) => Some(archive),
+        Err(e) => {
+            uart_println!("  Disk payload unusable: {}", e);
+            None
+        }
+    }
+}
+
+/// Locate the payload header on a raw disk or in an MBR primary partition
+fn find_header(dev: &mut VirtioBlk) -> Option<(u64, DiskHeader)> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    dev.read_sectors(0, &mut sector).ok()?;
+
+    if let Some(header) = DiskHeader::parse(&sector) {
+        return Some((0, header));
+    }
+
+    if sector[510..512] != MBR_SIGNATURE {
+        return None;
+    }
+
+    let mbr = sector;
+    for i in 0..4 {
+        let entry = &mbr[MBR_PARTITION_TABLE + i * MBR_PARTITION_ENTRY_SIZE..][..MBR_PARTITION_ENTRY_SIZE];
+        let first_lba = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64;
+        if entry[4] == 0 || first_lba == 0 {
+            continue;
+        }
+
+        if dev.read_sectors(first_lba, &mut sector).is_ok() {
+            if let Some(header) = DiskHeader::parse(&sector) {
+                return Some((first_lba, header));
+            }
+        }
+    }
+
+    None
+}
+
+/// Read the archive into page-aligned loader memory
+fn read_archive(
+    dev: &mut VirtioBlk,
+    start_sector: u64,
+    header: &DiskHeader,
+) -> Result<cpio::Archive<'static>, &'static str> {
+    if header.archive_offset % SECTOR_SIZE as u64 != 0 {
+        return Err("Archive offset is not sector aligned");
+    }
+
+    let size = header.archive_size as usize;
+    let padded = size.div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+    let buffer = super::alloc_image(padded)?;
+
+    let sector = start_sector + header.archive_offset / SECTOR_SIZE as u64;
+    dev.read_sectors(sector, buffer)?;
+
+    cpio::Archive::new(&buffer[..size])
+}
@@ -1,8 +1,36 @@
 //! Payload structures (shared with elfloader-builder)
 
+pub mod compression;
+#[cfg(feature = "virtio-blk")]
+pub mod disk;
+#[cfg(feature = "secure-boot")]
+pub mod signature;
+pub mod verify;
+
 use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
 
+/// Allocate a page-aligned image buffer that lives until kernel handoff
+///
+/// Used for images that must be relocated out of the payload archive
+/// (decompressed images, or entries that are not page aligned). The buffer
+/// comes from the loader heap and is never freed.
+pub fn alloc_image(size: usize) -> Result<&'static mut [u8], &'static str> {
+    if size == 0 {
+        return Err("Image size is zero");
+    }
+
+    let layout = core::alloc::Layout::from_size_align(size, crate::utils::PAGE_SIZE)
+        .map_err(|_| "Invalid image layout")?;
+
+    let ptr = unsafe { alloc::alloc::alloc(layout) };
+    if ptr.is_null() {
+        return Err("Out of memory for image buffer");
+    }
+
+    Ok(unsafe { core::slice::from_raw_parts_mut(ptr, size) })
+}
+
 /// Memory region to be loaded
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Region {
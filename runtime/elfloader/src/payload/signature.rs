@@ -0,0 +1,44 @@
+//! Signed-image secure boot
+//!
+//! With the `secure-boot` feature the loader only boots payload archives
+//! signed by the holder of the build's Ed25519 key. The signature covers the
+//! `SHA256SUMS` manifest, which in turn pins the digest of every other file
+//! in the archive (see `payload::verify`), so one signature authenticates
+//! the whole payload:
+//!
+//! ```text
+//! public key (in loader) -> SHA256SUMS.sig -> SHA256SUMS -> kernel, rootserver, ...
+//! ```
+//!
+//! The public key is embedded in the loader binary at build time from the
+//! 32-byte raw key file named by `KAAL_SECURE_BOOT_PUBKEY`.
+
+use ed25519_compact::{PublicKey, Signature};
+
+use super::verify::MANIFEST_NAME;
+use crate::cpio;
+
+/// Archive entry holding the detached manifest signature
+pub const SIGNATURE_NAME: &str = "SHA256SUMS.sig";
+
+/// Ed25519 public key trusted by this loader
+static PUBLIC_KEY: &[u8; PublicKey::BYTES] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/secure_boot_pubkey.bin"));
+
+/// Check the archive's manifest signature against the embedded public key
+pub fn verify_manifest(archive: &cpio::Archive) -> Result<(), &'static str> {
+    let manifest = archive.find(MANIFEST_NAME).ok_or("Payload archive has no SHA256SUMS manifest")?;
+    let signature = archive.find(SIGNATURE_NAME).ok_or("Payload archive is not signed (no SHA256SUMS.sig)")?;
+
+    let public_key = PublicKey::new(*PUBLIC_KEY);
+    let signature = Signature::from_slice(signature.data).map_err(|_| "Malformed manifest signature")?;
+
+    public_key
+        .verify(manifest.data, &signature)
+        .map_err(|_| "Manifest signature does not match the embedded public key")
+}
+
+/// First 8 bytes of the embedded key, for identifying it in boot logs
+pub fn key_id() -> &'static [u8] {
+    &PUBLIC_KEY[..8]
+}
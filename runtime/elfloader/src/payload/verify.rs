@@ -0,0 +1,198 @@
+//! Image integrity verification
+//!
+//! The build system records a SHA-256 digest of every file it packs into the
+//! payload archive in a `SHA256SUMS` entry (standard `sha256sum` format:
+//! `<64 hex digits>  <name>` per line). Before the images are decompressed or
+//! loaded, each listed entry is hashed and compared. A mismatch halts the
+//! boot with a UART report instead of jumping into a corrupted kernel.
+
+use sha2::{Digest, Sha256};
+
+use crate::cpio;
+use crate::uart_println;
+
+/// Archive entry holding the digest manifest
+pub const MANIFEST_NAME: &str = "SHA256SUMS";
+
+/// SHA-256 digest length in bytes
+pub const DIGEST_LEN: usize = 32;
+
+/// Verification failure
+#[derive(Debug, Clone, Copy)]
+pub enum VerifyError<'a> {
+    /// Manifest line could not be parsed
+    MalformedManifest { line: usize },
+    /// A file listed in the manifest is missing from the archive
+    MissingImage { name: &'a str },
+    /// A required image has no digest in the manifest
+    Unlisted { name: &'a str },
+    /// Signature check failed (`secure-boot` feature)
+    Signature(&'static str),
+    /// Digest of the image does not match the manifest
+    Mismatch {
+        name: &'a str,
+        expected: [u8; DIGEST_LEN],
+        actual: [u8; DIGEST_LEN],
+    },
+}
+
+/// Compute the SHA-256 digest of a byte slice
+pub fn sha256(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Parse a 64-digit hex string into a digest
+fn parse_digest(hex: &str) -> Option<[u8; DIGEST_LEN]> {
+    let hex = hex.as_bytes();
+    if hex.len() != DIGEST_LEN * 2 {
+        return None;
+    }
+
+    let mut digest = [0u8; DIGEST_LEN];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        let hi = (hex[i * 2] as char).to_digit(16)?;
+        let lo = (hex[i * 2 + 1] as char).to_digit(16)?;
+        *byte = ((hi << 4) | lo) as u8;
+    }
+    Some(digest)
+}
+
+/// Iterate over manifest lines as `(line_number, Some((name, digest)))`,
+/// with `None` for lines that do not parse
+fn manifest_entries(
+    manifest: &str,
+) -> impl Iterator<Item = (usize, Option<(&str, [u8; DIGEST_LEN])>)> {
+    manifest
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let entry = line.split_once(char::is_whitespace).and_then(|(hex, name)| {
+                // sha256sum marks binary mode with a leading '*'
+                let name = name.trim_start().trim_start_matches('*').trim_end();
+                let name = name.strip_prefix("./").unwrap_or(name);
+                Some((name, parse_digest(hex)?))
+            });
+            (i + 1, entry)
+        })
+}
+
+/// Verify every image listed in the archive's digest manifest
+///
+/// `required` images must be listed. Returns the number of verified images,
+/// or `Ok(0)` when the archive carries no manifest at all.
+///
+/// With the `secure-boot` feature the manifest must be present and signed,
+/// and every file in the archive must be listed in it.
+pub fn verify_archive<'a>(
+    archive: &cpio::Archive<'a>,
+    required: &[&'a str],
+) -> Result<usize, VerifyError<'a>> {
+    #[cfg(feature = "secure-boot")]
+    super::signature::verify_manifest(archive).map_err(VerifyError::Signature)?;
+
+    let manifest = match archive.find(MANIFEST_NAME) {
+        Some(entry) => core::str::from_utf8(entry.data)
+            .map_err(|_| VerifyError::MalformedManifest { line: 0 })?,
+        None => return Ok(0),
+    };
+
+    let is_listed = |name: &str| manifest_entries(manifest).any(|(_, entry)| entry.map(|(n, _)| n) == Some(name));
+
+    for name in required {
+        if !is_listed(name) {
+            return Err(VerifyError::Unlisted { name });
+        }
+    }
+
+    // A signed manifest only vouches for what it lists, so nothing else may
+    // ride along in the archive
+    #[cfg(feature = "secure-boot")]
+    for entry in archive.entries().filter(|e| e.is_file()) {
+        if entry.name != MANIFEST_NAME && entry.name != super::signature::SIGNATURE_NAME && !is_listed(entry.name) {
+            return Err(VerifyError::Unlisted { name: entry.name });
+        }
+    }
+
+    let mut verified = 0;
+    for (line, entry) in manifest_entries(manifest) {
+        let (listed_name, expected) = entry.ok_or(VerifyError::MalformedManifest { line })?;
+        let image = archive
+            .find(listed_name)
+            .ok_or(VerifyError::MissingImage { name: listed_name })?;
+
+        let actual = sha256(image.data);
+        if actual != expected {
+            return Err(VerifyError::Mismatch { name: image.name, expected, actual });
+        }
+
+        uart_println!("  {:<24} sha256 OK", image.name);
+        verified += 1;
+    }
+
+    Ok(verified)
+}
+
+/// Verify the payload archive, halting the boot on any failure
+pub fn verify_or_halt(archive: &cpio::Archive<'static>, required: &[&'static str]) {
+    uart_println!("Verifying image integrity...");
+    #[cfg(feature = "secure-boot")]
+    {
+        crate::uart_print!("  Secure boot: Ed25519 key ");
+        for byte in super::signature::key_id() {
+            crate::uart_print!("{:02x}", byte);
+        }
+        uart_println!("...");
+    }
+
+    match verify_archive(archive, required) {
+        Ok(0) => {
+            uart_println!("  WARNING: no {} in payload archive, images NOT verified", MANIFEST_NAME);
+        }
+        Ok(count) => {
+            uart_println!("  {} image(s) verified", count);
+        }
+        Err(err) => {
+            report(&err);
+            crate::arch::halt();
+        }
+    }
+}
+
+/// Print a verification failure over UART
+fn report(err: &VerifyError) {
+    uart_println!();
+    uart_println!("═══════════════════════════════════════════════════════════");
+    uart_println!("  IMAGE VERIFICATION FAILED - refusing to boot");
+    uart_println!("═══════════════════════════════════════════════════════════");
+    match err {
+        VerifyError::MalformedManifest { line } => {
+            uart_println!("  {} is malformed (line {})", MANIFEST_NAME, line);
+        }
+        VerifyError::MissingImage { name } => {
+            uart_println!("  Image '{}' listed in {} is missing", name, MANIFEST_NAME);
+        }
+        VerifyError::Unlisted { name } => {
+            uart_println!("  Image '{}' has no digest in {}", name, MANIFEST_NAME);
+        }
+        VerifyError::Signature(reason) => {
+            uart_println!("  {}", reason);
+        }
+        VerifyError::Mismatch { name, expected, actual } => {
+            uart_println!("  Image '{}' is corrupted", name);
+            uart_print_digest("  expected", expected);
+            uart_print_digest("  actual  ", actual);
+        }
+    }
+}
+
+fn uart_print_digest(label: &str, digest: &[u8; DIGEST_LEN]) {
+    crate::uart_print!("{}: ", label);
+    for byte in digest {
+        crate::uart_print!("{:02x}", byte);
+    }
+    uart_println!();
+}
+
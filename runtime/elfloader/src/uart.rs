@@ -1,53 +1,133 @@
 // UART driver for debug output
+//
+// The console UART is discovered from the device tree at boot, so the same
+// loader binary runs on any board whose UART is one of the supported types:
+//
+// - PL011        ("arm,pl011")                - QEMU virt, Raspberry Pi 4
+// - 8250/16550   ("ns16550a", "brcm,bcm2835-aux-uart", ...) - Pi mini UART, QEMU riscv64 virt
+// - i.MX UART    ("fsl,imx8mq-uart", "fsl,imx6q-uart", ...) - i.MX8 boards
+//
+// `/chosen/stdout-path` is preferred; otherwise the first enabled node with a
+// known compatible string is used. Until the DTB has been parsed, output only
+// works on platforms with a compile-time fallback (`platform-qemu-virt`).
 
+use alloc::string::String;
 use core::fmt::{self, Write};
 use spin::Mutex;
 
-/// PL011 UART base address for QEMU ARM virt platform
-const UART_BASE: usize = 0x0900_0000;
-
-/// PL011 UART registers
-#[repr(C)]
-struct Pl011Regs {
-    dr: u32,      // Data register
-    _rsrecr: u32,
-    _reserved1: [u32; 4],
-    fr: u32,      // Flag register
-    _reserved2: [u32; 2],
-    ibrd: u32,    // Integer baud rate divisor
-    fbrd: u32,    // Fractional baud rate divisor
-    lcrh: u32,    // Line control register
-    cr: u32,      // Control register
-}
-
-impl Pl011Regs {
-    fn is_tx_full(&self) -> bool {
-        (self.fr & (1 << 5)) != 0
+/// PL011 UART base address for QEMU ARM virt platform (pre-DTB fallback)
+#[cfg(all(feature = "platform-qemu-virt", target_arch = "aarch64"))]
+const EARLY_UART: Uart = Uart { base: 0x0900_0000, kind: UartKind::Pl011 };
+
+/// 16550 UART base address for QEMU RISC-V virt platform (pre-DTB fallback)
+#[cfg(all(feature = "platform-qemu-virt", target_arch = "riscv64"))]
+const EARLY_UART: Uart = Uart { base: 0x1000_0000, kind: UartKind::Ns16550 { reg_shift: 0, io_width: 1 } };
+
+/// PL011 registers and flags
+const PL011_DR: usize = 0x00;
+const PL011_FR: usize = 0x18;
+const PL011_FR_TXFF: u32 = 1 << 5;
+
+/// 16550 registers (in units of the register stride) and flags
+const NS16550_THR: usize = 0;
+const NS16550_LSR: usize = 5;
+const NS16550_LSR_THRE: u32 = 1 << 5;
+
+/// i.MX UART registers and flags
+const IMX_UTXD: usize = 0x40;
+const IMX_UTS: usize = 0xb4;
+const IMX_UTS_TXFULL: u32 = 1 << 4;
+
+/// Maximum depth of bus nodes searched for a UART
+const MAX_SCAN_DEPTH: usize = 4;
+
+/// Supported UART register interfaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartKind {
+    /// ARM PrimeCell PL011
+    Pl011,
+    /// 8250/16550 compatible, registers spaced `1 << reg_shift` bytes apart
+    /// and accessed `io_width` bytes wide
+    Ns16550 { reg_shift: u8, io_width: u8 },
+    /// NXP i.MX UART
+    Imx,
+}
+
+/// Compatible strings the loader can drive, in preference order
+const COMPATIBLE: &[(&str, UartKind)] = &[
+    ("arm,pl011", UartKind::Pl011),
+    ("ns16550a", UartKind::Ns16550 { reg_shift: 0, io_width: 1 }),
+    ("ns16550", UartKind::Ns16550 { reg_shift: 0, io_width: 1 }),
+    ("snps,dw-apb-uart", UartKind::Ns16550 { reg_shift: 2, io_width: 4 }),
+    ("brcm,bcm2835-aux-uart", UartKind::Ns16550 { reg_shift: 2, io_width: 4 }),
+    ("fsl,imx8mq-uart", UartKind::Imx),
+    ("fsl,imx6q-uart", UartKind::Imx),
+    ("fsl,imx21-uart", UartKind::Imx),
+];
+
+/// Console UART instance
+#[derive(Debug, Clone, Copy)]
+pub struct Uart {
+    /// Physical MMIO base address
+    pub base: usize,
+    /// Register interface
+    pub kind: UartKind,
+}
+
+impl Uart {
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, value) }
     }
 
-    fn putc(&mut self, c: u8) {
-        while self.is_tx_full() {
-            core::hint::spin_loop();
+    fn putc(&self, c: u8) {
+        match self.kind {
+            UartKind::Pl011 => {
+                while self.read_reg(PL011_FR) & PL011_FR_TXFF != 0 {
+                    core::hint::spin_loop();
+                }
+                self.write_reg(PL011_DR, c as u32);
+            }
+            UartKind::Ns16550 { reg_shift, io_width } => {
+                let lsr = NS16550_LSR << reg_shift;
+                let thr = NS16550_THR << reg_shift;
+                if io_width == 4 {
+                    while self.read_reg(lsr) & NS16550_LSR_THRE == 0 {
+                        core::hint::spin_loop();
+                    }
+                    self.write_reg(thr, c as u32);
+                } else {
+                    unsafe {
+                        while core::ptr::read_volatile((self.base + lsr) as *const u8) as u32 & NS16550_LSR_THRE == 0 {
+                            core::hint::spin_loop();
+                        }
+                        core::ptr::write_volatile((self.base + thr) as *mut u8, c);
+                    }
+                }
+            }
+            UartKind::Imx => {
+                while self.read_reg(IMX_UTS) & IMX_UTS_TXFULL != 0 {
+                    core::hint::spin_loop();
+                }
+                self.write_reg(IMX_UTXD, c as u32);
+            }
         }
-        self.dr = c as u32;
     }
 }
 
 struct UartWriter {
-    regs: &'static mut Pl011Regs,
+    uart: Uart,
 }
 
 impl UartWriter {
-    fn new() -> Self {
-        let regs = unsafe { &mut *(UART_BASE as *mut Pl011Regs) };
-        Self { regs }
-    }
-
     fn write_byte(&mut self, byte: u8) {
         if byte == b'\n' {
-            self.regs.putc(b'\r');
+            self.uart.putc(b'\r');
         }
-        self.regs.putc(byte);
+        self.uart.putc(byte);
     }
 }
 
@@ -62,20 +142,151 @@ impl Write for UartWriter {
 
 static UART: Mutex<Option<UartWriter>> = Mutex::new(None);
 
-/// Initialize UART with explicit hardware setup
+/// Initialize the early console before the device tree is available
+///
+/// Only platforms with a known UART address print anything at this point;
+/// everywhere else output starts with `init_from_dtb`.
 pub fn init() {
-    // First, write a raw test character directly to hardware to verify access
-    unsafe {
-        let uart_dr = UART_BASE as *mut u32;
-        core::ptr::write_volatile(uart_dr, b'!' as u32);
+    #[cfg(feature = "platform-qemu-virt")]
+    {
+        *UART.lock() = Some(UartWriter { uart: EARLY_UART });
+    }
+}
+
+/// Switch the console to the UART described by the device tree
+///
+/// Returns the UART in use, or `None` if the tree describes no supported
+/// UART (the early console, if any, stays active).
+pub fn init_from_dtb(dtb: &fdt::Fdt) -> Option<Uart> {
+    let uart = discover(dtb)?;
+    *UART.lock() = Some(UartWriter { uart });
+    Some(uart)
+}
+
+/// Find the console UART in the device tree
+pub fn discover(dtb: &fdt::Fdt) -> Option<Uart> {
+    stdout_uart(dtb).or_else(|| {
+        let root = dtb.find_node("/")?;
+        scan(dtb, root, String::new(), 0)
+    })
+}
+
+/// UART named by `/chosen/stdout-path` (path or alias, ":options" stripped)
+fn stdout_uart(dtb: &fdt::Fdt) -> Option<Uart> {
+    let stdout = dtb.find_node("/chosen")?.property("stdout-path")?.as_str()?;
+    let name = stdout.split(':').next()?;
+    let path = if name.starts_with('/') { name } else { dtb.aliases()?.resolve(name)? };
+
+    let node = dtb.find_node(path)?;
+    let kind = match_compatible(node)?;
+    probe(dtb, node, path, kind)
+}
+
+/// Depth-first search for an enabled node with a supported compatible string
+fn scan(dtb: &fdt::Fdt, node: fdt::node::FdtNode, path: String, depth: usize) -> Option<Uart> {
+    for child in node.children() {
+        let mut child_path = path.clone();
+        child_path.push('/');
+        child_path.push_str(child.name);
+
+        if let Some(kind) = match_compatible(child) {
+            if let Some(uart) = probe(dtb, child, &child_path, kind) {
+                return Some(uart);
+            }
+        }
+
+        // Only buses with `ranges` map their children into the CPU address space
+        if depth < MAX_SCAN_DEPTH && child.property("ranges").is_some() {
+            if let Some(uart) = scan(dtb, child, child_path, depth + 1) {
+                return Some(uart);
+            }
+        }
     }
+    None
+}
+
+fn match_compatible(node: fdt::node::FdtNode) -> Option<UartKind> {
+    let compatible = node.compatible()?;
+    COMPATIBLE
+        .iter()
+        .find(|(name, _)| compatible.all().any(|c| c == *name))
+        .map(|&(_, kind)| kind)
+}
+
+/// Build a `Uart` from an enabled node, applying 16550 register properties
+fn probe(dtb: &fdt::Fdt, node: fdt::node::FdtNode, path: &str, kind: UartKind) -> Option<Uart> {
+    let status = node.property("status").and_then(|p| p.as_str());
+    if !matches!(status, None | Some("okay") | Some("ok")) {
+        return None;
+    }
+
+    let reg = node.reg()?.next()?;
+    let base = translate(dtb, path, reg.starting_address as usize)?;
+
+    let kind = match kind {
+        UartKind::Ns16550 { reg_shift, io_width } => UartKind::Ns16550 {
+            reg_shift: node.property("reg-shift").and_then(|p| p.as_usize()).map_or(reg_shift, |v| v as u8),
+            io_width: node.property("reg-io-width").and_then(|p| p.as_usize()).map_or(io_width, |v| v as u8),
+        },
+        other => other,
+    };
+
+    Some(Uart { base, kind })
+}
 
-    let mut uart = UART.lock();
-    *uart = Some(UartWriter::new());
+/// Translate a bus address of the node at `path` into a CPU physical address
+/// by walking the `ranges` of every ancestor bus
+fn translate(dtb: &fdt::Fdt, path: &str, addr: usize) -> Option<usize> {
+    let parent_path = match path.rfind('/') {
+        Some(0) | None => return Some(addr),
+        Some(i) => &path[..i],
+    };
+    let grandparent_path = match parent_path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &parent_path[..i],
+    };
+
+    let bus = dtb.find_node(parent_path)?;
+    let child_cells = bus.cell_sizes();
+    let parent_cells = dtb.find_node(grandparent_path)?.cell_sizes();
+
+    let ranges = bus.property("ranges")?.value;
+    if ranges.is_empty() {
+        // Empty ranges: identity mapping
+        return translate(dtb, parent_path, addr);
+    }
+
+    let entry_cells = child_cells.address_cells + parent_cells.address_cells + child_cells.size_cells;
+    if entry_cells == 0 {
+        return None;
+    }
+    for entry in ranges.chunks_exact(entry_cells * 4) {
+        let (child, rest) = entry.split_at(child_cells.address_cells * 4);
+        let (parent, size) = rest.split_at(parent_cells.address_cells * 4);
+        let (child, parent, size) = (read_cells(child), read_cells(parent), read_cells(size));
+
+        if addr >= child && addr - child < size {
+            return translate(dtb, parent_path, parent + (addr - child));
+        }
+    }
+
+    None
+}
+
+/// Read a big-endian multi-cell value (only the low 64 bits are kept)
+fn read_cells(bytes: &[u8]) -> usize {
+    bytes.chunks_exact(4).fold(0usize, |acc, cell| {
+        (acc << 32) | u32::from_be_bytes([cell[0], cell[1], cell[2], cell[3]]) as usize
+    })
+}
 
-    // Write a second test character after initialization
-    if let Some(ref mut uart) = *uart {
-        uart.write_byte(b'@');
+/// Release the console lock held by an interrupted print
+///
+/// Only for the panic path: a fault inside `print` would otherwise leave the
+/// panic handler waiting on the lock forever.
+pub fn force_unlock() {
+    if UART.is_locked() {
+        unsafe { UART.force_unlock() };
     }
 }
 
@@ -0,0 +1,300 @@
+// Minimal polled virtio-blk driver (virtio-mmio transport)
+//
+// Just enough to read sectors from a virtio block device before the kernel
+// runs: one virtqueue, one request in flight, no interrupts. Both the legacy
+// (version 1, QEMU's default) and modern (version 2) MMIO register layouts
+// are supported. The MMU is off in the loader, so buffer pointers are
+// physical addresses and DMA needs no cache maintenance.
+
+use core::sync::atomic::{fence, Ordering};
+
+/// Sector size used by virtio-blk requests
+pub const SECTOR_SIZE: usize = 512;
+
+/// virtio-mmio magic value ("virt")
+const VIRTIO_MMIO_MAGIC: u32 = 0x7472_6976;
+/// Device ID of a block device
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+/// virtio-mmio registers
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_GUEST_PAGE_SIZE: usize = 0x028; // legacy only
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+const REG_QUEUE_ALIGN: usize = 0x03c; // legacy only
+const REG_QUEUE_PFN: usize = 0x040; // legacy only
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC_LOW: usize = 0x080;
+const REG_QUEUE_DESC_HIGH: usize = 0x084;
+const REG_QUEUE_AVAIL_LOW: usize = 0x090;
+const REG_QUEUE_AVAIL_HIGH: usize = 0x094;
+const REG_QUEUE_USED_LOW: usize = 0x0a0;
+const REG_QUEUE_USED_HIGH: usize = 0x0a4;
+const REG_CONFIG: usize = 0x100;
+
+/// Device status bits
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// VIRTIO_F_VERSION_1 (feature bit 32, bit 0 of the high feature word)
+const FEATURE_VERSION_1_HIGH: u32 = 1;
+
+/// Descriptor flags
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+/// Block request type: read
+const VIRTIO_BLK_T_IN: u32 = 0;
+/// Block request status: success
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// Virtqueue size (the loader keeps a single 3-descriptor request in flight)
+const QUEUE_SIZE: usize = 8;
+/// Legacy used-ring alignment and page size
+const LEGACY_ALIGN: usize = 4096;
+/// Sectors transferred per request
+const MAX_SECTORS_PER_REQUEST: usize = 128;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// Virtqueue memory in the legacy layout (descriptors and avail ring in the
+/// first page, used ring on the next aligned page). The modern transport
+/// takes the three addresses separately, so the same layout serves both.
+#[repr(C, align(4096))]
+struct QueueMemory {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    _pad: [u8; LEGACY_ALIGN - core::mem::size_of::<[Descriptor; QUEUE_SIZE]>() - core::mem::size_of::<AvailRing>()],
+    used: UsedRing,
+}
+
+#[repr(C)]
+struct RequestHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+static mut QUEUE: QueueMemory = QueueMemory {
+    desc: [Descriptor { addr: 0, len: 0, flags: 0, next: 0 }; QUEUE_SIZE],
+    avail: AvailRing { flags: 0, idx: 0, ring: [0; QUEUE_SIZE], used_event: 0 },
+    _pad: [0; LEGACY_ALIGN - core::mem::size_of::<[Descriptor; QUEUE_SIZE]>() - core::mem::size_of::<AvailRing>()],
+    used: UsedRing { flags: 0, idx: 0, ring: [UsedElem { id: 0, len: 0 }; QUEUE_SIZE], avail_event: 0 },
+};
+
+static mut REQUEST: RequestHeader = RequestHeader { req_type: 0, reserved: 0, sector: 0 };
+static mut REQUEST_STATUS: u8 = 0xff;
+
+/// An initialized virtio block device
+pub struct VirtioBlk {
+    base: usize,
+    /// Capacity in 512-byte sectors
+    capacity: u64,
+    last_used: u16,
+}
+
+impl VirtioBlk {
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Probe and initialize the device at `base`, if it is a virtio block device
+    ///
+    /// Only one device may be in use at a time (the virtqueue is static).
+    pub fn probe(base: usize) -> Result<Self, &'static str> {
+        let mut dev = Self { base, capacity: 0, last_used: 0 };
+
+        if dev.read_reg(REG_MAGIC) != VIRTIO_MMIO_MAGIC {
+            return Err("Not a virtio-mmio device");
+        }
+        let version = dev.read_reg(REG_VERSION);
+        if version != 1 && version != 2 {
+            return Err("Unsupported virtio-mmio version");
+        }
+        if dev.read_reg(REG_DEVICE_ID) != VIRTIO_DEVICE_BLOCK {
+            return Err("Not a virtio block device");
+        }
+
+        // Reset, then announce ourselves
+        dev.write_reg(REG_STATUS, 0);
+        dev.write_reg(REG_STATUS, STATUS_ACKNOWLEDGE);
+        dev.write_reg(REG_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        // No optional features; modern devices require VERSION_1
+        dev.write_reg(REG_DRIVER_FEATURES_SEL, 0);
+        dev.write_reg(REG_DRIVER_FEATURES, 0);
+        if version == 2 {
+            dev.write_reg(REG_DRIVER_FEATURES_SEL, 1);
+            dev.write_reg(REG_DRIVER_FEATURES, FEATURE_VERSION_1_HIGH);
+            dev.write_reg(REG_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+            if dev.read_reg(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+                return Err("virtio-blk rejected feature set");
+            }
+        }
+
+        // Queue 0
+        dev.write_reg(REG_QUEUE_SEL, 0);
+        let max = dev.read_reg(REG_QUEUE_NUM_MAX) as usize;
+        if max < QUEUE_SIZE {
+            return Err("virtio-blk queue too small");
+        }
+        dev.write_reg(REG_QUEUE_NUM, QUEUE_SIZE as u32);
+
+        let queue = core::ptr::addr_of_mut!(QUEUE);
+        if version == 1 {
+            dev.write_reg(REG_GUEST_PAGE_SIZE, LEGACY_ALIGN as u32);
+            dev.write_reg(REG_QUEUE_ALIGN, LEGACY_ALIGN as u32);
+            dev.write_reg(REG_QUEUE_PFN, (queue as usize / LEGACY_ALIGN) as u32);
+        } else {
+            let (desc, avail, used) = unsafe {
+                (
+                    core::ptr::addr_of!((*queue).desc) as u64,
+                    core::ptr::addr_of!((*queue).avail) as u64,
+                    core::ptr::addr_of!((*queue).used) as u64,
+                )
+            };
+            dev.write_reg(REG_QUEUE_DESC_LOW, desc as u32);
+            dev.write_reg(REG_QUEUE_DESC_HIGH, (desc >> 32) as u32);
+            dev.write_reg(REG_QUEUE_AVAIL_LOW, avail as u32);
+            dev.write_reg(REG_QUEUE_AVAIL_HIGH, (avail >> 32) as u32);
+            dev.write_reg(REG_QUEUE_USED_LOW, used as u32);
+            dev.write_reg(REG_QUEUE_USED_HIGH, (used >> 32) as u32);
+            dev.write_reg(REG_QUEUE_READY, 1);
+        }
+
+        let status = dev.read_reg(REG_STATUS);
+        dev.write_reg(REG_STATUS, status | STATUS_DRIVER_OK);
+
+        // Config space: capacity (u64, in sectors), read as two 32-bit halves
+        let low = dev.read_reg(REG_CONFIG) as u64;
+        let high = dev.read_reg(REG_CONFIG + 4) as u64;
+        dev.capacity = (high << 32) | low;
+        dev.last_used = unsafe { core::ptr::read_volatile(core::ptr::addr_of!((*queue).used.idx)) };
+
+        Ok(dev)
+    }
+
+    /// Device capacity in bytes
+    pub fn capacity_bytes(&self) -> u64 {
+        self.capacity * SECTOR_SIZE as u64
+    }
+
+    /// Read whole sectors starting at `sector` into `buf`
+    ///
+    /// `buf.len()` must be a multiple of `SECTOR_SIZE`.
+    pub fn read_sectors(&mut self, mut sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        if buf.len() % SECTOR_SIZE != 0 {
+            return Err("virtio-blk read not sector aligned");
+        }
+        if sector + (buf.len() / SECTOR_SIZE) as u64 > self.capacity {
+            return Err("virtio-blk read past end of device");
+        }
+
+        for chunk in buf.chunks_mut(MAX_SECTORS_PER_REQUEST * SECTOR_SIZE) {
+            self.read_request(sector, chunk)?;
+            sector += (chunk.len() / SECTOR_SIZE) as u64;
+        }
+        Ok(())
+    }
+
+    /// Issue one read request and poll for its completion
+    fn read_request(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        unsafe {
+            let queue = core::ptr::addr_of_mut!(QUEUE);
+            let request = core::ptr::addr_of_mut!(REQUEST);
+            let status = core::ptr::addr_of_mut!(REQUEST_STATUS);
+
+            request.write_volatile(RequestHeader { req_type: VIRTIO_BLK_T_IN, reserved: 0, sector });
+            status.write_volatile(0xff);
+
+            // header -> data (device writes) -> status (device writes)
+            (*queue).desc[0] = Descriptor {
+                addr: request as u64,
+                len: core::mem::size_of::<RequestHeader>() as u32,
+                flags: DESC_F_NEXT,
+                next: 1,
+            };
+            (*queue).desc[1] = Descriptor {
+                addr: buf.as_mut_ptr() as u64,
+                len: buf.len() as u32,
+                flags: DESC_F_WRITE | DESC_F_NEXT,
+                next: 2,
+            };
+            (*queue).desc[2] = Descriptor { addr: status as u64, len: 1, flags: DESC_F_WRITE, next: 0 };
+
+            let avail_idx = core::ptr::read_volatile(core::ptr::addr_of!((*queue).avail.idx));
+            (*queue).avail.ring[avail_idx as usize % QUEUE_SIZE] = 0;
+            fence(Ordering::SeqCst);
+            core::ptr::write_volatile(core::ptr::addr_of_mut!((*queue).avail.idx), avail_idx.wrapping_add(1));
+            fence(Ordering::SeqCst);
+
+            self.write_reg(REG_QUEUE_NOTIFY, 0);
+
+            while core::ptr::read_volatile(core::ptr::addr_of!((*queue).used.idx)) == self.last_used {
+                core::hint::spin_loop();
+            }
+            fence(Ordering::SeqCst);
+            self.last_used = self.last_used.wrapping_add(1);
+
+            if status.read_volatile() != VIRTIO_BLK_S_OK {
+                return Err("virtio-blk read failed");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find and initialize the first virtio block device in the device tree
+pub fn find_device(dtb: &fdt::Fdt) -> Option<VirtioBlk> {
+    dtb.all_nodes()
+        .filter(|node| {
+            node.compatible()
+                .map(|c| c.all().any(|s| s == "virtio,mmio"))
+                .unwrap_or(false)
+        })
+        .filter_map(|node| node.reg()?.next())
+        .find_map(|reg| VirtioBlk::probe(reg.starting_address as usize).ok())
+}
@@ -0,0 +1,109 @@
+//! SharedRing latency benchmark
+//!
+//! Checks the ring against the < 500 cycle target from the crate docs:
+//! - `push+pop`: one thread, an item in and out (hot caches, no sharing)
+//! - `one-way`: half a round trip between two threads on two rings, the
+//!   item crossing cores both ways, which is where head and tail sharing
+//!   a cache line used to hurt; skipped on a single core, where it would
+//!   measure the scheduler
+//!
+//! Cycles are derived from wall time at `CPU_GHZ` (default 2.0), so set it
+//! to the clock of the machine you run on. Exits non-zero if either
+//! figure misses the target.
+//!
+//! ```text
+//! CPU_GHZ=2.4 cargo bench --features mock --bench ring_latency
+//! ```
+//!
+//! The `mock` feature stands in for the kernel syscalls on the host. Rings
+//! are made without notifications all the same; the numbers are for the
+//! ring itself, not for signaling.
+
+use std::hint::{black_box, spin_loop};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use kaal_ipc::SharedRing;
+
+/// Target latency from the crate docs, in CPU cycles
+const TARGET_CYCLES: f64 = 500.0;
+
+const RING_SIZE: usize = 256;
+const ITERATIONS: u64 = 10_000_000;
+const ROUND_TRIPS: u64 = 1_000_000;
+
+static TO_ECHO: SharedRing<u64, RING_SIZE> = SharedRing::new();
+static FROM_ECHO: SharedRing<u64, RING_SIZE> = SharedRing::new();
+static DONE: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let ghz = std::env::var("CPU_GHZ")
+        .ok()
+        .and_then(|ghz| ghz.parse().ok())
+        .unwrap_or(2.0);
+
+    let mut results = vec![("push+pop", push_pop())];
+    if thread::available_parallelism().map_or(1, |n| n.get()) > 1 {
+        results.push(("one-way", one_way()));
+    } else {
+        println!("one core only, skipping one-way");
+    }
+
+    println!("SharedRing<u64, {RING_SIZE}> at {ghz} GHz, target < {TARGET_CYCLES} cycles");
+    let mut missed = false;
+    for (name, ns) in results {
+        let cycles = ns * ghz;
+        let verdict = if cycles < TARGET_CYCLES { "ok" } else { "MISSED" };
+        println!("  {name:<10} {ns:>8.1} ns {cycles:>8.0} cycles  {verdict}");
+        missed |= cycles >= TARGET_CYCLES;
+    }
+    if missed {
+        std::process::exit(1);
+    }
+}
+
+/// Nanoseconds per push and pop of one item on one thread
+fn push_pop() -> f64 {
+    let ring: SharedRing<u64, RING_SIZE> = SharedRing::new();
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        ring.push(black_box(i)).unwrap();
+        black_box(ring.pop().unwrap());
+    }
+    start.elapsed().as_nanos() as f64 / ITERATIONS as f64
+}
+
+/// Nanoseconds for an item to reach a thread that echoes it back, halved
+fn one_way() -> f64 {
+    let echo = thread::spawn(|| {
+        while !DONE.load(Ordering::Relaxed) {
+            let Ok(item) = TO_ECHO.pop() else {
+                spin_loop();
+                continue;
+            };
+            while FROM_ECHO.push(item).is_err() {
+                spin_loop();
+            }
+        }
+    });
+
+    let start = Instant::now();
+    for i in 0..ROUND_TRIPS {
+        while TO_ECHO.push(i).is_err() {
+            spin_loop();
+        }
+        let item = loop {
+            if let Ok(item) = FROM_ECHO.pop() {
+                break item;
+            }
+            spin_loop();
+        };
+        assert_eq!(item, i);
+    }
+    let elapsed = start.elapsed();
+
+    DONE.store(true, Ordering::Relaxed);
+    echo.join().unwrap();
+    elapsed.as_nanos() as f64 / (2 * ROUND_TRIPS) as f64
+}
@@ -0,0 +1,351 @@
+//! One-to-many broadcast ring
+//!
+//! Input events and system state updates go to every interested
+//! component, not to whichever pops them first as with an
+//! [`MpmcRing`](crate::MpmcRing). A [`BroadcastRing`] has one publisher
+//! and any number of subscribers, each reading every event through a
+//! cursor of its own. The publisher never waits: when the ring is full it
+//! overwrites the oldest event, and a subscriber that falls that far
+//! behind is told how many it missed.
+//!
+//! ```ignore
+//! // Input driver: signal each subscriber's notification on publish
+//! let mut keys = events.publisher();
+//! keys.add_subscriber(notepad_notification)?;
+//! keys.add_subscriber(monitor_notification)?;
+//! keys.publish(KeyEvent { code, pressed: true });
+//!
+//! // Each app, on its own notification
+//! let keys = events.subscribe(Some(my_notification));
+//! loop {
+//!     match keys.recv_blocking(None) {
+//!         Ok(event) => handle(event),
+//!         Err(IpcError::Lagged { missed }) => resync(missed),
+//!         Err(e) => return Err(e),
+//!     }
+//! }
+//! ```
+//!
+//! # Design
+//! Each slot's stamp says which event it holds: `2 * pos + 1` while the
+//! publisher writes event `pos`, `2 * pos + 2` once it is written, 0 if
+//! it never was. A subscriber checks the stamp before and after copying
+//! the event out; if either is not the one for its cursor, the publisher
+//! has lapped it, and it skips ahead to the oldest event still there.
+//! All zeroes is an empty ring, so one can be set up in fresh shared
+//! memory without constructing it.
+//!
+//! A notification signal only reaches the threads blocked when it comes,
+//! so subscribers sharing one could miss an event; the publisher signals
+//! each subscriber's own notification (badge 1) instead.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::sys::sys_signal;
+use crate::{block_on, create_timer, IpcError, NotificationCap, Result, TimerCap};
+
+/// Most subscriber notifications a [`Publisher`] signals
+pub const MAX_SUBSCRIBERS: usize = 16;
+
+/// One event and which it is
+#[repr(C)]
+struct Slot<T> {
+    stamp: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Shared memory ring broadcasting events to every subscriber
+///
+/// # Type Parameters
+/// * `T` - Event type (must be `Copy`, as it is copied out of shared memory)
+/// * `N` - Events kept; a subscriber more than `N` behind loses the oldest
+///
+/// # Safety
+/// Must be placed in shared memory accessible to the publisher and every
+/// subscriber. Only one thread may publish.
+#[repr(C)]
+pub struct BroadcastRing<T: Copy, const N: usize> {
+    slots: [Slot<T>; N],
+    /// Events published so far, which is the next one's position
+    head: AtomicU64,
+}
+
+// Subscribers only keep what the stamps say was written whole
+unsafe impl<T: Copy + Send, const N: usize> Sync for BroadcastRing<T, N> {}
+
+impl<T: Copy, const N: usize> BroadcastRing<T, N> {
+    /// Create an empty ring
+    ///
+    /// # Panics
+    /// Panics if N is 0
+    pub const fn new() -> Self {
+        assert!(N > 0, "Broadcast ring must hold at least one event");
+
+        Self {
+            slots: [const {
+                Slot {
+                    stamp: AtomicU64::new(0),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                }
+            }; N],
+            head: AtomicU64::new(0),
+        }
+    }
+
+    /// The publisher side, signaling no one until subscribers are added
+    ///
+    /// Only one may be used at a time.
+    pub fn publisher(&self) -> Publisher<'_, T, N> {
+        Publisher {
+            ring: self,
+            subscribers: [None; MAX_SUBSCRIBERS],
+        }
+    }
+
+    /// Subscribe from the next event on, waiting on `notify` in
+    /// `recv_blocking`
+    ///
+    /// `notify` is the caller's capability to the notification the
+    /// publisher signals for this subscriber.
+    pub fn subscribe(&self, notify: Option<NotificationCap>) -> Subscriber<'_, T, N> {
+        Subscriber {
+            ring: self,
+            next: Cell::new(self.published()),
+            notify,
+            timer: None,
+        }
+    }
+
+    /// Events published so far
+    pub fn published(&self) -> u64 {
+        self.head.load(Ordering::Acquire)
+    }
+
+    /// Write the next event and return its position (publisher only)
+    fn write(&self, item: T) -> u64 {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[(pos % N as u64) as usize];
+        slot.stamp.store(2 * pos + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe {
+            core::ptr::write_volatile(slot.value.get(), MaybeUninit::new(item));
+        }
+        slot.stamp.store(2 * pos + 2, Ordering::Release);
+        self.head.store(pos + 1, Ordering::Release);
+        pos
+    }
+}
+
+impl<T: Copy, const N: usize> Default for BroadcastRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Publishing side of a [`BroadcastRing`]
+pub struct Publisher<'a, T: Copy, const N: usize> {
+    ring: &'a BroadcastRing<T, N>,
+    /// Notifications to signal on publish, in the publisher's CSpace
+    subscribers: [Option<NotificationCap>; MAX_SUBSCRIBERS],
+}
+
+impl<T: Copy, const N: usize> Publisher<'_, T, N> {
+    /// Signal `notify` (the publisher's capability to a subscriber's
+    /// notification) on every publish
+    ///
+    /// # Errors
+    /// Returns `IpcError::InvalidNotification` if [`MAX_SUBSCRIBERS`] are
+    /// already signaled
+    pub fn add_subscriber(&mut self, notify: NotificationCap) -> Result<()> {
+        let free = self
+            .subscribers
+            .iter_mut()
+            .find(|subscriber| subscriber.is_none())
+            .ok_or(IpcError::InvalidNotification)?;
+        *free = Some(notify);
+        Ok(())
+    }
+
+    /// Stop signaling `notify`
+    pub fn remove_subscriber(&mut self, notify: NotificationCap) {
+        for subscriber in &mut self.subscribers {
+            if *subscriber == Some(notify) {
+                *subscriber = None;
+            }
+        }
+    }
+
+    /// Publish an event to every subscriber, overwriting the oldest if
+    /// the ring is full, and return its position
+    pub fn publish(&self, item: T) -> u64 {
+        let pos = self.ring.write(item);
+        for notify_cap in self.subscribers.iter().flatten() {
+            // Badge = 1 indicates data available
+            unsafe {
+                sys_signal(*notify_cap, 1);
+            }
+        }
+        pos
+    }
+}
+
+/// Subscribing side of a [`BroadcastRing`], with its own cursor
+pub struct Subscriber<'a, T: Copy, const N: usize> {
+    ring: &'a BroadcastRing<T, N>,
+    /// Position of the next event to read
+    next: Cell<u64>,
+    /// Notification the publisher signals for this subscriber
+    notify: Option<NotificationCap>,
+    /// Timer for `recv_blocking` timeouts
+    timer: Option<TimerCap>,
+}
+
+impl<T: Copy, const N: usize> Subscriber<'_, T, N> {
+    /// Let `recv_blocking` time out, creating a timer that signals the
+    /// subscriber's notification with `TIMEOUT_BADGE`
+    ///
+    /// Creating timers needs the CAP_CAPS capability.
+    ///
+    /// # Errors
+    /// Returns error if the subscriber has no notification or the kernel
+    /// refuses the timer
+    pub fn with_timer(mut self) -> Result<Self> {
+        self.timer = Some(create_timer(self.notify)?);
+        Ok(self)
+    }
+
+    /// Read the next event
+    ///
+    /// # Errors
+    /// - `IpcError::BufferEmpty` if there is no new event
+    /// - `IpcError::Lagged` if events were overwritten before they were
+    ///   read; the cursor moves on to the oldest one left, so the next
+    ///   call reads it
+    pub fn try_recv(&self) -> Result<T> {
+        let next = self.next.get();
+        let head = self.ring.published();
+        if next == head {
+            return Err(IpcError::BufferEmpty);
+        }
+        if head - next > N as u64 {
+            return Err(self.skip_to(head - N as u64));
+        }
+
+        let slot = &self.ring.slots[(next % N as u64) as usize];
+        let written = 2 * next + 2;
+        if slot.stamp.load(Ordering::Acquire) != written {
+            return Err(self.skip_past_overwrite());
+        }
+        let item = unsafe { core::ptr::read_volatile(slot.value.get()) };
+        fence(Ordering::Acquire);
+        if slot.stamp.load(Ordering::Relaxed) != written {
+            return Err(self.skip_past_overwrite());
+        }
+
+        self.next.set(next + 1);
+        Ok(unsafe { item.assume_init() })
+    }
+
+    /// Read the next event, waiting for one if there is none
+    ///
+    /// # Arguments
+    /// * `timeout` - How long to wait at most; `None` waits forever
+    ///
+    /// # Errors
+    /// - `IpcError::Lagged` as from `try_recv`
+    /// - `IpcError::Timeout` if no event came in time
+    /// - `IpcError::InvalidNotification` if the subscriber has no
+    ///   notification, or a timeout is given without a timer
+    pub fn recv_blocking(&self, timeout: Option<Duration>) -> Result<T> {
+        block_on(self.notify, None, self.timer, timeout, || self.try_recv())
+    }
+
+    /// Events published that the subscriber has not read (more than `N`
+    /// means some are lost)
+    pub fn pending(&self) -> u64 {
+        self.ring.published() - self.next.get()
+    }
+
+    /// Skip the events not read yet
+    pub fn skip_to_latest(&self) {
+        self.next.set(self.ring.published());
+    }
+
+    /// The publisher is overwriting the event at the cursor: skip to the
+    /// oldest one it is not
+    fn skip_past_overwrite(&self) -> IpcError {
+        let oldest = (self.ring.published() + 1).saturating_sub(N as u64);
+        self.skip_to(oldest.max(self.next.get() + 1))
+    }
+
+    fn skip_to(&self, oldest: u64) -> IpcError {
+        let missed = oldest - self.next.get();
+        self.next.set(oldest);
+        IpcError::Lagged { missed }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_every_subscriber_reads_every_event() {
+        // Room for every event, so no one lags
+        let ring: BroadcastRing<u64, 256> = BroadcastRing::new();
+        let mut publisher = ring.publisher();
+        let notifications = [300, 301, 302];
+        for notify in notifications {
+            publisher.add_subscriber(notify).unwrap();
+        }
+        thread::scope(|s| {
+            let readers: Vec<_> = notifications
+                .iter()
+                .map(|&notify| {
+                    let subscriber = ring.subscribe(Some(notify));
+                    s.spawn(move || (0..200).map(|_| subscriber.recv_blocking(None).unwrap()).sum::<u64>())
+                })
+                .collect();
+            for i in 0..200 {
+                if i % 20 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                publisher.publish(i);
+            }
+            for reader in readers {
+                let sum = reader.join().unwrap();
+                assert_eq!(sum, (0..200).sum::<u64>());
+            }
+        });
+    }
+
+    #[test]
+    fn test_lagging_subscriber_skips_to_oldest() {
+        let ring: BroadcastRing<u32, 4> = BroadcastRing::new();
+        let publisher = ring.publisher();
+        let early = ring.subscribe(None);
+        publisher.publish(0);
+        let late = ring.subscribe(None);
+        for i in 1..10 {
+            publisher.publish(i);
+        }
+
+        assert_eq!(early.pending(), 10);
+        assert_eq!(early.try_recv(), Err(IpcError::Lagged { missed: 6 }));
+        for i in 6..10 {
+            assert_eq!(early.try_recv(), Ok(i));
+        }
+        assert_eq!(early.try_recv(), Err(IpcError::BufferEmpty));
+
+        assert_eq!(late.try_recv(), Err(IpcError::Lagged { missed: 5 }));
+        late.skip_to_latest();
+        assert_eq!(late.pending(), 0);
+        publisher.publish(10);
+        assert_eq!(late.try_recv(), Ok(10));
+    }
+}
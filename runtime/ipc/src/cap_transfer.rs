@@ -0,0 +1,73 @@
+//! Capability transfer over endpoints
+//!
+//! Setting up a shared ring between two components needs capabilities on
+//! both sides: the frame to map, the notifications to signal. Rather than
+//! having the root task insert them into each CSpace, one side can hand
+//! its copies to the other with a message: [`send_with_cap`] sends a
+//! capability along, and the kernel copies it into the slot the receiver
+//! named in [`recv_with_cap`].
+//!
+//! ```ignore
+//! // Producer: offer the ring's frame
+//! kaal_ipc::send_with_cap(endpoint, b"ring", frame_cap)?;
+//!
+//! // Consumer: take it into slot 120
+//! let received = kaal_ipc::recv_with_cap(endpoint, &mut buf, 120)?;
+//! let frame_cap = received.cap.ok_or(IpcError::InvalidMessage)?;
+//! ```
+//!
+//! The capability must have the GRANT right. The receiver's copy is
+//! derived from the sender's, so the sender revoking its own revokes it
+//! too. A capability sent to a receiver in plain `recv`, or to a receive
+//! slot that is already taken, is not transferred; the message still is.
+
+use crate::rpc::MAX_MESSAGE;
+use crate::sys::{sys_recv_cap, sys_send_cap};
+use crate::{EndpointCap, IpcError, Result};
+
+/// A message taken by [`recv_with_cap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Received {
+    /// Message length
+    pub len: usize,
+    /// Badge of the sender's endpoint capability (0 if unbadged)
+    pub badge: u64,
+    /// Slot of the capability that came with it, if one did
+    pub cap: Option<u64>,
+}
+
+/// Send `message` and the capability in `cap` on `endpoint`
+///
+/// Blocks until a receiver takes the message.
+///
+/// # Errors
+/// - `IpcError::MessageTooLarge` if `message` is over 256 bytes
+/// - `IpcError::EndpointFailed` if the send syscall fails
+pub fn send_with_cap(endpoint: EndpointCap, message: &[u8], cap: u64) -> Result<()> {
+    if message.len() > MAX_MESSAGE {
+        return Err(IpcError::MessageTooLarge { size: message.len() });
+    }
+    match unsafe { sys_send_cap(endpoint, message, cap) } {
+        u64::MAX => Err(IpcError::EndpointFailed),
+        _ => Ok(()),
+    }
+}
+
+/// Receive a message on `endpoint` into `buffer`, and a capability sent
+/// along into `receive_slot` (which must be empty)
+///
+/// Blocks until a sender arrives.
+///
+/// # Errors
+/// Returns `IpcError::EndpointFailed` if the receive syscall fails
+/// (including a message longer than `buffer`)
+pub fn recv_with_cap(endpoint: EndpointCap, buffer: &mut [u8], receive_slot: u64) -> Result<Received> {
+    let buffer_len = buffer.len().min(MAX_MESSAGE);
+    let (len, badge, received) = unsafe { sys_recv_cap(endpoint, &mut buffer[..buffer_len], receive_slot) }
+        .ok_or(IpcError::EndpointFailed)?;
+    Ok(Received {
+        len: len as usize,
+        badge,
+        cap: (received != 0).then_some(receive_slot),
+    })
+}
@@ -0,0 +1,359 @@
+//! Zero-copy buffer grants
+//!
+//! Disk blocks and network packets are too large to copy through a ring
+//! of fixed-size elements. A [`GrantTable`] is a pool of buffers in
+//! shared memory: the producer fills a buffer in place and publishes a
+//! [`Grant`] (offset and length into the pool) on a descriptor ring; the
+//! consumer reads the bytes where they are and releases the grant, which
+//! hands the buffer back to the producer.
+//!
+//! ```ignore
+//! // Producer
+//! let mut buf = table.alloc()?;
+//! let len = nic.receive_into(&mut buf)?;
+//! buf.publish(len)?;
+//!
+//! // Consumer
+//! let packet = table.recv()?;
+//! stack.input(&packet);
+//! packet.release();
+//! ```
+//!
+//! Single producer, single consumer, signaled like `SharedRing`: badge 1
+//! to the consumer notification on publish, badge 2 to the producer
+//! notification when descriptor ring space or a buffer is freed.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::sys::sys_signal;
+use crate::{IpcError, NotificationCap, Result, SharedRing};
+
+/// Buffer states
+const FREE: u8 = 0;
+/// Allocated by the producer, being filled
+const FILLING: u8 = 1;
+/// Published, owned by the consumer until released
+const GRANTED: u8 = 2;
+
+/// A published region of a [`GrantTable`]'s pool
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grant {
+    /// Byte offset into the pool
+    pub offset: u32,
+    /// Length in bytes
+    pub len: u32,
+}
+
+/// Pool of buffers lent from a producer to a consumer
+///
+/// # Type Parameters
+/// * `N` - Number of buffers (must be power of 2); like `SharedRing`, the
+///   descriptor ring keeps one slot empty, so up to `N - 1` are
+///   published at once
+/// * `SIZE` - Bytes per buffer
+///
+/// # Safety
+/// Must be placed in shared memory accessible to both producer and
+/// consumer processes, like `SharedRing`. Grant offsets are relative to
+/// the pool, so each side may map it at its own address.
+#[repr(C)]
+pub struct GrantTable<const N: usize, const SIZE: usize> {
+    /// Descriptors of published buffers
+    ring: SharedRing<Grant, N>,
+    /// Who owns each buffer
+    states: [AtomicU8; N],
+    /// The buffers
+    pool: UnsafeCell<[[u8; SIZE]; N]>,
+}
+
+// A buffer is only touched by the side its state gives it to
+unsafe impl<const N: usize, const SIZE: usize> Sync for GrantTable<N, SIZE> {}
+
+impl<const N: usize, const SIZE: usize> GrantTable<N, SIZE> {
+    /// Create a grant table without notifications
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2
+    pub const fn new() -> Self {
+        Self {
+            ring: SharedRing::new(),
+            states: [const { AtomicU8::new(FREE) }; N],
+            pool: UnsafeCell::new([[0; SIZE]; N]),
+        }
+    }
+
+    /// Create a grant table with notification capabilities
+    ///
+    /// # Arguments
+    /// * `consumer_notify` - Notification capability to signal consumer
+    /// * `producer_notify` - Notification capability to signal producer
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2
+    pub fn with_notifications(
+        consumer_notify: NotificationCap,
+        producer_notify: NotificationCap,
+    ) -> Self {
+        Self {
+            ring: SharedRing::with_notifications(consumer_notify, producer_notify),
+            ..Self::new()
+        }
+    }
+
+    /// Take a free buffer to fill (producer side)
+    ///
+    /// Dropping it without publishing gives it back.
+    ///
+    /// # Errors
+    /// Returns `IpcError::BufferFull` if every buffer is lent out
+    pub fn alloc(&self) -> Result<GrantBuf<'_, N, SIZE>> {
+        // Only the producer takes buffers, so a free one stays free
+        let index = self
+            .states
+            .iter()
+            .position(|state| state.load(Ordering::Acquire) == FREE)
+            .ok_or(IpcError::BufferFull { capacity: N })?;
+        self.states[index].store(FILLING, Ordering::Relaxed);
+        Ok(GrantBuf { table: self, index })
+    }
+
+    /// Take the next published buffer (consumer side)
+    ///
+    /// # Errors
+    /// - `IpcError::BufferEmpty` if nothing is published
+    /// - `IpcError::Corrupted` if the descriptor ring is corrupted, or the
+    ///   next descriptor is out of the pool or names a buffer that was
+    ///   not published (that descriptor is dropped)
+    pub fn recv(&self) -> Result<Borrowed<'_, N, SIZE>> {
+        let grant = self.ring.pop()?;
+        let (index, start) = (grant.offset as usize / SIZE, grant.offset as usize % SIZE);
+        if index >= N
+            || grant.len as usize > SIZE - start
+            || self.states[index].load(Ordering::Acquire) != GRANTED
+        {
+            return Err(IpcError::Corrupted);
+        }
+        Ok(Borrowed { table: self, grant })
+    }
+
+    /// Buffers not lent out (producer side)
+    pub fn free(&self) -> usize {
+        self.states
+            .iter()
+            .filter(|state| state.load(Ordering::Acquire) == FREE)
+            .count()
+    }
+
+    /// Wait for consumer notification (blocking)
+    ///
+    /// # Errors
+    /// Returns error if no consumer notification is configured
+    pub fn wait_consumer(&self) -> Result<u64> {
+        self.ring.wait_consumer()
+    }
+
+    /// Wait for producer notification (blocking)
+    ///
+    /// # Errors
+    /// Returns error if no producer notification is configured
+    pub fn wait_producer(&self) -> Result<u64> {
+        self.ring.wait_producer()
+    }
+
+    /// Poll consumer notification (non-blocking)
+    pub fn poll_consumer(&self) -> u64 {
+        self.ring.poll_consumer()
+    }
+
+    /// Poll producer notification (non-blocking)
+    pub fn poll_producer(&self) -> u64 {
+        self.ring.poll_producer()
+    }
+
+    /// Buffer `index` of the pool
+    fn buffer(&self, index: usize) -> *mut [u8; SIZE] {
+        unsafe { (self.pool.get() as *mut [u8; SIZE]).add(index) }
+    }
+
+    /// Hand buffer `index` back to the producer, if it was granted
+    fn release(&self, index: usize) {
+        let granted = self.states[index].compare_exchange(GRANTED, FREE, Ordering::Release, Ordering::Relaxed);
+        if granted.is_err() {
+            return;
+        }
+        if let Some(notify_cap) = self.ring.producer_notify {
+            unsafe {
+                sys_signal(notify_cap, 2);
+            }
+        }
+    }
+}
+
+impl<const N: usize, const SIZE: usize> Default for GrantTable<N, SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A buffer the producer is filling
+pub struct GrantBuf<'a, const N: usize, const SIZE: usize> {
+    table: &'a GrantTable<N, SIZE>,
+    index: usize,
+}
+
+impl<const N: usize, const SIZE: usize> GrantBuf<'_, N, SIZE> {
+    /// Publish the first `len` bytes
+    ///
+    /// # Errors
+    /// See [`publish_range`](Self::publish_range)
+    pub fn publish(self, len: usize) -> Result<Grant> {
+        self.publish_range(0, len)
+    }
+
+    /// Publish `len` bytes from `start` (say, past a header the consumer
+    /// has no use for)
+    ///
+    /// On error the buffer is given back.
+    ///
+    /// # Errors
+    /// - `IpcError::MessageTooLarge` if the range is past the buffer's end
+    /// - `IpcError::BufferFull` if the descriptor ring is full
+    pub fn publish_range(self, start: usize, len: usize) -> Result<Grant> {
+        if start >= SIZE || len > SIZE - start {
+            return Err(IpcError::MessageTooLarge { size: len });
+        }
+        let grant = Grant {
+            offset: (self.index * SIZE + start) as u32,
+            len: len as u32,
+        };
+        let states = &self.table.states[self.index];
+        states.store(GRANTED, Ordering::Relaxed);
+        if let Err(e) = self.table.ring.push(grant) {
+            states.store(FILLING, Ordering::Relaxed);
+            return Err(e);
+        }
+        core::mem::forget(self);
+        Ok(grant)
+    }
+}
+
+impl<const N: usize, const SIZE: usize> Deref for GrantBuf<'_, N, SIZE> {
+    type Target = [u8; SIZE];
+
+    fn deref(&self) -> &[u8; SIZE] {
+        unsafe { &*self.table.buffer(self.index) }
+    }
+}
+
+impl<const N: usize, const SIZE: usize> DerefMut for GrantBuf<'_, N, SIZE> {
+    fn deref_mut(&mut self) -> &mut [u8; SIZE] {
+        unsafe { &mut *self.table.buffer(self.index) }
+    }
+}
+
+impl<const N: usize, const SIZE: usize> Drop for GrantBuf<'_, N, SIZE> {
+    fn drop(&mut self) {
+        self.table.states[self.index].store(FREE, Ordering::Release);
+    }
+}
+
+/// A published buffer the consumer holds, read in place
+///
+/// Released when dropped, or with [`release`](Self::release).
+pub struct Borrowed<'a, const N: usize, const SIZE: usize> {
+    table: &'a GrantTable<N, SIZE>,
+    grant: Grant,
+}
+
+impl<const N: usize, const SIZE: usize> Borrowed<'_, N, SIZE> {
+    /// The descriptor it was published with
+    pub fn grant(&self) -> Grant {
+        self.grant
+    }
+
+    /// Give the buffer back to the producer
+    pub fn release(self) {}
+
+    fn index(&self) -> usize {
+        self.grant.offset as usize / SIZE
+    }
+}
+
+impl<const N: usize, const SIZE: usize> Deref for Borrowed<'_, N, SIZE> {
+    type Target = [u8];
+
+    /// The published bytes
+    fn deref(&self) -> &[u8] {
+        let (start, len) = (self.grant.offset as usize % SIZE, self.grant.len as usize);
+        let buffer = unsafe { &*self.table.buffer(self.index()) };
+        &buffer[start..start + len]
+    }
+}
+
+impl<const N: usize, const SIZE: usize> Drop for Borrowed<'_, N, SIZE> {
+    fn drop(&mut self) {
+        self.table.release(self.index());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_and_release() {
+        let table: GrantTable<4, 64> = GrantTable::with_notifications(600, 601);
+        let mut buf = table.alloc().unwrap();
+        buf[..5].copy_from_slice(b"block");
+        let grant = buf.publish(5).unwrap();
+        assert_eq!(grant.len, 5);
+        assert_eq!(table.free(), 3);
+        assert_eq!(table.poll_consumer(), 1);
+
+        let borrowed = table.recv().unwrap();
+        assert_eq!(borrowed.grant(), grant);
+        assert_eq!(&*borrowed, b"block");
+        borrowed.release();
+        assert_eq!(table.free(), 4);
+        assert_eq!(table.poll_producer(), 2);
+        assert!(matches!(table.recv(), Err(IpcError::BufferEmpty)));
+    }
+
+    #[test]
+    fn test_publish_range() {
+        let table: GrantTable<4, 64> = GrantTable::new();
+        let mut buf = table.alloc().unwrap();
+        buf[..8].copy_from_slice(b"hdr:data");
+        buf.publish_range(4, 4).unwrap();
+        assert_eq!(&*table.recv().unwrap(), b"data");
+
+        // Past the buffer's end: given back
+        let buf = table.alloc().unwrap();
+        assert!(matches!(buf.publish_range(60, 8), Err(IpcError::MessageTooLarge { size: 8 })));
+        assert_eq!(table.free(), 4);
+    }
+
+    #[test]
+    fn test_buffers_run_out() {
+        let table: GrantTable<4, 64> = GrantTable::new();
+        let bufs: [_; 4] = core::array::from_fn(|_| table.alloc().unwrap());
+        assert!(matches!(table.alloc(), Err(IpcError::BufferFull { capacity: 4 })));
+
+        // The descriptor ring takes three; the fourth buffer is given back
+        let [a, b, c, d] = bufs;
+        for buf in [a, b, c] {
+            buf.publish(1).unwrap();
+        }
+        assert!(matches!(d.publish(1), Err(IpcError::BufferFull { capacity: 4 })));
+        assert_eq!(table.free(), 1);
+
+        // Dropping an unpublished buffer gives it back too
+        drop(table.alloc().unwrap());
+        assert_eq!(table.free(), 1);
+        drop(table.recv().unwrap());
+        assert_eq!(table.free(), 2);
+    }
+}
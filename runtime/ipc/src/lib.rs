@@ -14,7 +14,16 @@
 //! # Architecture
 //! Lock-free ring buffer using atomic operations with notification-based
 //! signaling. Supports single-producer/single-consumer pattern with zero-copy
-//! semantics.
+//! semantics; [`MpmcRing`] takes any number of producers and consumers,
+//! [`MsgRing`] variable-length byte messages, and a [`BroadcastRing`]
+//! hands every event to every subscriber. [`rpc`] adds blocking
+//! request/response calls over kernel endpoints, in which [`vfs`] defines
+//! the VFS service's protocol, and [`send_with_cap`]
+//! hands capabilities to another component with a message. A
+//! [`NotificationSet`] waits on many channels in one call, and a
+//! [`GrantTable`] lends large buffers without copying them. The memory
+//! all of these live in is a [`SharedMemory`]; a [`Semaphore`] or
+//! [`Condvar`] in it blocks on conditions in between.
 //!
 //! # Design
 //! Based on Chapter 9 Phase 2 shared memory IPC architecture:
@@ -22,16 +31,49 @@
 //! - Notification objects for lightweight signaling
 //! - Zero-copy communication (data stays in shared memory)
 //! - Target latency: < 500 CPU cycles
+//!
+//! # Testing
+//! The `mock` feature swaps the kernel syscalls for stand-ins on std, so
+//! `cargo test --features mock` runs the crate on the host, blocking and
+//! timeouts included. Run it from outside this directory (or with
+//! `--manifest-path`), as `.cargo/config.toml` here builds for the target.
 
 #![no_std]
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+// The host stand-ins for the syscalls, and the tests, are built on std
+#[cfg(any(test, feature = "mock"))]
+extern crate std;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::time::Duration;
 
+pub mod broadcast;
 #[cfg(feature = "alloc")]
 pub mod broker;
+pub mod cap_transfer;
+pub mod grant;
+pub mod mpmc;
+pub mod msg_ring;
+pub mod notification_set;
+pub mod rpc;
+pub mod shared_memory;
+pub mod sync;
+mod sys;
+pub mod vfs;
+
+pub use broadcast::BroadcastRing;
+pub use cap_transfer::{recv_with_cap, send_with_cap, Received};
+pub use grant::{Grant, GrantTable};
+pub use mpmc::MpmcRing;
+pub use msg_ring::MsgRing;
+pub use notification_set::NotificationSet;
+pub use shared_memory::SharedMemory;
+pub use sync::{Condvar, Semaphore};
+
+use sys::{sys_poll, sys_signal, sys_tcb_donate_priority, sys_timer_create, sys_timer_set, sys_wait};
 
 /// IPC error types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -46,6 +88,27 @@ pub enum IpcError {
     NotificationFailed,
     /// Invalid notification capability
     InvalidNotification,
+    /// Message of `size` bytes does not fit (in the ring, or in the
+    /// receive buffer)
+    MessageTooLarge { size: usize },
+    /// A blocking operation timed out
+    Timeout,
+    /// Endpoint operation failed
+    EndpointFailed,
+    /// Message could not be encoded or decoded, or a reply did not match
+    /// its request
+    InvalidMessage,
+    /// Shared memory holds what the peer could not have written there
+    /// correctly (an index out of range, a slot with the wrong sequence
+    /// number); the peer is misbehaving or crashed mid-write
+    Corrupted,
+    /// Memory allocation, mapping or publishing failed
+    MemoryFailed,
+    /// Nothing is published under the name
+    NotFound,
+    /// A broadcast subscriber fell behind and `missed` events were
+    /// overwritten before it read them
+    Lagged { missed: u64 },
 }
 
 pub type Result<T> = core::result::Result<T, IpcError>;
@@ -53,6 +116,20 @@ pub type Result<T> = core::result::Result<T, IpcError>;
 /// Notification capability slot (indexes into CSpace)
 pub type NotificationCap = u64;
 
+/// Timer capability slot (indexes into CSpace)
+pub type TimerCap = u64;
+
+/// Endpoint capability slot (indexes into CSpace)
+pub type EndpointCap = u64;
+
+/// Thread (TCB) capability slot (indexes into CSpace)
+pub type TcbCap = u64;
+
+/// Badge of the timers made by `SharedRing::producer_timer` and
+/// `SharedRing::consumer_timer`, clear of the ring's data (1) and space
+/// (2) badges
+pub const TIMEOUT_BADGE: u64 = 1 << 63;
+
 /// Shared memory ring buffer for high-performance IPC
 ///
 /// # Type Parameters
@@ -66,10 +143,28 @@ pub type NotificationCap = u64;
 /// # Memory Layout
 /// The ring buffer uses a single allocation containing:
 /// - Array of T elements (N items)
-/// - Atomic head pointer (producer writes here)
-/// - Atomic tail pointer (consumer reads here)
+/// - Array of sequence numbers (N `u32`s), one per element
+/// - Producer cache line: atomic head pointer (producer writes here) and
+///   the producer's cached copy of the tail
+/// - Consumer cache line: atomic tail pointer (consumer reads here) and
+///   the consumer's cached copy of the head
 /// - Notification capability slots for signaling
 ///
+/// Each side only writes its own cache line, and reads the other's only
+/// when its cached copy says the ring is full (producer) or empty
+/// (consumer), so on different cores the lines do not bounce between
+/// caches on every operation.
+///
+/// # Corruption Detection
+/// Each side counts the items it has passed. The producer stamps every
+/// slot it fills with its count (starting at 1, wrapping), and the
+/// consumer only takes an item stamped with the count it expects, so a
+/// slot the producer never finished, a stale item from an earlier lap or
+/// garbage written by a faulty peer is reported as `IpcError::Corrupted`
+/// rather than delivered. So is an index outside the ring. The ring stays
+/// stuck at the bad slot: the channel should be torn down and set up
+/// again.
+///
 /// # Lock-Free Guarantees
 /// - Single producer, single consumer (SPSC)
 /// - Wait-free for producer (if space available)
@@ -79,16 +174,49 @@ pub type NotificationCap = u64;
 pub struct SharedRing<T: Copy, const N: usize> {
     /// Ring buffer storage
     buffer: [T; N],
-    /// Head index (producer writes here)
-    head: AtomicUsize,
-    /// Tail index (consumer reads here)
-    tail: AtomicUsize,
+    /// Producer count that filled each slot
+    sequences: [AtomicU32; N],
+    /// Head index (producer writes here) and cached tail
+    producer: CacheLine<Cursor>,
+    /// Tail index (consumer reads here) and cached head
+    consumer: CacheLine<Cursor>,
     /// Notification capability for signaling consumer
     consumer_notify: Option<NotificationCap>,
     /// Notification capability for signaling producer
     producer_notify: Option<NotificationCap>,
 }
 
+/// Cache line size of the Cortex-A cores KaaL runs on
+const CACHE_LINE: usize = 64;
+
+/// Gives its contents a cache line of their own
+#[repr(C, align(64))]
+struct CacheLine<T>(T);
+
+const _: () = assert!(core::mem::align_of::<CacheLine<Cursor>>() == CACHE_LINE);
+
+/// One side's index into the ring, and what it last saw of the other's
+#[repr(C)]
+struct Cursor {
+    /// Written by this side only
+    index: AtomicUsize,
+    /// The other side's index when this side last looked; behind the real
+    /// one at worst, so it never overstates space or data
+    cached: AtomicUsize,
+    /// Items this side has passed, wrapping (written by this side only)
+    sequence: AtomicU32,
+}
+
+impl Cursor {
+    const fn new() -> Self {
+        Self {
+            index: AtomicUsize::new(0),
+            cached: AtomicUsize::new(0),
+            sequence: AtomicU32::new(0),
+        }
+    }
+}
+
 impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// Create a new shared ring buffer without notifications
     ///
@@ -100,8 +228,9 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
 
         Self {
             buffer: unsafe { core::mem::zeroed() },
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
+            sequences: [const { AtomicU32::new(0) }; N],
+            producer: CacheLine(Cursor::new()),
+            consumer: CacheLine(Cursor::new()),
             consumer_notify: None,
             producer_notify: None,
         }
@@ -123,13 +252,28 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
 
         Self {
             buffer: unsafe { core::mem::zeroed() },
-            head: AtomicUsize::new(0),
-            tail: AtomicUsize::new(0),
+            sequences: [const { AtomicU32::new(0) }; N],
+            producer: CacheLine(Cursor::new()),
+            consumer: CacheLine(Cursor::new()),
             consumer_notify: Some(consumer_notify),
             producer_notify: Some(producer_notify),
         }
     }
 
+    /// Create a new shared ring buffer that signals only the consumer
+    ///
+    /// For a producer that never waits for space, as when the consumer's
+    /// CSpace has no capability to signal it through.
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2
+    pub const fn with_consumer_notification(consumer_notify: NotificationCap) -> Self {
+        Self {
+            consumer_notify: Some(consumer_notify),
+            ..Self::new()
+        }
+    }
+
     /// Push an item into the ring buffer (producer side)
     ///
     /// # Arguments
@@ -139,28 +283,32 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// Ok(()) on success
     ///
     /// # Errors
-    /// Returns `IpcError::BufferFull` if buffer is full
+    /// - `IpcError::BufferFull` if buffer is full
+    /// - `IpcError::Corrupted` if an index is out of range
     ///
     /// # Implementation Notes
-    /// - Uses Acquire ordering to read head/tail
+    /// - Reads the tail with Acquire ordering, only if the cached copy says
+    ///   the buffer is full
     /// - Uses Release ordering to update head (ensures item write is visible)
     /// - Signals consumer via notification if configured
     pub fn push(&self, item: T) -> Result<()> {
-        let head = self.head.load(Ordering::Acquire);
-        let tail = self.tail.load(Ordering::Acquire);
+        let head = Self::slot(self.producer.0.index.load(Ordering::Relaxed))?;
 
         // Check if buffer is full (leaves one slot empty to distinguish full/empty)
-        if (head + 1) % N == tail {
+        if self.free_slots(head, 1)? == 0 {
             return Err(IpcError::BufferFull { capacity: N });
         }
 
-        // Write item to buffer
+        // Write item to buffer, then stamp it
         unsafe {
             core::ptr::write_volatile(self.buffer.as_ptr().add(head) as *mut T, item);
         }
+        let sequence = self.producer.0.sequence.load(Ordering::Relaxed).wrapping_add(1);
+        self.sequences[head].store(sequence, Ordering::Relaxed);
+        self.producer.0.sequence.store(sequence, Ordering::Relaxed);
 
         // Update head with release semantics for visibility
-        self.head.store((head + 1) % N, Ordering::Release);
+        self.producer.0.index.store((head + 1) % N, Ordering::Release);
 
         // Signal consumer via notification
         if let Some(notify_cap) = self.consumer_notify {
@@ -179,26 +327,34 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// The popped item on success
     ///
     /// # Errors
-    /// Returns `IpcError::BufferEmpty` if buffer is empty
+    /// - `IpcError::BufferEmpty` if buffer is empty
+    /// - `IpcError::Corrupted` if an index is out of range, or the next
+    ///   slot does not hold the item the consumer expects (see
+    ///   [Corruption Detection](Self#corruption-detection))
     ///
     /// # Implementation Notes
-    /// - Uses Acquire ordering to read head/tail
+    /// - Reads the head with Acquire ordering, only if the cached copy says
+    ///   the buffer is empty
     /// - Uses Release ordering to update tail (ensures read is complete)
     /// - Signals producer via notification if configured
     pub fn pop(&self) -> Result<T> {
-        let head = self.head.load(Ordering::Acquire);
-        let tail = self.tail.load(Ordering::Acquire);
+        let tail = Self::slot(self.consumer.0.index.load(Ordering::Relaxed))?;
 
         // Check if buffer is empty
-        if head == tail {
+        if self.available(tail, 1)? == 0 {
             return Err(IpcError::BufferEmpty);
         }
 
-        // Read item from buffer
+        // Check the stamp, then read item from buffer
+        let sequence = self.consumer.0.sequence.load(Ordering::Relaxed).wrapping_add(1);
+        if self.sequences[tail].load(Ordering::Relaxed) != sequence {
+            return Err(IpcError::Corrupted);
+        }
         let item = unsafe { core::ptr::read_volatile(self.buffer.as_ptr().add(tail) as *const T) };
+        self.consumer.0.sequence.store(sequence, Ordering::Relaxed);
 
         // Update tail with release semantics
-        self.tail.store((tail + 1) % N, Ordering::Release);
+        self.consumer.0.index.store((tail + 1) % N, Ordering::Release);
 
         // Signal producer that space is available
         if let Some(notify_cap) = self.producer_notify {
@@ -211,10 +367,129 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
         Ok(item)
     }
 
+    /// Push as many of `items` as fit (producer side)
+    ///
+    /// # Returns
+    /// Number of items pushed, from the front of `items` (0 if the buffer
+    /// is full, or if `push` would fail with `IpcError::Corrupted`)
+    ///
+    /// # Implementation Notes
+    /// - One head update and one consumer notification for the whole
+    ///   batch, instead of one per item as with `push`
+    pub fn push_slice(&self, items: &[T]) -> usize {
+        let Ok(head) = Self::slot(self.producer.0.index.load(Ordering::Relaxed)) else {
+            return 0;
+        };
+        let count = items.len().min(self.free_slots(head, items.len()).unwrap_or(0));
+        if count == 0 {
+            return 0;
+        }
+
+        let mut sequence = self.producer.0.sequence.load(Ordering::Relaxed);
+        for (i, &item) in items[..count].iter().enumerate() {
+            unsafe {
+                core::ptr::write_volatile(
+                    self.buffer.as_ptr().add((head + i) % N) as *mut T,
+                    item,
+                );
+            }
+            sequence = sequence.wrapping_add(1);
+            self.sequences[(head + i) % N].store(sequence, Ordering::Relaxed);
+        }
+        self.producer.0.sequence.store(sequence, Ordering::Relaxed);
+        self.producer.0.index.store((head + count) % N, Ordering::Release);
+
+        if let Some(notify_cap) = self.consumer_notify {
+            unsafe {
+                sys_signal(notify_cap, 1);
+            }
+        }
+        count
+    }
+
+    /// Pop as many items as are available into `out` (consumer side)
+    ///
+    /// # Returns
+    /// Number of items popped into the front of `out` (0 if the buffer
+    /// is empty). Stops before a corrupted slot, so that `pop` reports it.
+    ///
+    /// # Implementation Notes
+    /// - One tail update and one producer notification for the whole
+    ///   batch, instead of one per item as with `pop`
+    pub fn pop_into(&self, out: &mut [T]) -> usize {
+        let Ok(tail) = Self::slot(self.consumer.0.index.load(Ordering::Relaxed)) else {
+            return 0;
+        };
+        let available = out.len().min(self.available(tail, out.len()).unwrap_or(0));
+
+        let mut sequence = self.consumer.0.sequence.load(Ordering::Relaxed);
+        let mut count = 0;
+        for slot in out[..available].iter_mut() {
+            let index = (tail + count) % N;
+            if self.sequences[index].load(Ordering::Relaxed) != sequence.wrapping_add(1) {
+                break;
+            }
+            *slot = unsafe { core::ptr::read_volatile(self.buffer.as_ptr().add(index)) };
+            sequence = sequence.wrapping_add(1);
+            count += 1;
+        }
+        if count == 0 {
+            return 0;
+        }
+        self.consumer.0.sequence.store(sequence, Ordering::Relaxed);
+        self.consumer.0.index.store((tail + count) % N, Ordering::Release);
+
+        if let Some(notify_cap) = self.producer_notify {
+            unsafe {
+                sys_signal(notify_cap, 2);
+            }
+        }
+        count
+    }
+
+    /// Free slots seen from the producer at `head`
+    ///
+    /// Counts from the cached tail, and reloads the tail only if that
+    /// leaves fewer than `wanted` (one slot always stays empty).
+    fn free_slots(&self, head: usize, wanted: usize) -> Result<usize> {
+        let free = |tail| (tail + N - head - 1) % N;
+        let cached = free(Self::slot(self.producer.0.cached.load(Ordering::Relaxed))?);
+        if cached >= wanted {
+            return Ok(cached);
+        }
+        let tail = Self::slot(self.consumer.0.index.load(Ordering::Acquire))?;
+        self.producer.0.cached.store(tail, Ordering::Relaxed);
+        Ok(free(tail))
+    }
+
+    /// Items available to the consumer at `tail`
+    ///
+    /// Counts to the cached head, and reloads the head only if that gives
+    /// fewer than `wanted`.
+    fn available(&self, tail: usize, wanted: usize) -> Result<usize> {
+        let available = |head| (head + N - tail) % N;
+        let cached = available(Self::slot(self.consumer.0.cached.load(Ordering::Relaxed))?);
+        if cached >= wanted {
+            return Ok(cached);
+        }
+        let head = Self::slot(self.producer.0.index.load(Ordering::Acquire))?;
+        self.consumer.0.cached.store(head, Ordering::Relaxed);
+        Ok(available(head))
+    }
+
+    /// `index` as read from shared memory, if it is a slot of the ring
+    fn slot(index: usize) -> Result<usize> {
+        if index < N {
+            Ok(index)
+        } else {
+            Err(IpcError::Corrupted)
+        }
+    }
+
     /// Get current buffer occupancy
     pub fn len(&self) -> usize {
-        let head = self.head.load(Ordering::Acquire);
-        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.producer.0.index.load(Ordering::Acquire);
+        let tail = self.consumer.0.index.load(Ordering::Acquire);
 
         if head >= tail {
             head - tail
@@ -225,16 +500,41 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
 
     /// Check if buffer is empty
     pub fn is_empty(&self) -> bool {
-        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+        let head = self.producer.0.index.load(Ordering::Acquire);
+        head == self.consumer.0.index.load(Ordering::Acquire)
     }
 
     /// Check if buffer is full
     pub fn is_full(&self) -> bool {
-        let head = self.head.load(Ordering::Acquire);
-        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.producer.0.index.load(Ordering::Acquire);
+        let tail = self.consumer.0.index.load(Ordering::Acquire);
         (head + 1) % N == tail
     }
 
+    /// Create a timer for `Producer::with_timer`
+    ///
+    /// The timer signals the producer notification with `TIMEOUT_BADGE`.
+    /// Creating timers needs the CAP_CAPS capability.
+    ///
+    /// # Errors
+    /// Returns error if no producer notification is configured or the
+    /// kernel refuses the timer
+    pub fn producer_timer(&self) -> Result<TimerCap> {
+        create_timer(self.producer_notify)
+    }
+
+    /// Create a timer for `Consumer::with_timer`
+    ///
+    /// The timer signals the consumer notification with `TIMEOUT_BADGE`.
+    /// Creating timers needs the CAP_CAPS capability.
+    ///
+    /// # Errors
+    /// Returns error if no consumer notification is configured or the
+    /// kernel refuses the timer
+    pub fn consumer_timer(&self) -> Result<TimerCap> {
+        create_timer(self.consumer_notify)
+    }
+
     /// Get the consumer notification capability
     ///
     /// Returns the notification capability that the producer signals
@@ -255,17 +555,23 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// # Errors
     /// Returns error if no consumer notification is configured
     pub fn wait_consumer(&self) -> Result<u64> {
-        match self.consumer_notify {
-            Some(notify_cap) => {
-                let signals = unsafe { sys_wait(notify_cap) };
-                if signals == u64::MAX {
-                    Err(IpcError::NotificationFailed)
-                } else {
-                    Ok(signals)
-                }
-            }
-            None => Err(IpcError::InvalidNotification),
-        }
+        wait_notification(self.consumer_notify)
+    }
+
+    /// Wait for consumer notification (blocking), lending the caller's
+    /// priority to the producer's thread until it signals
+    ///
+    /// Priority inheritance: a high-priority consumer blocked on a
+    /// low-priority producer would otherwise wait behind every thread
+    /// ranked between the two. The producer only ever gains priority, and
+    /// drops back to its own when it signals. If the donation fails (say,
+    /// `producer_tcb` is not a TCB capability), this waits without it.
+    ///
+    /// # Errors
+    /// Returns error if no consumer notification is configured
+    pub fn wait_consumer_lending(&self, producer_tcb: TcbCap) -> Result<u64> {
+        lend_priority(Some(producer_tcb), self.consumer_notify);
+        wait_notification(self.consumer_notify)
     }
 
     /// Wait for producer notification (blocking)
@@ -279,17 +585,7 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// # Errors
     /// Returns error if no producer notification is configured
     pub fn wait_producer(&self) -> Result<u64> {
-        match self.producer_notify {
-            Some(notify_cap) => {
-                let signals = unsafe { sys_wait(notify_cap) };
-                if signals == u64::MAX {
-                    Err(IpcError::NotificationFailed)
-                } else {
-                    Ok(signals)
-                }
-            }
-            None => Err(IpcError::InvalidNotification),
-        }
+        wait_notification(self.producer_notify)
     }
 
     /// Poll consumer notification (non-blocking)
@@ -299,10 +595,7 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// # Returns
     /// Signal bits (0 if no signals available)
     pub fn poll_consumer(&self) -> u64 {
-        match self.consumer_notify {
-            Some(notify_cap) => unsafe { sys_poll(notify_cap) },
-            None => 0,
-        }
+        poll_notification(self.consumer_notify)
     }
 
     /// Poll producer notification (non-blocking)
@@ -312,64 +605,105 @@ impl<T: Copy, const N: usize> SharedRing<T, N> {
     /// # Returns
     /// Signal bits (0 if no signals available)
     pub fn poll_producer(&self) -> u64 {
-        match self.producer_notify {
-            Some(notify_cap) => unsafe { sys_poll(notify_cap) },
-            None => 0,
+        poll_notification(self.producer_notify)
+    }
+}
+
+/// Wait on a ring's notification, if it has one
+fn wait_notification(notify: Option<NotificationCap>) -> Result<u64> {
+    let notify_cap = notify.ok_or(IpcError::InvalidNotification)?;
+    let signals = unsafe { sys_wait(notify_cap) };
+    if signals == u64::MAX {
+        Err(IpcError::NotificationFailed)
+    } else {
+        Ok(signals)
+    }
+}
+
+/// Lend the caller's priority to `tcb` until it signals `notify`, if both
+/// are given (best effort)
+fn lend_priority(tcb: Option<TcbCap>, notify: Option<NotificationCap>) {
+    if let (Some(tcb), Some(notify)) = (tcb, notify) {
+        unsafe {
+            sys_tcb_donate_priority(tcb, notify);
         }
     }
 }
 
-// Syscall wrappers for notification operations
-// These call into kernel notification syscalls (0x17-0x1A)
-
-/// Signal a notification (non-blocking)
-unsafe fn sys_signal(notification_cap: u64, badge: u64) {
-    let syscall_num: u64 = 0x18; // SYS_SIGNAL
-    core::arch::asm!(
-        "mov x8, {syscall_num}",
-        "mov x0, {cap}",
-        "mov x1, {badge}",
-        "svc #0",
-        syscall_num = in(reg) syscall_num,
-        cap = in(reg) notification_cap,
-        badge = in(reg) badge,
-        out("x8") _,
-        out("x0") _,
-        out("x1") _,
-    );
+/// Poll a ring's notification (0 if it has none)
+fn poll_notification(notify: Option<NotificationCap>) -> u64 {
+    match notify {
+        Some(notify_cap) => unsafe { sys_poll(notify_cap) },
+        None => 0,
+    }
 }
 
-/// Wait for notification (blocking)
-unsafe fn sys_wait(notification_cap: u64) -> u64 {
-    let syscall_num: u64 = 0x19; // SYS_WAIT
-    let result: u64;
-    core::arch::asm!(
-        "mov x8, {syscall_num}",
-        "mov x0, {cap}",
-        "svc #0",
-        "mov {result}, x0",
-        syscall_num = in(reg) syscall_num,
-        cap = in(reg) notification_cap,
-        result = out(reg) result,
-        out("x8") _,
-    );
-    result
+/// Create a timer signaling `notify` with `TIMEOUT_BADGE`
+fn create_timer(notify: Option<NotificationCap>) -> Result<TimerCap> {
+    let notify_cap = notify.ok_or(IpcError::InvalidNotification)?;
+    match unsafe { sys_timer_create(notify_cap, TIMEOUT_BADGE) } {
+        u64::MAX => Err(IpcError::NotificationFailed),
+        timer => Ok(timer),
+    }
 }
 
-/// Poll notification (non-blocking)
-unsafe fn sys_poll(notification_cap: u64) -> u64 {
-    let syscall_num: u64 = 0x1A; // SYS_POLL
-    let result: u64;
-    core::arch::asm!(
-        "mov x8, {syscall_num}",
-        "mov x0, {cap}",
-        "svc #0",
-        "mov {result}, x0",
-        syscall_num = in(reg) syscall_num,
-        cap = in(reg) notification_cap,
-        result = out(reg) result,
-        out("x8") _,
-    );
+/// Run `attempt` until it stops failing for lack of space or data,
+/// waiting on `notify` in between
+///
+/// With a `timeout`, `timer` (bound to `notify`, see `create_timer`) is
+/// armed for it and `IpcError::Timeout` returned once it fires. Each wait
+/// lends the caller's priority to `lend_to`, the thread that signals
+/// `notify`, if there is one.
+fn block_on<R>(
+    notify: Option<NotificationCap>,
+    lend_to: Option<TcbCap>,
+    timer: Option<TimerCap>,
+    timeout: Option<Duration>,
+    mut attempt: impl FnMut() -> Result<R>,
+) -> Result<R> {
+    let would_block = |e: &IpcError| matches!(e, IpcError::BufferFull { .. } | IpcError::BufferEmpty);
+    match attempt() {
+        Err(e) if would_block(&e) => {}
+        done => return done,
+    }
+
+    let armed = match timeout {
+        Some(Duration::ZERO) => return Err(IpcError::Timeout),
+        Some(timeout) => {
+            let timer = timer.ok_or(IpcError::InvalidNotification)?;
+            // Drop a timeout left over from an earlier call; the ring is
+            // checked again before waiting, so no wakeup is lost
+            poll_notification(notify);
+            let timeout_us = timeout.as_micros().clamp(1, u64::MAX as u128) as u64;
+            if unsafe { sys_timer_set(timer, timeout_us, 0) } != 0 {
+                return Err(IpcError::NotificationFailed);
+            }
+            Some(timer)
+        }
+        None => None,
+    };
+
+    let result = loop {
+        match attempt() {
+            Err(e) if would_block(&e) => {}
+            done => break done,
+        }
+        lend_priority(lend_to, notify);
+        match wait_notification(notify) {
+            // Space or data may have come with the timeout
+            Ok(signals) if signals & TIMEOUT_BADGE != 0 => {
+                break attempt().map_err(|e| if would_block(&e) { IpcError::Timeout } else { e });
+            }
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+
+    if let Some(timer) = armed {
+        unsafe {
+            sys_timer_set(timer, 0, 0);
+        }
+    }
     result
 }
 
@@ -379,12 +713,25 @@ unsafe fn sys_poll(notification_cap: u64) -> u64 {
 /// Only allows push operations and producer notifications.
 pub struct Producer<'a, T: Copy, const N: usize> {
     ring: &'a SharedRing<T, N>,
+    /// Timer for `send_blocking` timeouts
+    timer: Option<TimerCap>,
 }
 
 impl<'a, T: Copy, const N: usize> Producer<'a, T, N> {
     /// Create a producer handle from a shared ring
     pub fn new(ring: &'a SharedRing<T, N>) -> Self {
-        Self { ring }
+        Self { ring, timer: None }
+    }
+
+    /// Create a producer handle that can time out in `send_blocking`
+    ///
+    /// `timer` must signal the ring's producer notification with
+    /// `TIMEOUT_BADGE`; see `SharedRing::producer_timer`.
+    pub fn with_timer(ring: &'a SharedRing<T, N>, timer: TimerCap) -> Self {
+        Self {
+            ring,
+            timer: Some(timer),
+        }
     }
 
     /// Push an item into the ring buffer
@@ -392,6 +739,25 @@ impl<'a, T: Copy, const N: usize> Producer<'a, T, N> {
         self.ring.push(item)
     }
 
+    /// Push as many of `items` as fit, signaling the consumer once
+    pub fn push_slice(&self, items: &[T]) -> usize {
+        self.ring.push_slice(items)
+    }
+
+    /// Push an item, waiting for space if the buffer is full
+    ///
+    /// # Arguments
+    /// * `item` - Item to push
+    /// * `timeout` - How long to wait at most; `None` waits forever
+    ///
+    /// # Errors
+    /// - `IpcError::Timeout` if there was no space in time
+    /// - `IpcError::InvalidNotification` if the ring has no producer
+    ///   notification, or a timeout is given without a timer
+    pub fn send_blocking(&self, item: T, timeout: Option<Duration>) -> Result<()> {
+        block_on(self.ring.producer_notify, None, self.timer, timeout, || self.ring.push(item))
+    }
+
     /// Check if buffer is full
     pub fn is_full(&self) -> bool {
         self.ring.is_full()
@@ -414,12 +780,41 @@ impl<'a, T: Copy, const N: usize> Producer<'a, T, N> {
 /// Only allows pop operations and consumer notifications.
 pub struct Consumer<'a, T: Copy, const N: usize> {
     ring: &'a SharedRing<T, N>,
+    /// Timer for `recv_blocking` timeouts
+    timer: Option<TimerCap>,
+    /// Producer's thread, lent the consumer's priority while it waits
+    producer_tcb: Option<TcbCap>,
 }
 
 impl<'a, T: Copy, const N: usize> Consumer<'a, T, N> {
     /// Create a consumer handle from a shared ring
     pub fn new(ring: &'a SharedRing<T, N>) -> Self {
-        Self { ring }
+        Self {
+            ring,
+            timer: None,
+            producer_tcb: None,
+        }
+    }
+
+    /// Create a consumer handle that can time out in `recv_blocking`
+    ///
+    /// `timer` must signal the ring's consumer notification with
+    /// `TIMEOUT_BADGE`; see `SharedRing::consumer_timer`.
+    pub fn with_timer(ring: &'a SharedRing<T, N>, timer: TimerCap) -> Self {
+        Self {
+            ring,
+            timer: Some(timer),
+            producer_tcb: None,
+        }
+    }
+
+    /// Lend the consumer's priority to the producer's thread whenever it
+    /// waits for data; see `SharedRing::wait_consumer_lending`
+    pub fn lend_priority_to(self, producer_tcb: TcbCap) -> Self {
+        Self {
+            producer_tcb: Some(producer_tcb),
+            ..self
+        }
     }
 
     /// Pop an item from the ring buffer
@@ -427,6 +822,26 @@ impl<'a, T: Copy, const N: usize> Consumer<'a, T, N> {
         self.ring.pop()
     }
 
+    /// Pop as many items as are available into `out`, signaling the
+    /// producer once
+    pub fn pop_into(&self, out: &mut [T]) -> usize {
+        self.ring.pop_into(out)
+    }
+
+    /// Pop an item, waiting for one if the buffer is empty
+    ///
+    /// # Arguments
+    /// * `timeout` - How long to wait at most; `None` waits forever
+    ///
+    /// # Errors
+    /// - `IpcError::Timeout` if no item came in time
+    /// - `IpcError::InvalidNotification` if the ring has no consumer
+    ///   notification, or a timeout is given without a timer
+    /// - `IpcError::Corrupted` as from `pop`
+    pub fn recv_blocking(&self, timeout: Option<Duration>) -> Result<T> {
+        block_on(self.ring.consumer_notify, self.producer_tcb, self.timer, timeout, || self.ring.pop())
+    }
+
     /// Check if buffer is empty
     pub fn is_empty(&self) -> bool {
         self.ring.is_empty()
@@ -439,7 +854,10 @@ impl<'a, T: Copy, const N: usize> Consumer<'a, T, N> {
 
     /// Wait for data to become available
     pub fn wait_for_data(&self) -> Result<u64> {
-        self.ring.wait_consumer()
+        match self.producer_tcb {
+            Some(producer_tcb) => self.ring.wait_consumer_lending(producer_tcb),
+            None => self.ring.wait_consumer(),
+        }
     }
 
     /// Poll for data availability notification
@@ -447,3 +865,118 @@ impl<'a, T: Copy, const N: usize> Consumer<'a, T, N> {
         self.ring.poll_consumer()
     }
 }
+
+// Notifications are shared by every test in the process, so each test
+// uses its own capability numbers
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_push_pop_in_order() {
+        let ring: SharedRing<u32, 8> = SharedRing::new();
+        for i in 0..7 {
+            ring.push(i).unwrap();
+        }
+        assert_eq!(ring.push(7), Err(IpcError::BufferFull { capacity: 8 }));
+        assert!(ring.is_full());
+        for i in 0..7 {
+            assert_eq!(ring.pop(), Ok(i));
+        }
+        assert_eq!(ring.pop(), Err(IpcError::BufferEmpty));
+    }
+
+    #[test]
+    fn test_push_slice_and_pop_into() {
+        let ring: SharedRing<u32, 8> = SharedRing::with_notifications(140, 141);
+        assert_eq!(ring.push_slice(&[]), 0);
+        assert_eq!(ring.poll_consumer(), 0);
+
+        // As many as fit, one slot staying empty, with one signal
+        let items: [u32; 10] = core::array::from_fn(|i| i as u32);
+        assert_eq!(ring.push_slice(&items), 7);
+        assert_eq!(ring.push_slice(&items), 0);
+        assert_eq!(ring.poll_consumer(), 1);
+
+        let mut out = [0u32; 4];
+        assert_eq!(ring.pop_into(&mut out), 4);
+        assert_eq!(out, [0, 1, 2, 3]);
+        assert_eq!(ring.poll_producer(), 2);
+
+        // Around the end of the buffer
+        assert_eq!(ring.push_slice(&[7, 8, 9]), 3);
+        let mut out = [0u32; 16];
+        assert_eq!(ring.pop_into(&mut out), 6);
+        assert_eq!(out[..6], [4, 5, 6, 7, 8, 9]);
+        assert_eq!(ring.pop_into(&mut out), 0);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_signal_before_wait_is_kept() {
+        let ring: SharedRing<u32, 4> = SharedRing::with_notifications(100, 101);
+        ring.push(1).unwrap();
+        assert_eq!(ring.wait_consumer(), Ok(1));
+        assert_eq!(ring.poll_consumer(), 0);
+        ring.pop().unwrap();
+        assert_eq!(ring.poll_producer(), 2);
+    }
+
+    #[test]
+    fn test_recv_blocking_wakes_on_push() {
+        let ring: SharedRing<u64, 16> = SharedRing::with_notifications(110, 111);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    if i % 10 == 0 {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    Producer::new(&ring).send_blocking(i, None).unwrap();
+                }
+            });
+            let consumer = Consumer::new(&ring);
+            for i in 0..100 {
+                assert_eq!(consumer.recv_blocking(None), Ok(i));
+            }
+        });
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_send_blocking_waits_for_space() {
+        let ring: SharedRing<u32, 2> = SharedRing::with_notifications(120, 121);
+        ring.push(1).unwrap();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(5));
+                assert_eq!(ring.pop(), Ok(1));
+            });
+            Producer::new(&ring).send_blocking(2, None).unwrap();
+        });
+        assert_eq!(ring.pop(), Ok(2));
+    }
+
+    #[test]
+    fn test_recv_blocking_times_out() {
+        let ring: SharedRing<u32, 4> = SharedRing::with_notifications(130, 131);
+        let consumer = Consumer::with_timer(&ring, ring.consumer_timer().unwrap());
+        assert_eq!(consumer.recv_blocking(Some(Duration::ZERO)), Err(IpcError::Timeout));
+        assert_eq!(consumer.recv_blocking(Some(Duration::from_millis(5))), Err(IpcError::Timeout));
+
+        // Data that is already there comes back, timeout or not
+        ring.push(3).unwrap();
+        assert_eq!(consumer.recv_blocking(Some(Duration::from_millis(5))), Ok(3));
+        assert_eq!(
+            Consumer::new(&ring).recv_blocking(Some(Duration::from_millis(5))),
+            Err(IpcError::InvalidNotification)
+        );
+    }
+
+    #[test]
+    fn test_blocking_without_notification() {
+        let ring: SharedRing<u32, 4> = SharedRing::new();
+        assert_eq!(Consumer::new(&ring).recv_blocking(None), Err(IpcError::InvalidNotification));
+        assert_eq!(ring.wait_consumer(), Err(IpcError::InvalidNotification));
+    }
+}
@@ -0,0 +1,298 @@
+//! Multi-producer/multi-consumer ring buffer
+//!
+//! [`SharedRing`](crate::SharedRing) assumes one producer and one consumer;
+//! a log aggregator or event bus fed by many components would need a ring
+//! per producer. [`MpmcRing`] lets any number of producers and consumers
+//! share one ring.
+//!
+//! # Design
+//! Bounded queue with a sequence number per slot (Vyukov). Producers
+//! reserve a position by CAS on `head`, consumers by CAS on `tail`; the
+//! slot's sequence says whose turn it is:
+//! - `sequence == pos`: empty, the producer reserving `pos` may write it
+//! - `sequence == pos + 1`: written, the consumer reserving `pos` may read it
+//! - after reading, `sequence = pos + N` hands it to the next lap's producer
+//!
+//! A producer or consumer preempted between reserving and publishing a
+//! slot only holds up that slot's readers; others keep going.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sys::sys_signal;
+use crate::{poll_notification, wait_notification, IpcError, NotificationCap, Result};
+
+/// One element and whose turn it is
+#[repr(C)]
+struct Slot<T> {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Shared memory ring buffer for many producers and consumers
+///
+/// # Type Parameters
+/// * `T` - Element type (must be `Copy` for zero-copy semantics)
+/// * `N` - Ring buffer capacity (must be power of 2); unlike `SharedRing`
+///   all `N` slots are usable
+///
+/// # Lock-Free Guarantees
+/// - Any number of producers and consumers
+/// - Lock-free: a failed CAS means another side made progress
+/// - Notifications as in `SharedRing`: badge 1 to the consumer
+///   notification on push, badge 2 to the producer notification on pop
+#[repr(C)]
+pub struct MpmcRing<T: Copy, const N: usize> {
+    slots: [Slot<T>; N],
+    /// Next position to push
+    head: AtomicUsize,
+    /// Next position to pop
+    tail: AtomicUsize,
+    /// Notification capability for signaling consumers
+    consumer_notify: Option<NotificationCap>,
+    /// Notification capability for signaling producers
+    producer_notify: Option<NotificationCap>,
+}
+
+// Slots are only touched by the side that reserved them
+unsafe impl<T: Copy + Send, const N: usize> Sync for MpmcRing<T, N> {}
+
+impl<T: Copy, const N: usize> MpmcRing<T, N> {
+    /// Create a new ring buffer without notifications
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2
+    pub fn new() -> Self {
+        assert!(N.is_power_of_two(), "Ring buffer size must be power of 2");
+
+        Self {
+            slots: core::array::from_fn(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            consumer_notify: None,
+            producer_notify: None,
+        }
+    }
+
+    /// Create a new ring buffer with notification capabilities
+    ///
+    /// # Arguments
+    /// * `consumer_notify` - Notification capability to signal consumers
+    /// * `producer_notify` - Notification capability to signal producers
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2
+    pub fn with_notifications(
+        consumer_notify: NotificationCap,
+        producer_notify: NotificationCap,
+    ) -> Self {
+        Self {
+            consumer_notify: Some(consumer_notify),
+            producer_notify: Some(producer_notify),
+            ..Self::new()
+        }
+    }
+
+    /// Push an item into the ring buffer (any producer)
+    ///
+    /// # Errors
+    /// Returns `IpcError::BufferFull` if buffer is full
+    pub fn push(&self, item: T) -> Result<()> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        let slot = loop {
+            let slot = &self.slots[pos % N];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence.wrapping_sub(pos) as isize).signum() {
+                // Free for this lap: try to reserve it
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break slot,
+                    Err(current) => pos = current,
+                },
+                // Not read yet on the previous lap
+                -1 => return Err(IpcError::BufferFull { capacity: N }),
+                // Another producer took it; catch up
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        };
+
+        unsafe {
+            (*slot.value.get()).write(item);
+        }
+        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+
+        if let Some(notify_cap) = self.consumer_notify {
+            unsafe {
+                sys_signal(notify_cap, 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pop an item from the ring buffer (any consumer)
+    ///
+    /// # Errors
+    /// Returns `IpcError::BufferEmpty` if buffer is empty
+    pub fn pop(&self) -> Result<T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        let slot = loop {
+            let slot = &self.slots[pos % N];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence.wrapping_sub(pos.wrapping_add(1)) as isize).signum() {
+                // Written for this lap: try to reserve it
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break slot,
+                    Err(current) => pos = current,
+                },
+                // Not written yet
+                -1 => return Err(IpcError::BufferEmpty),
+                // Another consumer took it; catch up
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        };
+
+        let item = unsafe { (*slot.value.get()).assume_init() };
+        slot.sequence.store(pos.wrapping_add(N), Ordering::Release);
+
+        if let Some(notify_cap) = self.producer_notify {
+            unsafe {
+                sys_signal(notify_cap, 2);
+            }
+        }
+        Ok(item)
+    }
+
+    /// Get current buffer occupancy
+    ///
+    /// A snapshot: other producers and consumers may change it right away.
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        head.wrapping_sub(tail).min(N)
+    }
+
+    /// Check if buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check if buffer is full
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// Wait for consumer notification (blocking)
+    ///
+    /// # Errors
+    /// Returns error if no consumer notification is configured
+    pub fn wait_consumer(&self) -> Result<u64> {
+        wait_notification(self.consumer_notify)
+    }
+
+    /// Wait for producer notification (blocking)
+    ///
+    /// # Errors
+    /// Returns error if no producer notification is configured
+    pub fn wait_producer(&self) -> Result<u64> {
+        wait_notification(self.producer_notify)
+    }
+
+    /// Poll consumer notification (non-blocking)
+    pub fn poll_consumer(&self) -> u64 {
+        poll_notification(self.consumer_notify)
+    }
+
+    /// Poll producer notification (non-blocking)
+    pub fn poll_producer(&self) -> u64 {
+        poll_notification(self.producer_notify)
+    }
+}
+
+impl<T: Copy, const N: usize> Default for MpmcRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_every_slot_is_usable() {
+        let ring: MpmcRing<u32, 4> = MpmcRing::new();
+        // Several laps, so positions wrap around the slots
+        for lap in 0..5 {
+            for i in 0..4 {
+                ring.push(lap * 4 + i).unwrap();
+            }
+            assert!(ring.is_full());
+            assert_eq!(ring.push(0), Err(IpcError::BufferFull { capacity: 4 }));
+            for i in 0..4 {
+                assert_eq!(ring.pop(), Ok(lap * 4 + i));
+            }
+            assert_eq!(ring.pop(), Err(IpcError::BufferEmpty));
+        }
+    }
+
+    #[test]
+    fn test_signals_both_sides() {
+        let ring: MpmcRing<u32, 4> = MpmcRing::with_notifications(400, 401);
+        ring.push(1).unwrap();
+        assert_eq!(ring.poll_consumer(), 1);
+        ring.pop().unwrap();
+        assert_eq!(ring.poll_producer(), 2);
+    }
+
+    #[test]
+    fn test_many_producers_and_consumers() {
+        const PRODUCERS: u64 = 4;
+        const ITEMS: u64 = 1000;
+        let ring: MpmcRing<u64, 16> = MpmcRing::new();
+        let popped = AtomicUsize::new(0);
+        let sum = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for producer in 0..PRODUCERS {
+                let ring = &ring;
+                s.spawn(move || {
+                    for i in 0..ITEMS {
+                        while ring.push(producer * ITEMS + i).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while popped.load(Ordering::Relaxed) < (PRODUCERS * ITEMS) as usize {
+                        match ring.pop() {
+                            Ok(item) => {
+                                sum.fetch_add(item as usize, Ordering::Relaxed);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(_) => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        });
+        // Each item came out once
+        assert_eq!(popped.load(Ordering::Relaxed), (PRODUCERS * ITEMS) as usize);
+        assert_eq!(sum.load(Ordering::Relaxed), (0..PRODUCERS * ITEMS).sum::<u64>() as usize);
+        assert!(ring.is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+//! Variable-length message ring
+//!
+//! [`SharedRing`](crate::SharedRing) moves fixed-size `T`s, so strings,
+//! packets or serialized structs need a worst-case element type.
+//! [`MsgRing`] moves byte messages of any length up to the ring's size
+//! through a shared byte buffer instead.
+//!
+//! # Framing
+//! Each message is a 4-byte little-endian length followed by its bytes.
+//! Frames are packed back to back and wrap around the end of the buffer,
+//! so no space is lost to padding; a frame takes `4 + len` bytes.
+//!
+//! Single producer, single consumer, signaled like `SharedRing`: badge 1
+//! to the consumer notification on send, badge 2 to the producer
+//! notification on receive.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sys::sys_signal;
+use crate::{poll_notification, wait_notification, IpcError, NotificationCap, Result};
+
+/// Bytes of the length prefix
+const HEADER: usize = 4;
+
+/// Shared memory ring of variable-length byte messages
+///
+/// # Type Parameters
+/// * `N` - Buffer size in bytes (must be power of 2), the most a message
+///   can take including its 4-byte length
+///
+/// # Safety
+/// Must be placed in shared memory accessible to both producer and
+/// consumer processes, like `SharedRing`.
+#[repr(C)]
+pub struct MsgRing<const N: usize> {
+    /// Frames, wrapping around
+    buffer: UnsafeCell<[u8; N]>,
+    /// Bytes ever written (producer writes here)
+    head: AtomicUsize,
+    /// Bytes ever read (consumer reads here)
+    tail: AtomicUsize,
+    /// Notification capability for signaling consumer
+    consumer_notify: Option<NotificationCap>,
+    /// Notification capability for signaling producer
+    producer_notify: Option<NotificationCap>,
+}
+
+// The producer only writes bytes the consumer is done with and the other
+// way round, ordered by head and tail
+unsafe impl<const N: usize> Sync for MsgRing<N> {}
+
+impl<const N: usize> MsgRing<N> {
+    /// Create a new message ring without notifications
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2 above 4 (compile-time check)
+    pub const fn new() -> Self {
+        assert!(N.is_power_of_two(), "Ring buffer size must be power of 2");
+        assert!(N > HEADER, "Ring buffer must hold more than a length prefix");
+
+        Self {
+            buffer: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            consumer_notify: None,
+            producer_notify: None,
+        }
+    }
+
+    /// Create a new message ring with notification capabilities
+    ///
+    /// # Arguments
+    /// * `consumer_notify` - Notification capability to signal consumer
+    /// * `producer_notify` - Notification capability to signal producer
+    ///
+    /// # Panics
+    /// Panics if N is not a power of 2
+    pub fn with_notifications(
+        consumer_notify: NotificationCap,
+        producer_notify: NotificationCap,
+    ) -> Self {
+        Self {
+            consumer_notify: Some(consumer_notify),
+            producer_notify: Some(producer_notify),
+            ..Self::new()
+        }
+    }
+
+    /// Largest message the ring can take
+    pub const fn max_message(&self) -> usize {
+        N - HEADER
+    }
+
+    /// Send a message (producer side)
+    ///
+    /// # Errors
+    /// - `IpcError::MessageTooLarge` if `msg` is longer than
+    ///   [`max_message`](Self::max_message)
+    /// - `IpcError::BufferFull` if there is not enough free space now
+    pub fn send(&self, msg: &[u8]) -> Result<()> {
+        if msg.len() > self.max_message() {
+            return Err(IpcError::MessageTooLarge { size: msg.len() });
+        }
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if N - head.wrapping_sub(tail) < HEADER + msg.len() {
+            return Err(IpcError::BufferFull { capacity: N });
+        }
+
+        self.write(head, &(msg.len() as u32).to_le_bytes());
+        self.write(head.wrapping_add(HEADER), msg);
+        self.head.store(head.wrapping_add(HEADER + msg.len()), Ordering::Release);
+
+        if let Some(notify_cap) = self.consumer_notify {
+            unsafe {
+                sys_signal(notify_cap, 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Length of the next message, if there is one (consumer side)
+    pub fn peek_len(&self) -> Option<usize> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if self.head.load(Ordering::Acquire) == tail {
+            return None;
+        }
+        let mut header = [0; HEADER];
+        self.read(tail, &mut header);
+        Some(u32::from_le_bytes(header) as usize)
+    }
+
+    /// Receive the next message into `buf`, returning its length
+    /// (consumer side)
+    ///
+    /// # Errors
+    /// - `IpcError::BufferEmpty` if there is no message
+    /// - `IpcError::MessageTooLarge` if it does not fit in `buf`; it stays
+    ///   in the ring, [`peek_len`](Self::peek_len) says how much room it
+    ///   needs
+    /// - `IpcError::Corrupted` if its length prefix runs past the bytes
+    ///   the producer has written
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.next_len()?;
+        let buf = buf
+            .get_mut(..len)
+            .ok_or(IpcError::MessageTooLarge { size: len })?;
+
+        let tail = self.tail.load(Ordering::Relaxed);
+        self.read(tail.wrapping_add(HEADER), buf);
+        self.tail.store(tail.wrapping_add(HEADER + len), Ordering::Release);
+
+        if let Some(notify_cap) = self.producer_notify {
+            unsafe {
+                sys_signal(notify_cap, 2);
+            }
+        }
+        Ok(len)
+    }
+
+    /// Receive the next message into a new vector
+    ///
+    /// # Errors
+    /// - `IpcError::BufferEmpty` if there is no message
+    /// - `IpcError::Corrupted` as from [`recv`](Self::recv)
+    #[cfg(feature = "alloc")]
+    pub fn recv_vec(&self) -> Result<alloc::vec::Vec<u8>> {
+        let mut msg = alloc::vec![0; self.next_len()?];
+        self.recv(&mut msg)?;
+        Ok(msg)
+    }
+
+    /// Bytes in use, frame headers included
+    pub fn used(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        self.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+
+    /// Check if there are no messages
+    pub fn is_empty(&self) -> bool {
+        self.used() == 0
+    }
+
+    /// Wait for consumer notification (blocking)
+    ///
+    /// # Errors
+    /// Returns error if no consumer notification is configured
+    pub fn wait_consumer(&self) -> Result<u64> {
+        wait_notification(self.consumer_notify)
+    }
+
+    /// Wait for producer notification (blocking)
+    ///
+    /// # Errors
+    /// Returns error if no producer notification is configured
+    pub fn wait_producer(&self) -> Result<u64> {
+        wait_notification(self.producer_notify)
+    }
+
+    /// Poll consumer notification (non-blocking)
+    pub fn poll_consumer(&self) -> u64 {
+        poll_notification(self.consumer_notify)
+    }
+
+    /// Poll producer notification (non-blocking)
+    pub fn poll_producer(&self) -> u64 {
+        poll_notification(self.producer_notify)
+    }
+
+    /// Length of the next message, checked against what the producer has
+    /// written
+    fn next_len(&self) -> Result<usize> {
+        let len = self.peek_len().ok_or(IpcError::BufferEmpty)?;
+        if len > self.used().min(N).saturating_sub(HEADER) {
+            return Err(IpcError::Corrupted);
+        }
+        Ok(len)
+    }
+
+    /// Copy `bytes` in at stream position `pos`, wrapping around
+    fn write(&self, pos: usize, bytes: &[u8]) {
+        let start = pos % N;
+        let first = bytes.len().min(N - start);
+        let buffer = self.buffer.get() as *mut u8;
+        unsafe {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer.add(start), first);
+            core::ptr::copy_nonoverlapping(bytes[first..].as_ptr(), buffer, bytes.len() - first);
+        }
+    }
+
+    /// Copy out `buf.len()` bytes from stream position `pos`, wrapping around
+    fn read(&self, pos: usize, buf: &mut [u8]) {
+        let start = pos % N;
+        let first = buf.len().min(N - start);
+        let buffer = self.buffer.get() as *const u8;
+        unsafe {
+            core::ptr::copy_nonoverlapping(buffer.add(start), buf.as_mut_ptr(), first);
+            core::ptr::copy_nonoverlapping(buffer, buf[first..].as_mut_ptr(), buf.len() - first);
+        }
+    }
+}
+
+impl<const N: usize> Default for MsgRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_in_order() {
+        let ring: MsgRing<64> = MsgRing::with_notifications(500, 501);
+        ring.send(b"hello").unwrap();
+        ring.send(b"").unwrap();
+        ring.send(b"world!").unwrap();
+        assert_eq!(ring.poll_consumer(), 1);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(ring.peek_len(), Some(5));
+        assert_eq!(ring.recv(&mut buf), Ok(5));
+        assert_eq!(&buf[..5], b"hello");
+        assert_eq!(ring.recv(&mut buf), Ok(0));
+        assert_eq!(ring.recv(&mut buf), Ok(6));
+        assert_eq!(&buf[..6], b"world!");
+        assert_eq!(ring.poll_producer(), 2);
+        assert_eq!(ring.recv(&mut buf), Err(IpcError::BufferEmpty));
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_frames_wrap_around() {
+        // 14-byte frames in 16 bytes start somewhere else every round, and
+        // most are split across the end, some in their length prefix
+        let ring: MsgRing<16> = MsgRing::new();
+        let mut buf = [0u8; 10];
+        for round in 0..20u8 {
+            let msg: [u8; 10] = core::array::from_fn(|i| round.wrapping_mul(10).wrapping_add(i as u8));
+            ring.send(&msg).unwrap();
+            // No room for a second frame
+            assert_eq!(ring.send(b"x"), Err(IpcError::BufferFull { capacity: 16 }));
+            assert_eq!(ring.recv(&mut buf), Ok(10));
+            assert_eq!(buf, msg);
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_oversize_messages() {
+        let ring: MsgRing<16> = MsgRing::new();
+        assert_eq!(ring.max_message(), 12);
+        assert_eq!(ring.send(&[0; 13]), Err(IpcError::MessageTooLarge { size: 13 }));
+        ring.send(&[7; 12]).unwrap();
+
+        // Too big for the buffer: it stays, for a bigger one
+        let mut small = [0u8; 4];
+        assert_eq!(ring.recv(&mut small), Err(IpcError::MessageTooLarge { size: 12 }));
+        assert_eq!(ring.peek_len(), Some(12));
+        let mut buf = [0u8; 12];
+        assert_eq!(ring.recv(&mut buf), Ok(12));
+        assert_eq!(buf, [7; 12]);
+    }
+}
@@ -0,0 +1,177 @@
+//! Waiting on many channels at once
+//!
+//! A server fed by several channels (a UART driver's input, output and
+//! control rings) would otherwise need a thread per notification, or
+//! polling. A [`NotificationSet`] gives each channel its own badge bit on
+//! one notification: the peer of each channel signals through a copy of
+//! the notification capability minted with that channel's badge, and the
+//! kernel ORs the badges together, so one `wait` says which channels are
+//! ready.
+//!
+//! ```ignore
+//! let mut set = NotificationSet::new(notification);
+//! let input = set.add()?;
+//! let control = set.add()?;
+//! // Mint the copies the peers signal through (or have the root task
+//! // mint them with `input.badge()`), then hand them over, e.g. with
+//! // `send_with_cap`
+//! set.mint(input, 120)?;
+//! set.mint(control, 121)?;
+//!
+//! loop {
+//!     for channel in set.wait()? {
+//!         if channel == input { /* drain the input ring */ }
+//!         if channel == control { /* handle control messages */ }
+//!     }
+//! }
+//! ```
+//!
+//! A badged capability always signals its own badge, so a ring whose
+//! notification slot holds such a copy signals its channel's bit whether
+//! it means data or space. Bit 63 is left out, as it is the rings'
+//! `TIMEOUT_BADGE`.
+
+use crate::sys::sys_cap_mint;
+use crate::{poll_notification, wait_notification, IpcError, NotificationCap, Result, TIMEOUT_BADGE};
+
+/// Badge bits channels can have (all but `TIMEOUT_BADGE`)
+const CHANNEL_BITS: u64 = !TIMEOUT_BADGE;
+
+/// A channel of a [`NotificationSet`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Channel(u8);
+
+impl Channel {
+    /// Index of the channel's badge bit
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    /// Badge to mint the channel's notification capability with
+    pub const fn badge(self) -> u64 {
+        1 << self.0
+    }
+}
+
+/// Channels that were signaled, lowest first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ready(u64);
+
+impl Ready {
+    /// Whether `channel` was signaled
+    pub const fn contains(self, channel: Channel) -> bool {
+        self.0 & channel.badge() != 0
+    }
+
+    /// Whether no channel was signaled
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Signaled badge bits
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+}
+
+impl Iterator for Ready {
+    type Item = Channel;
+
+    fn next(&mut self) -> Option<Channel> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(Channel(index))
+    }
+}
+
+/// Channels multiplexed onto the badge bits of one notification
+#[derive(Debug)]
+pub struct NotificationSet {
+    notification: NotificationCap,
+    /// Badge bits given to channels
+    channels: u64,
+}
+
+impl NotificationSet {
+    /// An empty set waiting on `notification` (unbadged)
+    pub const fn new(notification: NotificationCap) -> Self {
+        Self {
+            notification,
+            channels: 0,
+        }
+    }
+
+    /// Add a channel, on the lowest free badge bit
+    ///
+    /// # Errors
+    /// Returns `IpcError::InvalidNotification` if all 63 bits are taken
+    pub fn add(&mut self) -> Result<Channel> {
+        let free = CHANNEL_BITS & !self.channels;
+        if free == 0 {
+            return Err(IpcError::InvalidNotification);
+        }
+        let channel = Channel(free.trailing_zeros() as u8);
+        self.channels |= channel.badge();
+        Ok(channel)
+    }
+
+    /// Remove a channel; its bit is ignored from now on and may be given
+    /// to a new one
+    ///
+    /// The capabilities minted for it keep signaling until they are
+    /// deleted or revoked.
+    pub fn remove(&mut self, channel: Channel) {
+        self.channels &= !channel.badge();
+    }
+
+    /// Mint `channel`'s notification capability into `dest_slot` of the
+    /// caller's CSpace
+    ///
+    /// Needs capability management rights; without them, have the root
+    /// task mint it with [`Channel::badge`].
+    ///
+    /// # Errors
+    /// Returns `IpcError::NotificationFailed` if the mint syscall fails
+    pub fn mint(&self, channel: Channel, dest_slot: u64) -> Result<NotificationCap> {
+        match unsafe { sys_cap_mint(self.notification, dest_slot, channel.badge()) } {
+            0 => Ok(dest_slot),
+            _ => Err(IpcError::NotificationFailed),
+        }
+    }
+
+    /// Block until a channel is signaled, and return the ones that were
+    ///
+    /// Signals for bits not in the set are dropped; if only those came,
+    /// it waits again.
+    ///
+    /// # Errors
+    /// Returns `IpcError::NotificationFailed` if the wait syscall fails
+    pub fn wait(&self) -> Result<Ready> {
+        loop {
+            let ready = self.ready(wait_notification(Some(self.notification))?);
+            if !ready.is_empty() {
+                return Ok(ready);
+            }
+        }
+    }
+
+    /// Channels signaled since the last wait or poll, without blocking
+    pub fn poll(&self) -> Ready {
+        match poll_notification(Some(self.notification)) {
+            u64::MAX => Ready::default(),
+            signals => self.ready(signals),
+        }
+    }
+
+    /// The notification the set waits on
+    pub fn notification(&self) -> NotificationCap {
+        self.notification
+    }
+
+    fn ready(&self, signals: u64) -> Ready {
+        Ready(signals & self.channels)
+    }
+}
@@ -0,0 +1,247 @@
+//! Typed request/response over kernel endpoints
+//!
+//! Rings carry streams; a service answering requests (VFS, the broker)
+//! wants a call that blocks for its reply. [`Client`] and [`Server`] wrap
+//! the kernel's `SYS_CALL` and `SYS_REPLY_RECV` for request and response
+//! types that implement [`Message`], so a service only writes its
+//! encoding and a handler.
+//!
+//! ```ignore
+//! use kaal_ipc::rpc::{Client, Server};
+//!
+//! // Server, on its endpoint
+//! Server::<u64, u64>::new(endpoint).run(|_badge, x| x * 2);
+//!
+//! // Client, on a (badged) copy of it
+//! let doubler = Client::<u64, u64>::new(endpoint);
+//! assert_eq!(doubler.call(&21)?, 42);
+//! ```
+//!
+//! # Wire Format
+//! Little-endian, within the kernel's 256-byte message limit:
+//! - Request: correlation ID (u32), then the encoded `Req`
+//! - Reply: the request's correlation ID (u32), a status (u32, 0 for a
+//!   response, 1 if the request or response did not decode or encode),
+//!   then the encoded `Resp`
+//!
+//! # Reply Capabilities
+//! The kernel keeps one reply capability per server thread, for the last
+//! caller it received from, so a server answers each request before it
+//! takes the next; a client stays blocked until then. The correlation ID
+//! lets the client check that the reply is the one to its request.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+
+use crate::sys::{sys_call, sys_recv, sys_reply_recv};
+use crate::{EndpointCap, IpcError, Result};
+
+/// Largest message, request or reply (the kernel's IPC limit)
+pub const MAX_MESSAGE: usize = 256;
+
+/// Correlation ID, and on replies the status
+const REQUEST_HEADER: usize = 4;
+const REPLY_HEADER: usize = 8;
+
+/// Largest encoded request or response
+pub const MAX_PAYLOAD: usize = MAX_MESSAGE - REPLY_HEADER;
+
+/// Reply statuses
+const STATUS_OK: u32 = 0;
+const STATUS_MALFORMED: u32 = 1;
+
+/// A request or response that can be sent through an endpoint
+pub trait Message: Sized {
+    /// Encode into `buf` (at most [`MAX_PAYLOAD`] bytes), returning the
+    /// length, or `None` if it does not fit
+    fn encode(&self, buf: &mut [u8]) -> Option<usize>;
+
+    /// Decode a message, or `None` if it is malformed
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Message for () {
+    fn encode(&self, _buf: &mut [u8]) -> Option<usize> {
+        Some(0)
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        bytes.is_empty().then_some(())
+    }
+}
+
+macro_rules! impl_message_le {
+    ($($int:ty),*) => {$(
+        impl Message for $int {
+            fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+                let bytes = self.to_le_bytes();
+                buf.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+                Some(bytes.len())
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                Some(Self::from_le_bytes(bytes.try_into().ok()?))
+            }
+        }
+    )*};
+}
+
+impl_message_le!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// Calls a server with `Req`s and gets `Resp`s back
+pub struct Client<Req, Resp> {
+    endpoint: EndpointCap,
+    /// Correlation ID of the next call
+    next_id: Cell<u32>,
+    _messages: PhantomData<fn(&Req) -> Resp>,
+}
+
+impl<Req: Message, Resp: Message> Client<Req, Resp> {
+    /// Call the server listening on `endpoint`
+    pub const fn new(endpoint: EndpointCap) -> Self {
+        Self {
+            endpoint,
+            next_id: Cell::new(0),
+            _messages: PhantomData,
+        }
+    }
+
+    /// Send `request` and block for the response
+    ///
+    /// # Errors
+    /// - `IpcError::InvalidMessage` if `request` does not encode, the
+    ///   server could not decode it, or the reply is malformed or to
+    ///   another request
+    /// - `IpcError::EndpointFailed` if the call syscall fails
+    pub fn call(&self, request: &Req) -> Result<Resp> {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+
+        let mut message = [0u8; MAX_MESSAGE];
+        message[..REQUEST_HEADER].copy_from_slice(&id.to_le_bytes());
+        let len = request
+            .encode(&mut message[REQUEST_HEADER..REQUEST_HEADER + MAX_PAYLOAD])
+            .ok_or(IpcError::InvalidMessage)?;
+
+        let mut reply = [0u8; MAX_MESSAGE];
+        let reply_len = unsafe { sys_call(self.endpoint, &message[..REQUEST_HEADER + len], &mut reply) };
+        if reply_len == u64::MAX {
+            return Err(IpcError::EndpointFailed);
+        }
+        let reply = reply.get(..reply_len as usize).ok_or(IpcError::InvalidMessage)?;
+        if reply.len() < REPLY_HEADER || read_u32(reply, 0) != id || read_u32(reply, 4) != STATUS_OK {
+            return Err(IpcError::InvalidMessage);
+        }
+        Resp::decode(&reply[REPLY_HEADER..]).ok_or(IpcError::InvalidMessage)
+    }
+
+    /// The endpoint calls go to
+    pub fn endpoint(&self) -> EndpointCap {
+        self.endpoint
+    }
+}
+
+/// Answers `Req`s from clients with `Resp`s
+pub struct Server<Req, Resp> {
+    endpoint: EndpointCap,
+    _messages: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req: Message, Resp: Message> Server<Req, Resp> {
+    /// Serve calls arriving on `endpoint`
+    pub const fn new(endpoint: EndpointCap) -> Self {
+        Self {
+            endpoint,
+            _messages: PhantomData,
+        }
+    }
+
+    /// Serve requests forever
+    ///
+    /// `handler` gets the badge of the caller's endpoint capability (0 if
+    /// unbadged) and its request. A request that does not decode, or whose
+    /// response does not encode, is answered with an error status; the
+    /// client gets `IpcError::InvalidMessage`.
+    pub fn run(&self, mut handler: impl FnMut(u64, Req) -> Resp) -> ! {
+        let mut request = [0u8; MAX_MESSAGE];
+        let mut reply = [0u8; MAX_MESSAGE];
+        let mut owed = None;
+
+        loop {
+            let received = match owed.take() {
+                Some(len) => unsafe { sys_reply_recv(self.endpoint, &mut request, &reply[..len]) },
+                None => unsafe { sys_recv(self.endpoint, &mut request) },
+            };
+            let Some((len, badge)) = received else {
+                continue;
+            };
+            let request = &request[..(len as usize).min(MAX_MESSAGE)];
+            owed = Some(respond(request, &mut reply, |req| handler(badge, req)));
+        }
+    }
+
+    /// The endpoint calls arrive on
+    pub fn endpoint(&self) -> EndpointCap {
+        self.endpoint
+    }
+}
+
+/// Answer the request in `message` into `reply`, returning the reply length
+///
+/// A message too short for a correlation ID gets ID 0.
+fn respond<Req: Message, Resp: Message>(
+    message: &[u8],
+    reply: &mut [u8; MAX_MESSAGE],
+    handler: impl FnOnce(Req) -> Resp,
+) -> usize {
+    let id = if message.len() >= REQUEST_HEADER { read_u32(message, 0) } else { 0 };
+    reply[..4].copy_from_slice(&id.to_le_bytes());
+
+    let response = message.get(REQUEST_HEADER..).and_then(Req::decode).map(handler);
+    let len = response.and_then(|response| response.encode(&mut reply[REPLY_HEADER..]));
+    let status = if len.is_some() { STATUS_OK } else { STATUS_MALFORMED };
+    reply[4..REPLY_HEADER].copy_from_slice(&status.to_le_bytes());
+    REPLY_HEADER + len.unwrap_or(0)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_call_gets_its_response() {
+        thread::spawn(|| Server::<u64, u64>::new(700).run(|_badge, x| x * 2));
+        let doubler = Client::<u64, u64>::new(700);
+        assert_eq!(doubler.call(&21), Ok(42));
+        assert_eq!(doubler.call(&0), Ok(0));
+    }
+
+    #[test]
+    fn test_concurrent_clients() {
+        thread::spawn(|| Server::<u32, u32>::new(710).run(|_badge, x| x + 1));
+        thread::scope(|s| {
+            for client in 0..4u32 {
+                s.spawn(move || {
+                    let next = Client::<u32, u32>::new(710);
+                    for i in 0..50 {
+                        let x = client * 1000 + i;
+                        assert_eq!(next.call(&x), Ok(x + 1));
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_malformed_request() {
+        thread::spawn(|| Server::<u64, u64>::new(720).run(|_badge, x| x));
+        // Four bytes where the server decodes eight
+        assert_eq!(Client::<u32, u64>::new(720).call(&1), Err(IpcError::InvalidMessage));
+        assert_eq!(Client::<u64, u64>::new(720).call(&1), Ok(1));
+    }
+}
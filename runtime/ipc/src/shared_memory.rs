@@ -0,0 +1,262 @@
+//! Shared memory objects
+//!
+//! Setting up a channel used to mean carrying loose values around: the
+//! physical address from `SYS_MEMORY_ALLOCATE` or the kernel broker, the
+//! size, the address each side mapped it at. A [`SharedMemory`] keeps
+//! them together, with the mappings it made, so that tearing the memory
+//! down undoes all of them.
+//!
+//! ```ignore
+//! // Producer: allocate, map, and publish under a name
+//! let mut memory = SharedMemory::create(0x2000)?;
+//! let ring = memory.map(0x3)?;
+//! memory.publish("kaal.uart.output", Some(notification))?;
+//!
+//! // Consumer: find it by name and map it too
+//! let mut memory = SharedMemory::open("kaal.uart.output", 0x2000)?;
+//! let ring = memory.map(0x3)?;
+//!
+//! // Root task: map it into the processes it sets a channel up for,
+//! // and out of all of them again
+//! let mut memory = SharedMemory::create(0x2000)?;
+//! unsafe {
+//!     memory.map_into(producer_tcb, 0x9000_0000, 0x3)?;
+//!     memory.map_into(consumer_tcb, 0x9000_0000, 0x3)?;
+//!     memory.destroy()?;
+//! }
+//! ```
+//!
+//! # Lifecycle
+//! Nothing is undone on drop, as channel memory usually lives as long as
+//! its process; [`SharedMemory::destroy`] unmaps it from the caller and
+//! from every process it was mapped into. The kernel has no call to free
+//! a frame or withdraw a published name yet, so the physical memory and
+//! its broker entry outlive it.
+
+use crate::sys::{
+    sys_memory_allocate, sys_memory_map, sys_memory_map_into, sys_memory_unmap, sys_memory_unmap_from,
+    sys_shmem_query, sys_shmem_register,
+};
+use crate::{IpcError, NotificationCap, Result};
+
+/// Most mappings into other processes a [`SharedMemory`] keeps track of
+pub const MAX_MAPPINGS: usize = 8;
+
+/// Longest name the kernel broker takes
+const MAX_NAME: usize = 32;
+
+const PAGE_SIZE: usize = 4096;
+
+/// A mapping into another process's address space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mapping {
+    tcb_cap: u64,
+    virt_addr: usize,
+}
+
+/// A region of physical memory shared between processes
+///
+/// Mapping permissions are the kernel's: read 0x1, write 0x2, exec 0x4,
+/// plus the memory type and page size bits `SYS_MEMORY_MAP` takes.
+#[derive(Debug)]
+pub struct SharedMemory {
+    phys_addr: usize,
+    size: usize,
+    /// Where it is mapped in the caller's address space
+    local: Option<usize>,
+    /// Where it is mapped in other processes
+    remote: [Option<Mapping>; MAX_MAPPINGS],
+}
+
+impl SharedMemory {
+    /// Allocate `size` bytes of physical memory to share
+    ///
+    /// # Errors
+    /// - `IpcError::InvalidSize` if `size` is zero or not whole pages
+    /// - `IpcError::MemoryFailed` if the allocation fails
+    pub fn create(size: usize) -> Result<Self> {
+        check_size(size)?;
+        match unsafe { sys_memory_allocate(size as u64) } {
+            u64::MAX => Err(IpcError::MemoryFailed),
+            phys_addr => Ok(Self::from_phys(phys_addr as usize, size)),
+        }
+    }
+
+    /// Find the memory another process published under `name`
+    ///
+    /// `size` is what both sides agreed on; the broker does not keep it.
+    ///
+    /// # Errors
+    /// - `IpcError::InvalidSize` if `size` is zero or not whole pages
+    /// - `IpcError::NotFound` if nothing is published under `name` (yet)
+    pub fn open(name: &str, size: usize) -> Result<Self> {
+        check_size(size)?;
+        if name.is_empty() || name.len() > MAX_NAME {
+            return Err(IpcError::NotFound);
+        }
+        match unsafe { sys_shmem_query(name) } {
+            0 => Err(IpcError::NotFound),
+            phys_addr => Ok(Self::from_phys(phys_addr as usize, size)),
+        }
+    }
+
+    fn from_phys(phys_addr: usize, size: usize) -> Self {
+        Self {
+            phys_addr,
+            size,
+            local: None,
+            remote: [None; MAX_MAPPINGS],
+        }
+    }
+
+    /// Publish the memory with the kernel broker, so other processes can
+    /// [`open`](Self::open) it by `name`, along with a notification they
+    /// can get a capability to
+    ///
+    /// Publish after setting up what others expect to find in it.
+    ///
+    /// # Errors
+    /// Returns `IpcError::MemoryFailed` if the name is not 1-32 bytes,
+    /// the broker is full, or `notification` is not a notification
+    pub fn publish(&self, name: &str, notification: Option<NotificationCap>) -> Result<()> {
+        if name.is_empty() || name.len() > MAX_NAME {
+            return Err(IpcError::MemoryFailed);
+        }
+        let notification = notification.unwrap_or(0);
+        match unsafe { sys_shmem_register(name, self.phys_addr as u64, self.size as u64, notification) } {
+            0 => Ok(()),
+            _ => Err(IpcError::MemoryFailed),
+        }
+    }
+
+    /// Map the memory into the caller's address space, returning its
+    /// address (or where it already is, if it was mapped before)
+    ///
+    /// # Errors
+    /// Returns `IpcError::MemoryFailed` if the map syscall fails
+    pub fn map(&mut self, permissions: u64) -> Result<usize> {
+        if let Some(virt_addr) = self.local {
+            return Ok(virt_addr);
+        }
+        match unsafe { sys_memory_map(self.phys_addr as u64, self.size as u64, permissions) } {
+            u64::MAX => Err(IpcError::MemoryFailed),
+            virt_addr => {
+                self.local = Some(virt_addr as usize);
+                Ok(virt_addr as usize)
+            }
+        }
+    }
+
+    /// Unmap the memory from the caller's address space, if it is mapped
+    ///
+    /// # Errors
+    /// Returns `IpcError::MemoryFailed` if the unmap syscall fails
+    pub fn unmap(&mut self) -> Result<()> {
+        let Some(virt_addr) = self.local else {
+            return Ok(());
+        };
+        match unsafe { sys_memory_unmap(virt_addr as u64, self.size as u64) } {
+            0 => {
+                self.local = None;
+                Ok(())
+            }
+            _ => Err(IpcError::MemoryFailed),
+        }
+    }
+
+    /// Map the memory into the process of `tcb_cap` at `virt_addr`
+    ///
+    /// Needs CAP_MEMORY, as the root task has.
+    ///
+    /// # Errors
+    /// Returns `IpcError::MemoryFailed` if the map syscall fails, or
+    /// [`MAX_MAPPINGS`] are already tracked
+    ///
+    /// # Safety
+    /// Replaces whatever the process has mapped in that range.
+    pub unsafe fn map_into(&mut self, tcb_cap: u64, virt_addr: usize, permissions: u64) -> Result<()> {
+        let free = self
+            .remote
+            .iter()
+            .position(Option::is_none)
+            .ok_or(IpcError::MemoryFailed)?;
+        match sys_memory_map_into(tcb_cap, self.phys_addr as u64, self.size as u64, virt_addr as u64, permissions) {
+            0 => {
+                self.remote[free] = Some(Mapping { tcb_cap, virt_addr });
+                Ok(())
+            }
+            _ => Err(IpcError::MemoryFailed),
+        }
+    }
+
+    /// Unmap the memory from the process of `tcb_cap`, everywhere
+    /// [`map_into`](Self::map_into) put it
+    ///
+    /// # Errors
+    /// Returns `IpcError::MemoryFailed` if an unmap syscall fails; that
+    /// mapping stays tracked
+    ///
+    /// # Safety
+    /// The process must no longer use the memory; it faults if it does.
+    pub unsafe fn unmap_from(&mut self, tcb_cap: u64) -> Result<()> {
+        let mut result = Ok(());
+        for entry in &mut self.remote {
+            let Some(mapping) = *entry else {
+                continue;
+            };
+            if mapping.tcb_cap != tcb_cap {
+                continue;
+            }
+            match sys_memory_unmap_from(tcb_cap, mapping.virt_addr as u64, self.size as u64) {
+                0 => *entry = None,
+                _ => result = Err(IpcError::MemoryFailed),
+            }
+        }
+        result
+    }
+
+    /// Unmap the memory from every process it was mapped into, and from
+    /// the caller
+    ///
+    /// A mapping that fails to come out does not stop the others. See the
+    /// module documentation for what outlives the object either way.
+    ///
+    /// # Errors
+    /// Returns `IpcError::MemoryFailed` if any unmap syscall failed
+    ///
+    /// # Safety
+    /// As for [`unmap_from`](Self::unmap_from), for every process it was
+    /// mapped into.
+    pub unsafe fn destroy(mut self) -> Result<()> {
+        let mut result = self.unmap();
+        for mapping in self.remote.into_iter().flatten() {
+            if self.unmap_from(mapping.tcb_cap).is_err() {
+                result = Err(IpcError::MemoryFailed);
+            }
+        }
+        result
+    }
+
+    /// Physical address of the memory
+    pub fn phys_addr(&self) -> usize {
+        self.phys_addr
+    }
+
+    /// Size in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Where the memory is mapped in the caller's address space, if it is
+    pub fn virt_addr(&self) -> Option<usize> {
+        self.local
+    }
+}
+
+/// Shared memory comes in whole pages
+fn check_size(size: usize) -> Result<()> {
+    if size == 0 || !size.is_multiple_of(PAGE_SIZE) {
+        return Err(IpcError::InvalidSize);
+    }
+    Ok(())
+}
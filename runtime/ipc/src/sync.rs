@@ -0,0 +1,253 @@
+//! Semaphores and condition variables over notifications
+//!
+//! Waiting for "the producer has written its data" or "a buffer is free"
+//! by looping on `yield_now` keeps the waiter runnable and burns its
+//! time slice. A [`Semaphore`] or [`Condvar`] blocks on a notification
+//! instead, and the side that changes things signals it.
+//!
+//! ```ignore
+//! // In shared memory, zeroed: a semaphore with no permits
+//! let ready = unsafe { &*(buffer + READY_OFFSET as *const Semaphore) };
+//!
+//! // Producer: one permit per message written
+//! write_message(i);
+//! ready.release(producer_notification);
+//!
+//! // Consumer: wait for each message
+//! ready.acquire(consumer_notification)?;
+//! read_message(i);
+//! ```
+//!
+//! Both live in shared memory (all zeroes is a valid initial state) and
+//! keep only counters there. Each call takes the caller's capability to
+//! the notification they share, as the slot it sits in differs between
+//! processes. The notification's signal bits mean nothing to them, so it
+//! should not also carry a ring's signals.
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::sys::sys_signal;
+use crate::{block_on, create_timer, wait_notification, IpcError, NotificationCap, Result, TimerCap};
+
+/// Badge the primitives signal with
+const WAKE_BADGE: u64 = 1;
+
+/// A counting semaphore
+///
+/// Any number of threads, in any number of processes, may acquire and
+/// release it.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Semaphore {
+    permits: AtomicUsize,
+}
+
+impl Semaphore {
+    /// A semaphore holding `permits` permits
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+        }
+    }
+
+    /// Take a permit if one is available, without blocking
+    pub fn try_acquire(&self) -> bool {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        while permits > 0 {
+            match self
+                .permits
+                .compare_exchange_weak(permits, permits - 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(current) => permits = current,
+            }
+        }
+        false
+    }
+
+    /// Take a permit, blocking on `notify` until one is released
+    ///
+    /// # Errors
+    /// Returns `IpcError::NotificationFailed` if the wait syscall fails
+    pub fn acquire(&self, notify: NotificationCap) -> Result<()> {
+        block_on(Some(notify), None, None, None, || self.attempt())
+    }
+
+    /// Take a permit, blocking on `notify` for at most `timeout`
+    ///
+    /// `timer` must signal `notify` with `TIMEOUT_BADGE`; see
+    /// [`timer`](Self::timer).
+    ///
+    /// # Errors
+    /// - `IpcError::Timeout` if no permit was released in time
+    /// - `IpcError::NotificationFailed` if a syscall fails
+    pub fn acquire_timeout(&self, notify: NotificationCap, timer: TimerCap, timeout: Duration) -> Result<()> {
+        block_on(Some(notify), None, Some(timer), Some(timeout), || self.attempt())
+    }
+
+    /// Give a permit back, waking the threads blocked on `notify`
+    pub fn release(&self, notify: NotificationCap) {
+        self.permits.fetch_add(1, Ordering::Release);
+        unsafe {
+            sys_signal(notify, WAKE_BADGE);
+        }
+    }
+
+    /// Permits available right now
+    pub fn available(&self) -> usize {
+        self.permits.load(Ordering::Acquire)
+    }
+
+    /// Create a timer for [`acquire_timeout`](Self::acquire_timeout),
+    /// signaling `notify`
+    ///
+    /// # Errors
+    /// Returns `IpcError::NotificationFailed` if the timer syscall fails
+    pub fn timer(notify: NotificationCap) -> Result<TimerCap> {
+        create_timer(Some(notify))
+    }
+
+    /// `try_acquire`, failing the way `block_on` waits on
+    fn attempt(&self) -> Result<()> {
+        if self.try_acquire() {
+            Ok(())
+        } else {
+            Err(IpcError::BufferEmpty)
+        }
+    }
+}
+
+/// A condition variable: wait until a condition on shared state turns
+/// false
+///
+/// Counts its notifications, so a waiter can tell a wakeup that came
+/// with one from a stale signal. The kernel hands a signal to the
+/// threads already blocked, so a waiter that was about to block when it
+/// came would miss it; a waiter woken by a new notification passes the
+/// signal on for that one. Waiters on one `Condvar` should wait for the
+/// same condition, so that when one waits again the others have reason
+/// to as well.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Condvar {
+    /// Notifications so far, wrapping
+    events: AtomicU32,
+}
+
+impl Condvar {
+    /// A condition variable no one has waited on
+    pub const fn new() -> Self {
+        Self {
+            events: AtomicU32::new(0),
+        }
+    }
+
+    /// Block on `notify` while `condition` holds
+    ///
+    /// `condition` is checked first, and again after each wakeup.
+    ///
+    /// # Errors
+    /// Returns `IpcError::NotificationFailed` if the wait syscall fails
+    pub fn wait_while(&self, notify: NotificationCap, mut condition: impl FnMut() -> bool) -> Result<()> {
+        loop {
+            let seen = self.events.load(Ordering::Acquire);
+            if !condition() {
+                return Ok(());
+            }
+            wait_notification(Some(notify))?;
+            if self.events.load(Ordering::Acquire) != seen {
+                unsafe {
+                    sys_signal(notify, WAKE_BADGE);
+                }
+            }
+        }
+    }
+
+    /// Wake every thread waiting on `notify` to check its condition again
+    ///
+    /// Call after changing the state the condition is on.
+    pub fn notify_all(&self, notify: NotificationCap) {
+        self.events.fetch_add(1, Ordering::Release);
+        unsafe {
+            sys_signal(notify, WAKE_BADGE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicBool;
+    use std::thread;
+
+    #[test]
+    fn test_semaphore_counts_permits() {
+        let semaphore = Semaphore::new(2);
+        assert!(semaphore.try_acquire());
+        assert!(semaphore.try_acquire());
+        assert!(!semaphore.try_acquire());
+        semaphore.release(200);
+        assert_eq!(semaphore.available(), 1);
+        assert_eq!(semaphore.acquire(200), Ok(()));
+        assert_eq!(semaphore.available(), 0);
+    }
+
+    #[test]
+    fn test_semaphore_wakes_every_waiter() {
+        let semaphore = Semaphore::new(0);
+        let acquired = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..25 {
+                        semaphore.acquire(210).unwrap();
+                        acquired.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+            for i in 0..100 {
+                if i % 10 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                semaphore.release(210);
+            }
+        });
+        assert_eq!(acquired.load(Ordering::Relaxed), 100);
+        assert_eq!(semaphore.available(), 0);
+    }
+
+    #[test]
+    fn test_semaphore_acquire_times_out() {
+        let semaphore = Semaphore::new(0);
+        let timer = Semaphore::timer(220).unwrap();
+        assert_eq!(
+            semaphore.acquire_timeout(220, timer, Duration::from_millis(5)),
+            Err(IpcError::Timeout)
+        );
+        semaphore.release(220);
+        assert_eq!(semaphore.acquire_timeout(220, timer, Duration::from_millis(5)), Ok(()));
+    }
+
+    #[test]
+    fn test_condvar_wakes_every_waiter() {
+        let condvar = Condvar::new();
+        let ready = AtomicBool::new(false);
+        let woken = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for i in 0..4 {
+                let (condvar, ready, woken) = (&condvar, &ready, &woken);
+                s.spawn(move || {
+                    // Some waiters come in around the notification
+                    thread::sleep(Duration::from_millis(i));
+                    condvar.wait_while(230, || !ready.load(Ordering::Acquire)).unwrap();
+                    woken.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            thread::sleep(Duration::from_millis(2));
+            ready.store(true, Ordering::Release);
+            condvar.notify_all(230);
+        });
+        assert_eq!(woken.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,299 @@
+//! `svc #0` wrappers for the KaaL kernel's syscalls
+//!
+//! Each returns what the kernel leaves in x0 (and x1, x2 for receives);
+//! the callers turn that into `IpcError`s.
+
+// Notification syscalls (0x17-0x1A) and timers
+
+/// Signal a notification (non-blocking)
+pub(crate) unsafe fn sys_signal(notification_cap: u64, badge: u64) {
+    let syscall_num: u64 = 0x18; // SYS_SIGNAL
+    core::arch::asm!(
+        "mov x8, {syscall_num}",
+        "mov x0, {cap}",
+        "mov x1, {badge}",
+        "svc #0",
+        syscall_num = in(reg) syscall_num,
+        cap = in(reg) notification_cap,
+        badge = in(reg) badge,
+        out("x8") _,
+        out("x0") _,
+        out("x1") _,
+    );
+}
+
+/// Wait for notification (blocking)
+pub(crate) unsafe fn sys_wait(notification_cap: u64) -> u64 {
+    let syscall_num: u64 = 0x19; // SYS_WAIT
+    let result: u64;
+    core::arch::asm!(
+        "mov x8, {syscall_num}",
+        "mov x0, {cap}",
+        "svc #0",
+        "mov {result}, x0",
+        syscall_num = in(reg) syscall_num,
+        cap = in(reg) notification_cap,
+        result = out(reg) result,
+        out("x8") _,
+    );
+    result
+}
+
+/// Poll notification (non-blocking)
+pub(crate) unsafe fn sys_poll(notification_cap: u64) -> u64 {
+    let syscall_num: u64 = 0x1A; // SYS_POLL
+    let result: u64;
+    core::arch::asm!(
+        "mov x8, {syscall_num}",
+        "mov x0, {cap}",
+        "svc #0",
+        "mov {result}, x0",
+        syscall_num = in(reg) syscall_num,
+        cap = in(reg) notification_cap,
+        result = out(reg) result,
+        out("x8") _,
+    );
+    result
+}
+
+/// Create a timer signaling a notification (fails with u64::MAX)
+pub(crate) unsafe fn sys_timer_create(notification_cap: u64, badge: u64) -> u64 {
+    let syscall_num: u64 = 0x37; // SYS_TIMER_CREATE
+    let result: u64;
+    core::arch::asm!(
+        "mov x8, {syscall_num}",
+        "mov x0, {cap}",
+        "mov x1, {badge}",
+        "svc #0",
+        "mov {result}, x0",
+        syscall_num = in(reg) syscall_num,
+        cap = in(reg) notification_cap,
+        badge = in(reg) badge,
+        result = out(reg) result,
+        out("x8") _,
+        out("x0") _,
+        out("x1") _,
+    );
+    result
+}
+
+/// Arm a timer once after `timeout_us`, or cancel it with 0 (0 on success)
+pub(crate) unsafe fn sys_timer_set(timer_cap: u64, timeout_us: u64, period_us: u64) -> u64 {
+    let syscall_num: u64 = 0x38; // SYS_TIMER_SET
+    let result: u64;
+    core::arch::asm!(
+        "mov x8, {syscall_num}",
+        "mov x0, {timer}",
+        "mov x1, {timeout}",
+        "mov x2, {period}",
+        "svc #0",
+        "mov {result}, x0",
+        syscall_num = in(reg) syscall_num,
+        timer = in(reg) timer_cap,
+        timeout = in(reg) timeout_us,
+        period = in(reg) period_us,
+        result = out(reg) result,
+        out("x8") _,
+        out("x0") _,
+        out("x1") _,
+        out("x2") _,
+    );
+    result
+}
+
+// Syscall wrappers for endpoint operations (0x03-0x08), used by `rpc`
+// and `cap_transfer`
+
+/// Call an endpoint and block for the reply (reply length, or u64::MAX)
+pub(crate) unsafe fn sys_call(endpoint_cap: u64, request: &[u8], reply: &mut [u8]) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x04u64, // SYS_CALL
+        inlateout("x0") endpoint_cap => result,
+        inlateout("x1") request.as_ptr() => _,
+        inlateout("x2") request.len() => _,
+        inlateout("x3") reply.as_mut_ptr() => _,
+        inlateout("x4") reply.len() => _,
+    );
+    result
+}
+
+/// Receive on an endpoint: (length, sender's badge)
+pub(crate) unsafe fn sys_recv(endpoint_cap: u64, buffer: &mut [u8]) -> Option<(u64, u64)> {
+    let (len, badge): (u64, u64);
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x03u64, // SYS_RECV
+        inlateout("x0") endpoint_cap => len,
+        inlateout("x1") buffer.as_mut_ptr() => badge,
+        inlateout("x2") buffer.len() => _,
+    );
+    (len != u64::MAX).then_some((len, badge))
+}
+
+/// Reply to the last caller, then receive on an endpoint: (length,
+/// sender's badge)
+pub(crate) unsafe fn sys_reply_recv(endpoint_cap: u64, buffer: &mut [u8], reply: &[u8]) -> Option<(u64, u64)> {
+    let (len, badge): (u64, u64);
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x06u64, // SYS_REPLY_RECV
+        inlateout("x0") endpoint_cap => len,
+        inlateout("x1") buffer.as_mut_ptr() => badge,
+        inlateout("x2") buffer.len() => _,
+        inlateout("x3") reply.as_ptr() => _,
+        inlateout("x4") reply.len() => _,
+    );
+    (len != u64::MAX).then_some((len, badge))
+}
+
+/// Send on an endpoint with a capability along (0 on success)
+pub(crate) unsafe fn sys_send_cap(endpoint_cap: u64, message: &[u8], cap: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x07u64, // SYS_SEND_CAP
+        inlateout("x0") endpoint_cap => result,
+        inlateout("x1") message.as_ptr() => _,
+        inlateout("x2") message.len() => _,
+        inlateout("x3") cap => _,
+    );
+    result
+}
+
+/// Receive on an endpoint with a slot for a capability: (length, sender's
+/// badge, whether a capability was received)
+pub(crate) unsafe fn sys_recv_cap(endpoint_cap: u64, buffer: &mut [u8], receive_slot: u64) -> Option<(u64, u64, u64)> {
+    let (len, badge, received): (u64, u64, u64);
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x08u64, // SYS_RECV_CAP
+        inlateout("x0") endpoint_cap => len,
+        inlateout("x1") buffer.as_mut_ptr() => badge,
+        inlateout("x2") buffer.len() => received,
+        inlateout("x3") receive_slot => _,
+    );
+    (len != u64::MAX).then_some((len, badge, received))
+}
+
+/// Mint a badged copy of a capability in the caller's CSpace (0 on success)
+pub(crate) unsafe fn sys_cap_mint(src_slot: u64, dest_slot: u64, badge: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x20u64, // SYS_CAP_MINT
+        inlateout("x0") 0u64 => result, // caller's own CSpace
+        inlateout("x1") src_slot => _,
+        inlateout("x2") dest_slot => _,
+        inlateout("x3") badge => _,
+    );
+    result
+}
+
+// Syscall wrappers for memory and the kernel broker, used by
+// `shared_memory`
+
+/// Allocate physical memory (physical address, or u64::MAX)
+pub(crate) unsafe fn sys_memory_allocate(size: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x11u64, // SYS_MEMORY_ALLOCATE
+        inlateout("x0") size => result,
+    );
+    result
+}
+
+/// Map physical memory into the caller (virtual address, or u64::MAX)
+pub(crate) unsafe fn sys_memory_map(phys_addr: u64, size: u64, permissions: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x15u64, // SYS_MEMORY_MAP
+        inlateout("x0") phys_addr => result,
+        inlateout("x1") size => _,
+        inlateout("x2") permissions => _,
+    );
+    result
+}
+
+/// Unmap memory from the caller (0 on success)
+pub(crate) unsafe fn sys_memory_unmap(virt_addr: u64, size: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x16u64, // SYS_MEMORY_UNMAP
+        inlateout("x0") virt_addr => result,
+        inlateout("x1") size => _,
+    );
+    result
+}
+
+/// Map physical memory into another process (0 on success)
+pub(crate) unsafe fn sys_memory_map_into(tcb_cap: u64, phys_addr: u64, size: u64, virt_addr: u64, permissions: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x1Bu64, // SYS_MEMORY_MAP_INTO
+        inlateout("x0") tcb_cap => result,
+        inlateout("x1") phys_addr => _,
+        inlateout("x2") size => _,
+        inlateout("x3") virt_addr => _,
+        inlateout("x4") permissions => _,
+    );
+    result
+}
+
+/// Unmap memory from another process (0 on success)
+pub(crate) unsafe fn sys_memory_unmap_from(tcb_cap: u64, virt_addr: u64, size: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x3Fu64, // SYS_MEMORY_UNMAP_FROM
+        inlateout("x0") tcb_cap => result,
+        inlateout("x1") virt_addr => _,
+        inlateout("x2") size => _,
+    );
+    result
+}
+
+/// Publish memory under a name with the kernel broker (0 on success)
+pub(crate) unsafe fn sys_shmem_register(name: &str, phys_addr: u64, size: u64, notification_cap: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x33u64, // SYS_SHMEM_REGISTER
+        inlateout("x0") name.as_ptr() => result,
+        inlateout("x1") name.len() => _,
+        inlateout("x2") phys_addr => _,
+        inlateout("x3") size => _,
+        inlateout("x4") notification_cap => _,
+    );
+    result
+}
+
+/// Look a name up with the kernel broker (physical address, or 0)
+pub(crate) unsafe fn sys_shmem_query(name: &str) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x34u64, // SYS_SHMEM_QUERY
+        inlateout("x0") name.as_ptr() => result,
+        inlateout("x1") name.len() => _,
+    );
+    result
+}
+
+/// Lend the caller's priority to a thread until it signals a notification
+/// (0 on success)
+pub(crate) unsafe fn sys_tcb_donate_priority(tcb_cap: u64, notification_cap: u64) -> u64 {
+    let result: u64;
+    core::arch::asm!(
+        "svc #0",
+        in("x8") 0x09u64, // SYS_TCB_DONATE_PRIORITY
+        inlateout("x0") tcb_cap => result,
+        inlateout("x1") notification_cap => _,
+    );
+    result
+}
@@ -0,0 +1,281 @@
+//! Host stand-ins for the kernel syscalls
+//!
+//! Notifications behave like the kernel's: any `u64` names one, a signal
+//! no one is waiting for sticks in its word until the next wait or poll
+//! takes it, and a signal while threads are blocked wakes all of them
+//! with that badge and leaves the word clear. Timers fire from a thread
+//! of their own.
+//!
+//! Endpoints carry calls too: any `u64` names one, a call waits there
+//! until a thread receives it, and that thread's next reply goes to it, as
+//! with the kernel's reply capability. Callers are unbadged.
+//!
+//! Capability transfer and minting, memory and the broker are not
+//! modelled: those calls fail the way the kernel fails them on a bad
+//! capability.
+//! Priority donation succeeds and does nothing, as host threads have no
+//! KaaL priorities.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use std::vec::Vec;
+
+/// Every notification, timer and endpoint, behind one lock
+static STATE: Mutex<State> = Mutex::new(State::new());
+
+/// Woken on every signal delivered to blocked threads, and every call or
+/// reply sent
+static DELIVERED: Condvar = Condvar::new();
+
+std::thread_local! {
+    /// Ticket of the call this thread received last and owes a reply
+    static REPLY_TO: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+struct State {
+    notifications: BTreeMap<u64, Notification>,
+    /// Badges handed to woken threads, by wait ticket
+    delivered: BTreeMap<u64, u64>,
+    next_ticket: u64,
+    timers: Vec<Timer>,
+    /// Calls not received yet, by endpoint, as (ticket, request)
+    calls: BTreeMap<u64, VecDeque<(u64, Vec<u8>)>>,
+    /// Replies not taken yet, by call ticket
+    replies: BTreeMap<u64, Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Notification {
+    /// Signals that came while no thread was waiting
+    word: u64,
+    /// Tickets of the threads blocked on it
+    waiters: Vec<u64>,
+}
+
+struct Timer {
+    notification: u64,
+    badge: u64,
+    /// Bumped on every set, so a thread left from an earlier one stops
+    armed: u64,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            notifications: BTreeMap::new(),
+            delivered: BTreeMap::new(),
+            next_ticket: 0,
+            timers: Vec::new(),
+            calls: BTreeMap::new(),
+            replies: BTreeMap::new(),
+        }
+    }
+
+    fn ticket(&mut self) -> u64 {
+        self.next_ticket += 1;
+        self.next_ticket
+    }
+
+    fn signal(&mut self, notification_cap: u64, badge: u64) {
+        let notification = self.notifications.entry(notification_cap).or_default();
+        if notification.waiters.is_empty() {
+            notification.word |= badge;
+            return;
+        }
+        for ticket in notification.waiters.drain(..) {
+            self.delivered.insert(ticket, badge);
+        }
+        DELIVERED.notify_all();
+    }
+}
+
+fn lock() -> MutexGuard<'static, State> {
+    // A test that panicked while holding the lock leaves the state whole
+    STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Signal a notification (non-blocking)
+pub(crate) unsafe fn sys_signal(notification_cap: u64, badge: u64) {
+    lock().signal(notification_cap, badge);
+}
+
+/// Wait for notification (blocking)
+pub(crate) unsafe fn sys_wait(notification_cap: u64) -> u64 {
+    let mut state = lock();
+    let notification = state.notifications.entry(notification_cap).or_default();
+    if notification.word != 0 {
+        return core::mem::take(&mut notification.word);
+    }
+
+    let ticket = state.ticket();
+    state.notifications.entry(notification_cap).or_default().waiters.push(ticket);
+    loop {
+        state = DELIVERED.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(badge) = state.delivered.remove(&ticket) {
+            return badge;
+        }
+    }
+}
+
+/// Poll notification (non-blocking)
+pub(crate) unsafe fn sys_poll(notification_cap: u64) -> u64 {
+    lock()
+        .notifications
+        .get_mut(&notification_cap)
+        .map_or(0, |notification| core::mem::take(&mut notification.word))
+}
+
+/// Create a timer signaling a notification (fails with u64::MAX)
+pub(crate) unsafe fn sys_timer_create(notification_cap: u64, badge: u64) -> u64 {
+    let mut state = lock();
+    state.timers.push(Timer {
+        notification: notification_cap,
+        badge,
+        armed: 0,
+    });
+    state.timers.len() as u64 - 1
+}
+
+/// Arm a timer once after `timeout_us`, or cancel it with 0 (0 on success)
+///
+/// A period re-arms it after every expiry, as the kernel's does.
+pub(crate) unsafe fn sys_timer_set(timer_cap: u64, timeout_us: u64, period_us: u64) -> u64 {
+    let mut state = lock();
+    let Some(timer) = state.timers.get_mut(timer_cap as usize) else {
+        return u64::MAX;
+    };
+    timer.armed += 1;
+    if timeout_us == 0 {
+        return 0;
+    }
+
+    let armed = timer.armed;
+    thread::spawn(move || {
+        let mut delay = timeout_us;
+        loop {
+            thread::sleep(Duration::from_micros(delay));
+            let mut state = lock();
+            let timer = &state.timers[timer_cap as usize];
+            if timer.armed != armed {
+                return;
+            }
+            let (notification, badge) = (timer.notification, timer.badge);
+            state.signal(notification, badge);
+            if period_us == 0 {
+                return;
+            }
+            delay = period_us;
+        }
+    });
+    0
+}
+
+/// Call an endpoint and block for the reply (reply length, or u64::MAX)
+pub(crate) unsafe fn sys_call(endpoint_cap: u64, request: &[u8], reply: &mut [u8]) -> u64 {
+    let mut state = lock();
+    let ticket = state.ticket();
+    state.calls.entry(endpoint_cap).or_default().push_back((ticket, request.to_vec()));
+    DELIVERED.notify_all();
+    loop {
+        state = DELIVERED.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(message) = state.replies.remove(&ticket) {
+            let len = message.len().min(reply.len());
+            reply[..len].copy_from_slice(&message[..len]);
+            return len as u64;
+        }
+    }
+}
+
+/// Receive on an endpoint: (length, sender's badge)
+pub(crate) unsafe fn sys_recv(endpoint_cap: u64, buffer: &mut [u8]) -> Option<(u64, u64)> {
+    let mut state = lock();
+    loop {
+        if let Some((ticket, message)) = state.calls.get_mut(&endpoint_cap).and_then(VecDeque::pop_front) {
+            REPLY_TO.set(Some(ticket));
+            let len = message.len().min(buffer.len());
+            buffer[..len].copy_from_slice(&message[..len]);
+            return Some((len as u64, 0));
+        }
+        state = DELIVERED.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+}
+
+/// Reply to the last caller, then receive on an endpoint: (length,
+/// sender's badge)
+pub(crate) unsafe fn sys_reply_recv(endpoint_cap: u64, buffer: &mut [u8], reply: &[u8]) -> Option<(u64, u64)> {
+    if let Some(ticket) = REPLY_TO.take() {
+        lock().replies.insert(ticket, reply.to_vec());
+        DELIVERED.notify_all();
+    }
+    sys_recv(endpoint_cap, buffer)
+}
+
+/// Send on an endpoint with a capability along (0 on success)
+pub(crate) unsafe fn sys_send_cap(_endpoint_cap: u64, _message: &[u8], _cap: u64) -> u64 {
+    u64::MAX
+}
+
+/// Receive on an endpoint with a slot for a capability: (length, sender's
+/// badge, whether a capability was received)
+pub(crate) unsafe fn sys_recv_cap(
+    _endpoint_cap: u64,
+    _buffer: &mut [u8],
+    _receive_slot: u64,
+) -> Option<(u64, u64, u64)> {
+    None
+}
+
+/// Mint a badged copy of a capability in the caller's CSpace (0 on success)
+pub(crate) unsafe fn sys_cap_mint(_src_slot: u64, _dest_slot: u64, _badge: u64) -> u64 {
+    u64::MAX
+}
+
+/// Allocate physical memory (physical address, or u64::MAX)
+pub(crate) unsafe fn sys_memory_allocate(_size: u64) -> u64 {
+    u64::MAX
+}
+
+/// Map physical memory into the caller (virtual address, or u64::MAX)
+pub(crate) unsafe fn sys_memory_map(_phys_addr: u64, _size: u64, _permissions: u64) -> u64 {
+    u64::MAX
+}
+
+/// Unmap memory from the caller (0 on success)
+pub(crate) unsafe fn sys_memory_unmap(_virt_addr: u64, _size: u64) -> u64 {
+    u64::MAX
+}
+
+/// Map physical memory into another process (0 on success)
+pub(crate) unsafe fn sys_memory_map_into(
+    _tcb_cap: u64,
+    _phys_addr: u64,
+    _size: u64,
+    _virt_addr: u64,
+    _permissions: u64,
+) -> u64 {
+    u64::MAX
+}
+
+/// Unmap memory from another process (0 on success)
+pub(crate) unsafe fn sys_memory_unmap_from(_tcb_cap: u64, _virt_addr: u64, _size: u64) -> u64 {
+    u64::MAX
+}
+
+/// Publish memory under a name with the kernel broker (0 on success)
+pub(crate) unsafe fn sys_shmem_register(_name: &str, _phys_addr: u64, _size: u64, _notification_cap: u64) -> u64 {
+    u64::MAX
+}
+
+/// Look a name up with the kernel broker (physical address, or 0)
+pub(crate) unsafe fn sys_shmem_query(_name: &str) -> u64 {
+    0
+}
+
+/// Lend the caller's priority to a thread until it signals a notification
+/// (0 on success)
+pub(crate) unsafe fn sys_tcb_donate_priority(_tcb_cap: u64, _notification_cap: u64) -> u64 {
+    0
+}
@@ -0,0 +1,17 @@
+//! Kernel syscalls the crate makes
+//!
+//! By default these are `svc #0` into the KaaL kernel. With the `mock`
+//! feature they are stand-ins built on std threads instead, so that the
+//! rings, their blocking paths, the sync primitives and rpc calls run
+//! under `cargo test` on the host (`cargo test --features mock`); `mock.rs`
+//! says what they model.
+
+#[cfg(not(feature = "mock"))]
+mod kernel;
+#[cfg(not(feature = "mock"))]
+pub(crate) use kernel::*;
+
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "mock")]
+pub(crate) use mock::*;
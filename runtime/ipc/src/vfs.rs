@@ -0,0 +1,388 @@
+//! VFS service protocol
+//!
+//! The requests a component makes of the VFS service, and its responses,
+//! as [`rpc`](crate::rpc) messages on the service's endpoint. Files are
+//! read and written through handles the service hands out on
+//! [`Request::Open`], each with its own position; a directory is listed an
+//! entry per [`Request::ReadDir`], by index.
+//!
+//! ```ignore
+//! use kaal_ipc::rpc::Client;
+//! use kaal_ipc::vfs::{flags, Bytes, Request, Response};
+//!
+//! let vfs = Client::<Request, Response>::new(endpoint);
+//! let path = Bytes::from_slice(b"/todo.txt").unwrap();
+//! let Response::Opened { handle } = vfs.call(&Request::Open { path, flags: flags::READ })? else { .. };
+//! ```
+//!
+//! # Wire Format
+//! Little-endian: a tag byte, then the fields in order. Byte strings are a
+//! u16 length and the bytes. Paths are absolute and `/`-separated; paths
+//! and names are UTF-8.
+
+use crate::rpc::{Message, MAX_PAYLOAD};
+
+/// Longest path
+pub const MAX_PATH: usize = 192;
+
+/// Longest directory entry name
+pub const MAX_NAME: usize = 128;
+
+/// Most bytes read or written in one request
+pub const MAX_DATA: usize = 224;
+
+/// Handle of an open file
+pub type Handle = u32;
+
+/// How [`Request::Open`] opens a file
+pub mod flags {
+    /// Open for reading
+    pub const READ: u32 = 1 << 0;
+    /// Open for writing
+    pub const WRITE: u32 = 1 << 1;
+    /// Create the file if it does not exist
+    pub const CREATE: u32 = 1 << 2;
+    /// Empty the file on opening
+    pub const TRUNCATE: u32 = 1 << 3;
+    /// Write at the end of the file
+    pub const APPEND: u32 = 1 << 4;
+}
+
+/// Up to `N` bytes, held inline
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Bytes<const N: usize> {
+    len: usize,
+    buf: [u8; N],
+}
+
+impl<const N: usize> Bytes<N> {
+    /// Copy `bytes`, or `None` if there are more than `N`
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        let mut buf = [0; N];
+        buf.get_mut(..bytes.len())?.copy_from_slice(bytes);
+        Some(Self { len: bytes.len(), buf })
+    }
+
+    /// The bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for Bytes<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match core::str::from_utf8(self.as_slice()) {
+            Ok(s) => write!(f, "{:?}", s),
+            Err(_) => write!(f, "{:?}", self.as_slice()),
+        }
+    }
+}
+
+/// A request to the VFS service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Open the file at `path`, with [`flags`]: answered with
+    /// [`Response::Opened`]
+    Open { path: Bytes<MAX_PATH>, flags: u32 },
+    /// Read up to `len` bytes (at most [`MAX_DATA`]) at the file's
+    /// position: answered with [`Response::Data`], empty at the end of the
+    /// file
+    Read { handle: Handle, len: u32 },
+    /// Write `data` at the file's position: answered with
+    /// [`Response::Written`]
+    Write { handle: Handle, data: Bytes<MAX_DATA> },
+    /// Close the file: answered with [`Response::Closed`]
+    Close { handle: Handle },
+    /// The `index`-th entry of the directory at `path`: answered with
+    /// [`Response::Entry`], or [`Response::EndOfDir`] past the last one
+    ReadDir { path: Bytes<MAX_PATH>, index: u32 },
+}
+
+/// A response of the VFS service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    Opened { handle: Handle },
+    Data(Bytes<MAX_DATA>),
+    Written { len: u32 },
+    Closed,
+    Entry { kind: EntryKind, size: u64, name: Bytes<MAX_NAME> },
+    EndOfDir,
+    /// The request failed
+    Error(VfsError),
+}
+
+/// What a directory entry is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EntryKind {
+    File = 1,
+    Directory = 2,
+}
+
+/// Why a request failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum VfsError {
+    /// Nothing at the path
+    NotFound = 1,
+    /// Something is at the path already
+    Exists = 2,
+    /// A directory was needed
+    NotADirectory = 3,
+    /// A file was needed
+    IsADirectory = 4,
+    /// The caller may not do this, or the file is not open for it
+    PermissionDenied = 5,
+    /// The handle is not an open file of the caller's
+    BadHandle = 6,
+    /// The caller has the most files open it may
+    TooManyOpen = 7,
+    /// The filesystem is full
+    NoSpace = 8,
+    /// The path is not absolute, or not UTF-8
+    InvalidPath = 9,
+    /// The storage failed
+    Io = 10,
+}
+
+// Requests and responses must fit in a message with their largest data
+const _: () = assert!(1 + 4 + 2 + MAX_PATH <= MAX_PAYLOAD);
+const _: () = assert!(1 + 4 + 2 + MAX_DATA <= MAX_PAYLOAD);
+const _: () = assert!(1 + 1 + 8 + 2 + MAX_NAME <= MAX_PAYLOAD);
+
+impl Message for Request {
+    fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut w = Writer { buf, len: 0 };
+        match self {
+            Request::Open { path, flags } => {
+                w.u8(1)?;
+                w.u32(*flags)?;
+                w.bytes(path.as_slice())?;
+            }
+            Request::Read { handle, len } => {
+                w.u8(2)?;
+                w.u32(*handle)?;
+                w.u32(*len)?;
+            }
+            Request::Write { handle, data } => {
+                w.u8(3)?;
+                w.u32(*handle)?;
+                w.bytes(data.as_slice())?;
+            }
+            Request::Close { handle } => {
+                w.u8(4)?;
+                w.u32(*handle)?;
+            }
+            Request::ReadDir { path, index } => {
+                w.u8(5)?;
+                w.u32(*index)?;
+                w.bytes(path.as_slice())?;
+            }
+        }
+        Some(w.len)
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut r = Reader { bytes };
+        let request = match r.u8()? {
+            1 => Request::Open { flags: r.u32()?, path: r.bytes()? },
+            2 => Request::Read { handle: r.u32()?, len: r.u32()? },
+            3 => Request::Write { handle: r.u32()?, data: r.bytes()? },
+            4 => Request::Close { handle: r.u32()? },
+            5 => Request::ReadDir { index: r.u32()?, path: r.bytes()? },
+            _ => return None,
+        };
+        r.end(request)
+    }
+}
+
+impl Message for Response {
+    fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut w = Writer { buf, len: 0 };
+        match self {
+            Response::Opened { handle } => {
+                w.u8(1)?;
+                w.u32(*handle)?;
+            }
+            Response::Data(data) => {
+                w.u8(2)?;
+                w.bytes(data.as_slice())?;
+            }
+            Response::Written { len } => {
+                w.u8(3)?;
+                w.u32(*len)?;
+            }
+            Response::Closed => w.u8(4)?,
+            Response::Entry { kind, size, name } => {
+                w.u8(5)?;
+                w.u8(*kind as u8)?;
+                w.u64(*size)?;
+                w.bytes(name.as_slice())?;
+            }
+            Response::EndOfDir => w.u8(6)?,
+            Response::Error(error) => {
+                w.u8(0xFF)?;
+                w.u8(*error as u8)?;
+            }
+        }
+        Some(w.len)
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut r = Reader { bytes };
+        let response = match r.u8()? {
+            1 => Response::Opened { handle: r.u32()? },
+            2 => Response::Data(r.bytes()?),
+            3 => Response::Written { len: r.u32()? },
+            4 => Response::Closed,
+            5 => Response::Entry { kind: EntryKind::from_u8(r.u8()?)?, size: r.u64()?, name: r.bytes()? },
+            6 => Response::EndOfDir,
+            0xFF => Response::Error(VfsError::from_u8(r.u8()?)?),
+            _ => return None,
+        };
+        r.end(response)
+    }
+}
+
+impl EntryKind {
+    fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(EntryKind::File),
+            2 => Some(EntryKind::Directory),
+            _ => None,
+        }
+    }
+}
+
+impl VfsError {
+    fn from_u8(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => VfsError::NotFound,
+            2 => VfsError::Exists,
+            3 => VfsError::NotADirectory,
+            4 => VfsError::IsADirectory,
+            5 => VfsError::PermissionDenied,
+            6 => VfsError::BadHandle,
+            7 => VfsError::TooManyOpen,
+            8 => VfsError::NoSpace,
+            9 => VfsError::InvalidPath,
+            10 => VfsError::Io,
+            _ => return None,
+        })
+    }
+}
+
+/// Appends fields to a message; `None` once it is full
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) -> Option<()> {
+        self.buf.get_mut(self.len..self.len + bytes.len())?.copy_from_slice(bytes);
+        self.len += bytes.len();
+        Some(())
+    }
+
+    fn u8(&mut self, value: u8) -> Option<()> {
+        self.put(&[value])
+    }
+
+    fn u32(&mut self, value: u32) -> Option<()> {
+        self.put(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> Option<()> {
+        self.put(&value.to_le_bytes())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Option<()> {
+        self.put(&u16::try_from(bytes.len()).ok()?.to_le_bytes())?;
+        self.put(bytes)
+    }
+}
+
+/// Takes fields off a message; `None` once it runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.bytes.split_first_chunk()?;
+        self.bytes = rest;
+        Some(*head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[b]| b)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Option<Bytes<N>> {
+        let len = u16::from_le_bytes(self.take()?) as usize;
+        let bytes = self.bytes.get(..len)?;
+        self.bytes = &self.bytes[len..];
+        Bytes::from_slice(bytes)
+    }
+
+    /// `message`, if it used up all the bytes
+    fn end<T>(self, message: T) -> Option<T> {
+        self.bytes.is_empty().then_some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<M: Message + PartialEq + core::fmt::Debug>(message: M) {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let len = message.encode(&mut buf).unwrap();
+        assert_eq!(M::decode(&buf[..len]), Some(message));
+    }
+
+    #[test]
+    fn test_messages_round_trip() {
+        let path = Bytes::from_slice(b"/data/todo.txt").unwrap();
+        round_trip(Request::Open { path, flags: flags::WRITE | flags::CREATE });
+        round_trip(Request::Read { handle: 3, len: MAX_DATA as u32 });
+        round_trip(Request::Write { handle: 3, data: Bytes::from_slice(&[0xAB; MAX_DATA]).unwrap() });
+        round_trip(Request::Close { handle: 3 });
+        round_trip(Request::ReadDir { path, index: 7 });
+
+        round_trip(Response::Opened { handle: 3 });
+        round_trip(Response::Data(Bytes::from_slice(b"").unwrap()));
+        round_trip(Response::Written { len: 12 });
+        round_trip(Response::Closed);
+        let name = Bytes::from_slice("notes é".as_bytes()).unwrap();
+        round_trip(Response::Entry { kind: EntryKind::Directory, size: 1 << 40, name });
+        round_trip(Response::EndOfDir);
+        round_trip(Response::Error(VfsError::TooManyOpen));
+    }
+
+    #[test]
+    fn test_malformed_messages_do_not_decode() {
+        let mut buf = [0u8; MAX_PAYLOAD];
+        let len = Request::Close { handle: 1 }.encode(&mut buf).unwrap();
+        // Cut short, with bytes left over, or of an unknown tag
+        assert_eq!(Request::decode(&buf[..len - 1]), None);
+        assert_eq!(Request::decode(&buf[..len + 1]), None);
+        assert_eq!(Request::decode(&[9]), None);
+        // A string longer than its type holds
+        let mut long = [0u8; 1 + 4 + 2 + MAX_PATH + 1];
+        long[0] = 5;
+        long[5..7].copy_from_slice(&(MAX_PATH as u16 + 1).to_le_bytes());
+        assert_eq!(Request::decode(&long), None);
+        assert_eq!(Response::decode(&[0xFF, 0]), None);
+        assert!(Bytes::<4>::from_slice(b"12345").is_none());
+    }
+}
@@ -1,14 +1,18 @@
 //! Build script for root-task
 //!
 //! This script:
-//! 1. Locates components.toml at the project root
-//! 2. Embeds it as a compile-time constant for parsing
-//! 3. Sets up rebuild triggers when components.toml changes
+//! 1. Locates system.toml at the project root
+//! 2. Parses and validates it with kaal-compose
+//! 3. Generates the component registry into OUT_DIR, embedding the
+//!    component binaries that have been built
+//! 4. Sets up rebuild triggers when the manifest or a binary changes
 
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 
+use kaal_compose::SystemManifest;
+
 fn main() {
     // Get the project root (two levels up from runtime/root-task)
     let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
@@ -17,33 +21,38 @@ fn main() {
         .and_then(|p| p.parent())
         .expect("Failed to find project root");
 
-    // Path to components.toml at project root
-    let components_toml = project_root.join("components.toml");
+    // Path to system.toml at project root
+    let system_toml = project_root.join("system.toml");
 
-    // Verify it exists
-    if !components_toml.exists() {
-        panic!(
-            "components.toml not found at project root: {}",
-            components_toml.display()
-        );
-    }
+    // Tell cargo to rerun if system.toml changes
+    println!("cargo:rerun-if-changed={}", system_toml.display());
 
-    // Tell cargo to rerun if components.toml changes
-    println!("cargo:rerun-if-changed={}", components_toml.display());
+    let system = SystemManifest::load(&system_toml)
+        .unwrap_or_else(|e| panic!("{}: {e}", system_toml.display()));
 
-    // Read and validate it's valid UTF-8
-    let contents = fs::read_to_string(&components_toml)
-        .expect("Failed to read components.toml");
+    for component in &system.components {
+        for cap in component.unknown_capabilities() {
+            println!(
+                "cargo:warning=component `{}`: unknown capability `{cap}` grants nothing",
+                component.name
+            );
+        }
+    }
 
-    // Basic validation: check it contains at least one [[component]]
-    if !contents.contains("[[component]]") {
-        panic!("components.toml appears to be empty or invalid (no [[component]] sections found)");
+    // Regenerate once a missing binary is built, or a present one rebuilt;
+    // the others are read from the filesystem at runtime
+    for component in system.root_task_components().filter(|component| component.embed) {
+        println!("cargo:rerun-if-changed={}", component.binary_path(project_root).display());
     }
 
-    println!("cargo:rustc-env=COMPONENTS_TOML_PATH={}", components_toml.display());
-    println!("cargo:rustc-env=COMPONENTS_COUNT={}", contents.matches("[[component]]").count());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(
+        out_dir.join("component_registry.rs"),
+        system.root_task_registry(project_root),
+    )
+    .expect("Failed to write component registry");
 
     // Output location for developer convenience
-    eprintln!("🔍 Component manifest: {}", components_toml.display());
-    eprintln!("📦 Found {} component(s)", contents.matches("[[component]]").count());
+    eprintln!("🔍 System manifest: {}", system_toml.display());
+    eprintln!("📦 Found {} component(s)", system.components.len());
 }
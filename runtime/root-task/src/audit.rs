@@ -0,0 +1,98 @@
+//! Capability audit
+//!
+//! Once boot is done, the root task emits a checkpoint of what it has
+//! handed out, for reviewing the access control a composed system starts
+//! with: for each component it spawned, the kernel capability bits it runs
+//! with and the untyped memory it got, then every capability put in its
+//! CSpace, with slot and type. One record per line, `key=value` fields:
+//!
+//! ```text
+//! cap-audit begin
+//! cap-audit component=uart_driver pid=1099511701504 rights=0x40b untyped_kb=0
+//! cap-audit component=uart_driver pid=1099511701504 slot=1 type=irq_control
+//! cap-audit component=uart_driver pid=1099511701504 slot=4 type=control_notification
+//! cap-audit end components=1 caps=2
+//! ```
+//!
+//! The lines go to the log server as the root task's, when one is
+//! running, and to the console otherwise. Components another component
+//! spawned are their spawner's to account for.
+
+use core::fmt::Write;
+
+use crate::component_loader::ComponentLoader;
+
+/// Name the root task's log messages carry
+const LOG_NAME: &str = "root_task";
+
+/// Log level of the lines (`kaal_sdk::log::Level::Info`)
+const LOG_LEVEL_INFO: u8 = 3;
+
+/// Longest line
+const MAX_LINE: usize = 160;
+
+/// Emit the capability audit
+pub unsafe fn checkpoint(loader: &ComponentLoader) {
+    let log = loader.log_endpoint();
+    let mut components = 0;
+    let mut caps = 0;
+
+    emit(log, format_args!("cap-audit begin"));
+    for delegated in loader.delegations() {
+        components += 1;
+        emit(log, format_args!(
+            "cap-audit component={} pid={} rights={:#x} untyped_kb={}",
+            delegated.name,
+            delegated.pid,
+            delegated.rights,
+            delegated.memory / 1024,
+        ));
+        for (slot, cap) in delegated.caps.iter().flatten() {
+            caps += 1;
+            emit(log, format_args!(
+                "cap-audit component={} pid={} slot={} type={}",
+                delegated.name,
+                delegated.pid,
+                slot,
+                cap.as_str(),
+            ));
+        }
+    }
+    emit(log, format_args!("cap-audit end components={components} caps={caps}"));
+}
+
+/// Send a line to the log server on `log`, or print it
+unsafe fn emit(log: Option<usize>, args: core::fmt::Arguments) {
+    // A log message: level, name length, name, text
+    let mut line = Line { buf: [0; 2 + LOG_NAME.len() + MAX_LINE], len: 0 };
+    line.buf[0] = LOG_LEVEL_INFO;
+    line.buf[1] = LOG_NAME.len() as u8;
+    line.buf[2..2 + LOG_NAME.len()].copy_from_slice(LOG_NAME.as_bytes());
+    line.len = 2 + LOG_NAME.len();
+    let _ = line.write_fmt(args);
+    let _ = line.write_str("\n");
+
+    let sent = log.is_some_and(|endpoint| crate::sys_send(endpoint, &line.buf[..line.len]) == 0);
+    if !sent {
+        let text = &line.buf[2 + LOG_NAME.len()..line.len];
+        crate::sys_print(core::str::from_utf8_unchecked(text));
+    }
+}
+
+/// A line being formatted, cut short at its buffer's end
+struct Line {
+    buf: [u8; 2 + LOG_NAME.len() + MAX_LINE],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut len = s.len().min(self.buf.len() - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
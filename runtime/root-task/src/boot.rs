@@ -0,0 +1,216 @@
+//! Boot requests from the init component
+//!
+//! Boot happens in two phases. First the root task sets up what only it
+//! can, the supervisor and the objects it hands out, and spawns a single
+//! component: the init component (`boot:init` in system.toml). Then the
+//! init component decides the rest, which components run, in what order
+//! and with how much untyped memory, and asks the root task to start them.
+//! The root task still does the spawning, as it holds the binaries, the
+//! capabilities and the supervisor; a system without an init component
+//! gets everything autostarted by the root task as before.
+//!
+//! The init component's boot endpoint (`BOOT_ENDPOINT_SLOT`) is the
+//! supervisor's fault endpoint. Requests are calls shorter than any fault
+//! message, which is how the supervisor tells them apart, and it answers
+//! them between faults; an init component that crashes is restarted like
+//! any other component, and can ask again. Components with `boot:control`
+//! get the same endpoint, to start and stop components after boot, as a
+//! system monitor does with applications.
+//!
+//! # Requests
+//!
+//! Little-endian, `kaal_sdk::boot` sends them:
+//!
+//! | Request | Reply |
+//! |---------|-------|
+//! | [`OP_LIST`], `u8` index | status, `u8` flags, `u8` name length, name |
+//! | [`OP_START`], `u32` untyped KiB, name | status, `u64` PID |
+//! | [`OP_DONE`] | status |
+//! | [`OP_STOP`], name | status, `u8` exited by itself |
+//!
+//! `OP_LIST` goes through the components the root task can start, by
+//! index, until [`STATUS_NOT_FOUND`]. `OP_DONE` ends boot: the root task
+//! emits its capability audit (see `audit`). `OP_STOP` stops a running
+//! component with `ComponentLoader::stop`, asking it to shut down before
+//! destroying it; the supervisor does not restart it.
+
+use crate::component_loader::{ComponentDescriptor, ComponentError, ComponentLoader, SpawnResult};
+
+/// List a component: its flags and name
+pub const OP_LIST: u8 = 1;
+
+/// Start a component
+pub const OP_START: u8 = 2;
+
+/// Boot is done
+pub const OP_DONE: u8 = 3;
+
+/// Stop a component
+pub const OP_STOP: u8 = 4;
+
+/// Done
+pub const STATUS_OK: u8 = 0;
+/// No such component
+pub const STATUS_NOT_FOUND: u8 = 1;
+/// It is running already
+pub const STATUS_ALREADY_RUNNING: u8 = 2;
+/// A component it depends on is not running yet
+pub const STATUS_DEPENDENCY_NOT_RUNNING: u8 = 3;
+/// Spawning it failed
+pub const STATUS_FAILED: u8 = 4;
+/// Not a request
+pub const STATUS_INVALID: u8 = 5;
+/// It is not running
+pub const STATUS_NOT_RUNNING: u8 = 6;
+
+/// `OP_LIST` flag: the component is autostart in system.toml
+pub const FLAG_AUTOSTART: u8 = 1 << 0;
+/// `OP_LIST` flag: the component is running
+pub const FLAG_RUNNING: u8 = 1 << 1;
+
+/// Longest component name a request or reply carries
+pub const MAX_NAME: usize = 32;
+
+/// Longest reply
+pub const MAX_REPLY: usize = 3 + MAX_NAME;
+
+/// What answering a request came to
+pub struct Handled {
+    /// Length of the reply
+    pub len: usize,
+    /// The component it started, for the caller to supervise
+    pub started: Option<(&'static ComponentDescriptor, SpawnResult)>,
+    /// The component it stopped, for the caller to stop supervising
+    pub stopped: Option<&'static ComponentDescriptor>,
+    /// Boot is done
+    pub done: bool,
+}
+
+/// Answer a request from the init component, writing the reply to `reply`
+pub unsafe fn handle(loader: &ComponentLoader, request: &[u8], reply: &mut [u8; MAX_REPLY]) -> Handled {
+    let (len, started) = match request {
+        [OP_LIST, index] => (list(loader, *index as usize, reply), None),
+        [OP_DONE] => {
+            reply[0] = STATUS_OK;
+            return Handled { len: 1, started: None, stopped: None, done: true };
+        }
+        [OP_STOP, name @ ..] if name.len() <= MAX_NAME => {
+            let (len, stopped) = match core::str::from_utf8(name) {
+                Ok(name) => stop(loader, name, reply),
+                Err(_) => {
+                    reply[0] = STATUS_INVALID;
+                    (1, None)
+                }
+            };
+            return Handled { len, started: None, stopped, done: false };
+        }
+        [OP_START, a, b, c, d, name @ ..] if name.len() <= MAX_NAME => {
+            let untyped_kb = u32::from_le_bytes([*a, *b, *c, *d]);
+            match core::str::from_utf8(name) {
+                Ok(name) => start(loader, name, untyped_kb, reply),
+                Err(_) => {
+                    reply[0] = STATUS_INVALID;
+                    (1, None)
+                }
+            }
+        }
+        _ => {
+            reply[0] = STATUS_INVALID;
+            (1, None)
+        }
+    };
+    Handled { len, started, stopped: None, done: false }
+}
+
+/// Describe the registry component at `index`
+fn list(loader: &ComponentLoader, index: usize, reply: &mut [u8; MAX_REPLY]) -> usize {
+    let Some(component) = loader.registry().components().get(index) else {
+        reply[0] = STATUS_NOT_FOUND;
+        return 1;
+    };
+    let mut flags = 0;
+    if component.autostart {
+        flags |= FLAG_AUTOSTART;
+    }
+    if loader.running(component.name).is_some() {
+        flags |= FLAG_RUNNING;
+    }
+    let name = &component.name.as_bytes()[..component.name.len().min(MAX_NAME)];
+
+    reply[0] = STATUS_OK;
+    reply[1] = flags;
+    reply[2] = name.len() as u8;
+    reply[3..3 + name.len()].copy_from_slice(name);
+    3 + name.len()
+}
+
+/// Start the component `name`
+unsafe fn start(
+    loader: &ComponentLoader,
+    name: &str,
+    untyped_kb: u32,
+    reply: &mut [u8; MAX_REPLY],
+) -> (usize, Option<(&'static ComponentDescriptor, SpawnResult)>) {
+    crate::sys_print("[boot] Starting ");
+    crate::sys_print(name);
+    crate::sys_print("\n");
+
+    let (status, started) = match loader.start(name, untyped_kb) {
+        Ok(spawn) => {
+            crate::sys_print("  → ");
+            crate::sys_print(name);
+            crate::sys_print(" (PID: ");
+            crate::print_number(spawn.pid);
+            crate::sys_print(")\n");
+            let descriptor = loader.registry().find(name);
+            (STATUS_OK, descriptor.map(|descriptor| (descriptor, spawn)))
+        }
+        Err(e) => {
+            crate::sys_print("  → ");
+            crate::sys_print(name);
+            crate::sys_print(" - ");
+            crate::sys_print(e.as_str());
+            crate::sys_print("\n");
+            let status = match e {
+                ComponentError::NotFound => STATUS_NOT_FOUND,
+                ComponentError::AlreadyRunning => STATUS_ALREADY_RUNNING,
+                ComponentError::DependencyNotRunning => STATUS_DEPENDENCY_NOT_RUNNING,
+                _ => STATUS_FAILED,
+            };
+            (status, None)
+        }
+    };
+
+    let pid = started.map_or(0, |(_, spawn)| spawn.pid as u64);
+    reply[0] = status;
+    reply[1..9].copy_from_slice(&pid.to_le_bytes());
+    (9, started)
+}
+
+/// Stop the component `name`
+unsafe fn stop(
+    loader: &ComponentLoader,
+    name: &str,
+    reply: &mut [u8; MAX_REPLY],
+) -> (usize, Option<&'static ComponentDescriptor>) {
+    match loader.stop(name) {
+        Ok(exited) => {
+            reply[0] = STATUS_OK;
+            reply[1] = exited as u8;
+            (2, loader.registry().find(name))
+        }
+        Err(e) => {
+            crate::sys_print("  → ");
+            crate::sys_print(name);
+            crate::sys_print(" - ");
+            crate::sys_print(e.as_str());
+            crate::sys_print("\n");
+            reply[0] = match e {
+                ComponentError::NotFound => STATUS_NOT_FOUND,
+                ComponentError::NotRunning => STATUS_NOT_RUNNING,
+                _ => STATUS_FAILED,
+            };
+            (1, None)
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Boot blocks for spawned components
+//!
+//! Each component we spawn gets a page with its arguments and environment
+//! from system.toml and a map of the capabilities we put in its CSpace.
+//! The kernel maps it read-only into the component when creating it
+//! (`PROCESS_CREATE_BOOT_BLOCK`) and starts the component with its address
+//! in x0, where `kaal_sdk::env` picks it up. We keep it mapped writable, so
+//! that capabilities handed over after the spawn are added to the map.
+//!
+//! The layout is `kaal_sdk::env`'s; the constants here mirror it.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Size of a boot block (`kaal_sdk::env::BOOT_BLOCK_SIZE`)
+pub const BOOT_BLOCK_SIZE: usize = 4096;
+
+/// First word of a boot block (`kaal_sdk::env::BOOT_BLOCK_MAGIC`)
+const BOOT_BLOCK_MAGIC: u32 = 0x4C41_414B;
+
+/// Layout version (`kaal_sdk::env::BOOT_BLOCK_VERSION`)
+const BOOT_BLOCK_VERSION: u32 = 1;
+
+/// Most entries in the capability map (`kaal_sdk::env::MAX_BOOT_CAPS`)
+const MAX_BOOT_CAPS: usize = 16;
+
+const ARGC_OFFSET: usize = 8;
+const ENVC_OFFSET: usize = 12;
+const CAPC_OFFSET: usize = 16;
+const STRINGS_LEN_OFFSET: usize = 20;
+const CAPS_OFFSET: usize = 64;
+const STRINGS_OFFSET: usize = CAPS_OFFSET + MAX_BOOT_CAPS * 8;
+
+/// Capabilities the capability map names (`kaal_sdk::env::InitialCap`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum InitialCap {
+    IrqControl = 1,
+    Untyped = 2,
+    ReadyNotification = 3,
+    ControlNotification = 4,
+    Log = 5,
+    LogServer = 6,
+    Boot = 7,
+    Heartbeat = 8,
+    VfsServer = 9,
+}
+
+impl InitialCap {
+    /// Name of the capability type in the capability audit
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InitialCap::IrqControl => "irq_control",
+            InitialCap::Untyped => "untyped",
+            InitialCap::ReadyNotification => "ready_notification",
+            InitialCap::ControlNotification => "control_notification",
+            InitialCap::Log => "log",
+            InitialCap::LogServer => "log_server",
+            InitialCap::Boot => "boot",
+            InitialCap::Heartbeat => "heartbeat",
+            InitialCap::VfsServer => "vfs_server",
+        }
+    }
+}
+
+/// A boot block page, mapped in our address space
+///
+/// Laid out as `SYS_PROCESS_CREATE` reads it at x12.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BootBlock {
+    /// Physical address, which the kernel maps into the component
+    pub phys: usize,
+    /// Where we have it mapped, which is also where the component gets it:
+    /// mappings come from one address range for all processes, so the
+    /// address is free in the component as well
+    pub virt: usize,
+}
+
+impl BootBlock {
+    /// Allocate a page and map it
+    pub unsafe fn create() -> Option<Self> {
+        let phys = crate::sys_memory_allocate(BOOT_BLOCK_SIZE);
+        if phys == usize::MAX {
+            return None;
+        }
+        let virt = crate::sys_memory_map(phys, BOOT_BLOCK_SIZE, 0x3); // RW
+        if virt == usize::MAX {
+            return None;
+        }
+        Some(Self { phys, virt })
+    }
+
+    /// Fill the block in for a new component, with an empty capability map
+    ///
+    /// `name` is the first argument. Returns false if the strings did not
+    /// all fit; those that did are kept.
+    pub unsafe fn write(&self, name: &str, args: &[&str], env: &[(&str, &str)]) -> bool {
+        core::ptr::write_bytes(self.virt as *mut u8, 0, BOOT_BLOCK_SIZE);
+
+        let mut strings = Strings { block: *self, len: 0 };
+        let mut argc = 0;
+        for arg in core::iter::once(&name).chain(args) {
+            if !strings.push(&[arg.as_bytes()]) {
+                break;
+            }
+            argc += 1;
+        }
+        let mut envc = 0;
+        if argc == args.len() + 1 {
+            for (key, value) in env {
+                if !strings.push(&[key.as_bytes(), b"=", value.as_bytes()]) {
+                    break;
+                }
+                envc += 1;
+            }
+        }
+
+        self.write_u32(0, BOOT_BLOCK_MAGIC);
+        self.write_u32(4, BOOT_BLOCK_VERSION);
+        self.write_u32(ARGC_OFFSET, argc as u32);
+        self.write_u32(ENVC_OFFSET, envc as u32);
+        self.write_u32(STRINGS_LEN_OFFSET, strings.len as u32);
+        argc == args.len() + 1 && envc == env.len()
+    }
+
+    /// Add a capability handed to the component to its capability map
+    pub unsafe fn add_cap(&self, cap: InitialCap, slot: usize) {
+        let capc = &*((self.virt + CAPC_OFFSET) as *const AtomicU32);
+        let count = capc.load(Ordering::Relaxed) as usize;
+        if count == MAX_BOOT_CAPS {
+            return;
+        }
+        self.write_u32(CAPS_OFFSET + count * 8, cap as u32);
+        self.write_u32(CAPS_OFFSET + count * 8 + 4, slot as u32);
+        // The component may be reading the map already
+        capc.store(count as u32 + 1, Ordering::Release);
+    }
+
+    unsafe fn write_u32(&self, offset: usize, value: u32) {
+        core::ptr::write_volatile((self.virt + offset) as *mut u32, value.to_le());
+    }
+}
+
+/// The string area of a boot block being written
+struct Strings {
+    block: BootBlock,
+    len: usize,
+}
+
+impl Strings {
+    /// Append the concatenation of `parts` and a NUL, if there is room
+    unsafe fn push(&mut self, parts: &[&[u8]]) -> bool {
+        let size: usize = parts.iter().map(|part| part.len()).sum::<usize>() + 1;
+        if STRINGS_OFFSET + self.len + size > BOOT_BLOCK_SIZE {
+            return false;
+        }
+        let mut at = (self.block.virt + STRINGS_OFFSET + self.len) as *mut u8;
+        for part in parts {
+            core::ptr::copy_nonoverlapping(part.as_ptr(), at, part.len());
+            at = at.add(part.len());
+        }
+        // The page was zeroed, so the NUL is there already
+        self.len += size;
+        true
+    }
+}
@@ -3,7 +3,10 @@
 //! This module provides integration between the root task and the capability broker,
 //! demonstrating how to use the broker's clean API instead of raw syscalls.
 
-use capability_broker::{CapabilityBroker, DeviceId};
+use capability_broker::{BrokerError, CapabilityBroker, DeviceId};
+
+/// Owner recorded for devices the root task requests for itself
+const ROOT_TASK_PID: usize = 0;
 
 /// Print helper for integration messages
 unsafe fn sys_print(msg: &str) {
@@ -101,7 +104,7 @@ pub unsafe fn test_capability_broker() {
 
     // Test 2: Request device through broker
     sys_print("\n[root_task] Test 2: Requesting UART0 device via broker...\n");
-    match broker.request_device(DeviceId::Uart(0)) {
+    match broker.request_device(DeviceId::Uart(0), ROOT_TASK_PID) {
         Ok(dev) => {
             sys_print("  ✓ UART0 device allocated:\n");
             sys_print("    MMIO base: 0x");
@@ -115,6 +118,14 @@ pub unsafe fn test_capability_broker() {
                 print_number(irq_cap);
                 sys_print("\n");
             }
+            match broker.request_device(DeviceId::Uart(0), ROOT_TASK_PID) {
+                Err(BrokerError::DeviceBusy) => sys_print("  ✓ Second request refused: device busy\n"),
+                _ => sys_print("  ✗ Second request was not refused\n"),
+            }
+            match broker.release_device(dev) {
+                Ok(()) => sys_print("  ✓ UART0 released, slots returned to the broker\n"),
+                Err(_) => sys_print("  ✗ UART0 release failed\n"),
+            }
         }
         Err(_) => {
             sys_print("  ✗ Device request failed\n");
@@ -142,7 +153,7 @@ pub unsafe fn test_capability_broker() {
     sys_print("\n[root_task] Test 4: Requesting multiple devices...\n");
 
     sys_print("  → Requesting RTC...\n");
-    match broker.request_device(DeviceId::Rtc) {
+    match broker.request_device(DeviceId::Rtc, ROOT_TASK_PID) {
         Ok(dev) => {
             sys_print("    ✓ RTC MMIO: 0x");
             print_hex(dev.mmio_base);
@@ -154,7 +165,7 @@ pub unsafe fn test_capability_broker() {
     }
 
     sys_print("  → Requesting Timer...\n");
-    match broker.request_device(DeviceId::Timer) {
+    match broker.request_device(DeviceId::Timer, ROOT_TASK_PID) {
         Ok(dev) => {
             sys_print("    ✓ Timer MMIO: 0x");
             print_hex(dev.mmio_base);
@@ -295,19 +306,19 @@ unsafe fn test_ipc_syscalls() {
 
     // Test 4: IPC Reply syscall
     sys_print("\n[root_task] Test 4: IPC Reply syscall\n");
-    sys_print("  → Calling sys_reply(reply_cap=200, msg_ptr=0x3000)...\n");
+    sys_print("  → Calling sys_reply(msg_ptr, msg_len=5)...\n");
 
     let reply_msg = b"REPLY";
     let reply_result: u64;
     core::arch::asm!(
         "mov x8, {syscall_num}",
-        "mov x0, {reply_cap}",
-        "mov x1, {msg_ptr}",
+        "mov x0, {msg_ptr}",
+        "mov x1, {msg_len}",
         "svc #0",
         "mov {result}, x0",
         syscall_num = in(reg) SYS_REPLY,
-        reply_cap = in(reg) 200u64,
         msg_ptr = in(reg) reply_msg.as_ptr() as u64,
+        msg_len = in(reg) reply_msg.len() as u64,
         result = out(reg) reply_result,
         out("x8") _,
         out("x0") _,
@@ -1,21 +1,27 @@
 //! Component Discovery and Loading
 //!
 //! This module handles:
-//! - Parsing component manifests from PROJECT_ROOT/components.toml
+//! - The component registry, generated from PROJECT_ROOT/system.toml
 //! - Loading component binaries
-//! - Spawning components with proper capabilities
+//! - Spawning components with proper capabilities, after the components they
+//!   depend on have reported ready
+//! - Starting components at the init component's request (see `boot`)
 //! - Component lifecycle management
 //!
-//! The components.toml manifest is located at the project root for developer convenience,
-//! and is embedded into the binary at build time via build.rs.
+//! The system.toml manifest is located at the project root for developer convenience.
+//! build.rs parses it with kaal-compose and generates [`SYSTEM_COMPONENTS`] from it,
+//! embedding the component binaries, so the manifest is all there is to edit.
 
 use core::str;
 
-/// Components manifest embedded at build time from PROJECT_ROOT/components.toml
+use crate::boot_block::{BootBlock, InitialCap};
+
+/// Components the root task spawns, in system.toml order
 ///
-/// This allows developers to configure components at the project root without
-/// dealing with runtime/ or kernel/ directories.
-const COMPONENTS_TOML: &str = include_str!(env!("COMPONENTS_TOML_PATH"));
+/// Generated by build.rs from system.toml; components another component spawns
+/// (`spawned_by`) are left out.
+pub static SYSTEM_COMPONENTS: &[ComponentDescriptor] =
+    include!(concat!(env!("OUT_DIR"), "/component_registry.rs"));
 
 /// Component type classification
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,20 +34,76 @@ pub enum ComponentType {
     Application,
 }
 
-/// Component capability specification
+/// End of a channel a component is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelRole {
+    /// Sets the channel up and writes to it
+    Producer,
+    /// Joins the channel and reads from it
+    Consumer,
+}
+
+/// A channel a component declares in the manifest
 #[derive(Debug, Clone, Copy)]
-pub enum ComponentCapability {
-    /// Memory mapping: (physical_addr, size)
-    MemoryMap { phys_addr: usize, size: usize },
-    /// Interrupt capability
-    Interrupt { irq: u32 },
-    /// IPC endpoint access
-    IpcEndpoint { name: &'static str },
-    /// Process management
-    ProcessCreate,
-    ProcessDestroy,
-    /// General memory allocation
-    MemoryAllocate,
+pub struct ChannelDescriptor {
+    /// Name the channel is registered under with the broker
+    pub name: &'static str,
+    /// Which end the component is
+    pub role: ChannelRole,
+}
+
+/// When the supervisor restarts a component that crashed or exited
+///
+/// The backoff is the delay before the first restart; it doubles with each
+/// restart after that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave it down
+    Never,
+    /// Restart it if it crashed or exited with a nonzero code, at most
+    /// `max_restarts` times
+    OnFailure { max_restarts: u32, backoff_ms: u32 },
+    /// Restart it however it ended
+    Always { backoff_ms: u32 },
+}
+
+/// What the supervisor does with a component that stops signaling
+/// heartbeats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HangAction {
+    /// Report it, with what it was doing, and leave it running
+    Dump,
+    /// Report it, destroy it and apply its restart policy, as for a crash
+    Restart,
+}
+
+/// Hang detection for a component: it signals a heartbeat at least every
+/// `interval_ms`, and counts as hung once it has missed `max_missed` in a
+/// row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub interval_ms: u32,
+    pub max_missed: u32,
+    pub on_hang: HangAction,
+}
+
+/// Ceilings on what the loader gives a component, so that a slip in the
+/// manifest cannot hand an experimental component half the system
+///
+/// None is no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Most untyped memory delegated to it, in KiB
+    pub max_memory_kb: Option<u32>,
+    /// Most capabilities delegated into its CSpace
+    pub max_cap_slots: Option<u32>,
+    /// Highest priority it may run at (the lowest priority number)
+    pub priority_ceiling: Option<u8>,
+}
+
+impl ResourceLimits {
+    /// No limits
+    pub const NONE: Self = Self { max_memory_kb: None, max_cap_slots: None, priority_ceiling: None };
 }
 
 /// Result of spawning a component
@@ -66,6 +128,437 @@ pub struct SpawnResult {
     pub pid: usize,
 }
 
+/// Maximum number of distinct binaries kept loaded for sharing
+const MAX_LOADED_IMAGES: usize = 16;
+
+/// A binary loaded into physical memory, shared copy-on-write by its processes
+#[derive(Clone, Copy)]
+struct LoadedImage {
+    /// Binary name
+    binary: &'static str,
+    /// Physical address of the image
+    phys: usize,
+    /// Image size in bytes
+    size: usize,
+}
+
+/// Images loaded so far
+static mut LOADED_IMAGES: [Option<LoadedImage>; MAX_LOADED_IMAGES] = [None; MAX_LOADED_IMAGES];
+
+/// Where binaries not embedded in the root task are read from
+///
+/// At boot this is the VFS service (`vfs_source`); see
+/// [`ComponentLoader::set_binary_source`].
+pub trait BinarySource {
+    /// Size in bytes of the binary named `binary`, if there is one
+    fn len(&self, binary: &str) -> Option<usize>;
+
+    /// Read the whole binary into `buf`, which is [`len`](Self::len)
+    /// bytes; returns whether it could
+    fn read(&self, binary: &str, buf: &mut [u8]) -> bool;
+}
+
+/// Source of the binaries not embedded, once there is one
+static mut BINARY_SOURCE: Option<&'static dyn BinarySource> = None;
+
+/// Binaries read from the [`BinarySource`] so far, kept for later spawns
+static mut READ_BINARIES: [Option<(&'static str, &'static [u8])>; MAX_LOADED_IMAGES] = [None; MAX_LOADED_IMAGES];
+
+/// Frames of a binary that could not be read, as (physical address, size),
+/// kept for the next binary read
+static mut SPARE_BINARY_FRAMES: Option<(usize, usize)> = None;
+
+/// Take at least `size` bytes of frames to read a binary into, returning
+/// their physical address and size
+///
+/// The spare frames if they are big enough, new ones otherwise.
+unsafe fn alloc_binary_frames(size: usize) -> Option<(usize, usize)> {
+    if let Some((phys, spare)) = SPARE_BINARY_FRAMES.filter(|&(_, spare)| spare >= size) {
+        SPARE_BINARY_FRAMES = None;
+        return Some((phys, spare));
+    }
+    let phys = crate::sys_memory_allocate(size);
+    (phys != usize::MAX).then_some((phys, size))
+}
+
+/// Give back frames [`alloc_binary_frames`] took
+///
+/// The kernel cannot take frames back once allocated, so we keep the
+/// largest block given back for the next binary, rather than allocating
+/// again for every binary that fails to read.
+unsafe fn free_binary_frames(phys: usize, size: usize) {
+    if SPARE_BINARY_FRAMES.is_none_or(|(_, spare)| spare < size) {
+        SPARE_BINARY_FRAMES = Some((phys, size));
+    }
+}
+
+/// Maximum number of freed TCB capability slots kept for reuse
+const MAX_FREE_TCB_SLOTS: usize = 8;
+
+/// Next never-used TCB capability slot in our CSpace
+static mut NEXT_CAP_SLOT: usize = 10;
+
+/// TCB capability slots of destroyed components, reused before new ones so
+/// that restarting components does not use up our CSpace
+static mut FREE_TCB_SLOTS: [Option<usize>; MAX_FREE_TCB_SLOTS] = [None; MAX_FREE_TCB_SLOTS];
+
+/// Take a slot for a new component's TCB capability
+unsafe fn alloc_tcb_slot() -> usize {
+    let free = &mut *core::ptr::addr_of_mut!(FREE_TCB_SLOTS);
+    if let Some(slot) = free.iter_mut().find_map(|slot| slot.take()) {
+        return slot;
+    }
+    let slot = NEXT_CAP_SLOT;
+    NEXT_CAP_SLOT += 1;
+    slot
+}
+
+/// Give back the slot of a deleted TCB capability
+unsafe fn free_tcb_slot(slot: usize) {
+    let free = &mut *core::ptr::addr_of_mut!(FREE_TCB_SLOTS);
+    if let Some(entry) = free.iter_mut().find(|entry| entry.is_none()) {
+        *entry = Some(slot);
+    }
+}
+
+/// Slot left empty by a failed untyped delegation, which the next one takes
+/// instead of allocating another
+static mut SPARE_UNTYPED_SLOT: Option<usize> = None;
+
+/// Our capability to the VFS endpoint, while the VFS service is running to
+/// receive on it
+pub fn vfs_endpoint() -> Option<usize> {
+    let (slot, _) = unsafe { VFS_ENDPOINT }?;
+    let server = unsafe { VFS_SERVER }?;
+    let serving = unsafe { (*core::ptr::addr_of!(RUNNING))[server].is_some() };
+    serving.then_some(slot)
+}
+
+/// Slot in a component's CSpace holding the notification it signals once it
+/// has initialized (`kaal_sdk::component::READY_NOTIFICATION_SLOT`)
+pub const READY_NOTIFICATION_SLOT: usize = 3;
+
+/// Signal bit of a component reporting ready
+const READY_BADGE: usize = 1 << 0;
+
+/// Signal bit of the timer giving up on it
+const READY_TIMEOUT_BADGE: usize = 1 << 1;
+
+/// How long startup waits for a component to report ready
+const READY_TIMEOUT_MS: usize = 5_000;
+
+/// Slot in a component's CSpace holding the notification we signal to ask it
+/// to shut down (`kaal_sdk::component::CONTROL_NOTIFICATION_SLOT`)
+pub const CONTROL_NOTIFICATION_SLOT: usize = 4;
+
+/// Signal bit of a shutdown request
+const SHUTDOWN_BADGE: usize = 1 << 0;
+
+/// Slot in a component's CSpace holding the log endpoint, which the log
+/// server receives on and the others send their output to (`kaal_sdk::log`)
+pub const LOG_ENDPOINT_SLOT: usize = 5;
+
+/// `log:serve` capability bit: the component is the log server
+const LOG_SERVER_BIT: u64 = 1 << 11;
+
+/// Slot in the init component's CSpace holding the endpoint it asks us to
+/// start components on (`kaal_sdk::boot`)
+pub const BOOT_ENDPOINT_SLOT: usize = 6;
+
+/// `boot:init` capability bit: the component is the init component
+const BOOT_INIT_BIT: u64 = 1 << 12;
+
+/// `boot:control` capability bit: the component starts and stops components
+/// on the boot endpoint too
+const BOOT_CONTROL_BIT: u64 = 1 << 13;
+
+/// Slot in a component's CSpace holding the notification it signals its
+/// heartbeats on (`kaal_sdk::component::HEARTBEAT_NOTIFICATION_SLOT`)
+pub const HEARTBEAT_NOTIFICATION_SLOT: usize = 7;
+
+/// Slot in the VFS service's CSpace holding the endpoint it receives on,
+/// which we read binaries through (`kaal_sdk::env::InitialCap::VfsServer`)
+pub const VFS_ENDPOINT_SLOT: usize = 8;
+
+/// `vfs:serve` capability bit: the component is the VFS service
+const VFS_SERVER_BIT: u64 = 1 << 14;
+
+/// Slot in a component's CSpace the untyped memory we give it goes to
+pub const UNTYPED_SLOT: usize = 10;
+
+/// How long [`ComponentLoader::stop`] lets a component take to exit
+const STOP_TIMEOUT_MS: usize = 2_000;
+
+/// How often [`ComponentLoader::stop`] checks whether it has
+const STOP_POLL_MS: usize = 10;
+
+/// Most registry components the loader keeps track of
+const MAX_COMPONENTS: usize = 32;
+
+/// Our UntypedMemory capability
+const ROOT_UNTYPED_SLOT: usize = 1;
+
+/// UntypedMemory, Endpoint and Notification object types for sys_retype
+/// and sys_cap_insert_into
+const CAP_TYPE_UNTYPED: usize = 1;
+const CAP_TYPE_ENDPOINT: usize = 2;
+const CAP_TYPE_NOTIFICATION: usize = 3;
+
+/// Untyped memory kept for the objects we hand to components: room for a
+/// readiness, a control and a heartbeat notification per component, and the
+/// log, boot and VFS endpoints
+const OBJECT_UNTYPED_SIZE_BITS: usize = 19;
+
+/// A page per object
+const OBJECT_SIZE_BITS: usize = 12;
+
+/// Slot of the untyped memory handed objects are made from, once set aside
+static mut OBJECT_UNTYPED: Option<usize> = None;
+
+/// Our capability to the log endpoint and its physical address, once the
+/// log server is spawned
+static mut LOG_ENDPOINT: Option<(usize, usize)> = None;
+
+/// Our capability to the VFS endpoint and its physical address, once the
+/// VFS service is spawned
+static mut VFS_ENDPOINT: Option<(usize, usize)> = None;
+
+/// Registry index of the VFS service
+static mut VFS_SERVER: Option<usize> = None;
+
+/// Physical address of the endpoint the init component's requests come on
+static mut BOOT_ENDPOINT: Option<usize> = None;
+
+/// Readiness notification of each registry component others depend on, by
+/// registry index, made when [`ComponentLoader::start`] first starts it
+static mut READINESS: [Option<Readiness>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+
+/// Running instance of each registry component, by registry index
+static mut RUNNING: [Option<SpawnResult>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+
+/// Control notification of each registry component, by registry index, kept
+/// for the instances that replace it
+static mut CONTROL: [Option<HandedNotification>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+
+/// Heartbeat notification of each registry component with hang detection,
+/// by registry index, kept for the instances that replace it
+static mut HEARTBEATS: [Option<HandedNotification>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+
+/// Notification and timer [`ComponentLoader::stop`] sleeps on, once made
+static mut STOP_TIMER: Option<(usize, usize)> = None;
+
+/// What the loader has delegated to each running component, checked against
+/// its [`ResourceLimits`]
+static mut DELEGATED: [Option<Delegated>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+
+/// Boot block of each registry component, by registry index, kept for the
+/// instances that replace it
+static mut BOOT_BLOCKS: [Option<BootBlock>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+
+/// Most capabilities the loader records for one process
+pub const MAX_RECORDED_CAPS: usize = 8;
+
+/// What the loader has delegated to one process
+#[derive(Clone, Copy)]
+pub struct Delegated {
+    /// Component it is an instance of
+    pub name: &'static str,
+    pub pid: usize,
+    /// Kernel capability bits it was created with (`capabilities_bitmask`)
+    pub rights: u64,
+    /// Untyped memory, in bytes
+    pub memory: usize,
+    /// Capabilities put in its CSpace
+    pub cap_slots: u32,
+    /// Those capabilities, as (slot, type)
+    pub caps: [Option<(usize, InitialCap)>; MAX_RECORDED_CAPS],
+    /// Where its capability map is
+    boot_block: Option<BootBlock>,
+}
+
+/// What has been delegated to the process `pid` so far
+unsafe fn delegated(pid: usize) -> Delegated {
+    (*core::ptr::addr_of!(DELEGATED))
+        .iter()
+        .flatten()
+        .find(|delegated| delegated.pid == pid)
+        .copied()
+        .unwrap_or(Delegated {
+            name: "",
+            pid,
+            rights: 0,
+            memory: 0,
+            cap_slots: 0,
+            caps: [None; MAX_RECORDED_CAPS],
+            boot_block: None,
+        })
+}
+
+/// The record of what has been delegated to the process `pid`, made if
+/// there is none
+///
+/// None with every entry taken: MAX_COMPONENTS bounds the running
+/// instances, so only a leak gets there, and the process goes untracked.
+unsafe fn delegated_mut(pid: usize) -> Option<&'static mut Delegated> {
+    let all = &mut *core::ptr::addr_of_mut!(DELEGATED);
+    let index = all
+        .iter()
+        .position(|entry| entry.is_some_and(|entry| entry.pid == pid))
+        .or_else(|| all.iter().position(|entry| entry.is_none()))?;
+    Some(all[index].insert(delegated(pid)))
+}
+
+/// Record the delegation of a capability to the process `pid`: its
+/// `memory` bytes of untyped memory, if any, and where it went
+unsafe fn charge(pid: usize, memory: usize, slot: usize, initial: InitialCap) {
+    let Some(used) = delegated_mut(pid) else {
+        return;
+    };
+    used.memory += memory;
+    used.cap_slots += 1;
+    if let Some(entry) = used.caps.iter_mut().find(|entry| entry.is_none()) {
+        *entry = Some((slot, initial));
+    }
+    if let Some(boot_block) = used.boot_block {
+        boot_block.add_cap(initial, slot);
+    }
+}
+
+/// Put a capability in a spawned component's CSpace, if that keeps it
+/// within its `max_cap_slots`, and list it in its capability map
+unsafe fn delegate_cap(
+    desc: &ComponentDescriptor,
+    spawn: &SpawnResult,
+    target_slot: usize,
+    cap_type: usize,
+    paddr: usize,
+    initial: InitialCap,
+) -> bool {
+    let within = desc.limits.max_cap_slots.is_none_or(|max| delegated(spawn.pid).cap_slots < max);
+    if !within {
+        crate::sys_print("[component_loader] ✗ ");
+        crate::sys_print(desc.name);
+        crate::sys_print(" is at its capability limit\n");
+        return false;
+    }
+    if crate::sys_cap_insert_into(spawn.tcb_cap_slot, target_slot, cap_type, paddr) != 0 {
+        return false;
+    }
+    charge(spawn.pid, 0, target_slot, initial);
+    true
+}
+
+/// Make an object to hand to components, returning our capability to it
+/// and its physical address, which sys_cap_insert_into takes
+///
+/// We only learn an object's address by retyping it ourselves. The first
+/// call sets untyped memory aside for them, which must happen before the
+/// rest of our untyped memory is handed out.
+unsafe fn create_object(cap_type: usize) -> Option<(usize, usize)> {
+    let pool = match OBJECT_UNTYPED {
+        Some(pool) => pool,
+        None => {
+            let pool = crate::sys_cap_allocate();
+            if pool == usize::MAX
+                || crate::sys_retype(ROOT_UNTYPED_SLOT, CAP_TYPE_UNTYPED, OBJECT_UNTYPED_SIZE_BITS, 0, pool)
+                    == usize::MAX
+            {
+                return None;
+            }
+            OBJECT_UNTYPED = Some(pool);
+            pool
+        }
+    };
+
+    let slot = crate::sys_cap_allocate();
+    if slot == usize::MAX {
+        return None;
+    }
+    let paddr = crate::sys_retype(pool, cap_type, OBJECT_SIZE_BITS, 0, slot);
+    if paddr == usize::MAX {
+        return None;
+    }
+    Some((slot, paddr))
+}
+
+/// Make an endpoint to hand to components, returning our capability to it
+/// and its physical address
+///
+/// Like every handed object, the first one must be made before our untyped
+/// memory is handed out.
+pub unsafe fn create_endpoint() -> Option<(usize, usize)> {
+    create_object(CAP_TYPE_ENDPOINT)
+}
+
+/// A notification we can hand to components
+#[derive(Clone, Copy)]
+struct HandedNotification {
+    /// Our capability to it
+    slot: usize,
+    /// Its physical address, which sys_cap_insert_into takes
+    paddr: usize,
+}
+
+impl HandedNotification {
+    /// Make a notification from the untyped memory set aside for them
+    unsafe fn create() -> Option<Self> {
+        let (slot, paddr) = create_object(CAP_TYPE_NOTIFICATION)?;
+        Some(Self { slot, paddr })
+    }
+
+    /// Put a capability to it in a spawned component's CSpace
+    unsafe fn hand_to(
+        &self,
+        desc: &ComponentDescriptor,
+        spawn: &SpawnResult,
+        target_slot: usize,
+        initial: InitialCap,
+    ) -> bool {
+        delegate_cap(desc, spawn, target_slot, CAP_TYPE_NOTIFICATION, self.paddr, initial)
+    }
+}
+
+/// Block for `ms` milliseconds
+unsafe fn sleep_ms(ms: usize) {
+    let (notification, timer) = match STOP_TIMER {
+        Some(sleeper) => sleeper,
+        None => {
+            let notification = crate::sys_notification_create();
+            if notification == usize::MAX {
+                return;
+            }
+            let timer = crate::sys_timer_create(notification, 1);
+            if timer == usize::MAX {
+                return;
+            }
+            STOP_TIMER = Some((notification, timer));
+            (notification, timer)
+        }
+    };
+    if crate::sys_timer_set(timer, ms * 1000) == 0 {
+        crate::sys_wait(notification);
+    }
+}
+
+/// Whether the process `pid` has stopped running: exited, or blocked for
+/// good reporting its exit or a crash to its fault handler
+unsafe fn has_exited(pid: usize) -> bool {
+    const THREAD_INACTIVE: u64 = 0;
+    const THREAD_BLOCKED_ON_FAULT: u64 = 7;
+
+    let mut stats = [0u64; 8];
+    for index in 0.. {
+        if crate::sys_thread_stats(index, &mut stats) != 0 {
+            return true;
+        }
+        // tid, priority, state, ...
+        if stats[0] as usize == pid {
+            return matches!(stats[2], THREAD_INACTIVE | THREAD_BLOCKED_ON_FAULT);
+        }
+    }
+    true
+}
+
 /// Component descriptor from manifest
 #[derive(Debug)]
 pub struct ComponentDescriptor {
@@ -82,9 +575,26 @@ pub struct ComponentDescriptor {
     /// Required capabilities (as strings)
     pub capabilities: &'static [&'static str],
     /// Required capabilities (as bitmask)
-    /// Bit 0: CAP_MEMORY, Bit 1: CAP_PROCESS, Bit 2: CAP_IPC, Bit 3: CAP_CAPS
+    /// Bit 0: CAP_MEMORY, Bit 1: CAP_PROCESS, Bit 2: CAP_IPC, Bit 3: CAP_CAPS, Bit 4: CAP_DOMAIN,
+    /// Bit 5: CAP_KLOG,
+    /// Bit 10: IRQControl delegation
     pub capabilities_bitmask: u64,
-    /// Embedded binary data (set at compile time)
+    /// Channels the component produces or consumes
+    pub channels: &'static [ChannelDescriptor],
+    /// What the supervisor does when it crashes or exits
+    pub restart: RestartPolicy,
+    /// How the supervisor tells it has hung, if it watches it
+    pub heartbeat: Option<Heartbeat>,
+    /// Components that must have reported ready before it is spawned
+    pub depends_on: &'static [&'static str],
+    /// Most the loader may give it
+    pub limits: ResourceLimits,
+    /// Arguments it is spawned with, after its name
+    pub args: &'static [&'static str],
+    /// Environment variables it is spawned with, as (key, value)
+    pub env: &'static [(&'static str, &'static str)],
+    /// Embedded binary data (set at compile time); None reads the binary
+    /// from the loader's [`BinarySource`]
     pub binary_data: Option<&'static [u8]>,
 }
 
@@ -103,6 +613,13 @@ impl ComponentDescriptor {
             autostart: false,
             capabilities: &[],
             capabilities_bitmask: 0,
+            channels: &[],
+            restart: RestartPolicy::Never,
+            heartbeat: None,
+            depends_on: &[],
+            limits: ResourceLimits::NONE,
+            args: &[],
+            env: &[],
             binary_data: None,
         }
     }
@@ -125,6 +642,48 @@ impl ComponentDescriptor {
         self
     }
 
+    /// Set channels
+    pub const fn with_channels(mut self, channels: &'static [ChannelDescriptor]) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Set restart policy
+    pub const fn with_restart(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// Set hang detection
+    pub const fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Set the components it waits for
+    pub const fn with_depends_on(mut self, depends_on: &'static [&'static str]) -> Self {
+        self.depends_on = depends_on;
+        self
+    }
+
+    /// Set resource limits
+    pub const fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set arguments
+    pub const fn with_args(mut self, args: &'static [&'static str]) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Set environment variables
+    pub const fn with_env(mut self, env: &'static [(&'static str, &'static str)]) -> Self {
+        self.env = env;
+        self
+    }
+
     /// Set binary data
     pub const fn with_binary(mut self, data: &'static [u8]) -> Self {
         self.binary_data = Some(data);
@@ -134,8 +693,8 @@ impl ComponentDescriptor {
 
 /// Component registry - statically defined components
 ///
-/// In a future version, this could be generated from components.toml at build time.
-/// For now, we define components programmatically.
+/// Usually [`SYSTEM_COMPONENTS`], generated from system.toml; tests and bring-up
+/// code can define components programmatically instead.
 pub struct ComponentRegistry {
     components: &'static [ComponentDescriptor],
 }
@@ -151,9 +710,10 @@ impl ComponentRegistry {
         self.components
     }
 
-    /// Get components that should autostart
-    pub fn autostart_components(&self) -> impl Iterator<Item = &ComponentDescriptor> {
-        self.components.iter().filter(|c| c.autostart)
+    /// The init component (`boot:init`), which starts the others, if there
+    /// is one
+    pub fn init_component(&self) -> Option<&ComponentDescriptor> {
+        self.components.iter().find(|c| c.autostart && c.capabilities_bitmask & BOOT_INIT_BIT != 0)
     }
 
     /// Find a component by name
@@ -185,124 +745,524 @@ impl ComponentLoader {
         self.spawn_component(descriptor)
     }
 
-    /// Spawn all autostart components
-    pub unsafe fn spawn_autostart(&self) -> Result<(), ComponentError> {
-        for component in self.registry.autostart_components() {
-            match self.spawn_component(component) {
-                Ok(result) => {
-                    crate::sys_print("[component_loader] Spawned: ");
-                    crate::sys_print(component.name);
-                    crate::sys_print(" (PID ");
-                    // Print PID (simplified - would need proper number formatting)
-                    crate::sys_print(")\n");
+    /// The registry components are spawned from
+    pub fn registry(&self) -> &'static ComponentRegistry {
+        self.registry
+    }
+
+    /// Start a component at the init component's request
+    ///
+    /// Unlike [`spawn`](Self::spawn), it is refused while the component is
+    /// running, or while a registry component it depends on is not. A
+    /// component others depend on is waited for, like [`spawn_all`] does,
+    /// until it reports ready or [`READY_TIMEOUT_MS`] pass. With
+    /// `untyped_kb`, it gets that much of our untyped memory, rounded down to
+    /// a power of two, in its [`UNTYPED_SLOT`].
+    ///
+    /// [`spawn_all`]: Self::spawn_all
+    pub unsafe fn start(&self, name: &str, untyped_kb: u32) -> Result<SpawnResult, ComponentError> {
+        let index = self.index_of(name).ok_or(ComponentError::NotFound)?;
+        let component = &self.registry.components[index];
+        if (*core::ptr::addr_of!(RUNNING))[index].is_some() {
+            return Err(ComponentError::AlreadyRunning);
+        }
+        let waiting = component
+            .depends_on
+            .iter()
+            .any(|dependency| self.index_of(dependency).is_some() && self.running(dependency).is_none());
+        if waiting {
+            return Err(ComponentError::DependencyNotRunning);
+        }
+
+        let awaited = self.registry.components.iter().any(|other| other.depends_on.contains(&component.name));
+        let readiness = &mut (*core::ptr::addr_of_mut!(READINESS))[index];
+        if awaited && readiness.is_none() {
+            *readiness = Readiness::create();
+        }
+
+        let spawn = self.spawn_component(component)?;
+        let ready = readiness.filter(|_| awaited).and_then(|ready| {
+            // A report the last instance made is not this one's
+            crate::sys_poll(ready.notification.slot);
+            ready.hand_to(component, &spawn)
+        });
+
+        if untyped_kb > 0 {
+            let bytes = untyped_kb as usize * 1024;
+            let size_bits = (usize::BITS - 1 - bytes.leading_zeros()) as usize;
+            match self.delegate_untyped(component, &spawn, UNTYPED_SLOT, size_bits.max(OBJECT_SIZE_BITS)) {
+                Ok(size) => {
+                    crate::sys_print("[component_loader] ");
+                    crate::print_number(size / 1024);
+                    crate::sys_print(" KiB of untyped memory delegated to ");
+                    crate::sys_print(name);
+                    crate::sys_print("\n");
                 }
                 Err(e) => {
-                    crate::sys_print("[component_loader] Failed to spawn: ");
-                    crate::sys_print(component.name);
+                    crate::sys_print("[component_loader] ✗ No untyped memory for ");
+                    crate::sys_print(name);
+                    crate::sys_print(": ");
+                    crate::sys_print(e.as_str());
                     crate::sys_print("\n");
-                    return Err(e);
                 }
             }
         }
-        Ok(())
+
+        if let Some(ready) = ready {
+            crate::sys_print("[component_loader] Waiting for ");
+            crate::sys_print(name);
+            crate::sys_print(" to report ready\n");
+            if !ready.wait() {
+                crate::sys_print("[component_loader] ✗ ");
+                crate::sys_print(name);
+                crate::sys_print(" did not report ready\n");
+            }
+        }
+        Ok(spawn)
     }
 
-    /// Internal: Spawn a single component
-    unsafe fn spawn_component(&self, desc: &ComponentDescriptor) -> Result<SpawnResult, ComponentError> {
-        // 1. Get binary data
-        let binary_data = desc.binary_data.ok_or(ComponentError::NoBinary)?;
+    /// Spawn all autostart components, each after the ones it depends on
+    ///
+    /// Components start in registry order, except that one waits until
+    /// everything in its `depends_on` has been spawned and has reported
+    /// ready: a component others depend on gets a notification in its
+    /// [`READY_NOTIFICATION_SLOT`], which it signals once it has
+    /// initialized. After [`READY_TIMEOUT_MS`] without a signal, its
+    /// dependents are started anyway.
+    ///
+    /// `spawned` gets each component's spawn result as soon as it is
+    /// spawned, before waiting for it to report ready. A component that
+    /// fails to spawn does not hold up its dependents.
+    pub unsafe fn spawn_all(
+        &self,
+        mut spawned: impl FnMut(&'static ComponentDescriptor, Result<SpawnResult, ComponentError>),
+    ) {
+        let components: &'static [ComponentDescriptor] = self.registry.components;
+        let count = components.len().min(MAX_COMPONENTS);
+        let components = &components[..count];
 
-        // Debug: Check what binary we got
-        crate::sys_print("[loader] Spawning component: ");
-        crate::sys_print(desc.name);
-        crate::sys_print(", binary_data len=");
-        crate::print_number(binary_data.len());
-        crate::sys_print(", contains: ");
-        // Search for distinctive strings to identify the binary
-        let has_producer = binary_data.windows(18).any(|w| w == b"IPC Producer v0.1.");
-        let has_consumer = binary_data.windows(20).any(|w| w == b"THIS IS THE CONSUMER");
+        // Made before anything is spawned, as spawned components may be
+        // handed the rest of our untyped memory
+        let mut readiness: [Option<Readiness>; MAX_COMPONENTS] = [None; MAX_COMPONENTS];
+        for (index, component) in components.iter().enumerate() {
+            let awaited = component.autostart
+                && components.iter().any(|other| other.autostart && other.depends_on.contains(&component.name));
+            if awaited {
+                readiness[index] = Readiness::create();
+                if readiness[index].is_none() {
+                    crate::sys_print("[component_loader] ✗ No readiness notification for ");
+                    crate::sys_print(component.name);
+                    crate::sys_print(", its dependents will not wait for it\n");
+                }
+            }
+        }
 
-        if has_producer {
-            crate::sys_print("PRODUCER ");
+        let mut started = [false; MAX_COMPONENTS];
+        loop {
+            let mut pending = (0..count).filter(|&index| components[index].autostart && !started[index]).peekable();
+            let Some(&first) = pending.peek() else {
+                break;
+            };
+            let index = match pending.find(|&index| !waits_on_pending(&components[index], components, &started)) {
+                Some(index) => index,
+                None => {
+                    crate::sys_print("[component_loader] ✗ Dependency cycle, starting ");
+                    crate::sys_print(components[first].name);
+                    crate::sys_print(" first\n");
+                    first
+                }
+            };
+            started[index] = true;
+
+            let component = &components[index];
+            let result = self.spawn_component(component);
+            let ready = match (&result, readiness[index]) {
+                (Ok(spawn), Some(ready)) => ready.hand_to(component, spawn),
+                _ => None,
+            };
+            spawned(component, result);
+
+            if let Some(ready) = ready {
+                crate::sys_print("[component_loader] Waiting for ");
+                crate::sys_print(component.name);
+                crate::sys_print(" to report ready\n");
+                if ready.wait() {
+                    crate::sys_print("[component_loader] ✓ ");
+                    crate::sys_print(component.name);
+                    crate::sys_print(" ready\n");
+                } else {
+                    crate::sys_print("[component_loader] ✗ ");
+                    crate::sys_print(component.name);
+                    crate::sys_print(" did not report ready, starting its dependents anyway\n");
+                }
+            }
         }
-        if has_consumer {
-            crate::sys_print("CONSUMER ");
+    }
+
+    /// Destroy a spawned component
+    ///
+    /// Kills the component through its TCB capability and drops the
+    /// capability, whose slot the next spawn reuses. What the capability
+    /// broker handed it is reclaimed by the broker server
+    /// (`BrokerServer::component_exited`).
+    pub unsafe fn kill(&self, component: &SpawnResult) -> Result<(), ComponentError> {
+        if crate::sys_process_destroy(component.tcb_cap_slot) != 0 {
+            return Err(ComponentError::CapabilityError);
         }
-        if !has_producer && !has_consumer {
-            crate::sys_print("UNKNOWN");
+
+        crate::sys_cap_delete(component.tcb_cap_slot);
+        free_tcb_slot(component.tcb_cap_slot);
+
+        let running = &mut *core::ptr::addr_of_mut!(RUNNING);
+        for instance in running.iter_mut() {
+            if instance.is_some_and(|instance| instance.pid == component.pid) {
+                *instance = None;
+            }
         }
-        crate::sys_print("\n");
+        let delegated = &mut *core::ptr::addr_of_mut!(DELEGATED);
+        for entry in delegated.iter_mut() {
+            if entry.is_some_and(|entry| entry.pid == component.pid) {
+                *entry = None;
+            }
+        }
+        Ok(())
+    }
 
-        // 2. Parse ELF
-        let elf_info = crate::elf::parse_elf(binary_data)
-            .map_err(|_| ComponentError::InvalidElf)?;
+    /// Stop a running component, letting it exit on its own first
+    ///
+    /// Signals a shutdown request on the component's control notification
+    /// (in its [`CONTROL_NOTIFICATION_SLOT`]) and gives it
+    /// [`STOP_TIMEOUT_MS`] to exit. Then it is destroyed like [`kill`] does,
+    /// whether it exited or not: the kernel frees its memory, IRQs and
+    /// channels, and we drop its TCB capability. The request is a
+    /// notification rather than an endpoint message so that a component
+    /// that never listens cannot block us.
+    ///
+    /// Returns whether the component exited by itself.
+    ///
+    /// [`kill`]: Self::kill
+    pub unsafe fn stop(&self, name: &str) -> Result<bool, ComponentError> {
+        let index = self.index_of(name).ok_or(ComponentError::NotFound)?;
+        let component = (*core::ptr::addr_of!(RUNNING))[index].ok_or(ComponentError::NotRunning)?;
 
-        // Debug: Print ELF info
-        crate::sys_print("[loader] ELF for ");
-        crate::sys_print(desc.name);
-        crate::sys_print(":\n");
-        crate::sys_print("  Entry: 0x");
-        crate::print_hex(elf_info.entry_point);
+        crate::sys_print("[component_loader] Stopping ");
+        crate::sys_print(name);
         crate::sys_print("\n");
-        crate::sys_print("  Segments:\n");
-        for i in 0..elf_info.num_segments {
-            let (vaddr, filesz, memsz, _offset) = elf_info.segments[i];
-            crate::sys_print("    [");
-            crate::print_number(i);
-            crate::sys_print("] vaddr=0x");
-            crate::print_hex(vaddr);
-            crate::sys_print(" filesz=0x");
-            crate::print_hex(filesz);
-            crate::sys_print(" memsz=0x");
-            crate::print_hex(memsz);
-            crate::sys_print("\n");
+
+        let requested = (*core::ptr::addr_of!(CONTROL))[index]
+            .is_some_and(|control| crate::sys_signal(control.slot, SHUTDOWN_BADGE) == 0);
+        let mut exited = false;
+        if requested {
+            for _ in 0..STOP_TIMEOUT_MS / STOP_POLL_MS {
+                exited = has_exited(component.pid);
+                if exited {
+                    break;
+                }
+                sleep_ms(STOP_POLL_MS);
+            }
         }
-        crate::sys_print("  Total range: 0x");
-        crate::print_hex(elf_info.min_vaddr);
-        crate::sys_print(" - 0x");
-        crate::print_hex(elf_info.max_vaddr);
+        if !exited {
+            crate::sys_print("[component_loader] ");
+            crate::sys_print(name);
+            crate::sys_print(" did not exit, destroying it\n");
+        }
+
+        self.kill(&component)?;
+        crate::sys_print("[component_loader] ✓ Stopped ");
+        crate::sys_print(name);
         crate::sys_print("\n");
+        Ok(exited)
+    }
 
-        // 3. Allocate memory for process image
-        // Future-proof: Always allocate an extra page beyond the highest address
-        // This ensures entry stubs at the end of .text have room to execute
-        let base_size = elf_info.memory_size();
-        let extra_safety = 4096;  // One extra page for entry stub safety
-        let process_size = ((base_size + extra_safety + 4095) & !4095);  // Round up to pages
-        let process_mem = crate::sys_memory_allocate(process_size);
-        if process_mem == usize::MAX {
-            return Err(ComponentError::OutOfMemory);
+    /// Give a spawned component UntypedMemory of its own, from ours
+    ///
+    /// Retypes a `2^size_bits` byte child of our untyped memory and puts it
+    /// in `target_slot` of the component's CSpace. With a `max_memory_kb`,
+    /// the child is shrunk to the largest power of two still within it.
+    ///
+    /// Returns the bytes delegated.
+    pub unsafe fn delegate_untyped(
+        &self,
+        desc: &ComponentDescriptor,
+        spawn: &SpawnResult,
+        target_slot: usize,
+        size_bits: usize,
+    ) -> Result<usize, ComponentError> {
+        let used = delegated(spawn.pid);
+        if desc.limits.max_cap_slots.is_some_and(|max| used.cap_slots >= max) {
+            return Err(ComponentError::OverBudget);
         }
 
-        // 4. Allocate stack (16KB)
-        let stack_size = 16384;
-        let stack_mem = crate::sys_memory_allocate(stack_size);
-        if stack_mem == usize::MAX {
-            return Err(ComponentError::OutOfMemory);
+        let mut size_bits = size_bits;
+        if let Some(max_kb) = desc.limits.max_memory_kb {
+            let remaining = (max_kb as usize * 1024).saturating_sub(used.memory);
+            if remaining < 1 << OBJECT_SIZE_BITS {
+                return Err(ComponentError::OverBudget);
+            }
+            // Largest power of two within what is left
+            let fits = (usize::BITS - 1 - remaining.leading_zeros()) as usize;
+            if fits < size_bits {
+                crate::sys_print("[component_loader] ");
+                crate::sys_print(desc.name);
+                crate::sys_print(" gets ");
+                crate::print_number((1 << fits) / 1024);
+                crate::sys_print(" KiB of untyped memory, its limit\n");
+                size_bits = fits;
+            }
         }
 
-        // 5. Allocate page table root (4KB)
-        let pt_root = crate::sys_memory_allocate(4096);
-        if pt_root == usize::MAX {
+        let slot = match (*core::ptr::addr_of_mut!(SPARE_UNTYPED_SLOT)).take() {
+            Some(slot) => slot,
+            None => crate::sys_cap_allocate(),
+        };
+        if slot == usize::MAX {
+            return Err(ComponentError::CapabilityError);
+        }
+        let paddr = crate::sys_retype(ROOT_UNTYPED_SLOT, CAP_TYPE_UNTYPED, size_bits, 0, slot);
+        if paddr == usize::MAX {
+            SPARE_UNTYPED_SLOT = Some(slot);
             return Err(ComponentError::OutOfMemory);
         }
-        crate::sys_print("[loader] Allocated PT for ");
-        crate::sys_print(desc.name);
-        crate::sys_print(" at 0x");
-        crate::print_hex(pt_root);
-        crate::sys_print("\n");
+        if crate::sys_cap_insert_into(spawn.tcb_cap_slot, target_slot, CAP_TYPE_UNTYPED, paddr) != 0 {
+            // Nothing was handed out: drop the untyped and keep its slot
+            crate::sys_cap_revoke(slot);
+            SPARE_UNTYPED_SLOT = Some(slot);
+            return Err(ComponentError::CapabilityError);
+        }
+        charge(spawn.pid, 1 << size_bits, target_slot, InitialCap::Untyped);
+        Ok(1 << size_bits)
+    }
 
-        // 6. Allocate CNode for capability space
-        // CNode needs:
-        // - CNode struct (~24 bytes)
-        // - Capability slots array (256 slots × 32 bytes = 8KB = 2 pages)
-        // Total: 3 pages minimum (12KB) to avoid overlap with TCB
-        let cspace_root = crate::sys_memory_allocate(12288); // 3 pages
-        if cspace_root == usize::MAX {
+    /// Read the binaries of components not embedded in the root task
+    /// (`embed = false` in system.toml) from `source` from now on
+    ///
+    /// Until this is called, spawning those fails with
+    /// [`ComponentError::NoBinary`].
+    pub unsafe fn set_binary_source(&self, source: &'static dyn BinarySource) {
+        BINARY_SOURCE = Some(source);
+    }
+
+    /// Take the init component's requests on the endpoint at `paddr`, which
+    /// the init component is handed in its [`BOOT_ENDPOINT_SLOT`]
+    pub unsafe fn set_boot_endpoint(&self, paddr: usize) {
+        BOOT_ENDPOINT = Some(paddr);
+    }
+
+    /// Our capability to the log endpoint, while a log server is running to
+    /// receive on it
+    pub fn log_endpoint(&self) -> Option<usize> {
+        let (slot, _) = unsafe { LOG_ENDPOINT }?;
+        let serving = self
+            .registry
+            .components
+            .iter()
+            .any(|c| c.capabilities_bitmask & LOG_SERVER_BIT != 0 && self.running(c.name).is_some());
+        serving.then_some(slot)
+    }
+
+    /// Our capability to the notification the running instance of the
+    /// component `name` signals its heartbeats on, if it was handed one
+    pub fn heartbeat(&self, name: &str) -> Option<usize> {
+        let index = self.index_of(name)?;
+        let spawn = self.running(name)?;
+        let handed = unsafe { delegated(spawn.pid) }
+            .caps
+            .iter()
+            .flatten()
+            .any(|(_, cap)| *cap == InitialCap::Heartbeat);
+        let heartbeat = unsafe { (*core::ptr::addr_of!(HEARTBEATS))[index] }?;
+        handed.then_some(heartbeat.slot)
+    }
+
+    /// What has been delegated to each running process
+    pub fn delegations(&self) -> impl Iterator<Item = Delegated> {
+        unsafe { (*core::ptr::addr_of!(DELEGATED)).iter().flatten().copied() }
+    }
+
+    /// Running instance of a component, if it has one
+    pub fn running(&self, name: &str) -> Option<SpawnResult> {
+        let index = self.index_of(name)?;
+        unsafe { (*core::ptr::addr_of!(RUNNING))[index] }
+    }
+
+    /// Index of a component in the registry, within the ones we track
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.registry.components.iter().take(MAX_COMPONENTS).position(|c| c.name == name)
+    }
+
+    /// Fill in a boot block for a component about to be spawned
+    ///
+    /// Its instances reuse one block, unless one is still running when
+    /// another is spawned. None if no page is left for it, and the
+    /// component starts without one.
+    unsafe fn boot_block(&self, desc: &ComponentDescriptor) -> Option<BootBlock> {
+        let index = self.index_of(desc.name);
+        let running = index.is_some_and(|index| (*core::ptr::addr_of!(RUNNING))[index].is_some());
+        let kept = index.filter(|_| !running).and_then(|index| (*core::ptr::addr_of!(BOOT_BLOCKS))[index]);
+        let block = match kept {
+            Some(block) => block,
+            None => {
+                let block = BootBlock::create()?;
+                if let Some(index) = index.filter(|_| !running) {
+                    (*core::ptr::addr_of_mut!(BOOT_BLOCKS))[index] = Some(block);
+                }
+                block
+            }
+        };
+
+        if !block.write(desc.name, desc.args, desc.env) {
+            crate::sys_print("[loader] ✗ Arguments and environment of ");
+            crate::sys_print(desc.name);
+            crate::sys_print(" do not fit its boot block, some are left out\n");
+        }
+        Some(block)
+    }
+
+    /// Hand a freshly spawned component its control notification, and its
+    /// heartbeat notification if it has hang detection, and record it as the
+    /// component's running instance
+    unsafe fn track(&self, desc: &ComponentDescriptor, spawn: &SpawnResult) {
+        let Some(index) = self.index_of(desc.name) else {
+            return;
+        };
+        (*core::ptr::addr_of_mut!(RUNNING))[index] = Some(*spawn);
+
+        let control = &mut (*core::ptr::addr_of_mut!(CONTROL))[index];
+        if control.is_none() {
+            *control = HandedNotification::create();
+        }
+        let handed = control.is_some_and(|control| {
+            // A request the last instance did not take is not for this one
+            crate::sys_poll(control.slot);
+            control.hand_to(desc, spawn, CONTROL_NOTIFICATION_SLOT, InitialCap::ControlNotification)
+        });
+        if !handed {
+            crate::sys_print("[component_loader] ✗ No control notification for ");
+            crate::sys_print(desc.name);
+            crate::sys_print(", stopping it will not ask it first\n");
+        }
+
+        if desc.heartbeat.is_none() {
+            return;
+        }
+        let heartbeat = &mut (*core::ptr::addr_of_mut!(HEARTBEATS))[index];
+        if heartbeat.is_none() {
+            *heartbeat = HandedNotification::create();
+        }
+        let handed = heartbeat.is_some_and(|heartbeat| {
+            // Beats of the last instance do not count for this one
+            crate::sys_poll(heartbeat.slot);
+            heartbeat.hand_to(desc, spawn, HEARTBEAT_NOTIFICATION_SLOT, InitialCap::Heartbeat)
+        });
+        if !handed {
+            crate::sys_print("[component_loader] ✗ No heartbeat notification for ");
+            crate::sys_print(desc.name);
+            crate::sys_print(", it is not watched for hangs\n");
+        }
+    }
+
+    /// Hand a freshly spawned component the log endpoint: the log server
+    /// (`log:serve`) to receive on, the others to send their output to
+    ///
+    /// Components spawned before the log server get none, and print
+    /// straight to the console, as the log server itself does. While the
+    /// log server is down, the others wait in their next print until it is
+    /// back, so it should be restarted always.
+    unsafe fn hand_log_endpoint(&self, desc: &ComponentDescriptor, spawn: &SpawnResult, capabilities: u64) {
+        let serves = capabilities & LOG_SERVER_BIT != 0;
+        let endpoint = match LOG_ENDPOINT {
+            Some((_, endpoint)) => endpoint,
+            None if serves => {
+                let Some((slot, endpoint)) = create_object(CAP_TYPE_ENDPOINT) else {
+                    crate::sys_print("[component_loader] ✗ Failed to create the log endpoint\n");
+                    return;
+                };
+                LOG_ENDPOINT = Some((slot, endpoint));
+                endpoint
+            }
+            None => return,
+        };
+
+        let initial = if serves { InitialCap::LogServer } else { InitialCap::Log };
+        if !delegate_cap(desc, spawn, LOG_ENDPOINT_SLOT, CAP_TYPE_ENDPOINT, endpoint, initial) {
+            crate::sys_print("[component_loader] ✗ No log endpoint for ");
+            crate::sys_print(desc.name);
+            crate::sys_print(", it prints to the console directly\n");
+        }
+    }
+
+    /// Hand the VFS service (`vfs:serve`) the endpoint it receives on, which
+    /// we read the binaries not embedded through (see `vfs_source`)
+    unsafe fn hand_vfs_endpoint(&self, desc: &ComponentDescriptor, spawn: &SpawnResult, capabilities: u64) {
+        if capabilities & VFS_SERVER_BIT == 0 {
+            return;
+        }
+        let endpoint = match VFS_ENDPOINT {
+            Some((_, endpoint)) => endpoint,
+            None => {
+                let Some((slot, endpoint)) = create_object(CAP_TYPE_ENDPOINT) else {
+                    crate::sys_print("[component_loader] ✗ Failed to create the VFS endpoint\n");
+                    return;
+                };
+                VFS_ENDPOINT = Some((slot, endpoint));
+                endpoint
+            }
+        };
+        VFS_SERVER = self.index_of(desc.name);
+
+        if !delegate_cap(desc, spawn, VFS_ENDPOINT_SLOT, CAP_TYPE_ENDPOINT, endpoint, InitialCap::VfsServer) {
+            crate::sys_print("[component_loader] ✗ No VFS endpoint for ");
+            crate::sys_print(desc.name);
+            crate::sys_print(", binaries not embedded cannot be read\n");
+        }
+    }
+
+    /// Hand the init component (`boot:init`), and the components that start
+    /// and stop others (`boot:control`), the endpoint they ask us on
+    unsafe fn hand_boot_endpoint(&self, desc: &ComponentDescriptor, spawn: &SpawnResult, capabilities: u64) {
+        let Some(endpoint) = BOOT_ENDPOINT.filter(|_| capabilities & (BOOT_INIT_BIT | BOOT_CONTROL_BIT) != 0) else {
+            return;
+        };
+        if !delegate_cap(desc, spawn, BOOT_ENDPOINT_SLOT, CAP_TYPE_ENDPOINT, endpoint, InitialCap::Boot) {
+            crate::sys_print("[component_loader] ✗ No boot endpoint for ");
+            crate::sys_print(desc.name);
+            crate::sys_print(", it cannot start components\n");
+        }
+    }
+
+    /// Internal: Load a component's ELF image into physical memory
+    ///
+    /// Each binary is loaded once. Its processes map the image copy-on-write,
+    /// so it stays unmodified and later spawns of the same binary share it
+    /// instead of copying the binary again.
+    ///
+    /// Returns the image's physical address and size.
+    unsafe fn load_image(
+        &self,
+        desc: &ComponentDescriptor,
+        binary_data: &'static [u8],
+        elf_info: &crate::elf::ElfInfo,
+    ) -> Result<(usize, usize), ComponentError> {
+        let images = &mut *core::ptr::addr_of_mut!(LOADED_IMAGES);
+        if let Some(image) = images.iter().flatten().find(|image| image.binary == desc.binary) {
+            crate::sys_print("[loader] Sharing loaded image of ");
+            crate::sys_print(desc.binary);
+            crate::sys_print(" (copy-on-write)\n");
+            return Ok((image.phys, image.size));
+        }
+
+        // 1. Allocate memory for process image
+        // Future-proof: Always allocate an extra page beyond the highest address
+        // This ensures entry stubs at the end of .text have room to execute
+        let base_size = elf_info.memory_size();
+        let extra_safety = 4096;  // One extra page for entry stub safety
+        let process_size = ((base_size + extra_safety + 4095) & !4095);  // Round up to pages
+        let process_mem = crate::sys_memory_allocate(process_size);
+        if process_mem == usize::MAX {
             return Err(ComponentError::OutOfMemory);
         }
 
-        // 7. Map the allocated physical memory so we can copy the ELF segments
+        // 2. Map the allocated physical memory so we can copy the ELF segments
         const RW_PERMS: usize = 0x3; // Read + Write
         crate::sys_print("[loader] Mapping phys 0x");
         crate::print_hex(process_mem);
@@ -321,8 +1281,11 @@ impl ComponentLoader {
         crate::print_number(binary_data.len());
         crate::sys_print(" bytes from binary_data\n");
 
-        // 8. Copy each LOAD segment to the mapped memory
-        let base_vaddr = elf_info.min_vaddr;
+        // 3. Zero the whole image, so BSS, the padding between segments and
+        // the rest of their last pages hold nothing left over, then copy
+        // each LOAD segment's file data to the mapped memory
+        let base_vaddr = elf_info.image_base();
+        core::ptr::write_bytes(virt_mem as *mut u8, 0, process_size);
 
         // Debug: Show first few bytes of source binary
         if binary_data.len() >= 4 {
@@ -332,33 +1295,24 @@ impl ComponentLoader {
             crate::sys_print("\n");
         }
 
-        for i in 0..elf_info.num_segments {
-            let (vaddr, filesz, memsz, offset) = elf_info.segments[i];
-
+        for (i, segment) in elf_info.segments().iter().enumerate() {
             // Calculate destination in mapped memory
-            let segment_offset = vaddr - base_vaddr;
+            let segment_offset = segment.vaddr - base_vaddr;
             let dest_ptr = (virt_mem + segment_offset) as *mut u8;
-            let src_ptr = binary_data.as_ptr().add(offset);
+            let src_ptr = binary_data.as_ptr().add(segment.offset);
 
-            // Copy file data
-            if filesz > 0 {
+            // Copy file data; the BSS past it is already zero
+            if segment.filesz > 0 {
                 crate::sys_print("[loader] Copying segment ");
                 crate::print_number(i);
                 crate::sys_print(": ");
-                crate::print_number(filesz);
+                crate::print_number(segment.filesz);
                 crate::sys_print(" bytes from src=0x");
                 crate::print_hex(src_ptr as usize);
                 crate::sys_print(" to dest=0x");
                 crate::print_hex(dest_ptr as usize);
                 crate::sys_print("\n");
-                core::ptr::copy_nonoverlapping(src_ptr, dest_ptr, filesz);
-            }
-
-            // Zero BSS (memsz > filesz means there's BSS to zero)
-            if memsz > filesz {
-                let bss_ptr = dest_ptr.add(filesz);
-                let bss_size = memsz - filesz;
-                core::ptr::write_bytes(bss_ptr, 0, bss_size);
+                core::ptr::copy_nonoverlapping(src_ptr, dest_ptr, segment.filesz);
             }
         }
 
@@ -372,7 +1326,7 @@ impl ComponentLoader {
         crate::print_hex(entry_instr as usize);
         crate::sys_print("\n");
 
-        // 9. Unmap the memory (we're done writing to it)
+        // 4. Unmap the memory (we're done writing to it)
         // TEMPORARILY DISABLED to test if unmap is causing second process hang bug
         crate::sys_print("[loader] SKIPPING unmap of virt 0x");
         crate::print_hex(virt_mem);
@@ -380,7 +1334,183 @@ impl ComponentLoader {
         // crate::sys_memory_unmap(virt_mem, process_size);
         // crate::sys_print("[loader] Unmap complete\n");
 
-        // 10. Map stack memory to get unique virtual address for this process
+        if let Some(slot) = images.iter_mut().find(|image| image.is_none()) {
+            *slot = Some(LoadedImage { binary: desc.binary, phys: process_mem, size: process_size });
+        }
+
+        Ok((process_mem, process_size))
+    }
+
+    /// Internal: Map each segment of a spawned component's image with its
+    /// own permissions: text read-execute, rodata read-only, data and BSS
+    /// read-write
+    ///
+    /// The kernel maps the whole image copy-on-write and executable. Data
+    /// pages stay copy-on-write; text and rodata pages can no longer be
+    /// written at all.
+    unsafe fn protect_segments(&self, desc: &ComponentDescriptor, tcb_cap_slot: usize, elf_info: &crate::elf::ElfInfo) {
+        for segment in elf_info.segments() {
+            let start = segment.page_start();
+            let size = segment.page_end() - start;
+            if crate::sys_memory_protect_in(tcb_cap_slot, start, size, segment.permissions) != 0 {
+                crate::sys_print("[loader] ✗ Failed to map ");
+                crate::sys_print(desc.name);
+                crate::sys_print("'s segment at 0x");
+                crate::print_hex(segment.vaddr);
+                crate::sys_print(" ");
+                crate::sys_print(segment.permissions_str());
+                crate::sys_print(", it stays writable and executable\n");
+            }
+        }
+    }
+
+    /// Internal: A component's binary, embedded or read from the binary
+    /// source
+    ///
+    /// A binary read is kept in memory, like the embedded ones, so it is
+    /// read once however often the component is spawned. Memory for one
+    /// that cannot be read is kept for the next (see [`alloc_binary_frames`]).
+    unsafe fn binary(&self, desc: &ComponentDescriptor) -> Result<&'static [u8], ComponentError> {
+        if let Some(data) = desc.binary_data {
+            return Ok(data);
+        }
+        let read = &mut *core::ptr::addr_of_mut!(READ_BINARIES);
+        if let Some((_, data)) = read.iter().flatten().find(|(binary, _)| *binary == desc.binary) {
+            return Ok(data);
+        }
+
+        let source = BINARY_SOURCE.ok_or(ComponentError::NoBinary)?;
+        let len = source.len(desc.binary).ok_or(ComponentError::NoBinary)?;
+        let entry = read.iter_mut().find(|entry| entry.is_none()).ok_or(ComponentError::OutOfMemory)?;
+
+        let size = len.div_ceil(4096) * 4096;
+        let (phys, size) = alloc_binary_frames(size).ok_or(ComponentError::OutOfMemory)?;
+        let virt = crate::sys_memory_map(phys, size, 0x3); // RW
+        if virt == usize::MAX {
+            free_binary_frames(phys, size);
+            return Err(ComponentError::OutOfMemory);
+        }
+        let data = core::slice::from_raw_parts_mut(virt as *mut u8, len);
+        if !source.read(desc.binary, data) {
+            crate::sys_memory_unmap(virt, size);
+            free_binary_frames(phys, size);
+            return Err(ComponentError::BinaryUnreadable);
+        }
+
+        crate::sys_print("[loader] Read ");
+        crate::sys_print(desc.binary);
+        crate::sys_print(" (");
+        crate::print_number(len);
+        crate::sys_print(" bytes) from the binary source\n");
+        *entry = Some((desc.binary, data));
+        Ok(data)
+    }
+
+    /// Internal: Spawn a single component
+    unsafe fn spawn_component(&self, desc: &ComponentDescriptor) -> Result<SpawnResult, ComponentError> {
+        // 1. Get binary data
+        let binary_data = self.binary(desc)?;
+
+        // Debug: Check what binary we got
+        crate::sys_print("[loader] Spawning component: ");
+        crate::sys_print(desc.name);
+        crate::sys_print(", binary_data len=");
+        crate::print_number(binary_data.len());
+        crate::sys_print(", contains: ");
+        // Search for distinctive strings to identify the binary
+        let has_producer = binary_data.windows(18).any(|w| w == b"IPC Producer v0.1.");
+        let has_consumer = binary_data.windows(20).any(|w| w == b"THIS IS THE CONSUMER");
+
+        if has_producer {
+            crate::sys_print("PRODUCER ");
+        }
+        if has_consumer {
+            crate::sys_print("CONSUMER ");
+        }
+        if !has_producer && !has_consumer {
+            crate::sys_print("UNKNOWN");
+        }
+        crate::sys_print("\n");
+
+        if desc.limits.priority_ceiling.is_some_and(|ceiling| desc.priority < ceiling) {
+            crate::sys_print("[loader] ✗ ");
+            crate::sys_print(desc.name);
+            crate::sys_print(" asks for a priority above its ceiling\n");
+            return Err(ComponentError::OverBudget);
+        }
+
+        // 2. Parse ELF
+        let elf_info = match crate::elf::parse_elf(binary_data) {
+            Ok(info) => info,
+            Err(e) => {
+                crate::sys_print("[loader] ✗ ELF for ");
+                crate::sys_print(desc.name);
+                crate::sys_print(" rejected: ");
+                crate::sys_print(e.as_str());
+                crate::sys_print("\n");
+                return Err(e.into());
+            }
+        };
+
+        // Debug: Print ELF info
+        crate::sys_print("[loader] ELF for ");
+        crate::sys_print(desc.name);
+        crate::sys_print(":\n");
+        crate::sys_print("  Entry: 0x");
+        crate::print_hex(elf_info.entry_point);
+        crate::sys_print("\n");
+        crate::sys_print("  Segments:\n");
+        for (i, segment) in elf_info.segments().iter().enumerate() {
+            crate::sys_print("    [");
+            crate::print_number(i);
+            crate::sys_print("] vaddr=0x");
+            crate::print_hex(segment.vaddr);
+            crate::sys_print(" filesz=0x");
+            crate::print_hex(segment.filesz);
+            crate::sys_print(" memsz=0x");
+            crate::print_hex(segment.memsz);
+            crate::sys_print(" ");
+            crate::sys_print(segment.permissions_str());
+            crate::sys_print("\n");
+        }
+        crate::sys_print("  Total range: 0x");
+        crate::print_hex(elf_info.min_vaddr);
+        crate::sys_print(" - 0x");
+        crate::print_hex(elf_info.max_vaddr);
+        crate::sys_print("\n");
+
+        // 3. Load the image, or share the one loaded by an earlier spawn
+        let (process_mem, process_size) = self.load_image(desc, binary_data, &elf_info)?;
+
+        // 4. Allocate stack (16KB)
+        let stack_size = 16384;
+        let stack_mem = crate::sys_memory_allocate(stack_size);
+        if stack_mem == usize::MAX {
+            return Err(ComponentError::OutOfMemory);
+        }
+
+        // 5. Allocate page table root (4KB)
+        let pt_root = crate::sys_memory_allocate(4096);
+        if pt_root == usize::MAX {
+            return Err(ComponentError::OutOfMemory);
+        }
+        crate::sys_print("[loader] Allocated PT for ");
+        crate::sys_print(desc.name);
+        crate::sys_print(" at 0x");
+        crate::print_hex(pt_root);
+        crate::sys_print("\n");
+
+        // 6. Allocate CNode for capability space
+        // CNode needs:
+        // - CNode struct (~24 bytes)
+        // - Capability slots array (256 slots × 32 bytes = 8KB = 2 pages)
+        // Total: 3 pages minimum (12KB) to avoid overlap with TCB
+        let cspace_root = crate::sys_memory_allocate(12288); // 3 pages
+        if cspace_root == usize::MAX {
+            return Err(ComponentError::OutOfMemory);
+        }
+
+        // 7. Map stack memory to get unique virtual address for this process
         // This ensures each process has its own stack and prevents stack collisions
         let stack_virt = crate::sys_memory_map(stack_mem, stack_size, 0x3);  // RW permissions
         if stack_virt == usize::MAX {
@@ -409,28 +1539,39 @@ impl ComponentLoader {
         // Use capabilities from component descriptor
         let capabilities = desc.capabilities_bitmask;
 
+        // Arguments, environment and capability map, at x0 in the component
+        let boot_block = self.boot_block(desc);
+        let flags = match boot_block {
+            Some(_) => crate::PROCESS_CREATE_COW | crate::PROCESS_CREATE_BOOT_BLOCK,
+            None => crate::PROCESS_CREATE_COW,
+        };
+
         let result = crate::sys_process_create(
             elf_info.entry_point,
             stack_top,
             pt_root,
             cspace_root,
             process_mem,
-            elf_info.min_vaddr,  // Virtual address where code should be mapped
+            elf_info.image_base(),  // Virtual address where code should be mapped
             process_size,
             stack_mem,
             desc.priority,  // Pass the component priority from manifest
             capabilities,  // Pass parsed capabilities from manifest
+            flags,  // Share the loaded image copy-on-write, map the boot block
+            boot_block.as_ref(),
         );
 
         if result.pid == usize::MAX {
             return Err(ComponentError::OutOfMemory);
         }
+        if let Some(used) = delegated_mut(result.pid) {
+            used.name = desc.name;
+            used.rights = capabilities;
+            used.boot_block = boot_block;
+        }
 
         // Allocate capability slot for TCB in our CSpace
-        // Start at slot 200 to avoid initial capabilities populated during boot
-        static mut NEXT_CAP_SLOT: usize = 10;
-        let tcb_cap_slot = NEXT_CAP_SLOT;
-        NEXT_CAP_SLOT += 1;
+        let tcb_cap_slot = alloc_tcb_slot();
 
         // Insert TCB capability into our CSpace at the allocated slot
         // CapType::Tcb = 4
@@ -445,6 +1586,17 @@ impl ComponentLoader {
             crate::sys_print("[loader] Warning: Failed to insert TCB capability\n");
         }
 
+        self.protect_segments(desc, tcb_cap_slot, &elf_info);
+
+        // Convert to SpawnResult with capability information
+        let spawn = SpawnResult {
+            tcb_cap_slot,                   // Slot number for use with syscalls
+            tcb_phys: result.tcb_phys,      // Physical address for reference
+            vspace_cap: result.pt_phys,     // Page table root
+            cspace_cap: result.cspace_phys, // CSpace root
+            pid: result.pid,
+        };
+
         // Check if component needs IRQControl and delegate it
         // IRQControl capability is at slot 0 in root-task's CSpace (from boot_info)
         // If component has irq:control capability, insert IRQControl into its CSpace at slot 0
@@ -460,28 +1612,88 @@ impl ComponentLoader {
             const IRQ_CONTROL_SLOT: usize = 1;
             const CAP_TYPE_IRQCONTROL: usize = 10;
 
-            let insert_result = crate::sys_cap_insert_into(
-                tcb_cap_slot,
-                IRQ_CONTROL_SLOT,
-                CAP_TYPE_IRQCONTROL,
-                self.irq_control_paddr,
-            );
-
-            if insert_result == 0 {
+            let irq_control = self.irq_control_paddr;
+            if delegate_cap(desc, &spawn, IRQ_CONTROL_SLOT, CAP_TYPE_IRQCONTROL, irq_control, InitialCap::IrqControl) {
                 crate::sys_print("[loader] ✓ IRQControl delegated to slot 1\n");
             } else {
                 crate::sys_print("[loader] ✗ Failed to delegate IRQControl\n");
             }
         }
 
-        // Convert to SpawnResult with capability information
-        Ok(SpawnResult {
-            tcb_cap_slot,                   // Slot number for use with syscalls
-            tcb_phys: result.tcb_phys,      // Physical address for reference
-            vspace_cap: result.pt_phys,     // Page table root
-            cspace_cap: result.cspace_phys, // CSpace root
-            pid: result.pid,
-        })
+        self.hand_log_endpoint(desc, &spawn, capabilities);
+        self.hand_boot_endpoint(desc, &spawn, capabilities);
+        self.hand_vfs_endpoint(desc, &spawn, capabilities);
+
+        // Channels are set up by the components themselves through the broker;
+        // the manifest only declares them, so log what to expect
+        for channel in desc.channels {
+            crate::sys_print("[loader] ");
+            crate::sys_print(desc.name);
+            crate::sys_print(match channel.role {
+                ChannelRole::Producer => " produces channel ",
+                ChannelRole::Consumer => " consumes channel ",
+            });
+            crate::sys_print(channel.name);
+            crate::sys_print("\n");
+        }
+
+        self.track(desc, &spawn);
+        Ok(spawn)
+    }
+}
+
+/// Whether `component` depends on an autostart component not started yet
+fn waits_on_pending(component: &ComponentDescriptor, components: &[ComponentDescriptor], started: &[bool]) -> bool {
+    component.depends_on.iter().any(|dependency| {
+        components
+            .iter()
+            .position(|other| other.name == *dependency && other.autostart)
+            .is_some_and(|index| !started[index])
+    })
+}
+
+/// Notification a component signals once it is ready, with the timer that
+/// gives up waiting for it
+#[derive(Clone, Copy)]
+struct Readiness {
+    notification: HandedNotification,
+    /// Timer signaling the notification with READY_TIMEOUT_BADGE
+    timer: usize,
+}
+
+impl Readiness {
+    /// Make the notification and its timer
+    unsafe fn create() -> Option<Self> {
+        let notification = HandedNotification::create()?;
+        let timer = crate::sys_timer_create(notification.slot, READY_TIMEOUT_BADGE);
+        if timer == usize::MAX {
+            return None;
+        }
+        Some(Self { notification, timer })
+    }
+
+    /// Put the notification in the spawned component's READY_NOTIFICATION_SLOT
+    ///
+    /// Returns None if it could not, as then the component cannot report
+    /// ready.
+    unsafe fn hand_to(self, component: &ComponentDescriptor, spawn: &SpawnResult) -> Option<Self> {
+        if !self.notification.hand_to(component, spawn, READY_NOTIFICATION_SLOT, InitialCap::ReadyNotification) {
+            crate::sys_print("[component_loader] ✗ Failed to hand ");
+            crate::sys_print(component.name);
+            crate::sys_print(" its readiness notification\n");
+            return None;
+        }
+        Some(self)
+    }
+
+    /// Block until the component reports ready or READY_TIMEOUT_MS pass;
+    /// returns whether it reported ready
+    unsafe fn wait(self) -> bool {
+        if crate::sys_timer_set(self.timer, READY_TIMEOUT_MS * 1000) != 0 {
+            return false;
+        }
+        let bits = crate::sys_wait(self.notification.slot);
+        bits != usize::MAX && bits & READY_BADGE != 0
     }
 }
 
@@ -498,47 +1710,58 @@ pub enum ComponentError {
     OutOfMemory,
     /// Capability granting failed
     CapabilityError,
-    /// Feature not yet implemented
-    NotImplemented,
+    /// Binary has more LOAD segments than the loader takes
+    TooManySegments,
+    /// A segment's file data lies outside the binary
+    SegmentOutOfBounds,
+    /// A segment is not aligned as its header says, or shares a page with a
+    /// segment of other permissions
+    MisalignedSegment,
+    /// Two segments cover the same addresses
+    OverlappingSegments,
+    /// Component has no running instance
+    NotRunning,
+    /// The binary source failed to read the binary
+    BinaryUnreadable,
+    /// Spawning or delegating would exceed the component's resource limits
+    OverBudget,
+    /// Component already has a running instance
+    AlreadyRunning,
+    /// A component it depends on is not running
+    DependencyNotRunning,
 }
 
-/// Get the embedded components manifest
-///
-/// This returns the contents of PROJECT_ROOT/components.toml that was
-/// embedded at build time. Developers configure components at the project
-/// root for easy discovery.
-///
-/// # Example
-/// ```no_run
-/// let manifest = get_components_manifest();
-/// // Parse TOML and discover components
-/// ```
-pub fn get_components_manifest() -> &'static str {
-    COMPONENTS_TOML
+impl From<crate::elf::ElfError> for ComponentError {
+    fn from(e: crate::elf::ElfError) -> Self {
+        use crate::elf::ElfError;
+        match e {
+            ElfError::Invalid(_) => ComponentError::InvalidElf,
+            ElfError::TooManySegments => ComponentError::TooManySegments,
+            ElfError::SegmentOutOfBounds => ComponentError::SegmentOutOfBounds,
+            ElfError::MisalignedSegment => ComponentError::MisalignedSegment,
+            ElfError::OverlappingSegments => ComponentError::OverlappingSegments,
+        }
+    }
 }
 
-/// Print component manifest information
-///
-/// Helper for debugging - shows where manifest was loaded from
-pub unsafe fn print_manifest_info() {
-    crate::sys_print("═══════════════════════════════════════════════════════════\n");
-    crate::sys_print("  Component Manifest\n");
-    crate::sys_print("═══════════════════════════════════════════════════════════\n");
-    crate::sys_print("  Location: PROJECT_ROOT/components.toml\n");
-    crate::sys_print("  Components found: ");
-    // Count [[component]] occurrences
-    let count = COMPONENTS_TOML.matches("[[component]]").count();
-    if count == 0 {
-        crate::sys_print("0\n");
-    } else {
-        // Simple number printing (1-9)
-        let digit = b'0' + (count as u8);
-        let digit_byte = [digit];
-        let s = core::str::from_utf8_unchecked(&digit_byte);
-        crate::sys_print(s);
-        crate::sys_print("\n");
+impl ComponentError {
+    /// Short description for the console
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComponentError::NotFound => "not found",
+            ComponentError::NoBinary => "no binary",
+            ComponentError::InvalidElf => "invalid ELF",
+            ComponentError::OutOfMemory => "out of memory",
+            ComponentError::CapabilityError => "capability error",
+            ComponentError::TooManySegments => "too many ELF segments",
+            ComponentError::SegmentOutOfBounds => "ELF segment out of bounds",
+            ComponentError::MisalignedSegment => "misaligned ELF segment",
+            ComponentError::OverlappingSegments => "overlapping ELF segments",
+            ComponentError::NotRunning => "not running",
+            ComponentError::BinaryUnreadable => "binary could not be read",
+            ComponentError::OverBudget => "over its resource limits",
+            ComponentError::AlreadyRunning => "already running",
+            ComponentError::DependencyNotRunning => "a dependency is not running",
+        }
     }
-    crate::sys_print("  Status: Embedded at build time\n");
-    crate::sys_print("═══════════════════════════════════════════════════════════\n");
-    crate::sys_print("\n");
 }
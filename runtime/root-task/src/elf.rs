@@ -55,12 +55,144 @@ struct Elf64ProgramHeader {
     p_align: u64,           // Segment alignment
 }
 
+/// Page size segments are mapped with; segments with different
+/// permissions must not share a page
+pub const PAGE_SIZE: usize = 4096;
+
+/// Most PT_LOAD segments a binary may have
+pub const MAX_SEGMENTS: usize = 8;
+
+/// Segment permission flags (p_flags)
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+/// Memory permissions, as SYS_MEMORY_PROTECT_IN takes them
+pub const PERM_READ: usize = 1 << 0;
+pub const PERM_WRITE: usize = 1 << 1;
+pub const PERM_EXEC: usize = 1 << 2;
+
+/// Why a binary cannot be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// Not a little-endian ELF64 executable, or its headers are malformed
+    Invalid(&'static str),
+    /// More than [`MAX_SEGMENTS`] PT_LOAD segments
+    TooManySegments,
+    /// A segment's file data lies outside the binary
+    SegmentOutOfBounds,
+    /// A segment's address does not match its file offset modulo its
+    /// alignment, or it shares a page with a segment of other permissions
+    MisalignedSegment,
+    /// Two segments cover the same addresses
+    OverlappingSegments,
+}
+
+impl ElfError {
+    /// Get a short description of the error
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElfError::Invalid(reason) => reason,
+            ElfError::TooManySegments => "Too many LOAD segments",
+            ElfError::SegmentOutOfBounds => "Segment data out of bounds",
+            ElfError::MisalignedSegment => "Segment misaligned",
+            ElfError::OverlappingSegments => "Segments overlap",
+        }
+    }
+}
+
+/// A PT_LOAD segment
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Segment {
+    /// Virtual address
+    pub vaddr: usize,
+    /// Bytes in the file
+    pub filesz: usize,
+    /// Bytes in memory; past `filesz` is BSS, zeroed
+    pub memsz: usize,
+    /// File offset of the data
+    pub offset: usize,
+    /// `PERM_READ`, `PERM_WRITE` and `PERM_EXEC` bits
+    pub permissions: usize,
+}
+
+impl Segment {
+    /// Check a program header's fields and make the segment
+    ///
+    /// # Arguments
+    /// * `flags` - p_flags
+    /// * `align` - p_align (0 or 1 for none)
+    /// * `file_len` - Size of the binary
+    pub fn new(
+        vaddr: u64,
+        filesz: u64,
+        memsz: u64,
+        offset: u64,
+        flags: u32,
+        align: u64,
+        file_len: usize,
+    ) -> Result<Self, ElfError> {
+        if filesz > memsz {
+            return Err(ElfError::Invalid("Segment larger in file than in memory"));
+        }
+        if vaddr.checked_add(memsz).is_none() {
+            return Err(ElfError::Invalid("Segment wraps the address space"));
+        }
+        if offset.checked_add(filesz).is_none_or(|end| end > file_len as u64) {
+            return Err(ElfError::SegmentOutOfBounds);
+        }
+        if align > 1 && (!align.is_power_of_two() || vaddr % align != offset % align) {
+            return Err(ElfError::MisalignedSegment);
+        }
+
+        let mut permissions = 0;
+        if flags & PF_R != 0 {
+            permissions |= PERM_READ;
+        }
+        if flags & PF_W != 0 {
+            permissions |= PERM_WRITE;
+        }
+        if flags & PF_X != 0 {
+            permissions |= PERM_EXEC;
+        }
+
+        Ok(Self {
+            vaddr: vaddr as usize,
+            filesz: filesz as usize,
+            memsz: memsz as usize,
+            offset: offset as usize,
+            permissions,
+        })
+    }
+
+    /// End of the segment in memory
+    pub fn end(&self) -> usize {
+        self.vaddr + self.memsz
+    }
+
+    /// Start of the segment's first page
+    pub fn page_start(&self) -> usize {
+        self.vaddr & !(PAGE_SIZE - 1)
+    }
+
+    /// End of the segment's last page
+    pub fn page_end(&self) -> usize {
+        self.end().next_multiple_of(PAGE_SIZE)
+    }
+
+    /// Permissions as `rwx`
+    pub fn permissions_str(&self) -> &'static str {
+        const NAMES: [&str; 8] = ["---", "r--", "-w-", "rw-", "--x", "r-x", "-wx", "rwx"];
+        NAMES[self.permissions & 7]
+    }
+}
+
 /// Parsed ELF information needed for process creation
 pub struct ElfInfo {
     /// Entry point (initial PC)
     pub entry_point: usize,
-    /// Load segments (vaddr, filesz, memsz, file_offset)
-    pub segments: [(usize, usize, usize, usize); 8],
+    /// Load segments
+    pub segments: [Segment; MAX_SEGMENTS],
     /// Number of load segments
     pub num_segments: usize,
     /// Minimum virtual address
@@ -70,9 +202,65 @@ pub struct ElfInfo {
 }
 
 impl ElfInfo {
+    /// No segments yet
+    pub fn new(entry_point: usize) -> Self {
+        Self {
+            entry_point,
+            segments: [Segment::default(); MAX_SEGMENTS],
+            num_segments: 0,
+            min_vaddr: usize::MAX,
+            max_vaddr: 0,
+        }
+    }
+
+    /// Add a load segment
+    pub fn push(&mut self, segment: Segment) -> Result<(), ElfError> {
+        if self.num_segments == MAX_SEGMENTS {
+            return Err(ElfError::TooManySegments);
+        }
+        self.min_vaddr = self.min_vaddr.min(segment.vaddr);
+        self.max_vaddr = self.max_vaddr.max(segment.end());
+        self.segments[self.num_segments] = segment;
+        self.num_segments += 1;
+        Ok(())
+    }
+
+    /// Check that the segments can each be mapped with their own
+    /// permissions: none may overlap, and only segments with the same
+    /// permissions may share a page
+    pub fn check_layout(&self) -> Result<(), ElfError> {
+        if self.num_segments == 0 {
+            return Err(ElfError::Invalid("No LOAD segments found"));
+        }
+
+        let segments = self.segments();
+        for (i, a) in segments.iter().enumerate() {
+            for b in &segments[i + 1..] {
+                if a.vaddr < b.end() && b.vaddr < a.end() {
+                    return Err(ElfError::OverlappingSegments);
+                }
+                let share_page = a.page_start() < b.page_end() && b.page_start() < a.page_end();
+                if share_page && a.permissions != b.permissions {
+                    return Err(ElfError::MisalignedSegment);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The load segments
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments[..self.num_segments]
+    }
+
+    /// Address the image is mapped at: the first segment's page
+    pub fn image_base(&self) -> usize {
+        self.min_vaddr & !(PAGE_SIZE - 1)
+    }
+
     /// Get the total memory size needed for the process
     pub fn memory_size(&self) -> usize {
-        self.max_vaddr - self.min_vaddr
+        self.max_vaddr - self.image_base()
     }
 }
 
@@ -83,29 +271,29 @@ impl ElfInfo {
 ///
 /// # Returns
 /// * `Ok(ElfInfo)` - Parsed ELF information
-/// * `Err(&str)` - Error message
-pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
+/// * `Err(ElfError)` - Why the binary cannot be loaded
+pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, ElfError> {
     // Validate minimum size
     if elf_data.len() < core::mem::size_of::<Elf64Header>() {
-        return Err("ELF too small");
+        return Err(ElfError::Invalid("ELF too small"));
     }
 
-    // Cast to ELF header
-    let header = unsafe { &*(elf_data.as_ptr() as *const Elf64Header) };
+    // Embedded binaries are only byte-aligned
+    let header = unsafe { core::ptr::read_unaligned(elf_data.as_ptr() as *const Elf64Header) };
 
     // Validate magic
     if header.e_ident[0..4] != ELF_MAGIC {
-        return Err("Invalid ELF magic");
+        return Err(ElfError::Invalid("Invalid ELF magic"));
     }
 
     // Validate class (64-bit)
     if header.e_ident[4] != ELFCLASS64 {
-        return Err("Not 64-bit ELF");
+        return Err(ElfError::Invalid("Not 64-bit ELF"));
     }
 
     // Validate endianness (little endian)
     if header.e_ident[5] != ELFDATA2LSB {
-        return Err("Not little endian");
+        return Err(ElfError::Invalid("Not little endian"));
     }
 
     // Parse program headers
@@ -113,60 +301,36 @@ pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
     let phnum = header.e_phnum as usize;
     let phentsize = header.e_phentsize as usize;
 
-    if phnum > 8 {
-        return Err("Too many program headers (max 8)");
+    if phentsize < core::mem::size_of::<Elf64ProgramHeader>() {
+        return Err(ElfError::Invalid("Program header entries too small"));
     }
 
-    let mut info = ElfInfo {
-        entry_point: header.e_entry as usize,
-        segments: [(0, 0, 0, 0); 8],
-        num_segments: 0,
-        min_vaddr: usize::MAX,
-        max_vaddr: 0,
-    };
+    let mut info = ElfInfo::new(header.e_entry as usize);
 
     // Parse LOAD segments
     for i in 0..phnum {
         let ph_offset = phoff + (i * phentsize);
         if ph_offset + phentsize > elf_data.len() {
-            return Err("Program header out of bounds");
+            return Err(ElfError::Invalid("Program header out of bounds"));
         }
 
         let ph = unsafe {
-            &*(elf_data.as_ptr().add(ph_offset) as *const Elf64ProgramHeader)
+            core::ptr::read_unaligned(elf_data.as_ptr().add(ph_offset) as *const Elf64ProgramHeader)
         };
 
         if ph.p_type == PT_LOAD {
-            let vaddr = ph.p_vaddr as usize;
-            let filesz = ph.p_filesz as usize;
-            let memsz = ph.p_memsz as usize;
-            let offset = ph.p_offset as usize;
-
-            // Track address range
-            if vaddr < info.min_vaddr {
-                info.min_vaddr = vaddr;
-            }
-            let segment_end = vaddr + memsz;
-            if segment_end > info.max_vaddr {
-                info.max_vaddr = segment_end;
-            }
-
-            // Store segment info
-            info.segments[info.num_segments] = (vaddr, filesz, memsz, offset);
-            info.num_segments += 1;
+            info.push(Segment::new(
+                ph.p_vaddr,
+                ph.p_filesz,
+                ph.p_memsz,
+                ph.p_offset,
+                ph.p_flags,
+                ph.p_align,
+                elf_data.len(),
+            )?)?;
         }
     }
 
-    if info.num_segments == 0 {
-        return Err("No LOAD segments found");
-    }
-
-    // Debug: Print extracted ELF info
-    // (need to import the print functions)
-    #[cfg(debug_assertions)]
-    {
-        // For now, just return the info - we can add debug later
-    }
-
+    info.check_layout()?;
     Ok(info)
 }
@@ -1,33 +1,15 @@
 //! ELF parser using xmas-elf crate
 //!
 //! This replaces our custom ELF parser with the well-tested xmas-elf crate.
+//! It produces the same [`ElfInfo`], with the same checks on the segments.
 
 use xmas_elf::ElfFile;
 use xmas_elf::program::Type;
 
-/// Information extracted from ELF for process creation
-pub struct ElfInfo {
-    /// Entry point address
-    pub entry_point: usize,
-    /// Segments: (vaddr, filesz, memsz, offset)
-    pub segments: [(usize, usize, usize, usize); 8],
-    /// Number of LOAD segments found
-    pub num_segments: usize,
-    /// Minimum virtual address
-    pub min_vaddr: usize,
-    /// Maximum virtual address (for total size calculation)
-    pub max_vaddr: usize,
-}
-
-impl ElfInfo {
-    /// Calculate total memory size needed
-    pub fn memory_size(&self) -> usize {
-        self.max_vaddr - self.min_vaddr
-    }
-}
+pub use crate::elf::{ElfError, ElfInfo, Segment};
 
 /// Parse ELF binary using xmas-elf
-pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
+pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, ElfError> {
     // Debug: Print that we're using xmas-elf
     unsafe {
         crate::sys_print("[xmas-elf] Starting ELF parse...\n");
@@ -42,7 +24,7 @@ pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
                 crate::sys_print(e);
                 crate::sys_print("\n");
             }
-            return Err("Failed to parse ELF");
+            return Err(ElfError::Invalid("Failed to parse ELF"));
         }
     };
 
@@ -55,13 +37,7 @@ pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
         crate::sys_print("[xmas-elf] Entry point retrieved\n");
     }
 
-    let mut info = ElfInfo {
-        entry_point,
-        segments: [(0, 0, 0, 0); 8],
-        num_segments: 0,
-        min_vaddr: usize::MAX,
-        max_vaddr: 0,
-    };
+    let mut info = ElfInfo::new(entry_point);
 
     // Process program headers
     unsafe {
@@ -72,33 +48,19 @@ pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
             crate::sys_print("[xmas-elf] Checking program header...\n");
         }
         if program_header.get_type() == Ok(Type::Load) {
-            if info.num_segments >= 8 {
-                return Err("Too many LOAD segments");
-            }
-
-            let vaddr = program_header.virtual_addr() as usize;
-            let filesz = program_header.file_size() as usize;
-            let memsz = program_header.mem_size() as usize;
-            let offset = program_header.offset() as usize;
-
-            // Track address range
-            if vaddr < info.min_vaddr {
-                info.min_vaddr = vaddr;
-            }
-            let segment_end = vaddr + memsz;
-            if segment_end > info.max_vaddr {
-                info.max_vaddr = segment_end;
-            }
-
-            // Store segment info
-            info.segments[info.num_segments] = (vaddr, filesz, memsz, offset);
-            info.num_segments += 1;
+            info.push(Segment::new(
+                program_header.virtual_addr(),
+                program_header.file_size(),
+                program_header.mem_size(),
+                program_header.offset(),
+                program_header.flags().0,
+                program_header.align(),
+                elf_data.len(),
+            )?)?;
         }
     }
 
-    if info.num_segments == 0 {
-        return Err("No LOAD segments found");
-    }
+    info.check_layout()?;
 
     // Debug output
     unsafe {
@@ -109,16 +71,17 @@ pub fn parse_elf(elf_data: &[u8]) -> Result<ElfInfo, &'static str> {
         crate::sys_print("  Segments: ");
         crate::print_number(info.num_segments);
         crate::sys_print("\n");
-        for i in 0..info.num_segments {
-            let (vaddr, filesz, memsz, _) = info.segments[i];
+        for (i, segment) in info.segments().iter().enumerate() {
             crate::sys_print("    [");
             crate::print_number(i);
             crate::sys_print("] vaddr=0x");
-            crate::print_hex(vaddr);
+            crate::print_hex(segment.vaddr);
             crate::sys_print(" filesz=0x");
-            crate::print_hex(filesz);
+            crate::print_hex(segment.filesz);
             crate::sys_print(" memsz=0x");
-            crate::print_hex(memsz);
+            crate::print_hex(segment.memsz);
+            crate::sys_print(" ");
+            crate::sys_print(segment.permissions_str());
             crate::sys_print("\n");
         }
         crate::sys_print("  Range: 0x");
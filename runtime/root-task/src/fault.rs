@@ -0,0 +1,174 @@
+//! Fault messages and crash reports
+//!
+//! The kernel reports a supervised component's faults, crashes and exits
+//! on its fault endpoint (kernel/src/ipc/fault.rs). A crash gets a report
+//! on the console: what went wrong, decoded from the syndrome, and the
+//! component's registers, instead of the component just going quiet.
+
+/// Fault kind the kernel reports for SYS_PROCESS_EXIT; the address is the
+/// exit code (`FAULT_EXIT` in kernel/src/ipc/fault.rs)
+pub const FAULT_EXIT: usize = 4;
+
+/// General purpose registers in a fault message, x0 up (`FAULT_REGS`)
+const FAULT_REGS: usize = 23;
+
+/// Size of a fault message: the five words every fault has, the registers,
+/// then FP, LR, SP and SPSR
+pub const FAULT_MESSAGE_SIZE: usize = (5 + FAULT_REGS + 4) * 8;
+
+/// The five words every fault message has; anything shorter on the fault
+/// endpoint is a boot request (see `boot`)
+pub const FAULT_HEADER_SIZE: usize = 5 * 8;
+
+/// ESR_EL1 exception classes a report names
+const EC_UNKNOWN: usize = 0x00;
+const EC_INSTRUCTION_ABORT: usize = 0x20;
+const EC_PC_ALIGNMENT: usize = 0x22;
+const EC_DATA_ABORT: usize = 0x24;
+const EC_SP_ALIGNMENT: usize = 0x26;
+const EC_BRK: usize = 0x3C;
+
+/// ESR_EL1.ISS.WnR: the data abort was a write
+const ISS_WNR: usize = 1 << 6;
+
+/// A fault message from the kernel
+pub struct FaultMessage {
+    /// TID of the faulting thread, which is its PID
+    pub tid: usize,
+    /// Fault kind
+    pub kind: usize,
+    /// Faulting address, or the exit code
+    pub addr: usize,
+    /// Faulting instruction
+    pub pc: usize,
+    /// Raw ESR_EL1 syndrome
+    pub esr: usize,
+    /// x0 to x22
+    regs: [usize; FAULT_REGS],
+    fp: usize,
+    lr: usize,
+    sp: usize,
+    spsr: usize,
+}
+
+impl FaultMessage {
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < FAULT_HEADER_SIZE {
+            return None;
+        }
+        // Registers past a short message read as 0
+        let word = |i: usize| {
+            let mut raw = [0u8; 8];
+            if let Some(bytes) = bytes.get(i * 8..i * 8 + 8) {
+                raw.copy_from_slice(bytes);
+            }
+            u64::from_le_bytes(raw) as usize
+        };
+        let trailer = 5 + FAULT_REGS;
+        Some(Self {
+            tid: word(0),
+            kind: word(1),
+            addr: word(2),
+            pc: word(3),
+            esr: word(4),
+            regs: core::array::from_fn(|i| word(5 + i)),
+            fp: word(trailer),
+            lr: word(trailer + 1),
+            sp: word(trailer + 2),
+            spsr: word(trailer + 3),
+        })
+    }
+
+    /// Whether the component ended badly: crashed, faulted, or exited with
+    /// a nonzero code
+    pub fn is_failure(&self) -> bool {
+        self.kind != FAULT_EXIT || self.addr != 0
+    }
+
+    /// Print a crash report for the component `name`
+    pub unsafe fn report(&self, name: &str) {
+        crate::sys_print("[crash] ==== ");
+        crate::sys_print(name);
+        crate::sys_print(" (PID ");
+        crate::print_number(self.tid);
+        crate::sys_print(") crashed ====\n");
+
+        crate::sys_print("[crash] ");
+        self.print_cause();
+        crate::sys_print("\n");
+
+        print_pair("PC  ", self.pc, "LR  ", self.lr);
+        print_pair("SP  ", self.sp, "FP  ", self.fp);
+        print_pair("ESR ", self.esr, "SPSR", self.spsr);
+        for (row, regs) in self.regs.chunks(4).enumerate() {
+            crate::sys_print("[crash]");
+            for (i, &value) in regs.iter().enumerate() {
+                let reg = row * 4 + i;
+                crate::sys_print(" x");
+                crate::print_number(reg);
+                crate::sys_print(if reg < 10 { "  0x" } else { " 0x" });
+                crate::print_hex(value);
+            }
+            crate::sys_print("\n");
+        }
+    }
+
+    /// What the syndrome says happened
+    unsafe fn print_cause(&self) {
+        let ec = (self.esr >> 26) & 0x3F;
+        let fsc = self.esr & 0x3F;
+        match ec {
+            EC_DATA_ABORT | EC_INSTRUCTION_ABORT => {
+                crate::sys_print(fault_status(fsc));
+                if fsc <= 0x0F {
+                    // The low bits are the translation table level
+                    crate::sys_print(" at level ");
+                    crate::print_number(fsc & 0x3);
+                }
+                crate::sys_print(match ec {
+                    EC_INSTRUCTION_ABORT => " fetching an instruction at 0x",
+                    _ if self.esr & ISS_WNR != 0 => " writing 0x",
+                    _ => " reading 0x",
+                });
+                crate::print_hex(self.addr);
+            }
+            EC_PC_ALIGNMENT => {
+                crate::sys_print("Misaligned PC 0x");
+                crate::print_hex(self.addr);
+            }
+            EC_SP_ALIGNMENT => crate::sys_print("Misaligned stack pointer"),
+            EC_UNKNOWN => crate::sys_print("Undefined instruction (a panic, or a jump into data)"),
+            EC_BRK => crate::sys_print("Breakpoint instruction"),
+            _ => {
+                crate::sys_print("Exception class 0x");
+                crate::print_hex(ec);
+            }
+        }
+    }
+}
+
+/// Name of an abort's fault status code
+fn fault_status(fsc: usize) -> &'static str {
+    match fsc {
+        0x00..=0x03 => "Address size fault",
+        0x04..=0x07 => "Translation fault (unmapped address)",
+        0x08..=0x0B => "Access flag fault",
+        0x0C..=0x0F => "Permission fault",
+        0x10 => "External abort",
+        0x21 => "Alignment fault",
+        0x30 => "TLB conflict",
+        _ => "Abort",
+    }
+}
+
+unsafe fn print_pair(first: &str, first_value: usize, second: &str, second_value: usize) {
+    crate::sys_print("[crash] ");
+    crate::sys_print(first);
+    crate::sys_print(" 0x");
+    crate::print_hex(first_value);
+    crate::sys_print("  ");
+    crate::sys_print(second);
+    crate::sys_print(" 0x");
+    crate::print_hex(second_value);
+    crate::sys_print("\n");
+}
@@ -3,5 +3,4 @@
 //! This file is auto-generated by build.nu
 //! DO NOT EDIT MANUALLY
 
-pub mod component_registry;
 pub mod memory_config;
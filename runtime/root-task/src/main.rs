@@ -18,14 +18,17 @@ extern crate alloc;
 use core::panic::PanicInfo;
 
 mod allocator;
+mod boot_block;
 mod broker_integration;
 mod component_loader;
 mod elf;
 mod elf_xmas;
+mod fault;
 mod generated;
-
-// Import ComponentError for error handling
-use component_loader::ComponentError;
+mod supervisor;
+mod boot;
+mod audit;
+mod vfs_source;
 
 /// Global IRQControl physical address (populated from boot_info)
 static mut IRQ_CONTROL_PADDR: usize = 0;
@@ -35,8 +38,11 @@ const SYS_DEBUG_PRINT: usize = 0x1001;
 const SYS_CAP_ALLOCATE: usize = 0x10;
 const SYS_MEMORY_ALLOCATE: usize = 0x11;
 const SYS_DEVICE_REQUEST: usize = 0x12;
-const SYS_ENDPOINT_CREATE: usize = 0x13;
 const SYS_PROCESS_CREATE: usize = 0x14;
+/// SYS_PROCESS_CREATE flag: map the image copy-on-write
+const PROCESS_CREATE_COW: u64 = 1 << 0;
+/// SYS_PROCESS_CREATE flag: map a boot block, described at x12
+const PROCESS_CREATE_BOOT_BLOCK: u64 = 1 << 1;
 const SYS_MEMORY_MAP: usize = 0x15;
 const SYS_MEMORY_UNMAP: usize = 0x16;
 const SYS_NOTIFICATION_CREATE: usize = 0x17;
@@ -44,10 +50,22 @@ const SYS_SIGNAL: usize = 0x18;
 const SYS_WAIT: usize = 0x19;
 const SYS_POLL: usize = 0x1A;
 const SYS_MEMORY_MAP_INTO: usize = 0x1B;
+const SYS_MEMORY_PROTECT_IN: usize = 0x45;
 const SYS_CAP_INSERT_INTO: usize = 0x1C;
 const SYS_CAP_INSERT_SELF: usize = 0x1D;
 const SYS_RETYPE: usize = 0x26;
+const SYS_CAP_DELETE: usize = 0x22;
+const SYS_CAP_REVOKE: usize = 0x1E;
+const SYS_PROCESS_DESTROY: usize = 0x2A;
 const SYS_YIELD: usize = 0x01;
+const SYS_SEND: usize = 0x02;
+const SYS_RECV: usize = 0x03;
+const SYS_REPLY: usize = 0x05;
+const SYS_TCB_SET_FAULT_HANDLER: usize = 0x27;
+const SYS_TIMER_CREATE: usize = 0x37;
+const SYS_TIMER_SET: usize = 0x38;
+const SYS_THREAD_STATS: usize = 0x2C;
+const SYS_TCB_BIND_NOTIFICATION: usize = 0x47;
 
 /// Make a syscall to print a message
 ///
@@ -158,21 +176,6 @@ unsafe fn sys_device_request(device_id: usize) -> usize {
     result
 }
 
-/// Create an IPC endpoint
-unsafe fn sys_endpoint_create() -> usize {
-    let result: usize;
-    core::arch::asm!(
-        "mov x8, {syscall_num}",
-        "svc #0",
-        "mov {result}, x0",
-        syscall_num = in(reg) SYS_ENDPOINT_CREATE,
-        result = out(reg) result,
-        out("x8") _,
-        out("x0") _,
-    );
-    result
-}
-
 /// Create a new process
 /// Result from sys_process_create containing PID and capability information
 struct ProcessCreateResult {
@@ -193,6 +196,8 @@ unsafe fn sys_process_create(
     stack_phys: usize,
     priority: u8,
     capabilities: u64,
+    flags: u64,
+    boot_block: Option<&boot_block::BootBlock>,
 ) -> ProcessCreateResult {
     let pid: usize;
     let tcb_phys: usize;
@@ -212,6 +217,8 @@ unsafe fn sys_process_create(
         in("x8") SYS_PROCESS_CREATE,
         in("x9") priority as usize,
         in("x10") capabilities as usize,
+        in("x11") flags as usize,
+        in("x12") boot_block.map_or(0, |block| block as *const boot_block::BootBlock as usize),
     );
 
     // Debug: Check what we received (avoid sys_print which causes syscalls)
@@ -335,15 +342,14 @@ pub unsafe fn print_hex(n: usize) {
     sys_print(hex_str);
 }
 
-/// Create a notification object
+/// Create a notification object, in a new slot
 unsafe fn sys_notification_create() -> usize {
     let result: usize;
     core::arch::asm!(
         "mov x8, {syscall_num}",
         "svc #0",
-        "mov {result}, x0",
         syscall_num = in(reg) SYS_NOTIFICATION_CREATE,
-        result = out(reg) result,
+        inlateout("x0") 0usize => result,
         out("x8") _,
     );
     result
@@ -415,6 +421,21 @@ unsafe fn sys_memory_map_into(
     result
 }
 
+/// Change the permissions (read=1, write=2, exec=4) of pages mapped in a
+/// target process's address space
+unsafe fn sys_memory_protect_in(target_tcb_cap: usize, virt_addr: usize, size: usize, permissions: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") target_tcb_cap => result,
+        in("x1") virt_addr,
+        in("x2") size,
+        in("x3") permissions,
+        in("x8") SYS_MEMORY_PROTECT_IN,
+    );
+    result
+}
+
 /// Insert capability into target process's CSpace (Phase 5)
 unsafe fn sys_cap_insert_into(
     target_tcb_cap: usize,
@@ -455,6 +476,171 @@ unsafe fn sys_cap_insert_self(cap_slot: usize, cap_type: usize, object_ptr: usiz
     result
 }
 
+/// Delete a capability from caller's own CSpace
+unsafe fn sys_cap_delete(cap_slot: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") 0usize => result, // own CSpace
+        in("x1") cap_slot,
+        in("x8") SYS_CAP_DELETE,
+    );
+    result
+}
+
+/// Revoke a capability in caller's own CSpace, with everything derived
+/// from it, emptying its slot
+unsafe fn sys_cap_revoke(cap_slot: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") 0usize => result, // own CSpace
+        in("x1") cap_slot,
+        in("x8") SYS_CAP_REVOKE,
+    );
+    result
+}
+
+/// Terminate a process through its TCB capability
+unsafe fn sys_process_destroy(tcb_cap_slot: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") tcb_cap_slot => result,
+        in("x8") SYS_PROCESS_DESTROY,
+    );
+    result
+}
+
+/// Send a message on an IPC endpoint (blocking)
+///
+/// Returns 0 on success, or usize::MAX on error
+unsafe fn sys_send(endpoint_cap: usize, message: &[u8]) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") endpoint_cap => result,
+        in("x1") message.as_ptr() as usize,
+        in("x2") message.len(),
+        in("x8") SYS_SEND,
+    );
+    result
+}
+
+/// Receive a message on an IPC endpoint (blocking)
+///
+/// Returns the number of bytes received, or usize::MAX on error, and the
+/// sender's badge: the signal bits, for a receive our bound notification
+/// ended (0 bytes)
+unsafe fn sys_recv(endpoint_cap: usize, buffer: &mut [u8]) -> (usize, usize) {
+    let result: usize;
+    let badge: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") endpoint_cap => result,
+        inout("x1") buffer.as_mut_ptr() as usize => badge,
+        in("x2") buffer.len(),
+        in("x8") SYS_RECV,
+    );
+    (result, badge)
+}
+
+/// Reply to the caller whose message we received last
+///
+/// Returns 0 on success, or usize::MAX on error
+unsafe fn sys_reply(message: &[u8]) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") message.as_ptr() as usize => result,
+        in("x1") message.len(),
+        in("x8") SYS_REPLY,
+    );
+    result
+}
+
+/// Bind a notification to us, so that its signals end our receives
+///
+/// Returns 0 on success, or usize::MAX on error
+unsafe fn sys_tcb_bind_notification(notification_cap: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") notification_cap => result,
+        in("x8") SYS_TCB_BIND_NOTIFICATION,
+    );
+    result
+}
+
+/// Wait for a notification (blocking)
+///
+/// Returns the signal bits, or usize::MAX on error
+unsafe fn sys_wait(notification_cap: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") notification_cap => result,
+        in("x8") SYS_WAIT,
+    );
+    result
+}
+
+/// Create a timer signaling a notification with `badge` when it expires
+///
+/// Returns the timer's capability slot, or usize::MAX on error
+unsafe fn sys_timer_create(notification_cap: usize, badge: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") notification_cap => result,
+        in("x1") badge,
+        in("x8") SYS_TIMER_CREATE,
+    );
+    result
+}
+
+/// Arm a timer to expire once after `timeout_us` microseconds
+unsafe fn sys_timer_set(timer_cap: usize, timeout_us: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") timer_cap => result,
+        in("x1") timeout_us,
+        in("x2") 0usize, // not periodic
+        in("x8") SYS_TIMER_SET,
+    );
+    result
+}
+
+/// Read the statistics of the thread at `index` in the kernel's thread list:
+/// tid, priority, state, CPU time, cycles, instructions, switches and
+/// capabilities
+///
+/// Returns 0 on success, or usize::MAX past the last thread
+unsafe fn sys_thread_stats(index: usize, stats: &mut [u64; 8]) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") index => result,
+        in("x1") stats.as_mut_ptr() as usize,
+        in("x2") core::mem::size_of_val(stats),
+        in("x8") SYS_THREAD_STATS,
+    );
+    result
+}
+
+/// Send a process's faults, crashes and exit to a fault endpoint
+unsafe fn sys_tcb_set_fault_handler(tcb_cap_slot: usize, endpoint_cap: usize) -> usize {
+    let result: usize;
+    core::arch::asm!(
+        "svc #0",
+        inout("x0") tcb_cap_slot => result,
+        in("x1") endpoint_cap,
+        in("x8") SYS_TCB_SET_FAULT_HANDLER,
+    );
+    result
+}
+
 /// Retype untyped memory into kernel object (capability-based allocation)
 ///
 /// # Arguments
@@ -486,6 +672,40 @@ unsafe fn sys_retype(
     result
 }
 
+/// Give the init component UntypedMemory of its own to spawn components
+/// from
+///
+/// Up to its `max_memory_kb` in system.toml.
+unsafe fn delegate_untyped_to_init(
+    loader: &component_loader::ComponentLoader,
+    init: &component_loader::ComponentDescriptor,
+    spawn: &component_loader::SpawnResult,
+) {
+    sys_print("[root_task] Delegating UntypedMemory to ");
+    sys_print(init.name);
+    sys_print("...\n");
+
+    // A child of our UntypedMemory, so the init component gets its own
+    // isolated region to retype from
+    const CHILD_UNTYPED_SIZE_BITS: usize = 24; // 16MB (half of our 32MB)
+
+    match loader.delegate_untyped(init, spawn, component_loader::UNTYPED_SLOT, CHILD_UNTYPED_SIZE_BITS) {
+        Ok(size) => {
+            sys_print("  ✓ ");
+            print_number(size / 1024);
+            sys_print(" KiB of UntypedMemory delegated to ");
+            sys_print(init.name);
+            sys_print("!\n");
+            sys_print("    It can now use sys_retype(10, ...) to spawn processes\n");
+        }
+        Err(e) => {
+            sys_print("  ✗ Failed to delegate UntypedMemory: ");
+            sys_print(e.as_str());
+            sys_print("\n");
+        }
+    }
+}
+
 /// Test shared memory IPC with notifications
 unsafe fn test_shared_memory_ipc() {
     sys_print("═══════════════════════════════════════════════════════════\n");
@@ -674,7 +894,9 @@ unsafe fn test_notifications() {
 /// 3. Kernel resumes TCB
 /// 4. Root task starts executing here
 /// 5. Root task calls sys_print to demonstrate userspace execution
-/// 6. Root task enters idle loop
+/// 6. Root task spawns the init component, which has it start the others
+///    (or, without one, spawns the system's components itself)
+/// 7. Root task supervises them, restarting them as system.toml says
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // Print banner and welcome message from userspace
@@ -737,96 +959,86 @@ pub extern "C" fn _start() -> ! {
     let boot_info = unsafe { &*(BOOT_INFO_VADDR as *const BootInfo) };
     let irq_control_paddr = boot_info.irq_control_paddr as usize;
 
-    // Create component loader with registry and IRQControl address
+    // Create component loader with the registry generated from system.toml
+    // and the IRQControl address
     use component_loader::{ComponentLoader, ComponentRegistry};
-    static REGISTRY: ComponentRegistry =
-        ComponentRegistry::new(generated::component_registry::COMPONENT_REGISTRY);
+    static REGISTRY: ComponentRegistry = ComponentRegistry::new(component_loader::SYSTEM_COMPONENTS);
     let loader = ComponentLoader::new(&REGISTRY, irq_control_paddr);
 
-    // Component Loading & Spawning - See docs/chapters/CHAPTER_09_STATUS.md
-    unsafe {
-        // Spawn all autostart components
-        sys_print("[root_task] Spawning components...\n");
-
-        let mut system_init_tcb_cap: Option<usize> = None;
+    // Binaries not embedded are read through the VFS service, once it runs
+    static VFS_BINARIES: vfs_source::VfsBinarySource = vfs_source::VfsBinarySource;
+    unsafe { loader.set_binary_source(&VFS_BINARIES) };
 
-        for component in generated::component_registry::get_autostart_components() {
-            sys_print("  → ");
-            sys_print(component.name);
-
-            match loader.spawn(component.name) {
-                Ok(result) => {
-                    sys_print(" (PID: ");
-                    print_number(result.pid);
-                    sys_print(")\n");
+    // Components report crashes and exits to the supervisor
+    let mut supervisor = match unsafe { supervisor::Supervisor::new(&loader) } {
+        Ok(supervisor) => Some(supervisor),
+        Err(_) => {
+            unsafe { sys_print("[root_task] ✗ Failed to set up supervision, components run unsupervised\n") };
+            None
+        }
+    };
 
-                    // Remember system_init's TCB capability for delegation
-                    if component.name == "system_init" {
-                        system_init_tcb_cap = Some(result.tcb_cap_slot);
+    // Component Loading & Spawning - See docs/chapters/CHAPTER_09_STATUS.md
+    unsafe {
+        match REGISTRY.init_component().filter(|_| supervisor.is_some()) {
+            // Phase 1: spawn the init component alone. Phase 2, starting
+            // the components it asks for, is the supervisor's (see `boot`)
+            Some(init) => {
+                sys_print("[root_task] Spawning the init component...\n");
+                sys_print("  → ");
+                sys_print(init.name);
+                match loader.spawn(init.name) {
+                    Ok(result) => {
+                        sys_print(" (PID: ");
+                        print_number(result.pid);
+                        sys_print(")\n");
+                        delegate_untyped_to_init(&loader, init, &result);
+                        if let Some(supervisor) = supervisor.as_mut() {
+                            supervisor.supervise(init, result);
+                        }
                     }
-                }
-                Err(e) => {
-                    sys_print(" - Failed: ");
-                    match e {
-                        ComponentError::NotFound => sys_print("not found"),
-                        ComponentError::NoBinary => sys_print("no binary"),
-                        ComponentError::InvalidElf => sys_print("invalid ELF"),
-                        ComponentError::OutOfMemory => sys_print("out of memory"),
-                        ComponentError::CapabilityError => sys_print("capability error"),
-                        ComponentError::NotImplemented => sys_print("not implemented"),
+                    Err(e) => {
+                        sys_print(" - Failed: ");
+                        sys_print(e.as_str());
+                        sys_print("\n");
                     }
-                    sys_print("\n");
                 }
             }
-        }
-        sys_print("\n");
-
-        // Delegate UntypedMemory capability to system_init
-        if let Some(system_init_tcb) = system_init_tcb_cap {
-            sys_print("[root_task] Delegating UntypedMemory to system_init...\n");
-
-            // Root-task has UntypedMemory cap at slot 1 (32MB region from kernel)
-            // Strategy: Retype a CHILD Untyped from parent, give it to system_init
-            // This way system_init gets its own isolated Untyped memory region
-
-            const PARENT_UNTYPED_SLOT: usize = 1;
-            const CAP_TYPE_UNTYPED: usize = 1;
-            const CHILD_UNTYPED_SIZE_BITS: usize = 24; // 16MB (half of parent's 32MB)
-            const TEMP_CHILD_UNTYPED_SLOT: usize = 50; // Temporary slot in root-task CSpace
-            const SYSTEM_INIT_UNTYPED_SLOT: usize = 10; // Target slot in system_init CSpace (slots 1-2 are occupied)
-
-            // Step 1: Create child Untyped from parent (16MB region - enough for 2-4 small components)
-            sys_print("  → Creating child Untyped (16MB) from parent...\n");
-            let child_untyped_paddr = sys_retype(
-                PARENT_UNTYPED_SLOT,        // Parent Untyped at slot 1
-                CAP_TYPE_UNTYPED,            // Create another Untyped
-                CHILD_UNTYPED_SIZE_BITS,     // 16MB (2^24)
-                0,                           // dest_cnode=0 means own CSpace
-                TEMP_CHILD_UNTYPED_SLOT,     // Put it in slot 50 temporarily
-            );
-
-            if child_untyped_paddr == usize::MAX {
-                sys_print("  ✗ Failed to create child Untyped\n");
-            } else {
-                sys_print("  ✓ Child Untyped created at phys: 0x");
-                print_hex(child_untyped_paddr);
+            // No init component, or no supervisor to take its requests:
+            // spawn all autostart components, each after the ones it
+            // depends on
+            None => {
+                sys_print("[root_task] Spawning components...\n");
+
+                loader.spawn_all(|component, result| {
+                    sys_print("  → ");
+                    sys_print(component.name);
+
+                    match result {
+                        Ok(result) => {
+                            sys_print(" (PID: ");
+                            print_number(result.pid);
+                            sys_print(")\n");
+
+                            // Right away, as it starts spawning its own components
+                            // while we wait for the next component to be ready
+                            if component.name == "system_init" {
+                                delegate_untyped_to_init(&loader, component, &result);
+                            }
+
+                            if let Some(supervisor) = supervisor.as_mut() {
+                                supervisor.supervise(component, result);
+                            }
+                        }
+                        Err(e) => {
+                            sys_print(" - Failed: ");
+                            sys_print(e.as_str());
+                            sys_print("\n");
+                        }
+                    }
+                });
                 sys_print("\n");
-
-                // Step 2: Insert child Untyped cap into system_init's CSpace
-                sys_print("  → Inserting into system_init CSpace slot 10...\n");
-                let result = sys_cap_insert_into(
-                    system_init_tcb,             // system_init's TCB cap
-                    SYSTEM_INIT_UNTYPED_SLOT,   // Slot 10 in system_init (slots 1-2 occupied)
-                    CAP_TYPE_UNTYPED,            // UntypedMemory capability
-                    child_untyped_paddr,         // Physical address of child Untyped
-                );
-
-                if result == 0 {
-                    sys_print("  ✓ UntypedMemory delegated to system_init!\n");
-                    sys_print("    system_init can now use sys_retype(10, ...) to spawn processes\n");
-                } else {
-                    sys_print("  ✗ Failed to insert cap into system_init\n");
-                }
+                audit::checkpoint(&loader);
             }
         }
         sys_print("\n");
@@ -908,11 +1120,23 @@ pub extern "C" fn _start() -> ! {
     }
     */
 
-    // Idle loop - yield to allow lower-priority components to run
-    // Note: Using yield instead of wfi because wfi doesn't release scheduler priority
-    loop {
-        unsafe {
-            sys_yield(); // Yield to scheduler - allows lower priorities to run
+    // Supervise the components and start the ones the init component asks
+    // for; blocking on the fault endpoint lets lower-priority components run
+    if let Some(supervisor) = supervisor.as_mut() {
+        unsafe { supervisor.run() }
+    }
+
+    // Nothing left to do: block on a notification no one signals, so that
+    // once the components block too the kernel idles the CPU in WFI
+    unsafe {
+        sys_print("[root_task] Idle\n");
+        let idle = sys_notification_create();
+        loop {
+            if idle == usize::MAX {
+                sys_yield();
+            } else {
+                sys_wait(idle);
+            }
         }
     }
 }
@@ -0,0 +1,523 @@
+//! Component Supervision
+//!
+//! The root task supervises the components it spawns. Each gets the
+//! supervisor's fault endpoint as its fault handler, so when it crashes,
+//! takes a page fault, or exits, the kernel blocks it and reports it there
+//! instead of halting the system or tearing it down unseen. The supervisor
+//! prints a crash report (see `fault`), destroys it and applies its restart
+//! policy from system.toml:
+//!
+//! - `never`: leave it down
+//! - `on-failure`: restart it after a crash or a nonzero exit code, at most
+//!   `max_restarts` times
+//! - `always`: restart it however it ended
+//!
+//! Restarts back off: the first waits `restart_backoff_ms`, each one after
+//! twice as long as the one before, up to [`MAX_BACKOFF_MS`]. A component
+//! stopped on purpose with `ComponentLoader::stop` is not restarted.
+//!
+//! # Hang Detection
+//!
+//! A component that crashes is reported to us by the kernel; one that hangs,
+//! deadlocked or livelocked, is not. Components with a `heartbeat_ms` in
+//! system.toml get a heartbeat notification (`kaal_sdk::component::heartbeat`)
+//! and must signal it at least that often. A watch timer, whose notification
+//! is bound to us so that it ends our receive on the fault endpoint, has us
+//! check the heartbeats; one that has missed `max_missed_heartbeats` in a row
+//! is reported as hung, with its scheduling state and the CPU time it used
+//! since its last heartbeat, which tells a component stuck waiting from one
+//! spinning. With `on_hang = "restart"` it is then destroyed and its restart
+//! policy applied, as for a crash. The timer only runs while there is a
+//! component to watch.
+//!
+//! We run at the lowest priority, so a component spinning at a higher one
+//! starves us along with everyone below it. The kernel's watchdog
+//! (`SYS_WATCHDOG_KICK`) is what catches that.
+//!
+//! # Channels
+//!
+//! Destroying a producer withdraws the channels it published, and its
+//! restarted instance sets them up afresh. The consumers still hold the old
+//! buffer, so the supervisor restarts the ones it spawned as well, and they
+//! join the new channel. Consumers another component spawned are left to
+//! their spawner.
+//!
+//! # Boot Requests
+//!
+//! The fault endpoint is also the init component's boot endpoint. The
+//! supervisor answers its requests to start and stop components (see
+//! `boot`) as they come, supervises the components it starts and leaves
+//! down the ones it stops. When the init component is done, it emits the
+//! capability audit (see `audit`).
+
+use crate::component_loader::{
+    ChannelRole, ComponentDescriptor, ComponentError, ComponentLoader, HangAction, RestartPolicy, SpawnResult,
+};
+use crate::fault::{FaultMessage, FAULT_EXIT, FAULT_HEADER_SIZE, FAULT_MESSAGE_SIZE};
+
+/// Most components the supervisor watches
+pub const MAX_SUPERVISED: usize = 16;
+
+/// Longest delay before a restart
+pub const MAX_BACKOFF_MS: u32 = 10_000;
+
+/// Signal bit of the watch timer
+const WATCH_BADGE: usize = 1 << 0;
+
+/// A supervised component
+struct Supervised {
+    descriptor: &'static ComponentDescriptor,
+    /// The running instance; None once it is left down
+    running: Option<SpawnResult>,
+    /// Restarts so far
+    restarts: u32,
+    /// Heartbeats missed in a row
+    missed: u32,
+    /// Time since its heartbeat was last checked, as the watch timer counts
+    since_check_ms: u32,
+    /// Its CPU time in µs when it missed the first of them
+    cpu_time_us: u64,
+}
+
+impl Supervised {
+    fn new(descriptor: &'static ComponentDescriptor, spawn: SpawnResult) -> Self {
+        Self { descriptor, running: Some(spawn), restarts: 0, missed: 0, since_check_ms: 0, cpu_time_us: 0 }
+    }
+}
+
+/// Watches the components the root task spawned and restarts them
+pub struct Supervisor<'a> {
+    loader: &'a ComponentLoader,
+    /// Endpoint the kernel reports the components' faults to
+    fault_endpoint: usize,
+    /// Notification the backoff timer signals
+    backoff_notification: usize,
+    /// Timer for the delay before a restart
+    backoff_timer: usize,
+    /// Timer for checking heartbeats, signaling a notification bound to
+    /// us; None if binding it failed, and hangs go undetected
+    watch_timer: Option<usize>,
+    /// Period the watch timer was last armed with, 0 if it is not
+    watch_period_ms: u32,
+    components: [Option<Supervised>; MAX_SUPERVISED],
+}
+
+impl<'a> Supervisor<'a> {
+    /// Create the fault endpoint, which the loader hands to the init
+    /// component as its boot endpoint, and the backoff timer
+    pub unsafe fn new(loader: &'a ComponentLoader) -> Result<Self, ComponentError> {
+        let (fault_endpoint, fault_endpoint_paddr) =
+            crate::component_loader::create_endpoint().ok_or(ComponentError::CapabilityError)?;
+        let backoff_notification = crate::sys_notification_create();
+        if backoff_notification == usize::MAX {
+            return Err(ComponentError::CapabilityError);
+        }
+        let backoff_timer = crate::sys_timer_create(backoff_notification, 1);
+        if backoff_timer == usize::MAX {
+            return Err(ComponentError::CapabilityError);
+        }
+
+        loader.set_boot_endpoint(fault_endpoint_paddr);
+
+        Ok(Self {
+            loader,
+            fault_endpoint,
+            backoff_notification,
+            backoff_timer,
+            watch_timer: Self::create_watch_timer(),
+            watch_period_ms: 0,
+            components: [const { None }; MAX_SUPERVISED],
+        })
+    }
+
+    /// Create the watch timer, on a notification bound to us
+    unsafe fn create_watch_timer() -> Option<usize> {
+        let notification = crate::sys_notification_create();
+        if notification == usize::MAX || crate::sys_tcb_bind_notification(notification) != 0 {
+            crate::sys_print("[supervisor] ✗ Cannot bind a notification, hangs go undetected\n");
+            return None;
+        }
+        let timer = crate::sys_timer_create(notification, WATCH_BADGE);
+        (timer != usize::MAX).then_some(timer)
+    }
+
+    /// Watch a component just spawned from `descriptor`
+    pub unsafe fn supervise(&mut self, descriptor: &'static ComponentDescriptor, spawn: SpawnResult) {
+        if crate::sys_tcb_set_fault_handler(spawn.tcb_cap_slot, self.fault_endpoint) != 0 {
+            crate::sys_print("[supervisor] ✗ Cannot supervise ");
+            crate::sys_print(descriptor.name);
+            crate::sys_print(", it runs unsupervised\n");
+            return;
+        }
+
+        // One left down and started again keeps its entry
+        let known = self
+            .components
+            .iter()
+            .position(|entry| entry.as_ref().is_some_and(|component| component.descriptor.name == descriptor.name));
+        let free = || self.components.iter().position(|entry| entry.is_none());
+        let Some(entry) = known.or_else(free).map(|index| &mut self.components[index]) else {
+            crate::sys_print("[supervisor] ✗ Too many components to supervise ");
+            crate::sys_print(descriptor.name);
+            crate::sys_print("\n");
+            return;
+        };
+        *entry = Some(Supervised::new(descriptor, spawn));
+        if descriptor.heartbeat.is_some() {
+            self.arm_watch();
+        }
+    }
+
+    /// Handle the components' crashes and exits, and the init component's
+    /// requests, forever
+    pub unsafe fn run(&mut self) -> ! {
+        crate::sys_print("[supervisor] Watching components\n");
+        let mut buffer = [0u8; FAULT_MESSAGE_SIZE];
+        loop {
+            let (received, signals) = crate::sys_recv(self.fault_endpoint, &mut buffer);
+            if received == usize::MAX {
+                crate::sys_print("[supervisor] ✗ Receive on the fault endpoint failed\n");
+                crate::sys_yield();
+                continue;
+            }
+            if received == 0 {
+                // Our bound notification, not a message
+                if signals & WATCH_BADGE != 0 {
+                    self.watch();
+                }
+            } else if received < FAULT_HEADER_SIZE {
+                self.boot_request(&buffer[..received]);
+            } else if let Some(fault) = FaultMessage::decode(&buffer[..received.min(buffer.len())]) {
+                self.handle(&fault);
+            }
+        }
+    }
+
+    /// Answer a request from the init component, and supervise the
+    /// component it has us start, or leave down the one it has us stop
+    unsafe fn boot_request(&mut self, request: &[u8]) {
+        let mut reply = [0u8; crate::boot::MAX_REPLY];
+        let handled = crate::boot::handle(self.loader, request, &mut reply);
+        if let Some((descriptor, spawn)) = handled.started {
+            self.supervise(descriptor, spawn);
+        }
+        if let Some(descriptor) = handled.stopped {
+            let stopped = self.components.iter_mut().flatten().find(|c| c.descriptor.name == descriptor.name);
+            if let Some(component) = stopped {
+                component.running = None;
+            }
+        }
+        if crate::sys_reply(&reply[..handled.len]) != 0 {
+            crate::sys_print("[supervisor] ✗ Failed to answer a boot request\n");
+        }
+        if handled.done {
+            crate::audit::checkpoint(self.loader);
+        }
+    }
+
+    /// Reap the component that faulted and apply its restart policy
+    unsafe fn handle(&mut self, fault: &FaultMessage) {
+        let Some(index) = self.index_of(fault.tid) else {
+            crate::sys_print("[supervisor] Fault from unknown PID ");
+            crate::print_number(fault.tid);
+            crate::sys_print("\n");
+            return;
+        };
+        let Some(component) = self.components[index].as_ref() else {
+            return;
+        };
+        let name = component.descriptor.name;
+
+        if fault.kind == FAULT_EXIT {
+            crate::sys_print("[supervisor] ");
+            crate::sys_print(name);
+            crate::sys_print(" exited with code ");
+            crate::print_number(fault.addr);
+            crate::sys_print("\n");
+        } else {
+            fault.report(name);
+        }
+        self.reap(index, fault.is_failure());
+    }
+
+    /// Destroy a supervised component that crashed, exited or hung, and
+    /// restart it if its policy says so
+    unsafe fn reap(&mut self, index: usize, failed: bool) {
+        let Some(component) = self.components[index].as_mut() else {
+            return;
+        };
+        let name = component.descriptor.name;
+
+        if let Some(spawn) = component.running.take() {
+            if self.loader.kill(&spawn).is_err() {
+                crate::sys_print("[supervisor] ✗ Failed to destroy ");
+                crate::sys_print(name);
+                crate::sys_print("\n");
+            }
+        }
+
+        let (restart, backoff_ms) = match component.descriptor.restart {
+            RestartPolicy::Never => (false, 0),
+            RestartPolicy::OnFailure { max_restarts, backoff_ms } => {
+                (failed && component.restarts < max_restarts, backoff_ms)
+            }
+            RestartPolicy::Always { backoff_ms } => (true, backoff_ms),
+        };
+        if !restart {
+            crate::sys_print("[supervisor] ");
+            crate::sys_print(name);
+            crate::sys_print(" left down\n");
+            return;
+        }
+
+        // Doubled for each restart so far, within MAX_BACKOFF_MS
+        let delay_ms = (u64::from(backoff_ms) << component.restarts.min(16)).min(u64::from(MAX_BACKOFF_MS)) as u32;
+        component.restarts += 1;
+
+        crate::sys_print("[supervisor] Restarting ");
+        crate::sys_print(name);
+        crate::sys_print(" in ");
+        crate::print_number(delay_ms as usize);
+        crate::sys_print(" ms (restart ");
+        crate::print_number(component.restarts as usize);
+        crate::sys_print(")\n");
+        self.sleep_ms(delay_ms);
+
+        if self.respawn(index) {
+            self.rejoin_consumers(index);
+        }
+    }
+
+    /// Spawn a supervised component again; returns whether it is running
+    unsafe fn respawn(&mut self, index: usize) -> bool {
+        let Some(component) = self.components[index].as_mut() else {
+            return false;
+        };
+        let descriptor = component.descriptor;
+        match self.loader.spawn(descriptor.name) {
+            Ok(spawn) => {
+                component.running = Some(spawn);
+                component.missed = 0;
+                component.since_check_ms = 0;
+                if crate::sys_tcb_set_fault_handler(spawn.tcb_cap_slot, self.fault_endpoint) != 0 {
+                    crate::sys_print("[supervisor] ✗ Cannot supervise ");
+                    crate::sys_print(descriptor.name);
+                    crate::sys_print(" any more\n");
+                }
+                crate::sys_print("[supervisor] ");
+                crate::sys_print(descriptor.name);
+                crate::sys_print(" restarted (PID: ");
+                crate::print_number(spawn.pid);
+                crate::sys_print(")\n");
+                true
+            }
+            Err(e) => {
+                crate::sys_print("[supervisor] ✗ Failed to restart ");
+                crate::sys_print(descriptor.name);
+                crate::sys_print(": ");
+                crate::sys_print(e.as_str());
+                crate::sys_print("\n");
+                false
+            }
+        }
+    }
+
+    /// Restart the running consumers of the channels a restarted component
+    /// produces, so they join its new channels
+    unsafe fn rejoin_consumers(&mut self, producer: usize) {
+        let Some(descriptor) = self.components[producer].as_ref().map(|c| c.descriptor) else {
+            return;
+        };
+        for channel in descriptor.channels.iter().filter(|c| c.role == ChannelRole::Producer) {
+            crate::sys_print("[supervisor] Channel ");
+            crate::sys_print(channel.name);
+            crate::sys_print(" set up again\n");
+
+            for index in 0..MAX_SUPERVISED {
+                let Some(consumer) = self.components[index].as_mut() else {
+                    continue;
+                };
+                let consumes = consumer
+                    .descriptor
+                    .channels
+                    .iter()
+                    .any(|c| c.name == channel.name && c.role == ChannelRole::Consumer);
+                let Some(spawn) = consumer.running.filter(|_| consumes) else {
+                    continue;
+                };
+
+                crate::sys_print("[supervisor] Restarting consumer ");
+                crate::sys_print(consumer.descriptor.name);
+                crate::sys_print("\n");
+                consumer.running = None;
+                if self.loader.kill(&spawn).is_ok() {
+                    self.respawn(index);
+                }
+            }
+        }
+    }
+
+    /// Check the heartbeats of the components watched for hangs that are
+    /// due, and arm the watch timer for the next check
+    unsafe fn watch(&mut self) {
+        let period_ms = self.watch_period_ms;
+        self.watch_period_ms = 0;
+
+        for index in 0..MAX_SUPERVISED {
+            let Some(component) = self.components[index].as_mut() else {
+                continue;
+            };
+            let Some(heartbeat) = component.descriptor.heartbeat.filter(|_| component.running.is_some()) else {
+                continue;
+            };
+            component.since_check_ms = component.since_check_ms.saturating_add(period_ms);
+            if component.since_check_ms < heartbeat.interval_ms {
+                continue;
+            }
+            component.since_check_ms = 0;
+
+            // Not handed one, or stopped with ComponentLoader::stop
+            let Some(notification) = self.loader.heartbeat(component.descriptor.name) else {
+                continue;
+            };
+            let beats = crate::sys_poll(notification);
+            if beats != 0 && beats != usize::MAX {
+                component.missed = 0;
+                continue;
+            }
+
+            component.missed += 1;
+            let pid = component.running.map_or(0, |spawn| spawn.pid);
+            if component.missed == 1 {
+                component.cpu_time_us = thread_stats(pid).map_or(0, |stats| stats[STAT_CPU_TIME]);
+            }
+            if component.missed >= heartbeat.max_missed {
+                self.hung(index);
+            }
+        }
+
+        self.arm_watch();
+    }
+
+    /// Report a component that has missed too many heartbeats, and restart
+    /// it if it is to be
+    unsafe fn hung(&mut self, index: usize) {
+        let Some(component) = self.components[index].as_mut() else {
+            return;
+        };
+        let (Some(heartbeat), Some(spawn)) = (component.descriptor.heartbeat, component.running) else {
+            return;
+        };
+        let name = component.descriptor.name;
+
+        crate::sys_print("[supervisor] ✗ ");
+        crate::sys_print(name);
+        crate::sys_print(" (PID: ");
+        crate::print_number(spawn.pid);
+        crate::sys_print(") has hung: ");
+        crate::print_number(component.missed as usize);
+        crate::sys_print(" heartbeats missed, ");
+        crate::print_number(heartbeat.interval_ms as usize);
+        crate::sys_print(" ms apart\n");
+        if let Some(stats) = thread_stats(spawn.pid) {
+            crate::sys_print("  state: ");
+            crate::sys_print(state_name(stats[STAT_STATE]));
+            crate::sys_print(", priority ");
+            crate::print_number(stats[STAT_PRIORITY] as usize);
+            crate::sys_print("\n  CPU time since its last heartbeat: ");
+            crate::print_number(stats[STAT_CPU_TIME].saturating_sub(component.cpu_time_us) as usize);
+            crate::sys_print(" µs, scheduled ");
+            crate::print_number(stats[STAT_SWITCHES] as usize);
+            crate::sys_print(" times in all\n");
+        }
+        component.missed = 0;
+
+        if heartbeat.on_hang == HangAction::Restart {
+            self.reap(index, true);
+        }
+    }
+
+    /// Arm the watch timer for the next heartbeat check, if a running
+    /// component is watched
+    ///
+    /// The period is the shortest heartbeat interval, so the others are
+    /// checked at most a period late.
+    unsafe fn arm_watch(&mut self) {
+        let Some(timer) = self.watch_timer else {
+            return;
+        };
+        let period_ms = self
+            .components
+            .iter()
+            .flatten()
+            .filter(|component| component.running.is_some())
+            .filter_map(|component| component.descriptor.heartbeat)
+            .map(|heartbeat| heartbeat.interval_ms)
+            .min();
+        if let Some(period_ms) = period_ms {
+            if crate::sys_timer_set(timer, period_ms as usize * 1000) == 0 {
+                self.watch_period_ms = period_ms;
+            }
+        }
+    }
+
+    /// Block for `ms` milliseconds on the backoff timer
+    unsafe fn sleep_ms(&self, ms: u32) {
+        if ms == 0 {
+            return;
+        }
+        if crate::sys_timer_set(self.backoff_timer, ms as usize * 1000) != 0 {
+            return;
+        }
+        crate::sys_wait(self.backoff_notification);
+    }
+
+    /// Supervised component whose running instance has `pid`
+    ///
+    /// One stopped with `ComponentLoader::stop` is no longer running as far
+    /// as the loader knows, and is left alone: its PID may belong to
+    /// another process by now.
+    fn index_of(&self, pid: usize) -> Option<usize> {
+        self.components.iter().position(|entry| {
+            entry.as_ref().is_some_and(|component| {
+                component.running.is_some_and(|spawn| spawn.pid == pid)
+                    && self.loader.running(component.descriptor.name).is_some_and(|spawn| spawn.pid == pid)
+            })
+        })
+    }
+}
+
+/// Fields of a `sys_thread_stats` record
+const STAT_PRIORITY: usize = 1;
+const STAT_STATE: usize = 2;
+const STAT_CPU_TIME: usize = 3;
+const STAT_SWITCHES: usize = 6;
+
+/// Statistics of the thread `pid`, if it is alive
+unsafe fn thread_stats(pid: usize) -> Option<[u64; 8]> {
+    let mut stats = [0u64; 8];
+    for index in 0.. {
+        if crate::sys_thread_stats(index, &mut stats) != 0 {
+            return None;
+        }
+        if stats[0] as usize == pid {
+            return Some(stats);
+        }
+    }
+    None
+}
+
+/// What a thread in the state `state` (`sys_thread_stats`) is doing
+fn state_name(state: u64) -> &'static str {
+    match state {
+        0 => "inactive",
+        1 => "running",
+        2 => "runnable",
+        3 => "blocked receiving",
+        4 => "blocked sending",
+        5 => "blocked waiting for a reply",
+        6 => "blocked on a notification",
+        7 => "blocked on a fault",
+        _ => "unknown",
+    }
+}
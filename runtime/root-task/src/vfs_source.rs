@@ -0,0 +1,83 @@
+//! Component binaries from the VFS service
+//!
+//! Components not embedded in the root task (`embed = false` in
+//! system.toml) are read from `/components/<binary>` through the VFS
+//! service, on the endpoint we hand it (`vfs:serve`). Until it is running
+//! there is nothing to read them from, and spawning them fails with
+//! `ComponentError::NoBinary`; the VFS service itself must be embedded.
+//!
+//! Reading is a call to the VFS service, which we block in, so it must not
+//! wait on us meanwhile: no faults, and no requests on the boot endpoint.
+
+use kaal_ipc::rpc::Client;
+use kaal_ipc::vfs::{flags, Bytes, EntryKind, Request, Response, MAX_DATA, MAX_PATH};
+
+use crate::component_loader::{self, BinarySource};
+
+/// Directory the binaries are in
+const BINARY_DIR: &str = "/components";
+
+/// Reads binaries from [`BINARY_DIR`] through the VFS service
+pub struct VfsBinarySource;
+
+impl VfsBinarySource {
+    /// A client of the VFS service, while it is running
+    fn client() -> Option<Client<Request, Response>> {
+        component_loader::vfs_endpoint().map(|slot| Client::new(slot as u64))
+    }
+}
+
+/// Path of the binary named `binary`
+fn path(binary: &str) -> Option<Bytes<MAX_PATH>> {
+    let mut path = [0u8; MAX_PATH];
+    let dir = BINARY_DIR.len();
+    let len = dir + 1 + binary.len();
+    if len > MAX_PATH {
+        return None;
+    }
+    path[..dir].copy_from_slice(BINARY_DIR.as_bytes());
+    path[dir] = b'/';
+    path[dir + 1..len].copy_from_slice(binary.as_bytes());
+    Bytes::from_slice(&path[..len])
+}
+
+impl BinarySource for VfsBinarySource {
+    fn len(&self, binary: &str) -> Option<usize> {
+        let vfs = Self::client()?;
+        let dir = Bytes::from_slice(BINARY_DIR.as_bytes())?;
+        (0..)
+            .map_while(|index| match vfs.call(&Request::ReadDir { path: dir, index }) {
+                Ok(Response::Entry { kind, size, name }) => Some((kind, size, name)),
+                _ => None,
+            })
+            .find(|(kind, _, name)| *kind == EntryKind::File && name.as_slice() == binary.as_bytes())
+            .and_then(|(_, size, _)| usize::try_from(size).ok())
+    }
+
+    fn read(&self, binary: &str, buf: &mut [u8]) -> bool {
+        let (Some(vfs), Some(path)) = (Self::client(), path(binary)) else {
+            return false;
+        };
+        let handle = match vfs.call(&Request::Open { path, flags: flags::READ }) {
+            Ok(Response::Opened { handle }) => handle,
+            _ => return false,
+        };
+
+        let mut filled = 0;
+        while filled < buf.len() {
+            let len = (buf.len() - filled).min(MAX_DATA);
+            match vfs.call(&Request::Read { handle, len: len as u32 }) {
+                Ok(Response::Data(data)) if !data.as_slice().is_empty() && data.as_slice().len() <= len => {
+                    let data = data.as_slice();
+                    buf[filled..filled + data.len()].copy_from_slice(data);
+                    filled += data.len();
+                }
+                // An error, or the file is shorter than it was listed
+                _ => break,
+            }
+        }
+
+        let _ = vfs.call(&Request::Close { handle });
+        filled == buf.len()
+    }
+}
@@ -0,0 +1,1125 @@
+//! KaaL Compose - the system manifest and the code generated from it
+//!
+//! A KaaL system is described by `system.toml` at the project root: which
+//! components there are, where their binaries come from, their priority
+//! and capabilities, whether they start at boot, which channels they
+//! produce or consume, what they wait for at startup, whether the root task
+//! restarts them or watches them for hangs, the most it may give them, and
+//! their arguments and environment. The root task's build
+//! script reads it with this crate and generates the component registry
+//! its `ComponentLoader` spawns from, so adding or changing a component
+//! only means editing the manifest.
+//!
+//! # Manifest
+//! ```toml
+//! [[component]]
+//! name = "uart_driver"            # Unique component identifier
+//! binary = "uart-driver"          # Crate under components/, and its binary
+//! type = "driver"                 # driver | service | application
+//! priority = 50                   # 0-255, lower runs first
+//! autostart = true                # Spawn at boot (default false)
+//! spawned_by = "system_init"      # Spawner, if not the root task
+//! capabilities = ["irq:control", "memory:map"]
+//! channels = [
+//!     { name = "kaal.uart.output", role = "producer" },
+//! ]
+//! restart = "on-failure"          # never | on-failure | always (default never)
+//! max_restarts = 5                # on-failure: give up after this many
+//! restart_backoff_ms = 100        # First restart delay, doubling each time
+//! heartbeat_ms = 500              # Watched for hangs: signals a heartbeat this often
+//! max_missed_heartbeats = 3       # Hung after missing this many in a row
+//! on_hang = "restart"             # dump | restart (default dump)
+//! max_memory_kb = 4096            # Most untyped memory delegated to it
+//! max_cap_slots = 8               # Most capabilities delegated to it
+//! priority_ceiling = 50           # Highest priority (lowest number) it may have
+//! args = ["--baud", "115200"]     # After its name, in kaal_sdk::env::args()
+//! env = { LOG_LEVEL = "debug" }   # In kaal_sdk::env::var()
+//! embed = false                   # Read from the filesystem when spawned (default true)
+//!
+//! [[component]]
+//! name = "notepad"
+//! # ...
+//! depends_on = ["uart_driver"]    # Spawned once these report ready
+//! ```
+//!
+//! # Example
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let manifest = kaal_compose::SystemManifest::load(Path::new("system.toml"))?;
+//! let registry = manifest.root_task_registry(Path::new("."));
+//! std::fs::write("component_registry.rs", registry)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Longest channel name the channel broker takes
+pub const MAX_CHANNEL_NAME: usize = 32;
+
+/// Kernel capability bit for memory allocation and mapping (`TCB::CAP_MEMORY`)
+pub const CAP_MEMORY: u64 = 1 << 0;
+/// Kernel capability bit for creating processes
+pub const CAP_PROCESS: u64 = 1 << 1;
+/// Kernel capability bit for endpoints and notifications
+pub const CAP_IPC: u64 = 1 << 2;
+/// Kernel capability bit for capability management
+pub const CAP_CAPS: u64 = 1 << 3;
+/// Kernel capability bit for scheduling domain control
+pub const CAP_DOMAIN: u64 = 1 << 4;
+/// Kernel capability bit for reading the kernel log
+pub const CAP_KLOG: u64 = 1 << 5;
+/// Delegation of IRQControl by the root task
+pub const CAP_IRQ_CONTROL: u64 = 1 << 10;
+/// Receiving on the log endpoint from the root task, as the log server
+pub const CAP_LOG_SERVER: u64 = 1 << 11;
+/// Starting the other components for the root task, as the init component
+pub const CAP_BOOT_INIT: u64 = 1 << 12;
+/// Starting and stopping the root task's components, through its boot endpoint
+pub const CAP_BOOT_CONTROL: u64 = 1 << 13;
+/// Receiving on the VFS endpoint from the root task, as the VFS service
+pub const CAP_VFS_SERVER: u64 = 1 << 14;
+
+/// Spawner name for components the root task spawns itself
+const ROOT_SPAWNER: &str = "root";
+
+/// Room for arguments and environment in a boot block
+/// (`kaal_sdk::env::BOOT_BLOCK_SIZE` less its header and capability map)
+pub const MAX_BOOT_STRINGS: usize = 4096 - 192;
+
+/// Restarts an `on-failure` component gets unless `max_restarts` is set
+pub const DEFAULT_MAX_RESTARTS: u32 = 5;
+
+/// First restart delay unless `restart_backoff_ms` is set
+pub const DEFAULT_RESTART_BACKOFF_MS: u32 = 100;
+
+/// Heartbeats a watched component may miss in a row unless
+/// `max_missed_heartbeats` is set
+pub const DEFAULT_MAX_MISSED_HEARTBEATS: u32 = 3;
+
+/// Shortest heartbeat interval, well above the kernel's 5 ms timer tick
+/// that the root task's checks are rounded to
+pub const MIN_HEARTBEAT_MS: u32 = 50;
+
+/// A whole system: `system.toml`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemManifest {
+    /// Components, in spawn order
+    #[serde(rename = "component", default)]
+    pub components: Vec<Component>,
+}
+
+/// One `[[component]]` entry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Component {
+    /// Unique component identifier
+    pub name: String,
+    /// Binary name, which is also its crate's directory under `components/`
+    pub binary: String,
+    /// What kind of component it is
+    #[serde(rename = "type")]
+    pub component_type: ComponentType,
+    /// Scheduling priority (lower runs first)
+    pub priority: u8,
+    /// Spawn automatically at boot
+    #[serde(default)]
+    pub autostart: bool,
+    /// Component that spawns it; the root task if unset
+    #[serde(default)]
+    pub spawned_by: Option<String>,
+    /// Required capabilities, such as `"memory:map"` or `"irq:control"`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Channels it produces or consumes
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+    /// When the root task restarts it after it crashes or exits
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Restarts an `on-failure` component gets before it is left down
+    #[serde(default)]
+    pub max_restarts: Option<u32>,
+    /// Delay before the first restart, doubled for each one after
+    #[serde(default)]
+    pub restart_backoff_ms: Option<u32>,
+    /// How often it signals a heartbeat, for the root task to watch it for
+    /// hangs; not watched if unset
+    #[serde(default)]
+    pub heartbeat_ms: Option<u32>,
+    /// Heartbeats it may miss in a row before it counts as hung
+    #[serde(default)]
+    pub max_missed_heartbeats: Option<u32>,
+    /// What the root task does once it has hung
+    #[serde(default)]
+    pub on_hang: Option<HangAction>,
+    /// Components that must have signaled readiness before it is spawned
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Most untyped memory the root task delegates to it, in KiB
+    #[serde(default)]
+    pub max_memory_kb: Option<u32>,
+    /// Most capabilities the root task delegates into its CSpace
+    #[serde(default)]
+    pub max_cap_slots: Option<u32>,
+    /// Highest priority it may be given: its priority number may not be
+    /// lower
+    #[serde(default)]
+    pub priority_ceiling: Option<u8>,
+    /// Arguments it is spawned with, after its name
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables it is spawned with
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Build its binary into the root task; if not, the root task reads it
+    /// from the filesystem when it spawns it
+    #[serde(default = "embed_by_default")]
+    pub embed: bool,
+}
+
+fn embed_by_default() -> bool {
+    true
+}
+
+/// Component type classification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentType {
+    /// Device driver (hardware access)
+    Driver,
+    /// System service (no hardware)
+    Service,
+    /// User application
+    Application,
+}
+
+/// When a crashed or exited component is restarted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave it down
+    #[default]
+    Never,
+    /// Restart it if it crashed or exited with a nonzero code
+    OnFailure,
+    /// Restart it however it ended
+    Always,
+}
+
+/// What the root task does with a component that has hung
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HangAction {
+    /// Report it and leave it running
+    #[default]
+    Dump,
+    /// Report it, destroy it and apply its restart policy
+    Restart,
+}
+
+/// A channel a component is on
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Channel {
+    /// Name the channel is registered under with the broker
+    pub name: String,
+    /// Which end the component is
+    pub role: ChannelRole,
+}
+
+/// End of a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelRole {
+    /// Sets the channel up and writes to it
+    Producer,
+    /// Joins the channel and reads from it
+    Consumer,
+}
+
+/// Why a manifest could not be used
+#[derive(Debug)]
+pub enum Error {
+    /// The manifest could not be read
+    Io(PathBuf, std::io::Error),
+    /// The manifest is not valid TOML, or has missing or unknown fields
+    Parse(toml::de::Error),
+    /// The manifest is well-formed but describes an impossible system
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "cannot read {}: {e}", path.display()),
+            Self::Parse(e) => write!(f, "{e}"),
+            Self::Invalid(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl SystemManifest {
+    /// Read and validate the manifest at `path`
+    ///
+    /// # Errors
+    /// Returns why the file cannot be read, parsed, or is not a valid
+    /// system
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+        Self::parse(&contents)
+    }
+
+    /// Parse and validate a manifest
+    ///
+    /// # Errors
+    /// Returns why the text is not valid TOML or not a valid system
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let manifest: Self = toml::from_str(contents).map_err(Error::Parse)?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Check what the types alone cannot: unique names, known spawners,
+    /// that every channel has exactly one producer, and that the
+    /// dependencies can be started first
+    fn validate(&self) -> Result<(), Error> {
+        if self.components.is_empty() {
+            return invalid("no [[component]] entries".into());
+        }
+
+        let mut names = BTreeMap::new();
+        for component in &self.components {
+            if component.name.is_empty() || component.binary.is_empty() {
+                return invalid("components need a name and a binary".into());
+            }
+            if names.insert(component.name.as_str(), component).is_some() {
+                return invalid(format!("component `{}` is declared twice", component.name));
+            }
+        }
+
+        let mut producers: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut consumers: BTreeMap<&str, &str> = BTreeMap::new();
+        for component in &self.components {
+            if let Some(spawner) = component.spawned_by.as_deref() {
+                if spawner != ROOT_SPAWNER && !names.contains_key(spawner) {
+                    return invalid(format!(
+                        "component `{}` is spawned by `{spawner}`, which is not a component",
+                        component.name
+                    ));
+                }
+            }
+            component.validate_restart()?;
+            component.validate_heartbeat()?;
+            component.validate_limits()?;
+            component.validate_boot_strings()?;
+
+            for (i, channel) in component.channels.iter().enumerate() {
+                let name = channel.name.as_str();
+                if name.is_empty() || name.len() > MAX_CHANNEL_NAME {
+                    return invalid(format!(
+                        "channel `{name}` of `{}`: names must be 1-{MAX_CHANNEL_NAME} characters",
+                        component.name
+                    ));
+                }
+                if component.channels[..i].iter().any(|earlier| earlier.name == name) {
+                    return invalid(format!("component `{}` lists channel `{name}` twice", component.name));
+                }
+                match channel.role {
+                    ChannelRole::Producer => {
+                        if let Some(other) = producers.insert(name, &component.name) {
+                            return invalid(format!(
+                                "channel `{name}` has two producers, `{other}` and `{}`",
+                                component.name
+                            ));
+                        }
+                    }
+                    ChannelRole::Consumer => {
+                        consumers.entry(name).or_insert(&component.name);
+                    }
+                }
+            }
+        }
+
+        if let Some((name, consumer)) = consumers.iter().find(|(name, _)| !producers.contains_key(*name)) {
+            return invalid(format!("`{consumer}` consumes channel `{name}`, which nothing produces"));
+        }
+        self.validate_log_server()?;
+        self.validate_boot_init()?;
+        self.validate_boot_control()?;
+        self.validate_vfs_server()?;
+        self.validate_dependencies(&names)
+    }
+
+    /// The root task hands the log endpoint to one log server (`log:serve`),
+    /// which it must spawn itself
+    fn validate_log_server(&self) -> Result<(), Error> {
+        let mut servers = self
+            .components
+            .iter()
+            .filter(|component| component.capabilities_bitmask() & CAP_LOG_SERVER != 0);
+        if let Some(server) = servers.clone().find(|server| !server.is_root_spawned()) {
+            return invalid(format!(
+                "component `{}` is a log server, but only the root task hands out the log endpoint",
+                server.name
+            ));
+        }
+        if let (Some(first), Some(second)) = (servers.next(), servers.next()) {
+            return invalid(format!("two log servers, `{}` and `{}`", first.name, second.name));
+        }
+        Ok(())
+    }
+
+    /// The root task spawns one init component (`boot:init`) at boot and
+    /// starts the others at its request, so there is at most one, and the
+    /// root task spawns it at boot
+    fn validate_boot_init(&self) -> Result<(), Error> {
+        let mut inits = self
+            .components
+            .iter()
+            .filter(|component| component.capabilities_bitmask() & CAP_BOOT_INIT != 0);
+        if let Some(init) = inits.clone().find(|init| !init.is_root_spawned() || !init.autostart) {
+            return invalid(format!(
+                "component `{}` is the init component, which the root task must spawn at boot",
+                init.name
+            ));
+        }
+        if let (Some(first), Some(second)) = (inits.next(), inits.next()) {
+            return invalid(format!("two init components, `{}` and `{}`", first.name, second.name));
+        }
+        Ok(())
+    }
+
+    /// Only the root task hands out its boot endpoint (`boot:control`)
+    fn validate_boot_control(&self) -> Result<(), Error> {
+        let controller = self.components.iter().find(|component| {
+            component.capabilities_bitmask() & CAP_BOOT_CONTROL != 0 && !component.is_root_spawned()
+        });
+        match controller {
+            Some(controller) => invalid(format!(
+                "component `{}` starts and stops components, but only the root task hands out its boot endpoint",
+                controller.name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The root task hands the VFS endpoint to one VFS service (`vfs:serve`),
+    /// which it must spawn itself
+    fn validate_vfs_server(&self) -> Result<(), Error> {
+        let mut servers = self
+            .components
+            .iter()
+            .filter(|component| component.capabilities_bitmask() & CAP_VFS_SERVER != 0);
+        if let Some(server) = servers.clone().find(|server| !server.is_root_spawned()) {
+            return invalid(format!(
+                "component `{}` is a VFS service, but only the root task hands out the VFS endpoint",
+                server.name
+            ));
+        }
+        if let (Some(first), Some(second)) = (servers.next(), servers.next()) {
+            return invalid(format!("two VFS services, `{}` and `{}`", first.name, second.name));
+        }
+        Ok(())
+    }
+
+    /// Dependencies must be components the root task spawns, as it is the
+    /// one waiting for them, and must not form a cycle
+    fn validate_dependencies(&self, names: &BTreeMap<&str, &Component>) -> Result<(), Error> {
+        for component in &self.components {
+            for (i, dependency) in component.depends_on.iter().enumerate() {
+                let Some(spawned) = names.get(dependency.as_str()) else {
+                    return invalid(format!(
+                        "component `{}` depends on `{dependency}`, which is not a component",
+                        component.name
+                    ));
+                };
+                if !spawned.is_root_spawned() {
+                    return invalid(format!(
+                        "component `{}` depends on `{dependency}`, but only components the root task spawns \
+                         can be waited for",
+                        component.name
+                    ));
+                }
+                if component.depends_on[..i].contains(dependency) {
+                    return invalid(format!("component `{}` lists dependency `{dependency}` twice", component.name));
+                }
+            }
+        }
+
+        let roots: Vec<&Component> = self.root_task_components().collect();
+        for component in roots.iter().filter(|component| component.autostart) {
+            if let Some(dependency) = self
+                .startup_dependencies(component)
+                .into_iter()
+                .find(|dependency| !names[dependency].autostart)
+            {
+                return invalid(format!(
+                    "component `{}` starts at boot, but `{dependency}`, which it waits for, does not",
+                    component.name
+                ));
+            }
+        }
+
+        // Start whatever has its dependencies started until nothing is left,
+        // or only components waiting for each other
+        let mut started: Vec<&str> = Vec::new();
+        while started.len() < roots.len() {
+            let next = roots.iter().find(|component| {
+                !started.contains(&component.name.as_str())
+                    && self.startup_dependencies(component).iter().all(|dependency| started.contains(dependency))
+            });
+            match next {
+                Some(component) => started.push(&component.name),
+                None => {
+                    let waiting: Vec<&str> = roots
+                        .iter()
+                        .map(|component| component.name.as_str())
+                        .filter(|name| !started.contains(name))
+                        .collect();
+                    return invalid(format!("components `{}` wait for each other", waiting.join("`, `")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// What the root task waits for before spawning `component`: its
+    /// dependencies and those of every component it spawns, directly or
+    /// not, other than itself
+    pub fn startup_dependencies(&self, component: &Component) -> Vec<&str> {
+        let mut dependencies: Vec<&str> = Vec::new();
+        for spawned in &self.components {
+            if spawned.name != component.name && !self.spawned_under(spawned, &component.name) {
+                continue;
+            }
+            for dependency in &spawned.depends_on {
+                if *dependency != component.name && !dependencies.contains(&dependency.as_str()) {
+                    dependencies.push(dependency);
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// Whether `component` is spawned by `spawner` or by a component under it
+    fn spawned_under(&self, component: &Component, spawner: &str) -> bool {
+        let mut current = component;
+        // Spawners could name each other in a loop
+        for _ in 0..self.components.len() {
+            let Some(parent) = current.spawned_by.as_deref() else {
+                return false;
+            };
+            if parent == spawner {
+                return true;
+            }
+            match self.components.iter().find(|other| other.name == parent) {
+                Some(next) => current = next,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Components the root task spawns itself, in spawn order
+    pub fn root_task_components(&self) -> impl Iterator<Item = &Component> {
+        self.components.iter().filter(|component| component.is_root_spawned())
+    }
+
+    /// Rust source for the root task's registry: a
+    /// `&[ComponentDescriptor]` expression to `include!`
+    ///
+    /// Binaries are embedded from `project_root` if they have been built;
+    /// a component whose binary is missing or that sets `embed = false`
+    /// gets `binary_data: None`, and the root task reads its binary from
+    /// the filesystem when it spawns it. Each component's `depends_on` is its
+    /// [`startup_dependencies`](Self::startup_dependencies).
+    /// `ComponentDescriptor`, `ComponentType`, `ChannelDescriptor`,
+    /// `ChannelRole`, `RestartPolicy`, `Heartbeat`, `HangAction` and
+    /// `ResourceLimits` must be in scope where the source is included.
+    pub fn root_task_registry(&self, project_root: &Path) -> String {
+        let mut out = String::from("&[\n");
+        for component in self.root_task_components() {
+            let capabilities: Vec<String> = component.capabilities.iter().map(|cap| format!("{cap:?}")).collect();
+            let channels: Vec<String> = component
+                .channels
+                .iter()
+                .map(|channel| {
+                    format!(
+                        "ChannelDescriptor {{ name: {:?}, role: ChannelRole::{:?} }}",
+                        channel.name, channel.role
+                    )
+                })
+                .collect();
+            let depends_on: Vec<String> = self
+                .startup_dependencies(component)
+                .iter()
+                .map(|dependency| format!("{dependency:?}"))
+                .collect();
+            let args: Vec<String> = component.args.iter().map(|arg| format!("{arg:?}")).collect();
+            let env: Vec<String> = component.env.iter().map(|(key, value)| format!("({key:?}, {value:?})")).collect();
+            let binary = component.binary_path(project_root);
+            let binary_data = if component.embed && binary.exists() {
+                format!("Some(include_bytes!({:?}))", binary.display().to_string())
+            } else {
+                "None".to_owned()
+            };
+
+            // Writing to a String cannot fail
+            let _ = write!(
+                out,
+                "    ComponentDescriptor {{
+        name: {name:?},
+        binary: {binary_name:?},
+        component_type: ComponentType::{component_type:?},
+        priority: {priority},
+        autostart: {autostart},
+        capabilities: &[{capabilities}],
+        capabilities_bitmask: {bitmask},
+        channels: &[{channels}],
+        restart: {restart},
+        heartbeat: {heartbeat},
+        depends_on: &[{depends_on}],
+        limits: {limits},
+        args: &[{args}],
+        env: &[{env}],
+        binary_data: {binary_data},
+    }},
+",
+                name = component.name,
+                binary_name = component.binary,
+                component_type = component.component_type,
+                priority = component.priority,
+                autostart = component.autostart,
+                capabilities = capabilities.join(", "),
+                bitmask = component.capabilities_bitmask(),
+                channels = channels.join(", "),
+                restart = component.restart_source(),
+                heartbeat = component.heartbeat_source(),
+                depends_on = depends_on.join(", "),
+                limits = component.limits_source(),
+                args = args.join(", "),
+                env = env.join(", "),
+            );
+        }
+        out.push(']');
+        out
+    }
+}
+
+impl Component {
+    /// Where the build puts the component's binary
+    pub fn binary_path(&self, project_root: &Path) -> PathBuf {
+        project_root
+            .join("components")
+            .join(&self.binary)
+            .join("target/aarch64-unknown-none/release")
+            .join(&self.binary)
+    }
+
+    /// The kernel capability bits its capabilities grant
+    pub fn capabilities_bitmask(&self) -> u64 {
+        self.capabilities
+            .iter()
+            .filter_map(|cap| capability_bits(cap))
+            .fold(0, |mask, bits| mask | bits)
+    }
+
+    /// Whether the root task spawns it, and so can supervise it
+    pub fn is_root_spawned(&self) -> bool {
+        self.spawned_by.as_deref().is_none_or(|spawner| spawner == ROOT_SPAWNER)
+    }
+
+    /// The restart settings only make sense for a component the root task
+    /// supervises, and `max_restarts` only with `on-failure`
+    fn validate_restart(&self) -> Result<(), Error> {
+        let tuned = self.max_restarts.is_some() || self.restart_backoff_ms.is_some();
+        if self.restart == RestartPolicy::Never {
+            if tuned {
+                return invalid(format!("component `{}` sets restart limits but restart = \"never\"", self.name));
+            }
+            return Ok(());
+        }
+        if !self.is_root_spawned() {
+            return invalid(format!(
+                "component `{}` has a restart policy, but only the root task restarts components",
+                self.name
+            ));
+        }
+        if self.restart == RestartPolicy::Always && self.max_restarts.is_some() {
+            return invalid(format!(
+                "component `{}`: max_restarts needs restart = \"on-failure\"",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `RestartPolicy` expression for the registry
+    fn restart_source(&self) -> String {
+        let backoff_ms = self.restart_backoff_ms.unwrap_or(DEFAULT_RESTART_BACKOFF_MS);
+        match self.restart {
+            RestartPolicy::Never => "RestartPolicy::Never".to_owned(),
+            RestartPolicy::OnFailure => format!(
+                "RestartPolicy::OnFailure {{ max_restarts: {}, backoff_ms: {backoff_ms} }}",
+                self.max_restarts.unwrap_or(DEFAULT_MAX_RESTARTS)
+            ),
+            RestartPolicy::Always => format!("RestartPolicy::Always {{ backoff_ms: {backoff_ms} }}"),
+        }
+    }
+
+    /// Only the root task watches components for hangs, the heartbeat
+    /// settings need a `heartbeat_ms` to apply to, and restarting a hung
+    /// component needs a restart policy that restarts it
+    fn validate_heartbeat(&self) -> Result<(), Error> {
+        let Some(interval_ms) = self.heartbeat_ms else {
+            if self.max_missed_heartbeats.is_some() || self.on_hang.is_some() {
+                return invalid(format!("component `{}` sets hang handling but no heartbeat_ms", self.name));
+            }
+            return Ok(());
+        };
+        if !self.is_root_spawned() {
+            return invalid(format!(
+                "component `{}` has a heartbeat, but only the root task watches components for hangs",
+                self.name
+            ));
+        }
+        if interval_ms < MIN_HEARTBEAT_MS {
+            return invalid(format!(
+                "component `{}`: heartbeat_ms must be at least {MIN_HEARTBEAT_MS}",
+                self.name
+            ));
+        }
+        if self.max_missed_heartbeats == Some(0) {
+            return invalid(format!("component `{}`: max_missed_heartbeats must be at least 1", self.name));
+        }
+        if self.on_hang == Some(HangAction::Restart) && self.restart == RestartPolicy::Never {
+            return invalid(format!(
+                "component `{}`: on_hang = \"restart\" needs a restart policy other than \"never\"",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `Option<Heartbeat>` expression for the registry
+    fn heartbeat_source(&self) -> String {
+        match self.heartbeat_ms {
+            None => "None".to_owned(),
+            Some(interval_ms) => format!(
+                "Some(Heartbeat {{ interval_ms: {interval_ms}, max_missed: {}, on_hang: HangAction::{:?} }})",
+                self.max_missed_heartbeats.unwrap_or(DEFAULT_MAX_MISSED_HEARTBEATS),
+                self.on_hang.unwrap_or_default()
+            ),
+        }
+    }
+
+    /// Resource limits are enforced by the root task as it delegates, and
+    /// the priority must be within its own ceiling
+    fn validate_limits(&self) -> Result<(), Error> {
+        let limited = self.max_memory_kb.is_some() || self.max_cap_slots.is_some() || self.priority_ceiling.is_some();
+        if limited && !self.is_root_spawned() {
+            return invalid(format!(
+                "component `{}` has resource limits, but only the root task enforces them",
+                self.name
+            ));
+        }
+        if let Some(ceiling) = self.priority_ceiling.filter(|&ceiling| self.priority < ceiling) {
+            return invalid(format!(
+                "component `{}` has priority {}, above its priority_ceiling {ceiling} (lower runs first)",
+                self.name, self.priority
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `ResourceLimits` expression for the registry
+    fn limits_source(&self) -> String {
+        format!(
+            "ResourceLimits {{ max_memory_kb: {:?}, max_cap_slots: {:?}, priority_ceiling: {:?} }}",
+            self.max_memory_kb, self.max_cap_slots, self.priority_ceiling
+        )
+    }
+
+    /// Arguments and environment go in the boot block the root task gives
+    /// the component, as NUL-terminated strings and variables as
+    /// `KEY=VALUE`, so they must fit and be representable
+    fn validate_boot_strings(&self) -> Result<(), Error> {
+        if (!self.args.is_empty() || !self.env.is_empty()) && !self.is_root_spawned() {
+            return invalid(format!(
+                "component `{}` has arguments or environment, but only the root task passes them",
+                self.name
+            ));
+        }
+        let strings = self.args.iter().chain(self.env.values());
+        if let Some(string) = strings.clone().find(|string| string.contains('\0')) {
+            return invalid(format!("component `{}`: {string:?} contains a NUL", self.name));
+        }
+        if let Some(key) = self.env.keys().find(|key| key.is_empty() || key.contains(['=', '\0'])) {
+            return invalid(format!("component `{}`: bad environment variable name {key:?}", self.name));
+        }
+        // Each string and its NUL, the name first; a key adds its `=`
+        let size = self.name.len() + 1
+            + strings.map(|string| string.len() + 1).sum::<usize>()
+            + self.env.keys().map(|key| key.len() + 1).sum::<usize>();
+        if size > MAX_BOOT_STRINGS {
+            return invalid(format!(
+                "component `{}`: arguments and environment take {size} bytes, more than the {MAX_BOOT_STRINGS} a boot block holds",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Capabilities that are neither a kernel capability nor a
+    /// device-specific one (`"interrupt:33"`), which are likely typos
+    pub fn unknown_capabilities(&self) -> impl Iterator<Item = &str> {
+        self.capabilities
+            .iter()
+            .map(String::as_str)
+            .filter(|cap| capability_bits(cap).is_none())
+    }
+}
+
+/// Kernel capability bits for a capability string
+///
+/// The class before the `:` decides: `"memory:map"` and `"memory"` are
+/// both CAP_MEMORY. Device-specific capabilities (`"memory_map:ADDR:SIZE"`,
+/// `"interrupt:IRQ"`, `"untyped:N"`) carry no kernel bit and give `Some(0)`;
+/// unknown bare words give `None`.
+pub fn capability_bits(cap: &str) -> Option<u64> {
+    let cap = cap.to_ascii_lowercase();
+    let class = cap.split(':').next().unwrap_or_default();
+    match class {
+        "memory" => Some(CAP_MEMORY),
+        "process" => Some(CAP_PROCESS),
+        "ipc" | "notification" | "endpoint" => Some(CAP_IPC),
+        "caps" => Some(CAP_CAPS),
+        "domain" => Some(CAP_DOMAIN),
+        "klog" => Some(CAP_KLOG),
+        "irq" => Some(CAP_IRQ_CONTROL),
+        "log" => Some(CAP_LOG_SERVER),
+        "boot" if cap == "boot:control" => Some(CAP_BOOT_CONTROL),
+        "boot" => Some(CAP_BOOT_INIT),
+        "vfs" => Some(CAP_VFS_SERVER),
+        _ if cap.contains(':') => Some(0),
+        _ => None,
+    }
+}
+
+fn invalid<T>(reason: String) -> Result<T, Error> {
+    Err(Error::Invalid(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE: &str = r#"
+        [[component]]
+        name = "producer"
+        binary = "ipc-producer"
+        type = "service"
+        priority = 100
+        autostart = true
+        capabilities = ["memory:map", "caps:allocate", "interrupt:33"]
+        channels = [{ name = "kaal.pipe", role = "producer" }]
+        restart = "on-failure"
+        max_restarts = 3
+
+        [[component]]
+        name = "consumer"
+        binary = "ipc-consumer"
+        type = "application"
+        priority = 110
+        spawned_by = "producer"
+        capabilities = ["notification:wait"]
+        channels = [{ name = "kaal.pipe", role = "consumer" }]
+    "#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = SystemManifest::parse(PIPELINE).unwrap();
+        let producer = &manifest.components[0];
+        assert_eq!(producer.component_type, ComponentType::Service);
+        assert!(producer.autostart);
+        assert_eq!(producer.capabilities_bitmask(), CAP_MEMORY | CAP_CAPS);
+        assert_eq!(producer.channels[0].role, ChannelRole::Producer);
+        assert_eq!(producer.restart, RestartPolicy::OnFailure);
+
+        let consumer = &manifest.components[1];
+        assert!(!consumer.autostart);
+        assert_eq!(consumer.restart, RestartPolicy::Never);
+        assert_eq!(consumer.capabilities_bitmask(), CAP_IPC);
+
+        // The consumer is the producer's to spawn
+        let roots: Vec<_> = manifest.root_task_components().map(|c| c.name.as_str()).collect();
+        assert_eq!(roots, ["producer"]);
+    }
+
+    #[test]
+    fn test_capability_bits() {
+        assert_eq!(capability_bits("process:create"), Some(CAP_PROCESS));
+        assert_eq!(capability_bits("IPC"), Some(CAP_IPC));
+        assert_eq!(capability_bits("klog:read"), Some(CAP_KLOG));
+        assert_eq!(capability_bits("irq:control"), Some(CAP_IRQ_CONTROL));
+        assert_eq!(capability_bits("log:serve"), Some(CAP_LOG_SERVER));
+        assert_eq!(capability_bits("boot:init"), Some(CAP_BOOT_INIT));
+        assert_eq!(capability_bits("boot:control"), Some(CAP_BOOT_CONTROL));
+        assert_eq!(capability_bits("vfs:serve"), Some(CAP_VFS_SERVER));
+        assert_eq!(capability_bits("memory_map:0x09000000:4096"), Some(0));
+        assert_eq!(capability_bits("untyped:1"), Some(0));
+        assert_eq!(capability_bits("memroy"), None);
+    }
+
+    #[test]
+    fn test_rejects_impossible_systems() {
+        let twice = PIPELINE.replace("name = \"consumer\"", "name = \"producer\"");
+        assert!(matches!(SystemManifest::parse(&twice), Err(Error::Invalid(_))));
+
+        let orphan = PIPELINE.replace("role = \"producer\"", "role = \"consumer\"");
+        assert!(matches!(SystemManifest::parse(&orphan), Err(Error::Invalid(_))));
+
+        let two_producers = PIPELINE.replace("role = \"consumer\"", "role = \"producer\"");
+        assert!(matches!(SystemManifest::parse(&two_producers), Err(Error::Invalid(_))));
+
+        let unknown_spawner = PIPELINE.replace("spawned_by = \"producer\"", "spawned_by = \"init\"");
+        assert!(matches!(SystemManifest::parse(&unknown_spawner), Err(Error::Invalid(_))));
+
+        let unknown_field = PIPELINE.replace("autostart = true", "autostart = true\nautostrat = true");
+        assert!(matches!(SystemManifest::parse(&unknown_field), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_root_task_registry() {
+        let manifest = SystemManifest::parse(PIPELINE).unwrap();
+        let registry = manifest.root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.starts_with("&[\n") && registry.ends_with(']'));
+        assert!(registry.contains("name: \"producer\""));
+        assert!(!registry.contains("name: \"consumer\""));
+        assert!(registry.contains("capabilities_bitmask: 9,"));
+        assert!(registry.contains("ChannelDescriptor { name: \"kaal.pipe\", role: ChannelRole::Producer }"));
+        assert!(registry.contains("binary_data: None,"));
+        assert!(registry.contains("restart: RestartPolicy::OnFailure { max_restarts: 3, backoff_ms: 100 },"));
+    }
+
+    #[test]
+    fn test_restart_policies() {
+        let always = PIPELINE.replace("restart = \"on-failure\"\n        max_restarts = 3", "restart = \"always\"");
+        let manifest = SystemManifest::parse(&always).unwrap();
+        assert_eq!(manifest.components[0].restart, RestartPolicy::Always);
+        let registry = manifest.root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains("restart: RestartPolicy::Always { backoff_ms: 100 },"));
+
+        // Limits without restarts, or a limit `always` would not count
+        let limits_only = PIPELINE.replace("restart = \"on-failure\"", "restart = \"never\"");
+        assert!(matches!(SystemManifest::parse(&limits_only), Err(Error::Invalid(_))));
+        let unlimited = PIPELINE.replace("restart = \"on-failure\"", "restart = \"always\"");
+        assert!(matches!(SystemManifest::parse(&unlimited), Err(Error::Invalid(_))));
+
+        // The root task cannot restart what another component spawned
+        let nested = PIPELINE.replace("spawned_by = \"producer\"", "spawned_by = \"producer\"\nrestart = \"always\"");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+
+        let unknown = PIPELINE.replace("\"on-failure\"", "\"sometimes\"");
+        assert!(matches!(SystemManifest::parse(&unknown), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let registry = SystemManifest::parse(PIPELINE).unwrap().root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains("heartbeat: None,"));
+
+        let watched = PIPELINE.replace("max_restarts = 3", "max_restarts = 3\nheartbeat_ms = 500\non_hang = \"restart\"");
+        let manifest = SystemManifest::parse(&watched).unwrap();
+        assert_eq!(manifest.components[0].on_hang, Some(HangAction::Restart));
+        let registry = manifest.root_task_registry(Path::new("/nonexistent"));
+        assert!(registry
+            .contains("heartbeat: Some(Heartbeat { interval_ms: 500, max_missed: 3, on_hang: HangAction::Restart }),"));
+
+        // Hang handling without a heartbeat, or one too frequent to check
+        let no_heartbeat = PIPELINE.replace("max_restarts = 3", "max_restarts = 3\nmax_missed_heartbeats = 2");
+        assert!(matches!(SystemManifest::parse(&no_heartbeat), Err(Error::Invalid(_))));
+        let too_fast = PIPELINE.replace("max_restarts = 3", "max_restarts = 3\nheartbeat_ms = 5");
+        assert!(matches!(SystemManifest::parse(&too_fast), Err(Error::Invalid(_))));
+        let never_missed = PIPELINE.replace("max_restarts = 3", "max_restarts = 3\nheartbeat_ms = 500\nmax_missed_heartbeats = 0");
+        assert!(matches!(SystemManifest::parse(&never_missed), Err(Error::Invalid(_))));
+
+        // Restarting a hung component needs a policy that restarts it
+        let never = watched.replace("restart = \"on-failure\"\n        max_restarts = 3", "restart = \"never\"");
+        assert!(matches!(SystemManifest::parse(&never), Err(Error::Invalid(_))));
+
+        // The root task only watches what it spawned
+        let nested = PIPELINE.replace("spawned_by = \"producer\"", "spawned_by = \"producer\"\nheartbeat_ms = 500");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_startup_dependencies() {
+        let driver = r#"
+        [[component]]
+        name = "driver"
+        binary = "uart-driver"
+        type = "driver"
+        priority = 50
+        autostart = true
+        "#;
+        let waits = format!("{PIPELINE}{driver}").replace(
+            "capabilities = [\"notification:wait\"]",
+            "capabilities = [\"notification:wait\"]\ndepends_on = [\"driver\", \"producer\"]",
+        );
+        let manifest = SystemManifest::parse(&waits).unwrap();
+
+        // The producer waits for what the consumer it spawns needs, but
+        // not for itself
+        assert_eq!(manifest.startup_dependencies(&manifest.components[0]), ["driver"]);
+        assert!(manifest.startup_dependencies(&manifest.components[2]).is_empty());
+        let registry = manifest.root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains("depends_on: &[\"driver\"],"));
+        assert!(registry.contains("depends_on: &[],"));
+
+        let cycle = waits.replace("priority = 50", "priority = 50\ndepends_on = [\"producer\"]");
+        assert!(matches!(SystemManifest::parse(&cycle), Err(Error::Invalid(_))));
+
+        let unknown = waits.replace("[\"driver\", \"producer\"]", "[\"drvier\"]");
+        assert!(matches!(SystemManifest::parse(&unknown), Err(Error::Invalid(_))));
+
+        // Only the root task waits, so only for what it spawns itself
+        let nested = waits.replace("priority = 50", "priority = 50\ndepends_on = [\"consumer\"]");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+
+        let never_started = waits.replace("priority = 50\n        autostart = true", "priority = 50");
+        assert!(matches!(SystemManifest::parse(&never_started), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let registry = SystemManifest::parse(PIPELINE).unwrap().root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains("limits: ResourceLimits { max_memory_kb: None, max_cap_slots: None, priority_ceiling: None },"));
+
+        let limited = PIPELINE.replace(
+            "max_restarts = 3",
+            "max_restarts = 3\nmax_memory_kb = 1024\nmax_cap_slots = 4\npriority_ceiling = 100",
+        );
+        let manifest = SystemManifest::parse(&limited).unwrap();
+        assert_eq!(manifest.components[0].max_memory_kb, Some(1024));
+        let registry = manifest.root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains(
+            "limits: ResourceLimits { max_memory_kb: Some(1024), max_cap_slots: Some(4), priority_ceiling: Some(100) },"
+        ));
+
+        // Priority 100 is within a ceiling of 100, but not of 101
+        let above_ceiling = limited.replace("priority_ceiling = 100", "priority_ceiling = 101");
+        assert!(matches!(SystemManifest::parse(&above_ceiling), Err(Error::Invalid(_))));
+
+        // The root task cannot limit what another component spawns
+        let nested = PIPELINE.replace("spawned_by = \"producer\"", "spawned_by = \"producer\"\nmax_cap_slots = 4");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_args_and_env() {
+        let registry = SystemManifest::parse(PIPELINE).unwrap().root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains("args: &[],\n        env: &[],"));
+
+        let configured = PIPELINE.replace(
+            "max_restarts = 3",
+            "max_restarts = 3\nargs = [\"--rate\", \"10\"]\nenv = { MODE = \"fast\", LOG = \"debug\" }",
+        );
+        let manifest = SystemManifest::parse(&configured).unwrap();
+        let registry = manifest.root_task_registry(Path::new("/nonexistent"));
+        assert!(registry.contains("args: &[\"--rate\", \"10\"],"));
+        assert!(registry.contains("env: &[(\"LOG\", \"debug\"), (\"MODE\", \"fast\")],"));
+
+        let bad_key = configured.replace("MODE = ", "\"MO=DE\" = ");
+        assert!(matches!(SystemManifest::parse(&bad_key), Err(Error::Invalid(_))));
+
+        let too_long = configured.replace("\"10\"", &format!("{:?}", "x".repeat(MAX_BOOT_STRINGS)));
+        assert!(matches!(SystemManifest::parse(&too_long), Err(Error::Invalid(_))));
+
+        // Only the root task passes them
+        let nested = PIPELINE.replace("spawned_by = \"producer\"", "spawned_by = \"producer\"\nargs = [\"-v\"]");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_embed() {
+        // A built binary is embedded unless the component says otherwise
+        let root = std::env::temp_dir().join(format!("kaal-compose-embed-{}", std::process::id()));
+        let manifest = SystemManifest::parse(PIPELINE).unwrap();
+        let binary = manifest.components[0].binary_path(&root);
+        std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        std::fs::write(&binary, b"\x7fELF").unwrap();
+
+        assert!(manifest.components[0].embed);
+        assert!(manifest.root_task_registry(&root).contains("binary_data: Some(include_bytes!("));
+
+        let loaded = SystemManifest::parse(&PIPELINE.replace("max_restarts = 3", "max_restarts = 3\nembed = false")).unwrap();
+        assert!(loaded.root_task_registry(&root).contains("binary_data: None,"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_log_server() {
+        let served = PIPELINE.replace("\"interrupt:33\"]", "\"interrupt:33\", \"log:serve\"]");
+        let manifest = SystemManifest::parse(&served).unwrap();
+        assert_eq!(manifest.components[0].capabilities_bitmask(), CAP_MEMORY | CAP_CAPS | CAP_LOG_SERVER);
+
+        // Only the root task hands out the log endpoint, to one server
+        let nested = PIPELINE.replace("\"notification:wait\"]", "\"notification:wait\", \"log:serve\"]");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+
+        let second = format!("{served}\n[[component]]\nname = \"logger\"\nbinary = \"logger\"\ntype = \"service\"\npriority = 20\ncapabilities = [\"log:serve\"]\n");
+        assert!(matches!(SystemManifest::parse(&second), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_boot_init() {
+        let init = PIPELINE.replace("\"interrupt:33\"]", "\"interrupt:33\", \"boot:init\"]");
+        let manifest = SystemManifest::parse(&init).unwrap();
+        assert_eq!(manifest.components[0].capabilities_bitmask(), CAP_MEMORY | CAP_CAPS | CAP_BOOT_INIT);
+
+        // The root task spawns it at boot, and there is one
+        let idle = init.replace("autostart = true", "autostart = false");
+        assert!(matches!(SystemManifest::parse(&idle), Err(Error::Invalid(_))));
+
+        let nested = PIPELINE.replace("\"notification:wait\"]", "\"notification:wait\", \"boot:init\"]");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+
+        let second = format!("{init}\n[[component]]\nname = \"init2\"\nbinary = \"init2\"\ntype = \"service\"\npriority = 20\nautostart = true\ncapabilities = [\"boot:init\"]\n");
+        assert!(matches!(SystemManifest::parse(&second), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_boot_control() {
+        let control = PIPELINE.replace("\"interrupt:33\"]", "\"interrupt:33\", \"boot:control\"]");
+        let manifest = SystemManifest::parse(&control).unwrap();
+        assert_eq!(manifest.components[0].capabilities_bitmask(), CAP_MEMORY | CAP_CAPS | CAP_BOOT_CONTROL);
+
+        // Only the root task hands out its boot endpoint
+        let nested = PIPELINE.replace("\"notification:wait\"]", "\"notification:wait\", \"boot:control\"]");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_vfs_server() {
+        let served = PIPELINE.replace("\"interrupt:33\"]", "\"interrupt:33\", \"vfs:serve\"]");
+        let manifest = SystemManifest::parse(&served).unwrap();
+        assert_eq!(manifest.components[0].capabilities_bitmask(), CAP_MEMORY | CAP_CAPS | CAP_VFS_SERVER);
+
+        // Only the root task hands out the VFS endpoint, to one service
+        let nested = PIPELINE.replace("\"notification:wait\"]", "\"notification:wait\", \"vfs:serve\"]");
+        assert!(matches!(SystemManifest::parse(&nested), Err(Error::Invalid(_))));
+
+        let second = format!("{served}\n[[component]]\nname = \"vfs2\"\nbinary = \"vfs2\"\ntype = \"service\"\npriority = 20\ncapabilities = [\"vfs:serve\"]\n");
+        assert!(matches!(SystemManifest::parse(&second), Err(Error::Invalid(_))));
+    }
+}
@@ -0,0 +1,313 @@
+//! KaaL Interface Definitions - typed channel stubs from a trait
+//!
+//! Every component on a channel repeats the same setup: the channel name,
+//! the buffer size, `establish_channel`, the conversion to a message
+//! `ChannelConfig`, and the message type. [`interface`] generates all of
+//! it from one trait that both sides share.
+//!
+//! # Example
+//! ```ignore
+//! #[kaal_sdk::interface(channel = "kaal.uart.output")]
+//! pub trait UartInput {
+//!     /// A byte arrived on the UART
+//!     fn key(&mut self, byte: u8);
+//! }
+//!
+//! // Producer
+//! let uart = UartInputClient::connect()?;
+//! uart.try_key(b'a')?;
+//!
+//! // Consumer, implementing the trait
+//! let input = UartInputServer::connect()?;
+//! loop {
+//!     input.serve(&mut app)?;
+//! }
+//! ```
+//!
+//! # Generated Items
+//! Next to the trait `Name`:
+//! - `NameMessage`: an enum with a variant per method (`Key { byte }`),
+//!   and `dispatch(self, handler)` to call the method it stands for
+//! - `NameClient`: the producer side; `connect()`, and per method one
+//!   that sends its message, waiting while the channel is full (`key`),
+//!   and one that does not (`try_key`)
+//! - `NameServer`: the consumer side; `connect()`, `receive()`,
+//!   `try_receive()`, and `serve(handler)` to receive one message and
+//!   dispatch it; `lend_priority_to(client_tcb)` makes its waits lend
+//!   the client's thread its priority
+//!
+//! # Rules
+//! - Methods take `&mut self` and their arguments by value, and return
+//!   nothing: a channel is one-way
+//! - Argument types must be `Copy + 'static`, as messages go through a
+//!   shared ring
+//! - Neither the trait nor its methods may be generic
+//!
+//! The generated code refers to `kaal_sdk`, which the component must
+//! depend on (it re-exports this macro as `kaal_sdk::interface`).
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::{
+    parse_macro_input, Attribute, Error, FnArg, Ident, ItemTrait, LitStr, Pat, ReturnType, Token, TraitItem, Type,
+};
+
+/// Longest channel name the channel broker takes
+const MAX_CHANNEL_NAME: usize = 32;
+
+/// Generate a message enum, client and server for a channel interface
+///
+/// Takes the channel name: `#[interface(channel = "kaal.uart.output")]`.
+/// See the crate documentation for what is generated.
+#[proc_macro_attribute]
+pub fn interface(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as Args);
+    let item = parse_macro_input!(item as ItemTrait);
+    expand(&args, &item).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// Arguments of `#[interface(...)]`
+struct Args {
+    channel: LitStr,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: Ident = input.parse()?;
+        if key != "channel" {
+            return Err(Error::new(key.span(), "expected `channel = \"name\"`"));
+        }
+        input.parse::<Token![=]>()?;
+        let channel: LitStr = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+        if !input.is_empty() {
+            return Err(input.error("unexpected argument after the channel name"));
+        }
+
+        let len = channel.value().len();
+        if len == 0 || len > MAX_CHANNEL_NAME {
+            return Err(Error::new(channel.span(), "channel name must be 1-32 characters"));
+        }
+        Ok(Self { channel })
+    }
+}
+
+/// A method of the interface, and the message variant for it
+struct Method {
+    name: Ident,
+    variant: Ident,
+    docs: Vec<Attribute>,
+    args: Vec<(Ident, Type)>,
+}
+
+impl Method {
+    fn parse(item: &TraitItem) -> syn::Result<Self> {
+        let TraitItem::Fn(method) = item else {
+            return Err(Error::new_spanned(item, "interfaces can only have methods"));
+        };
+        let sig = &method.sig;
+        if !sig.generics.params.is_empty() {
+            return Err(Error::new_spanned(&sig.generics, "interface methods cannot be generic"));
+        }
+        if let ReturnType::Type(..) = sig.output {
+            return Err(Error::new_spanned(
+                &sig.output,
+                "interface methods cannot return values; a channel is one-way",
+            ));
+        }
+
+        let mut inputs = sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() && receiver.mutability.is_some() => {}
+            _ => return Err(Error::new_spanned(sig, "interface methods must take `&mut self`")),
+        }
+        let args = inputs
+            .map(|input| match input {
+                FnArg::Typed(arg) => match &*arg.pat {
+                    Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {
+                        Ok((pat.ident.clone(), (*arg.ty).clone()))
+                    }
+                    _ => Err(Error::new_spanned(&arg.pat, "interface arguments must be plain names")),
+                },
+                FnArg::Receiver(receiver) => Err(Error::new_spanned(receiver, "unexpected `self`")),
+            })
+            .collect::<syn::Result<_>>()?;
+
+        Ok(Self {
+            name: sig.ident.clone(),
+            variant: Ident::new(&upper_camel_case(&sig.ident.to_string()), sig.ident.span()),
+            docs: method.attrs.iter().filter(|attr| attr.path().is_ident("doc")).cloned().collect(),
+            args,
+        })
+    }
+}
+
+fn expand(args: &Args, item: &ItemTrait) -> syn::Result<TokenStream2> {
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(&item.generics, "interfaces cannot be generic"));
+    }
+    let methods = item.items.iter().map(Method::parse).collect::<syn::Result<Vec<_>>>()?;
+    if methods.is_empty() {
+        return Err(Error::new_spanned(&item.ident, "interfaces need at least one method"));
+    }
+
+    let vis = &item.vis;
+    let interface = &item.ident;
+    let channel = &args.channel;
+    let message = format_ident!("{}Message", interface);
+    let client = format_ident!("{}Client", interface);
+    let server = format_ident!("{}Server", interface);
+
+    let message_doc = format!("Messages of [`{interface}`], one per method");
+    let client_doc = format!("Producer side of the `{}` channel ([`{interface}`])", channel.value());
+    let server_doc = format!("Consumer side of the `{}` channel ([`{interface}`])", channel.value());
+
+    let variants = methods.iter().map(|method| {
+        let Method { variant, docs, args, .. } = method;
+        let fields = args.iter().map(|(arg, ty)| quote! { #arg: #ty });
+        quote! {
+            #(#docs)*
+            #variant { #(#fields),* }
+        }
+    });
+
+    let arms = methods.iter().map(|method| {
+        let Method { name, variant, args, .. } = method;
+        let args = args.iter().map(|(arg, _)| arg);
+        let fields = args.clone();
+        quote! {
+            Self::#variant { #(#fields),* } => handler.#name(#(#args),*),
+        }
+    });
+
+    let sends = methods.iter().map(|method| {
+        let Method { name, variant, docs, args } = method;
+        let try_name = format_ident!("try_{}", name);
+        let try_doc = format!("Like [`{name}`](Self::{name}), but fails with `IpcError::BufferFull` instead of waiting");
+        let params = args.iter().map(|(arg, ty)| quote! { #arg: #ty });
+        let fields = args.iter().map(|(arg, _)| arg);
+        let try_params = params.clone();
+        let try_fields = fields.clone();
+        quote! {
+            #(#docs)*
+            pub fn #name(&self, #(#params),*) -> ::core::result::Result<(), ::kaal_sdk::ipc::IpcError> {
+                self.channel.send(#message::#variant { #(#fields),* })
+            }
+
+            #[doc = #try_doc]
+            pub fn #try_name(&self, #(#try_params),*) -> ::core::result::Result<(), ::kaal_sdk::ipc::IpcError> {
+                self.channel.try_send(#message::#variant { #(#try_fields),* })
+            }
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        #[doc = #message_doc]
+        #[derive(Debug, Clone, Copy)]
+        #vis enum #message {
+            #(#variants),*
+        }
+
+        impl #message {
+            /// Call the method of `handler` this message stands for
+            pub fn dispatch(self, handler: &mut impl #interface) {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+
+        #[doc = #client_doc]
+        #vis struct #client {
+            channel: ::kaal_sdk::message::Channel<#message>,
+        }
+
+        impl #client {
+            /// Name the channel is registered under
+            pub const CHANNEL: &'static str = #channel;
+
+            /// Set up the channel, as its producer
+            ///
+            /// # Errors
+            /// Returns why the channel could not be set up
+            pub fn connect() -> ::core::result::Result<Self, &'static str> {
+                let config = ::kaal_sdk::channel_setup::establish_channel_for::<#message>(
+                    Self::CHANNEL,
+                    ::kaal_sdk::channel_setup::ChannelRole::Producer,
+                )?;
+                // establish_channel_for built a ring of this message type there
+                let channel = unsafe { ::kaal_sdk::message::Channel::sender((&config).into()) };
+                Ok(Self { channel })
+            }
+
+            #(#sends)*
+        }
+
+        #[doc = #server_doc]
+        #vis struct #server {
+            channel: ::kaal_sdk::message::Channel<#message>,
+        }
+
+        impl #server {
+            /// Name the channel is registered under
+            pub const CHANNEL: &'static str = #channel;
+
+            /// Join the channel, as its consumer
+            ///
+            /// # Errors
+            /// Returns why the channel could not be joined, including the
+            /// producer not having set it up yet
+            pub fn connect() -> ::core::result::Result<Self, &'static str> {
+                let config = ::kaal_sdk::channel_setup::establish_channel_for::<#message>(
+                    Self::CHANNEL,
+                    ::kaal_sdk::channel_setup::ChannelRole::Consumer,
+                )?;
+                // The producer built a ring of this message type there
+                let channel = unsafe { ::kaal_sdk::message::Channel::receiver((&config).into()) };
+                Ok(Self { channel })
+            }
+
+            /// Lend the server's priority to the client's thread while
+            /// waiting for a message (priority inheritance)
+            ///
+            /// `client_tcb` is the TCB capability slot of the client's
+            /// thread, in the server's CSpace.
+            pub fn lend_priority_to(self, client_tcb: u64) -> Self {
+                Self {
+                    channel: self.channel.lend_priority_to(client_tcb),
+                }
+            }
+
+            /// Wait for the next message
+            pub fn receive(&self) -> ::core::result::Result<#message, ::kaal_sdk::ipc::IpcError> {
+                self.channel.receive()
+            }
+
+            /// Take the next message, failing with `IpcError::BufferEmpty`
+            /// if there is none
+            pub fn try_receive(&self) -> ::core::result::Result<#message, ::kaal_sdk::ipc::IpcError> {
+                self.channel.try_receive()
+            }
+
+            /// Wait for the next message and call `handler`'s method for it
+            pub fn serve(&self, handler: &mut impl #interface) -> ::core::result::Result<(), ::kaal_sdk::ipc::IpcError> {
+                self.receive()?.dispatch(handler);
+                Ok(())
+            }
+        }
+    })
+}
+
+/// `snake_case` method name to `UpperCamelCase` variant name
+fn upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_ascii_uppercase()).into_iter().chain(chars)
+        })
+        .collect()
+}
@@ -2,6 +2,9 @@
 //!
 //! This allocator is suitable for components that don't need sophisticated
 //! memory management. It allocates from a fixed-size heap and never frees.
+//!
+//! The SDK's global allocator is the heap in [`memory::heap`](crate::memory::heap),
+//! which does free; this one is for arenas a component sets up itself.
 
 use core::alloc::{GlobalAlloc, Layout};
 use core::cell::UnsafeCell;
@@ -9,7 +12,6 @@ use core::ptr;
 
 /// Simple bump allocator
 pub struct BumpAllocator {
-    heap_start: UnsafeCell<usize>,
     heap_end: usize,
     next: UnsafeCell<usize>,
 }
@@ -20,7 +22,6 @@ impl BumpAllocator {
     /// Create a new bump allocator
     pub const fn new(heap_start: usize, heap_size: usize) -> Self {
         Self {
-            heap_start: UnsafeCell::new(heap_start),
             heap_end: heap_start + heap_size,
             next: UnsafeCell::new(heap_start),
         }
@@ -53,15 +54,7 @@ unsafe impl GlobalAlloc for BumpAllocator {
     }
 }
 
-/// Static heap for components (64KB) - starts at a fixed address
-const HEAP_START: usize = 0x100_0000; // 16MB mark in virtual memory
-const HEAP_SIZE: usize = 0x10000; // 64KB
-
-/// Global allocator instance
-#[global_allocator]
-static ALLOCATOR: BumpAllocator = BumpAllocator::new(HEAP_START, HEAP_SIZE);
-
 /// Initialize the allocator (called by component startup)
 pub fn init() {
-    // Nothing to do for bump allocator
-}
\ No newline at end of file
+    // Nothing to do: the global heap takes its memory on first use
+}
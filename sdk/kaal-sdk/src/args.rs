@@ -9,6 +9,9 @@
 //! - x2: Third argument (sender notification cap slot)
 //!
 //! For IPC components, these arguments form a ChannelConfig structure.
+//!
+//! Components the root task spawns get the address of their boot block in
+//! x0 instead, and read their arguments with [`crate::env`].
 
 /// Component startup arguments
 ///
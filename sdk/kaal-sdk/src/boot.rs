@@ -0,0 +1,197 @@
+//! Starting and stopping components, for the init component and others
+//! with `boot:control`
+//!
+//! The root task spawns one component at boot, the init component
+//! (`boot:init` in system.toml), and leaves the rest to it: the init
+//! component decides which of the components the root task spawns run, in
+//! what order and with how much untyped memory, and asks the root task to
+//! start them. The root task spawns and supervises them as before, and
+//! stops them again when asked to. Components with `boot:control` in
+//! system.toml get the same boot endpoint, to start and stop components
+//! after boot, such as a system monitor launching applications.
+//!
+//! ```no_run
+//! use kaal_sdk::boot;
+//!
+//! for component in boot::components().filter(|c| c.autostart && !c.running) {
+//!     let pid = boot::start(component.name(), 0)?;
+//! }
+//! boot::done()?;
+//! # Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! # Requests
+//!
+//! Calls on the boot endpoint ([`InitialCap::Boot`]), little-endian:
+//!
+//! | Request | Reply |
+//! |---------|-------|
+//! | `1`, `u8` index | status, `u8` flags, `u8` name length, name |
+//! | `2`, `u32` untyped KiB, name | status, `u64` PID |
+//! | `3` | status |
+//! | `4`, name | status, `u8` exited by itself |
+
+use core::mem::ManuallyDrop;
+
+use crate::capability::{Cap, Endpoint};
+use crate::env::{self, InitialCap};
+use crate::{syscall, Error, Result};
+
+const OP_LIST: u8 = 1;
+const OP_START: u8 = 2;
+const OP_DONE: u8 = 3;
+const OP_STOP: u8 = 4;
+
+const STATUS_OK: u8 = 0;
+const STATUS_NOT_FOUND: u8 = 1;
+const STATUS_ALREADY_RUNNING: u8 = 2;
+const STATUS_DEPENDENCY_NOT_RUNNING: u8 = 3;
+const STATUS_FAILED: u8 = 4;
+const STATUS_NOT_RUNNING: u8 = 6;
+
+const FLAG_AUTOSTART: u8 = 1 << 0;
+const FLAG_RUNNING: u8 = 1 << 1;
+
+/// Longest component name a request carries
+pub const MAX_NAME: usize = 32;
+
+/// A component the root task can start
+#[derive(Debug, Clone, Copy)]
+pub struct Component {
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    /// Autostart in system.toml
+    pub autostart: bool,
+    /// It has a running instance
+    pub running: bool,
+}
+
+impl Component {
+    /// Its name in system.toml
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+}
+
+/// Whether we have the boot endpoint, as the init component or with
+/// `boot:control`, and can start components
+pub fn is_init() -> bool {
+    env::cap_slot(InitialCap::Boot).is_some()
+}
+
+/// The components the root task can start, in system.toml order
+///
+/// Empty unless we have the boot endpoint.
+pub fn components() -> impl Iterator<Item = Component> {
+    let endpoint = endpoint().ok();
+    (0..=u8::MAX).map_while(move |index| list(endpoint.as_ref()?, index))
+}
+
+/// The boot endpoint, which the root task keeps in our CSpace for good
+fn endpoint() -> Result<ManuallyDrop<Cap<Endpoint>>> {
+    let slot = env::cap_slot(InitialCap::Boot).ok_or(Error::CapabilityNotFound)?;
+    Ok(unsafe { Cap::borrow_raw(slot, Endpoint) })
+}
+
+fn list(endpoint: &Cap<Endpoint>, index: u8) -> Option<Component> {
+    let mut reply = [0u8; 3 + MAX_NAME];
+    let len = syscall::call(endpoint, &[OP_LIST, index], &mut reply).ok()?;
+    if len < 3 || reply[0] != STATUS_OK {
+        return None;
+    }
+    let name_len = (reply[2] as usize).min(MAX_NAME).min(len - 3);
+    let mut name = [0u8; MAX_NAME];
+    name[..name_len].copy_from_slice(&reply[3..3 + name_len]);
+    Some(Component {
+        name,
+        name_len,
+        autostart: reply[1] & FLAG_AUTOSTART != 0,
+        running: reply[1] & FLAG_RUNNING != 0,
+    })
+}
+
+/// Have the root task start the component `name`, with `untyped_kb` KiB of
+/// untyped memory (rounded down to a power of two) if not 0
+///
+/// Blocks while the root task spawns it and, if others depend on it, until
+/// it reports ready. Returns its PID.
+///
+/// # Errors
+/// - [`Error::CapabilityNotFound`]: we do not have the boot endpoint
+/// - [`Error::NotFound`]: no such component
+/// - [`Error::Busy`]: it is running already
+/// - [`Error::WouldBlock`]: a component it depends on is not running yet
+/// - [`Error::SyscallFailed`]: spawning it failed
+pub fn start(name: &str, untyped_kb: u32) -> Result<usize> {
+    let endpoint = endpoint()?;
+    if name.len() > MAX_NAME {
+        return Err(Error::NotFound);
+    }
+
+    let mut request = [0u8; 5 + MAX_NAME];
+    request[0] = OP_START;
+    request[1..5].copy_from_slice(&untyped_kb.to_le_bytes());
+    request[5..5 + name.len()].copy_from_slice(name.as_bytes());
+
+    let mut reply = [0u8; 9];
+    let len = syscall::call(&endpoint, &request[..5 + name.len()], &mut reply)?;
+    match reply[0] {
+        STATUS_OK if len >= 9 => {
+            let mut pid = [0u8; 8];
+            pid.copy_from_slice(&reply[1..9]);
+            Ok(u64::from_le_bytes(pid) as usize)
+        }
+        STATUS_NOT_FOUND => Err(Error::NotFound),
+        STATUS_ALREADY_RUNNING => Err(Error::Busy),
+        STATUS_DEPENDENCY_NOT_RUNNING => Err(Error::WouldBlock),
+        STATUS_FAILED => Err(Error::SyscallFailed),
+        _ => Err(Error::InvalidParameter),
+    }
+}
+
+/// Have the root task stop the component `name`
+///
+/// The root task signals it to shut down (see
+/// [`component::shutdown_requested`](crate::component::shutdown_requested)),
+/// gives it a moment to exit and then destroys it, and does not restart
+/// it. Blocks until it is gone. Returns whether it exited by itself.
+///
+/// # Errors
+/// - [`Error::CapabilityNotFound`]: we do not have the boot endpoint
+/// - [`Error::NotFound`]: no such component, or it is not running
+/// - [`Error::SyscallFailed`]: destroying it failed
+pub fn stop(name: &str) -> Result<bool> {
+    let endpoint = endpoint()?;
+    if name.len() > MAX_NAME {
+        return Err(Error::NotFound);
+    }
+
+    let mut request = [0u8; 1 + MAX_NAME];
+    request[0] = OP_STOP;
+    request[1..1 + name.len()].copy_from_slice(name.as_bytes());
+
+    let mut reply = [0u8; 2];
+    let len = syscall::call(&endpoint, &request[..1 + name.len()], &mut reply)?;
+    match reply[0] {
+        STATUS_OK if len >= 2 => Ok(reply[1] != 0),
+        STATUS_NOT_FOUND | STATUS_NOT_RUNNING => Err(Error::NotFound),
+        STATUS_FAILED => Err(Error::SyscallFailed),
+        _ => Err(Error::InvalidParameter),
+    }
+}
+
+/// Tell the root task boot is done
+///
+/// It then emits its audit of the capabilities it has handed out. The
+/// components started after this are not in it.
+///
+/// # Errors
+/// [`Error::CapabilityNotFound`] if we do not have the boot endpoint
+pub fn done() -> Result<()> {
+    let endpoint = endpoint()?;
+    let mut reply = [0u8; 1];
+    match syscall::call(&endpoint, &[OP_DONE], &mut reply)? {
+        1 if reply[0] == STATUS_OK => Ok(()),
+        _ => Err(Error::InvalidParameter),
+    }
+}
@@ -1,33 +1,154 @@
 //! Capability management
 //!
 //! Higher-level abstractions for working with capabilities.
+//!
+//! Endpoints, notifications and frames are held as a typed [`Cap`], which
+//! owns its slot: dropping it revokes the capability, with every copy
+//! minted or derived from it, and the slot is handed out again by
+//! [`allocate_slot`]. A capability that must outlive its handle, because
+//! another component was given a copy, is let go with
+//! [`into_raw`](Cap::into_raw).
+
+use core::mem::ManuallyDrop;
 
+use crate::sync::Mutex;
 use crate::{Result, syscall};
 
 /// Capability slot type
 pub type CapSlot = usize;
 
-/// Notification capability wrapper
+/// Most emptied slots kept for reuse
+const MAX_FREE_SLOTS: usize = 32;
+
+/// Slots emptied by dropped capabilities; the kernel hands out every slot
+/// number only once
+static FREE_SLOTS: Mutex<FreeSlots> = Mutex::new(FreeSlots { slots: [0; MAX_FREE_SLOTS], len: 0 });
+
+struct FreeSlots {
+    slots: [CapSlot; MAX_FREE_SLOTS],
+    len: usize,
+}
+
+/// An empty slot in our CSpace: one a dropped capability left, or a new one
+pub fn allocate_slot() -> Result<CapSlot> {
+    let mut free = FREE_SLOTS.lock();
+    if free.len > 0 {
+        free.len -= 1;
+        return Ok(free.slots[free.len]);
+    }
+    drop(free);
+    syscall::cap_allocate()
+}
+
+/// Give back `slot`, which must be empty, for [`allocate_slot`] to reuse
+pub(crate) fn free_slot(slot: CapSlot) {
+    let mut free = FREE_SLOTS.lock();
+    if free.len < MAX_FREE_SLOTS {
+        let len = free.len;
+        free.slots[len] = slot;
+        free.len += 1;
+    }
+}
+
+/// A kind of kernel object a [`Cap`] refers to
+pub trait CapType: sealed::Sealed {
+    /// The kernel's code for the object type (as `sys_retype` takes it)
+    const TYPE: usize;
+}
+
+/// Kinds whose capabilities can be minted with a badge
+pub trait Badged: CapType {}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for super::Endpoint {}
+    impl Sealed for super::Notification {}
+    impl Sealed for super::Frame {}
+}
+
+/// IPC endpoint, for [`Cap<Endpoint>`]
+#[derive(Debug, Clone, Copy)]
+pub struct Endpoint;
+
+/// Notification, for [`Cap<Notification>`]
+#[derive(Debug, Clone, Copy)]
+pub struct Notification;
+
+/// Physical memory frame, for [`Cap<Frame>`]
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    phys: usize,
+    size: usize,
+}
+
+impl Frame {
+    /// The frame of `size` bytes at `phys`
+    pub const fn new(phys: usize, size: usize) -> Self {
+        Self { phys, size }
+    }
+}
+
+impl CapType for Endpoint {
+    const TYPE: usize = 2;
+}
+
+impl CapType for Notification {
+    const TYPE: usize = 3;
+}
+
+impl CapType for Frame {
+    const TYPE: usize = 8;
+}
+
+impl Badged for Endpoint {}
+impl Badged for Notification {}
+
+/// A capability of kind `T`, owning its slot
 ///
-/// Provides RAII-style management of notification capabilities.
+/// Dropping it revokes the capability and its descendants, ignoring
+/// errors; a component without the capability-management capability
+/// (`caps:allocate`) cannot revoke, and leaves it in place.
 ///
 /// # Example
 /// ```no_run
-/// use kaal_sdk::capability::Notification;
+/// use kaal_sdk::capability::{Cap, Notification};
 ///
-/// let notification = Notification::create()?;
+/// let notification = Cap::<Notification>::create()?;
+/// let badged = notification.mint(0x2)?; // for a sender to signal
 /// notification.signal(0x1)?;
 /// let signals = notification.poll()?;
 /// ```
-pub struct Notification {
+#[derive(Debug)]
+pub struct Cap<T: CapType> {
     slot: CapSlot,
+    object: T,
 }
 
-impl Notification {
-    /// Create a new notification object
-    pub fn create() -> Result<Self> {
-        let slot = syscall::notification_create()?;
-        Ok(Self { slot })
+impl<T: CapType> Cap<T> {
+    /// Take over the capability in `slot`
+    ///
+    /// # Safety
+    /// `slot` must hold a capability to `object`, and nothing else may
+    /// revoke or delete it, or hold another `Cap` of it.
+    pub const unsafe fn from_raw(slot: CapSlot, object: T) -> Self {
+        Self { slot, object }
+    }
+
+    /// Use the capability in `slot` without taking it over: it is never
+    /// revoked, nor its slot reused, as for the initial capabilities the
+    /// root task gives us
+    ///
+    /// # Safety
+    /// `slot` must hold a capability to `object` while it is used.
+    pub const unsafe fn borrow_raw(slot: CapSlot, object: T) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Self { slot, object })
+    }
+
+    /// Let go of the capability without revoking it, returning its slot
+    pub fn into_raw(self) -> CapSlot {
+        let slot = self.slot;
+        core::mem::forget(self);
+        slot
     }
 
     /// Get the capability slot
@@ -35,31 +156,174 @@ impl Notification {
         self.slot
     }
 
+    /// Revoke the capability and its descendants now, reporting errors
+    pub fn revoke(self) -> Result<()> {
+        let slot = self.into_raw();
+        syscall::cap_revoke(0, slot)?;
+        free_slot(slot);
+        Ok(())
+    }
+}
+
+impl<T: CapType> Drop for Cap<T> {
+    fn drop(&mut self) {
+        if syscall::cap_revoke(0, self.slot).is_ok() {
+            free_slot(self.slot);
+        }
+    }
+}
+
+impl<T: Badged + Copy> Cap<T> {
+    /// A copy of this (unbadged) capability carrying `badge`, which
+    /// receivers see: on messages through an endpoint, or as the bits a
+    /// signal sets. Revoking this capability revokes the copy too.
+    pub fn mint(&self, badge: u64) -> Result<Cap<T>> {
+        let slot = allocate_slot()?;
+        if let Err(e) = syscall::cap_mint(0, self.slot, slot, badge as usize) {
+            free_slot(slot);
+            return Err(e);
+        }
+        Ok(Cap { slot, object: self.object })
+    }
+}
+
+impl Cap<Notification> {
+    /// Create a new notification object, in a slot from [`allocate_slot`]
+    pub fn create() -> Result<Self> {
+        syscall::notification_create()
+    }
+
     /// Signal this notification with a badge
     pub fn signal(&self, badge: u64) -> Result<()> {
-        syscall::signal(self.slot, badge)
+        syscall::signal(self, badge)
     }
 
     /// Wait for notification (blocking)
     pub fn wait(&self) -> Result<u64> {
-        syscall::wait(self.slot)
+        syscall::wait(self)
     }
 
     /// Poll notification (non-blocking)
     pub fn poll(&self) -> Result<u64> {
-        syscall::poll(self.slot)
+        syscall::poll(self)
+    }
+
+    /// Bind it to the calling thread, so that its signals end the thread's
+    /// endpoint receives (see [`syscall::tcb_bind_notification`])
+    pub fn bind(&self) -> Result<()> {
+        syscall::tcb_bind_notification(self)
     }
 }
 
-/// Endpoint capability wrapper
-pub struct Endpoint {
+impl Cap<Endpoint> {
+    /// Create a new endpoint, in a slot from [`allocate_slot`]
+    pub fn create() -> Result<Self> {
+        syscall::endpoint_create()
+    }
+
+    /// Send `request` and block for the reply, returning its length (see
+    /// [`syscall::call`])
+    pub fn call(&self, request: &[u8], reply: &mut [u8]) -> Result<usize> {
+        syscall::call(self, request, reply)
+    }
+}
+
+impl Cap<Frame> {
+    /// Retype 2^`size_bits` bytes of the UntypedMemory in `untyped` into a
+    /// frame
+    pub fn retype(untyped: CapSlot, size_bits: usize) -> Result<Self> {
+        let slot = allocate_slot()?;
+        match syscall::sys_retype(untyped, Frame::TYPE, size_bits, 0, slot) {
+            Ok(phys) => Ok(Self { slot, object: Frame::new(phys, 1 << size_bits) }),
+            Err(e) => {
+                free_slot(slot);
+                Err(e)
+            }
+        }
+    }
+
+    /// Physical address of the frame
+    pub fn phys(&self) -> usize {
+        self.object.phys
+    }
+
+    /// Size of the frame in bytes
+    pub fn size(&self) -> usize {
+        self.object.size
+    }
+
+    /// Map the frame into our address space with `permissions` (see
+    /// [`syscall::memory_map`]), returning its virtual address
+    pub fn map(&self, permissions: usize) -> Result<usize> {
+        syscall::memory_map(self.object.phys, self.object.size, permissions)
+    }
+}
+
+/// Timer capability wrapper
+///
+/// Signals a notification after a timeout, once or periodically.
+///
+/// # Example
+/// ```no_run
+/// use kaal_sdk::capability::{Cap, Notification, Timer};
+///
+/// let notification = Cap::<Notification>::create()?;
+/// let timer = Timer::create(&notification, 0x1)?;
+/// timer.sleep(&notification, 10_000)?; // 10 ms
+/// ```
+pub struct Timer {
     slot: CapSlot,
 }
 
-impl Endpoint {
-    /// Create a new endpoint
+impl Timer {
+    /// Create a timer that signals `badge` on `notification`
+    pub fn create(notification: &Cap<Notification>, badge: u64) -> Result<Self> {
+        let slot = syscall::timer_create(notification, badge)?;
+        Ok(Self { slot })
+    }
+
+    /// Get the capability slot
+    pub fn slot(&self) -> CapSlot {
+        self.slot
+    }
+
+    /// Expire once, after `timeout_us` microseconds
+    pub fn set_oneshot(&self, timeout_us: u64) -> Result<()> {
+        syscall::timer_set(self.slot, timeout_us, 0)
+    }
+
+    /// Expire every `period_us` microseconds
+    pub fn set_periodic(&self, period_us: u64) -> Result<()> {
+        syscall::timer_set(self.slot, 0, period_us)
+    }
+
+    /// Cancel a pending timeout
+    pub fn cancel(&self) -> Result<()> {
+        syscall::timer_set(self.slot, 0, 0)
+    }
+
+    /// Block for `timeout_us` microseconds
+    ///
+    /// `notification` must be the one the timer was created with. Other
+    /// signals on it end the sleep early; they are returned.
+    pub fn sleep(&self, notification: &Cap<Notification>, timeout_us: u64) -> Result<u64> {
+        self.set_oneshot(timeout_us)?;
+        notification.wait()
+    }
+}
+
+/// VCPU capability wrapper
+///
+/// A virtual CPU running a guest under stage-2 translation. Needs a kernel
+/// built with the `hypervisor` feature and entered at EL2.
+pub struct Vcpu {
+    slot: CapSlot,
+}
+
+impl Vcpu {
+    /// Create a VCPU with an empty guest physical address space
     pub fn create() -> Result<Self> {
-        let slot = syscall::endpoint_create()?;
+        let slot = syscall::vcpu_create()?;
         Ok(Self { slot })
     }
 
@@ -67,6 +331,31 @@ impl Endpoint {
     pub fn slot(&self) -> CapSlot {
         self.slot
     }
+
+    /// Map `size` bytes at `phys_addr` into the guest at `ipa`
+    pub fn map(&self, phys_addr: usize, size: usize, ipa: usize, permissions: usize) -> Result<()> {
+        syscall::vcpu_map(self.slot, phys_addr, size, ipa, permissions)
+    }
+
+    /// Run the guest until it exits
+    pub fn run(&self) -> Result<syscall::VcpuExit> {
+        syscall::vcpu_run(self.slot)
+    }
+
+    /// Read a guest register
+    pub fn reg(&self, reg: usize) -> Result<u64> {
+        syscall::vcpu_read_reg(self.slot, reg)
+    }
+
+    /// Write a guest register
+    pub fn set_reg(&self, reg: usize, value: u64) -> Result<()> {
+        syscall::vcpu_write_reg(self.slot, reg, value)
+    }
+
+    /// Raise or lower the guest's virtual IRQ line
+    pub fn set_irq(&self, level: bool) -> Result<()> {
+        syscall::vcpu_inject_irq(self.slot, level)
+    }
 }
 
 /// Device capability wrapper
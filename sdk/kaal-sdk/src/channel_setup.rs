@@ -13,6 +13,9 @@
 //! For high-level message passing, see the `message` module which provides
 //! the `Channel<T>` type that uses the infrastructure set up by this module.
 
+use crate::capability::{Cap, Notification};
+use crate::ipc::{SharedMemory, SharedRing};
+use crate::memory::Permissions;
 use crate::syscall;
 
 /// Role in the channel
@@ -37,9 +40,11 @@ pub struct ChannelConfig {
     /// Size of the buffer in bytes
     pub buffer_size: usize,
     /// Notification capability for signaling (producer) or receiving (consumer)
-    pub notification_cap: usize,
+    pub notification_cap: Cap<Notification>,
     /// Memory capability slot for the shared buffer (for remapping/unmapping)
     pub memory_cap: Option<usize>,
+    /// The shared buffer, to unmap or destroy when the channel is done
+    pub memory: SharedMemory,
     /// Channel identifier for management operations
     pub channel_id: usize,
     /// This component's role in the channel
@@ -59,10 +64,36 @@ pub struct ChannelConfig {
 /// # Returns
 /// * `Ok(ChannelConfig)` - Channel configuration on success
 /// * `Err(&str)` - Error message on failure
+///
+/// The producer sets the buffer up as a `message::Channel<u8>`; for other
+/// message types, use [`establish_channel_for`].
 pub fn establish_channel(
     channel_name: &str,
     buffer_size: usize,
     role: ChannelRole,
+) -> Result<ChannelConfig, &'static str> {
+    establish::<u8>(channel_name, buffer_size, role)
+}
+
+/// Establish an IPC channel carrying `T` messages
+///
+/// Like [`establish_channel`], with the buffer sized (in whole pages) and
+/// set up for a `message::Channel<T>`. Both sides must use the same `T`.
+pub fn establish_channel_for<T: Copy>(
+    channel_name: &str,
+    role: ChannelRole,
+) -> Result<ChannelConfig, &'static str> {
+    let ring_size = core::mem::size_of::<SharedRing<T, CHANNEL_CAPACITY>>();
+    establish::<T>(channel_name, (ring_size + 0xFFF) & !0xFFF, role)
+}
+
+/// Capacity of the ring a `message::Channel` uses
+const CHANNEL_CAPACITY: usize = 256;
+
+fn establish<T: Copy>(
+    channel_name: &str,
+    buffer_size: usize,
+    role: ChannelRole,
 ) -> Result<ChannelConfig, &'static str> {
     use crate::printf;
 
@@ -75,19 +106,14 @@ pub fn establish_channel(
         return Err("Buffer size must be non-zero and page-aligned");
     }
 
-    let (phys_addr, virt_addr, producer_notification) = match role {
+    let rw = Permissions::RW.bits() as u64;
+    let (memory, virt_addr, notification_cap) = match role {
         ChannelRole::Producer => {
-            // Producer allocates the shared buffer physical memory
-            let buffer_phys = match syscall::memory_allocate(buffer_size) {
-                Ok(addr) => addr,
-                Err(_) => return Err("Failed to allocate buffer physical memory"),
-            };
-
-            // Map buffer into our address space
-            let buffer_virt = match syscall::memory_map(buffer_phys, buffer_size, 0x3) {
-                Ok(addr) => addr,
-                Err(_) => return Err("Failed to map buffer into address space"),
-            };
+            // Producer allocates the shared buffer and maps it into our address space
+            let mut memory = SharedMemory::create(buffer_size)
+                .map_err(|_| "Failed to allocate buffer physical memory")?;
+            let buffer_virt = memory.map(rw)
+                .map_err(|_| "Failed to map buffer into address space")?;
             // WORKAROUND: This prevents optimization bug - DO NOT REMOVE
             if buffer_virt == 0 { printf!(""); }
 
@@ -98,7 +124,7 @@ pub fn establish_channel(
             };
 
             // Initialize SharedRing in the mapped memory with the notification
-            // This MUST happen before shmem_register so consumers see initialized memory
+            // This MUST happen before publishing so consumers see initialized memory
             use core::ptr;
 
             // Zero the entire buffer first (includes the ring buffer and atomics)
@@ -106,71 +132,38 @@ pub fn establish_channel(
                 ptr::write_bytes(buffer_virt as *mut u8, 0, buffer_size);
             }
 
-            // Now set the notification field at the correct offset
-            // SharedRing layout: buffer[256], head(usize), tail(usize), consumer_notify(Option<u64>), producer_notify(Option<u64>)
-            // Offset calculations:
-            // - buffer: 0..256
-            // - head: 256..264 (AtomicUsize = usize = 8 bytes)
-            // - tail: 264..272
-            // - consumer_notify: 272..288 (Option<u64> = 16 bytes with discriminant)
-            // - producer_notify: 288..304
-            //
-            // Option<u64> layout in memory:
-            // - Byte 0: discriminant (0=None, 1=Some)
-            // - Bytes 1-7: padding
-            // - Bytes 8-15: u64 value
-            let consumer_notify_offset = 256 + 8 + 8; // After buffer + head + tail
+            // Now build the ring in place, signaling the consumer through the
+            // notification (the layout is SharedRing's own, so it is not
+            // hardcoded here)
             unsafe {
-                // Write discriminant: 1 for Some
-                let discriminant_ptr = (buffer_virt + consumer_notify_offset) as *mut u8;
-                ptr::write(discriminant_ptr, 1);
-
-                // Write the u64 value at offset +8
-                let value_ptr = (buffer_virt + consumer_notify_offset + 8) as *mut u64;
-                ptr::write(value_ptr, notification_cap as u64);
+                ptr::write(
+                    buffer_virt as *mut SharedRing<T, CHANNEL_CAPACITY>,
+                    SharedRing::with_consumer_notification(notification_cap.slot() as u64),
+                );
             }
 
-            // Register the physical address and notification with the kernel broker
-            // After this point, consumers can query and map the memory, and get the notification
-            unsafe {
-                syscall::shmem_register(channel_name, buffer_phys, buffer_size, notification_cap)
-                    .map_err(|_| "Failed to register shared memory with broker")?;
-            }
-            (buffer_phys, buffer_virt, Some(notification_cap))
+            // Publish the buffer and notification with the kernel broker
+            // After this point, consumers can open and map the memory, and get the notification
+            memory.publish(channel_name, Some(notification_cap.slot() as u64))
+                .map_err(|_| "Failed to register shared memory with broker")?;
+            (memory, buffer_virt, notification_cap)
         }
         ChannelRole::Consumer => {
-            // Query the broker for the physical address
-            let buffer_phys = unsafe {
-                syscall::shmem_query(channel_name)
-                    .map_err(|_| "Producer has not yet allocated shared memory")?
-            };
-
-            // Map the producer's buffer into our address space
-            let buffer_virt = match syscall::memory_map(buffer_phys, buffer_size, 0x3) {
-                Ok(addr) => addr,
-                Err(_) => return Err("Failed to map shared buffer into address space"),
-            };
+            // Find the producer's buffer through the broker and map it
+            let mut memory = SharedMemory::open(channel_name, buffer_size)
+                .map_err(|_| "Producer has not yet allocated shared memory")?;
+            let buffer_virt = memory.map(rw)
+                .map_err(|_| "Failed to map shared buffer into address space")?;
 
             // Get notification capability from the kernel broker
             // This creates a capability in our CSpace pointing to the producer's notification object
-            const CONSUMER_NOTIFY_SLOT: usize = 103; // Allocate slot 103 for the notification
-            unsafe {
-                syscall::shmem_get_notification(channel_name, CONSUMER_NOTIFY_SLOT)
-                    .map_err(|_| "Failed to get notification capability from broker")?;
-            }
+            let notification_cap = unsafe { syscall::shmem_get_notification(channel_name) }
+                .map_err(|_| "Failed to get notification capability from broker")?;
 
-            (buffer_phys, buffer_virt, Some(CONSUMER_NOTIFY_SLOT))
+            (memory, buffer_virt, notification_cap)
         }
     };
 
-    // Step 3: Determine notification capability
-    // Producer has notification for signaling consumer (not used yet - polling instead)
-    // Consumer has no notification (will use polling)
-    let notification_cap = match producer_notification {
-        Some(cap) => cap, // Producer has notification (unused for now)
-        None => 0,  // Consumer doesn't need notification for polling
-    };
-
     // WORKAROUND: This printf prevents a heisenbug where buffer_addr gets corrupted
     // The bug appears to be related to compiler optimization or stack layout
     // Removing this line causes crashes with FAR=0x66/0x164 (wrong buffer address)
@@ -187,12 +180,24 @@ pub fn establish_channel(
         buffer_addr: virt_addr,
         buffer_size,
         notification_cap,
-        memory_cap: Some(phys_addr), // Store physical address for debugging
+        memory_cap: Some(memory.phys_addr()), // Store physical address for debugging
+        memory,
         channel_id: 0, // TODO: Get from broker
         role,
     })
 }
 
+impl From<&ChannelConfig> for crate::message::ChannelConfig {
+    /// The message channel over an established channel's buffer
+    fn from(config: &ChannelConfig) -> Self {
+        Self {
+            shared_memory: config.buffer_addr,
+            receiver_notify: config.notification_cap.slot() as u64,
+            sender_notify: config.notification_cap.slot() as u64,
+        }
+    }
+}
+
 /// Query information about an established channel
 ///
 /// # Arguments
@@ -2,7 +2,79 @@
 //!
 //! Provides patterns and helpers for building system components (drivers, services, apps).
 
-use crate::{Result, capability::Notification};
+use crate::env::{self, InitialCap};
+use crate::{Result, capability::{Cap, Notification}, syscall};
+
+/// CSpace slot where the root task puts the notification a component
+/// signals once it has initialized
+///
+/// Only components others depend on (`depends_on` in system.toml) get one.
+pub const READY_NOTIFICATION_SLOT: usize = 3;
+
+/// Signal bit for reporting ready
+pub const READY_BADGE: u64 = 1 << 0;
+
+/// CSpace slot where the root task puts the notification it signals to ask
+/// a component to shut down
+pub const CONTROL_NOTIFICATION_SLOT: usize = 4;
+
+/// Signal bit of a shutdown request
+pub const SHUTDOWN_BADGE: u64 = 1 << 0;
+
+/// CSpace slot where the root task puts the notification a component it
+/// watches for hangs signals its heartbeats on
+///
+/// Only components with a `heartbeat_ms` in system.toml get one.
+pub const HEARTBEAT_NOTIFICATION_SLOT: usize = 7;
+
+/// Signal bit of a heartbeat
+pub const HEARTBEAT_BADGE: u64 = 1 << 0;
+
+/// Whether the root task has asked this component to shut down
+///
+/// The root task destroys a component it stops once a grace period is over.
+/// A component with state to put away checks this in its main loop, and
+/// once done calls [`syscall::process_exit`] with 0, so it is not cut off
+/// halfway. Always false for components the root task did not spawn.
+pub fn shutdown_requested() -> bool {
+    let control = unsafe { Cap::borrow_raw(CONTROL_NOTIFICATION_SLOT, Notification) };
+    matches!(
+        syscall::poll(&control),
+        Ok(bits) if bits != u64::MAX && bits & SHUTDOWN_BADGE != 0
+    )
+}
+
+/// Report that this component has initialized
+///
+/// The root task spawns the components depending on this one only once it
+/// has. [`Component::start`] calls this after `init` succeeds; a component
+/// with its own entry point calls it when it is ready to serve. Does
+/// nothing if no one waits for the component.
+pub fn signal_ready() {
+    let ready = unsafe { Cap::borrow_raw(READY_NOTIFICATION_SLOT, Notification) };
+    // Polling an empty slot fails quietly, where signaling it would not
+    if matches!(syscall::poll(&ready), Ok(bits) if bits != u64::MAX) {
+        let _ = syscall::signal(&ready, READY_BADGE);
+    }
+}
+
+/// Signal a heartbeat: this component is still making progress
+///
+/// A component with a `heartbeat_ms` in system.toml calls this from its
+/// main loop at least that often, where it has done a round of work. The
+/// root task reports it as hung once it has missed `max_missed_heartbeats`
+/// in a row, and restarts it with `on_hang = "restart"`. One that can wait
+/// for work longer than `heartbeat_ms` has a [`Timer`] wake it up to beat,
+/// on a notification [bound](Cap::bind) to it if it waits for
+/// messages. Does nothing for components the root task does not watch.
+///
+/// [`Timer`]: crate::capability::Timer
+pub fn heartbeat() {
+    if let Some(slot) = env::cap_slot(InitialCap::Heartbeat) {
+        let heartbeat = unsafe { Cap::borrow_raw(slot, Notification) };
+        let _ = syscall::signal(&heartbeat, HEARTBEAT_BADGE);
+    }
+}
 
 /// Component lifecycle trait
 ///
@@ -40,10 +112,14 @@ pub trait Component: Sized {
 
     /// Start the component (convenience method)
     ///
-    /// Combines init + run for simple components.
+    /// Combines init + run for simple components, reporting ready with
+    /// [`signal_ready`] in between.
     fn start() -> ! {
         match Self::init() {
-            Ok(mut component) => component.run(),
+            Ok(mut component) => {
+                signal_ready();
+                component.run()
+            }
             Err(_) => {
                 // Component failed to initialize
                 loop {
@@ -151,7 +227,7 @@ macro_rules! component_metadata {
 /// This macro generates everything needed for a component:
 /// - Component metadata
 /// - _start entry point
-/// - Panic handler
+/// - Panic handler, printing a backtrace (see [`panic`](crate::panic))
 /// - Global allocator
 #[macro_export]
 macro_rules! component {
@@ -175,25 +251,17 @@ macro_rules! component {
                 .with_caps(&[$($cap),*])
             )?;
 
-        // Generate _start entry point
+        // Generate _start entry point; x0 holds the boot block's address
         #[no_mangle]
-        pub extern "C" fn _start() -> ! {
+        pub extern "C" fn _start(boot_block: usize) -> ! {
+            unsafe { $crate::env::init(boot_block) };
             <$component_type as $crate::component::Component>::start()
         }
 
-        // Generate panic handler
+        // Generate panic handler: report with a backtrace, then halt
         #[panic_handler]
         fn panic(info: &core::panic::PanicInfo) -> ! {
-            $crate::syscall::print("[");
-            $crate::syscall::print($name);
-            $crate::syscall::print("] PANIC");
-
-            if let Some(location) = info.location() {
-                $crate::syscall::print(" at ");
-                $crate::syscall::print(location.file());
-            }
-
-            $crate::syscall::print("\n");
+            $crate::panic::report($name, info);
 
             loop {
                 unsafe {
@@ -209,7 +277,7 @@ macro_rules! component {
 /// Provides common functionality for device drivers.
 pub struct DriverBase {
     /// IRQ notification
-    pub irq_notification: Option<Notification>,
+    pub irq_notification: Option<Cap<Notification>>,
     /// Device name
     pub name: &'static str,
 }
@@ -225,7 +293,7 @@ impl DriverBase {
 
     /// Register for IRQ notifications
     pub fn register_irq(&mut self) -> Result<()> {
-        let notification = Notification::create()?;
+        let notification = Cap::<Notification>::create()?;
         self.irq_notification = Some(notification);
         Ok(())
     }
@@ -271,12 +339,12 @@ impl ServiceBase {
     }
 }
 
-/// Component discovery support
-///
-/// Components can be discovered by root-task or system_init through:
-/// 1. Static registry (components known at compile time)
-/// 2. Component metadata section (embedded in binary)
-/// 3. Manifest file (components.toml)
+// Component discovery support
+//
+// Components can be discovered by root-task or system_init through:
+// 1. Static registry (components known at compile time)
+// 2. Component metadata section (embedded in binary)
+// 3. Manifest file (components.toml)
 
 /// Component binary descriptor
 ///
@@ -377,4 +445,4 @@ macro_rules! register_component {
 
 // Component spawning
 pub mod spawn;
-pub use spawn::{SpawnResult, spawn_from_elf};
+pub use spawn::{SpawnResult, Thread, spawn_from_elf, spawn_thread};
@@ -2,6 +2,10 @@
 //!
 //! Allows privileged components like system_init to spawn other components.
 //! Uses existing syscalls - no kernel changes needed!
+//!
+//! Any component can also spawn worker threads of its own with
+//! [`spawn_thread`]: they run in its address space with its capabilities,
+//! each on its own stack and with its own IPC buffer.
 
 use crate::{Result, Error, elf, syscall};
 
@@ -59,6 +63,17 @@ pub fn spawn_from_elf_with_untyped(
     priority: u8,
     capabilities: u64,
     untyped_cap_slot: usize,
+) -> Result<SpawnResult> {
+    spawn_elf(binary_data, priority, capabilities, Some(untyped_cap_slot))
+}
+
+/// Spawn a component, its image and stack retyped from `untyped_cap_slot`,
+/// or allocated with `memory_allocate` without one
+pub(crate) fn spawn_elf(
+    binary_data: &[u8],
+    priority: u8,
+    capabilities: u64,
+    untyped_cap_slot: Option<usize>,
 ) -> Result<SpawnResult> {
     unsafe {
         // Debug: log binary size
@@ -78,7 +93,7 @@ pub fn spawn_from_elf_with_untyped(
 
         // Process image (with extra page for safety)
         let base_size = elf_info.memory_size();
-        let process_size = ((base_size + 8192 + 4095) & !4095); // Round up to pages
+        let process_size = (base_size + 8192 + 4095) & !4095; // Round up to pages
         // Calculate log2 ceiling: round up to next power of 2, then take log2
         let process_size_pow2 = if process_size.is_power_of_two() {
             process_size
@@ -88,34 +103,18 @@ pub fn spawn_from_elf_with_untyped(
         let process_size_bits = process_size_pow2.trailing_zeros() as usize;
 
         // Sanity check: size_bits should be reasonable (12 to 25 = 4KB to 32MB)
-        if process_size_bits < 12 || process_size_bits > 25 {
+        if !(12..=25).contains(&process_size_bits) {
             printf!("[spawn_from_elf] ERROR: Invalid process_size_bits={} for size={}\n",
                     process_size_bits, process_size);
             return Err(Error::InvalidParameter);
         }
 
-        // Allocate capability slots dynamically to avoid conflicts
-        let process_cap_slot = syscall::cap_allocate()?;
-        let stack_cap_slot = syscall::cap_allocate()?;
-
         // Allocate process memory from UntypedMemory (capability-based!)
-        let process_phys = syscall::sys_retype(
-            untyped_cap_slot,
-            8, // CAP_TYPE_PAGE
-            process_size_bits,
-            0, // dest_cnode=0 means own CSpace
-            process_cap_slot,
-        )?;
+        let process_phys = allocate_pages(untyped_cap_slot, process_size_bits)?;
 
         // Stack from UntypedMemory (16KB = 2^14)
         let stack_size = 16384;
-        let stack_phys = syscall::sys_retype(
-            untyped_cap_slot,
-            8, // CAP_TYPE_PAGE
-            14, // 2^14 = 16KB
-            0,
-            stack_cap_slot,
-        )?;
+        let stack_phys = allocate_pages(untyped_cap_slot, 14)?;
 
         // Page table root - use traditional allocation for now
         // TODO: Implement PageTable initialization in sys_retype
@@ -181,6 +180,7 @@ pub fn spawn_from_elf_with_untyped(
             stack_phys,
             priority,
             capabilities,  // Pass capabilities to new process
+            0,  // The image was loaded for this process alone
         ) {
             Ok(p) => {
                 printf!("[spawn_from_elf] process_create succeeded, PID={:#x}\n", p);
@@ -202,3 +202,103 @@ pub fn spawn_from_elf_with_untyped(
         })
     }
 }
+
+/// Allocate 2^`size_bits` bytes of pages, retyped from UntypedMemory into
+/// a fresh capability slot if there is an untyped capability
+fn allocate_pages(untyped_cap_slot: Option<usize>, size_bits: usize) -> Result<usize> {
+    match untyped_cap_slot {
+        Some(untyped_cap_slot) => {
+            // Allocate capability slots dynamically to avoid conflicts
+            let cap_slot = syscall::cap_allocate()?;
+            syscall::sys_retype(
+                untyped_cap_slot,
+                8, // CAP_TYPE_PAGE
+                size_bits,
+                0, // dest_cnode=0 means own CSpace
+                cap_slot,
+            )
+        }
+        None => syscall::memory_allocate(1 << size_bits),
+    }
+}
+
+/// Size of a worker thread's stack (16KB, as for a component's main thread)
+pub const THREAD_STACK_SIZE: usize = 16384;
+
+/// Size of a worker thread's IPC buffer
+pub const THREAD_IPC_BUFFER_SIZE: usize = 4096;
+
+/// A worker thread of the calling component
+#[derive(Debug, Clone, Copy)]
+pub struct Thread {
+    /// Thread ID (TCB physical address)
+    pub tid: usize,
+    /// Top of its stack
+    pub stack_top: usize,
+    /// Its IPC buffer
+    pub ipc_buffer: usize,
+}
+
+/// Spawn a worker thread running `entry(arg)`
+///
+/// The thread shares the component's address space, capabilities and
+/// supervisor: a crash in it is the component's, and destroying the
+/// component ends it. It ends when `entry` returns. Its stack and IPC
+/// buffer are allocated together and mapped, which needs the memory
+/// capability, and stay with the component after it ends. The kernel
+/// allows a component 15 worker threads at a time.
+///
+/// Memory the threads share needs its own synchronization.
+///
+/// # Arguments
+/// * `entry` - What the thread runs
+/// * `arg` - Passed to `entry`
+/// * `priority` - Scheduling priority (0-255), clamped to the caller's
+///
+/// # Example
+/// ```no_run
+/// fn worker(id: usize) {
+///     printf!("worker {} running\n", id);
+/// }
+///
+/// let thread = spawn_thread(worker, 1, 100)?;
+/// printf!("Spawned worker TID: {:#x}\n", thread.tid);
+/// ```
+pub fn spawn_thread(entry: fn(usize), arg: usize, priority: u8) -> Result<Thread> {
+    // The stack, with the IPC buffer above its top
+    let size = THREAD_STACK_SIZE + THREAD_IPC_BUFFER_SIZE;
+    let phys = syscall::memory_allocate(size)?;
+    const RW_PERMS: usize = 0x3;
+    let base = syscall::memory_map(phys, size, RW_PERMS)?;
+    let stack_top = base + THREAD_STACK_SIZE;
+    let ipc_buffer = stack_top;
+
+    let tid = unsafe {
+        syscall::thread_create(
+            thread_start as extern "C" fn(usize, usize) -> ! as usize,
+            stack_top,
+            ipc_buffer,
+            priority,
+            entry as usize,
+            arg,
+        )?
+    };
+
+    Ok(Thread {
+        tid,
+        stack_top,
+        ipc_buffer,
+    })
+}
+
+/// Where worker threads start: run the entry point, then end the thread
+extern "C" fn thread_start(entry: usize, arg: usize) -> ! {
+    let entry: fn(usize) = unsafe { core::mem::transmute(entry) };
+    entry(arg);
+    syscall::thread_exit();
+
+    // Not reached: only a component's main thread gets back here
+    loop {
+        syscall::yield_now();
+    }
+}
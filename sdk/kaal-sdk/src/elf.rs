@@ -39,11 +39,7 @@ pub struct ElfInfo {
 impl ElfInfo {
     /// Calculate total memory size needed for process
     pub fn memory_size(&self) -> usize {
-        if self.max_vaddr >= self.min_vaddr {
-            self.max_vaddr - self.min_vaddr
-        } else {
-            0
-        }
+        self.max_vaddr.saturating_sub(self.min_vaddr)
     }
 }
 
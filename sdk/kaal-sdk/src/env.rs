@@ -0,0 +1,212 @@
+//! Arguments, environment and initial capabilities
+//!
+//! The root task gives each component it spawns a boot block: a read-only
+//! page holding the component's arguments and environment from system.toml,
+//! and a map of the capabilities it put in the component's CSpace. The
+//! component starts with the block's address in x0, which the
+//! [`component!`](crate::component!) entry point hands to [`init`].
+//! Components the root task did not spawn have no boot block: they get no
+//! arguments, no environment, and an empty capability map.
+//!
+//! ```no_run
+//! use kaal_sdk::env::{self, InitialCap};
+//!
+//! let verbose = env::args().any(|arg| arg == "--verbose");
+//! let log_level = env::var("LOG_LEVEL").unwrap_or("info");
+//! let untyped = env::cap_slot(InitialCap::Untyped);
+//! ```
+//!
+//! # Layout
+//!
+//! Little-endian, in one page:
+//!
+//! | Offset | Contents |
+//! |--------|----------|
+//! | 0      | `u32` magic, [`BOOT_BLOCK_MAGIC`] |
+//! | 4      | `u32` version, [`BOOT_BLOCK_VERSION`] |
+//! | 8      | `u32` argument count |
+//! | 12     | `u32` environment variable count |
+//! | 16     | `u32` capability map entries |
+//! | 20     | `u32` bytes of strings |
+//! | 64     | capability map: [`MAX_BOOT_CAPS`] × (`u32` [`InitialCap`], `u32` slot) |
+//! | 192    | arguments, then `KEY=VALUE` variables, each NUL-terminated |
+//!
+//! The first argument is the component's name. The root task adds to the
+//! capability map as it hands capabilities over, some after the component
+//! has started, so one may show up only later.
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Size of the boot block
+pub const BOOT_BLOCK_SIZE: usize = 4096;
+
+/// First word of a boot block ("KAAL")
+pub const BOOT_BLOCK_MAGIC: u32 = 0x4C41_414B;
+
+/// Layout version described here
+pub const BOOT_BLOCK_VERSION: u32 = 1;
+
+/// Most entries in the capability map
+pub const MAX_BOOT_CAPS: usize = 16;
+
+const ARGC_OFFSET: usize = 8;
+const ENVC_OFFSET: usize = 12;
+const CAPC_OFFSET: usize = 16;
+const STRINGS_LEN_OFFSET: usize = 20;
+const CAPS_OFFSET: usize = 64;
+const STRINGS_OFFSET: usize = CAPS_OFFSET + MAX_BOOT_CAPS * 8;
+
+/// A capability the root task may put in a component's CSpace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum InitialCap {
+    /// IRQControl, for `irq:control` components
+    IrqControl = 1,
+    /// UntypedMemory of its own
+    Untyped = 2,
+    /// Notification to report ready on ([`component::signal_ready`](crate::component::signal_ready))
+    ReadyNotification = 3,
+    /// Notification shutdown requests come on ([`component::shutdown_requested`](crate::component::shutdown_requested))
+    ControlNotification = 4,
+    /// Endpoint to send log messages to ([`log`](crate::log))
+    Log = 5,
+    /// The same endpoint, for the log server to receive them on
+    LogServer = 6,
+    /// Endpoint to ask the root task to start and stop components on, for
+    /// the init component and `boot:control` ([`boot`](crate::boot))
+    Boot = 7,
+    /// Notification to signal heartbeats on, for components the root task
+    /// watches for hangs ([`component::heartbeat`](crate::component::heartbeat))
+    Heartbeat = 8,
+    /// Endpoint the VFS service (`vfs:serve`) receives on, which the root
+    /// task reads component binaries through
+    VfsServer = 9,
+}
+
+/// Address of our boot block, 0 if there is none
+static BOOT_BLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// Take the boot block at `addr`, the value of x0 at entry
+///
+/// The [`component!`](crate::component!) entry point does this; a component
+/// with its own `_start` calls it first thing, with its first argument.
+/// Ignored unless `addr` holds a boot block of this version.
+///
+/// # Safety
+/// `addr` must be 0 or the address x0 held when the component started.
+pub unsafe fn init(addr: usize) {
+    if addr == 0 || !addr.is_multiple_of(BOOT_BLOCK_SIZE) {
+        return;
+    }
+    let block = &*(addr as *const [u8; BOOT_BLOCK_SIZE]);
+    if read_u32(block, 0) == BOOT_BLOCK_MAGIC && read_u32(block, 4) == BOOT_BLOCK_VERSION {
+        BOOT_BLOCK.store(addr, Ordering::Release);
+    }
+}
+
+/// The boot block, if we were given one
+fn boot_block() -> Option<&'static [u8; BOOT_BLOCK_SIZE]> {
+    match BOOT_BLOCK.load(Ordering::Acquire) {
+        0 => None,
+        addr => Some(unsafe { &*(addr as *const [u8; BOOT_BLOCK_SIZE]) }),
+    }
+}
+
+fn read_u32(block: &[u8; BOOT_BLOCK_SIZE], offset: usize) -> u32 {
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&block[offset..offset + 4]);
+    u32::from_le_bytes(raw)
+}
+
+/// The arguments and variables, in order
+fn strings() -> Strings {
+    let Some(block) = boot_block() else {
+        return Strings { rest: &[], left: 0 };
+    };
+    let len = (read_u32(block, STRINGS_LEN_OFFSET) as usize).min(BOOT_BLOCK_SIZE - STRINGS_OFFSET);
+    let count = read_u32(block, ARGC_OFFSET) as usize + read_u32(block, ENVC_OFFSET) as usize;
+    Strings { rest: &block[STRINGS_OFFSET..STRINGS_OFFSET + len], left: count }
+}
+
+/// Number of arguments
+fn argc() -> usize {
+    boot_block().map_or(0, |block| read_u32(block, ARGC_OFFSET) as usize)
+}
+
+/// The component's arguments, its name first
+pub fn args() -> Args {
+    let mut strings = strings();
+    strings.left = strings.left.min(argc());
+    Args(strings)
+}
+
+/// The component's environment variables, as `(key, value)`
+pub fn vars() -> Vars {
+    let mut strings = strings();
+    for _ in 0..argc() {
+        strings.next();
+    }
+    Vars(strings)
+}
+
+/// Value of the environment variable `key`
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|&(k, _)| k == key).map(|(_, value)| value)
+}
+
+/// Slot of an initial capability in our CSpace, if the root task has put
+/// it there
+pub fn cap_slot(cap: InitialCap) -> Option<usize> {
+    let block = boot_block()?;
+    // Entries are written before the count that includes them
+    let capc = unsafe { &*(block.as_ptr().add(CAPC_OFFSET) as *const AtomicU32) };
+    let count = (capc.load(Ordering::Acquire) as usize).min(MAX_BOOT_CAPS);
+    (0..count).find_map(|i| {
+        let entry = CAPS_OFFSET + i * 8;
+        (read_u32(block, entry) == cap as u32).then(|| read_u32(block, entry + 4) as usize)
+    })
+}
+
+/// NUL-terminated strings of the boot block
+struct Strings {
+    rest: &'static [u8],
+    left: usize,
+}
+
+impl Iterator for Strings {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        if self.left == 0 || self.rest.is_empty() {
+            return None;
+        }
+        self.left -= 1;
+        let end = self.rest.iter().position(|&b| b == 0).unwrap_or(self.rest.len());
+        let string = &self.rest[..end];
+        self.rest = self.rest.get(end + 1..).unwrap_or(&[]);
+        Some(core::str::from_utf8(string).unwrap_or(""))
+    }
+}
+
+/// Iterator over the component's arguments; see [`args`]
+pub struct Args(Strings);
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        self.0.next()
+    }
+}
+
+/// Iterator over the component's environment variables; see [`vars`]
+pub struct Vars(Strings);
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let variable = self.0.next()?;
+        Some(variable.split_once('=').unwrap_or((variable, "")))
+    }
+}
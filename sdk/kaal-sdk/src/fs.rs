@@ -0,0 +1,266 @@
+//! Files, through the VFS service
+//!
+//! A std-like face on the VFS service's protocol ([`kaal_ipc::vfs`]):
+//! open a [`File`], read and write it, and list directories with
+//! [`read_dir`].
+//!
+//! ```no_run
+//! use kaal_sdk::fs::{self, File};
+//!
+//! fs::set_endpoint(vfs_endpoint);
+//!
+//! let mut todo = File::create("/todo.txt")?;
+//! todo.write_all(b"[ ] water the plants\n")?;
+//! todo.close()?;
+//!
+//! for entry in fs::read_dir("/")? {
+//!     let entry = entry?;
+//!     kaal_sdk::printf!("{} ({} bytes)\n", entry.name(), entry.size());
+//! }
+//! # Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! Each call is an IPC call to the service, moving at most [`MAX_DATA`]
+//! bytes: [`File::read`] and [`File::write`] do no more than that at a
+//! time, like their std namesakes may. The root task hands the service its
+//! endpoint ([`InitialCap::VfsServer`](crate::env::InitialCap::VfsServer)),
+//! but not the other components yet; a component that holds it passes it
+//! to [`set_endpoint`].
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kaal_ipc::rpc::Client;
+use kaal_ipc::vfs::{flags, Bytes, Handle, Request, Response, VfsError};
+pub use kaal_ipc::vfs::{EntryKind, MAX_DATA, MAX_NAME, MAX_PATH};
+
+use crate::capability::{Cap, Endpoint};
+use crate::{Error, Result};
+
+/// Slot of the VFS service's endpoint, 0 until it is set
+static ENDPOINT: AtomicUsize = AtomicUsize::new(0);
+
+/// Use `endpoint`, the VFS service's, from now on
+///
+/// The endpoint set before, if any, is dropped.
+pub fn set_endpoint(endpoint: Cap<Endpoint>) {
+    let previous = ENDPOINT.swap(endpoint.into_raw(), Ordering::Relaxed);
+    if previous != 0 {
+        drop(unsafe { Cap::from_raw(previous, Endpoint) });
+    }
+}
+
+/// Call the VFS service
+///
+/// # Errors
+/// `CapabilityNotFound` before [`set_endpoint`]; `SyscallFailed` if the
+/// call fails; the service's error, as [`Error`], if the request does
+fn call(request: &Request) -> Result<Response> {
+    let endpoint = match ENDPOINT.load(Ordering::Relaxed) {
+        0 => return Err(Error::CapabilityNotFound),
+        slot => slot,
+    };
+    match Client::<Request, Response>::new(endpoint as u64).call(request) {
+        Ok(Response::Error(error)) => Err(from_vfs(error)),
+        Ok(response) => Ok(response),
+        Err(_) => Err(Error::SyscallFailed),
+    }
+}
+
+fn from_vfs(error: VfsError) -> Error {
+    match error {
+        VfsError::NotFound => Error::NotFound,
+        VfsError::PermissionDenied => Error::PermissionDenied,
+        VfsError::TooManyOpen => Error::Busy,
+        VfsError::NoSpace => Error::OutOfMemory,
+        VfsError::Io => Error::SyscallFailed,
+        VfsError::Exists
+        | VfsError::NotADirectory
+        | VfsError::IsADirectory
+        | VfsError::BadHandle
+        | VfsError::InvalidPath => Error::InvalidParameter,
+    }
+}
+
+fn path(path: &str) -> Result<Bytes<MAX_PATH>> {
+    Bytes::from_slice(path.as_bytes()).ok_or(Error::InvalidParameter)
+}
+
+/// A file open on the VFS service
+///
+/// Dropping it closes it, ignoring errors; [`close`](Self::close) reports
+/// them.
+#[derive(Debug)]
+pub struct File {
+    handle: Handle,
+}
+
+impl File {
+    /// Open the file at `path` for reading
+    pub fn open(path: &str) -> Result<File> {
+        Self::open_with(path, flags::READ)
+    }
+
+    /// Open the file at `path` for writing, creating it, or emptying it if
+    /// it exists
+    pub fn create(path: &str) -> Result<File> {
+        Self::open_with(path, flags::WRITE | flags::CREATE | flags::TRUNCATE)
+    }
+
+    /// Open the file at `path` with [`flags`]
+    pub fn open_with(path: &str, flags: u32) -> Result<File> {
+        match call(&Request::Open { path: self::path(path)?, flags })? {
+            Response::Opened { handle } => Ok(File { handle }),
+            _ => Err(Error::SyscallFailed),
+        }
+    }
+
+    /// Read into `buf` from the file's position, returning how many bytes
+    /// were read: at most [`MAX_DATA`], and 0 at the end of the file
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(MAX_DATA) as u32;
+        match call(&Request::Read { handle: self.handle, len })? {
+            Response::Data(data) if data.as_slice().len() <= buf.len() => {
+                let data = data.as_slice();
+                buf[..data.len()].copy_from_slice(data);
+                Ok(data.len())
+            }
+            _ => Err(Error::SyscallFailed),
+        }
+    }
+
+    /// Write from `buf` at the file's position, returning how many bytes
+    /// were written: at most [`MAX_DATA`]
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let data = Bytes::from_slice(&buf[..buf.len().min(MAX_DATA)]).ok_or(Error::InvalidParameter)?;
+        match call(&Request::Write { handle: self.handle, data })? {
+            Response::Written { len } => Ok(len as usize),
+            _ => Err(Error::SyscallFailed),
+        }
+    }
+
+    /// Write all of `buf`
+    ///
+    /// # Errors
+    /// As [`write`](Self::write), or `OutOfMemory` if the service stops
+    /// taking bytes
+    pub fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::OutOfMemory),
+                written => buf = &buf[written.min(buf.len())..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the file
+    pub fn close(self) -> Result<()> {
+        let handle = self.handle;
+        core::mem::forget(self);
+        match call(&Request::Close { handle })? {
+            Response::Closed => Ok(()),
+            _ => Err(Error::SyscallFailed),
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = call(&Request::Close { handle: self.handle });
+    }
+}
+
+/// An entry of a directory
+#[derive(Debug, Clone, Copy)]
+pub struct DirEntry {
+    kind: EntryKind,
+    size: u64,
+    name: Bytes<MAX_NAME>,
+}
+
+impl DirEntry {
+    /// The entry's name, without the directory's path
+    pub fn name(&self) -> &str {
+        // Checked when the entry was read
+        core::str::from_utf8(self.name.as_slice()).unwrap_or_default()
+    }
+
+    pub fn kind(&self) -> EntryKind {
+        self.kind
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+
+    /// Size in bytes; 0 for a directory
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Entries of a directory, from [`read_dir`]
+///
+/// Each one is a call to the service; an error ends the iteration.
+pub struct ReadDir {
+    path: Bytes<MAX_PATH>,
+    index: u32,
+    /// The first entry, read by `read_dir` to check the path
+    first: Option<Option<DirEntry>>,
+    done: bool,
+}
+
+impl ReadDir {
+    fn entry(&self, index: u32) -> Result<Option<DirEntry>> {
+        match call(&Request::ReadDir { path: self.path, index })? {
+            Response::Entry { kind, size, name } => {
+                core::str::from_utf8(name.as_slice()).map_err(|_| Error::InvalidParameter)?;
+                Ok(Some(DirEntry { kind, size, name }))
+            }
+            Response::EndOfDir => Ok(None),
+            _ => Err(Error::SyscallFailed),
+        }
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Result<DirEntry>> {
+        if self.done {
+            return None;
+        }
+        let entry = match self.first.take() {
+            Some(entry) => Ok(entry),
+            None => self.entry(self.index),
+        };
+        self.index += 1;
+        match entry {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// List the directory at `path`
+///
+/// # Errors
+/// `NotFound` if there is nothing at `path`, `InvalidParameter` if it is
+/// not a directory, or as [`File::open`]
+pub fn read_dir(path: &str) -> Result<ReadDir> {
+    let mut dir = ReadDir {
+        path: self::path(path)?,
+        index: 0,
+        first: None,
+        done: false,
+    };
+    dir.first = Some(dir.entry(0)?);
+    Ok(dir)
+}
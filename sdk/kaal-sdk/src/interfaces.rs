@@ -0,0 +1,18 @@
+//! Channel interfaces shared by system components
+//!
+//! Both ends of a channel use the definition here, so they agree on its
+//! name and message type. See [`interface`](crate::interface) for what
+//! each one generates.
+
+use crate::interface;
+
+/// Bytes read by the UART driver, for the application that has the
+/// console
+///
+/// The driver is the producer ([`UartInputClient`]), and applications
+/// consume ([`UartInputServer`]).
+#[interface(channel = "kaal.uart.output")]
+pub trait UartInput {
+    /// A byte (a key press, on a terminal) arrived on the UART
+    fn key(&mut self, byte: u8);
+}
@@ -6,9 +6,22 @@
 //! - [`syscall`]: Low-level syscall wrappers
 //! - [`ipc`]: High-level IPC utilities (re-exports from kaal-ipc)
 //! - [`capability`]: Capability management
-//! - [`memory`]: Memory allocation and mapping
+//! - [`memory`]: Memory allocation and mapping, and the heap `alloc` uses
 //! - [`process`]: Process creation and management
 //! - [`component`]: Component development patterns (drivers, services, apps)
+//! - [`env`]: Arguments, environment and initial capabilities from the root task
+//! - [`log`]: Logging through the log server, tagged with name and level
+//! - [`boot`]: Starting components, for the init component
+//! - [`time`]: Monotonic clock, sleeping and periodic timers
+//! - [`thread`]: Spawning threads from closures, and joining them
+//! - [`sync`]: Mutex, reader-writer lock and once-cell for sharing state
+//!   between threads
+//! - [`task`]: Async tasks on an executor woken by notification signals
+//! - [`fs`]: Files and directories, through the VFS service
+//! - [`panic`]: Panic reports with a backtrace, for the component panic handler
+//! - [`interfaces`]: Channel interfaces shared by system components, declared
+//!   with [`interface`]
+//! - [`trace`]: Syscall tracing (kernels built with `syscall-trace`)
 //!
 //! # Example
 //! ```no_run
@@ -22,6 +35,12 @@
 
 #![no_std]
 
+// Lets `interface`-generated code name the SDK the same way inside it as
+// in components
+extern crate self as kaal_sdk;
+
+extern crate alloc;
+
 pub mod syscall;
 pub mod capability;
 pub mod memory;
@@ -30,12 +49,26 @@ pub mod component;
 pub mod message;
 pub mod allocator;
 pub mod args;
+pub mod env;
+pub mod log;
+pub mod boot;
 pub mod channel_setup;
 pub mod elf;
+pub mod trace;
+pub mod time;
+pub mod thread;
+pub mod sync;
+pub mod task;
+pub mod fs;
+pub mod panic;
+pub mod interfaces;
 
 // Re-export IPC from kaal-ipc for convenience
 pub use kaal_ipc as ipc;
 
+// Typed channel stubs from a trait; see kaal-idl
+pub use kaal_idl::interface;
+
 /// SDK version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
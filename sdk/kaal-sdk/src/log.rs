@@ -0,0 +1,323 @@
+//! Logging through the log server
+//!
+//! [`printf!`](crate::printf!) and [`log!`](crate::log!) output goes to the
+//! log server, a component holding the console that the root task gives
+//! the log endpoint to receive on. It prints whole lines, each tagged with
+//! the name and level of the component it came from, so components
+//! printing at the same time no longer garble each other's lines.
+//!
+//! ```no_run
+//! use kaal_sdk::{log, printf};
+//!
+//! printf!("Mapped {} pages\n", 4);              // Info
+//! log!(Warn, "RX buffer overflow\n");
+//! log!(Debug, "Status register: {:#x}\n", 0x90);
+//! ```
+//!
+//! [`log_error!`](crate::log_error!), [`log_warn!`](crate::log_warn!),
+//! [`log_info!`](crate::log_info!) and [`log_debug!`](crate::log_debug!)
+//! log one line each, and end it themselves:
+//!
+//! ```no_run
+//! use kaal_sdk::{log_debug, log_warn};
+//!
+//! log_warn!("RX buffer overflow");
+//! log_debug!("Status register: {:#x}", 0x90);
+//! ```
+//!
+//! Their messages above [`MAX_LEVEL`] are compiled out, format arguments
+//! and all: it is `Debug` unless one of the SDK's `max-level-error`,
+//! `max-level-warn` or `max-level-info` features lowers it, so a release
+//! build can shed its debug logging.
+//!
+//! Messages below the component's `LOG_LEVEL` (`error`, `warn`, `info` or
+//! `debug`, from `env` in system.toml; `info` if unset) are dropped. Output
+//! goes straight to the debug console, untagged, where there is no log
+//! server to send it to: in components the root task did not spawn, before
+//! the root task has handed over the log endpoint, and in the log server
+//! itself.
+//!
+//! # Messages
+//!
+//! A message on the log endpoint is a `u8` [`Level`], a `u8` name length,
+//! the name, and text. Text longer than a message takes is sent in pieces;
+//! the log server puts lines back together.
+
+use core::fmt;
+
+use crate::capability::{Cap, Endpoint};
+use crate::env::{self, InitialCap};
+use crate::syscall;
+
+/// Severity of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    /// Something failed
+    Error = 1,
+    /// Something is wrong, but the component carries on
+    Warn = 2,
+    /// Progress; what [`printf!`](crate::printf!) logs at
+    Info = 3,
+    /// Detail for debugging
+    Debug = 4,
+}
+
+impl Level {
+    /// Name of the level, as `LOG_LEVEL` takes it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    /// The level named `name`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Level::Error, Level::Warn, Level::Info, Level::Debug]
+            .into_iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(name))
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        [Level::Error, Level::Warn, Level::Info, Level::Debug]
+            .into_iter()
+            .find(|level| *level as u8 == value)
+    }
+}
+
+/// Longest component name a message carries; longer ones are cut short
+pub const MAX_NAME: usize = 32;
+
+/// Lowest level the `log_*!` macros are compiled in for, from the SDK's
+/// `max-level-*` features
+pub const MAX_LEVEL: Level = if cfg!(feature = "max-level-error") {
+    Level::Error
+} else if cfg!(feature = "max-level-warn") {
+    Level::Warn
+} else if cfg!(feature = "max-level-info") {
+    Level::Info
+} else {
+    Level::Debug
+};
+
+/// Longest text [`log!`](crate::log!) and the `log_*!` macros log at
+/// once; longer text is cut short
+pub const MAX_LINE: usize = 512;
+
+/// Lowest level logged, from `LOG_LEVEL`
+pub fn max_level() -> Level {
+    env::var("LOG_LEVEL").and_then(Level::from_name).unwrap_or(Level::Info)
+}
+
+/// Whether messages at `level` are logged
+pub fn enabled(level: Level) -> bool {
+    level <= max_level()
+}
+
+/// Log `text` at `level`
+///
+/// What [`printf!`](crate::printf!) and [`log!`](crate::log!) come down to.
+pub fn write(level: Level, text: &str) {
+    if !enabled(level) {
+        return;
+    }
+    let Some(endpoint) = env::cap_slot(InitialCap::Log) else {
+        syscall::print(text);
+        return;
+    };
+    let endpoint = unsafe { Cap::borrow_raw(endpoint, Endpoint) };
+
+    let mut name = env::args().next().unwrap_or("");
+    if name.len() > MAX_NAME {
+        let mut end = MAX_NAME;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = &name[..end];
+    }
+
+    let mut message = [0u8; syscall::MAX_MESSAGE];
+    message[0] = level as u8;
+    message[1] = name.len() as u8;
+    message[2..2 + name.len()].copy_from_slice(name.as_bytes());
+    let header = 2 + name.len();
+
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut len = rest.len().min(message.len() - header);
+        while !rest.is_char_boundary(len) {
+            len -= 1;
+        }
+        message[header..header + len].copy_from_slice(&rest.as_bytes()[..len]);
+        if syscall::send(&endpoint, &message[..header + len]).is_err() {
+            syscall::print(rest);
+            return;
+        }
+        rest = &rest[len..];
+    }
+}
+
+/// Log formatted text at `level`
+///
+/// What [`log!`](crate::log!) comes down to. Formats on the stack, as
+/// [`write_line`] does, cutting the text short at [`MAX_LINE`] bytes.
+pub fn write_fmt(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let mut line = Line::new(MAX_LINE);
+    let _ = fmt::Write::write_fmt(&mut line, args);
+    write(level, line.as_str());
+}
+
+/// Log formatted text at `level` as a line of its own
+///
+/// What the `log_*!` macros come down to. Formats on the stack, so
+/// threads can log at once.
+pub fn write_line(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    // Room kept for the newline
+    let mut line = Line::new(MAX_LINE - 1);
+    // Cut short if too long: what fits is still worth logging
+    let _ = fmt::Write::write_fmt(&mut line, args);
+    line.buf[line.len] = b'\n';
+    line.len += 1;
+    write(level, line.as_str());
+}
+
+/// Text formatted on the stack, up to `limit` bytes of it
+struct Line {
+    buf: [u8; MAX_LINE],
+    len: usize,
+    limit: usize,
+}
+
+impl Line {
+    fn new(limit: usize) -> Self {
+        Self { buf: [0; MAX_LINE], len: 0, limit }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole characters are written
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+}
+
+impl fmt::Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = s.len().min(self.limit - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        if len < s.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A message received on the log endpoint
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    /// Level it was logged at
+    pub level: Level,
+    /// Name of the component that logged it
+    pub name: &'a str,
+    /// The text, or a piece of it
+    pub text: &'a str,
+}
+
+impl<'a> Record<'a> {
+    /// Decode a message; None if it is not one [`write`] sends
+    pub fn decode(message: &'a [u8]) -> Option<Self> {
+        let (&level, rest) = message.split_first()?;
+        let (&name_len, rest) = rest.split_first()?;
+        let name_len = name_len as usize;
+        if name_len > rest.len() {
+            return None;
+        }
+        let (name, text) = rest.split_at(name_len);
+        Some(Self {
+            level: Level::from_u8(level)?,
+            name: core::str::from_utf8(name).ok()?,
+            text: core::str::from_utf8(text).ok()?,
+        })
+    }
+}
+
+/// Log formatted text at a [`Level`]
+///
+/// Like [`printf!`](crate::printf!), which logs at `Info`, with the level
+/// first.
+///
+/// # Example
+/// ```no_run
+/// use kaal_sdk::log;
+/// log!(Error, "Failed to map {:#x}\n", 0x0900_0000);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:ident, $fmt:literal) => {
+        $crate::log::write($crate::log::Level::$level, $fmt)
+    };
+    ($level:ident, $fmt:literal, $($arg:expr),* $(,)?) => {
+        $crate::log::write_fmt($crate::log::Level::$level, core::format_args!($fmt, $($arg),*))
+    };
+}
+
+/// Log a line at `Error`
+///
+/// Like [`format!`](alloc::format!), without the newline: each call is a
+/// line. See the [`log`](crate::log) module for the levels compiled in.
+///
+/// # Example
+/// ```no_run
+/// use kaal_sdk::log_error;
+/// log_error!("Failed to map {:#x}", 0x0900_0000);
+/// ```
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => {
+        if $crate::log::Level::Error <= $crate::log::MAX_LEVEL {
+            $crate::log::write_line($crate::log::Level::Error, core::format_args!($($arg)+))
+        }
+    };
+}
+
+/// Log a line at `Warn`, as [`log_error!`](crate::log_error!)
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {
+        if $crate::log::Level::Warn <= $crate::log::MAX_LEVEL {
+            $crate::log::write_line($crate::log::Level::Warn, core::format_args!($($arg)+))
+        }
+    };
+}
+
+/// Log a line at `Info`, as [`log_error!`](crate::log_error!)
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => {
+        if $crate::log::Level::Info <= $crate::log::MAX_LEVEL {
+            $crate::log::write_line($crate::log::Level::Info, core::format_args!($($arg)+))
+        }
+    };
+}
+
+/// Log a line at `Debug`, as [`log_error!`](crate::log_error!)
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {
+        if $crate::log::Level::Debug <= $crate::log::MAX_LEVEL {
+            $crate::log::write_line($crate::log::Level::Debug, core::format_args!($($arg)+))
+        }
+    };
+}
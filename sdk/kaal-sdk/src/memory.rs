@@ -1,6 +1,9 @@
 //! Memory management
 //!
-//! Higher-level abstractions for memory allocation and mapping.
+//! Higher-level abstractions for memory allocation and mapping, and the
+//! component heap ([`heap`]).
+
+pub mod heap;
 
 use crate::{Result, syscall};
 
@@ -23,6 +26,21 @@ impl Permissions {
     pub const RX: Self = Self { bits: 0x5 };
     /// Read + Write + Execute
     pub const RWX: Self = Self { bits: 0x7 };
+    /// Map with 2MB large pages (combine with `or`; physical and virtual
+    /// addresses must be 2MB aligned)
+    pub const LARGE_PAGE: Self = Self { bits: 0x100 };
+    /// Map with 1GB huge pages (combine with `or`; physical and virtual
+    /// addresses must be 1GB aligned)
+    pub const HUGE_PAGE: Self = Self { bits: 0x200 };
+    /// Map as device memory, Device-nGnRnE (combine with `or`): MMIO
+    /// registers
+    pub const DEVICE: Self = Self { bits: 0x10 };
+    /// Map as normal non-cacheable memory (combine with `or`): buffers
+    /// shared with devices that do not snoop the caches
+    pub const NON_CACHEABLE: Self = Self { bits: 0x20 };
+    /// Map as write-combining device memory, Device-GRE (combine with
+    /// `or`): framebuffers; accesses must be aligned
+    pub const WRITE_COMBINE: Self = Self { bits: 0x30 };
 
     /// Get raw permission bits
     pub fn bits(&self) -> usize {
@@ -125,12 +143,18 @@ impl MappedMemory {
         self.virt_addr as *mut T
     }
 
-    /// Get as a byte slice (unsafe - caller must ensure proper alignment and validity)
+    /// Get as a byte slice
+    ///
+    /// # Safety
+    /// Caller must ensure proper alignment and validity
     pub unsafe fn as_slice(&self) -> &[u8] {
         core::slice::from_raw_parts(self.as_ptr(), self.size)
     }
 
-    /// Get as a mutable byte slice (unsafe - caller must ensure proper alignment and validity)
+    /// Get as a mutable byte slice
+    ///
+    /// # Safety
+    /// Caller must ensure proper alignment and validity
     pub unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
         core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.size)
     }
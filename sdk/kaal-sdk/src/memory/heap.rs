@@ -0,0 +1,268 @@
+//! Component heap
+//!
+//! The SDK's global allocator, so components can use `alloc::vec::Vec`,
+//! `alloc::string::String` and the like without bringing their own:
+//!
+//! ```no_run
+//! extern crate alloc;
+//! use alloc::vec::Vec;
+//!
+//! kaal_sdk::memory::heap::init(256 * 1024)?;
+//! let mut items = Vec::new();
+//! items.push(42);
+//! # Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! Freed memory is reused: the heap keeps a free list ordered by address
+//! and merges neighbouring blocks as they are freed. It takes its memory
+//! from the kernel in regions, the first on the first allocation unless
+//! [`init`] reserved one, and another whenever the free list has no block
+//! large enough: from the component's own untyped memory (`max_memory_kb`
+//! in system.toml) if it has some, otherwise with `memory_allocate`, which
+//! needs the memory capability. Regions are never handed back.
+//!
+//! Allocation takes a [`Mutex`], so worker threads (see
+//! [`component::spawn_thread`](crate::component::spawn_thread)) can share
+//! the heap.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::{align_of, size_of};
+use core::ptr;
+
+use crate::env::{self, InitialCap};
+use crate::sync::Mutex;
+use crate::{syscall, Error, Result};
+
+/// Smallest region taken from the kernel at a time (64KB)
+pub const REGION_SIZE: usize = 0x10000;
+
+const PAGE_SIZE: usize = 4096;
+
+/// `sys_retype` object type: page
+const OBJECT_PAGE: usize = 8;
+
+/// The heap the SDK installs as the global allocator
+#[global_allocator]
+static HEAP: Heap = Heap::new();
+
+/// Reserve `size` bytes of heap up front
+///
+/// Optional: the heap grows on demand. Reserving what a component needs at
+/// start fails early, before it is half-initialized, and spares it the
+/// stalls of growing later.
+///
+/// # Errors
+/// [`Error::OutOfMemory`] if the kernel has no memory for it, or the
+/// component may not allocate any
+pub fn init(size: usize) -> Result<()> {
+    let (start, size) = allocate_region(size)?;
+    unsafe { HEAP.add_region(start, size) };
+    Ok(())
+}
+
+/// A free block, kept in the block itself
+struct Block {
+    size: usize,
+    next: *mut Block,
+}
+
+/// Smallest block: a free one must hold its header
+const MIN_BLOCK: usize = size_of::<Block>();
+
+/// Alignment of every block, so a freed one can hold its header
+const BLOCK_ALIGN: usize = align_of::<Block>();
+
+/// Free list allocator over regions of memory it is given
+///
+/// First fit over a list ordered by address; freed blocks are merged with
+/// their neighbours. Not synchronized: [`Heap`] wraps it in a lock.
+pub struct FreeList {
+    head: *mut Block,
+}
+
+// It owns the blocks it points to
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    /// A free list with no memory yet
+    pub const fn new() -> Self {
+        Self { head: ptr::null_mut() }
+    }
+
+    /// Give the free list the memory at `[start, start + size)`
+    ///
+    /// # Safety
+    /// The memory must be mapped read-write and used by nothing else, for
+    /// as long as the free list is.
+    pub unsafe fn add_region(&mut self, start: usize, size: usize) {
+        let aligned = start.next_multiple_of(BLOCK_ALIGN);
+        let size = size.saturating_sub(aligned - start) & !(BLOCK_ALIGN - 1);
+        if size >= MIN_BLOCK {
+            self.insert(aligned, size);
+        }
+    }
+
+    /// Allocate a block for `layout`; null if no free block fits
+    ///
+    /// # Safety
+    /// Only memory given with [`add_region`](Self::add_region) is handed out.
+    pub unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let size = block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut link: *mut *mut Block = &mut self.head;
+        while !(*link).is_null() {
+            let block = *link;
+            let start = block as usize;
+            let end = start + (*block).size;
+
+            // Padding in front must be a free block of its own
+            let mut alloc_start = start.next_multiple_of(align);
+            if alloc_start != start && alloc_start - start < MIN_BLOCK {
+                alloc_start = (start + MIN_BLOCK).next_multiple_of(align);
+            }
+            let fits = alloc_start
+                .checked_add(size)
+                .filter(|&alloc_end| alloc_end <= end)
+                // and so must what is left behind it
+                .filter(|&alloc_end| end - alloc_end == 0 || end - alloc_end >= MIN_BLOCK);
+
+            if let Some(alloc_end) = fits {
+                let mut rest = (*block).next;
+                if alloc_end < end {
+                    let back = alloc_end as *mut Block;
+                    back.write(Block { size: end - alloc_end, next: rest });
+                    rest = back;
+                }
+                if alloc_start > start {
+                    (*block).size = alloc_start - start;
+                    (*block).next = rest;
+                    rest = block;
+                }
+                *link = rest;
+                return alloc_start as *mut u8;
+            }
+            link = &mut (*block).next;
+        }
+        ptr::null_mut()
+    }
+
+    /// Free a block [`allocate`](Self::allocate) returned for `layout`
+    ///
+    /// # Safety
+    /// `ptr` must have come from `allocate` with the same layout, and not
+    /// be freed already.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        self.insert(ptr as usize, block_size(layout));
+    }
+
+    /// Put `[start, start + size)` on the list, merged with its neighbours
+    unsafe fn insert(&mut self, start: usize, size: usize) {
+        let mut prev: *mut Block = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = start as *mut Block;
+        block.write(Block { size, next });
+        if !next.is_null() && start + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
+
+impl Default for FreeList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Size of the block holding an allocation of `layout`
+fn block_size(layout: Layout) -> usize {
+    layout.size().max(MIN_BLOCK).next_multiple_of(BLOCK_ALIGN)
+}
+
+/// Growable, locked heap: the SDK's global allocator
+///
+/// A [`FreeList`] that takes a new region from the kernel whenever it has
+/// no block large enough.
+pub struct Heap {
+    free: Mutex<FreeList>,
+}
+
+impl Heap {
+    /// A heap with no memory yet
+    pub const fn new() -> Self {
+        Self { free: Mutex::new(FreeList::new()) }
+    }
+
+    /// Give the heap the memory at `[start, start + size)`
+    ///
+    /// # Safety
+    /// As for [`FreeList::add_region`].
+    pub unsafe fn add_region(&self, start: usize, size: usize) {
+        self.free.lock().add_region(start, size);
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut free = self.free.lock();
+        let mut block = free.allocate(layout);
+        if block.is_null() {
+            // Room for the block and any padding to align it
+            let needed = block_size(layout) + layout.align() + MIN_BLOCK;
+            if let Ok((start, size)) = allocate_region(needed) {
+                free.add_region(start, size);
+                block = free.allocate(layout);
+            }
+        }
+        block
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.free.lock().deallocate(ptr, layout);
+    }
+}
+
+/// Take a region of at least `size` bytes from the kernel and map it
+///
+/// Returns its address and size.
+fn allocate_region(size: usize) -> Result<(usize, usize)> {
+    const RW_PERMS: usize = 0x3;
+
+    let size = size.max(REGION_SIZE).next_multiple_of(PAGE_SIZE);
+    let phys = untyped_region(size)
+        .or_else(|_| syscall::memory_allocate(size).map(|phys| (phys, size)))
+        .map_err(|_| Error::OutOfMemory);
+    let (phys, size) = phys?;
+    let start = syscall::memory_map(phys, size, RW_PERMS).map_err(|_| Error::OutOfMemory)?;
+    Ok((start, size))
+}
+
+/// Retype a region of at least `size` bytes from our untyped memory
+fn untyped_region(size: usize) -> Result<(usize, usize)> {
+    let untyped = env::cap_slot(InitialCap::Untyped).ok_or(Error::CapabilityNotFound)?;
+    let size = size.next_power_of_two();
+    let slot = syscall::cap_allocate()?;
+    let phys = syscall::sys_retype(untyped, OBJECT_PAGE, size.trailing_zeros() as usize, 0, slot)?;
+    Ok((phys, size))
+}
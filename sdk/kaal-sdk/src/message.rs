@@ -4,7 +4,7 @@
 //!
 //! ## Distinction from Notifications
 //!
-//! - **Notification** (`syscall::notification_create`, `capability::Notification`):
+//! - **Notification** (`syscall::notification_create`, `capability::Cap<Notification>`):
 //!   - Pure synchronization primitive (like eventfd/semaphore)
 //!   - Only carries signal bits (badges), no data payload
 //!   - Use for: event signaling, interrupts, general synchronization
@@ -47,7 +47,6 @@
 //! ```
 
 use crate::ipc::{SharedRing, IpcError};
-use crate::syscall;
 
 /// Channel configuration for establishing message-passing connection
 #[derive(Debug, Clone, Copy)]
@@ -74,6 +73,8 @@ pub struct Channel<T: Copy + 'static> {
     ring: &'static SharedRing<T, 256>,
     role: ChannelRole,
     my_notification: u64, // My notification cap for waiting (receiver) or signaling back (sender)
+    /// Sender's thread, lent the receiver's priority while it waits
+    sender_tcb: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -100,6 +101,7 @@ impl<T: Copy + 'static> Channel<T> {
             ring,
             role: ChannelRole::Sender,
             my_notification: config.receiver_notify, // Sender signals the RECEIVER's notification
+            sender_tcb: None,
         }
     }
 
@@ -120,6 +122,21 @@ impl<T: Copy + 'static> Channel<T> {
             ring,
             role: ChannelRole::Receiver,
             my_notification: config.receiver_notify,
+            sender_tcb: None,
+        }
+    }
+
+    /// Lend the receiver's priority to the sender's thread while waiting
+    /// in `receive`, so a low-priority sender cannot hold up a
+    /// high-priority receiver (priority inheritance)
+    ///
+    /// # Arguments
+    /// * `sender_tcb` - TCB capability slot of the sender's thread, in the
+    ///   receiver's CSpace
+    pub fn lend_priority_to(self, sender_tcb: u64) -> Self {
+        Self {
+            sender_tcb: Some(sender_tcb),
+            ..self
         }
     }
 
@@ -197,8 +214,14 @@ impl<T: Copy + 'static> Channel<T> {
                 }
                 Err(IpcError::BufferEmpty) => {
                     // Stream empty - block until producer signals more data available
+                    use crate::capability::{Cap, Notification};
                     use crate::syscall;
-                    match syscall::wait(self.my_notification as usize) {
+                    let notification = unsafe { Cap::borrow_raw(self.my_notification as usize, Notification) };
+                    if let Some(sender_tcb) = self.sender_tcb {
+                        // Best effort: without it the wait is just not boosted
+                        let _ = syscall::tcb_donate_priority(sender_tcb as usize, &notification);
+                    }
+                    match syscall::wait(&notification) {
                         Ok(_signals) => {
                             // Producer signaled - loop back to try reading from stream again
                         }
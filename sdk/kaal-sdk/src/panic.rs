@@ -0,0 +1,171 @@
+//! Panic reports with a backtrace
+//!
+//! The panic handler [`component!`](crate::component!) generates prints
+//! the panic's message and location, then the return addresses on the
+//! stack, found by walking the chain of frame records (x29):
+//!
+//! ```text
+//! [uart_driver] PANIC at src/main.rs:142:17: index out of bounds
+//! backtrace:
+//!   #0  0x0000000000401a2c  kaal_sdk::panic::report+0x3c
+//!   #1  0x0000000000400f18  rust_begin_unwind+0x18
+//!   #2  0x0000000000403b60  core::panicking::panic_fmt+0x30
+//!   #3  0x0000000000400d94  uart_driver::UartDriver::handle_rx_interrupt+0x74
+//! ```
+//!
+//! Components keep their frame records with `-C force-frame-pointers=yes`,
+//! which the build's `.cargo/config.toml` for components passes. Without
+//! them the backtrace stops short, or is empty.
+//!
+//! # Symbols
+//!
+//! With the SDK's `backtrace-symbols` feature, a component carries a
+//! symbol table of [`SYMBOLS_SIZE`] bytes for the addresses to be named
+//! by. The SDK reserves it, starting with [`SYMBOLS_MAGIC`], and the build
+//! fills it in once the component is linked, from `llvm-nm`: lines of a
+//! 16-digit hex address, a space and a function name, in address order,
+//! ended by a NUL. Until then it is empty, and addresses are printed bare;
+//! `llvm-addr2line -e <component ELF>` resolves them as well.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use crate::syscall;
+
+/// Most frames a backtrace prints
+pub const MAX_FRAMES: usize = 32;
+
+/// Largest step from one frame record to the next that is believed; a
+/// larger one means the chain is broken
+const MAX_FRAME_SIZE: usize = 0x10000;
+
+/// Start of the symbol table, for the build to find it by
+pub const SYMBOLS_MAGIC: &[u8; 16] = b"KAAL_SYMBOLS_V1\0";
+
+/// Size of the symbol table, magic included
+pub const SYMBOLS_SIZE: usize = 32 * 1024;
+
+#[cfg(feature = "backtrace-symbols")]
+#[used]
+static SYMBOLS: [u8; SYMBOLS_SIZE] = {
+    let mut table = [0; SYMBOLS_SIZE];
+    let mut i = 0;
+    while i < SYMBOLS_MAGIC.len() {
+        table[i] = SYMBOLS_MAGIC[i];
+        i += 1;
+    }
+    table
+};
+
+/// Print the report of a panic in component `name`
+///
+/// What the panic handler of [`component!`](crate::component!) does
+/// before halting. Prints straight to the debug console, not through the
+/// log server: the panic may be in the middle of logging.
+pub fn report(name: &str, info: &PanicInfo) {
+    let mut console = Console;
+    let _ = write!(console, "[{}] PANIC", name);
+    if let Some(location) = info.location() {
+        let _ = write!(console, " at {}:{}:{}", location.file(), location.line(), location.column());
+    }
+    let _ = writeln!(console, ": {}", info.message());
+
+    let _ = writeln!(console, "backtrace:");
+    for (i, pc) in Frames::here().enumerate() {
+        let _ = write!(console, "  #{:<2} {:#018x}", i, pc);
+        if let Some((symbol, offset)) = symbolize(pc) {
+            let _ = write!(console, "  {}+{:#x}", symbol, offset);
+        }
+        let _ = writeln!(console);
+    }
+}
+
+/// Return addresses on the calling thread's stack, innermost first
+///
+/// Each is the address of the call instruction, so that it falls within
+/// the calling function.
+pub struct Frames {
+    /// The next frame record: the caller's frame pointer, then the return
+    /// address
+    fp: usize,
+    count: usize,
+}
+
+impl Frames {
+    /// Frames from the caller's up
+    #[inline(always)]
+    pub fn here() -> Self {
+        let fp: usize;
+        unsafe { core::arch::asm!("mov {}, x29", out(reg) fp, options(nomem, nostack)) };
+        Self { fp, count: 0 }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.fp == 0 || !self.fp.is_multiple_of(8) || self.count == MAX_FRAMES {
+            return None;
+        }
+        let record = self.fp as *const usize;
+        let (caller_fp, lr) = unsafe { (record.read(), record.add(1).read()) };
+
+        // Threads start with no return address
+        if lr == 0 {
+            return None;
+        }
+        // Callers' frames are further up the stack
+        self.fp = if caller_fp > self.fp && caller_fp - self.fp <= MAX_FRAME_SIZE {
+            caller_fp
+        } else {
+            0
+        };
+        self.count += 1;
+        Some(lr - 4)
+    }
+}
+
+/// The function `pc` is in, and how far into it; None without a symbol
+/// table, or if `pc` comes before every symbol in it
+pub fn symbolize(pc: usize) -> Option<(&'static str, usize)> {
+    // Past the magic, without comparing it: a copy of it in the binary
+    // would be a second place for the build to fill in
+    let table = symbols()?.get(SYMBOLS_MAGIC.len()..)?;
+    let end = table.iter().position(|&b| b == 0).unwrap_or(table.len());
+    let text = core::str::from_utf8(&table[..end]).ok()?;
+
+    let mut found = None;
+    for line in text.lines() {
+        let Some((addr, name)) = line.split_once(' ') else { continue };
+        let Ok(addr) = usize::from_str_radix(addr, 16) else { continue };
+        if addr > pc {
+            break;
+        }
+        found = Some((name, pc - addr));
+    }
+    found
+}
+
+#[cfg(feature = "backtrace-symbols")]
+fn symbols() -> Option<&'static [u8]> {
+    // Filled in after linking: the compiler must not read it as the zeros
+    // it was built with
+    let table = core::hint::black_box(core::ptr::addr_of!(SYMBOLS));
+    Some(unsafe { &*table })
+}
+
+#[cfg(not(feature = "backtrace-symbols"))]
+fn symbols() -> Option<&'static [u8]> {
+    None
+}
+
+/// The debug console, written to directly
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        syscall::print(s);
+        Ok(())
+    }
+}
@@ -1,15 +1,70 @@
 //! Process management
 //!
-//! Utilities for process creation and management (placeholder for future implementation).
+//! Privileged components start processes from ELF images and stop them,
+//! and any component can list the running ones:
+//!
+//! ```no_run
+//! use kaal_sdk::process::{self, SpawnConfig};
+//!
+//! let config = SpawnConfig::new()
+//!     .with_priority(110)
+//!     .with_capabilities(process::CAP_MEMORY | process::CAP_CAPS);
+//! let notepad = process::spawn(NOTEPAD_ELF, &config)?;
+//!
+//! for p in process::list() {
+//!     kaal_sdk::printf!("{:#x}: {} us\n", p.tid, p.cpu_time_us);
+//! }
+//! process::kill(notepad.pid())?;
+//! # Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! Spawning needs the process and memory capabilities (`process:create`
+//! and `memory:map` in system.toml), and capability slots (`caps:allocate`);
+//! killing needs the process capability (`process:destroy`) and slots.
+//! Spawned processes are not supervised: nothing restarts them.
+//!
+//! # Limitations
+//!
+//! A process spawned here gets its capability bits and nothing else. The
+//! components the root task spawns also get a boot block with their
+//! arguments, environment and initial capabilities ([`env`](crate::env)),
+//! a log endpoint and a control notification, and are restarted along with
+//! the producers of the channels they consume; a spawned process has none
+//! of that, and prints straight to the console. [`kill`] destroys a process
+//! outright, without asking it to shut down first.
+//!
+//! To start and stop a component of system.toml with all of that, have the
+//! root task do it with [`boot::start`](crate::boot::start) and
+//! [`boot::stop`](crate::boot::stop) (`boot:control`).
+
+use crate::capability::{allocate_slot, free_slot};
+use crate::component::spawn::spawn_elf;
+use crate::env::{self, InitialCap};
+use crate::{syscall, Error, Result};
 
 /// Process ID type
 pub type Pid = usize;
 
+/// Memory management: allocate, map and unmap memory
+pub const CAP_MEMORY: u64 = 1 << 0;
+/// Process management: create and destroy processes
+pub const CAP_PROCESS: u64 = 1 << 1;
+/// IPC: create notifications and endpoints
+pub const CAP_IPC: u64 = 1 << 2;
+/// Capability management: allocate, insert and delete capabilities
+pub const CAP_CAPS: u64 = 1 << 3;
+/// Kernel log: read it with `syscall::klog_read`
+pub const CAP_KLOG: u64 = 1 << 5;
+
+/// Capability type of a TCB, for `cap_insert_self`
+const CAP_TYPE_TCB: usize = 4;
+
 /// Process handle
 ///
 /// Represents a running process in the system.
 pub struct Process {
     pid: Pid,
+    tcb_cap_slot: usize,
 }
 
 impl Process {
@@ -17,6 +72,265 @@ impl Process {
     pub fn pid(&self) -> Pid {
         self.pid
     }
+
+    /// Slot of the process's TCB capability in our CSpace
+    pub fn tcb_cap_slot(&self) -> usize {
+        self.tcb_cap_slot
+    }
+
+    /// Whether the process is still running, rather than having exited
+    pub fn is_running(&self) -> bool {
+        is_running(self.pid)
+    }
+
+    /// Destroy the process, as [`kill`], and free its capability slot
+    pub fn kill(self) -> Result<()> {
+        // Its TCB is gone once it has exited
+        if !self.is_running() {
+            if syscall::cap_delete(0, self.tcb_cap_slot).is_ok() {
+                free_slot(self.tcb_cap_slot);
+            }
+            return Err(Error::NotFound);
+        }
+        destroy(self.tcb_cap_slot)
+    }
+}
+
+/// How [`spawn`] starts a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnConfig {
+    priority: u8,
+    capabilities: u64,
+    untyped: Option<usize>,
+}
+
+impl SpawnConfig {
+    /// Priority 100, no capabilities, memory from our own UntypedMemory
+    pub const fn new() -> Self {
+        Self {
+            priority: 100,
+            capabilities: 0,
+            untyped: None,
+        }
+    }
+
+    /// Set the scheduling priority (0 = highest)
+    pub const fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set the capability bitmask, `CAP_*` bits
+    pub const fn with_capabilities(mut self, capabilities: u64) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Take the process's memory from the UntypedMemory capability in
+    /// `slot`
+    ///
+    /// By default it comes from the one the root task gave us
+    /// ([`InitialCap::Untyped`]), or, without one, from the kernel's
+    /// frame allocator.
+    pub const fn with_untyped(mut self, slot: usize) -> Self {
+        self.untyped = Some(slot);
+        self
+    }
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Start a process from the ELF image `binary`
+///
+/// Returns a handle holding the process's TCB capability.
+///
+/// # Errors
+/// `InvalidElf` if `binary` is not a loadable ELF image; `SyscallFailed`
+/// if we lack a needed capability, or memory runs out
+pub fn spawn(binary: &[u8], config: &SpawnConfig) -> Result<Process> {
+    let untyped = config.untyped.or_else(|| env::cap_slot(InitialCap::Untyped));
+    let spawned = spawn_elf(binary, config.priority, config.capabilities, untyped)?;
+    Ok(Process {
+        pid: spawned.pid,
+        tcb_cap_slot: spawned.tcb_cap_slot,
+    })
+}
+
+/// Destroy the process `pid`, with all its threads
+///
+/// It is not asked to shut down first; [`boot::stop`](crate::boot::stop)
+/// does that for the root task's components. Killing our own process does
+/// not return.
+///
+/// # Errors
+/// `NotFound` if `pid` is not a running process (see [`list`]);
+/// `SyscallFailed` if we lack the capabilities, or it is the root task
+pub fn kill(pid: Pid) -> Result<()> {
+    // The kernel takes the TCB address on trust: it must name a live one
+    if !is_running(pid) {
+        return Err(Error::NotFound);
+    }
+
+    let slot = allocate_slot()?;
+    if let Err(e) = unsafe { syscall::cap_insert_self(slot, CAP_TYPE_TCB, pid) } {
+        free_slot(slot);
+        return Err(e);
+    }
+    destroy(slot)
+}
+
+/// Iterate over the statistics of the running processes' main threads
+pub fn list() -> impl Iterator<Item = ThreadStats> {
+    threads().filter(ThreadStats::is_process)
+}
+
+fn is_running(pid: Pid) -> bool {
+    list().any(|process| process.tid as usize == pid)
+}
+
+/// Destroy the process whose TCB capability is in `slot`, then delete the
+/// capability
+fn destroy(slot: usize) -> Result<()> {
+    let result = unsafe { syscall::process_destroy(slot) };
+    if syscall::cap_delete(0, slot).is_ok() {
+        free_slot(slot);
+    }
+    result
+}
+
+/// Scheduling state of a thread, as reported by the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Inactive,
+    Running,
+    Runnable,
+    BlockedOnReceive,
+    BlockedOnSend,
+    BlockedOnReply,
+    BlockedOnNotification,
+    BlockedOnFault,
+    Unknown,
+}
+
+impl ThreadState {
+    /// Short name for display
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThreadState::Inactive => "Inactive",
+            ThreadState::Running => "Running",
+            ThreadState::Runnable => "Ready",
+            ThreadState::BlockedOnReceive => "Recv",
+            ThreadState::BlockedOnSend => "Send",
+            ThreadState::BlockedOnReply => "Reply",
+            ThreadState::BlockedOnNotification => "Wait",
+            ThreadState::BlockedOnFault => "Fault",
+            ThreadState::Unknown => "?",
+        }
+    }
+}
+
+/// CPU usage statistics of a thread (`SYS_THREAD_STATS`)
+///
+/// Cycle and instruction counts are 0 on cores without a PMU.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadStats {
+    /// Thread ID (the PID for processes)
+    pub tid: u64,
+    /// Scheduling priority (0 = highest)
+    pub priority: u64,
+    /// Raw state code, see `state()`
+    pub state: u64,
+    /// CPU time in microseconds
+    pub cpu_time_us: u64,
+    /// CPU cycles
+    pub cycles: u64,
+    /// Retired instructions
+    pub instructions: u64,
+    /// Number of times the thread was scheduled
+    pub switches: u64,
+    /// Capability bitmask
+    pub capabilities: u64,
+    /// TID of the thread's process: its own for a main thread
+    pub process: u64,
 }
 
-// TODO: Implement process creation when SYS_PROCESS_CREATE is fully functional
+impl ThreadStats {
+    /// Decode the scheduling state
+    pub fn state(&self) -> ThreadState {
+        match self.state {
+            0 => ThreadState::Inactive,
+            1 => ThreadState::Running,
+            2 => ThreadState::Runnable,
+            3 => ThreadState::BlockedOnReceive,
+            4 => ThreadState::BlockedOnSend,
+            5 => ThreadState::BlockedOnReply,
+            6 => ThreadState::BlockedOnNotification,
+            7 => ThreadState::BlockedOnFault,
+            _ => ThreadState::Unknown,
+        }
+    }
+
+    /// Whether this is a process's main thread, rather than a worker
+    pub fn is_process(&self) -> bool {
+        self.process == self.tid
+    }
+}
+
+/// Iterate over the statistics of all live threads
+///
+/// # Example
+/// ```no_run
+/// for thread in kaal_sdk::process::threads() {
+///     kaal_sdk::printf!("{:#x}: {} us\n", thread.tid, thread.cpu_time_us);
+/// }
+/// ```
+pub fn threads() -> impl Iterator<Item = ThreadStats> {
+    (0..).map_while(|index| crate::syscall::thread_stats(index).ok())
+}
+
+/// CPU idle statistics (`SYS_IDLE_STATS`)
+///
+/// The kernel idles in WFI whenever no thread is runnable.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IdleStats {
+    /// Time since boot in microseconds
+    pub uptime_us: u64,
+    /// Time spent idle in microseconds
+    pub idle_us: u64,
+    /// Number of times the CPU went idle
+    pub idle_entries: u64,
+    /// Number of interrupts that woke the CPU while idle
+    pub wakeups: u64,
+}
+
+impl IdleStats {
+    /// Share of the time since boot spent idle, in percent
+    pub fn idle_percent(&self) -> u64 {
+        (self.idle_us as u128 * 100 / self.uptime_us.max(1) as u128) as u64
+    }
+
+    /// Share of the time between `earlier` and `self` spent idle, in
+    /// percent: the idle share of a recent interval rather than since boot
+    pub fn idle_percent_since(&self, earlier: &IdleStats) -> u64 {
+        let uptime = self.uptime_us.saturating_sub(earlier.uptime_us).max(1);
+        let idle = self.idle_us.saturating_sub(earlier.idle_us);
+        (idle as u128 * 100 / uptime as u128) as u64
+    }
+}
+
+/// Get CPU idle statistics
+///
+/// # Example
+/// ```no_run
+/// let stats = kaal_sdk::process::idle_stats().unwrap();
+/// kaal_sdk::printf!("CPU idle {}%\n", stats.idle_percent());
+/// ```
+pub fn idle_stats() -> crate::Result<IdleStats> {
+    crate::syscall::idle_stats()
+}
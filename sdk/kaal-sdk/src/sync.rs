@@ -0,0 +1,514 @@
+//! Synchronization primitives for a component's threads
+//!
+//! [`Mutex`], [`RwLock`] and [`OnceCell`], shaped like their `std`
+//! counterparts, for state shared between the threads of a component (see
+//! [`thread`](crate::thread)): an IRQ-wait thread and the main loop, say.
+//!
+//! ```no_run
+//! use kaal_sdk::sync::Mutex;
+//!
+//! static RX_BYTES: Mutex<usize> = Mutex::new(0);
+//!
+//! // IRQ thread
+//! *RX_BYTES.lock() += 64;
+//!
+//! // Main loop
+//! kaal_sdk::printf!("received {} bytes\n", *RX_BYTES.lock());
+//! ```
+//!
+//! Taking a free lock is a single atomic operation. A thread that finds it
+//! taken blocks on a notification, which the holder signals on release,
+//! rather than spinning: it uses no CPU while it waits, and the holder gets
+//! to run. Each primitive creates its notification the first time a thread
+//! has to wait, which needs the `caps:allocate` capability; without it,
+//! waiting threads yield until the holder is done.
+//!
+//! Locks are not poisoned: a panic ends the component.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::capability::{Cap, Notification};
+use crate::syscall;
+
+/// Badge a primitive signals its notification with on release
+const WAKE_BADGE: u64 = 1 << 0;
+
+/// Notification capability of a [`WaitQueue`] being created
+const CREATING: usize = usize::MAX;
+
+/// Threads waiting for a primitive to change state
+///
+/// Waiters count themselves in before checking the state a last time, and
+/// a release changes the state before checking for waiters: either the
+/// waiter sees the change, or the release sees the waiter and signals. The
+/// notification keeps a signal nobody waits for yet, so one sent between a
+/// waiter's check and its wait ends the wait.
+struct WaitQueue {
+    /// Notification capability, 0 until a thread first waits
+    notification: AtomicUsize,
+    waiters: AtomicU32,
+}
+
+impl WaitQueue {
+    const fn new() -> Self {
+        Self {
+            notification: AtomicUsize::new(0),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// Block the calling thread until woken, if `blocked` still holds
+    ///
+    /// Can return early: callers check again and come back.
+    fn wait_while(&self, blocked: impl Fn() -> bool) {
+        // Created before counting ourselves in, so any release that sees
+        // us has a notification to signal
+        let Some(notification) = self.notification() else {
+            syscall::yield_now();
+            return;
+        };
+
+        let notification = unsafe { Cap::borrow_raw(notification, Notification) };
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        if blocked() && syscall::wait(&notification).is_err() {
+            syscall::yield_now();
+        }
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Wake the threads waiting, after a release
+    fn wake(&self) {
+        if self.waiters.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let notification = self.notification.load(Ordering::Acquire);
+        if notification != 0 && notification != CREATING {
+            let notification = unsafe { Cap::borrow_raw(notification, Notification) };
+            let _ = syscall::signal(&notification, WAKE_BADGE);
+        }
+    }
+
+    /// The notification, created on first use; None if it cannot be
+    fn notification(&self) -> Option<usize> {
+        loop {
+            match self.notification.load(Ordering::Acquire) {
+                0 => {
+                    if self
+                        .notification
+                        .compare_exchange(0, CREATING, Ordering::Acquire, Ordering::Acquire)
+                        .is_err()
+                    {
+                        continue;
+                    }
+                    // Left at 0 on failure, for a later waiter to try again.
+                    // In a new slot rather than one from `allocate_slot`,
+                    // whose free list this may be the lock of
+                    let created = crate::syscall!(syscall::numbers::SYS_NOTIFICATION_CREATE, 0);
+                    let created = if created == usize::MAX { 0 } else { created };
+                    self.notification.store(created, Ordering::Release);
+                    return (created != 0).then_some(created);
+                }
+                // Another thread is creating it
+                CREATING => syscall::yield_now(),
+                notification => return Some(notification),
+            }
+        }
+    }
+}
+
+/// A mutual exclusion lock protecting a `T`
+pub struct Mutex<T: ?Sized> {
+    locked: AtomicU32,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// A new, unlocked mutex
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicU32::new(0),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Take the protected value out of the mutex
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Lock the mutex, blocking until it is free
+    ///
+    /// Locking it again from the thread holding it deadlocks.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            self.queue.wait_while(|| self.locked.load(Ordering::SeqCst) != 0);
+        }
+    }
+
+    /// Lock the mutex if it is free, without blocking
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// The protected value, without locking: `&mut self` proves no one
+    /// else holds it
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self) {
+        self.locked.store(0, Ordering::SeqCst);
+        self.queue.wake();
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// A locked [`Mutex`]; dropping it unlocks the mutex
+#[must_use = "the mutex unlocks as soon as the guard is dropped"]
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// `RwLock` state while a writer holds it; otherwise it counts readers
+const WRITER: u32 = u32::MAX;
+
+/// A reader-writer lock protecting a `T`
+///
+/// Any number of readers, or one writer. Readers are let in while others
+/// read even if a writer is waiting, so a steady stream of readers can
+/// keep a writer out.
+pub struct RwLock<T: ?Sized> {
+    state: AtomicU32,
+    queue: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// A new, unlocked lock
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            queue: WaitQueue::new(),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Take the protected value out of the lock
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Lock for reading, blocking while a writer holds it
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            self.queue.wait_while(|| self.state.load(Ordering::SeqCst) == WRITER);
+        }
+    }
+
+    /// Lock for reading if no writer holds it, without blocking
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.load(Ordering::Relaxed);
+        // One short of WRITER: that many readers is as many as it counts
+        while state < WRITER - 1 {
+            match self.state.compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(RwLockReadGuard { lock: self }),
+                Err(current) => state = current,
+            }
+        }
+        None
+    }
+
+    /// Lock for writing, blocking while anyone holds it
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            self.queue.wait_while(|| self.state.load(Ordering::SeqCst) != 0);
+        }
+    }
+
+    /// Lock for writing if no one holds it, without blocking
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwLockWriteGuard { lock: self })
+    }
+
+    /// The protected value, without locking: `&mut self` proves no one
+    /// else holds it
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn read_unlock(&self) {
+        // Only the last reader out can let a writer in
+        if self.state.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.queue.wake();
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.state.store(0, Ordering::SeqCst);
+        self.queue.wake();
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+/// An [`RwLock`] locked for reading; dropping it unlocks
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+/// An [`RwLock`] locked for writing; dropping it unlocks
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+/// `OnceCell` states
+const EMPTY: u32 = 0;
+const INITIALIZING: u32 = 1;
+const FULL: u32 = 2;
+
+/// A cell written once, then only read: for values set up at start and
+/// shared between threads
+///
+/// ```no_run
+/// use kaal_sdk::sync::OnceCell;
+///
+/// static DEVICE_BASE: OnceCell<usize> = OnceCell::new();
+///
+/// let base = *DEVICE_BASE.get_or_init(|| 0x0900_0000);
+/// ```
+pub struct OnceCell<T> {
+    state: AtomicU32,
+    queue: WaitQueue,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T> {
+    /// A new, empty cell
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(EMPTY),
+            queue: WaitQueue::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The value, if it is set
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == FULL {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Set the value, if it is not set yet
+    ///
+    /// Blocks while another thread is setting it.
+    ///
+    /// # Errors
+    /// `value`, given back, if the cell was already set
+    pub fn set(&self, value: T) -> core::result::Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// The value, set with `init` first if it is not set yet
+    ///
+    /// Only one thread runs `init`; any others calling meanwhile block
+    /// until it is done.
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        let mut init = Some(init);
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            if self
+                .state
+                .compare_exchange(EMPTY, INITIALIZING, Ordering::Acquire, Ordering::Acquire)
+                .is_ok()
+            {
+                // The only thread to get here: `init` is still ours
+                let value = (init.take().unwrap())();
+                unsafe { (*self.value.get()).write(value) };
+                self.state.store(FULL, Ordering::SeqCst);
+                self.queue.wake();
+            } else {
+                self.queue.wait_while(|| self.state.load(Ordering::SeqCst) == INITIALIZING);
+            }
+        }
+    }
+
+    /// The value, if it is set, without synchronizing: `&mut self` proves
+    /// no one else is using the cell
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if *self.state.get_mut() == FULL {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Take the value out of the cell, if it is set
+    pub fn into_inner(mut self) -> Option<T> {
+        if *self.state.get_mut() != FULL {
+            return None;
+        }
+        // Emptied so that drop does not drop it again
+        *self.state.get_mut() = EMPTY;
+        Some(unsafe { self.value.get_mut().assume_init_read() })
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<unset>)"),
+        }
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == FULL {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
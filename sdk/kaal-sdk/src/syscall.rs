@@ -2,22 +2,30 @@
 //!
 //! Provides safe, ergonomic wrappers around raw KaaL syscalls.
 
+use crate::capability::{allocate_slot, free_slot, Cap, Endpoint, Notification};
 use crate::{Result, Error};
 
 /// Syscall numbers (re-exported for use in other modules)
 pub mod numbers {
     pub const SYS_YIELD: usize = 0x01;
+    pub const SYS_SEND: usize = 0x02;
+    pub const SYS_RECV: usize = 0x03;
+    pub const SYS_CALL: usize = 0x04;
     pub const SYS_CAP_ALLOCATE: usize = 0x10;
     pub const SYS_MEMORY_ALLOCATE: usize = 0x11;
     pub const SYS_DEVICE_REQUEST: usize = 0x12;
     pub const SYS_ENDPOINT_CREATE: usize = 0x13;
     pub const SYS_PROCESS_CREATE: usize = 0x14;
+    /// SYS_PROCESS_CREATE flag: map the image copy-on-write
+    pub const PROCESS_CREATE_COW: u64 = 1 << 0;
     pub const SYS_MEMORY_MAP: usize = 0x15;
     pub const SYS_MEMORY_UNMAP: usize = 0x16;
     pub const SYS_NOTIFICATION_CREATE: usize = 0x17;
     pub const SYS_SIGNAL: usize = 0x18;
     pub const SYS_WAIT: usize = 0x19;
     pub const SYS_POLL: usize = 0x1A;
+    pub const SYS_TCB_BIND_NOTIFICATION: usize = 0x47;
+    pub const SYS_TCB_DONATE_PRIORITY: usize = 0x09;
 
     // Channel management syscalls
     pub const SYS_CHANNEL_ESTABLISH: usize = 0x30;
@@ -27,6 +35,17 @@ pub mod numbers {
     pub const SYS_SHMEM_REGISTER: usize = 0x33;
     pub const SYS_SHMEM_QUERY: usize = 0x34;
     pub const SYS_SHMEM_GET_NOTIFICATION: usize = 0x35;
+    pub const SYS_CNODE_SET_GUARD: usize = 0x36;
+    pub const SYS_TIMER_CREATE: usize = 0x37;
+    pub const SYS_TIMER_SET: usize = 0x38;
+
+    // Guest syscalls (kernels built with the `hypervisor` feature)
+    pub const SYS_VCPU_CREATE: usize = 0x39;
+    pub const SYS_VCPU_MAP: usize = 0x3A;
+    pub const SYS_VCPU_RUN: usize = 0x3B;
+    pub const SYS_VCPU_READ_REG: usize = 0x3C;
+    pub const SYS_VCPU_WRITE_REG: usize = 0x3D;
+    pub const SYS_VCPU_INJECT_IRQ: usize = 0x3E;
 
     // Privileged syscalls for root-task
     pub const SYS_MEMORY_MAP_INTO: usize = 0x1B;
@@ -40,16 +59,33 @@ pub mod numbers {
     pub const SYS_CAP_MOVE: usize = 0x23;
     pub const SYS_MEMORY_REMAP: usize = 0x24;
     pub const SYS_MEMORY_SHARE: usize = 0x25;
+    pub const SYS_MEMORY_UNMAP_FROM: usize = 0x3F;
     pub const SYS_RETYPE: usize = 0x26;
+    pub const SYS_TCB_SET_FAULT_HANDLER: usize = 0x27;
+    pub const SYS_FAULT_RESUME: usize = 0x28;
+    pub const SYS_PROCESS_EXIT: usize = 0x29;
+    pub const SYS_PROCESS_DESTROY: usize = 0x2A;
+    pub const SYS_THREAD_CREATE: usize = 0x48;
+    pub const SYS_THREAD_EXIT: usize = 0x49;
+    pub const SYS_KLOG_READ: usize = 0x2B;
+    pub const SYS_THREAD_STATS: usize = 0x2C;
+    pub const SYS_WATCHDOG_KICK: usize = 0x2D;
+    pub const SYS_TRACE_CTL: usize = 0x2E;
+    pub const SYS_DOMAIN_SET: usize = 0x2F;
+    pub const SYS_IDLE_STATS: usize = 0x46;
 
     // IRQ handling syscalls
     pub const SYS_IRQ_HANDLER_GET: usize = 0x40;
     pub const SYS_IRQ_HANDLER_ACK: usize = 0x41;
+    pub const SYS_IRQ_MSI_GET: usize = 0x42;
+    pub const SYS_IRQ_HANDLER_GET_SHARED: usize = 0x43;
+    pub const SYS_IRQ_HANDLER_CLEAR: usize = 0x44;
 
     // System control syscalls
     pub const SYS_SHUTDOWN: usize = 0x50;
 
     pub const SYS_DEBUG_PRINT: usize = 0x1001;
+    pub const SYS_DEBUG_CAP_IDENTIFY: usize = 0x1002;
 }
 
 /// Print a message to the debug console
@@ -74,7 +110,59 @@ pub fn print(msg: &str) {
     }
 }
 
-/// Print formatted text to the debug console
+/// What a capability slot holds (see `debug_cap_identify`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapIdentity {
+    /// Capability type (0 = empty slot)
+    pub cap_type: u8,
+    /// Rights bits (read = 1, write = 2, grant = 4)
+    pub rights: u8,
+    /// Badge, or the guard word of a CNode capability
+    pub badge: u64,
+    /// Physical address of the kernel object
+    pub object: usize,
+}
+
+/// Identify the capability behind a CPtr, for debugging
+///
+/// Only available in kernels built with the `debug-syscall` feature;
+/// other kernels return an error. An empty slot, or an address that does
+/// not resolve, reads as type 0.
+pub fn debug_cap_identify(cptr: usize) -> Result<CapIdentity> {
+    let result: usize;
+    let rights: usize;
+    let badge: usize;
+    let object: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_DEBUG_CAP_IDENTIFY,
+            inlateout("x0") cptr => result,
+            lateout("x1") rights,
+            lateout("x2") badge,
+            lateout("x3") object,
+            out("x8") _,
+        );
+    }
+
+    if result == usize::MAX {
+        return Err(Error::SyscallFailed);
+    }
+
+    Ok(CapIdentity {
+        cap_type: result as u8,
+        rights: rights as u8,
+        badge: badge as u64,
+        object,
+    })
+}
+
+/// Print formatted text, through the log server if there is one
+///
+/// Logs at `Info`; see [`log`](crate::log) for where the text goes and
+/// [`log!`](crate::log!) for the other levels. [`print`] writes to the
+/// debug console directly.
 ///
 /// # Example
 /// ```no_run
@@ -85,35 +173,125 @@ pub fn print(msg: &str) {
 #[macro_export]
 macro_rules! printf {
     ($fmt:literal) => {
-        $crate::syscall::print($fmt)
+        $crate::log!(Info, $fmt)
     };
-    ($fmt:literal, $($arg:expr),* $(,)?) => {{
-        use core::fmt::Write;
-
-        static mut BUF: [u8; 512] = [0; 512];
-        static mut LEN: usize = 0;
-
-        struct Writer;
-        impl Write for Writer {
-            fn write_str(&mut self, s: &str) -> core::fmt::Result {
-                unsafe {
-                    let bytes = s.as_bytes();
-                    if LEN + bytes.len() > BUF.len() {
-                        return Err(core::fmt::Error);
-                    }
-                    BUF[LEN..LEN + bytes.len()].copy_from_slice(bytes);
-                    LEN += bytes.len();
-                    Ok(())
-                }
-            }
-        }
+    ($fmt:literal, $($arg:expr),* $(,)?) => {
+        $crate::log!(Info, $fmt, $($arg),*)
+    };
+}
 
-        unsafe {
-            LEN = 0;
-            let _ = core::write!(&mut Writer, $fmt, $($arg),*);
-            $crate::syscall::print(core::str::from_utf8_unchecked(&BUF[..LEN]));
-        }
-    }};
+/// Read kernel log messages
+///
+/// Copies kernel log bytes starting at `offset` (bytes logged since boot)
+/// into `buf`. If `offset` has already been overwritten in the kernel's
+/// ring buffer, reading continues from the oldest byte still held; pass
+/// `usize::MAX` to get the current end of the log. Needs the `klog:read`
+/// capability (`process::CAP_KLOG`).
+///
+/// # Returns
+/// `(bytes_copied, next_offset)`
+///
+/// # Example
+/// ```no_run
+/// let mut buf = [0u8; 256];
+/// let (len, next) = kaal_sdk::syscall::klog_read(&mut buf, 0)?;
+/// ```
+pub fn klog_read(buf: &mut [u8], offset: usize) -> Result<(usize, usize)> {
+    let len: usize;
+    let next: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_KLOG_READ,
+            inlateout("x0") buf.as_mut_ptr() as usize => len,
+            inlateout("x1") buf.len() => next,
+            inlateout("x2") offset => _,
+            out("x8") _,
+        );
+    }
+
+    if len == usize::MAX {
+        Err(Error::SyscallFailed)
+    } else {
+        Ok((len, next))
+    }
+}
+
+/// Get CPU usage statistics for a thread
+///
+/// Threads are enumerated by index, counting up from 0 until this returns
+/// an error (see `process::threads`).
+///
+/// # Arguments
+/// * `index` - Position in the kernel's list of live threads
+pub fn thread_stats(index: usize) -> Result<crate::process::ThreadStats> {
+    let mut stats = crate::process::ThreadStats::default();
+    let result = crate::syscall!(
+        numbers::SYS_THREAD_STATS,
+        index,
+        &mut stats as *mut crate::process::ThreadStats as usize,
+        core::mem::size_of::<crate::process::ThreadStats>()
+    );
+
+    if result == 0 {
+        Ok(stats)
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Get CPU idle statistics (see `process::idle_stats`)
+pub fn idle_stats() -> Result<crate::process::IdleStats> {
+    let mut stats = crate::process::IdleStats::default();
+    let result = crate::syscall!(
+        numbers::SYS_IDLE_STATS,
+        &mut stats as *mut crate::process::IdleStats as usize,
+        core::mem::size_of::<crate::process::IdleStats>()
+    );
+
+    if result == 0 {
+        Ok(stats)
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Arm or kick the kernel's hang detection watchdog
+///
+/// The first call arms the watchdog; from then on it must be called again
+/// within the timeout, or the kernel prints a thread dump and resets the
+/// system. Requires CAP_PROCESS.
+///
+/// # Arguments
+/// * `timeout_ms` - New timeout in milliseconds (at least 100), or 0 to
+///   keep the current one (5 s on the first call)
+pub fn watchdog_kick(timeout_ms: u64) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_WATCHDOG_KICK, timeout_ms as usize);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Move a thread to another scheduling domain
+///
+/// Threads only run during their domain's windows of the kernel's domain
+/// schedule, which isolates components in time. Requires CAP_DOMAIN.
+///
+/// # Arguments
+/// * `tcb_cap` - TCB capability slot of the thread
+/// * `domain` - Domain number (0-15)
+pub fn domain_set(tcb_cap: usize, domain: u8) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_DOMAIN_SET, tcb_cap, domain as usize);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
 }
 
 /// Yield the current thread to the scheduler
@@ -241,14 +419,15 @@ pub fn cap_derive(cnode_cap: usize, src_slot: usize, dest_slot: usize, new_right
     }
 }
 
-/// Mint a badged endpoint capability
+/// Mint a badged endpoint or notification capability
 ///
-/// Creates a badged child capability for IPC endpoint identification.
-/// The badge allows the receiver to identify which endpoint was used in IPC.
+/// Creates a badged child capability so a server can tell its clients
+/// apart: the badge is returned (in x1) with every message received through
+/// the endpoint, or is the bit pattern a signal through the notification sets.
 ///
 /// # Arguments
 /// * `cnode_cap` - Capability slot containing the CNode capability
-/// * `src_slot` - Source endpoint capability slot
+/// * `src_slot` - Source endpoint or notification capability slot (unbadged)
 /// * `dest_slot` - Destination slot (must be empty)
 /// * `badge` - Badge value (non-zero, identifies the sender)
 ///
@@ -257,7 +436,8 @@ pub fn cap_derive(cnode_cap: usize, src_slot: usize, dest_slot: usize, new_right
 ///
 /// # Errors
 /// * Permission denied if caller lacks CAP_CAPS capability
-/// * Invalid capability if cnode_cap is not a valid CNode or src is not an endpoint
+/// * Invalid capability if cnode_cap is not a valid CNode, or src is not an
+///   unbadged endpoint or notification
 /// * Insufficient rights if CNode doesn't have WRITE rights
 /// * Slot occupied if destination slot is not empty
 ///
@@ -270,7 +450,8 @@ pub fn cap_derive(cnode_cap: usize, src_slot: usize, dest_slot: usize, new_right
 /// ```
 ///
 /// # Security
-/// Requires CAP_CAPS permission and WRITE rights on CNode. Source must be an endpoint.
+/// Requires CAP_CAPS permission and WRITE rights on CNode. Source must be an
+/// unbadged endpoint or notification.
 pub fn cap_mint(cnode_cap: usize, src_slot: usize, dest_slot: usize, badge: usize) -> Result<()> {
     unsafe {
         let result: usize;
@@ -403,6 +584,44 @@ pub fn cap_move(src_cnode_cap: usize, src_slot: usize, dest_cnode_cap: usize, de
     }
 }
 
+/// Bit position of the lookup depth in a capability address
+pub const CPTR_DEPTH_SHIFT: u32 = 56;
+
+/// Build a capability address for a multi-level CSpace
+///
+/// Capability arguments of every syscall may address a slot anywhere in
+/// a tree of CNodes: the kernel consumes `depth` bits of `address` from
+/// the most significant end, taking the root CNode's index bits, then for
+/// each CNode capability on the way its guard bits and the next CNode's
+/// index bits. Plain slot numbers (depth 0) index the CSpace root.
+pub const fn cptr(address: usize, depth: u8) -> usize {
+    ((depth as usize) << CPTR_DEPTH_SHIFT) | (address & ((1 << CPTR_DEPTH_SHIFT) - 1))
+}
+
+/// Set the guard of a CNode capability
+///
+/// During a lookup through the CNode capability, the next `guard_bits`
+/// bits of the address must equal `guard`. Guards let a small CNode cover
+/// a large part of the address space without intermediate levels.
+///
+/// # Arguments
+/// * `cnode_cap` - CNode holding the capability (0 = caller's CSpace)
+/// * `slot` - Slot of the CNode capability
+/// * `guard` - Guard value
+/// * `guard_bits` - Guard size in bits (at most 56)
+///
+/// # Security
+/// Requires CAP_CAPS permission and WRITE rights on the holding CNode.
+pub fn cnode_set_guard(cnode_cap: usize, slot: usize, guard: u64, guard_bits: u8) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_CNODE_SET_GUARD, cnode_cap, slot, guard, guard_bits);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
 /// Allocate physical memory
 ///
 /// # Arguments
@@ -482,7 +701,10 @@ pub fn sys_retype(
 /// # Arguments
 /// * `phys_addr` - Physical address to map
 /// * `size` - Size in bytes
-/// * `permissions` - Memory permissions (read=0x1, write=0x2, exec=0x4)
+/// * `permissions` - Memory permissions (read=0x1, write=0x2, exec=0x4), plus
+///   the memory type in bits 4-5 (0x10 = device, 0x20 = non-cacheable,
+///   0x30 = write-combining, default normal cacheable) and the page size in
+///   bits 8-9 (0x100 = 2MB, 0x200 = 1GB, default 4KB)
 ///
 /// # Returns
 /// Virtual address of mapped memory on success.
@@ -681,7 +903,7 @@ pub fn device_request(device_id: usize) -> Result<usize> {
 /// which combines notifications with shared memory ring buffers.
 ///
 /// # Returns
-/// Notification capability slot on success.
+/// The notification capability, in a slot from [`allocate_slot`].
 ///
 /// # Use Cases
 /// - Event signaling (e.g., "data ready", "work complete")
@@ -692,41 +914,48 @@ pub fn device_request(device_id: usize) -> Result<usize> {
 /// ```no_run
 /// let notification = kaal_sdk::syscall::notification_create()?;
 /// // Later: signal it
-/// kaal_sdk::syscall::signal(notification, 0x1)?;
+/// kaal_sdk::syscall::signal(&notification, 0x1)?;
 /// ```
-pub fn notification_create() -> Result<usize> {
+pub fn notification_create() -> Result<Cap<Notification>> {
+    let slot = allocate_slot()?;
+    let result: usize;
     unsafe {
-        let result: usize;
         core::arch::asm!(
             "mov x8, {syscall_num}",
             "svc #0",
-            "mov {result}, x0",
             syscall_num = in(reg) numbers::SYS_NOTIFICATION_CREATE,
-            result = out(reg) result,
+            inlateout("x0") slot => result,
             out("x8") _,
         );
-        Error::from_syscall(result)
+    }
+    match Error::from_syscall(result) {
+        Ok(slot) => Ok(unsafe { Cap::from_raw(slot, Notification) }),
+        Err(e) => {
+            free_slot(slot);
+            Err(e)
+        }
     }
 }
 
 /// Signal a notification (non-blocking)
 ///
 /// # Arguments
-/// * `notification` - Notification capability slot
-/// * `badge` - Signal badge to OR into notification
+/// * `notification` - Notification capability
+/// * `badge` - Signal badge to OR into notification (ignored if the
+///   notification capability is badged: its badge is signalled instead)
 ///
 /// # Example
 /// ```no_run
-/// kaal_sdk::syscall::signal(notification, 0x1)?;
+/// kaal_sdk::syscall::signal(&notification, 0x1)?;
 /// ```
-pub fn signal(notification: usize, badge: u64) -> Result<()> {
+pub fn signal(notification: &Cap<Notification>, badge: u64) -> Result<()> {
     unsafe {
         let result: usize;
         core::arch::asm!(
             "mov x8, {syscall_num}",
             "svc #0",
             syscall_num = in(reg) numbers::SYS_SIGNAL,
-            inlateout("x0") notification => result,
+            inlateout("x0") notification.slot() => result,
             inlateout("x1") badge => _,
             lateout("x8") _,
         );
@@ -740,50 +969,76 @@ pub fn signal(notification: usize, badge: u64) -> Result<()> {
 /// Blocks until the notification is signaled, then returns the signal bits.
 ///
 /// # Arguments
-/// * `notification` - Notification capability slot
+/// * `notification` - Notification capability
 ///
 /// # Returns
 /// Signal bits on success.
 ///
 /// # Example
 /// ```no_run
-/// let signals = kaal_sdk::syscall::wait(notification)?;
+/// let signals = kaal_sdk::syscall::wait(&notification)?;
 /// if signals & 0x1 != 0 {
 ///     // Handle signal 1
 /// }
 /// ```
-pub fn wait(notification: usize) -> Result<u64> {
+pub fn wait(notification: &Cap<Notification>) -> Result<u64> {
     unsafe {
         let result: usize;
         core::arch::asm!(
             "mov x8, {syscall_num}",
             "svc #0",
             syscall_num = in(reg) numbers::SYS_WAIT,
-            inlateout("x0") notification => result,
+            inlateout("x0") notification.slot() => result,
             lateout("x8") _,
         );
         Error::from_syscall(result).map(|v| v as u64)
     }
 }
 
+/// Lend the caller's priority to a thread until it signals a notification
+///
+/// Priority inheritance for notification waits: call it right before
+/// waiting on `notification`, with the TCB capability of the thread that
+/// signals it. The kernel never lowers that thread's priority, and gives
+/// it back its own once it signals `notification`.
+///
+/// # Arguments
+/// * `tcb` - TCB capability slot of the signalling thread
+/// * `notification` - Notification capability about to be waited on
+pub fn tcb_donate_priority(tcb: usize, notification: &Cap<Notification>) -> Result<()> {
+    unsafe {
+        let result: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_TCB_DONATE_PRIORITY,
+            inlateout("x0") tcb => result,
+            inlateout("x1") notification.slot() => _,
+            lateout("x8") _,
+        );
+        Error::from_syscall(result)?;
+        Ok(())
+    }
+}
+
 /// Poll notification (non-blocking)
 ///
 /// Returns immediately with signal bits, or 0 if no signals pending.
 ///
 /// # Arguments
-/// * `notification` - Notification capability slot
+/// * `notification` - Notification capability
 ///
 /// # Returns
 /// Signal bits (0 if none pending).
 ///
 /// # Example
 /// ```no_run
-/// let signals = kaal_sdk::syscall::poll(notification)?;
+/// let signals = kaal_sdk::syscall::poll(&notification)?;
 /// if signals != 0 {
 ///     // Process signals
 /// }
 /// ```
-pub fn poll(notification: usize) -> Result<u64> {
+pub fn poll(notification: &Cap<Notification>) -> Result<u64> {
     unsafe {
         let result: usize;
         core::arch::asm!(
@@ -792,7 +1047,7 @@ pub fn poll(notification: usize) -> Result<u64> {
             "svc #0",
             "mov {result}, x0",
             syscall_num = in(reg) numbers::SYS_POLL,
-            cap = in(reg) notification,
+            cap = in(reg) notification.slot(),
             result = out(reg) result,
             out("x8") _,
         );
@@ -801,21 +1056,344 @@ pub fn poll(notification: usize) -> Result<u64> {
     }
 }
 
+/// Bind a notification to the calling thread
+///
+/// A signal on it then ends a [`recv`] the thread is blocked in, which
+/// returns 0 bytes with the signal bits as the badge, so a server can wait
+/// for requests and signals at once, a timer's for instance. Signals already
+/// pending end the next receive at once. Binding replaces the thread's
+/// earlier binding; [`tcb_unbind_notification`] undoes it.
+///
+/// # Errors
+/// [`Error::SyscallFailed`] if `notification` is bound to another thread
+pub fn tcb_bind_notification(notification: &Cap<Notification>) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_TCB_BIND_NOTIFICATION, notification.slot());
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Unbind the calling thread's notification (see [`tcb_bind_notification`])
+pub fn tcb_unbind_notification() -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_TCB_BIND_NOTIFICATION, usize::MAX);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Create a timer bound to a notification
+///
+/// # Arguments
+/// * `notification` - Notification capability to signal on expiry
+/// * `badge` - Bits to signal (non-zero); a badged notification capability
+///   signals its own badge instead
+///
+/// # Returns
+/// Timer capability slot on success.
+pub fn timer_create(notification: &Cap<Notification>, badge: u64) -> Result<usize> {
+    let result = crate::syscall!(numbers::SYS_TIMER_CREATE, notification.slot(), badge);
+    Error::from_syscall(result)
+}
+
+/// Arm a timer
+///
+/// The timer signals its notification after `timeout_us` microseconds
+/// (after `period_us` if that is 0), then every `period_us` unless it is 0.
+/// Setting a timer again replaces its pending timeout; both 0 cancel it.
+/// Timeouts are rounded up to the kernel's scheduler tick.
+///
+/// # Example
+/// ```no_run
+/// use kaal_sdk::syscall;
+///
+/// let notification = syscall::notification_create()?;
+/// let timer = syscall::timer_create(&notification, 0x1)?;
+/// syscall::timer_set(timer, 0, 1_000_000)?; // once a second
+/// loop {
+///     syscall::wait(&notification)?;
+/// }
+/// ```
+pub fn timer_set(timer: usize, timeout_us: u64, period_us: u64) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_TIMER_SET, timer, timeout_us, period_us);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Guest register numbers for `vcpu_read_reg` / `vcpu_write_reg`
+///
+/// x0-x30 are registers 0-30.
+pub mod vcpu_reg {
+    pub const PC: usize = 31;
+    pub const PSTATE: usize = 32;
+    /// MPIDR_EL1 as the guest reads it
+    pub const MPIDR: usize = 33;
+    pub const SCTLR_EL1: usize = 34;
+    pub const TTBR0_EL1: usize = 35;
+    pub const TTBR1_EL1: usize = 36;
+    pub const TCR_EL1: usize = 37;
+    pub const MAIR_EL1: usize = 38;
+    pub const VBAR_EL1: usize = 40;
+    pub const SP_EL0: usize = 45;
+    pub const SP_EL1: usize = 46;
+    pub const ELR_EL1: usize = 47;
+    pub const SPSR_EL1: usize = 48;
+    pub const ESR_EL1: usize = 49;
+    pub const FAR_EL1: usize = 50;
+}
+
+/// Why `vcpu_run` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcpuExit {
+    /// A host interrupt arrived (the scheduler tick or a device); run again
+    Interrupt,
+    /// The guest trapped on HVC, SMC, WFI or a stage-2 fault. `esr` is ESR_EL2; for aborts `ipa` is the faulting
+    /// guest physical address.
+    Trap { esr: u64, far: u64, ipa: u64 },
+    /// The guest caused an SError
+    SError,
+}
+
+/// Create a VCPU with an empty guest physical address space
+///
+/// Needs CAP_CAPS and a kernel built with the `hypervisor` feature and
+/// entered at EL2; other kernels return an error. The guest starts in EL1h
+/// with interrupts masked and its MMU off.
+///
+/// # Returns
+/// VCPU capability slot on success.
+pub fn vcpu_create() -> Result<usize> {
+    let result = crate::syscall!(numbers::SYS_VCPU_CREATE);
+    Error::from_syscall(result)
+}
+
+/// Map physical memory into a guest at guest physical address `ipa`
+///
+/// `permissions` takes the same bits as `memory_map` (read=1, write=2,
+/// exec=4, plus a page size). Needs CAP_MEMORY.
+pub fn vcpu_map(vcpu: usize, phys_addr: usize, size: usize, ipa: usize, permissions: usize) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_VCPU_MAP, vcpu, phys_addr, size, ipa, permissions);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Run a guest until it exits
+///
+/// # Example
+/// ```no_run
+/// use kaal_sdk::syscall::{self, vcpu_reg, VcpuExit};
+///
+/// let vcpu = syscall::vcpu_create()?;
+/// syscall::vcpu_map(vcpu, guest_ram, 0x200000, 0x4000_0000, 0x7)?;
+/// syscall::vcpu_write_reg(vcpu, vcpu_reg::PC, 0x4000_0000)?;
+/// loop {
+///     match syscall::vcpu_run(vcpu)? {
+///         VcpuExit::Interrupt => continue,
+///         VcpuExit::Trap { esr, .. } => handle_trap(vcpu, esr)?,
+///         VcpuExit::SError => break,
+///     }
+/// }
+/// ```
+pub fn vcpu_run(vcpu: usize) -> Result<VcpuExit> {
+    let result: usize;
+    let esr: usize;
+    let far: usize;
+    let ipa: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_VCPU_RUN,
+            inlateout("x0") vcpu => result,
+            lateout("x1") esr,
+            lateout("x2") far,
+            lateout("x3") ipa,
+            out("x8") _,
+        );
+    }
+
+    match result {
+        0 => Ok(VcpuExit::Interrupt),
+        1 => Ok(VcpuExit::Trap { esr: esr as u64, far: far as u64, ipa: ipa as u64 }),
+        2 => Ok(VcpuExit::SError),
+        _ => Err(Error::SyscallFailed),
+    }
+}
+
+/// Read a guest register (x0-x30, or a `vcpu_reg` constant)
+pub fn vcpu_read_reg(vcpu: usize, reg: usize) -> Result<u64> {
+    let result: usize;
+    let value: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_VCPU_READ_REG,
+            inlateout("x0") vcpu => result,
+            inlateout("x1") reg => value,
+            out("x8") _,
+        );
+    }
+
+    if result == 0 {
+        Ok(value as u64)
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Write a guest register (x0-x30, or a `vcpu_reg` constant)
+pub fn vcpu_write_reg(vcpu: usize, reg: usize, value: u64) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_VCPU_WRITE_REG, vcpu, reg, value);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
+/// Raise (`true`) or lower a guest's virtual IRQ line
+///
+/// The line stays raised until lowered; the guest takes the interrupt the
+/// next time it runs with IRQs unmasked.
+pub fn vcpu_inject_irq(vcpu: usize, level: bool) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_VCPU_INJECT_IRQ, vcpu, level as usize);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(Error::SyscallFailed)
+    }
+}
+
 /// Create an IPC endpoint
 ///
 /// # Returns
-/// Endpoint capability slot on success.
-pub fn endpoint_create() -> Result<usize> {
+/// The endpoint capability, in a slot from [`allocate_slot`].
+pub fn endpoint_create() -> Result<Cap<Endpoint>> {
+    let slot = allocate_slot()?;
+    let result: usize;
     unsafe {
-        let result: usize;
         core::arch::asm!(
             "mov x8, {syscall_num}",
             "svc #0",
-            "mov {result}, x0",
             syscall_num = in(reg) numbers::SYS_ENDPOINT_CREATE,
-            result = out(reg) result,
+            inlateout("x0") slot => result,
             out("x8") _,
         );
+    }
+    match Error::from_syscall(result) {
+        Ok(slot) => Ok(unsafe { Cap::from_raw(slot, Endpoint) }),
+        Err(e) => {
+            free_slot(slot);
+            Err(e)
+        }
+    }
+}
+
+/// Largest message [`send`] carries (bytes)
+pub const MAX_MESSAGE: usize = 256;
+
+/// Send a message on an endpoint (blocking)
+///
+/// Blocks until a receiver takes the message.
+///
+/// # Arguments
+/// * `endpoint` - Endpoint capability
+/// * `message` - At most [`MAX_MESSAGE`] bytes
+pub fn send(endpoint: &Cap<Endpoint>, message: &[u8]) -> Result<()> {
+    if message.len() > MAX_MESSAGE {
+        return Err(Error::InvalidParameter);
+    }
+    unsafe {
+        let result: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_SEND,
+            inlateout("x0") endpoint.slot() => result,
+            inlateout("x1") message.as_ptr() as usize => _,
+            inlateout("x2") message.len() => _,
+            lateout("x8") _,
+        );
+        Error::from_syscall(result)?;
+        Ok(())
+    }
+}
+
+/// Receive a message on an endpoint (blocking)
+///
+/// Blocks until a sender arrives.
+///
+/// # Returns
+/// `(bytes_received, badge)`, the badge of the sender's endpoint capability
+/// (0 if unbadged)
+///
+/// # Example
+/// ```no_run
+/// let mut buf = [0u8; kaal_sdk::syscall::MAX_MESSAGE];
+/// let (len, badge) = kaal_sdk::syscall::recv(&endpoint, &mut buf)?;
+/// ```
+pub fn recv(endpoint: &Cap<Endpoint>, buf: &mut [u8]) -> Result<(usize, u64)> {
+    unsafe {
+        let result: usize;
+        let badge: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_RECV,
+            inlateout("x0") endpoint.slot() => result,
+            inlateout("x1") buf.as_mut_ptr() as usize => badge,
+            inlateout("x2") buf.len() => _,
+            lateout("x8") _,
+        );
+        Error::from_syscall(result).map(|len| (len, badge as u64))
+    }
+}
+
+/// Send a request on an endpoint and wait for the reply (blocking)
+///
+/// The receiver answers with a reply of its own instead of the plain
+/// acknowledgement [`send`] gets.
+///
+/// # Arguments
+/// * `endpoint` - Endpoint capability
+/// * `request` - At most [`MAX_MESSAGE`] bytes
+/// * `reply` - Buffer for the reply
+///
+/// # Returns
+/// Bytes of reply received
+pub fn call(endpoint: &Cap<Endpoint>, request: &[u8], reply: &mut [u8]) -> Result<usize> {
+    if request.len() > MAX_MESSAGE {
+        return Err(Error::InvalidParameter);
+    }
+    unsafe {
+        let result: usize;
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_CALL,
+            inlateout("x0") endpoint.slot() => result,
+            inlateout("x1") request.as_ptr() as usize => _,
+            inlateout("x2") request.len() => _,
+            inlateout("x3") reply.as_mut_ptr() as usize => _,
+            inlateout("x4") reply.len() => _,
+            lateout("x8") _,
+        );
         Error::from_syscall(result)
     }
 }
@@ -893,12 +1471,13 @@ macro_rules! syscall {
     // 0 arguments
     ($num:expr) => {{
         let result: usize;
+        let num: usize = $num;
         unsafe {
             core::arch::asm!(
                 "mov x8, {syscall_num}",
                 "svc #0",
                 "mov {result}, x0",
-                syscall_num = in(reg) $num,
+                syscall_num = in(reg) num,
                 result = out(reg) result,
                 out("x8") _,
             );
@@ -908,19 +1487,23 @@ macro_rules! syscall {
 
     // 1 argument
     ($num:expr, $arg0:expr) => {{
-        unsafe { $crate::syscall::raw_syscall_1arg($num, $arg0 as usize) }
+        let (num, arg0) = ($num, $arg0 as usize);
+        unsafe { $crate::syscall::raw_syscall_1arg(num, arg0) }
     }};
 
     // 2 arguments
     ($num:expr, $arg0:expr, $arg1:expr) => {{
         let result: usize;
+        let num: usize = $num;
+        let arg0 = $arg0 as usize;
+        let arg1 = $arg1 as usize;
         unsafe {
             core::arch::asm!(
                 "mov x8, {syscall_num}",
                 "svc #0",
-                syscall_num = in(reg) $num,
-                inlateout("x0") $arg0 as usize => result,
-                inlateout("x1") $arg1 as usize => _,
+                syscall_num = in(reg) num,
+                inlateout("x0") arg0 => result,
+                inlateout("x1") arg1 => _,
                 lateout("x8") _,
             );
             result
@@ -929,21 +1512,27 @@ macro_rules! syscall {
 
     // 3 arguments
     ($num:expr, $arg0:expr, $arg1:expr, $arg2:expr) => {{
-        unsafe { $crate::syscall::raw_syscall_3args($num, $arg0 as usize, $arg1 as usize, $arg2 as usize) }
+        let (num, arg0, arg1, arg2) = ($num, $arg0 as usize, $arg1 as usize, $arg2 as usize);
+        unsafe { $crate::syscall::raw_syscall_3args(num, arg0, arg1, arg2) }
     }};
 
     // 4 arguments
     ($num:expr, $arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr) => {{
         let result: usize;
+        let num: usize = $num;
+        let arg0 = $arg0 as usize;
+        let arg1 = $arg1 as usize;
+        let arg2 = $arg2 as usize;
+        let arg3 = $arg3 as usize;
         unsafe {
             core::arch::asm!(
                 "mov x8, {num}",
                 "svc #0",
-                num = in(reg) $num,
-                inlateout("x0") $arg0 as usize => result,
-                inlateout("x1") $arg1 as usize => _,
-                inlateout("x2") $arg2 as usize => _,
-                inlateout("x3") $arg3 as usize => _,
+                num = in(reg) num,
+                inlateout("x0") arg0 => result,
+                inlateout("x1") arg1 => _,
+                inlateout("x2") arg2 => _,
+                inlateout("x3") arg3 => _,
                 lateout("x8") _,
             );
             result
@@ -953,41 +1542,87 @@ macro_rules! syscall {
     // 5 arguments
     ($num:expr, $arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr) => {{
         let result: usize;
+        let num: usize = $num;
+        let arg0 = $arg0 as usize;
+        let arg1 = $arg1 as usize;
+        let arg2 = $arg2 as usize;
+        let arg3 = $arg3 as usize;
+        let arg4 = $arg4 as usize;
+        unsafe {
+            core::arch::asm!(
+                "mov x8, {num}",
+                "svc #0",
+                num = in(reg) num,
+                inlateout("x0") arg0 => result,
+                inlateout("x1") arg1 => _,
+                inlateout("x2") arg2 => _,
+                inlateout("x3") arg3 => _,
+                inlateout("x4") arg4 => _,
+                lateout("x8") _,
+            );
+            result
+        }
+    }};
+
+    // 6 arguments
+    ($num:expr, $arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr) => {{
+        let result: usize;
+        let num: usize = $num;
+        let arg0 = $arg0 as usize;
+        let arg1 = $arg1 as usize;
+        let arg2 = $arg2 as usize;
+        let arg3 = $arg3 as usize;
+        let arg4 = $arg4 as usize;
+        let arg5 = $arg5 as usize;
         unsafe {
             core::arch::asm!(
                 "mov x8, {num}",
                 "svc #0",
-                num = in(reg) $num,
-                inlateout("x0") $arg0 as usize => result,
-                inlateout("x1") $arg1 as usize => _,
-                inlateout("x2") $arg2 as usize => _,
-                inlateout("x3") $arg3 as usize => _,
-                inlateout("x4") $arg4 as usize => _,
+                num = in(reg) num,
+                inlateout("x0") arg0 => result,
+                inlateout("x1") arg1 => _,
+                inlateout("x2") arg2 => _,
+                inlateout("x3") arg3 => _,
+                inlateout("x4") arg4 => _,
+                inlateout("x5") arg5 => _,
                 lateout("x8") _,
             );
             result
         }
     }};
 
-    // 10 arguments (8 in x0-x7, priority in x9, capabilities in x10)
+    // 11 arguments (8 in x0-x7, priority in x9, capabilities in x10, flags in x11)
     // Special case for SYS_PROCESS_CREATE
-    ($num:expr, $arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr, $priority:expr, $capabilities:expr) => {{
+    ($num:expr, $arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr, $priority:expr, $capabilities:expr, $flags:expr) => {{
         let result: usize;
+        let num: usize = $num;
+        let arg0 = $arg0 as usize;
+        let arg1 = $arg1 as usize;
+        let arg2 = $arg2 as usize;
+        let arg3 = $arg3 as usize;
+        let arg4 = $arg4 as usize;
+        let arg5 = $arg5 as usize;
+        let arg6 = $arg6 as usize;
+        let arg7 = $arg7 as usize;
+        let priority = $priority as usize;
+        let capabilities = $capabilities as usize;
+        let flags = $flags as usize;
         unsafe {
             core::arch::asm!(
                 "mov x8, {num}",
                 "svc #0",
-                num = in(reg) $num,
-                inlateout("x0") $arg0 as usize => result,
-                inlateout("x1") $arg1 as usize => _,
-                inlateout("x2") $arg2 as usize => _,
-                inlateout("x3") $arg3 as usize => _,
-                inlateout("x4") $arg4 as usize => _,
-                inlateout("x5") $arg5 as usize => _,
-                inlateout("x6") $arg6 as usize => _,
-                inlateout("x7") $arg7 as usize => _,
-                inlateout("x9") $priority as usize => _,
-                inlateout("x10") $capabilities as usize => _,
+                num = in(reg) num,
+                inlateout("x0") arg0 => result,
+                inlateout("x1") arg1 => _,
+                inlateout("x2") arg2 => _,
+                inlateout("x3") arg3 => _,
+                inlateout("x4") arg4 => _,
+                inlateout("x5") arg5 => _,
+                inlateout("x6") arg6 => _,
+                inlateout("x7") arg7 => _,
+                inlateout("x9") priority => _,
+                inlateout("x10") capabilities => _,
+                inlateout("x11") flags => _,
                 lateout("x8") _,
             );
             result
@@ -998,14 +1633,24 @@ macro_rules! syscall {
 /// Register shared memory with the kernel registry
 ///
 /// Allows producer to publish physical address for consumers to discover
-pub unsafe fn shmem_register(channel_name: &str, phys_addr: usize, size: usize, notification_cap: usize) -> crate::Result<()> {
+///
+/// # Safety
+///
+/// Unsafe because any component can then map the `size` bytes at
+/// `phys_addr`, which must be memory we allocated to share
+pub unsafe fn shmem_register(
+    channel_name: &str,
+    phys_addr: usize,
+    size: usize,
+    notification: &Cap<Notification>,
+) -> crate::Result<()> {
     let result = crate::syscall!(
         numbers::SYS_SHMEM_REGISTER,
         channel_name.as_ptr(),
         channel_name.len(),
         phys_addr,
         size,
-        notification_cap
+        notification.slot()
     );
 
     if result == usize::MAX {
@@ -1018,6 +1663,11 @@ pub unsafe fn shmem_register(channel_name: &str, phys_addr: usize, size: usize,
 /// Query shared memory from the kernel registry
 ///
 /// Allows consumer to discover physical address published by producer
+///
+/// # Safety
+///
+/// Unsafe because the memory is the producer's, which may write it at any
+/// time while we read it
 pub unsafe fn shmem_query(channel_name: &str) -> crate::Result<usize> {
     let phys_addr = crate::syscall!(
         numbers::SYS_SHMEM_QUERY,
@@ -1034,19 +1684,27 @@ pub unsafe fn shmem_query(channel_name: &str) -> crate::Result<usize> {
 
 /// Get notification capability for a shared memory channel
 ///
-/// Allows consumer to get a capability to the producer's notification for signaling
-pub unsafe fn shmem_get_notification(channel_name: &str, dest_cap_slot: usize) -> crate::Result<()> {
+/// Allows consumer to get a capability to the producer's notification for
+/// signaling, in a slot from [`allocate_slot`]
+///
+/// # Safety
+///
+/// Unsafe because the notification is the producer's, which may revoke it
+/// while we still hold it
+pub unsafe fn shmem_get_notification(channel_name: &str) -> crate::Result<Cap<Notification>> {
+    let slot = allocate_slot()?;
     let result = crate::syscall!(
         numbers::SYS_SHMEM_GET_NOTIFICATION,
         channel_name.as_ptr(),
         channel_name.len(),
-        dest_cap_slot
+        slot
     );
 
     if result == usize::MAX {
+        free_slot(slot);
         Err(crate::Error::SyscallFailed)
     } else {
-        Ok(())
+        Ok(Cap::from_raw(slot, Notification))
     }
 }
 
@@ -1061,7 +1719,9 @@ pub unsafe fn shmem_get_notification(channel_name: &str, dest_cap_slot: usize) -
 /// * `phys_addr` - Physical address to map
 /// * `size` - Size in bytes (must be page-aligned)
 /// * `virt_addr` - Virtual address in target's address space
-/// * `permissions` - Permission flags (0x3 = read-write)
+/// * `permissions` - Permission flags (0x3 = read-write), plus the memory
+///   type in bits 4-5 (as for [`memory_map`]) and the page size in bits 8-9
+///   (0x100 = 2MB, 0x200 = 1GB, default 4KB)
 ///
 /// # Safety
 ///
@@ -1089,6 +1749,35 @@ pub unsafe fn memory_map_into(
     }
 }
 
+/// Unmap memory from another component's address space (privileged)
+///
+/// Undoes [`memory_map_into`].
+///
+/// # Arguments
+///
+/// * `target_tcb_cap` - TCB capability of target component
+/// * `virt_addr` - Virtual address in target's address space
+/// * `size` - Size in bytes
+///
+/// # Safety
+///
+/// Unsafe because it modifies another component's address space; the
+/// component faults if it still uses the memory
+pub unsafe fn memory_unmap_from(target_tcb_cap: usize, virt_addr: usize, size: usize) -> crate::Result<()> {
+    let result = crate::syscall!(
+        numbers::SYS_MEMORY_UNMAP_FROM,
+        target_tcb_cap,
+        virt_addr,
+        size
+    );
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
 /// Insert a capability into another component's CSpace (privileged)
 ///
 /// This is a privileged syscall only available to the root-task for
@@ -1125,6 +1814,134 @@ pub unsafe fn cap_insert_into(
     }
 }
 
+/// Page fault report received by a pager
+///
+/// Delivered on the fault endpoint registered with `tcb_set_fault_handler`
+/// as little-endian u64 words: the first five fields, then the registers.
+/// A receive buffer shorter than [`SIZE`](Self::SIZE) gets the first five
+/// and the registers that fit; those that did not read as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultMessage {
+    /// TID of the faulting thread
+    pub tid: usize,
+    /// `FAULT_READ`, `FAULT_WRITE`, `FAULT_EXECUTE`, `FAULT_CRASH` or
+    /// `FAULT_EXIT`
+    pub kind: usize,
+    /// Faulting virtual address, or the exit code for `FAULT_EXIT`
+    pub addr: usize,
+    /// Faulting instruction
+    pub pc: usize,
+    /// Raw ESR_EL1 syndrome
+    pub esr: usize,
+    /// x0 to x22
+    pub regs: [usize; Self::REGS],
+    /// Frame pointer (x29)
+    pub fp: usize,
+    /// Link register (x30)
+    pub lr: usize,
+    /// Stack pointer
+    pub sp: usize,
+    /// Processor state (SPSR_EL1)
+    pub spsr: usize,
+}
+
+impl FaultMessage {
+    /// Data read fault
+    pub const FAULT_READ: usize = 0;
+    /// Data write fault
+    pub const FAULT_WRITE: usize = 1;
+    /// Instruction fetch fault
+    pub const FAULT_EXECUTE: usize = 2;
+    /// Exception no mapping resolves; the component can only be destroyed
+    pub const FAULT_CRASH: usize = 3;
+    /// The component called `process_exit`
+    pub const FAULT_EXIT: usize = 4;
+
+    /// General purpose registers in a message, x0 up
+    pub const REGS: usize = 23;
+
+    /// Size of an encoded fault message in bytes
+    pub const SIZE: usize = (5 + Self::REGS + 4) * 8;
+
+    /// Size of the first five fields, the least a receive buffer must hold
+    pub const HEADER_SIZE: usize = 5 * 8;
+
+    /// Decode a fault message received from the fault endpoint
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::HEADER_SIZE {
+            return None;
+        }
+        let word = |i: usize| {
+            let mut raw = [0u8; 8];
+            if let Some(bytes) = bytes.get(i * 8..i * 8 + 8) {
+                raw.copy_from_slice(bytes);
+            }
+            u64::from_le_bytes(raw) as usize
+        };
+        let trailer = 5 + Self::REGS;
+        Some(Self {
+            tid: word(0),
+            kind: word(1),
+            addr: word(2),
+            pc: word(3),
+            esr: word(4),
+            regs: core::array::from_fn(|i| word(5 + i)),
+            fp: word(trailer),
+            lr: word(trailer + 1),
+            sp: word(trailer + 2),
+            spsr: word(trailer + 3),
+        })
+    }
+}
+
+/// Register a pager endpoint for another component's page faults
+///
+/// Translation and permission faults taken by the target are sent to the
+/// endpoint as a `FaultMessage` instead of halting the system. So are its
+/// crashes and its `process_exit`, after which it waits for the caller to
+/// `process_destroy` it.
+///
+/// # Arguments
+///
+/// * `target_tcb_cap` - TCB capability of the component to page
+/// * `endpoint_cap` - Fault endpoint in the caller's CSpace, or `usize::MAX`
+///   to unregister
+///
+/// # Safety
+///
+/// Unsafe because it changes how another component's faults are handled
+pub unsafe fn tcb_set_fault_handler(target_tcb_cap: usize, endpoint_cap: usize) -> crate::Result<()> {
+    let result = crate::syscall!(
+        numbers::SYS_TCB_SET_FAULT_HANDLER,
+        target_tcb_cap,
+        endpoint_cap
+    );
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
+/// Resume a component blocked on a page fault
+///
+/// The component retries the faulting instruction, so map the page (e.g.
+/// with `memory_map_into`) before calling this.
+///
+/// # Arguments
+///
+/// * `target_tcb_cap` - TCB capability of the faulted component
+pub fn fault_resume(target_tcb_cap: usize) -> crate::Result<()> {
+    let result = crate::syscall!(numbers::SYS_FAULT_RESUME, target_tcb_cap);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
 /// Create a new process with full isolation
 ///
 /// # Arguments
@@ -1139,6 +1956,10 @@ pub unsafe fn cap_insert_into(
 /// * `stack_phys` - Physical address where stack is located
 /// * `priority` - Scheduling priority (0-255)
 /// * `capabilities` - Capability bitmask for the new process
+/// * `flags` - `numbers::PROCESS_CREATE_COW` to map the code region
+///   copy-on-write, so several processes can share one loaded image
+///   (which the caller must then leave unmodified); 0 hands the region to
+///   the new process
 ///
 /// # Returns
 ///
@@ -1147,6 +1968,7 @@ pub unsafe fn cap_insert_into(
 /// # Safety
 ///
 /// Unsafe because it creates a new isolated process with its own address space
+#[allow(clippy::too_many_arguments)]
 pub unsafe fn process_create(
     entry_point: usize,
     stack_pointer: usize,
@@ -1158,6 +1980,7 @@ pub unsafe fn process_create(
     stack_phys: usize,
     priority: u8,
     capabilities: u64,
+    flags: u64,
 ) -> crate::Result<usize> {
     let result = crate::syscall!(
         numbers::SYS_PROCESS_CREATE,
@@ -1170,7 +1993,75 @@ pub unsafe fn process_create(
         code_size,
         stack_phys,
         priority,
-        capabilities
+        capabilities,
+        flags
+    );
+
+    if result == usize::MAX {
+        Err(crate::Error::SyscallFailed)
+    } else {
+        Ok(result)
+    }
+}
+
+/// Terminate the calling component
+///
+/// The kernel frees the component's TCB, CSpace and page tables. Only the
+/// root task, which may not exit, gets control back; it then keeps
+/// yielding. A supervised component (one with a fault endpoint) is left for
+/// its supervisor to destroy, and may be restarted. Called from a worker
+/// thread, it ends the whole component; `thread_exit` ends just the thread.
+///
+/// # Arguments
+///
+/// * `exit_code` - Exit code, reported on the kernel console and to the
+///   supervisor
+pub fn process_exit(exit_code: usize) -> ! {
+    crate::syscall!(numbers::SYS_PROCESS_EXIT, exit_code);
+
+    loop {
+        yield_now();
+    }
+}
+
+/// Create a worker thread in the calling component
+///
+/// The thread shares the component's address space and capabilities, and
+/// starts at `entry` with `arg0` and `arg1` as its first two arguments.
+/// `component::spawn_thread` sets up its stack and IPC buffer and calls
+/// this.
+///
+/// # Arguments
+///
+/// * `entry` - Where the thread starts
+/// * `stack_top` - Top of its stack, 16-byte aligned and mapped
+/// * `ipc_buffer` - Its IPC buffer, page-aligned and mapped (0 for none)
+/// * `priority` - Its priority, no higher than the caller's
+///
+/// # Returns
+///
+/// The thread's TID
+///
+/// # Safety
+///
+/// The stack and IPC buffer must stay mapped, and be used by nothing
+/// else, while the thread runs
+pub unsafe fn thread_create(
+    entry: usize,
+    stack_top: usize,
+    ipc_buffer: usize,
+    priority: u8,
+    arg0: usize,
+    arg1: usize,
+) -> crate::Result<usize> {
+    let result = crate::syscall!(
+        numbers::SYS_THREAD_CREATE,
+        entry,
+        stack_top,
+        ipc_buffer,
+        priority,
+        arg0,
+        arg1
     );
 
     if result == usize::MAX {
@@ -1180,6 +2071,36 @@ pub unsafe fn process_create(
     }
 }
 
+/// End the calling worker thread
+///
+/// The rest of the component keeps running. From the component's main
+/// thread this does nothing: it ends with `process_exit`.
+pub fn thread_exit() {
+    crate::syscall!(numbers::SYS_THREAD_EXIT);
+}
+
+/// Terminate another component
+///
+/// Same teardown as `process_exit`. The TCB capability refers to freed
+/// memory afterwards and should be deleted.
+///
+/// # Arguments
+///
+/// * `target_tcb_cap` - TCB capability of the component to destroy
+///
+/// # Safety
+///
+/// Unsafe because it destroys another component and everything it owns
+pub unsafe fn process_destroy(target_tcb_cap: usize) -> crate::Result<()> {
+    let result = crate::syscall!(numbers::SYS_PROCESS_DESTROY, target_tcb_cap);
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
 /// Insert capability into caller's own CSpace
 ///
 /// # Arguments
@@ -1227,7 +2148,7 @@ pub unsafe fn cap_insert_self(
 ///
 /// * `irq_control_cap` - Capability slot containing IRQControl capability
 /// * `irq_num` - Hardware IRQ number to allocate (e.g., 27 for timer, 33 for UART0)
-/// * `notification_cap` - Capability slot containing notification to signal on IRQ
+/// * `notification` - Notification to signal on IRQ
 /// * `irq_handler_slot` - Empty capability slot to store the new IRQHandler
 ///
 /// # Returns
@@ -1256,19 +2177,19 @@ pub unsafe fn cap_insert_self(
 /// let irq_handler_slot = syscall::cap_allocate()?;
 ///
 /// // Allocate IRQ handler
-/// syscall::irq_handler_get(irq_control, uart_irq, notification, irq_handler_slot)?;
+/// syscall::irq_handler_get(irq_control, uart_irq, &notification, irq_handler_slot)?;
 /// ```
 pub fn irq_handler_get(
     irq_control_cap: usize,
     irq_num: usize,
-    notification_cap: usize,
+    notification: &Cap<Notification>,
     irq_handler_slot: usize,
 ) -> crate::Result<()> {
     let result = crate::syscall!(
         numbers::SYS_IRQ_HANDLER_GET,
         irq_control_cap,
         irq_num,
-        notification_cap,
+        notification.slot(),
         irq_handler_slot
     );
 
@@ -1305,7 +2226,7 @@ pub fn irq_handler_get(
 /// // Device driver IRQ handling loop
 /// loop {
 ///     // Wait for IRQ
-///     syscall::wait(notification)?;
+///     syscall::wait(&notification)?;
 ///
 ///     // Service the device
 ///     handle_uart_interrupt();
@@ -1327,6 +2248,121 @@ pub fn irq_handler_ack(irq_handler_cap: usize) -> crate::Result<()> {
     }
 }
 
+/// Subscribe to a shared IRQ line (requires IRQControl capability)
+///
+/// Like `irq_handler_get`, but any number of shared handlers can hold the
+/// IRQ, each signaling its own notification with its own `badge`
+/// (non-zero). The IRQ is unmasked again once every subscriber has called
+/// `irq_handler_ack`. Fails if the IRQ has an exclusive handler.
+///
+/// # Example
+///
+/// ```no_run
+/// use kaal_sdk::syscall;
+///
+/// // A monitor watching the NIC's INTx line next to its driver
+/// let notification = syscall::notification_create()?;
+/// let irq_handler_slot = syscall::cap_allocate()?;
+/// syscall::irq_handler_get_shared(irq_control, 36, &notification, irq_handler_slot, 1 << 4)?;
+/// ```
+pub fn irq_handler_get_shared(
+    irq_control_cap: usize,
+    irq_num: usize,
+    notification: &Cap<Notification>,
+    irq_handler_slot: usize,
+    badge: u64,
+) -> crate::Result<()> {
+    let result = crate::syscall!(
+        numbers::SYS_IRQ_HANDLER_GET_SHARED,
+        irq_control_cap,
+        irq_num,
+        notification.slot(),
+        irq_handler_slot,
+        badge
+    );
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
+/// Unbind an IRQHandler from its IRQ (requires IRQHandler capability)
+///
+/// Its notification is no longer signaled and, on a shared line, the other
+/// subscribers no longer wait for its acknowledgement. The capability stays
+/// until deleted.
+pub fn irq_handler_clear(irq_handler_cap: usize) -> crate::Result<()> {
+    let result = crate::syscall!(
+        numbers::SYS_IRQ_HANDLER_CLEAR,
+        irq_handler_cap
+    );
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
+/// Message a device writes to raise an MSI (see `irq_msi_get`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiMessage {
+    /// Address the device writes to
+    pub address: u64,
+    /// Value the device writes
+    pub data: u32,
+}
+
+/// Allocate an MSI vector for a PCIe device (requires IRQControl capability)
+///
+/// Like `irq_handler_get`, but instead of claiming a wired IRQ the kernel
+/// allocates a message-signaled interrupt for the device and returns the
+/// address/data pair to program into its MSI capability or MSI-X table.
+/// The notification is signaled with bit `data % 64`, so several vectors
+/// can share one notification. MSIs need no `irq_handler_ack`.
+///
+/// # Arguments
+///
+/// * `irq_control_cap` - Capability slot containing IRQControl capability
+/// * `device_id` - The device's PCIe requester ID (bus << 8 | dev << 3 | fn)
+/// * `notification` - Notification to signal on IRQ
+/// * `irq_handler_slot` - Empty capability slot to store the new IRQHandler
+///
+/// # Returns
+///
+/// The MSI message, or an error if the system has no MSI support (GICv3 ITS)
+/// or is out of vectors
+pub fn irq_msi_get(
+    irq_control_cap: usize,
+    device_id: usize,
+    notification: &Cap<Notification>,
+    irq_handler_slot: usize,
+) -> crate::Result<MsiMessage> {
+    let result: usize;
+    let address: usize;
+    let data: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_IRQ_MSI_GET,
+            inlateout("x0") irq_control_cap => result,
+            inlateout("x1") device_id => address,
+            inlateout("x2") notification.slot() => data,
+            inlateout("x3") irq_handler_slot => _,
+            out("x8") _,
+        );
+    }
+
+    if result == 0 {
+        Ok(MsiMessage { address: address as u64, data: data as u32 })
+    } else {
+        Err(crate::Error::SyscallFailed)
+    }
+}
+
 // ============================================================================
 // System Control Functions
 // ============================================================================
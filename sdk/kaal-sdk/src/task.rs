@@ -0,0 +1,436 @@
+//! Async tasks: a single-threaded executor woken by notification signals
+//!
+//! Event-driven code as `async` functions instead of hand-written state
+//! machines in a `run()` loop. The [`Executor`] owns a notification that
+//! event sources signal with badges of their own: an IRQ handler, a
+//! timer, a channel's producer, another component. Tasks wait for those
+//! badges with [`Signals::wait`]; when none of its tasks can go on, the
+//! executor blocks on the notification, and wakes the tasks waiting for
+//! the bits it receives.
+//!
+//! ```no_run
+//! use core::time::Duration;
+//! use kaal_sdk::env::{self, InitialCap};
+//! use kaal_sdk::syscall;
+//! use kaal_sdk::task::{self, Executor};
+//!
+//! const UART0_IRQ: usize = 33;
+//!
+//! let executor = Executor::new()?;
+//! let signals = executor.signals();
+//!
+//! // UART IRQs signal the executor's notification
+//! let irq_control = env::cap_slot(InitialCap::IrqControl).unwrap();
+//! let irq_handler = syscall::cap_allocate()?;
+//! syscall::irq_handler_get(irq_control, UART0_IRQ, executor.notification().slot(), irq_handler)?;
+//!
+//! let irqs = signals.clone();
+//! executor.spawn(async move {
+//!     loop {
+//!         irqs.wait(task::irq_badge(UART0_IRQ)).await;
+//!         // ... drain the UART FIFO ...
+//!         let _ = unsafe { syscall::irq_handler_ack(irq_handler) };
+//!     }
+//! });
+//!
+//! let timer = signals.timer(1 << 0)?;
+//! executor.block_on(async {
+//!     loop {
+//!         timer.sleep(Duration::from_secs(1)).await?;
+//!         kaal_sdk::printf!("still alive\n");
+//!     }
+//! })
+//! # ; Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! Badge bits received while no task waits for them are kept for the next
+//! task that does. Bit 63 ([`WAKE_BADGE`]) is the executor's own, for
+//! wakers called from other threads.
+//!
+//! Tasks run on the thread that runs the executor, one at a time, so they
+//! need not be `Send`.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use crate::capability::{Cap, Notification, Timer};
+use crate::sync::Mutex;
+use crate::{syscall, Result};
+
+/// Badge bit the executor signals itself with when a task is woken from
+/// another thread while it blocks; not for event sources
+pub const WAKE_BADGE: u64 = 1 << 63;
+
+/// Badge an IRQ handler bound to the executor's notification signals it
+/// with (see [`syscall::irq_handler_get`])
+pub const fn irq_badge(irq: usize) -> u64 {
+    1 << (irq % 64)
+}
+
+/// Task ID of the future given to [`Executor::block_on`]
+const MAIN_TASK: usize = usize::MAX;
+
+type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// A task's place in the executor; its index is the task's ID
+enum Slot {
+    Free,
+    /// Out of its slot while polled, so that it can spawn tasks
+    Polling,
+    Task(LocalFuture),
+}
+
+type Tasks = Rc<RefCell<Vec<Slot>>>;
+
+/// What the executor shares with wakers and [`Signals`]
+struct Shared {
+    notification: Cap<Notification>,
+    /// Tasks woken, to poll
+    ready: Mutex<VecDeque<usize>>,
+    /// The executor is blocked on the notification, or about to be
+    blocked: AtomicBool,
+    signals: Mutex<SignalState>,
+}
+
+impl Shared {
+    fn wake_task(&self, id: usize) {
+        let mut ready = self.ready.lock();
+        if !ready.contains(&id) {
+            ready.push_back(id);
+        }
+        drop(ready);
+
+        // The executor marks itself blocked before looking at `ready` a
+        // last time: either it sees the task, or we see it blocked
+        if self.blocked.load(Ordering::SeqCst) {
+            let _ = self.notification.signal(WAKE_BADGE);
+        }
+    }
+}
+
+/// Badge bits received, and the tasks waiting for them
+#[derive(Default)]
+struct SignalState {
+    pending: u64,
+    waiting: Vec<(u64, Waker)>,
+}
+
+/// Waker of one task
+struct TaskWaker {
+    id: usize,
+    shared: Arc<Shared>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.shared.wake_task(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.shared.wake_task(self.id);
+    }
+}
+
+/// Single-threaded executor of `async` tasks
+pub struct Executor {
+    shared: Arc<Shared>,
+    tasks: Tasks,
+}
+
+impl Executor {
+    /// A new executor, with a notification of its own
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_notification(Cap::<Notification>::create()?))
+    }
+
+    /// A new executor, woken by `notification`
+    ///
+    /// For a notification event sources were set up to signal already.
+    pub fn with_notification(notification: Cap<Notification>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                notification,
+                ready: Mutex::new(VecDeque::new()),
+                blocked: AtomicBool::new(false),
+                signals: Mutex::new(SignalState::default()),
+            }),
+            tasks: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// The notification it blocks on, for event sources to signal
+    pub fn notification(&self) -> &Cap<Notification> {
+        &self.shared.notification
+    }
+
+    /// Handle for tasks to wait for badges with
+    pub fn signals(&self) -> Signals {
+        Signals { shared: self.shared.clone() }
+    }
+
+    /// Handle for tasks to spawn more tasks with
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+            tasks: self.tasks.clone(),
+        }
+    }
+
+    /// Add a task, run once the executor runs
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        self.spawner().spawn(future);
+    }
+
+    /// Run the tasks until `future` completes, and return its output
+    ///
+    /// Tasks still running then stay, for the next `block_on` or
+    /// [`run`](Self::run).
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        let mut future = core::pin::pin!(future);
+        let waker = self.waker(MAIN_TASK);
+        let mut cx = Context::from_waker(&waker);
+        self.shared.wake_task(MAIN_TASK);
+
+        loop {
+            while let Some(id) = self.next_ready() {
+                if id != MAIN_TASK {
+                    self.poll_task(id);
+                } else if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+            self.block();
+        }
+    }
+
+    /// Run the tasks until all of them complete
+    pub fn run(&self) {
+        while self.has_tasks() {
+            while let Some(id) = self.next_ready() {
+                self.poll_task(id);
+            }
+            if self.has_tasks() {
+                self.block();
+            }
+        }
+    }
+
+    fn has_tasks(&self) -> bool {
+        self.tasks.borrow().iter().any(|slot| !matches!(slot, Slot::Free))
+    }
+
+    fn next_ready(&self) -> Option<usize> {
+        self.shared.ready.lock().pop_front()
+    }
+
+    fn waker(&self, id: usize) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            id,
+            shared: self.shared.clone(),
+        }))
+    }
+
+    fn poll_task(&self, id: usize) {
+        let mut future = match self.tasks.borrow_mut().get_mut(id) {
+            Some(slot @ Slot::Task(_)) => match core::mem::replace(slot, Slot::Polling) {
+                Slot::Task(future) => future,
+                _ => unreachable!(),
+            },
+            // Completed since it was woken
+            _ => return,
+        };
+
+        let waker = self.waker(id);
+        let poll = future.as_mut().poll(&mut Context::from_waker(&waker));
+        self.tasks.borrow_mut()[id] = match poll {
+            Poll::Pending => Slot::Task(future),
+            Poll::Ready(()) => Slot::Free,
+        };
+    }
+
+    /// Wait for the notification, and wake the tasks waiting for what it
+    /// received
+    fn block(&self) {
+        self.shared.blocked.store(true, Ordering::SeqCst);
+        let bits = if self.shared.ready.lock().is_empty() {
+            self.shared.notification.wait().unwrap_or_else(|_| {
+                syscall::yield_now();
+                0
+            })
+        } else {
+            0
+        };
+        self.shared.blocked.store(false, Ordering::SeqCst);
+
+        let bits = bits & !WAKE_BADGE;
+        if bits != 0 {
+            self.signals().deliver(bits);
+        }
+    }
+}
+
+/// Handle to spawn tasks on an [`Executor`] from its tasks
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+    tasks: Tasks,
+}
+
+impl Spawner {
+    /// Add a task, run once the executor runs
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        let mut tasks = self.tasks.borrow_mut();
+        let task = Slot::Task(Box::pin(future));
+        let id = match tasks.iter().position(|slot| matches!(slot, Slot::Free)) {
+            Some(id) => {
+                tasks[id] = task;
+                id
+            }
+            None => {
+                tasks.push(task);
+                tasks.len() - 1
+            }
+        };
+        drop(tasks);
+        self.shared.wake_task(id);
+    }
+}
+
+/// Handle for tasks to wait for badges signalled to an [`Executor`]'s
+/// notification
+#[derive(Clone)]
+pub struct Signals {
+    shared: Arc<Shared>,
+}
+
+impl Signals {
+    /// Wait until any of the badge bits in `mask` is signalled
+    ///
+    /// Resolves to the bits of `mask` received, which are taken: bits
+    /// outside it stay for other tasks.
+    pub fn wait(&self, mask: u64) -> Signal {
+        Signal {
+            shared: self.shared.clone(),
+            mask: mask & !WAKE_BADGE,
+        }
+    }
+
+    /// Signal the executor's notification with `badge`, from a task or
+    /// from another thread
+    pub fn signal(&self, badge: u64) -> Result<()> {
+        self.shared.notification.signal(badge)
+    }
+
+    /// A timer on the executor's notification, signalling `badge`
+    pub fn timer(&self, badge: u64) -> Result<AsyncTimer> {
+        let timer = Timer::create(&self.shared.notification, badge)?;
+        Ok(AsyncTimer {
+            timer,
+            signals: self.clone(),
+            badge,
+        })
+    }
+
+    /// Hand the bits received to the tasks waiting for them
+    fn deliver(&self, bits: u64) {
+        let mut state = self.shared.signals.lock();
+        state.pending |= bits;
+
+        let pending = state.pending;
+        let mut woken = Vec::new();
+        state.waiting.retain(|(mask, waker)| {
+            let wanted = mask & pending != 0;
+            if wanted {
+                woken.push(waker.clone());
+            }
+            !wanted
+        });
+        drop(state);
+
+        // Not under the lock: waking takes the ready queue's
+        for waker in woken {
+            waker.wake();
+        }
+    }
+}
+
+/// Future of [`Signals::wait`]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Signal {
+    shared: Arc<Shared>,
+    mask: u64,
+}
+
+impl Future for Signal {
+    type Output = u64;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+        let mut state = self.shared.signals.lock();
+        let bits = state.pending & self.mask;
+        if bits != 0 {
+            state.pending &= !bits;
+            return Poll::Ready(bits);
+        }
+
+        // Polled again before its bits came: one entry per task
+        let waker = cx.waker();
+        match state
+            .waiting
+            .iter_mut()
+            .find(|(mask, w)| *mask == self.mask && w.will_wake(waker))
+        {
+            Some((_, w)) => w.clone_from(waker),
+            None => state.waiting.push((self.mask, waker.clone())),
+        }
+        Poll::Pending
+    }
+}
+
+/// A timer for tasks: a kernel timer signalling the executor's
+/// notification (see [`Signals::timer`])
+///
+/// Each timer needs a badge of its own, for its wait to be its own.
+pub struct AsyncTimer {
+    timer: Timer,
+    signals: Signals,
+    badge: u64,
+}
+
+impl AsyncTimer {
+    /// Wait at least `duration`
+    ///
+    /// Rounded up to the scheduler tick, as [`time::sleep`](crate::time::sleep).
+    pub async fn sleep(&self, duration: Duration) -> Result<()> {
+        let us = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX).max(1);
+        self.timer.set_oneshot(us)?;
+        self.signals.wait(self.badge).await;
+        Ok(())
+    }
+
+    /// Start ticking every `period`, for [`tick`](Self::tick)
+    pub fn set_periodic(&self, period: Duration) -> Result<()> {
+        let us = u64::try_from(period.as_micros()).unwrap_or(u64::MAX).max(1);
+        self.timer.set_periodic(us)
+    }
+
+    /// Wait for the next tick
+    pub async fn tick(&self) {
+        self.signals.wait(self.badge).await;
+    }
+}
+
+impl Drop for AsyncTimer {
+    fn drop(&mut self) {
+        let _ = self.timer.cancel();
+    }
+}
@@ -0,0 +1,126 @@
+//! Threads
+//!
+//! A std-like face on the component's worker threads
+//! ([`component::spawn_thread`](crate::component::spawn_thread)): spawn a
+//! closure, get a handle, join it for the closure's result.
+//!
+//! ```no_run
+//! use kaal_sdk::thread;
+//!
+//! let worker = thread::spawn(|| (1..=10).sum::<u32>())?;
+//! let sum = worker.join()?;
+//! # Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! Joining waits on a notification the thread signals when it is done.
+//! Threads share the component's heap; each one's stack, IPC buffer and
+//! notification stay with the component after it ends.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+
+use crate::capability::{Cap, Notification};
+use crate::component::spawn_thread;
+use crate::Result;
+
+pub use crate::syscall::yield_now;
+
+/// Badge a thread signals its join notification with when done
+const DONE_BADGE: u64 = 1 << 0;
+
+/// What a thread and its handle share: its result, and the notification
+/// announcing it
+struct Packet<T> {
+    result: UnsafeCell<Option<T>>,
+    done: Cap<Notification>,
+}
+
+// The thread writes the result before signalling `done`, and the handle
+// reads it only after seeing the signal
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+/// Handle to a spawned thread, to join it
+///
+/// Dropping it detaches the thread, which keeps running.
+pub struct JoinHandle<T> {
+    tid: usize,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// The thread's TID
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+
+    /// Wait for the thread to finish, and take its closure's result
+    pub fn join(self) -> Result<T> {
+        while self.packet.done.wait()? & DONE_BADGE == 0 {}
+        Ok(self.take())
+    }
+
+    /// Whether the thread has finished, without blocking; [`join`](Self::join)
+    /// then returns at once
+    pub fn is_finished(&self) -> Result<bool> {
+        if self.packet.done.poll()? & DONE_BADGE == 0 {
+            return Ok(false);
+        }
+        // Seen here, so signal again for join to see
+        self.packet.done.signal(DONE_BADGE)?;
+        Ok(true)
+    }
+
+    fn take(&self) -> T {
+        unsafe { (*self.packet.result.get()).take() }
+            .expect("thread signalled done without a result")
+    }
+}
+
+/// Spawn a thread running `f`, at the caller's priority
+///
+/// # Errors
+/// As [`spawn_thread`], or if the join notification cannot be created
+pub fn spawn<F, T>(f: F) -> Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    // The kernel clamps priorities to the caller's
+    spawn_with_priority(0, f)
+}
+
+/// Spawn a thread running `f` at `priority`, no higher than the caller's
+pub fn spawn_with_priority<F, T>(priority: u8, f: F) -> Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: UnsafeCell::new(None),
+        done: Cap::<Notification>::create()?,
+    });
+
+    let theirs = packet.clone();
+    let main: Box<dyn FnOnce() + Send> = Box::new(move || {
+        let result = f();
+        unsafe { *theirs.result.get() = Some(result) };
+        let _ = theirs.done.signal(DONE_BADGE);
+    });
+
+    // A thin pointer to the closure, to pass in a register
+    let main = Box::into_raw(Box::new(main));
+    match spawn_thread(run, main as usize, priority) {
+        Ok(thread) => Ok(JoinHandle { tid: thread.tid, packet }),
+        Err(e) => {
+            drop(unsafe { Box::from_raw(main) });
+            Err(e)
+        }
+    }
+}
+
+/// A spawned thread's entry point: run its closure
+fn run(main: usize) {
+    let main = unsafe { Box::from_raw(main as *mut Box<dyn FnOnce() + Send>) };
+    main();
+}
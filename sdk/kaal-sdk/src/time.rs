@@ -0,0 +1,214 @@
+//! Time: a monotonic clock, sleeping and periodic timers
+//!
+//! The clock is the kernel's uptime, counted in microseconds from the
+//! generic timer since boot. Sleeps and intervals wait on kernel timer
+//! objects, which expire on the scheduler tick (5 ms): waits are rounded
+//! up to it.
+//!
+//! ```no_run
+//! use core::time::Duration;
+//! use kaal_sdk::time::{self, Instant, Interval};
+//!
+//! let start = Instant::now();
+//! time::sleep(Duration::from_millis(100));
+//! kaal_sdk::printf!("slept {} ms\n", start.elapsed().as_millis());
+//!
+//! let interval = Interval::new(Duration::from_secs(1))?;
+//! loop {
+//!     interval.tick()?;
+//!     kaal_sdk::printf!("up {} s\n", time::uptime().as_secs());
+//! }
+//! # Ok::<(), kaal_sdk::Error>(())
+//! ```
+//!
+//! Timers take a notification and a timer object, and creating them needs
+//! the `caps:allocate` capability. [`sleep`] creates its pair on first use
+//! and keeps it; without the capability, it yields until the time is up.
+
+use core::mem::ManuallyDrop;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::capability::{Cap, Notification, Timer};
+use crate::sync::Mutex;
+use crate::{syscall, Result};
+
+/// Badge the SDK's timers signal their notification with
+const TIMER_BADGE: u64 = 1 << 0;
+
+/// A point on the monotonic clock
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    /// Microseconds since boot
+    us: u64,
+}
+
+impl Instant {
+    /// The current time
+    pub fn now() -> Self {
+        let us = syscall::idle_stats().map_or(0, |stats| stats.uptime_us);
+        Self { us }
+    }
+
+    /// Time passed since `self`
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Time from `earlier` to `self`; zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.us.saturating_sub(earlier.us))
+    }
+
+    /// Time from boot to `self`
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_micros(self.us)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        let us = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        Instant { us: self.us.saturating_add(us) }
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// The current time
+pub fn now() -> Instant {
+    Instant::now()
+}
+
+/// Time since boot
+pub fn uptime() -> Duration {
+    Instant::now().since_boot()
+}
+
+/// Block the calling thread for at least `duration`
+pub fn sleep(duration: Duration) {
+    sleep_until(Instant::now() + duration);
+}
+
+/// Block the calling thread until `deadline`
+///
+/// The component's threads share one sleep timer: while one sleeps on
+/// it, another waits its turn and then sleeps what is left of its own
+/// time.
+pub fn sleep_until(deadline: Instant) {
+    let _sleeping = SLEEPING.lock();
+
+    match sleep_timer() {
+        Some((notification, timer)) => loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            // Rounded up: ending early would only mean waiting again
+            let left = deadline.duration_since(now).as_micros().max(1) as u64;
+            if syscall::timer_set(timer, left, 0).is_err() || syscall::wait(&notification).is_err() {
+                yield_until(deadline);
+                break;
+            }
+        },
+        None => yield_until(deadline),
+    }
+}
+
+/// Held by whoever is sleeping on the sleep timer
+static SLEEPING: Mutex<()> = Mutex::new(());
+
+/// Notification and timer capabilities of the sleep timer, 0 until created
+static SLEEP_NOTIFICATION: AtomicUsize = AtomicUsize::new(0);
+static SLEEP_TIMER: AtomicUsize = AtomicUsize::new(0);
+
+/// The sleep timer's notification and timer, created on first use
+///
+/// Called with `SLEEPING` held. None if they cannot be created.
+fn sleep_timer() -> Option<(ManuallyDrop<Cap<Notification>>, usize)> {
+    let timer = SLEEP_TIMER.load(Ordering::Acquire);
+    if timer != 0 {
+        let notification = SLEEP_NOTIFICATION.load(Ordering::Acquire);
+        return Some((unsafe { Cap::borrow_raw(notification, Notification) }, timer));
+    }
+
+    let notification = match SLEEP_NOTIFICATION.load(Ordering::Acquire) {
+        0 => {
+            // Kept for good
+            let notification = syscall::notification_create().ok()?.into_raw();
+            SLEEP_NOTIFICATION.store(notification, Ordering::Release);
+            notification
+        }
+        notification => notification,
+    };
+    let notification = unsafe { Cap::borrow_raw(notification, Notification) };
+    let timer = syscall::timer_create(&notification, TIMER_BADGE).ok()?;
+    SLEEP_TIMER.store(timer, Ordering::Release);
+    Some((notification, timer))
+}
+
+/// Wait for `deadline` without a timer
+fn yield_until(deadline: Instant) {
+    while Instant::now() < deadline {
+        syscall::yield_now();
+    }
+}
+
+/// A periodic timer
+///
+/// Ticks every period from its creation, whether or not anyone is waiting:
+/// a tick missed while busy is not made up later, the next [`tick`](Self::tick)
+/// just returns at once.
+pub struct Interval {
+    notification: Cap<Notification>,
+    timer: Timer,
+    period: Duration,
+}
+
+impl Interval {
+    /// Start a timer ticking every `period`
+    pub fn new(period: Duration) -> Result<Self> {
+        let notification = Cap::<Notification>::create()?;
+        let timer = Timer::create(&notification, TIMER_BADGE)?;
+        let period_us = u64::try_from(period.as_micros()).unwrap_or(u64::MAX).max(1);
+        timer.set_periodic(period_us)?;
+        Ok(Self { notification, timer, period })
+    }
+
+    /// Block until the next tick
+    pub fn tick(&self) -> Result<()> {
+        self.notification.wait().map(|_| ())
+    }
+
+    /// Whether it ticked since the last [`tick`](Self::tick), without
+    /// blocking; a tick seen here is taken
+    pub fn ticked(&self) -> Result<bool> {
+        Ok(self.notification.poll()? & TIMER_BADGE != 0)
+    }
+
+    /// Its period
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The notification it ticks on, to wait for ticks together with
+    /// other events (see [`Cap::bind`])
+    pub fn notification(&self) -> &Cap<Notification> {
+        &self.notification
+    }
+}
+
+impl Drop for Interval {
+    fn drop(&mut self) {
+        let _ = self.timer.cancel();
+    }
+}
@@ -0,0 +1,131 @@
+//! Syscall tracing
+//!
+//! Kernels built with the `syscall-trace` feature record syscalls into a
+//! ring buffer. Tracing starts disabled; enable it, optionally restrict it
+//! to a few threads, then read the records back by sequence number. On
+//! kernels without the feature every call fails.
+//!
+//! # Example
+//! ```no_run
+//! use kaal_sdk::trace;
+//!
+//! trace::filter_add(pid)?;
+//! trace::enable()?;
+//! // ... exercise the components ...
+//! trace::disable()?;
+//!
+//! let mut records = [trace::TraceRecord::default(); 16];
+//! let mut seq = 0;
+//! loop {
+//!     let (count, next) = trace::read(&mut records, seq)?;
+//!     if count == 0 {
+//!         break;
+//!     }
+//!     for r in &records[..count] {
+//!         kaal_sdk::printf!("{} tid={:#x} nr={:#x} ret={:#x}\n", r.seq, r.tid, r.number, r.ret);
+//!     }
+//!     seq = next;
+//! }
+//! ```
+
+use crate::syscall::numbers;
+use crate::{Result, Error};
+
+const OP_ENABLE: usize = 0;
+const OP_DISABLE: usize = 1;
+const OP_CLEAR: usize = 2;
+const OP_FILTER_ADD: usize = 3;
+const OP_FILTER_CLEAR: usize = 4;
+const OP_READ: usize = 5;
+
+/// Record flag: completed by the kernel's IPC fastpath
+pub const FLAG_FASTPATH: u64 = 1 << 0;
+
+/// Record flag: the caller blocked or exited, so `ret` is not known
+pub const FLAG_SWITCHED: u64 = 1 << 1;
+
+/// One traced syscall
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Sequence number (counts from 0 at boot or the last `clear()`)
+    pub seq: u64,
+    /// Entry time in nanoseconds
+    pub timestamp_ns: u64,
+    /// Time spent in the kernel in nanoseconds
+    pub duration_ns: u64,
+    /// Caller TID
+    pub tid: u64,
+    /// Syscall number
+    pub number: u64,
+    /// Arguments x0-x5
+    pub args: [u64; 6],
+    /// Return value (x0)
+    pub ret: u64,
+    /// `FLAG_*` bits
+    pub flags: u64,
+}
+
+fn ctl(op: usize, arg: usize) -> Result<()> {
+    let result = crate::syscall!(numbers::SYS_TRACE_CTL, op, arg);
+    if result == usize::MAX {
+        Err(Error::SyscallFailed)
+    } else {
+        Ok(())
+    }
+}
+
+/// Start recording syscalls
+pub fn enable() -> Result<()> {
+    ctl(OP_ENABLE, 0)
+}
+
+/// Stop recording syscalls
+pub fn disable() -> Result<()> {
+    ctl(OP_DISABLE, 0)
+}
+
+/// Drop all records and restart sequence numbers from 0
+pub fn clear() -> Result<()> {
+    ctl(OP_CLEAR, 0)
+}
+
+/// Only trace `tid` (and any other TIDs added); fails when the filter is full
+pub fn filter_add(tid: usize) -> Result<()> {
+    ctl(OP_FILTER_ADD, tid)
+}
+
+/// Trace every thread again
+pub fn filter_clear() -> Result<()> {
+    ctl(OP_FILTER_CLEAR, 0)
+}
+
+/// Read records starting at sequence number `seq`
+///
+/// If `seq` has already been overwritten, reading continues from the
+/// oldest record still held.
+///
+/// # Returns
+/// `(records_copied, next_seq)`
+pub fn read(buf: &mut [TraceRecord], seq: u64) -> Result<(usize, u64)> {
+    let count: usize;
+    let next: usize;
+    unsafe {
+        core::arch::asm!(
+            "mov x8, {syscall_num}",
+            "svc #0",
+            syscall_num = in(reg) numbers::SYS_TRACE_CTL,
+            inlateout("x0") OP_READ => count,
+            inlateout("x1") buf.as_mut_ptr() as usize => next,
+            inlateout("x2") core::mem::size_of_val(buf) => _,
+            inlateout("x3") seq as usize => _,
+            out("x8") _,
+        );
+    }
+
+    if count == usize::MAX {
+        Err(Error::SyscallFailed)
+    } else {
+        Ok((count, next as u64))
+    }
+}